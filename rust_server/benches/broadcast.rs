@@ -0,0 +1,112 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use galavox::broadcast::BroadcastFrame;
+use galavox::protocol::{GameState, Player, PlayerAppearance, Position, ServerMessage, WireFormat};
+use tokio::sync::{broadcast, mpsc};
+
+/*
+Worst-case broadcast tick with 2,000 simulated clients, with snapshots
+encoded lazily by the first client to receive them ("inline", as before) and
+encoded on a blocking thread before publishing ("offloaded").
+
+Each client is a task that takes its format's encoding of every frame, as a
+connection task does before writing it. A probe task wakes every
+millisecond; how late it wakes is how long the runtime was stalled. A tick
+lasts from building the frame until every client has its encoding.
+
+    cargo bench --bench broadcast
+*/
+
+const CLIENTS: usize = 2_000;
+const TICKS: u64 = 50;
+const INTERVAL: Duration = Duration::from_millis(50);
+
+fn world() -> GameState {
+    let origin = Position { x: 0.0, y: 0.0, z: 0.0 };
+    let players = (0..CLIENTS as u32).map(|id| Player {
+        id,
+        name: format!("Player_{}", id).into(),
+        level: 1,
+        position: Position { x: id as f32, y: 0.0, z: 0.0 },
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name(&format!("Player_{}", id)),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }).collect();
+    GameState::new(vec![], players, origin)
+}
+
+struct Report {
+    build_max: Duration,
+    tick_max: Duration,
+    stall_max: Duration,
+}
+
+async fn run(offload: bool) -> Report {
+    let (tx, _) = broadcast::channel::<Arc<BroadcastFrame>>(16);
+    let (done_tx, mut done_rx) = mpsc::unbounded_channel::<u64>();
+    for i in 0..CLIENTS {
+        let mut rx = tx.subscribe();
+        let done_tx = done_tx.clone();
+        let format = if i % 2 == 0 { WireFormat::Binary } else { WireFormat::Json };
+        tokio::spawn(async move {
+            let mut tick = 0;
+            while let Ok(frame) = rx.recv().await {
+                std::hint::black_box(frame.encoded(format));
+                tick += 1;
+                let _ = done_tx.send(tick);
+            }
+        });
+    }
+
+    let probing = Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let probe = {
+        let probing = probing.clone();
+        tokio::spawn(async move {
+            let mut stall_max = Duration::ZERO;
+            while probing.load(std::sync::atomic::Ordering::Relaxed) {
+                let before = Instant::now();
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                stall_max = stall_max.max(before.elapsed().saturating_sub(Duration::from_millis(1)));
+            }
+            stall_max
+        })
+    };
+
+    let state = world();
+    let (mut build_max, mut tick_max) = (Duration::ZERO, Duration::ZERO);
+    for tick in 1..=TICKS {
+        let started = Instant::now();
+        let message = ServerMessage::State { tick, server_time_ms: 0, state: state.clone() };
+        let frame = if offload { BroadcastFrame::prepare(message).await } else { BroadcastFrame::new(message) };
+        build_max = build_max.max(started.elapsed());
+        let _ = tx.send(Arc::new(frame));
+        let mut received = 0;
+        while received < CLIENTS {
+            if done_rx.recv().await == Some(tick) {
+                received += 1;
+            }
+        }
+        tick_max = tick_max.max(started.elapsed());
+        tokio::time::sleep(INTERVAL.saturating_sub(started.elapsed())).await;
+    }
+    probing.store(false, std::sync::atomic::Ordering::Relaxed);
+    Report { build_max, tick_max, stall_max: probe.await.unwrap() }
+}
+
+fn main() {
+    for (name, offload) in [("inline", false), ("offloaded", true)] {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        let report = runtime.block_on(run(offload));
+        println!("{:>9}: worst build {:>7.2}ms, worst tick {:>7.2}ms, worst runtime stall {:>7.2}ms",
+                 name,
+                 report.build_max.as_secs_f64() * 1000.0,
+                 report.tick_max.as_secs_f64() * 1000.0,
+                 report.stall_max.as_secs_f64() * 1000.0);
+    }
+}
@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use galavox::protocol::{Position, PositionUpdate};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+
+/*
+Position updates from many players at once while the broadcast loop keeps
+snapshotting the state, as on a busy server.
+
+Each writer is a thread standing in for one player's connection task,
+sending position updates as fast as it can. One reader broadcasts the state
+every `INTERVAL` meanwhile. With the players in shards, writers only wait
+for writers in the same shard and for the moment a broadcast copies their
+shard, so the update rate should grow with the writer count up to the
+number of cores.
+
+Run with:
+
+    cargo bench --bench contention
+*/
+
+const DURATION: Duration = Duration::from_secs(2);
+const INTERVAL: Duration = Duration::from_millis(10);
+
+fn connection(port: u16) -> Connection {
+    Connection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+struct Report {
+    updates: u64,
+    broadcasts: u32,
+    broadcast_max: Duration,
+}
+
+fn run(writers: u16) -> Report {
+    let server = GameServer::new();
+    let running = Arc::new(AtomicBool::new(true));
+    let updates = Arc::new(AtomicU64::new(0));
+
+    let threads: Vec<_> = (0..writers).map(|i| {
+        let (server, running, updates) = (server.clone(), running.clone(), updates.clone());
+        std::thread::spawn(move || {
+            let (connection, _, _) = server.add_player(connection(4000 + i), format!("Writer_{}", i)).unwrap();
+            let mut seq = 0;
+            while running.load(Ordering::Relaxed) {
+                seq += 1;
+                // Far out, where no planet is in discovery range
+                let position = Position { x: 50_000.0 + seq as f32 % 100.0, y: i as f32, z: 0.0 };
+                server.update_player_position(connection, PositionUpdate { seq: Some(seq), position, velocity: [1.0, 0.0, 0.0], rotation: [0.0, 0.0, 0.0, 1.0] });
+                updates.fetch_add(1, Ordering::Relaxed);
+                // A connection task goes back to the runtime between messages
+                std::thread::yield_now();
+            }
+        })
+    }).collect();
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let (broadcasts, broadcast_max) = runtime.block_on(async {
+        let (mut broadcasts, mut broadcast_max) = (0, Duration::ZERO);
+        let started = Instant::now();
+        while started.elapsed() < DURATION {
+            let before = Instant::now();
+            server.broadcast_game_state().await;
+            broadcast_max = broadcast_max.max(before.elapsed());
+            broadcasts += 1;
+            tokio::time::sleep(INTERVAL).await;
+        }
+        (broadcasts, broadcast_max)
+    });
+    running.store(false, Ordering::Relaxed);
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    Report { updates: updates.load(Ordering::Relaxed), broadcasts, broadcast_max }
+}
+
+fn main() {
+    for writers in [1, 4, 16, 64] {
+        let report = run(writers);
+        eprintln!("{:>3} writers: {:>9.0} updates/s, {:>4} broadcasts, worst broadcast {:>7.2}ms",
+                  writers,
+                  report.updates as f64 / DURATION.as_secs_f64(),
+                  report.broadcasts,
+                  report.broadcast_max.as_secs_f64() * 1000.0);
+    }
+}
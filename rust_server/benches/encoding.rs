@@ -0,0 +1,97 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use galavox::protocol::{encode_state, GameState, Player, PlayerAppearance, Position, ServerMessage, WireFormat};
+
+/*
+Allocations and time to serialize a state with 1,000 players, the way the
+join snapshot used to be sent (clone the state, then `bincode::serialize` /
+`serde_json::to_string` into a fresh buffer) and the way it is now
+(`encode_state` on the borrowed state into a reused buffer).
+
+    cargo bench --bench encoding
+*/
+
+const PLAYERS: u32 = 1_000;
+const ROUNDS: u32 = 200;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn world() -> GameState {
+    let players = (0..PLAYERS).map(|id| Player {
+        id,
+        name: format!("Player_{}", id).into(),
+        level: 1,
+        position: Position { x: id as f32, y: 0.0, z: 0.0 },
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name(&format!("Player_{}", id)),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }).collect();
+    GameState::new(vec![], players, Position { x: 0.0, y: 0.0, z: 0.0 })
+}
+
+fn measure(name: &str, mut encode: impl FnMut()) {
+    encode();  // warm up, so that reused buffers have grown
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed));
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        encode();
+    }
+    let elapsed = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes;
+    println!("{:>16}: {:>6} allocations, {:>8} bytes allocated, {:>7.1}µs per encode",
+             name,
+             allocations / ROUNDS as u64,
+             bytes / ROUNDS as u64,
+             elapsed.as_secs_f64() * 1e6 / ROUNDS as f64);
+}
+
+fn main() {
+    let state = world();
+    measure("clone + bincode", || {
+        let message = ServerMessage::State { tick: 1, server_time_ms: 0, state: state.clone() };
+        black_box(message.to_bincode().unwrap());
+    });
+    measure("borrowed bincode", || {
+        black_box(encode_state(WireFormat::Binary, 1, 0, &state).unwrap());
+    });
+    measure("clone + JSON", || {
+        let message = ServerMessage::State { tick: 1, server_time_ms: 0, state: state.clone() };
+        black_box(serde_json::to_string(&message).unwrap());
+    });
+    measure("borrowed JSON", || {
+        black_box(encode_state(WireFormat::Json, 1, 0, &state).unwrap());
+    });
+}
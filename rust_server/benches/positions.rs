@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use galavox::id_hash::IdMap;
+use galavox::protocol::{Player, PlayerAppearance, Position, PositionUpdate, IDENTITY_ROTATION};
+use galavox::server::{Connection, ConnectionId, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+
+/*
+Applying 100,000 position updates from 1,000 players, round robin:
+
+- to a bare map of the players by `ConnectionId`, hashed with the standard
+  library's SipHash and with `IdHasher` (see `id_hash`), which is all a
+  player shard does with an update's key;
+- through `GameServer::update_player_position`, as a connection task does,
+  where the map is one step of several.
+
+Run with:
+
+    cargo bench --bench positions
+*/
+
+const PLAYERS: u64 = 1_000;
+const UPDATES: u64 = 100_000;
+const ROUNDS: u32 = 20;
+
+fn player(id: u64) -> Player {
+    let name = format!("Player_{}", id);
+    Player {
+        id: id as u32,
+        name: name.as_str().into(),
+        level: 1,
+        position: Position { x: 0.0, y: 0.0, z: 0.0 },
+        velocity: [0.0; 3],
+        rotation: IDENTITY_ROTATION,
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name(&name),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+fn update(seq: u64) -> PositionUpdate {
+    // Far out, where no planet is in discovery range
+    let position = Position { x: 50_000.0 + (seq % 100) as f32, y: 0.0, z: 0.0 };
+    PositionUpdate { seq: Some(seq as u32), position, velocity: [1.0, 0.0, 0.0], rotation: IDENTITY_ROTATION }
+}
+
+/// The fastest of `ROUNDS` runs of `f`.
+fn fastest(mut f: impl FnMut()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let started = Instant::now();
+            f();
+            started.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn map_updates<S: BuildHasher>(players: &mut HashMap<ConnectionId, Player, S>) -> Duration {
+    fastest(|| {
+        for seq in 0..UPDATES {
+            let update = update(seq);
+            let player = players.get_mut(&ConnectionId(seq % PLAYERS)).unwrap();
+            player.position = update.position;
+            player.velocity = update.velocity;
+            player.rotation = update.rotation;
+            player.last_processed_seq = seq as u32;
+        }
+        black_box(&*players);
+    })
+}
+
+fn server_updates() -> Duration {
+    let server = GameServer::new();
+    let connections: Vec<ConnectionId> = (0..PLAYERS)
+        .map(|i| {
+            let joining = Connection {
+                addr: format!("127.0.0.1:{}", 4000 + i).parse().unwrap(),
+                stats: Arc::new(ConnectionStats::new(Instant::now())),
+                kick: Arc::new(Notify::new()),
+            };
+            server.add_player(joining, format!("Mover_{}", i)).unwrap().0
+        })
+        .collect();
+    fastest(|| {
+        for seq in 0..UPDATES {
+            black_box(server.update_player_position(connections[(seq % PLAYERS) as usize], update(seq)));
+        }
+    })
+}
+
+fn report(label: &str, elapsed: Duration) {
+    eprintln!("{:<28} {:>8.2}ms, {:>6.1}ns/update", label, elapsed.as_secs_f64() * 1000.0, elapsed.as_nanos() as f64 / UPDATES as f64);
+}
+
+fn main() {
+    let mut sip: HashMap<ConnectionId, Player> = (0..PLAYERS).map(|id| (ConnectionId(id), player(id))).collect();
+    let mut ids: IdMap<ConnectionId, Player> = (0..PLAYERS).map(|id| (ConnectionId(id), player(id))).collect();
+    report("map, SipHash", map_updates(&mut sip));
+    report("map, IdHasher", map_updates(&mut ids));
+    report("update_player_position", server_updates());
+}
@@ -1,5 +1,3 @@
-use std::ptr::null;
-
 use tokio::net::{TcpListener, TcpStream};
 use mini_redis::{Connection, Frame};
 use bytes::Bytes;
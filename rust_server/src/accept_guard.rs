@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/*
+Throttling on the accept path, before the TLS or WebSocket handshake.
+
+`max_connections_per_ip` only counts connections that got as far as
+joining, so a host that opens sockets and never finishes the handshake, or
+opens them faster than anyone plays, goes unseen by it. Every accepted TCP
+connection is put to the `AcceptGuard` first, and closed straight away if:
+- its address has opened `attempts_per_minute` connections this minute,
+  the refused ones included, so hammering keeps a host out until the
+  minute is over;
+- its address already has `open_per_ip` sockets open; or
+- the server already has `max_open` sockets open, from anywhere. Unless it
+  is configured this is the process's open-file limit less
+  `FD_RESERVE`, where that limit can be read, so a flood is refused
+  before `accept` starts failing for want of file descriptors.
+
+A refusal is reported as the first of its host's window, so the log gets
+one line per offender a minute rather than one per socket. Connections
+from a trusted proxy stand for many hosts, and only count towards
+`max_open`.
+
+Counters are kept for at most `TRACKED_HOSTS` addresses, the least
+recently seen of them forgotten first; a host with sockets open is never
+forgotten. Like the rate limiter, everything takes an explicit `now`.
+*/
+
+/// The window `attempts_per_minute` counts over.
+pub const WINDOW: Duration = Duration::from_secs(60);
+
+/// Most addresses whose counters are kept.
+pub const TRACKED_HOSTS: usize = 4_096;
+
+/// File descriptors left for everything but connections: listeners, the
+/// journal, the config and ban files, and so on.
+pub const FD_RESERVE: usize = 64;
+
+/// The thresholds; `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AcceptLimits {
+    pub max_open: Option<usize>,
+    pub open_per_ip: Option<usize>,
+    pub attempts_per_minute: Option<u32>,
+}
+
+impl AcceptLimits {
+    /// `max_open` from the open-file limit when it was not given.
+    pub fn or_fd_limit(self) -> Self {
+        AcceptLimits { max_open: self.max_open.or_else(|| Some(fd_limit()?.saturating_sub(FD_RESERVE))), ..self }
+    }
+}
+
+/// The soft limit on this process's open files, where it can be read.
+pub fn fd_limit() -> Option<usize> {
+    let limits = std::fs::read_to_string("/proc/self/limits").ok()?;
+    let line = limits.lines().find(|line| line.starts_with("Max open files"))?;
+    line["Max open files".len()..].split_whitespace().next()?.parse().ok()
+}
+
+/// Why a connection was turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    TooManyAttempts { limit: u32 },
+    TooManyOpen { limit: usize },
+    ServerFull { limit: usize },
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::TooManyAttempts { limit } => write!(f, "more than {} connections a minute", limit),
+            Refusal::TooManyOpen { limit } => write!(f, "{} connections open already", limit),
+            Refusal::ServerFull { limit } => write!(f, "the server has {} connections open", limit),
+        }
+    }
+}
+
+/// A refused connection, and whether it is the first refusal of its host
+/// (or, for a host that is not counted, of the server) this window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Refused {
+    pub refusal: Refusal,
+    pub first_in_window: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Host {
+    open: usize,
+    window_start: Instant,
+    attempts: u32,
+    refused_in_window: bool,
+    last_seen: Instant,
+}
+
+/// Counts what every address has open and has opened lately.
+#[derive(Debug)]
+pub struct AcceptGuard {
+    limits: AcceptLimits,
+    hosts: HashMap<IpAddr, Host>,
+    open: usize,
+    full_since: Option<Instant>,  // when the last window of `ServerFull` refusals began
+}
+
+impl AcceptGuard {
+    pub fn new(limits: AcceptLimits) -> Self {
+        AcceptGuard { limits, hosts: HashMap::new(), open: 0, full_since: None }
+    }
+
+    pub fn limits(&self) -> AcceptLimits {
+        self.limits
+    }
+
+    /// Sockets admitted and not released yet.
+    pub fn open(&self) -> usize {
+        self.open
+    }
+
+    /// Addresses with counters kept.
+    pub fn tracked(&self) -> usize {
+        self.hosts.len()
+    }
+
+    /// Counts a connection from `host` at `now` and admits it, or says why
+    /// not. `host` is `None` for a trusted proxy. An admitted connection
+    /// must be `release`d when it closes.
+    pub fn admit(&mut self, host: Option<IpAddr>, now: Instant) -> Result<(), Refused> {
+        let Some(ip) = host else {
+            return self.admit_anywhere(now);
+        };
+        if !self.hosts.contains_key(&ip) && self.hosts.len() >= TRACKED_HOSTS {
+            self.forget_one();
+        }
+        let limits = self.limits;
+        let open = self.open;
+        let entry = self.hosts.entry(ip).or_insert(Host {
+            open: 0,
+            window_start: now,
+            attempts: 0,
+            refused_in_window: false,
+            last_seen: now,
+        });
+        if now.saturating_duration_since(entry.window_start) >= WINDOW {
+            entry.window_start = now;
+            entry.attempts = 0;
+            entry.refused_in_window = false;
+        }
+        entry.attempts = entry.attempts.saturating_add(1);
+        entry.last_seen = now;
+
+        let refusal = match limits {
+            AcceptLimits { attempts_per_minute: Some(limit), .. } if entry.attempts > limit => Refusal::TooManyAttempts { limit },
+            AcceptLimits { open_per_ip: Some(limit), .. } if entry.open >= limit => Refusal::TooManyOpen { limit },
+            AcceptLimits { max_open: Some(limit), .. } if open >= limit => Refusal::ServerFull { limit },
+            _ => {
+                entry.open += 1;
+                self.open += 1;
+                return Ok(());
+            }
+        };
+        let first_in_window = !entry.refused_in_window;
+        entry.refused_in_window = true;
+        Err(Refused { refusal, first_in_window })
+    }
+
+    fn admit_anywhere(&mut self, now: Instant) -> Result<(), Refused> {
+        match self.limits.max_open {
+            Some(limit) if self.open >= limit => {
+                let first_in_window = self.full_since.is_none_or(|since| now.saturating_duration_since(since) >= WINDOW);
+                if first_in_window {
+                    self.full_since = Some(now);
+                }
+                Err(Refused { refusal: Refusal::ServerFull { limit }, first_in_window })
+            }
+            _ => {
+                self.open += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Gives back a connection admitted from `host`.
+    pub fn release(&mut self, host: Option<IpAddr>) {
+        self.open = self.open.saturating_sub(1);
+        if let Some(ip) = host
+            && let Some(entry) = self.hosts.get_mut(&ip)
+        {
+            entry.open = entry.open.saturating_sub(1);
+        }
+    }
+
+    /// Forgets the least recently seen host with nothing open.
+    fn forget_one(&mut self) {
+        let oldest = self.hosts.iter().filter(|(_, host)| host.open == 0).min_by_key(|(_, host)| host.last_seen).map(|(ip, _)| *ip);
+        if let Some(ip) = oldest {
+            self.hosts.remove(&ip);
+        }
+    }
+}
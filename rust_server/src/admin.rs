@@ -0,0 +1,724 @@
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::color::LabelContrast;
+use crate::economy::capacity_for;
+use crate::map::{self, MapSize};
+use crate::mutes::{parse_duration, MuteKind};
+use crate::planet_names::valid_planet_name;
+use crate::protocol::{normalize_name, Color, Planet, Position, TerrainParams};
+use crate::roles::{audit_line, Role};
+use crate::server::{unix_ms, GameServer};
+use crate::world_clock::parse_world_time;
+use crate::worldgen::MIN_PLANET_GAP;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+/*
+Admin commands for editing the world at runtime.
+
+The same commands are accepted on the server console (stdin) and remotely as
+an `Admin { token, command }` client message when the server was started
+with an admin token:
+
+    planet list
+    planet add size=<s> x=<x> y=<y> z=<z> [module=<0-255>] [colors=<rrggbb>,<rrggbb>,<rrggbb>] [name=<name>]
+    planet remove <id>
+    planet set <id> <size|module|x|y|z|color1|color2|color3|owner|resources|roughness|ocean_level|atmosphere|terrain_seed|name> <value>
+    players [-v]
+    stats [<player id>]
+    whois <name>
+    kick <player id>
+    ban <name>
+    unban <name>
+    bans
+    mute <name> [duration]
+    shadowmute <name> [duration]
+    unmute <name>
+    mutes
+    grant <name> <player|moderator|owner>
+    revoke <name>
+    roles
+    broadcast
+    announce <text>
+    reload
+    regenerate-world [seed]
+    fakes add|remove <n>
+    economy stats
+    map [<width>x<height>]
+    pause
+    step [n]
+    resume
+    settime <fraction>
+    validate-world [<path>]
+    promote
+
+Planets are addressed by their stable id, never by position in the list.
+`owner` takes a player id, or `none` to release the planet, and
+`resources` at most the planet's capacity (see `economy`). `roughness`
+and `ocean_level` take 0 to 1, `atmosphere` 0 to 255 and `terrain_seed`
+any u64 (see `TerrainParams`); added planets get terrain generated for
+their module type. `name` must be a valid planet name no other planet has;
+an added planet given one that is taken gets a number after it, and one
+given none is named (see `planet_names`).
+`planet list` shows how many players were near each planet at the last
+broadcast (see `population`). `players` lists spectators after the players; `players -v` and `stats <player id>` show
+each connection's traffic counters (see `stats`); `stats` alone shows the
+server's over all its runs (see `lifetime`). `whois` shows whether a
+player is online and how their last session went (see `session`).
+`kick` disconnects a player with the `Kicked` error code.
+`ban` turns away players joining under a name, however they write it, with
+the `Banned` error code, and `unban` lifts it; `bans` lists the banned names
+(see `bans`). A ban does not disconnect anyone already playing.
+`mute` stops a player's chats reaching anyone, telling them so, and
+`shadowmute` does the same while their chats look sent to them, either
+for a duration such as `10m` or `2h` or until `unmute`; `mutes` lists
+the mutes in force (see `mutes`).
+`grant` gives a name a role, replying with the admin token it is issued
+when it first gets one, `revoke` takes the role and token away, and
+`roles` lists who has what; remote commands need the role shown for them
+in `roles::required_role` (see `roles`).
+`broadcast` shows how long periodic broadcasts take to build (see `broadcast`),
+how long the server has been hibernating, if it is (see `hibernate`),
+how much the movement trails hold when they are on (see `trail`), and how
+many webhook events were sent, failed or dropped if there are webhooks (see
+`webhooks`).
+`announce` sends an `Announcement` to everyone; it may use `{player_count}`
+(see `announce`).
+A planet's size is its diameter; edits are rejected when the size is out of
+bounds or the planet would come within `min_planet_gap` of another, surface
+to surface, naming the planet it would crowd. `planet add` is refused once the world has
+`max_planets`, and `owner` once the player owns `max_planets_per_owner`
+(see `config`); added planets nobody visits or owns may be pruned (see
+`prune`). `reload` re-reads the server configuration
+(see `config`), like SIGHUP. `regenerate-world` replaces every planet with
+a freshly generated set, from `seed` or a random one, and moves everyone to
+a new spawn; the reply names the seed so the world can be made again.
+`fakes` adds or removes server-owned fake players (see `fakes`), removing
+the newest first. `economy stats` sums up the resources left on planets and
+notable asteroids and those held by players as cargo, online or not, and
+how fast the planets are growing theirs back.
+`map` prints a top-down ASCII map of the planets and players, 72x32
+characters unless a size is given (see `map`).
+`pause` freezes the simulation, `step` advances a paused one by n ticks
+(1 unless given) and `resume` lets it run again (see `pause`); `broadcast`
+shows whether it is paused and how many position updates are held.
+`settime` sets the time of day, 0 to 1 from midnight (1 is midnight again),
+and tells clients to snap to it (see `world_clock`).
+`validate-world` lists the pairs of planets closer than `min_planet_gap`,
+overlapping or not, in the running world or in the world file at `path`
+(saved or designed, see `world_file`), which need not be the one running.
+Worlds saved before the gap was kept may have some.
+`promote` makes a warm standby take over from the server it follows, which
+sends its clients here and stops (see `replication`); it fails until the
+standby has the primary's world.
+
+Dry runs:
+The destructive commands, `planet remove`, `kick`, `ban`, `revoke`,
+`regenerate-world` and `fakes remove`, take a `--dry-run` suffix (or
+`dry_run: true` in a remote `Admin` message). The command is checked and
+worked out exactly as it would run, up to the point where anything would
+change, and the reply is an `AdminPlan` instead: the players it would
+affect, the planets it would remove and add, and the files it would write
+with their sizes. Nothing changes and nothing is broadcast. The plan's
+`command` runs what was planned, e.g. `regenerate-world` with the seed the
+dry run picked. A remote dry run is answered with `AdminPlanned`; other
+commands refuse `--dry-run`.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlanetLimits {
+    pub min_size: f32,
+    pub max_size: f32,
+    pub min_gap: f32,  // between planets, surface to surface
+    pub max_planets: Option<usize>,  // unlimited when None
+    pub max_planets_per_owner: Option<usize>,
+}
+
+impl Default for PlanetLimits {
+    fn default() -> Self {
+        PlanetLimits { min_size: 10.0, max_size: 400.0, min_gap: MIN_PLANET_GAP, max_planets: None, max_planets_per_owner: None }
+    }
+}
+
+/// What a destructive command would do, or did (see Dry runs).
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AdminPlan {
+    pub command: String,  // runs what was planned
+    pub players_affected: Vec<AffectedPlayer>,  // kicked, moved or removed
+    pub planets_removed: Vec<u32>,
+    pub planets_added: usize,
+    pub files_written: Vec<FileWrite>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AffectedPlayer {
+    pub id: u32,
+    pub name: String,
+}
+
+/// A file a command rewrites, and how many bytes it will hold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FileWrite {
+    pub path: String,
+    pub bytes: u64,
+}
+
+impl FileWrite {
+    pub fn new(path: &Path, text: &str) -> Self {
+        FileWrite { path: path.display().to_string(), bytes: text.len() as u64 }
+    }
+}
+
+impl std::fmt::Display for AdminPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_none = |items: Vec<String>| if items.is_empty() { "none".to_string() } else { items.join(", ") };
+        write!(f, "dry run of `{}`: players affected: {}; planets removed: {}; planets added: {}; files written: {}",
+            self.command,
+            or_none(self.players_affected.iter().map(|p| format!("#{} {}", p.id, p.name)).collect()),
+            or_none(self.planets_removed.iter().map(u32::to_string).collect()),
+            self.planets_added,
+            or_none(self.files_written.iter().map(|w| format!("{} ({} bytes)", w.path, w.bytes)).collect()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanetEdit {
+    Size(f32),
+    ModuleType(u8),
+    X(f32),
+    Y(f32),
+    Z(f32),
+    Color(usize, Color),
+    Owner(Option<u32>),
+    Resources(u32),
+    Roughness(f32),
+    OceanLevel(f32),
+    Atmosphere(u8),
+    TerrainSeed(u64),
+    Name(String),
+}
+
+impl PlanetEdit {
+    pub fn apply(&self, planet: &mut Planet) {
+        match self {
+            PlanetEdit::Size(size) => planet.size = *size,
+            PlanetEdit::ModuleType(module_type) => planet.module_type = *module_type,
+            PlanetEdit::X(x) => planet.position.x = *x,
+            PlanetEdit::Y(y) => planet.position.y = *y,
+            PlanetEdit::Z(z) => planet.position.z = *z,
+            PlanetEdit::Color(i, color) => {
+                planet.colors[*i] = color.clone();
+                planet.refresh_display();
+            }
+            PlanetEdit::Owner(owner) => planet.owner = *owner,
+            PlanetEdit::Resources(resources) => planet.resources = *resources,
+            PlanetEdit::Roughness(roughness) => planet.terrain.roughness = *roughness,
+            PlanetEdit::OceanLevel(ocean_level) => planet.terrain.ocean_level = *ocean_level,
+            PlanetEdit::Atmosphere(atmosphere) => planet.terrain.atmosphere = *atmosphere,
+            PlanetEdit::TerrainSeed(seed) => planet.terrain.seed = *seed,
+            PlanetEdit::Name(name) => planet.name = name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    ListPlanets,
+    AddPlanet(Planet),  // the id is assigned when the planet is added
+    RemovePlanet { id: u32 },
+    SetPlanet { id: u32, edit: PlanetEdit },
+    ListPlayers { verbose: bool },
+    Stats { player_id: u32 },
+    LifetimeStats,
+    Whois { name: String },
+    Kick { player_id: u32 },
+    Ban { name: String },
+    Unban { name: String },
+    ListBans,
+    Mute { name: String, kind: MuteKind, duration: Option<Duration> },
+    Unmute { name: String },
+    ListMutes,
+    Grant { name: String, role: Role },
+    Revoke { name: String },
+    ListRoles,
+    BroadcastStats,
+    Announce { text: String },
+    Reload,
+    RegenerateWorld { seed: Option<u64> },
+    AddFakes { count: usize },
+    RemoveFakes { count: usize },
+    EconomyStats,
+    Map { size: MapSize },
+    Pause,
+    Step { ticks: u64 },
+    Resume,
+    SetTime { world_time: f32 },
+    ValidateWorld { path: Option<PathBuf> },
+    Promote,
+}
+
+/// A command line's command, and whether it ends in `--dry-run`.
+pub fn parse_request(line: &str) -> Result<(AdminCommand, bool), String> {
+    match line.trim_end().strip_suffix("--dry-run") {
+        Some(command) if command.is_empty() || command.ends_with(char::is_whitespace) => Ok((parse_command(command)?, true)),
+        _ => Ok((parse_command(line)?, false)),
+    }
+}
+
+pub fn parse_command(line: &str) -> Result<AdminCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["planet", "list"] => Ok(AdminCommand::ListPlanets),
+        ["planet", "add", params @ ..] => parse_new_planet(params).map(AdminCommand::AddPlanet),
+        ["planet", "remove", id] => Ok(AdminCommand::RemovePlanet { id: parse_id(id)? }),
+        ["planet", "set", id, field, value] => Ok(AdminCommand::SetPlanet { id: parse_id(id)?, edit: parse_edit(field, value)? }),
+        ["players"] => Ok(AdminCommand::ListPlayers { verbose: false }),
+        ["players", "-v"] => Ok(AdminCommand::ListPlayers { verbose: true }),
+        ["stats"] => Ok(AdminCommand::LifetimeStats),
+        ["stats", id] => Ok(AdminCommand::Stats { player_id: parse_number("player id", id)? }),
+        ["whois"] => Err("usage: whois <name>".to_string()),
+        ["whois", ..] => Ok(AdminCommand::Whois { name: line.trim()["whois".len()..].trim().to_string() }),
+        ["kick", id] => Ok(AdminCommand::Kick { player_id: parse_number("player id", id)? }),
+        ["ban" | "unban"] => Err(format!("usage: {} <name>", words[0])),
+        ["ban", ..] => Ok(AdminCommand::Ban { name: line.trim()["ban".len()..].trim().to_string() }),
+        ["unban", ..] => Ok(AdminCommand::Unban { name: line.trim()["unban".len()..].trim().to_string() }),
+        ["bans"] => Ok(AdminCommand::ListBans),
+        [command @ ("mute" | "shadowmute"), name, duration @ ..] if duration.len() <= 1 => {
+            let kind = if *command == "mute" { MuteKind::Mute } else { MuteKind::Shadow };
+            let duration = duration.first().map(|duration| parse_duration(duration)).transpose()?;
+            Ok(AdminCommand::Mute { name: name.to_string(), kind, duration })
+        }
+        ["mute" | "shadowmute", ..] => Err(format!("usage: {} <name> [duration]", words[0])),
+        ["unmute", name] => Ok(AdminCommand::Unmute { name: name.to_string() }),
+        ["unmute", ..] => Err("usage: unmute <name>".to_string()),
+        ["mutes"] => Ok(AdminCommand::ListMutes),
+        ["grant", _, .., role] => {
+            let name = line.trim()["grant".len()..].trim().strip_suffix(role).unwrap_or_default().trim().to_string();
+            Ok(AdminCommand::Grant { name, role: role.parse()? })
+        }
+        ["grant", ..] => Err("usage: grant <name> <player|moderator|owner>".to_string()),
+        ["revoke"] => Err("usage: revoke <name>".to_string()),
+        ["revoke", ..] => Ok(AdminCommand::Revoke { name: line.trim()["revoke".len()..].trim().to_string() }),
+        ["roles"] => Ok(AdminCommand::ListRoles),
+        ["broadcast"] => Ok(AdminCommand::BroadcastStats),
+        ["announce"] => Err("usage: announce <text>".to_string()),
+        ["announce", ..] => Ok(AdminCommand::Announce { text: line.trim()["announce".len()..].trim().to_string() }),
+        ["reload"] => Ok(AdminCommand::Reload),
+        ["regenerate-world"] => Ok(AdminCommand::RegenerateWorld { seed: None }),
+        ["regenerate-world", seed] => Ok(AdminCommand::RegenerateWorld { seed: Some(parse_number("seed", seed)?) }),
+        ["fakes", "add", count] => Ok(AdminCommand::AddFakes { count: parse_number("count", count)? }),
+        ["fakes", "remove", count] => Ok(AdminCommand::RemoveFakes { count: parse_number("count", count)? }),
+        ["fakes", ..] => Err("usage: fakes add|remove <n>".to_string()),
+        ["economy", "stats"] => Ok(AdminCommand::EconomyStats),
+        ["economy", ..] => Err("usage: economy stats".to_string()),
+        ["map"] => Ok(AdminCommand::Map { size: MapSize::ASCII }),
+        ["map", size] => Ok(AdminCommand::Map { size: size.parse()? }),
+        ["pause"] => Ok(AdminCommand::Pause),
+        ["step"] => Ok(AdminCommand::Step { ticks: 1 }),
+        ["step", ticks] => Ok(AdminCommand::Step { ticks: parse_number("tick count", ticks)? }),
+        ["resume"] => Ok(AdminCommand::Resume),
+        ["settime", time] => Ok(AdminCommand::SetTime { world_time: parse_world_time(time)? }),
+        ["settime", ..] => Err("usage: settime <fraction>".to_string()),
+        ["validate-world"] => Ok(AdminCommand::ValidateWorld { path: None }),
+        ["validate-world", ..] => Ok(AdminCommand::ValidateWorld { path: Some(line.trim()["validate-world".len()..].trim().into()) }),
+        ["promote"] => Ok(AdminCommand::Promote),
+        [] => Err("empty command".to_string()),
+        _ => Err(format!("unknown command: {} (try `planet list|add|remove|set`, `players`, `stats`, `whois`, `kick`, `ban`, `unban`, `bans`, `mute`, `shadowmute`, `unmute`, `mutes`, `grant`, `revoke`, `roles`, `broadcast`, `announce`, `reload`, `regenerate-world`, `fakes`, `economy stats`, `map`, `pause`, `step`, `resume`, `settime`, `validate-world` or `promote`)", line.trim())),
+    }
+}
+
+fn parse_id(id: &str) -> Result<u32, String> {
+    id.parse().map_err(|_| format!("invalid planet id: {}", id))
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid {}: {}", name, value))
+}
+
+/// A number from 0 to 1.
+fn parse_unit(name: &str, value: &str) -> Result<f32, String> {
+    let number: f32 = parse_number(name, value)?;
+    if !(0.0..=1.0).contains(&number) {
+        return Err(format!("{} must be between 0 and 1", name));
+    }
+    Ok(number)
+}
+
+fn parse_new_planet(params: &[&str]) -> Result<Planet, String> {
+    let grey = Color { r: 128, g: 128, b: 128 };
+    let mut planet = Planet {
+        id: 0,
+        size: f32::NAN,
+        colors: [grey.clone(), grey.clone(), grey],
+        module_type: 0,
+        position: Position { x: f32::NAN, y: f32::NAN, z: f32::NAN },
+        owner: None,
+        moons: Vec::new(),
+        resources: 0,
+        capacity: 0,
+        terrain: TerrainParams::default(),
+        name: String::new(),
+        primary_display_color: Color::default(),  // worked out by `add_planet`
+        label_contrast: LabelContrast::default(),
+    };
+    for param in params {
+        let (key, value) = param.split_once('=').ok_or_else(|| format!("expected key=value, got {}", param))?;
+        match key {
+            "size" => planet.size = parse_number(key, value)?,
+            "x" => planet.position.x = parse_number(key, value)?,
+            "y" => planet.position.y = parse_number(key, value)?,
+            "z" => planet.position.z = parse_number(key, value)?,
+            "module" => planet.module_type = parse_number(key, value)?,
+            "name" => planet.name = value.to_string(),
+            "colors" => {
+                let colors: Vec<Color> = value.split(',').map(Color::from_hex).collect::<Result<_, _>>()?;
+                planet.colors = colors.try_into().map_err(|_| "colors needs exactly three values".to_string())?;
+            }
+            other => return Err(format!("unknown planet parameter: {}", other)),
+        }
+    }
+    let p = &planet.position;
+    if planet.size.is_nan() || p.x.is_nan() || p.y.is_nan() || p.z.is_nan() {
+        return Err("planet add needs size, x, y and z".to_string());
+    }
+    planet.capacity = capacity_for(planet.size);
+    planet.resources = planet.capacity;
+    Ok(planet)
+}
+
+fn parse_edit(field: &str, value: &str) -> Result<PlanetEdit, String> {
+    Ok(match field {
+        "size" => PlanetEdit::Size(parse_number(field, value)?),
+        "module" | "module_type" => PlanetEdit::ModuleType(parse_number(field, value)?),
+        "x" => PlanetEdit::X(parse_number(field, value)?),
+        "y" => PlanetEdit::Y(parse_number(field, value)?),
+        "z" => PlanetEdit::Z(parse_number(field, value)?),
+        "color1" => PlanetEdit::Color(0, Color::from_hex(value)?),
+        "color2" => PlanetEdit::Color(1, Color::from_hex(value)?),
+        "color3" => PlanetEdit::Color(2, Color::from_hex(value)?),
+        "owner" if value == "none" => PlanetEdit::Owner(None),
+        "owner" => PlanetEdit::Owner(Some(parse_number("player id", value)?)),
+        "resources" => PlanetEdit::Resources(parse_number(field, value)?),
+        "roughness" => PlanetEdit::Roughness(parse_unit(field, value)?),
+        "ocean_level" => PlanetEdit::OceanLevel(parse_unit(field, value)?),
+        "atmosphere" => PlanetEdit::Atmosphere(parse_number(field, value)?),
+        "terrain_seed" => PlanetEdit::TerrainSeed(parse_number(field, value)?),
+        "name" => PlanetEdit::Name(value.to_string()),
+        other => return Err(format!("unknown planet field: {}", other)),
+    })
+}
+
+/// Checks `planet` against the limits and every other planet in `others`.
+pub fn validate_planet<'a>(
+    planet: &Planet,
+    others: impl IntoIterator<Item = &'a Planet>,
+    limits: &PlanetLimits,
+) -> Result<(), String> {
+    let p = &planet.position;
+    if ![planet.size, p.x, p.y, p.z].iter().all(|v| v.is_finite()) {
+        return Err("planet values must be finite".to_string());
+    }
+    if planet.size < limits.min_size || planet.size > limits.max_size {
+        return Err(format!("size must be between {} and {}", limits.min_size, limits.max_size));
+    }
+    if planet.resources > planet.capacity {
+        return Err(format!("resources must be at most the capacity, {}", planet.capacity));
+    }
+    planet.terrain.validate()?;
+    if !planet.name.is_empty() && !valid_planet_name(&planet.name) {
+        return Err(format!("invalid planet name: {}", planet.name));
+    }
+    let others: Vec<&Planet> = others.into_iter().filter(|o| o.id != planet.id).collect();
+    if let Some(other) = others.iter().find(|other| !planet.name.is_empty() && normalize_name(&other.name) == normalize_name(&planet.name)) {
+        return Err(format!("planet {} is already called {}", other.id, other.name));
+    }
+    match others.into_iter().find(|other| surface_gap(planet, other) < limits.min_gap) {
+        Some(other) => Err(TooClose { planet: planet.id, other: other.id, gap: surface_gap(planet, other) }.reason(limits.min_gap)),
+        None => Ok(()),
+    }
+}
+
+/// Checks that adding `planet` to `planets` (when `before` is `None`) or
+/// changing it from `before` stays within the planet counts in `limits`.
+/// Only new planets and new owners are counted, so a world loaded with more
+/// can still be edited.
+pub fn check_planet_counts(planet: &Planet, before: Option<&Planet>, planets: &[Planet], limits: &PlanetLimits) -> Result<(), String> {
+    if before.is_none()
+        && let Some(max) = limits.max_planets
+        && planets.len() >= max
+    {
+        return Err(format!("the world is full: it has {} planets, the most allowed", planets.len()));
+    }
+    if let Some(owner) = planet.owner
+        && before.and_then(|b| b.owner) != Some(owner)
+        && let Some(max) = limits.max_planets_per_owner
+    {
+        let owned = planets.iter().filter(|p| p.id != planet.id && p.owner == Some(owner)).count();
+        if owned >= max {
+            return Err(format!("player {} already owns {} planets, the most allowed", owner, owned));
+        }
+    }
+    Ok(())
+}
+
+/// How far apart the surfaces of two planets are; negative when they
+/// overlap.
+pub fn surface_gap(a: &Planet, b: &Planet) -> f32 {
+    let (pa, pb) = (&a.position, &b.position);
+    let distance = ((pa.x - pb.x).powi(2) + (pa.y - pb.y).powi(2) + (pa.z - pb.z).powi(2)).sqrt();
+    distance - (a.size + b.size) / 2.0
+}
+
+/// Two planets closer than the minimum gap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TooClose {
+    pub planet: u32,
+    pub other: u32,
+    pub gap: f32,  // negative when they overlap
+}
+
+impl TooClose {
+    /// Why `planet` cannot go where it is, given `min_gap`.
+    fn reason(&self, min_gap: f32) -> String {
+        if self.gap < 0.0 {
+            format!("overlaps planet {} by {:.1}", self.other, -self.gap)
+        } else {
+            format!("only {:.1} from planet {}, closer than the minimum gap of {}", self.gap, self.other, min_gap)
+        }
+    }
+}
+
+impl std::fmt::Display for TooClose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.gap < 0.0 {
+            write!(f, "planets {} and {} overlap by {:.1}", self.planet, self.other, -self.gap)
+        } else {
+            write!(f, "planets {} and {} are only {:.1} apart", self.planet, self.other, self.gap)
+        }
+    }
+}
+
+/// Every pair of `planets` closer than `min_gap`, in list order.
+pub fn too_close(planets: &[Planet], min_gap: f32) -> Vec<TooClose> {
+    let mut found = Vec::new();
+    for (i, planet) in planets.iter().enumerate() {
+        for other in &planets[i + 1..] {
+            let gap = surface_gap(planet, other);
+            if gap < min_gap {
+                found.push(TooClose { planet: planet.id, other: other.id, gap });
+            }
+        }
+    }
+    found
+}
+
+/// The `validate-world` report on `planets`.
+pub fn placement_report(planets: &[Planet], min_gap: f32) -> String {
+    let found = too_close(planets, min_gap);
+    if found.is_empty() {
+        return format!("{} planets, none closer than {}", planets.len(), min_gap);
+    }
+    let mut lines = vec![format!("{} planets, {} pair(s) closer than {}:", planets.len(), found.len(), min_gap)];
+    lines.extend(found.iter().map(TooClose::to_string));
+    lines.join("\n")
+}
+
+/// The planets of the world file at `path`, saved or designed; both keep
+/// them under `planets`.
+fn planets_in_file(path: &Path) -> Result<Vec<Planet>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let mut world: serde_json::Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_value(world["planets"].take()).map_err(|e| format!("{}: planets: {}", path.display(), e))
+}
+
+/// What a destructive command would do, worked out by the same code that
+/// runs it, changing nothing.
+pub fn plan(server: &GameServer, command: AdminCommand) -> Result<AdminPlan, String> {
+    match command {
+        AdminCommand::RemovePlanet { id } => server.plan_remove_planet(id, true),
+        AdminCommand::Kick { player_id } => server.plan_kick_player(player_id, true),
+        AdminCommand::Ban { name } => server.plan_ban(&name, true),
+        AdminCommand::Revoke { name } => server.plan_revoke_role(&name, true),
+        AdminCommand::RegenerateWorld { seed } => {
+            if !server.owns_planets() {
+                return Err("the planets belong to the cluster's primary instance".to_string());
+            }
+            Ok(server.plan_regenerate_world(seed.unwrap_or_else(rand::random), true).0)
+        }
+        AdminCommand::RemoveFakes { count } => Ok(server.plan_remove_fake_players(count, true).0),
+        _ => Err("only planet remove, kick, ban, revoke, regenerate-world and fakes remove have a dry run".to_string()),
+    }
+}
+
+/// Runs a command, returning the text to show the admin.
+pub fn execute(server: &GameServer, command: AdminCommand) -> Result<String, String> {
+    match command {
+        AdminCommand::ListPlanets => {
+            let population = server.planet_population();
+            let players = |id: u32| population.iter().find(|c| c.planet_id == id).map_or(0, |c| c.population);
+            let lines: Vec<String> = server.get_state().planets.iter()
+                .map(|p| format!("#{} {} size={:.1} module={} pos=({:.1}, {:.1}, {:.1}) players={} resources={}/{}{}",
+                    p.id, p.name, p.size, p.module_type, p.position.x, p.position.y, p.position.z, players(p.id), p.resources, p.capacity,
+                    p.owner.map(|owner| format!(" owner={}", owner)).unwrap_or_default()))
+                .collect();
+            Ok(if lines.is_empty() { "no planets".to_string() } else { lines.join("\n") })
+        }
+        AdminCommand::AddPlanet(planet) => {
+            let planet = server.add_planet(planet)?;
+            Ok(format!("added planet {}", planet.id))
+        }
+        AdminCommand::RemovePlanet { id } => {
+            server.remove_planet(id)?;
+            Ok(format!("removed planet {}", id))
+        }
+        AdminCommand::SetPlanet { id, edit } => {
+            server.update_planet(id, &edit)?;
+            Ok(format!("updated planet {}", id))
+        }
+        AdminCommand::ListPlayers { verbose } => {
+            let now = Instant::now();
+            let mut lines: Vec<String> = server.player_list(now).into_iter()
+                .map(|listing| {
+                    let p = &listing.player;
+                    let line = format!("#{} {} {} conn={} pos=({:.1}, {:.1}, {:.1}){}",
+                        p.id, p.name, listing.addr, listing.connection, p.position.x, p.position.y, p.position.z,
+                        if p.idle { " idle" } else { "" });
+                    if verbose { format!("{} {}", line, listing.stats) } else { line }
+                })
+                .collect();
+            if lines.is_empty() {
+                lines.push("no players".to_string());
+            }
+            let spectators = server.spectator_list(now);
+            if !spectators.is_empty() {
+                lines.push(format!("{} spectator(s):", spectators.len()));
+                lines.extend(spectators.into_iter().map(|listing| {
+                    let line = format!("spectator {} conn={}", listing.addr, listing.connection);
+                    if verbose { format!("{} {}", line, listing.stats) } else { line }
+                }));
+            }
+            Ok(lines.join("\n"))
+        }
+        AdminCommand::Stats { player_id } => server.connection_stats(player_id, Instant::now())
+            .map(|stats| format!("#{} {}", player_id, stats))
+            .ok_or_else(|| format!("no connected player with id {}", player_id)),
+        AdminCommand::LifetimeStats => Ok(server.lifetime_stats().to_string()),
+        AdminCommand::Whois { name } => {
+            let key = normalize_name(&name);
+            let online = server.player_list(Instant::now()).into_iter().find(|l| normalize_name(&l.player.name) == key);
+            let last = server.last_session(&name);
+            if online.is_none() && last.is_none() {
+                return Err(format!("nobody called {} has played since the server started", name));
+            }
+            let mut lines = Vec::new();
+            if let Some(listing) = online {
+                lines.push(format!("#{} {} is online from {}", listing.player.id, listing.player.name, listing.addr));
+            }
+            lines.push(match last {
+                Some(summary) => format!("last session: {}", summary),
+                None => "no finished session yet".to_string(),
+            });
+            Ok(lines.join("\n"))
+        }
+        AdminCommand::Kick { player_id } => server.kick_player(player_id),
+        AdminCommand::Ban { name } => server.ban(&name),
+        AdminCommand::Unban { name } => server.unban(&name),
+        AdminCommand::ListBans => {
+            let names = server.banned_names();
+            Ok(if names.is_empty() { "no bans".to_string() } else { names.join("\n") })
+        }
+        AdminCommand::Mute { name, kind, duration } => server.mute(&name, kind, duration),
+        AdminCommand::Unmute { name } => server.unmute(&name),
+        AdminCommand::ListMutes => {
+            let mutes = server.muted_names()?;
+            Ok(if mutes.is_empty() { "no mutes".to_string() } else { mutes.join("\n") })
+        }
+        AdminCommand::Grant { name, role } => server.grant_role(&name, role),
+        AdminCommand::Revoke { name } => server.revoke_role(&name),
+        AdminCommand::ListRoles => {
+            let profiles = server.role_profiles();
+            Ok(if profiles.is_empty() {
+                "nobody has a role".to_string()
+            } else {
+                profiles.iter().map(|profile| format!("{} {}", profile.name, profile.role)).collect::<Vec<_>>().join("\n")
+            })
+        }
+        AdminCommand::BroadcastStats => {
+            let hibernating = match server.hibernating_since() {
+                Some(since) => format!("{:.0}s", since.elapsed().as_secs_f64()),
+                None => "no".to_string(),
+            };
+            let paused = match server.paused_since() {
+                Some(since) => format!("{:.0}s held={}", since.elapsed().as_secs_f64(), server.held_updates()),
+                None => "no".to_string(),
+            };
+            let trails = server.trail_stats().map(|stats| format!(" {}", stats)).unwrap_or_default();
+            let webhooks = server.webhook_stats().map(|stats| format!(" {}", stats)).unwrap_or_default();
+            Ok(format!("{} paused={} hibernating={}{}{}", server.broadcast_stats(), paused, hibernating, trails, webhooks))
+        }
+        AdminCommand::Announce { text } => server.announce(&text).map(|text| format!("announced: {}", text)),
+        AdminCommand::Reload => server.reload_config(),
+        AdminCommand::RegenerateWorld { seed } => {
+            if !server.owns_planets() {
+                return Err("the planets belong to the cluster's primary instance".to_string());
+            }
+            let reset = server.regenerate_world(seed);
+            Ok(format!("regenerated the world from seed {}: {} planets, {} player(s) moved", reset.seed, reset.planets, reset.players))
+        }
+        AdminCommand::AddFakes { count } => {
+            let added = server.add_fake_players(count);
+            Ok(format!("added {} fake player(s), {} in all", added.len(), server.fake_player_count()))
+        }
+        AdminCommand::RemoveFakes { count } => {
+            let removed = server.remove_fake_players(count);
+            Ok(format!("removed {} fake player(s), {} left", removed.len(), server.fake_player_count()))
+        }
+        AdminCommand::EconomyStats => Ok(server.economy_stats().to_string()),
+        AdminCommand::Map { size } => Ok(map::render_ascii(&*server.snapshot(), size)),
+        AdminCommand::Pause => server.pause(),
+        AdminCommand::Step { ticks } => server.step(ticks),
+        AdminCommand::Resume => server.resume(),
+        AdminCommand::SetTime { world_time } => server.set_world_time(world_time),
+        AdminCommand::ValidateWorld { path } => {
+            let planets = match path {
+                Some(path) => planets_in_file(&path)?,
+                None => server.get_state().planets,
+            };
+            Ok(placement_report(&planets, server.planet_limits().min_gap))
+        }
+        AdminCommand::Promote => server.promote(),
+    }
+}
+
+/// Runs `command`, or for a dry run shows its plan.
+pub fn run(server: &GameServer, command: AdminCommand, dry_run: bool) -> Result<String, String> {
+    if dry_run {
+        return plan(server, command).map(|plan| plan.to_string());
+    }
+    execute(server, command)
+}
+
+/// Parses and runs one command line, a dry run if it ends in `--dry-run`.
+pub fn run_line(server: &GameServer, line: &str) -> Result<String, String> {
+    let (command, dry_run) = parse_request(line)?;
+    run(server, command, dry_run)
+}
+
+/// Reads admin commands from the server's stdin until it closes.
+pub fn spawn_console(server: GameServer) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let outcome = run_line(&server, &line);
+            match &outcome {
+                Ok(output) => println!("🛠️  {}", output),
+                Err(e) => println!("❌ {}", e),
+            }
+            println!("{}", audit_line("console", Role::Owner, &line, &outcome, unix_ms()));
+        }
+    })
+}
@@ -0,0 +1,154 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Serialize, Deserialize};
+use crate::protocol::{AmbientEvent, AmbientKind, Planet, Position, ServerMessage};
+
+/*
+Ambient events: every `every_ticks` ticks the server picks a kind, by the
+configured weights, and a planet, at random, and starts an event around it
+lasting `duration_ticks`.
+
+- SolarFlare: players within `FLARE_RANGE` of the planet's surface may
+  move at no more than `FLARE_SPEED_FACTOR` of the usual top speed; faster
+  position updates are rejected like any other too-fast one.
+- MeteorShower: mining the planet yields `SHOWER_YIELD` times the units
+  taken from it, the extra falling from the sky.
+- Aurora: for show; clients may draw it.
+
+Everyone gets a `WorldEvent` as each one starts and ends, and players and
+spectators who join meanwhile get one for each event under way. Events
+overlap when they last longer than the interval between them.
+
+With a `seed` the schedule, kinds and planets alike, is the same on every
+run over the same world. Events are not journaled, so a replay or a
+standby does not see them.
+*/
+
+/// How long each event lasts unless configured otherwise.
+pub const DEFAULT_AMBIENT_DURATION_TICKS: u64 = 300;
+
+/// How far from a flaring planet's surface players are slowed.
+pub const FLARE_RANGE: f32 = 200.0;
+
+/// The fraction of the top speed allowed near a flaring planet.
+pub const FLARE_SPEED_FACTOR: f32 = 0.5;
+
+/// Units a meteor shower adds to the cargo for each one mined.
+pub const SHOWER_YIELD: u32 = 2;
+
+fn distance(a: &Position, b: &Position) -> f64 {
+    let (dx, dy, dz) = ((a.x - b.x) as f64, (a.y - b.y) as f64, (a.z - b.z) as f64);
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+/// How often each kind is picked, relative to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AmbientWeights {
+    pub solar_flare: u32,
+    pub meteor_shower: u32,
+    pub aurora: u32,
+}
+
+impl Default for AmbientWeights {
+    fn default() -> Self {
+        AmbientWeights { solar_flare: 1, meteor_shower: 1, aurora: 2 }
+    }
+}
+
+impl AmbientWeights {
+    fn total(&self) -> u64 {
+        self.solar_flare as u64 + self.meteor_shower as u64 + self.aurora as u64
+    }
+
+    fn pick(&self, rng: &mut StdRng) -> AmbientKind {
+        let roll = rng.gen_range(0..self.total());
+        if roll < self.solar_flare as u64 {
+            AmbientKind::SolarFlare
+        } else if roll < self.solar_flare as u64 + self.meteor_shower as u64 {
+            AmbientKind::MeteorShower
+        } else {
+            AmbientKind::Aurora
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmbientConfig {
+    pub every_ticks: u64,
+    pub duration_ticks: u64,
+    pub weights: AmbientWeights,
+    pub seed: Option<u64>,
+}
+
+impl AmbientConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.duration_ticks == 0 {
+            return Err("ambient_duration_ticks must be positive".to_string());
+        }
+        if self.weights.total() == 0 {
+            return Err("ambient_weights must not all be zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The schedule and the events under way.
+#[derive(Debug)]
+pub struct Ambient {
+    config: AmbientConfig,
+    rng: StdRng,
+    next_id: u32,
+    next_start: u64,
+    active: Vec<AmbientEvent>,
+}
+
+impl Ambient {
+    pub fn new(config: AmbientConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Ambient { config, rng, next_id: 1, next_start: config.every_ticks, active: Vec::new() }
+    }
+
+    pub fn active(&self) -> &[AmbientEvent] {
+        &self.active
+    }
+
+    /// Ends the events over by `tick` and starts the one due, if any, on
+    /// one of `planets`; returns a `WorldEvent` for each change. A tick
+    /// skipped ahead to starts one event, not every one missed.
+    pub fn advance(&mut self, tick: u64, planets: &[Planet]) -> Vec<ServerMessage> {
+        let mut changes = Vec::new();
+        let (over, active): (Vec<_>, Vec<_>) = std::mem::take(&mut self.active).into_iter().partition(|e| e.end_tick() <= tick);
+        self.active = active;
+        changes.extend(over.into_iter().map(|event| ServerMessage::WorldEvent { event, active: false }));
+
+        if tick < self.next_start || planets.is_empty() {
+            return changes;
+        }
+        self.next_start = tick + self.config.every_ticks;
+        let kind = self.config.weights.pick(&mut self.rng);
+        let planet = &planets[self.rng.gen_range(0..planets.len())];
+        let event = AmbientEvent { id: self.next_id, kind, planet_id: planet.id, start_tick: tick, duration_ticks: self.config.duration_ticks };
+        self.next_id += 1;
+        self.active.push(event.clone());
+        changes.push(ServerMessage::WorldEvent { event, active: true });
+        changes
+    }
+
+    /// Whether a meteor shower is falling on `planet_id`.
+    pub fn is_showering(&self, planet_id: u32) -> bool {
+        self.active.iter().any(|e| e.kind == AmbientKind::MeteorShower && e.planet_id == planet_id)
+    }
+
+    /// The top speed at `position`, `max_speed` lowered near a flare.
+    pub fn max_speed_at(&self, max_speed: f32, position: &Position, planets: &[Planet]) -> f32 {
+        let flaring = self.active.iter()
+            .filter(|e| e.kind == AmbientKind::SolarFlare)
+            .filter_map(|e| planets.iter().find(|p| p.id == e.planet_id))
+            .any(|p| distance(&p.position, position) - (p.size / 2.0) as f64 <= FLARE_RANGE as f64);
+        if flaring { max_speed * FLARE_SPEED_FACTOR } else { max_speed }
+    }
+}
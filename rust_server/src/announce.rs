@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/*
+Server announcements: the message of the day sent to each player right after
+their join ack, `announce` from the admin console, and announcements repeated
+on a schedule from the config:
+
+    motd = "Welcome, {name}! {player_count} players online."
+
+    [[announcements]]
+    schedule = "every 30m"
+    text = "The outer belt is still unexplored"
+
+Templates may use `{player_count}` (connected players) and, in the message of
+the day only, `{name}` (the joining player); `{{` and `}}` are literal braces.
+Schedules are `every <n>s`, `<n>m` or `<n>h`, at least `MIN_INTERVAL` apart.
+Both are checked when the config is loaded, so a typo stops the server from
+starting rather than surfacing at broadcast time.
+
+Announcements go out as `Announcement` frames, a message type of their own
+rather than chat or a notice, so clients can style them.
+*/
+
+pub const MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// One `[[announcements]]` entry of the config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduledAnnouncement {
+    pub schedule: String,
+    pub text: String,
+}
+
+impl ScheduledAnnouncement {
+    /// The interval and the checked template.
+    pub fn parse(&self) -> Result<(Duration, String), String> {
+        let every = parse_schedule(&self.schedule)?;
+        render(&self.text, &Placeholders { player_count: 0, name: None })
+            .map_err(|e| format!("announcement {:?}: {}", self.text, e))?;
+        Ok((every, self.text.clone()))
+    }
+}
+
+/// The values a template may refer to; `name` only exists for the message
+/// of the day, which is rendered for one player.
+#[derive(Debug, Clone, Copy)]
+pub struct Placeholders<'a> {
+    pub player_count: usize,
+    pub name: Option<&'a str>,
+}
+
+/// `template` with its placeholders filled in.
+pub fn render(template: &str, values: &Placeholders) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{") {
+            out.push('{');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("}}") {
+            out.push('}');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix('{') {
+            let end = after.find('}').ok_or("unclosed `{` (write `{{` for a brace)")?;
+            match (&after[..end], values.name) {
+                ("player_count", _) => out.push_str(&values.player_count.to_string()),
+                ("name", Some(name)) => out.push_str(name),
+                ("name", None) => return Err("`{name}` is only available in the message of the day".to_string()),
+                (other, _) => return Err(format!("unknown placeholder `{{{}}}`", other)),
+            }
+            rest = &after[end + 1..];
+        } else {
+            return Err("unmatched `}` (write `}}` for a brace)".to_string());
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// How often an `every <n><s|m|h>` schedule repeats.
+pub fn parse_schedule(schedule: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid schedule {:?} (expected e.g. `every 30m`)", schedule);
+    let amount = schedule.trim().strip_prefix("every").filter(|a| a.starts_with(' ')).ok_or_else(invalid)?.trim();
+    let unit_at = amount.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let count: u64 = amount[..unit_at].parse().map_err(|_| invalid())?;
+    let unit = match &amount[unit_at..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(invalid()),
+    };
+    let every = Duration::from_secs(count.checked_mul(unit).ok_or_else(invalid)?);
+    if every < MIN_INTERVAL {
+        return Err(format!("schedule {:?} repeats more often than every {}s", schedule, MIN_INTERVAL.as_secs()));
+    }
+    Ok(every)
+}
@@ -0,0 +1,42 @@
+use crate::protocol::{Color, PlayerAppearance, SHIP_MODELS};
+
+/*
+How players look to everyone else.
+
+A new player's colors and ship model come from a hash of their name, so the
+same name always looks the same, on any server and across restarts. The hash
+is FNV-1a, spelled out here rather than taken from `std`, whose hasher may
+change between Rust releases. A player may replace it with `SetAppearance`;
+any colors are allowed, but the model must be one clients know how to draw.
+*/
+
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a of `bytes`, carrying on from `hash`.
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+fn name_hash(name: &str) -> u64 {
+    fnv1a(FNV_OFFSET, name.as_bytes())
+}
+
+impl PlayerAppearance {
+    /// The appearance a player with this name starts with.
+    pub fn for_name(name: &str) -> Self {
+        let [r1, g1, b1, r2, g2, b2, model, _] = name_hash(name).to_le_bytes();
+        PlayerAppearance {
+            primary: Color { r: r1, g: g1, b: b1 },
+            secondary: Color { r: r2, g: g2, b: b2 },
+            model: model % SHIP_MODELS,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.model >= SHIP_MODELS {
+            return Err(format!("unknown ship model {} (models are 0 to {})", self.model, SHIP_MODELS - 1));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+/*
+Per-connection bandwidth budgets, so that clients on poor links are slowed
+down rather than dropped.
+
+With a `bandwidth_budget` set, each connection's `BandwidthGovernor` looks at
+the bytes written to it (the `stats` counter) once per `WINDOW`. A window
+over budget raises the connection's level by one, up to `MAX_LEVEL`; at
+level n it gets the State of only every 2^n-th tick (1, 2, then 4), which
+roughly divides what it costs by as much. Events (planet edits, teleports
+and the like) still go out every tick, since there is no later State that
+would repeat them for a client that missed them. Once a window's usage,
+doubled as stepping back up would double it, fits in `RESTORE_SHARE` of the
+budget, the level drops by one again, so a client hovering around its
+budget does not flap between rates.
+
+The level is part of the connection's `stats` (`players -v`, `stats <id>`)
+and of its `DebugStats` frames. There is no interest management yet, so
+the area a client hears about stays the whole world at every level. Like the
+rate limiter, everything takes an explicit `now`.
+*/
+
+/// How often usage is measured and the level reconsidered.
+pub const WINDOW: Duration = Duration::from_secs(1);
+
+/// Highest level: a State every 2^MAX_LEVEL = 4 ticks.
+pub const MAX_LEVEL: u8 = 2;
+
+/// Share of the budget a window's doubled usage must fit in to step back up.
+pub const RESTORE_SHARE: f64 = 0.8;
+
+#[derive(Debug, Clone)]
+pub struct BandwidthGovernor {
+    budget: Option<u64>,  // bytes per second; None for unlimited
+    level: u8,
+    window_start: Instant,
+    window_bytes: u64,  // bytes sent when the window started
+}
+
+impl BandwidthGovernor {
+    /// A governor for a connection that has sent `bytes_sent` bytes so far.
+    pub fn new(budget: Option<u64>, bytes_sent: u64, now: Instant) -> Self {
+        BandwidthGovernor { budget, level: 0, window_start: now, window_bytes: bytes_sent }
+    }
+
+    /// Switches to a new budget; without one the connection is back to full rate.
+    pub fn set_budget(&mut self, budget: Option<u64>) {
+        self.budget = budget;
+        if budget.is_none() {
+            self.level = 0;
+        }
+    }
+
+    /// Takes the connection's running total of bytes sent. When a window
+    /// has passed, measures it and returns the new level if it changed.
+    pub fn observe(&mut self, bytes_sent: u64, now: Instant) -> Option<u8> {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < WINDOW {
+            return None;
+        }
+        let rate = bytes_sent.saturating_sub(self.window_bytes) as f64 / elapsed.as_secs_f64();
+        self.window_start = now;
+        self.window_bytes = bytes_sent;
+        let budget = self.budget? as f64;
+        let before = self.level;
+        if rate > budget {
+            self.level = (self.level + 1).min(MAX_LEVEL);
+        } else if self.level > 0 && rate * 2.0 <= budget * RESTORE_SHARE {
+            self.level -= 1;
+        }
+        (self.level != before).then_some(self.level)
+    }
+
+    /// 0 at full rate, up to `MAX_LEVEL`.
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// The connection gets the State of one tick in this many.
+    pub fn state_every(&self) -> u64 {
+        1 << self.level
+    }
+
+    /// Whether the connection gets the State of `tick`.
+    pub fn wants_state(&self, tick: u64) -> bool {
+        tick.is_multiple_of(self.state_every())
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::protocol::normalize_name;
+
+/*
+Banned player names, kept across restarts in the file given as `ban_file`.
+
+Names are compared by `normalize_name`, so a ban on `Player` also turns
+away `player`, ` Player ` and `Рlayer` with a Cyrillic Р. The file has one
+name per line as the admin typed it; blank lines and lines starting with
+`#` are ignored. Entries are normalized when the file is read, so files
+written by hand or before normalization keep working, and lines that now
+mean the same name become one entry, written back once on the next change.
+
+The admin `ban`, `unban` and `bans` commands edit and list the bans; every
+change rewrites the file. Without a file bans last until the server stops.
+Players have no accounts, so a ban is only on the name: a banned player can
+still join under another one.
+*/
+
+#[derive(Debug, Clone, Default)]
+pub struct BanList {
+    path: Option<PathBuf>,
+    names: BTreeMap<String, String>,  // normalized name -> as typed
+}
+
+impl BanList {
+    /// An empty list kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bans in `path`, saved back there on every change. A missing file
+    /// is an empty list, created on the first ban.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(BanList { path: Some(path.to_path_buf()), ..Self::parse(&text) })
+    }
+
+    /// The bans listed in a file's text.
+    pub fn parse(text: &str) -> Self {
+        let mut bans = Self::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            bans.names.entry(normalize_name(line)).or_insert_with(|| line.to_string());
+        }
+        bans
+    }
+
+    /// The file's text: one name per line, in normalized order.
+    pub fn to_file_text(&self) -> String {
+        self.names.values().map(|name| format!("{}\n", name)).collect()
+    }
+
+    pub fn is_banned(&self, name: &str) -> bool {
+        self.names.contains_key(&normalize_name(name))
+    }
+
+    /// Bans `name`; false if it (or a name that normalizes the same) already was.
+    pub fn ban(&mut self, name: &str) -> io::Result<bool> {
+        let name = name.trim();
+        let key = normalize_name(name);
+        if key.is_empty() || self.names.contains_key(&key) {
+            return Ok(false);
+        }
+        self.names.insert(key, name.to_string());
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Lifts the ban matching `name`, returning the name as it was banned.
+    pub fn unban(&mut self, name: &str) -> io::Result<Option<String>> {
+        let Some(banned) = self.names.remove(&normalize_name(name)) else { return Ok(None) };
+        self.save()?;
+        Ok(Some(banned))
+    }
+
+    /// Where the list is saved, if anywhere.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// A copy that changes without saving, to see what a change would write.
+    pub fn detached(&self) -> Self {
+        BanList { path: None, names: self.names.clone() }
+    }
+
+    /// The banned names as typed, in normalized order.
+    pub fn names(&self) -> Vec<String> {
+        self.names.values().cloned().collect()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => std::fs::write(path, self.to_file_text()),
+            None => Ok(()),
+        }
+    }
+}
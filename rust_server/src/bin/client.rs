@@ -1,108 +1,1153 @@
-use tokio_tungstenite::{
-    connect_async,
-    tungstenite::protocol::Message,
+use galavox::client::{ClientEvent, Connection, EventDecoder};
+use galavox::multi::{MultiConnection, MultiEvent, MultiOptions};
+use galavox::protocol::{
+    seq_newer, speed, Capabilities, ClientMessage, Color, ErrorCode, GameState, PlanetSort, Player, PlayerAppearance, Position, ServerMessage,
 };
-use futures_util::StreamExt;
-use serde::{Serialize, Deserialize};
+use galavox::capture::{read_capture, CaptureWriter, CapturedFrame};
+use galavox::chat_history::JOIN_HISTORY;
+use galavox::interpolation::Interpolator;
+use galavox::output::{
+    client_error_record, connected_record, connecting_record, event_record, is_error, reconnecting_record, violation_record,
+};
+use galavox::query::MAX_QUERY_RESULTS;
+use galavox::time_sync::{estimate_clock_offset, ClockSample};
+use galavox::traffic::ConnectionStats;
+use galavox::validate::{Validator, Violation};
+use galavox::world_clock::Phase;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const SERVER_URL: &str = "ws://localhost:8080";
+const BOT_UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+const TIME_SYNC_INTERVAL: Duration = Duration::from_secs(1);
+const TIME_SYNC_SAMPLES: usize = 5;
+const RENDER_INTERVAL: Duration = Duration::from_millis(100);
+const INTERPOLATION_DELAY_MS: u64 = 100;  // render this far behind the server
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// What goes to stdout: the human-readable lines, or with `--output json`
+/// one JSON record per line (see `galavox::output`). `--quiet` keeps only
+/// errors either way.
+#[derive(Clone, Copy, Default)]
+struct Output {
+    json: bool,
+    quiet: bool,
+}
+
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+fn output() -> Output {
+    OUTPUT.get().copied().unwrap_or_default()
+}
+
+thread_local! {
+    /// With several `--url`s, the server whose event is being handled.
+    static SOURCE: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with its output attributed to `source`.
+fn with_source<T>(source: &Arc<str>, f: impl FnOnce() -> T) -> T {
+    SOURCE.set(Some(source.clone()));
+    let result = f();
+    SOURCE.set(None);
+    result
+}
+
+/// Prints a human-readable line, prefixed with its source if there is one.
+fn print_line(line: std::fmt::Arguments) {
+    match SOURCE.with_borrow(Clone::clone) {
+        Some(source) => {
+            let line = line.to_string();
+            let text = line.trim_start_matches('\n');
+            println!("{}[{}] {}", &line[..line.len() - text.len()], source, text);
+        }
+        None => println!("{}", line),
+    }
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Position {
-    x: f32,
-    y: f32,
-    z: f32,
+/// A human-readable line, unless `--output json` or `--quiet`.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !output().json && !output().quiet {
+            print_line(format_args!($($arg)*));
+        }
+    };
+}
+
+/// A human-readable error line, which `--quiet` keeps.
+macro_rules! say_error {
+    ($($arg:tt)*) => {
+        if !output().json {
+            print_line(format_args!($($arg)*));
+        }
+    };
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Planet {
-    size: f32,
-    colors: [Color; 3],
-    module_type: u8,
-    position: Position,
+/// Prints a JSON record, with a `source` field if it has a source.
+fn emit(mut record: serde_json::Value) {
+    if let (Some(source), Some(fields)) = (SOURCE.with_borrow(Clone::clone), record.as_object_mut()) {
+        fields.insert("source".to_string(), source.to_string().into());
+    }
+    println!("{}", record);
+}
+
+/// A lifecycle record, with `--output json` and without `--quiet`.
+fn emit_lifecycle(record: impl FnOnce(u64) -> serde_json::Value) {
+    if output().json && !output().quiet {
+        emit(record(now_ms()));
+    }
+}
+
+/// Protocol violations found with `--validate`, over every session.
+static VIOLATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Prints a violation found with `--validate`, which `--quiet` keeps.
+fn report_violation(violation: &Violation) {
+    VIOLATIONS.fetch_add(1, Ordering::Relaxed);
+    if output().json {
+        emit(violation_record(violation, now_ms()));
+    } else {
+        println!("❗ Protocol violation: {}", violation);
+    }
+}
+
+/// The JSON record for a received event, with `--output json`.
+fn emit_event(event: &ClientEvent) {
+    let out = output();
+    if out.json && (!out.quiet || is_error(event))
+        && let Some(record) = event_record(event, now_ms())
+    {
+        emit(record);
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Player {
-    id: u32,
-    name: String,
-    level: u32,
+/// How many of the bot's position updates the server has acknowledged.
+#[derive(Default)]
+struct BotStats {
+    sent: u32,
+    last_sent_seq: u32,
+    last_applied_seq: Option<u32>,
+    acks_observed: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct GameState {
-    planets: Vec<Planet>,
-    players: Vec<Player>,
-    initial_player_location: Position,
+/// What the client knows about the world and prints about it. Live frames and
+/// recorded captures (`--playback`) both go through `handle_event`.
+struct ClientView {
+    own_id: Option<u32>,
+    bot_mode: bool,
+    once: bool,  // the first full state ends it
+    game_state: Option<GameState>,
+    clock_samples: VecDeque<ClockSample>,
+    interpolator: Interpolator,
+    // Idle players are left out of broadcasts; remember them so they can be shown greyed out
+    idle_players: HashMap<u32, Player>,
+    bot_stats: BotStats,
+    oldest_chat_ms: Option<u64>,  // of the earliest chat history line shown
+    phase: Option<Phase>,  // of the world's time of day, last shown
+}
+
+/// Shared with the connection's frame hook, which writes every received frame.
+type Recorder = Arc<Mutex<Option<CaptureWriter<BufWriter<File>>>>>;
+
+struct Args {
+    urls: Vec<String>,  // more than one are watched at once (see `run_multi`)
+    name: Option<String>,  // sent in the handshake; the server picks one otherwise
+    spectate: bool,  // watch without a player
+    bot: bool,
+    insecure: bool,  // skip TLS certificate verification (self-signed dev certs)
+    record: Option<PathBuf>,
+    playback: Option<PathBuf>,
+    speed: f64,  // playback speed multiplier; 0 ignores the recorded timing
+    admin_token: Option<String>,  // send other stdin lines as admin commands
+    reconnect: bool,  // when the server's close code allows it
+    output: Output,
+    once: bool,  // exit after the first full state
+    validate: bool,  // check what the server sends; exit non-zero if it breaks the protocol
+    crc: bool,  // ask for CRC trailers on binary frames, to catch corruption in transit
+    stats: bool,  // print the traffic summary every STATS_INTERVAL, not just on exit
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        urls: Vec::new(),
+        name: None,
+        spectate: false,
+        bot: false,
+        insecure: false,
+        record: None,
+        playback: None,
+        speed: 1.0,
+        admin_token: None,
+        reconnect: false,
+        output: Output::default(),
+        once: false,
+        validate: false,
+        crc: false,
+        stats: false,
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bot" => args.bot = true,
+            "--spectate" => args.spectate = true,
+            "--insecure" => args.insecure = true,
+            "--reconnect" => args.reconnect = true,
+            "--quiet" => args.output.quiet = true,
+            "--once" => args.once = true,
+            "--validate" => args.validate = true,
+            "--crc" => args.crc = true,
+            "--stats" => args.stats = true,
+            "--output" => args.output.json = match iter.next().ok_or("--output needs a value")?.as_str() {
+                "human" => false,
+                "json" => true,
+                other => return Err(format!("unknown --output {:?} (human or json)", other)),
+            },
+            "--url" => args.urls.push(iter.next().ok_or("--url needs a value")?),
+            "--name" => args.name = Some(iter.next().ok_or("--name needs a value")?),
+            "--admin-token" => args.admin_token = Some(iter.next().ok_or("--admin-token needs a value")?),
+            "--record" => args.record = Some(iter.next().ok_or("--record needs a path")?.into()),
+            "--playback" => args.playback = Some(iter.next().ok_or("--playback needs a path")?.into()),
+            "--speed" => {
+                args.speed = iter.next().ok_or("--speed needs a value")?.parse().map_err(|_| "--speed needs a number")?;
+                if !(args.speed >= 0.0 && args.speed.is_finite()) {
+                    return Err("--speed must be zero or positive".into());
+                }
+            }
+            other => return Err(format!("unknown argument: {}", other)),
+        }
+    }
+    if args.bot && args.spectate {
+        return Err("spectators cannot fly, so --bot and --spectate do not mix".into());
+    }
+    if args.urls.is_empty() {
+        args.urls.push(SERVER_URL.to_string());
+    }
+    if args.urls.len() > 1 && (args.bot || args.record.is_some() || args.once || args.validate || args.stats) {
+        return Err("--bot, --record, --once, --validate and --stats take a single --url".into());
+    }
+    Ok(args)
+}
+
+/// A typed stdin line as the message to send, `None` for a blank line.
+/// `position` is our own, when known, for commands relative to it, and
+/// `oldest_chat` the time of the earliest chat line shown, which `history`
+/// pages back from.
+fn parse_command(line: &str, admin_token: Option<&str>, position: Option<Position>, oldest_chat: Option<u64>) -> Result<Option<ClientMessage>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    Ok(Some(match (words.as_slice(), admin_token) {
+        ([], _) => return Ok(None),
+        (["respawn"], _) => ClientMessage::Respawn {},
+        (["tp", planet], _) => match planet.parse() {
+            Ok(planet_id) => ClientMessage::TeleportToPlanet { planet_id },
+            Err(_) => ClientMessage::TeleportToPlanetNamed { name: planet.to_string() },
+        },
+        (["tp", ..], _) => return Err("usage: tp <planet id or name>".to_string()),
+        (["land", planet], _) => match planet.parse() {
+            Ok(planet_id) => ClientMessage::Land { planet_id },
+            Err(_) => ClientMessage::LandNamed { name: planet.to_string() },
+        },
+        (["land", ..], _) => return Err("usage: land <planet id or name>".to_string()),
+        (["name-planet", planet_id, name], _) => ClientMessage::RenamePlanet {
+            planet_id: planet_id.parse().map_err(|_| format!("invalid planet id: {}", planet_id))?,
+            name: name.to_string(),
+        },
+        (["name-planet", ..], _) => return Err("usage: name-planet <planet id> <name>".to_string()),
+        (["takeoff"], _) => ClientMessage::TakeOff {},
+        (["mine", amount], _) => ClientMessage::Mine {
+            amount: amount.parse().map_err(|_| format!("invalid amount: {}", amount))?,
+        },
+        (["mine", ..], _) => return Err("usage: mine <amount>".to_string()),
+        (["sell", amount], _) => ClientMessage::Sell {
+            amount: amount.parse().map_err(|_| format!("invalid amount: {}", amount))?,
+        },
+        (["sell", ..], _) => return Err("usage: sell <amount>".to_string()),
+        (["rename", name], _) => ClientMessage::Join { name: name.to_string() },
+        (["rename", ..], _) => return Err("usage: rename <name>".to_string()),
+        (["ship", model, primary, secondary], _) => ClientMessage::SetAppearance {
+            appearance: PlayerAppearance {
+                primary: Color::from_hex(primary)?,
+                secondary: Color::from_hex(secondary)?,
+                model: model.parse().map_err(|_| format!("invalid ship model: {}", model))?,
+            },
+        },
+        (["ship", ..], _) => return Err("usage: ship <model> <rrggbb> <rrggbb>".to_string()),
+        (["discoveries"], _) => ClientMessage::Discoveries {},
+        (["nearby", radius], _) => ClientMessage::QueryPlanets {
+            center: position.ok_or("our position is not known yet")?,
+            radius: radius.parse().map_err(|_| format!("invalid radius: {}", radius))?,
+            max_results: MAX_QUERY_RESULTS,
+        },
+        (["nearby", ..], _) => return Err("usage: nearby <radius>".to_string()),
+        (["trail", player_id], _) => ClientMessage::QueryTrail {
+            player_id: player_id.parse().map_err(|_| format!("invalid player id: {}", player_id))?,
+        },
+        (["trail", ..], _) => return Err("usage: trail <player id>".to_string()),
+        (["debug", "on"], _) => ClientMessage::DebugStats { enabled: true },
+        (["debug", "off"], _) => ClientMessage::DebugStats { enabled: false },
+        (["debug", ..], _) => return Err("usage: debug on|off".to_string()),
+        (["subscribe", mask], _) => ClientMessage::SetSubscriptions {
+            mask: mask.parse().map_err(|_| format!("invalid mask: {}", mask))?,
+        },
+        (["subscribe", ..], _) => return Err("usage: subscribe <mask>".to_string()),
+        (["history"], _) => ClientMessage::MoreHistory { before_timestamp: oldest_chat.unwrap_or(u64::MAX), limit: JOIN_HISTORY as u32 },
+        (["history", count], _) => ClientMessage::MoreHistory {
+            before_timestamp: oldest_chat.unwrap_or(u64::MAX),
+            limit: count.parse().map_err(|_| format!("invalid count: {}", count))?,
+        },
+        (["history", ..], _) => return Err("usage: history [count]".to_string()),
+        (["offer", player_id, units, credits], _) => ClientMessage::TradeOffer {
+            to_player: player_id.parse().map_err(|_| format!("invalid player id: {}", player_id))?,
+            give_resources: units.parse().map_err(|_| format!("invalid amount: {}", units))?,
+            want_credits: credits.parse().map_err(|_| format!("invalid credits: {}", credits))?,
+        },
+        (["offer", ..], _) => return Err("usage: offer <player id> <units> <credits>".to_string()),
+        (["accept", offer_id], _) => ClientMessage::TradeAccept {
+            offer_id: offer_id.parse().map_err(|_| format!("invalid offer id: {}", offer_id))?,
+        },
+        (["accept", ..], _) => return Err("usage: accept <offer id>".to_string()),
+        (["decline", offer_id], _) => ClientMessage::TradeDecline {
+            offer_id: offer_id.parse().map_err(|_| format!("invalid offer id: {}", offer_id))?,
+        },
+        (["decline", ..], _) => return Err("usage: decline <offer id>".to_string()),
+        (["find", flags @ ..], _) => parse_find(flags, position.ok_or("our position is not known yet")?)?,
+        (["friend", "add", name], _) => ClientMessage::AddFriend { name: name.to_string() },
+        (["friend", "remove", name], _) => ClientMessage::RemoveFriend { name: name.to_string() },
+        (["friend", "list"], _) => ClientMessage::ListFriends {},
+        (["friend", ..], _) => return Err("usage: friend add|remove <name>, or friend list".to_string()),
+        (_, Some(token)) => ClientMessage::Admin { token: token.to_string(), command: line.trim().to_string(), dry_run: false },
+        (_, None) => ClientMessage::Chat { text: line.to_string(), client_ref: None },
+    }))
+}
+
+/// The `QueryPlanetsFiltered` for `find`'s flags, every planet when there
+/// are none, nearest to `center` first.
+fn parse_find(flags: &[&str], center: Position) -> Result<ClientMessage, String> {
+    const USAGE: &str = "usage: find [--module <type>] [--min-size <size>] [--max-size <size>] [--owner <player id>] [--unclaimed] [--name <name>] [--sort distance|size] [--limit <count>] [--after <cursor>]";
+    fn value<T: std::str::FromStr>(flag: &str, value: Option<&&str>) -> Result<T, String> {
+        let value = value.ok_or(USAGE)?;
+        value.parse().map_err(|_| format!("invalid {}: {}", flag.trim_start_matches('-'), value))
+    }
+    let (mut module_type, mut min_size, mut max_size, mut owned_by, mut unclaimed_only) = (None, None, None, None, false);
+    let (mut sort, mut limit, mut cursor, mut name) = (PlanetSort::Distance, MAX_QUERY_RESULTS, None, None);
+    let mut iter = flags.iter();
+    while let Some(&flag) = iter.next() {
+        match flag {
+            "--module" => module_type = Some(value(flag, iter.next())?),
+            "--min-size" => min_size = Some(value(flag, iter.next())?),
+            "--max-size" => max_size = Some(value(flag, iter.next())?),
+            "--owner" => owned_by = Some(value(flag, iter.next())?),
+            "--unclaimed" => unclaimed_only = true,
+            "--name" => name = Some(iter.next().ok_or(USAGE)?.to_string()),
+            "--sort" => sort = match iter.next() {
+                Some(&"distance") => PlanetSort::Distance,
+                Some(&"size") => PlanetSort::Size,
+                _ => return Err(USAGE.to_string()),
+            },
+            "--limit" => limit = value(flag, iter.next())?,
+            "--after" => cursor = Some(iter.next().ok_or(USAGE)?.parse()?),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    Ok(ClientMessage::QueryPlanetsFiltered { center, module_type, min_size, max_size, owned_by, unclaimed_only, sort, limit, cursor, name })
+}
+
+fn describe_appearance(appearance: &PlayerAppearance) -> String {
+    let (p, s) = (&appearance.primary, &appearance.secondary);
+    format!("ship model {} in #{:02x}{:02x}{:02x}/#{:02x}{:02x}{:02x}", appearance.model, p.r, p.g, p.b, s.r, s.g, s.b)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("🚀 Connecting to Crux Server...");
-    
-    let (ws_stream, _) = connect_async("ws://localhost:8080").await?;
-    println!("✅ Connected to server!\n");
-
-    let (_write, mut read) = ws_stream.split();
-    let mut game_state: Option<GameState> = None;
-
-    while let Some(msg) = read.next().await {
-        match msg? {
-            Message::Binary(data) => {
-                println!("📦 Received binary game state ({} bytes)", data.len());
-                
-                match bincode::deserialize::<GameState>(&data) {
-                    Ok(state) => {
-                        game_state = Some(state.clone());
-                        println!("\n🌍 Game State Loaded:");
-                        println!("   📍 Initial player location: ({:.1}, {:.1}, {:.1})", 
-                            state.initial_player_location.x,
-                            state.initial_player_location.y,
-                            state.initial_player_location.z);
-                        println!("   🪐 Planets: {}", state.planets.len());
-                        println!("   👥 Players: {}", state.players.len());
-                        
-                        println!("\n🪐 Planet details:");
-                        for (i, planet) in state.planets.iter().enumerate() {
-                            println!("   Planet {}: size={:.1}, module_type={}, pos=({:.1}, {:.1}, {:.1})",
-                                i + 1,
-                                planet.size,
-                                planet.module_type,
-                                planet.position.x,
-                                planet.position.y,
-                                planet.position.z);
-                            println!("      Colors: RGB({},{},{}), RGB({},{},{}), RGB({},{},{})",
-                                planet.colors[0].r, planet.colors[0].g, planet.colors[0].b,
-                                planet.colors[1].r, planet.colors[1].g, planet.colors[1].b,
-                                planet.colors[2].r, planet.colors[2].g, planet.colors[2].b);
+    let args = parse_args()?;
+    OUTPUT.set(args.output).ok();
+    if let Some(path) = &args.playback {
+        playback(path, args.speed, args.once, args.validate).await?;
+        return validation_result(args.validate);
+    }
+
+    let recorder: Recorder = Arc::new(Mutex::new(match &args.record {
+        Some(path) => {
+            say!("⏺️  Recording received frames to {}", path.display());
+            Some(CaptureWriter::new(BufWriter::new(File::create(path)?))?)
+        }
+        None => None,
+    }));
+
+    // Read stdin once for every session, so reconnecting does not lose lines
+    let (command_tx, mut command_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    if !args.bot {
+        say!("⌨️  Commands: `respawn`, `tp <planet id or name>`, `land <planet id or name>`, `name-planet <planet id> <name>`, `takeoff`, `mine <amount>`, `sell <amount>`, `rename <name>`, `ship <model> <rrggbb> <rrggbb>`, `discoveries`, `nearby <radius>`, `trail <player id>`, `debug on|off`, `subscribe <mask>`, `history [count]`, `friend add|remove <name>`, `friend list`, `find [--module <type>] [--unclaimed] ...`, `offer <player id> <units> <credits>`, `accept|decline <offer id>`, anything else is chat");
+        if args.admin_token.is_some() {
+            say!("🛠️  ...or an admin command, e.g. `planet list`");
+        }
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(tokio::io::stdin()).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if command_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    if args.urls.len() > 1 {
+        run_multi(&args, &mut command_rx).await;
+        return Ok(());
+    }
+    // A server handing over to a standby sends us to another URL
+    let mut url = args.urls[0].clone();
+    let end = loop {
+        let end = run_session(&args, &mut url, &recorder, &mut command_rx).await?;
+        if !(args.reconnect && should_reconnect(&end)) {
+            break end;
+        }
+        say!("🔁 Reconnecting in {}s...", RECONNECT_DELAY.as_secs());
+        emit_lifecycle(|ts_ms| reconnecting_record(RECONNECT_DELAY.as_millis() as u64, ts_ms));
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    };
+    if args.once && !matches!(end, SessionEnd::Once) {
+        return Err("--once: the session ended before a full state arrived".into());
+    }
+    validation_result(args.validate)
+}
+
+/// With `--validate`, an error if the server broke the protocol anywhere.
+fn validation_result(validate: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match VIOLATIONS.load(Ordering::Relaxed) {
+        _ if !validate => Ok(()),
+        0 => {
+            say!("✅ No protocol violations");
+            Ok(())
+        }
+        count => Err(format!("--validate: {} protocol violation(s)", count).into()),
+    }
+}
+
+/// How a session with the server ended.
+enum SessionEnd {
+    Quit,                // Ctrl-C
+    Once,                // the first full state arrived, with --once
+    Lost,                // the connection dropped without a close frame
+    Closed(Option<u16>), // the server closed it, with this close code
+}
+
+/// Reconnect after a dropped connection, or a close whose `ErrorCode` allows
+/// it; never after a normal close or one the client does not know.
+fn should_reconnect(end: &SessionEnd) -> bool {
+    match end {
+        SessionEnd::Quit | SessionEnd::Once => false,
+        SessionEnd::Lost => true,
+        SessionEnd::Closed(code) => code.and_then(ErrorCode::from_code).is_some_and(ErrorCode::may_reconnect),
+    }
+}
+
+async fn run_session(
+    args: &Args,
+    url: &mut String,
+    recorder: &Recorder,
+    command_rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+) -> Result<SessionEnd, Box<dyn std::error::Error>> {
+    let bot_mode = args.bot;
+
+    say!("🚀 Connecting to Crux Server at {}...", url);
+    emit_lifecycle(|ts_ms| connecting_record(url, ts_ms));
+    if args.insecure && !url.starts_with("wss://") {
+        say!("⚠️  --insecure has no effect on a plain ws:// connection");
+    }
+    let capabilities = if args.crc { Capabilities::DEFAULT | Capabilities::CRC } else { Capabilities::DEFAULT };
+    let mut connection = if args.spectate {
+        Connection::spectate_with_capabilities(url.as_str(), args.insecure, capabilities).await?
+    } else {
+        Connection::connect_with_capabilities(url.as_str(), args.name.as_deref(), args.insecure, capabilities).await?
+    };
+    if args.crc && !connection.capabilities().contains(Capabilities::CRC) {
+        say!("⚠️  The server does not do CRC trailers; frames go unchecked");
+    }
+    say!("✅ Connected to server!\n");
+    emit_lifecycle(|ts_ms| connected_record(url.as_str(), ts_ms));
+    if args.validate {
+        connection.validate();
+    }
+    let mut reported = 0;
+
+    let mut view = ClientView::new(bot_mode, args.once);
+
+    let started_at = Instant::now();
+    let client_time_ms = move || started_at.elapsed().as_millis() as u64;
+    {
+        let recorder = recorder.clone();
+        connection.on_frame(move |frame| {
+            if let (Some(recorder), Some(frame)) = (recorder.lock().unwrap().as_mut(), CapturedFrame::from_message(client_time_ms(), frame))
+                && let Err(e) = recorder.write_frame(&frame)
+            {
+                eprintln!("❌ Failed to record a frame: {}", e);
+            }
+        });
+    }
+    let mut sync_tick = tokio::time::interval(TIME_SYNC_INTERVAL);
+    let mut render_tick = tokio::time::interval(RENDER_INTERVAL);
+
+    let mut bot_tick = tokio::time::interval(BOT_UPDATE_INTERVAL);
+    let mut report_tick = tokio::time::interval(REPORT_INTERVAL);
+    report_tick.reset();
+    let mut stats_tick = tokio::time::interval(STATS_INTERVAL);
+    stats_tick.reset();
+
+    let end = loop {
+        tokio::select! {
+            event = connection.next_event() => {
+                emit_event(&event);
+                connection.violations()[reported..].iter().for_each(report_violation);
+                reported = connection.violations().len();
+                if let ClientEvent::Disconnected { code, reason } = &event {
+                    say!("\n👋 Connection closed by server{}", if reason.is_empty() { String::new() } else { format!(": {}", reason) });
+                    break match code {
+                        Some(code) => SessionEnd::Closed(Some(*code)),
+                        None => SessionEnd::Lost,
+                    };
+                }
+                if let ClientEvent::Redirected { to } = &event {
+                    *url = to.clone();
+                }
+                if !view.handle_event(event, client_time_ms()) {
+                    connection.close().await?;
+                    break SessionEnd::Once;
+                }
+            }
+
+            _ = bot_tick.tick(), if bot_mode => {
+                let seq = view.bot_stats.last_sent_seq.wrapping_add(1);
+                // Fly in a circle of radius 100, facing along the direction of travel
+                let t = seq as f32 * 0.05;
+                let angular_speed = 0.05 / BOT_UPDATE_INTERVAL.as_secs_f32();
+                let position = Position { x: t.cos() * 100.0, y: 0.0, z: t.sin() * 100.0 };
+                let velocity = [-t.sin() * 100.0 * angular_speed, 0.0, t.cos() * 100.0 * angular_speed];
+                let yaw = -t;
+                let rotation = [0.0, (yaw / 2.0).sin(), 0.0, (yaw / 2.0).cos()];
+                let stats = &mut view.bot_stats;
+                stats.last_sent_seq = connection.send_motion(position, velocity, rotation).await?;
+                stats.sent += 1;
+            }
+
+            _ = render_tick.tick() => view.render(client_time_ms()),
+
+            _ = sync_tick.tick() => {
+                connection.send_time_sync(client_time_ms()).await?;
+                // Keep the capture usable if the client is killed
+                if let Some(recorder) = recorder.lock().unwrap().as_mut() {
+                    recorder.flush()?;
+                }
+            }
+
+            _ = report_tick.tick() => view.report(),
+
+            _ = stats_tick.tick(), if args.stats => print_traffic(&connection.stats()),
+
+            Some(line) = command_rx.recv() => {
+                match parse_command(&line, args.admin_token.as_deref(), view.own_position(), view.oldest_chat_ms) {
+                    Ok(Some(message)) => connection.send(&message).await?,
+                    Ok(None) => {}
+                    Err(e) if output().json => emit(client_error_record(&e, now_ms())),
+                    Err(e) => say_error!("❌ {}", e),
+                }
+            }
+
+            _ = tokio::signal::ctrl_c() => {
+                connection.close().await?;
+                break SessionEnd::Quit;
+            }
+        }
+    };
+
+    if let Some(recorder) = recorder.lock().unwrap().as_mut() {
+        recorder.flush()?;
+    }
+    view.finish();
+    print_traffic(&connection.stats());
+    Ok(end)
+}
+
+/// Watches every `--url` at once, each with its own view, every line
+/// prefixed with the server it is about (and every JSON record given a
+/// `source`). Commands typed go to all of them. Each server reconnects on its
+/// own with `--reconnect`, while the others carry on; it ends on Ctrl-C or
+/// once every server is gone for good.
+async fn run_multi(args: &Args, command_rx: &mut tokio::sync::mpsc::UnboundedReceiver<String>) {
+    let options = MultiOptions {
+        name: args.name.clone(),
+        spectate: args.spectate,
+        insecure: args.insecure,
+        capabilities: if args.crc { Capabilities::DEFAULT | Capabilities::CRC } else { Capabilities::DEFAULT },
+        reconnect: args.reconnect.then_some(RECONNECT_DELAY),
+    };
+    say!("🚀 Watching {} servers: {}", args.urls.len(), args.urls.join(", "));
+    let mut multi = MultiConnection::open(&args.urls, options);
+    let mut views: HashMap<Arc<str>, ClientView> = multi.sources()
+        .map(|url| (Arc::from(url), ClientView::new(false, false)))
+        .collect();
+    for source in views.keys() {
+        with_source(source, || emit_lifecycle(|ts_ms| connecting_record(source, ts_ms)));
+    }
+    let mut connected = HashSet::new();
+
+    let started_at = Instant::now();
+    let client_time_ms = move || started_at.elapsed().as_millis() as u64;
+    let mut sync_tick = tokio::time::interval(TIME_SYNC_INTERVAL);
+    let mut render_tick = tokio::time::interval(RENDER_INTERVAL);
+    let mut report_tick = tokio::time::interval(REPORT_INTERVAL);
+    report_tick.reset();
+
+    loop {
+        tokio::select! {
+            event = multi.next_event() => {
+                let Some(MultiEvent { source, event }) = event else {
+                    say!("\n👋 Every server is gone");
+                    break;
+                };
+                let Some(view) = views.get_mut(&source) else { continue };
+                with_source(&source, || {
+                    if let ClientEvent::Disconnected { code, reason } = &event {
+                        emit_event(&event);
+                        connected.remove(&source);
+                        say!("👋 Disconnected{}", if reason.is_empty() { String::new() } else { format!(": {}", reason) });
+                        let end = code.map_or(SessionEnd::Lost, |code| SessionEnd::Closed(Some(code)));
+                        if args.reconnect && should_reconnect(&end) {
+                            say!("🔁 Reconnecting in {}s...", RECONNECT_DELAY.as_secs());
+                            emit_lifecycle(|ts_ms| reconnecting_record(RECONNECT_DELAY.as_millis() as u64, ts_ms));
                         }
-                        println!();
+                        return;
+                    }
+                    if connected.insert(source.clone()) {
+                        say!("✅ Connected");
+                        emit_lifecycle(|ts_ms| connected_record(&source, ts_ms));
                     }
-                    Err(e) => eprintln!("❌ Failed to deserialize game state: {}", e),
+                    emit_event(&event);
+                    view.handle_event(event, client_time_ms());
+                });
+            }
+
+            _ = render_tick.tick() => views.iter().for_each(|(source, view)| with_source(source, || view.render(client_time_ms()))),
+
+            _ = sync_tick.tick() => multi.send_all(ClientMessage::TimeSync { client_time_ms: client_time_ms() }),
+
+            _ = report_tick.tick() => {
+                views.iter().for_each(|(source, view)| with_source(source, || view.report()));
+                say!("📊 {} of {} servers connected, {} players in all", multi.connected(), views.len(), multi.total_players());
+            }
+
+            Some(line) = command_rx.recv() => {
+                match parse_command(&line, args.admin_token.as_deref(), None, None) {
+                    Ok(Some(message)) => multi.send_all(message),
+                    Ok(None) => {}
+                    Err(e) if output().json => emit(client_error_record(&e, now_ms())),
+                    Err(e) => say_error!("❌ {}", e),
                 }
             }
-            Message::Text(text) => {
-                println!("💬 Server: {}", text);
+
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    multi.close().await;
+    views.iter().for_each(|(source, view)| with_source(source, || view.finish()));
+}
+
+/// Re-drives the view from a `--record` capture, without a server.
+async fn playback(path: &Path, speed: f64, once: bool, validate: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let frames = read_capture(std::io::BufReader::new(File::open(path)?))?;
+    say!("⏯️  Playing back {} frames from {}{}\n", frames.len(), path.display(),
+        if speed > 0.0 { format!(" at {}x", speed) } else { " as fast as possible".to_string() });
+
+    // The recording's own client clock drives rendering, so output matches the live run
+    let mut view = ClientView::new(false, once);
+    let mut decoder = EventDecoder::new();
+    // A capture does not record the world radius, so bounds go unchecked
+    let mut validator = validate.then(|| Validator::new(None));
+    let render_ms = RENDER_INTERVAL.as_millis() as u64;
+    let mut next_render = render_ms;
+    let started_at = Instant::now();
+    for frame in frames {
+        if speed > 0.0 {
+            let due = started_at + Duration::from_secs_f64(frame.elapsed_ms as f64 / 1000.0 / speed);
+            tokio::time::sleep_until(due.into()).await;
+        }
+        while next_render <= frame.elapsed_ms {
+            view.render(next_render);
+            next_render += render_ms;
+        }
+        let events = decoder.decode(frame.to_message()?);
+        if !events.into_iter().all(|event| {
+            emit_event(&event);
+            if let Some(validator) = validator.as_mut() {
+                validator.check(&event).iter().for_each(report_violation);
+            }
+            view.handle_event(event, frame.elapsed_ms)
+        }) {
+            break;
+        }
+    }
+    view.finish();
+    Ok(())
+}
+
+impl ClientView {
+    fn new(bot_mode: bool, once: bool) -> Self {
+        ClientView {
+            own_id: None,
+            bot_mode,
+            once,
+            game_state: None,
+            clock_samples: VecDeque::with_capacity(TIME_SYNC_SAMPLES),
+            interpolator: Interpolator::default(),
+            idle_players: HashMap::new(),
+            bot_stats: BotStats::default(),
+            oldest_chat_ms: None,
+            phase: None,
+        }
+    }
+
+    /// Says so when the time of day has moved into another phase.
+    fn note_world_time(&mut self, world_time: f32) {
+        let phase = Phase::of(world_time);
+        if self.phase != Some(phase) {
+            say!("{} It is {} in the world ({:.2})", match phase {
+                Phase::Dawn => "🌅",
+                Phase::Day => "☀️ ",
+                Phase::Dusk => "🌇",
+                Phase::Night => "🌙",
+            }, phase, world_time);
+            self.phase = Some(phase);
+        }
+    }
+
+    fn own_position(&self) -> Option<Position> {
+        let state = self.game_state.as_ref()?;
+        state.players.iter().find(|p| Some(p.id) == self.own_id).map(|p| p.position.clone())
+    }
+
+    /// Handles one event; returns false once the connection is over, or
+    /// with `--once` after the first full state.
+    fn handle_event(&mut self, event: ClientEvent, client_time_ms: u64) -> bool {
+        match event {
+            ClientEvent::StateSnapshot { tick, server_time_ms, state } => {
+                for player in &state.players {
+                    self.interpolator.push(player.id, server_time_ms, player.position.clone());
+                }
+                for player in state.players.iter().filter(|p| p.idle) {
+                    self.idle_players.insert(player.id, player.clone());
+                }
+                let idle_players = &self.idle_players;
+                self.interpolator.retain_players(|id| {
+                    idle_players.contains_key(&id) || state.players.iter().any(|p| p.id == id)
+                });
+                if self.game_state.is_none() {
+                    say!("📦 Received game state at tick {}", tick);
+                    print_game_state(&state);
+                }
+                if self.bot_mode
+                    && let Some(me) = state.players.iter().find(|p| Some(p.id) == self.own_id)
+                {
+                    self.bot_stats.observe_ack(me.last_processed_seq);
+                }
+                self.note_world_time(state.world_time);
+                self.game_state = Some(state);
+                if self.once {
+                    return false;
+                }
+            }
+            ClientEvent::Delta { from_tick, tick, diffs } => {
+                if let Some(state) = self.game_state.as_mut() {
+                    for diff in &diffs {
+                        state.apply(diff);
+                    }
+                    say!("🔁 Resynced from tick {} to {} ({} diffs)", from_tick, tick, diffs.len());
+                    let world_time = state.world_time;
+                    self.note_world_time(world_time);
+                }
             }
-            Message::Close(_) => {
-                println!("\n👋 Connection closed by server");
-                break;
+            ClientEvent::Joined { player_id, spawn, spawn_planet_id } => {
+                self.own_id = Some(player_id);
+                let near = spawn_planet_id.map(|id| format!(" near planet {}", id)).unwrap_or_default();
+                say!("🛬 Joined as player {}, spawned at ({:.1}, {:.1}, {:.1}){}", player_id, spawn.x, spawn.y, spawn.z, near);
             }
-            Message::Ping(_) => {
-                // Pongs are handled automatically
+            ClientEvent::PlayerJoined { player } => say!("👋 {} joined", player.name),
+            ClientEvent::PlayerLeft { player_id } => {
+                let name = self.game_state.as_ref()
+                    .and_then(|state| state.players.iter().find(|p| p.id == player_id))
+                    .map_or_else(|| format!("Player {}", player_id), |p| p.name.to_string());
+                say!("👋 {} left", name);
+            }
+            ClientEvent::Chat { text } => say!("💬 Server: Echo: {}", text),
+            ClientEvent::Notice { text } | ClientEvent::Localized { text, .. } => say!("💬 Server: {}", text),
+            ClientEvent::Announcement { text } => say!("📢 {}", text),
+            ClientEvent::WorldReset { seed } => {
+                say!("🌌 The world was regenerated from seed {}", seed);
+                self.game_state = None;
+                self.idle_players.clear();
+                self.interpolator.retain_players(|_| false);
+            }
+            ClientEvent::Redirected { to } => {
+                say!("🔀 The server handed over to {}; reconnected there", to);
+                self.game_state = None;
+                self.idle_players.clear();
+                self.interpolator.retain_players(|_| false);
+            }
+            ClientEvent::Message(ServerMessage::TimeSync { client_time_ms: sent, server_time_ms, .. }) => {
+                if self.clock_samples.len() == TIME_SYNC_SAMPLES {
+                    self.clock_samples.pop_front();
+                }
+                self.clock_samples.push_back(ClockSample {
+                    client_send_ms: sent,
+                    server_time_ms,
+                    client_recv_ms: client_time_ms,
+                });
+            }
+            ClientEvent::Message(ServerMessage::PlayerIdle { player_id }) => {
+                if let Some(player) = self.game_state.as_ref()
+                    .and_then(|state| state.players.iter().find(|p| p.id == player_id))
+                {
+                    say!("💤 {} is idle", player.name);
+                    self.idle_players.insert(player_id, Player { idle: true, ..player.clone() });
+                }
+            }
+            ClientEvent::Message(ServerMessage::PlayerActive { player_id }) => {
+                if let Some(player) = self.idle_players.remove(&player_id) {
+                    say!("▶️  {} is active again", player.name);
+                }
+            }
+            ClientEvent::Message(ServerMessage::PlanetAdded { planet }) => {
+                say!("🪐 Planet {} added", planet.id);
+                if let Some(state) = self.game_state.as_mut() {
+                    state.planets.push(planet);
+                }
+            }
+            ClientEvent::Message(ServerMessage::PlanetRemoved { planet_id }) => {
+                say!("🪐 Planet {} removed", planet_id);
+                if let Some(state) = self.game_state.as_mut() {
+                    state.planets.retain(|p| p.id != planet_id);
+                }
+            }
+            ClientEvent::Message(ServerMessage::PlanetUpdated { planet }) => {
+                say!("🪐 Planet {} updated", planet.id);
+                if let Some(existing) = self.game_state.as_mut()
+                    .and_then(|state| state.planet_by_id_mut(planet.id))
+                {
+                    *existing = planet;
+                }
+            }
+            ClientEvent::Message(ServerMessage::AdminResult { ok, message }) => {
+                if ok {
+                    say!("🛠️  {}", message);
+                } else {
+                    say_error!("❌ {}", message);
+                }
+            }
+            ClientEvent::Message(ServerMessage::AdminPlanned { plan }) => {
+                say!("🔎 {}", plan);
+            }
+            ClientEvent::Message(ServerMessage::PlayerTeleported { player_id, position }) => {
+                say!("🌀 Player {} teleported to ({:.1}, {:.1}, {:.1})", player_id, position.x, position.y, position.z);
+                if let Some(player) = self.game_state.as_mut()
+                    .and_then(|state| state.players.iter_mut().find(|p| p.id == player_id))
+                {
+                    player.position = position;
+                }
+            }
+            ClientEvent::Message(ServerMessage::TeleportRejected { error }) => say_error!("❌ Teleport rejected: {}", error),
+            ClientEvent::Message(ServerMessage::LandRejected { error }) => say_error!("❌ Landing rejected: {}", error),
+            ClientEvent::Message(ServerMessage::Mined { planet_id, amount, cargo }) => {
+                say!("⛏️  Mined {} unit(s) of body {}, {} in cargo", amount, planet_id, cargo);
+            }
+            ClientEvent::Message(ServerMessage::MineRejected { error }) => say_error!("❌ Mining rejected: {}", error),
+            ClientEvent::Message(ServerMessage::Sold { amount, earned, credits }) => {
+                say!("💰 Sold {} unit(s) for {} credit(s), {} in all", amount, earned, credits);
+            }
+            ClientEvent::Message(ServerMessage::SellRejected { error }) => say_error!("❌ Sale rejected: {}", error),
+            ClientEvent::Message(ServerMessage::PauseChanged { paused: true, tick }) => say!("⏸️  The server paused the simulation at tick {}", tick),
+            ClientEvent::Message(ServerMessage::PauseChanged { paused: false, tick }) => say!("▶️  The server resumed the simulation at tick {}", tick),
+            ClientEvent::Message(ServerMessage::TimeSet { world_time }) => {
+                say!("🕰️  The server set the time of day to {:.2}", world_time);
+                if let Some(state) = self.game_state.as_mut() {
+                    state.world_time = world_time;
+                }
+                self.note_world_time(world_time);
+            }
+            ClientEvent::Message(ServerMessage::WorldEvent { event, active: true }) => {
+                say!("🌠 {:?} around planet {} for {} tick(s)", event.kind, event.planet_id, event.duration_ticks);
+            }
+            ClientEvent::Message(ServerMessage::WorldEvent { event, active: false }) => say!("🌠 The {:?} around planet {} is over", event.kind, event.planet_id),
+            ClientEvent::Message(ServerMessage::ServerPaused { tick }) => say_error!("⏸️  Not now: the simulation is paused at tick {}", tick),
+            ClientEvent::Message(ServerMessage::Chatted { line, .. }) => match &line.room {
+                Some(room) => say!("💬 [{}] {}: {}", room, line.sender, line.text),
+                None => say!("💬 {}: {}", line.sender, line.text),
+            },
+            ClientEvent::Message(ServerMessage::ChatHistory { messages }) => {
+                if messages.is_empty() {
+                    say!("📜 No earlier chat");
+                } else {
+                    say!("📜 {} earlier chat line(s):", messages.len());
+                }
+                for line in &messages {
+                    match &line.room {
+                        Some(room) => say!("   [{}] {}: {}", room, line.sender, line.text),
+                        None => say!("   {}: {}", line.sender, line.text),
+                    }
+                }
+                if let Some(first) = messages.first() {
+                    self.oldest_chat_ms = Some(self.oldest_chat_ms.map_or(first.timestamp_ms, |oldest| oldest.min(first.timestamp_ms)));
+                }
             }
-            _ => {}
+            ClientEvent::Message(ServerMessage::PlayerAppearanceChanged { player_id, appearance }) => {
+                say!("🎨 Player {} now flies {}", player_id, describe_appearance(&appearance));
+                if let Some(player) = self.game_state.as_mut()
+                    .and_then(|state| state.players.iter_mut().find(|p| p.id == player_id))
+                {
+                    player.appearance = appearance;
+                }
+            }
+            ClientEvent::Message(ServerMessage::AppearanceRejected { reason }) => say_error!("❌ Appearance rejected: {}", reason),
+            ClientEvent::Message(ServerMessage::PlayerRenamed { player_id, old, new }) => {
+                say!("🏷️  {} is now {}", old, new);
+                if let Some(player) = self.game_state.as_mut()
+                    .and_then(|state| state.players.iter_mut().find(|p| p.id == player_id))
+                {
+                    player.name = new.into();
+                }
+            }
+            ClientEvent::Message(ServerMessage::RenameRejected { error }) => say_error!("❌ Rename rejected: {}", error),
+            ClientEvent::Message(ServerMessage::PlanetDiscovered { player_id, planet_id }) => {
+                say!("🔭 Player {} discovered planet {}", player_id, planet_id);
+            }
+            ClientEvent::Message(ServerMessage::PlanetList { planets }) => {
+                say!("🪐 {} planet(s) nearby:", planets.len());
+                for planet in &planets {
+                    say!("   #{} size {:.0} at ({:.1}, {:.1}, {:.1})", planet.id, planet.size, planet.position.x, planet.position.y, planet.position.z);
+                }
+            }
+            ClientEvent::Message(ServerMessage::PlanetPage { results, next_cursor }) => {
+                say!("🪐 {} planet(s) found:", results.len());
+                for planet in &results {
+                    let owner = planet.owner.map_or("unclaimed".to_string(), |owner| format!("owned by {}", owner));
+                    say!("   #{} module {} size {:.0} {} at ({:.1}, {:.1}, {:.1})", planet.id, planet.module_type, planet.size, owner,
+                        planet.position.x, planet.position.y, planet.position.z);
+                }
+                if let Some(cursor) = next_cursor {
+                    say!("   ...more with `--after {}`", cursor);
+                }
+            }
+            ClientEvent::Message(ServerMessage::Trail { trail }) => {
+                let span = match (trail.points.first(), trail.points.last()) {
+                    (Some(first), Some(last)) => (last.time_ms - first.time_ms) as f64 / 1000.0,
+                    _ => 0.0,
+                };
+                say!("🐾 Player {}'s trail: {} point(s) over {:.1}s", trail.player_id, trail.points.len(), span);
+                for point in &trail.points {
+                    say!("   {} ms at ({:.1}, {:.1}, {:.1})", point.time_ms, point.position.x, point.position.y, point.position.z);
+                }
+            }
+            ClientEvent::Message(ServerMessage::FriendList { friends }) => {
+                if friends.is_empty() {
+                    say!("🤝 No friends yet");
+                } else {
+                    say!("🤝 {} friend(s):", friends.len());
+                }
+                for friend in &friends {
+                    let status = if friend.pending { "never joined" } else if friend.online { "online" } else { "offline" };
+                    say!("   {} ({})", friend.name, status);
+                }
+            }
+            ClientEvent::Message(ServerMessage::FriendRejected { reason }) => say_error!("❌ Friend list unchanged: {}", reason),
+            ClientEvent::Message(ServerMessage::TradeOffered { offer_id, to_player, expires_in_ms }) => {
+                say!("🤝 Offer {} sent to player {}, open for {}s", offer_id, to_player, expires_in_ms / 1000);
+            }
+            ClientEvent::Message(ServerMessage::TradeIncoming { offer_id, from_player, give_resources, want_credits, expires_in_ms }) => {
+                say!("🤝 Player {} offers {} unit(s) for {} credit(s): `accept {}` or `decline {}` within {}s",
+                    from_player, give_resources, want_credits, offer_id, offer_id, expires_in_ms / 1000);
+            }
+            ClientEvent::Message(ServerMessage::TradeCompleted { offer_id, cargo, credits }) => {
+                say!("🤝 Trade {} done: {} unit(s) in cargo, {} credit(s)", offer_id, cargo, credits);
+            }
+            ClientEvent::Message(ServerMessage::TradeEnded { offer_id, reason }) => say!("🤝 Trade {} ended: {}", offer_id, reason),
+            ClientEvent::Message(ServerMessage::TradeRejected { error }) => say_error!("❌ Trade refused: {}", error),
+            ClientEvent::Message(ServerMessage::PlanetRenamed { planet_id, name }) => {
+                say!("🪐 Planet {} is now called {}", planet_id, name);
+                if let Some(planet) = self.game_state.as_mut().and_then(|state| state.planet_by_id_mut(planet_id)) {
+                    planet.name = name;
+                }
+            }
+            ClientEvent::Message(ServerMessage::PlanetRenameRejected { error }) => say_error!("❌ Planet not renamed: {}", error),
+            ClientEvent::Message(ServerMessage::FriendOnline { name }) => say!("🤝 {} is online", name),
+            ClientEvent::Message(ServerMessage::FriendOffline { name }) => say!("🤝 {} went offline", name),
+            ClientEvent::Message(ServerMessage::DiscoveryList { planet_ids, explored_percent }) => {
+                say!("🔭 Discovered {} planet(s), {:.1}% explored: {:?}", planet_ids.len(), explored_percent, planet_ids);
+            }
+            ClientEvent::Message(ServerMessage::Error { code, message }) => match ErrorCode::from_code(code) {
+                Some(known) => say_error!("⛔ {} ({}: {})", known.explanation(), code, message),
+                None => say_error!("⛔ Server error {}: {}", code, message),
+            },
+            ClientEvent::Message(ServerMessage::PositionCorrection { position, .. }) => {
+                say!("📍 Server corrected our position to ({:.1}, {:.1}, {:.1})", position.x, position.y, position.z);
+            }
+            ClientEvent::Message(ServerMessage::OutOfBounds { radius, teleported }) => {
+                say!("🧱 Out of bounds (world radius {:.0}){}", radius, if teleported { ", sent back to spawn" } else { "" });
+            }
+            ClientEvent::Message(ServerMessage::DebugStats { tick, updates_received, updates_accepted, updates_rejected, send_queue_depth, rtt_ms, bandwidth_level }) => {
+                say!("🐞 tick {}: {} update(s) received, {} accepted, {} rejected; queue {}; RTT {}; states 1/{}",
+                         tick, updates_received, updates_accepted, updates_rejected, send_queue_depth,
+                         rtt_ms.map_or("unknown".to_string(), |rtt| format!("{} ms", rtt)), 1u64 << bandwidth_level);
+            }
+            ClientEvent::Message(ServerMessage::CorruptFrame { expected, got }) => {
+                say_error!("🧮 The server dropped a corrupt frame of ours: CRC {:08x} expected, {:08x} computed", expected, got);
+            }
+            ClientEvent::CorruptFrame { expected, got } => {
+                say_error!("🧮 Dropped a corrupt frame from the server: CRC {:08x} expected, {:08x} computed", expected, got);
+            }
+            ClientEvent::Message(_) => {}
+            ClientEvent::Disconnected { .. } => return false,
         }
+        true
     }
 
-    if let Some(state) = game_state {
-        println!("\n✅ Successfully received game state with {} planets", state.planets.len());
+    fn render(&self, client_time_ms: u64) {
+        let samples: Vec<ClockSample> = self.clock_samples.iter().copied().collect();
+        if let (Some(estimate), Some(state)) = (estimate_clock_offset(&samples), &self.game_state) {
+            let render_time = estimate.to_server_time(client_time_ms).saturating_sub(INTERPOLATION_DELAY_MS);
+            let remote: Vec<String> = state.players.iter()
+                .filter(|p| Some(p.id) != self.own_id)
+                .filter_map(|p| {
+                    let pos = self.interpolator.position_at(p.id, render_time)?;
+                    Some(format!("{} ({:.1}, {:.1}, {:.1}) speed {:.1}", p.name, pos.x, pos.y, pos.z, speed(&p.velocity)))
+                })
+                .collect();
+            let remote: Vec<String> = remote.into_iter()
+                .chain(self.idle_players.values().map(|p| format!("{} [idle]", p.name)))
+                .collect();
+            if !remote.is_empty() {
+                say!("🛰️  [{}] {}", render_time, remote.join(" | "));
+            }
+        }
     }
 
-    Ok(())
-}
\ No newline at end of file
+    fn report(&self) {
+        let samples: Vec<ClockSample> = self.clock_samples.iter().copied().collect();
+        if let Some(estimate) = estimate_clock_offset(&samples) {
+            say!("⏱️  RTT {} ms, clock offset {} ms", estimate.rtt_ms, estimate.offset_ms);
+        }
+        if self.bot_mode {
+            self.bot_stats.report();
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(state) = &self.game_state {
+            say!("\n✅ Successfully received game state with {} planets", state.planets.len());
+        }
+        if self.bot_mode {
+            self.bot_stats.report();
+        }
+    }
+}
+
+impl BotStats {
+    fn observe_ack(&mut self, seq: u32) {
+        if self.last_applied_seq.is_none_or(|last| seq_newer(seq, last)) {
+            self.last_applied_seq = Some(seq);
+            self.acks_observed += 1;
+        }
+    }
+
+    fn report(&self) {
+        match self.last_applied_seq {
+            Some(applied) => {
+                let in_flight = self.last_sent_seq.wrapping_sub(applied);
+                say!("🤖 Sent {} updates, server applied up to #{} ({} behind, {} acks observed)",
+                    self.sent, applied, in_flight, self.acks_observed);
+            }
+            None => say!("🤖 Sent {} updates, none acknowledged yet", self.sent),
+        }
+    }
+}
+
+/// The summary of what the connection received so far, as a table.
+fn print_traffic(stats: &ConnectionStats) {
+    say!("\n📊 Traffic received:");
+    for line in stats.to_string().lines() {
+        say!("   {}", line);
+    }
+}
+
+fn print_game_state(state: &GameState) {
+    say!("\n🌍 Game State Loaded:");
+    say!("   📍 Initial player location: ({:.1}, {:.1}, {:.1})", 
+        state.initial_player_location.x,
+        state.initial_player_location.y,
+        state.initial_player_location.z);
+    say!("   🪐 Planets: {}", state.planets.len());
+    say!("   👥 Players: {}", state.players.len());
+    say!("   🪨 Belts: {}", state.belts.len());
+    
+    say!("\n🪐 Planet details:");
+    for (i, planet) in state.planets.iter().enumerate() {
+        say!("   Planet {} (id {}): size={:.1}, module_type={}, pos=({:.1}, {:.1}, {:.1}), resources={}/{}",
+            i + 1,
+            planet.id,
+            planet.size,
+            planet.module_type,
+            planet.position.x,
+            planet.position.y,
+            planet.position.z,
+            planet.resources,
+            planet.capacity);
+        say!("      Colors: RGB({},{},{}), RGB({},{},{}), RGB({},{},{})",
+            planet.colors[0].r, planet.colors[0].g, planet.colors[0].b,
+            planet.colors[1].r, planet.colors[1].g, planet.colors[1].b,
+            planet.colors[2].r, planet.colors[2].g, planet.colors[2].b);
+        for (j, moon) in planet.moons.iter().enumerate() {
+            say!("      Moon {}: size={:.1}, RGB({},{},{}), orbit r={:.1} at {:.2} rad/s, phase {:.2}",
+                j + 1,
+                moon.size,
+                moon.color.r, moon.color.g, moon.color.b,
+                moon.orbit_radius,
+                moon.angular_speed,
+                moon.phase);
+        }
+    }
+
+    say!("\n👥 Player details:");
+    for player in &state.players {
+        say!("   {} (id {}): level {} ({} XP), {}", player.name, player.id, player.level, player.xp, describe_appearance(&player.appearance));
+    }
+
+    say!("\n🪨 Belt details:");
+    for (i, belt) in state.belts.iter().enumerate() {
+        say!("   Belt {}: radius={:.1}, width={:.1}, density={:.2}, rock seed {}",
+            i + 1,
+            belt.center_radius,
+            belt.width,
+            belt.density,
+            belt.rock_seed);
+        for asteroid in &belt.notable {
+            say!("      Asteroid {}: size={:.1}, pos=({:.1}, {:.1}, {:.1}), resources={}",
+                asteroid.id,
+                asteroid.size,
+                asteroid.position.x,
+                asteroid.position.y,
+                asteroid.position.z,
+                asteroid.resources);
+        }
+    }
+    say!("");
+}
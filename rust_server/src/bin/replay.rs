@@ -0,0 +1,112 @@
+use galavox::journal::{read_journal, replay_until, JournalEvent};
+use galavox::world::{load_world, save_world};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+/*
+Rebuilds the game state from a server journal (`server --journal <path>`).
+
+    replay <journal> [--tick N] [--events] [--save <state.json>] [--verify <state.json>]
+
+Without `--tick` the state after the last record is used. `--save` writes that
+state as JSON, and `--verify` compares it with a previously saved one, exiting
+non-zero when they differ.
+*/
+
+#[derive(Default)]
+struct Args {
+    journal: PathBuf,
+    tick: Option<u64>,
+    events: bool,
+    save: Option<PathBuf>,
+    verify: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut journal = None;
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--tick" => args.tick = Some(iter.next().ok_or("--tick needs a number")?.parse().map_err(|_| "--tick needs a number")?),
+            "--events" => args.events = true,
+            "--save" => args.save = Some(iter.next().ok_or("--save needs a path")?.into()),
+            "--verify" => args.verify = Some(iter.next().ok_or("--verify needs a path")?.into()),
+            other if other.starts_with("--") => return Err(format!("unknown argument: {}", other)),
+            path => journal = Some(PathBuf::from(path)),
+        }
+    }
+    args.journal = journal.ok_or("usage: replay <journal> [--tick N] [--events] [--save <state.json>] [--verify <state.json>]")?;
+    Ok(args)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args()?;
+    let records = read_journal(BufReader::new(File::open(&args.journal)?))?;
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return Err("journal is empty".into());
+    };
+    println!("📓 {} records, ticks {}..={}", records.len(), first.tick, last.tick);
+
+    let tick = args.tick.unwrap_or(last.tick);
+    if args.events {
+        for record in records.iter().take_while(|r| r.tick <= tick) {
+            println!("   [tick {} @ {} ms] {}", record.tick, record.server_time_ms, describe(&record.event));
+        }
+    }
+
+    let state = replay_until(&records, tick);
+    println!("🌍 State at tick {}: {} planets, {} players", tick, state.planets.len(), state.players.len());
+    for player in &state.players {
+        println!("   👤 {} (id {}) at ({:.1}, {:.1}, {:.1}){}", player.name, player.id,
+            player.position.x, player.position.y, player.position.z,
+            if player.idle { " [idle]" } else { "" });
+    }
+
+    if let Some(path) = &args.save {
+        std::fs::write(path, save_world(&state)?)?;
+        println!("💾 Saved state to {}", path.display());
+    }
+
+    if let Some(path) = &args.verify {
+        let expected = load_world(&std::fs::read_to_string(path)?)?;
+        if expected != state {
+            return Err(format!("state at tick {} does not match {}", tick, path.display()).into());
+        }
+        println!("✅ State matches {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn describe(event: &JournalEvent) -> String {
+    match event {
+        JournalEvent::WorldCreated { state } => format!("world created with {} planets", state.planets.len()),
+        JournalEvent::WorldReset { state } => format!("world regenerated with {} planets", state.planets.len()),
+        JournalEvent::PlayerJoined { player } => format!("{} joined as id {}", player.name, player.id),
+        JournalEvent::PlayerLeft { player_id } => format!("player {} left", player_id),
+        JournalEvent::PositionUpdated { player_id, seq, position, .. } => format!(
+            "player {} moved to ({:.1}, {:.1}, {:.1}) seq {:?}",
+            player_id, position.x, position.y, position.z, seq
+        ),
+        JournalEvent::IdleChanged { player_id, idle } => format!("player {} {}", player_id, if *idle { "idle" } else { "active" }),
+        JournalEvent::Chat { player_id, text } => format!("player {} says {:?}", player_id, text),
+        JournalEvent::PlanetAdded { planet } => format!("planet {} added", planet.id),
+        JournalEvent::PlanetRemoved { planet_id } => format!("planet {} removed", planet_id),
+        JournalEvent::PlanetUpdated { planet } => format!("planet {} updated", planet.id),
+        JournalEvent::PlanetDiscovered { player_id, planet_id } => format!("player {} discovered planet {}", player_id, planet_id),
+        JournalEvent::AppearanceChanged { player_id, appearance } => format!("player {} switched to ship model {}", player_id, appearance.model),
+        JournalEvent::PlayerRenamed { player_id, name } => format!("player {} renamed to {}", player_id, name),
+        JournalEvent::LandingChanged { player_id, landed_on: Some(planet_id), position } => format!(
+            "player {} on planet {} at ({:.1}, {:.1}, {:.1})",
+            player_id, planet_id, position.x, position.y, position.z
+        ),
+        JournalEvent::LandingChanged { player_id, landed_on: None, .. } => format!("player {} took off", player_id),
+        JournalEvent::XpEarned { player_id, xp } => format!("player {} earned {} XP", player_id, xp),
+        JournalEvent::Traded { seller, buyer, resources, credits } => {
+            format!("player {} traded {} unit(s) to player {} for {} credit(s)", seller, resources, buyer, credits)
+        }
+        JournalEvent::AsteroidMined { asteroid_id, resources } => format!("asteroid {} mined down to {} unit(s)", asteroid_id, resources),
+    }
+}
@@ -0,0 +1,53 @@
+use galavox::admin::PlanetLimits;
+use galavox::world::WorldConfig;
+use galavox::world_info::{read_world, WorldInfo};
+use std::path::PathBuf;
+
+/*
+Prints statistics on a saved or exported world file (see `world_info`).
+
+    worldinfo <world.json> [--json] [--world-radius R] [--min-planet-gap G]
+
+`--world-radius` is the radius a saved world is checked against; exported
+worlds carry their own. Exits non-zero only when the file cannot be read.
+*/
+
+struct Args {
+    path: PathBuf,
+    json: bool,
+    radius: f32,
+    min_gap: f32,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut path = None;
+    let mut json = false;
+    let mut radius = WorldConfig::default().radius;
+    let mut min_gap = PlanetLimits::default().min_gap;
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--world-radius" => radius = iter.next().and_then(|v| v.parse().ok()).ok_or("--world-radius needs a number")?,
+            "--min-planet-gap" => min_gap = iter.next().and_then(|v| v.parse().ok()).ok_or("--min-planet-gap needs a number")?,
+            other if other.starts_with("--") => return Err(format!("unknown argument: {}", other)),
+            other => path = Some(PathBuf::from(other)),
+        }
+    }
+    let path = path.ok_or("usage: worldinfo <world.json> [--json] [--world-radius R] [--min-planet-gap G]")?;
+    Ok(Args { path, json, radius, min_gap })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args()?;
+    let text = std::fs::read_to_string(&args.path).map_err(|e| format!("cannot read {}: {}", args.path.display(), e))?;
+    let (file, source) = read_world(&text, args.radius).map_err(|e| format!("{}: {}", args.path.display(), e))?;
+    let info = WorldInfo::of(&file, source, &PlanetLimits { min_gap: args.min_gap, ..PlanetLimits::default() });
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        println!("🌍 {}", args.path.display());
+        println!("{}", info.render());
+    }
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use crate::protocol::{encode_server_message, encoding_for, Batched, Capabilities, GameState, ServerMessage, WireFormat};
+use crate::server::ConnectionId;
+use crate::subscriptions::Subscriptions;
+
+/*
+Getting broadcasts encoded without stalling the runtime.
+
+Every connection task receives the same `BroadcastFrame` and takes its
+encoding for the client's wire format, which is produced at most once per
+format however many clients there are. Left to the connection tasks, the
+first client to see a frame encodes it while every other client of that
+format blocks on the result, so with a large world every runtime worker
+stalls at once each tick. Snapshots of more than `OFFLOAD_ENTITIES` bodies
+and players are therefore encoded in both formats on a blocking thread
+before they are published; smaller frames are still encoded lazily, as the
+round trip to the blocking pool costs more than encoding them. While a
+snapshot is being encoded, smaller messages sent in the meantime may reach
+clients ahead of it.
+
+Events broadcast during a tick (a player teleporting, a planet edited) are
+held back and published together just before the tick's State, so that
+fifty of them cost one frame per client rather than fifty. `batch` packs
+them into `Batch` messages of at most `MAX_BATCH_BYTES` each, measured in
+JSON, the larger encoding. What must not wait, like announcements, goes out
+`Immediate`ly, after the events queued before it so the order holds.
+Clients without the BATCH capability get a batch's events one frame each
+(see `BroadcastFrame::frames`), also encoded once per format. Binary
+clients without the TERRAIN, NAMES or LABELS capability get encodings of
+their own, planets without terrain, names or label colors, one for each
+combination in use (see `binary_layout`). Connections
+over their bandwidth budget skip some State frames (see `bandwidth`), never
+batches. Frames made `for_spectators`, like `Trails`, are skipped by player
+connections, and frames sent `to` some connections, like `FriendOnline`,
+by every other. Connections subscribed to only some kinds of broadcast
+skip the rest, and encode their own copy of a batch they want only part
+of (see `subscriptions`). Clients with the QUANTIZED capability get each
+State after their first as a `StateDelta` of their own (see `quantize`).
+
+`BroadcastStats` records how long each periodic broadcast took to build,
+from reading the state to the frame being ready to publish.
+*/
+
+/// Snapshots with more entities than this are encoded off the runtime.
+pub const OFFLOAD_ENTITIES: usize = 500;
+
+/// Largest batch of events, in JSON bytes; more makes another frame.
+pub const MAX_BATCH_BYTES: usize = 16 * 1024;
+
+/// Whether a broadcast may wait for the end of the tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Batched,
+    Immediate,
+}
+
+/// Packs `events` into as few messages of at most `cap` bytes as it can,
+/// in order. A batch of one is sent as the event itself, as is an event
+/// over the cap on its own.
+pub fn batch(events: Vec<ServerMessage>, cap: usize) -> Vec<ServerMessage> {
+    const OVERHEAD: usize = r#"{"type":"Batch","messages":[]}"#.len();
+    let mut frames = Vec::new();
+    let mut current: Vec<ServerMessage> = Vec::new();
+    let mut size = OVERHEAD;
+    for event in events {
+        let len = serde_json::to_vec(&event).map_or(0, |json| json.len()) + 1;  // and a comma
+        if !current.is_empty() && size + len > cap {
+            frames.push(wrap(std::mem::take(&mut current)));
+            size = OVERHEAD;
+        }
+        size += len;
+        current.push(event);
+    }
+    if !current.is_empty() {
+        frames.push(wrap(current));
+    }
+    frames
+}
+
+fn wrap(mut events: Vec<ServerMessage>) -> ServerMessage {
+    if events.len() == 1 {
+        return events.pop().unwrap();
+    }
+    ServerMessage::Batch { messages: Batched(events) }
+}
+
+/// How many ways planets are laid out in binary frames.
+const BINARY_LAYOUTS: usize = 8;
+
+/// Which of the binary layouts a client with `capabilities` takes: with or
+/// without terrain, with or without names, and with or without label
+/// colors.
+fn binary_layout(capabilities: Capabilities) -> usize {
+    capabilities.contains(Capabilities::TERRAIN) as usize
+        | (capabilities.contains(Capabilities::NAMES) as usize) << 1
+        | (capabilities.contains(Capabilities::LABELS) as usize) << 2
+}
+
+/// A broadcast message, encoded at most once per wire format no matter how
+/// many clients receive it.
+pub struct BroadcastFrame {
+    message: ServerMessage,
+    binary: [OnceLock<Option<Message>>; BINARY_LAYOUTS],
+    json: OnceLock<Option<Message>>,
+    unbatched_binary: [OnceLock<Vec<Message>>; BINARY_LAYOUTS],
+    unbatched_json: OnceLock<Vec<Message>>,
+    spectators_only: bool,
+    recipients: Option<Vec<ConnectionId>>,  // everyone when None
+}
+
+impl BroadcastFrame {
+    pub fn new(message: ServerMessage) -> Self {
+        BroadcastFrame {
+            message,
+            binary: Default::default(),
+            json: OnceLock::new(),
+            unbatched_binary: Default::default(),
+            unbatched_json: OnceLock::new(),
+            spectators_only: false,
+            recipients: None,
+        }
+    }
+
+    /// A frame only spectator connections send on.
+    pub fn for_spectators(message: ServerMessage) -> Self {
+        BroadcastFrame { spectators_only: true, ..BroadcastFrame::new(message) }
+    }
+
+    pub fn is_for_spectators(&self) -> bool {
+        self.spectators_only
+    }
+
+    /// A frame only `recipients` send on.
+    pub fn to(message: ServerMessage, recipients: Vec<ConnectionId>) -> Self {
+        BroadcastFrame { recipients: Some(recipients), ..BroadcastFrame::new(message) }
+    }
+
+    /// Whether `connection` sends this frame on, as far as its recipients go.
+    pub fn is_for(&self, connection: ConnectionId) -> bool {
+        self.recipients.as_ref().is_none_or(|recipients| recipients.contains(&connection))
+    }
+
+    /// A frame ready to publish: large snapshots come back already encoded.
+    pub async fn prepare(message: ServerMessage) -> Self {
+        let frame = BroadcastFrame::new(message);
+        if !frame.is_large() {
+            return frame;
+        }
+        tokio::task::spawn_blocking(move || {
+            frame.encoded(WireFormat::Binary);
+            frame.encoded(WireFormat::Json);
+            frame
+        })
+        .await
+        .expect("encoding a broadcast panicked")
+    }
+
+    pub fn is_large(&self) -> bool {
+        match &self.message {
+            ServerMessage::State { state, .. } => {
+                let asteroids: usize = state.belts.iter().map(|belt| belt.notable.len()).sum();
+                state.players.len() + state.planets.len() + asteroids > OFFLOAD_ENTITIES
+            }
+            _ => false,
+        }
+    }
+
+    /// The tick of a State frame; None for anything else.
+    pub fn state_tick(&self) -> Option<u64> {
+        match &self.message {
+            ServerMessage::State { tick, .. } => Some(*tick),
+            _ => None,
+        }
+    }
+
+    /// The tick, server time and world of a State frame; None for anything
+    /// else.
+    pub fn state(&self) -> Option<(u64, u64, &GameState)> {
+        match &self.message {
+            ServerMessage::State { tick, server_time_ms, state } => Some((*tick, *server_time_ms, state)),
+            _ => None,
+        }
+    }
+
+    /// The frame for a client in `format` with every capability.
+    pub fn encoded(&self, format: WireFormat) -> Option<Message> {
+        self.encoded_for(format, Capabilities::SUPPORTED)
+    }
+
+    fn encoded_for(&self, format: WireFormat, capabilities: Capabilities) -> Option<Message> {
+        let cell = match format {
+            WireFormat::Binary => &self.binary[binary_layout(capabilities)],
+            WireFormat::Json => &self.json,
+        };
+        cell.get_or_init(|| encoding_for(capabilities, || encode_server_message(format, &self.message)).ok()).clone()
+    }
+
+    /// What to send a client with these capabilities: the frame itself, or
+    /// a batch's events one by one to a client that cannot take batches.
+    pub fn frames(&self, format: WireFormat, capabilities: Capabilities) -> Vec<Message> {
+        let ServerMessage::Batch { messages } = &self.message else {
+            return self.encoded_for(format, capabilities).into_iter().collect();
+        };
+        if capabilities.contains(Capabilities::BATCH) {
+            return self.encoded_for(format, capabilities).into_iter().collect();
+        }
+        let cell = match format {
+            WireFormat::Binary => &self.unbatched_binary[binary_layout(capabilities)],
+            WireFormat::Json => &self.unbatched_json,
+        };
+        cell.get_or_init(|| encoding_for(capabilities, || {
+            messages.0.iter().filter_map(|message| encode_server_message(format, message).ok()).collect()
+        })).clone()
+    }
+
+    /// Like `frames`, for a client that only wants `subscriptions`: nothing
+    /// if it wants none of the frame, and a batch without the events it
+    /// does not want, encoded for it alone.
+    pub fn frames_for(&self, format: WireFormat, capabilities: Capabilities, subscriptions: Subscriptions) -> Vec<Message> {
+        if !subscriptions.wants(&self.message) {
+            return Vec::new();
+        }
+        let ServerMessage::Batch { messages } = &self.message else {
+            return self.frames(format, capabilities);
+        };
+        if messages.0.iter().all(|message| subscriptions.wants(message)) {
+            return self.frames(format, capabilities);
+        }
+        let wanted: Vec<ServerMessage> = messages.0.iter().filter(|message| subscriptions.wants(message)).cloned().collect();
+        let wanted = if capabilities.contains(Capabilities::BATCH) { vec![wrap(wanted)] } else { wanted };
+        encoding_for(capabilities, || {
+            wanted.iter().filter_map(|message| encode_server_message(format, message).ok()).collect()
+        })
+    }
+}
+
+/// How long periodic broadcasts have taken to build.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BroadcastStats {
+    pub ticks: u64,
+    pub offloaded: u64,  // encoded on a blocking thread
+    pub last: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+impl BroadcastStats {
+    pub fn record(&mut self, took: Duration, offloaded: bool) {
+        self.ticks += 1;
+        self.offloaded += offloaded as u64;
+        self.last = took;
+        self.max = self.max.max(took);
+        self.total += took;
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.ticks == 0 { Duration::ZERO } else { self.total / self.ticks as u32 }
+    }
+}
+
+impl std::fmt::Display for BroadcastStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "broadcasts={} offloaded={} build last={:.2}ms mean={:.2}ms max={:.2}ms",
+               self.ticks, self.offloaded,
+               self.last.as_secs_f64() * 1000.0,
+               self.mean().as_secs_f64() * 1000.0,
+               self.max.as_secs_f64() * 1000.0)
+    }
+}
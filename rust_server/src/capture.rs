@@ -0,0 +1,149 @@
+use std::io::{self, Read, Write};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Message};
+
+/*
+Client-side capture of received WebSocket frames (`client --record <file>`),
+replayed offline with `client --playback <file>`.
+
+File format, all integers little-endian:
+    header:  magic "GVXCAP" (6 bytes), format version u16
+    record:  elapsed ms since the client started u64, kind u8, payload length u32, payload
+Kinds: 0 text (UTF-8), 1 binary, 2 close (empty, or close code u16 then UTF-8
+reason), 3 ping, 4 pong. Readers reject other versions rather than guess, and
+a record cut short at the end of the file (the client was killed) ends it.
+*/
+
+pub const CAPTURE_MAGIC: &[u8; 6] = b"GVXCAP";
+pub const CAPTURE_VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Text = 0,
+    Binary = 1,
+    Close = 2,
+    Ping = 3,
+    Pong = 4,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => FrameKind::Text,
+            1 => FrameKind::Binary,
+            2 => FrameKind::Close,
+            3 => FrameKind::Ping,
+            4 => FrameKind::Pong,
+            other => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown frame kind {}", other))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedFrame {
+    pub elapsed_ms: u64,
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+impl CapturedFrame {
+    /// `None` for raw frames, which tungstenite never hands to readers.
+    pub fn from_message(elapsed_ms: u64, message: &Message) -> Option<Self> {
+        let (kind, payload) = match message {
+            Message::Text(text) => (FrameKind::Text, text.as_bytes().to_vec()),
+            Message::Binary(data) => (FrameKind::Binary, data.to_vec()),
+            Message::Close(None) => (FrameKind::Close, Vec::new()),
+            Message::Close(Some(frame)) => {
+                let mut payload = u16::from(frame.code).to_le_bytes().to_vec();
+                payload.extend_from_slice(frame.reason.as_bytes());
+                (FrameKind::Close, payload)
+            }
+            Message::Ping(data) => (FrameKind::Ping, data.to_vec()),
+            Message::Pong(data) => (FrameKind::Pong, data.to_vec()),
+            Message::Frame(_) => return None,
+        };
+        Some(CapturedFrame { elapsed_ms, kind, payload })
+    }
+
+    pub fn to_message(&self) -> io::Result<Message> {
+        let payload = self.payload.clone();
+        Ok(match self.kind {
+            FrameKind::Text => Message::Text(String::from_utf8(payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?.into()),
+            FrameKind::Binary => Message::Binary(payload.into()),
+            FrameKind::Close if payload.len() < 2 => Message::Close(None),
+            FrameKind::Close => Message::Close(Some(CloseFrame {
+                code: CloseCode::from(u16::from_le_bytes([payload[0], payload[1]])),
+                reason: String::from_utf8_lossy(&payload[2..]).into_owned().into(),
+            })),
+            FrameKind::Ping => Message::Ping(payload.into()),
+            FrameKind::Pong => Message::Pong(payload.into()),
+        })
+    }
+}
+
+pub struct CaptureWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the header; records follow with `write_frame`.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(CAPTURE_MAGIC)?;
+        out.write_all(&CAPTURE_VERSION.to_le_bytes())?;
+        Ok(CaptureWriter { out })
+    }
+
+    pub fn write_frame(&mut self, frame: &CapturedFrame) -> io::Result<()> {
+        self.out.write_all(&frame.elapsed_ms.to_le_bytes())?;
+        self.out.write_all(&[frame.kind as u8])?;
+        self.out.write_all(&(frame.payload.len() as u32).to_le_bytes())?;
+        self.out.write_all(&frame.payload)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+pub fn read_capture(mut input: impl Read) -> io::Result<Vec<CapturedFrame>> {
+    let mut header = [0u8; 8];
+    input.read_exact(&mut header)?;
+    if &header[..6] != CAPTURE_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a galavox capture"));
+    }
+    let version = u16::from_le_bytes([header[6], header[7]]);
+    if version != CAPTURE_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unsupported capture version {}", version)));
+    }
+
+    let mut frames = Vec::new();
+    loop {
+        let mut head = [0u8; 13];
+        if !read_full(&mut input, &mut head)? {
+            break;
+        }
+        let elapsed_ms = u64::from_le_bytes(head[..8].try_into().unwrap());
+        let kind = FrameKind::try_from(head[8])?;
+        let Some(payload) = read_exact_len(&mut input, u32::from_le_bytes(head[9..].try_into().unwrap()))? else { break };
+        frames.push(CapturedFrame { elapsed_ms, kind, payload });
+    }
+    Ok(frames)
+}
+
+/// Reads `len` bytes, or `None` if the input ended first. The buffer grows
+/// with what is actually read, so a corrupt length cannot allocate gigabytes.
+fn read_exact_len(input: &mut impl Read, len: u32) -> io::Result<Option<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    Ok((bytes.len() == len as usize).then_some(bytes))
+}
+
+/// Fills `buf`, returning false if the input ended first.
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match input.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::lifetime::{replace_file, set_aside};
+use crate::protocol::ChatLine;
+
+/*
+The global chat log, so that players who join have some context.
+
+Every chat message a player sends is logged with its sender, the time and
+the room they connected to (see `handshake`), the oldest dropped once there
+are `HISTORY_LIMIT`. A joining player gets the last `JOIN_HISTORY` in a
+`ChatHistory` right after their `Joined`, and pages further back with
+`MoreHistory { before_timestamp, limit }`, answered with the `limit` lines
+just before that time, at most `MORE_HISTORY_LIMIT`. Fewer lines than
+asked for means the log goes back no further.
+
+Only global chat belongs here. Everything players send with `Chat` today is
+global; whispers and local chat, when there are any, are meant for the
+players they were sent to and stay out of the log.
+
+Timestamps are milliseconds since the Unix epoch, made strictly increasing
+as lines are logged, so paging by timestamp never skips or repeats a line
+sent in the same millisecond as another.
+
+With a `chat_file` the log is kept across restarts the way the lifetime
+stats are (see `lifetime`): saved as JSON every `SAVE_INTERVAL` and at a
+clean shutdown, and an unreadable file set aside as `<chat_file>.corrupt`.
+*/
+
+/// Most lines kept.
+pub const HISTORY_LIMIT: usize = 1_000;
+
+/// Lines sent to a player on joining.
+pub const JOIN_HISTORY: usize = 20;
+
+/// Most lines one `MoreHistory` is answered with.
+pub const MORE_HISTORY_LIMIT: usize = 100;
+
+/// The last `HISTORY_LIMIT` global chat lines, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct ChatLog {
+    path: Option<PathBuf>,
+    lines: VecDeque<ChatLine>,
+}
+
+impl ChatLog {
+    /// An empty log, kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The log saved in `path`, saved back there from now on; a missing
+    /// file is an empty log, an unreadable one is set aside.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let lines = match std::fs::read_to_string(path) {
+            Ok(text) => match serde_json::from_str::<VecDeque<ChatLine>>(&text) {
+                Ok(mut lines) => {
+                    while lines.len() > HISTORY_LIMIT {
+                        lines.pop_front();
+                    }
+                    lines
+                }
+                Err(e) => {
+                    set_aside(path, "chat history", &e.to_string());
+                    VecDeque::new()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => {
+                set_aside(path, "chat history", &e.to_string());
+                VecDeque::new()
+            }
+        };
+        ChatLog { path: Some(path.to_path_buf()), lines }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Logs `text` from `sender`, connected to `room`, sent at `unix_ms`
+    /// (or just after the line before, if that is later). Returns the line.
+    pub fn record(&mut self, sender: &str, room: Option<&str>, text: &str, unix_ms: u64) -> ChatLine {
+        let timestamp_ms = match self.lines.back() {
+            Some(last) if last.timestamp_ms >= unix_ms => last.timestamp_ms + 1,
+            _ => unix_ms,
+        };
+        let line = ChatLine { sender: sender.to_string(), timestamp_ms, room: room.map(str::to_string), text: text.to_string() };
+        if self.lines.len() >= HISTORY_LIMIT {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.clone());
+        line
+    }
+
+    /// The last `count` lines, oldest first.
+    pub fn latest(&self, count: usize) -> Vec<ChatLine> {
+        self.lines.iter().skip(self.lines.len().saturating_sub(count)).cloned().collect()
+    }
+
+    /// Up to `limit` lines (at most `MORE_HISTORY_LIMIT`) from just before
+    /// `before_ms`, oldest first.
+    pub fn before(&self, before_ms: u64, limit: usize) -> Vec<ChatLine> {
+        let end = self.lines.partition_point(|line| line.timestamp_ms < before_ms);
+        let start = end.saturating_sub(limit.min(MORE_HISTORY_LIMIT));
+        self.lines.range(start..end).cloned().collect()
+    }
+
+    /// Writes the log to its file, if it has one.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        replace_file(path, &serde_json::to_string(&self.lines)?)
+    }
+}
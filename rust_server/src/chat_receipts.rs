@@ -0,0 +1,82 @@
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use std::time::Duration;
+use crate::mutes::format_duration;
+
+/*
+Receipts for chat messages.
+
+A client relaying chat elsewhere, a bridge bot say, needs to know whether
+the server took each message and in what order. A client with the
+CHAT_ACKS capability may attach a `client_ref` to a `Chat`, any number it
+likes, and the server answers that chat with one of:
+
+- ChatAck { client_ref, server_seq, tick }: the chat was logged and
+  queued for everyone as a `Chatted` of the same `tick`, the connection's
+  `server_seq`th accepted chat (counting from 1, see `ChatSequence`).
+  `tick` is the server's tick as the chat was queued, and the `Chatted`
+  goes out with the next batch of events, so it may reach the author
+  before or after the receipt.
+- ChatRejected { client_ref, error }: it was dropped, and why (see
+  `ChatError`).
+
+`client_ref` is only echoed, never checked: refs need not increase or be
+unique, and the order chats were taken in is `server_seq`'s. Chats without
+a `client_ref`, or from clients without the capability, are answered as
+before: an `Echo`, or a notice if they were refused.
+
+Chats are refused when the sender is over the chat rate limit, is muted
+(see `mutes`) or the text is longer than `MAX_CHAT_LEN` characters, with
+or without the capability. A sender far enough over the rate limit is
+disconnected instead, with no receipt.
+A shadow-muted sender's chats are acknowledged like any others.
+
+The client library's `Connection::send_chat_tracked` attaches refs itself
+and hands back a future of the receipt.
+*/
+
+/// Most characters in one chat message.
+pub const MAX_CHAT_LEN: usize = 500;
+
+/// Why a chat was refused.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ChatError {
+    RateLimited { retry_after_ms: u64 },
+    TooLong { max_len: u32 },
+    Muted { remaining_ms: Option<u64> },  // None until unmuted
+}
+
+impl std::fmt::Display for ChatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatError::RateLimited { retry_after_ms } => write!(f, "too many messages, try again in {:.1}s", *retry_after_ms as f64 / 1000.0),
+            ChatError::TooLong { max_len } => write!(f, "chat messages are at most {} characters", max_len),
+            ChatError::Muted { remaining_ms: Some(ms) } => write!(f, "you are muted for {}", format_duration(Duration::from_millis(*ms))),
+            ChatError::Muted { remaining_ms: None } => write!(f, "you are muted"),
+        }
+    }
+}
+
+/// `text` if it is short enough to send.
+pub fn check_length(text: &str) -> Result<(), ChatError> {
+    if text.chars().count() > MAX_CHAT_LEN {
+        return Err(ChatError::TooLong { max_len: MAX_CHAT_LEN as u32 });
+    }
+    Ok(())
+}
+
+/// Numbers one connection's accepted chats, 1, 2, 3...
+#[derive(Debug, Default)]
+pub struct ChatSequence(u32);
+
+impl ChatSequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of the chat just accepted.
+    pub fn accept(&mut self) -> u32 {
+        self.0 = self.0.wrapping_add(1);
+        self.0
+    }
+}
@@ -0,0 +1,749 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio_tungstenite::{connect_async, tungstenite, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream};
+#[cfg(feature = "tls")]
+use tokio_tungstenite::{connect_async_tls_with_config, Connector};
+use crate::chat_receipts::ChatError;
+use crate::diff::StateDiff;
+use crate::handshake::{valid_player_name, MAX_PLAYER_NAME_LEN};
+use crate::integrity::{self, CorruptFrame};
+use crate::locale::MessageTable;
+use crate::ordering::Sequencer;
+use crate::prediction::PendingInputs;
+use crate::protocol::{
+    encode_motion_update, encode_position_update, encode_resync_request, encode_time_sync_request, encoding_for, Capabilities, ClientMessage, GameState,
+    MessageCode, Player, Position, ServerMessage, CAPABILITIES_HEADER, WORLD_RADIUS_HEADER,
+};
+use crate::quantize::StreamDecoder;
+use crate::subscriptions::Subscriptions;
+use crate::traffic::ConnectionStats;
+use crate::validate::{Validator, Violation};
+
+pub mod blocking;
+
+/*
+A galavox client for embedding in other programs.
+
+`Connection::connect` joins a server and `next_event` yields what happens
+there as `ClientEvent`s; the `send_*` methods act in the world. The
+connection speaks the binary wire format and answers pings and the
+server's keepalives itself. Every
+position update gets the next sequence number, so updates from one
+`Connection` are never applied out of order. Updates are kept in
+`pending_inputs` until the server applies them, and when it corrects one
+the rest are replayed on top of the correction (see `prediction`). After
+`validate` every event is also checked against the protocol's invariants
+(see `validate`). A connection opened with the CRC capability seals the
+binary frames it sends and checks those it receives, dropping any that
+fail as a `CorruptFrame` event before the `on_frame` hook sees them (see
+`integrity`). Every frame received is counted by type in `stats` (see
+`traffic`). Notices sent as codes arrive as `Localized` events, rendered
+in English unless `set_message_table` gave the connection a translation
+(see `locale`). When the server hands over to a standby and sends
+`Reconnect`, the connection opens again there as it was first opened,
+name and capabilities included, and says so with `Redirected` (see
+`replication`).
+
+The server does not announce joins and departures, so `EventDecoder` infers
+them from the periodic snapshots: a player is `PlayerJoined` when first seen
+and `PlayerLeft` when missing from a snapshot without having gone idle
+(idle players are left out of broadcasts). Players in the first snapshot
+after connecting are not announced, and nor is a player who leaves while
+idle. `Batch` frames are unwrapped into the events of each message in
+them, in order. A `StateDelta` is applied to the world the State and
+deltas before it built, and comes out as a `StateSnapshot` like a State
+does (see `quantize`). A State, StateDelta, Resync or PositionCorrection
+older than one already decoded is dropped without an event, so our own
+position never goes back to before a correction (see `ordering`). The
+decoder also works on frames from elsewhere, e.g. a recorded capture.
+
+Programs without a tokio runtime use `blocking::Connection`, the same
+connection behind blocking calls on a thread of its own.
+
+    use galavox::client::{ClientEvent, Connection};
+
+    let mut connection = Connection::connect("ws://localhost:8080", Some("Ada")).await?;
+    loop {
+        match connection.next_event().await {
+            ClientEvent::PlayerJoined { player } => println!("{} joined", player.name),
+            ClientEvent::Disconnected { .. } => break,
+            _ => {}
+        }
+    }
+*/
+
+/// Something that happened on the server, as seen by one client.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// The whole world, on joining and then at every broadcast, whether it
+    /// came whole or as a `StateDelta`
+    StateSnapshot { tick: u64, server_time_ms: u64, state: GameState },
+    /// The changes since `from_tick`, in reply to `request_resync`
+    Delta { from_tick: u64, tick: u64, diffs: Vec<StateDiff> },
+    /// This client joined as `player_id`
+    Joined { player_id: u32, spawn: Position, spawn_planet_id: Option<u32> },
+    PlayerJoined { player: Player },
+    PlayerLeft { player_id: u32 },
+    /// Our own chat message, echoed back by the server
+    Chat { text: String },
+    /// An announcement or warning from the server
+    Notice { text: String },
+    /// A notice sent as a code, with `text` rendered by the connection's
+    /// `MessageTable` (see `locale`)
+    Localized { message: MessageCode, text: String },
+    /// The message of the day or a server-wide announcement
+    Announcement { text: String },
+    /// The world was regenerated: forget it, the next `StateSnapshot` is
+    /// the new one
+    WorldReset { seed: u64 },
+    /// Any other server message
+    Message(ServerMessage),
+    /// The server handed over to `to` and the connection now goes there;
+    /// we join afresh, so expect a new `Joined` and `StateSnapshot`
+    Redirected { to: String },
+    /// The connection is over; `code` is the server's close code, if it sent one
+    Disconnected { code: Option<u16>, reason: String },
+    /// A frame from the server failed its CRC and was dropped (see `integrity`)
+    CorruptFrame { expected: u32, got: u32 },
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    InvalidName(String),
+    /// `wss://` URLs need the `tls` feature
+    TlsUnavailable,
+    WebSocket(tungstenite::Error),
+    Json(serde_json::Error),
+    /// The call needs a capability the server did not agree to
+    Unsupported(Capabilities),
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::InvalidName(name) => write!(f, "invalid player name {:?} (1 to {} letters, digits, _ or -)", name, MAX_PLAYER_NAME_LEN),
+            ClientError::TlsUnavailable => write!(f, "wss:// URLs need galavox built with the `tls` feature"),
+            ClientError::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            ClientError::Json(e) => write!(f, "JSON encoding failed: {}", e),
+            ClientError::Unsupported(capabilities) => write!(f, "the server did not agree to capabilities {}", capabilities.0),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(e: tungstenite::Error) -> Self {
+        ClientError::WebSocket(e)
+    }
+}
+
+/// What became of a chat sent with `Connection::send_chat_tracked` (see
+/// `chat_receipts`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatReceipt {
+    /// The server took it as this connection's `server_seq`th chat, in
+    /// broadcast tick `tick`
+    Accepted { server_seq: u32, tick: u64 },
+    Rejected(ChatError),
+    /// The connection ended, or moved to another server, before an answer
+    Unanswered,
+}
+
+/// A `ChatReceipt` to come. It is settled by `Connection::next_event`
+/// reading the answer, so something must keep calling that meanwhile.
+pub struct PendingChat(oneshot::Receiver<ChatReceipt>);
+
+impl Future for PendingChat {
+    type Output = ChatReceipt;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ChatReceipt> {
+        Pin::new(&mut self.0).poll(cx).map(|receipt| receipt.unwrap_or(ChatReceipt::Unanswered))
+    }
+}
+
+/// Turns received frames into events, tracking who is in the world.
+#[derive(Debug, Default)]
+pub struct EventDecoder {
+    players: Option<HashMap<u32, Player>>,  // None until the first snapshot
+    idle: HashSet<u32>,
+    capabilities: Option<Capabilities>,  // None decodes frames carrying everything
+    messages: MessageTable,
+    sequencer: Sequencer,  // drops stale States and corrections (see `ordering`)
+    stream: StreamDecoder,  // the world the `StateDelta`s build on (see `quantize`)
+}
+
+impl EventDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A decoder for frames sent to a client with `capabilities`.
+    pub fn for_capabilities(capabilities: Capabilities) -> Self {
+        EventDecoder { capabilities: Some(capabilities), ..Self::default() }
+    }
+
+    /// Renders `Localized` notices with `messages` rather than in English.
+    pub fn set_message_table(&mut self, messages: MessageTable) {
+        self.messages = messages;
+    }
+
+    /// The table `Localized` notices are rendered with.
+    pub fn message_table(&self) -> &MessageTable {
+        &self.messages
+    }
+
+    fn decode_binary(&self, data: &[u8]) -> bincode::Result<ServerMessage> {
+        match self.capabilities {
+            Some(capabilities) => encoding_for(capabilities, || ServerMessage::from_bincode(data)),
+            None => ServerMessage::from_bincode(data),
+        }
+    }
+
+    /// The events one frame amounts to, in order; pings and pongs are none.
+    pub fn decode(&mut self, frame: Message) -> Vec<ClientEvent> {
+        match frame {
+            Message::Binary(data) => match self.decode_binary(&data) {
+                Ok(message) => self.decode_message(message),
+                Err(e) => vec![ClientEvent::Notice { text: format!("undecodable server message: {}", e) }],
+            },
+            // Binary-mode servers send notices and chat echoes as plain text
+            Message::Text(text) => match text.strip_prefix("Echo: ") {
+                Some(echo) => vec![ClientEvent::Chat { text: echo.to_string() }],
+                None => vec![ClientEvent::Notice { text: text.to_string() }],
+            },
+            Message::Close(frame) => vec![ClientEvent::Disconnected {
+                code: frame.as_ref().map(|f| f.code.into()),
+                reason: frame.map(|f| f.reason.to_string()).unwrap_or_default(),
+            }],
+            _ => vec![],
+        }
+    }
+
+    fn decode_message(&mut self, message: ServerMessage) -> Vec<ClientEvent> {
+        match message {
+            ServerMessage::State { tick, .. } | ServerMessage::Resync { tick, .. } | ServerMessage::StateDelta { tick, .. }
+                if !self.sequencer.admit_state(tick) => vec![],
+            ServerMessage::PositionCorrection { tick, .. } if !self.sequencer.admit_correction(tick) => vec![],
+            ServerMessage::State { tick, server_time_ms, state } => {
+                if self.capabilities.is_none_or(|capabilities| capabilities.contains(Capabilities::QUANTIZED)) {
+                    self.stream.reset(tick, &state);
+                }
+                self.snapshot(tick, server_time_ms, state)
+            }
+            ServerMessage::StateDelta { from_tick, tick, server_time_ms, diff } => match self.stream.apply(from_tick, tick, &diff) {
+                Some(state) => self.snapshot(tick, server_time_ms, state),
+                None => vec![ClientEvent::Notice { text: format!("undecodable state delta: no snapshot of tick {} to apply it to", from_tick) }],
+            },
+            ServerMessage::Resync { from_tick, tick, diffs } => vec![ClientEvent::Delta { from_tick, tick, diffs }],
+            ServerMessage::Batch { messages } => {
+                let mut events = Vec::new();
+                for message in messages.0 {
+                    events.extend(self.decode_message(message));
+                }
+                events
+            }
+            ServerMessage::Joined { player_id, spawn, spawn_planet_id } => vec![ClientEvent::Joined { player_id, spawn, spawn_planet_id }],
+            ServerMessage::Echo { text } => vec![ClientEvent::Chat { text }],
+            ServerMessage::Notice { text } => vec![ClientEvent::Notice { text }],
+            ServerMessage::Localized { message, .. } => {
+                let text = self.messages.render(&message);
+                vec![ClientEvent::Localized { message, text }]
+            }
+            ServerMessage::Announcement { text } => vec![ClientEvent::Announcement { text }],
+            ServerMessage::WorldReset { seed } => {
+                // Nobody joins or leaves in a reset; the next snapshot starts over
+                self.players = None;
+                self.idle.clear();
+                vec![ClientEvent::WorldReset { seed }]
+            }
+            message => {
+                match &message {
+                    ServerMessage::PlayerIdle { player_id } => { self.idle.insert(*player_id); }
+                    ServerMessage::PlayerActive { player_id } => { self.idle.remove(player_id); }
+                    _ => {}
+                }
+                vec![ClientEvent::Message(message)]
+            }
+        }
+    }
+
+    /// The events of a whole snapshot, however it came.
+    fn snapshot(&mut self, tick: u64, server_time_ms: u64, state: GameState) -> Vec<ClientEvent> {
+        let mut events = self.track_players(&state.players);
+        events.insert(0, ClientEvent::StateSnapshot { tick, server_time_ms, state });
+        events
+    }
+
+    fn track_players(&mut self, players: &[Player]) -> Vec<ClientEvent> {
+        // Join and reset snapshots include idle players; later ones do not
+        self.idle.extend(players.iter().filter(|p| p.idle).map(|p| p.id));
+        let current: HashMap<u32, Player> = players.iter().map(|p| (p.id, p.clone())).collect();
+        let Some(known) = self.players.replace(current) else { return vec![] };
+        let current = self.players.as_mut().unwrap();
+        let mut events: Vec<ClientEvent> = players.iter()
+            .filter(|p| !known.contains_key(&p.id))
+            .map(|p| ClientEvent::PlayerJoined { player: p.clone() })
+            .collect();
+        for (id, player) in known {
+            if current.contains_key(&id) {
+                continue;
+            }
+            if self.idle.contains(&id) {
+                current.insert(id, player);  // still here, just not broadcast
+            } else {
+                events.push(ClientEvent::PlayerLeft { player_id: id });
+            }
+        }
+        events
+    }
+}
+
+/// `url` with `pair` added to its query string.
+fn with_query(url: &str, pair: &str) -> String {
+    // `ws://host:port?name=` has no path, which the handshake rejects
+    let has_path = url.split_once("://").is_some_and(|(_, rest)| rest.contains('/'));
+    let root = if has_path || url.contains('?') { "" } else { "/" };
+    format!("{}{}{}{}", url, root, if url.contains('?') { '&' } else { '?' }, pair)
+}
+
+type FrameHook = Box<dyn FnMut(&Message) + Send>;
+
+/// How a connection was opened, to open it again after a `Reconnect`.
+#[derive(Debug, Clone)]
+struct Dial {
+    name: Option<String>,
+    spectator: bool,
+    insecure: bool,
+    capabilities: Capabilities,  // asked for, not agreed
+}
+
+impl Dial {
+    fn url(&self, base: &str) -> String {
+        let mut url = base.to_string();
+        if let Some(name) = &self.name {
+            url = with_query(&url, &format!("name={}", name));
+        }
+        if self.spectator {
+            url = with_query(&url, "role=spectator");
+        }
+        with_query(&url, &format!("caps={}", self.capabilities.0))
+    }
+}
+
+/// A live connection to a galavox server.
+pub struct Connection {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    decoder: EventDecoder,
+    pending: VecDeque<ClientEvent>,
+    last_seq: u32,
+    inputs: PendingInputs,
+    player_id: Option<u32>,
+    on_frame: Option<FrameHook>,
+    closed: bool,
+    capabilities: Capabilities,
+    world_radius: Option<f32>,
+    validator: Option<Validator>,
+    corrupt_frames: u64,
+    stats: ConnectionStats,
+    dial: Dial,
+    redirect: Option<String>,  // where a `Reconnect` said to go once this connection closes
+    chat_receipts: HashMap<u64, oneshot::Sender<ChatReceipt>>,  // by client_ref
+    last_chat_ref: u64,
+}
+
+impl Connection {
+    /// Connects to `url` (`ws://`, or `wss://` with the `tls` feature) as
+    /// `name`, or under a server-chosen name.
+    ///
+    /// ```no_run
+    /// # async fn run() -> Result<(), galavox::client::ClientError> {
+    /// let mut connection = galavox::client::Connection::connect("ws://localhost:8080", Some("Ada")).await?;
+    /// connection.send_chat("hello").await?;
+    /// connection.close().await
+    /// # }
+    /// ```
+    pub async fn connect(url: &str, name: Option<&str>) -> Result<Self, ClientError> {
+        Self::connect_with(url, name, false).await
+    }
+
+    /// Like `connect`; `insecure` skips TLS certificate checks (for
+    /// self-signed development certificates).
+    pub async fn connect_with(url: &str, name: Option<&str>, insecure: bool) -> Result<Self, ClientError> {
+        Self::connect_with_capabilities(url, name, insecure, Capabilities::DEFAULT).await
+    }
+
+    /// Like `connect_with`, asking for `capabilities` instead of the
+    /// `Capabilities::DEFAULT`, e.g. to add `Capabilities::CRC`.
+    pub async fn connect_with_capabilities(url: &str, name: Option<&str>, insecure: bool, capabilities: Capabilities) -> Result<Self, ClientError> {
+        if let Some(name) = name
+            && !valid_player_name(name)
+        {
+            return Err(ClientError::InvalidName(name.to_string()));
+        }
+        Self::open(url, Dial { name: name.map(str::to_string), spectator: false, insecure, capabilities }).await
+    }
+
+    /// Connects as a spectator, who receives the world but has no player in
+    /// it; position updates and chat are refused.
+    pub async fn spectate(url: &str, insecure: bool) -> Result<Self, ClientError> {
+        Self::spectate_with_capabilities(url, insecure, Capabilities::DEFAULT).await
+    }
+
+    /// Like `spectate`, asking for `capabilities`.
+    pub async fn spectate_with_capabilities(url: &str, insecure: bool, capabilities: Capabilities) -> Result<Self, ClientError> {
+        Self::open(url, Dial { name: None, spectator: true, insecure, capabilities }).await
+    }
+
+    async fn open(base: &str, dial: Dial) -> Result<Self, ClientError> {
+        let url = &dial.url(base);
+        let (ws, response) = if url.starts_with("wss://") {
+            #[cfg(feature = "tls")]
+            {
+                let connector = Connector::Rustls(crate::tls::client_config(dial.insecure));
+                connect_async_tls_with_config(url, None, false, Some(connector)).await?
+            }
+            #[cfg(not(feature = "tls"))]
+            return Err(ClientError::TlsUnavailable);
+        } else {
+            let _ = dial.insecure;
+            connect_async(url).await?
+        };
+        // Servers from before capabilities send no header, and no optional frames
+        let capabilities = response.headers().get(CAPABILITIES_HEADER)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .map_or(Capabilities::NONE, Capabilities);
+        let world_radius = response.headers().get(WORLD_RADIUS_HEADER)
+            .and_then(|value| value.to_str().ok()?.parse().ok());
+        Ok(Connection {
+            ws,
+            decoder: EventDecoder::for_capabilities(capabilities),
+            pending: VecDeque::new(),
+            last_seq: 0,
+            inputs: PendingInputs::new(),
+            player_id: None,
+            on_frame: None,
+            closed: false,
+            capabilities,
+            world_radius,
+            validator: None,
+            corrupt_frames: 0,
+            stats: ConnectionStats::new(),
+            dial,
+            redirect: None,
+            chat_receipts: HashMap::new(),
+            last_chat_ref: 0,
+        })
+    }
+
+    /// Calls `hook` with every frame received, before it is decoded, e.g. to
+    /// record a capture.
+    pub fn on_frame(&mut self, hook: impl FnMut(&Message) + Send + 'static) {
+        self.on_frame = Some(Box::new(hook));
+    }
+
+    /// Renders `Localized` notices with `messages` from now on.
+    pub fn set_message_table(&mut self, messages: MessageTable) {
+        self.decoder.set_message_table(messages);
+    }
+
+    /// How many frames from the server failed their CRC (see `integrity`).
+    pub fn corrupt_frames(&self) -> u64 {
+        self.corrupt_frames
+    }
+
+    /// What we have received so far, by message type (see `traffic`).
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats.clone()
+    }
+
+    /// What the server agreed to send us (see `Capabilities`).
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// The world's radius, if the server said (see `WORLD_RADIUS_HEADER`).
+    pub fn world_radius(&self) -> Option<f32> {
+        self.world_radius
+    }
+
+    /// Checks every event from now on against the protocol's invariants,
+    /// collecting what breaks them in `violations`.
+    pub fn validate(&mut self) {
+        self.validator = Some(Validator::new(self.world_radius));
+    }
+
+    /// What `validate` has found so far, oldest first.
+    pub fn violations(&self) -> &[Violation] {
+        self.validator.as_ref().map_or(&[], Validator::violations)
+    }
+
+    /// Our player id, once the server has sent `Joined`.
+    pub fn player_id(&self) -> Option<u32> {
+        self.player_id
+    }
+
+    /// The position updates the server has not applied yet, oldest first,
+    /// and where they put us (`PendingInputs::predicted`) after any
+    /// corrections.
+    pub fn pending_inputs(&self) -> &PendingInputs {
+        &self.inputs
+    }
+
+    /// Where the connection goes over the network, e.g. its local port.
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        match self.ws.get_ref() {
+            MaybeTlsStream::Plain(tcp) => tcp.local_addr().ok(),
+            #[cfg(feature = "tls")]
+            MaybeTlsStream::Rustls(tls) => tls.get_ref().0.local_addr().ok(),
+            _ => None,
+        }
+    }
+
+    /// The next event. Once the connection is over this is always
+    /// `Disconnected`. Cancel-safe, so it can be used in `tokio::select!`.
+    ///
+    /// ```no_run
+    /// # async fn run(mut connection: galavox::client::Connection) {
+    /// use galavox::client::ClientEvent;
+    /// loop {
+    ///     match connection.next_event().await {
+    ///         ClientEvent::StateSnapshot { state, .. } => println!("{} players", state.players.len()),
+    ///         ClientEvent::Disconnected { reason, .. } => break println!("gone: {}", reason),
+    ///         _ => {}
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn next_event(&mut self) -> ClientEvent {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                let event = match event {
+                    ClientEvent::Message(ServerMessage::Reconnect { to }) => {
+                        self.redirect = Some(to);
+                        continue;
+                    }
+                    ClientEvent::Disconnected { .. } if self.redirect.is_some() => self.follow_redirect().await,
+                    event => event,
+                };
+                match &event {
+                    // Answered here rather than passed on. Cancelling the
+                    // send loses the ack, which the server puts up with
+                    ClientEvent::Message(ServerMessage::Keepalive { seq }) => {
+                        let _ = self.send(&ClientMessage::KeepaliveAck { seq: *seq }).await;
+                        continue;
+                    }
+                    ClientEvent::Joined { player_id, .. } => self.player_id = Some(*player_id),
+                    ClientEvent::StateSnapshot { state, .. } => {
+                        if let Some(me) = state.players.iter().find(|p| Some(p.id) == self.player_id) {
+                            self.inputs.acknowledge(me.last_processed_seq);
+                        }
+                    }
+                    ClientEvent::Message(ServerMessage::PositionCorrection { position, seq: Some(seq), .. }) => {
+                        self.inputs.reconcile(*seq, position);
+                    }
+                    ClientEvent::Message(ServerMessage::ChatAck { client_ref, server_seq, tick }) => {
+                        self.settle_chat(*client_ref, ChatReceipt::Accepted { server_seq: *server_seq, tick: *tick });
+                    }
+                    ClientEvent::Message(ServerMessage::ChatRejected { client_ref, error }) => {
+                        self.settle_chat(*client_ref, ChatReceipt::Rejected(error.clone()));
+                    }
+                    ClientEvent::Disconnected { .. } => {
+                        self.closed = true;
+                        self.chat_receipts.clear();
+                    }
+                    _ => {}
+                }
+                if let Some(validator) = self.validator.as_mut() {
+                    validator.check(&event);
+                }
+                return event;
+            }
+            if self.closed {
+                return ClientEvent::Disconnected { code: None, reason: "connection closed".to_string() };
+            }
+            let frame = match self.ws.next().await.map(|frame| frame.map(|frame| self.unseal(frame))) {
+                Some(Ok(Ok(frame))) => frame,
+                Some(Ok(Err(CorruptFrame { expected, got }))) => {
+                    self.corrupt_frames += 1;
+                    self.pending.push_back(ClientEvent::CorruptFrame { expected, got });
+                    continue;
+                }
+                Some(Err(e)) => {
+                    self.pending.push_back(ClientEvent::Disconnected { code: None, reason: e.to_string() });
+                    continue;
+                }
+                None => {
+                    self.pending.push_back(ClientEvent::Disconnected { code: None, reason: "connection lost".to_string() });
+                    continue;
+                }
+            };
+            self.stats.record(&frame, Instant::now());
+            if let Some(hook) = self.on_frame.as_mut() {
+                hook(&frame);
+            }
+            if frame.is_ping() {
+                // tungstenite queues the pong; make sure it goes out
+                let _ = self.ws.flush().await;
+            }
+            self.pending.extend(self.decoder.decode(frame));
+        }
+    }
+
+    /// Opens the connection again where a `Reconnect` said, as it was first
+    /// opened; the stats, hooks and sequence numbers carry over. Cancelling
+    /// it leaves the redirect to be tried again.
+    async fn follow_redirect(&mut self) -> ClientEvent {
+        let to = self.redirect.clone().unwrap_or_default();
+        let opened = Self::open(&to, self.dial.clone()).await;
+        self.redirect = None;
+        let next = match opened {
+            Ok(next) => next,
+            Err(e) => return ClientEvent::Disconnected { code: None, reason: format!("could not reconnect to {}: {}", to, e) },
+        };
+        let messages = self.decoder.message_table().clone();
+        self.ws = next.ws;
+        self.decoder = next.decoder;
+        self.decoder.set_message_table(messages);
+        self.capabilities = next.capabilities;
+        self.world_radius = next.world_radius;
+        self.inputs = PendingInputs::new();
+        self.player_id = None;
+        // The old server will never answer these
+        self.chat_receipts.clear();
+        if self.validator.is_some() {
+            self.validator = Some(Validator::new(self.world_radius));
+        }
+        ClientEvent::Redirected { to }
+    }
+
+    /// Moves our ship to `position`, at rest.
+    pub async fn send_position(&mut self, position: Position) -> Result<u32, ClientError> {
+        let seq = self.next_seq();
+        self.ws.send(self.binary(encode_position_update(seq, &position))).await?;
+        self.inputs.push(seq, position);
+        Ok(seq)
+    }
+
+    /// Moves our ship to `position` with a velocity and a rotation quaternion;
+    /// returns the update's sequence number.
+    pub async fn send_motion(&mut self, position: Position, velocity: [f32; 3], rotation: [f32; 4]) -> Result<u32, ClientError> {
+        let seq = self.next_seq();
+        self.ws.send(self.binary(encode_motion_update(seq, &position, &velocity, &rotation))).await?;
+        self.inputs.push(seq, position);
+        Ok(seq)
+    }
+
+    pub async fn send_chat(&mut self, text: &str) -> Result<(), ClientError> {
+        self.send(&ClientMessage::Chat { text: text.to_string(), client_ref: None }).await
+    }
+
+    /// Sends a chat with a `client_ref` of its own, for the server to
+    /// answer with a `ChatAck` or `ChatRejected`; the receipt resolves when
+    /// `next_event` reads the answer. Needs the CHAT_ACKS capability.
+    ///
+    /// ```no_run
+    /// # async fn run(mut connection: galavox::client::Connection) -> Result<(), galavox::client::ClientError> {
+    /// use galavox::client::ClientEvent;
+    /// let mut receipt = connection.send_chat_tracked("hello").await?;
+    /// loop {
+    ///     tokio::select! {
+    ///         receipt = &mut receipt => break println!("{:?}", receipt),
+    ///         event = connection.next_event() => if let ClientEvent::Disconnected { .. } = event { break },
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_chat_tracked(&mut self, text: &str) -> Result<PendingChat, ClientError> {
+        if !self.capabilities.contains(Capabilities::CHAT_ACKS) {
+            return Err(ClientError::Unsupported(Capabilities::CHAT_ACKS));
+        }
+        self.last_chat_ref += 1;
+        let client_ref = self.last_chat_ref;
+        self.send(&ClientMessage::Chat { text: text.to_string(), client_ref: Some(client_ref) }).await?;
+        let (settle, receipt) = oneshot::channel();
+        self.chat_receipts.insert(client_ref, settle);
+        Ok(PendingChat(receipt))
+    }
+
+    /// Resolves the receipt of the chat sent with `client_ref`, if it is
+    /// still awaited.
+    fn settle_chat(&mut self, client_ref: u64, receipt: ChatReceipt) {
+        if let Some(settle) = self.chat_receipts.remove(&client_ref) {
+            let _ = settle.send(receipt);
+        }
+    }
+
+    /// Any command, e.g. `ClientMessage::TeleportToPlanet`.
+    pub async fn send(&mut self, message: &ClientMessage) -> Result<(), ClientError> {
+        let text = serde_json::to_string(message).map_err(ClientError::Json)?;
+        self.ws.send(Message::Text(text.into())).await?;
+        Ok(())
+    }
+
+    /// Asks for a `TimeSync` reply echoing `client_time_ms`.
+    pub async fn send_time_sync(&mut self, client_time_ms: u64) -> Result<(), ClientError> {
+        self.ws.send(self.binary(encode_time_sync_request(client_time_ms))).await?;
+        Ok(())
+    }
+
+    /// Asks for the changes since `tick`, answered with a `Delta` (or a
+    /// snapshot if the server no longer has them).
+    pub async fn request_resync(&mut self, tick: u64) -> Result<(), ClientError> {
+        self.ws.send(self.binary(encode_resync_request(tick))).await?;
+        if let Some(validator) = self.validator.as_mut() {
+            validator.expect_resync();
+        }
+        Ok(())
+    }
+
+    /// Starts or stops the server's once-a-second `DebugStats` frames about
+    /// this connection, which arrive as `ClientEvent::Message`s.
+    pub async fn set_debug_stats(&mut self, enabled: bool) -> Result<(), ClientError> {
+        self.send(&ClientMessage::DebugStats { enabled }).await
+    }
+
+    /// Asks the server to broadcast only the kinds of message in
+    /// `subscriptions` from now on; replies still all come.
+    pub async fn set_subscriptions(&mut self, subscriptions: Subscriptions) -> Result<(), ClientError> {
+        self.send(&ClientMessage::SetSubscriptions { mask: subscriptions.0 }).await
+    }
+
+    /// Asks for a `KeepaliveAck` echoing `seq`, to check the path to the
+    /// server; it arrives as a `ClientEvent::Message`.
+    pub async fn send_keepalive(&mut self, seq: u32) -> Result<(), ClientError> {
+        self.send(&ClientMessage::Keepalive { seq }).await
+    }
+
+    /// Closes the connection politely.
+    pub async fn close(&mut self) -> Result<(), ClientError> {
+        self.closed = true;
+        match self.ws.close(None).await {
+            Ok(()) | Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// A binary frame of `payload`, sealed if we have the CRC capability.
+    fn binary(&self, payload: Vec<u8>) -> Message {
+        if self.capabilities.contains(Capabilities::CRC) { Message::Binary(integrity::seal(&payload)) } else { Message::Binary(payload.into()) }
+    }
+
+    /// Checks and strips the CRC of a received binary frame if we have the
+    /// CRC capability.
+    fn unseal(&self, frame: Message) -> Result<Message, CorruptFrame> {
+        if self.capabilities.contains(Capabilities::CRC) { integrity::open_message(frame) } else { Ok(frame) }
+    }
+
+    fn next_seq(&mut self) -> u32 {
+        self.last_seq = self.last_seq.wrapping_add(1);
+        self.last_seq
+    }
+}
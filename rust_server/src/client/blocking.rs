@@ -0,0 +1,201 @@
+use std::sync::mpsc::{self as std_mpsc, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite;
+use crate::client::{ClientError, ClientEvent, Connection as AsyncConnection};
+use crate::protocol::{ClientMessage, Position};
+
+/*
+The client for programs that do not run tokio.
+
+`Connection` here is the async `client::Connection` behind plain blocking
+calls, with the same events, errors and arguments, so code moves between
+the two by adding or removing `.await`s. Each one owns a thread running a
+single-threaded runtime, on which the async connection does the work: it
+reads events as they come, queueing them for `recv_event`, and carries
+out the calls in the order they were made, each blocking until the
+connection has done it. Events wait in memory until received, so a
+program that stops calling `recv_event` should close the connection.
+
+`close` sends the close frame and waits for the thread to finish;
+dropping a connection does the same, so neither leaks the thread nor
+leaves the server to notice the connection broken. A close that cannot be
+sent within `CLOSE_TIMEOUT` is given up on. Once the connection is over,
+whichever side ended it, `recv_event` always returns `Disconnected` and
+the `send_*` calls fail, as with the async connection.
+
+The calls block, so they must not be made from inside an async runtime.
+
+    use galavox::client::{blocking::Connection, ClientEvent};
+    use std::time::Duration;
+
+    let mut connection = Connection::connect("ws://localhost:8080", Some("Ada"))?;
+    connection.send_chat("hello")?;
+    while let Some(event) = connection.recv_event(Duration::from_secs(1)) {
+        if let ClientEvent::Disconnected { .. } = event {
+            break;
+        }
+    }
+    connection.close()?;
+*/
+
+/// Longest a close, or a drop, waits to send the close frame.
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Reply<T> = oneshot::Sender<Result<T, ClientError>>;
+
+/// A call for the connection's thread to carry out.
+enum Command {
+    Position(Position, Reply<u32>),
+    Send(ClientMessage, Reply<()>),
+    Close(Reply<()>),
+}
+
+/// A live connection to a galavox server, driven from its own thread.
+pub struct Connection {
+    commands: mpsc::UnboundedSender<Command>,
+    events: std_mpsc::Receiver<ClientEvent>,
+    worker: Option<JoinHandle<()>>,  // None once closed
+    disconnected: Option<ClientEvent>,  // repeated once the connection is over
+}
+
+impl Connection {
+    /// Connects to `url` as `name`, or under a server-chosen name, as
+    /// `client::Connection::connect` does.
+    pub fn connect(url: &str, name: Option<&str>) -> Result<Self, ClientError> {
+        let (url, name) = (url.to_string(), name.map(str::to_string));
+        let (opened, connected) = std_mpsc::sync_channel(1);
+        let (commands, queued) = mpsc::unbounded_channel();
+        let (sent, events) = std_mpsc::channel();
+        let worker = std::thread::Builder::new()
+            .name("galavox-client".to_string())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => return drop(opened.send(Err(ClientError::WebSocket(tungstenite::Error::Io(e))))),
+                };
+                runtime.block_on(async move {
+                    match AsyncConnection::connect(&url, name.as_deref()).await {
+                        Ok(connection) => {
+                            let _ = opened.send(Ok(()));
+                            serve(connection, queued, sent).await;
+                        }
+                        Err(e) => drop(opened.send(Err(e))),
+                    }
+                });
+            })
+            .map_err(|e| ClientError::WebSocket(tungstenite::Error::Io(e)))?;
+        match connected.recv() {
+            Ok(Ok(())) => Ok(Connection { commands, events, worker: Some(worker), disconnected: None }),
+            Ok(Err(e)) => {
+                let _ = worker.join();
+                Err(e)
+            }
+            Err(_) => {
+                let _ = worker.join();
+                Err(closed())
+            }
+        }
+    }
+
+    /// The next event, or None if there was none within `timeout`. Once the
+    /// connection is over this is always `Disconnected`.
+    pub fn recv_event(&mut self, timeout: Duration) -> Option<ClientEvent> {
+        if let Some(disconnected) = &self.disconnected {
+            return Some(disconnected.clone());
+        }
+        let event = match self.events.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return None,
+            Err(RecvTimeoutError::Disconnected) => ClientEvent::Disconnected { code: None, reason: "connection closed".to_string() },
+        };
+        if let ClientEvent::Disconnected { .. } = event {
+            self.disconnected = Some(event.clone());
+        }
+        Some(event)
+    }
+
+    /// Moves our ship to `position`, at rest; returns the update's
+    /// sequence number.
+    pub fn send_position(&mut self, position: Position) -> Result<u32, ClientError> {
+        self.call(|reply| Command::Position(position, reply))
+    }
+
+    pub fn send_chat(&mut self, text: &str) -> Result<(), ClientError> {
+        self.send(&ClientMessage::Chat { text: text.to_string(), client_ref: None })
+    }
+
+    /// Any command, e.g. `ClientMessage::TeleportToPlanet`.
+    pub fn send(&mut self, message: &ClientMessage) -> Result<(), ClientError> {
+        let message = message.clone();
+        self.call(|reply| Command::Send(message, reply))
+    }
+
+    /// Closes the connection politely and waits for its thread to finish.
+    /// Closing a connection that is already over does nothing.
+    pub fn close(&mut self) -> Result<(), ClientError> {
+        let Some(worker) = self.worker.take() else { return Ok(()) };
+        let closed = match self.call(Command::Close) {
+            Err(ClientError::WebSocket(tungstenite::Error::AlreadyClosed)) => Ok(()),
+            closed => closed,
+        };
+        let _ = worker.join();
+        closed
+    }
+
+    /// Hands a command to the connection's thread and waits for its answer.
+    fn call<T>(&self, command: impl FnOnce(Reply<T>) -> Command) -> Result<T, ClientError> {
+        let (reply, answer) = oneshot::channel();
+        self.commands.send(command(reply)).map_err(|_| closed())?;
+        answer.blocking_recv().unwrap_or_else(|_| Err(closed()))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// What a call on a connection that is over fails with, as on the async one.
+fn closed() -> ClientError {
+    ClientError::WebSocket(tungstenite::Error::AlreadyClosed)
+}
+
+/// Runs on the connection's thread: passes events on and carries out
+/// calls until the connection is over or closed.
+async fn serve(mut connection: AsyncConnection, mut commands: mpsc::UnboundedReceiver<Command>, events: std_mpsc::Sender<ClientEvent>) {
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(Command::Position(position, reply)) => drop(reply.send(connection.send_position(position).await)),
+                Some(Command::Send(message, reply)) => drop(reply.send(connection.send(&message).await)),
+                Some(Command::Close(reply)) => {
+                    let _ = reply.send(close(&mut connection).await);
+                    break;
+                }
+                // The handle is gone without closing, which `Drop` prevents
+                None => {
+                    let _ = close(&mut connection).await;
+                    return;
+                }
+            },
+            // Cancel-safe, so a call coming in loses no event
+            event = connection.next_event() => {
+                let over = matches!(event, ClientEvent::Disconnected { .. });
+                let _ = events.send(event);
+                if over {
+                    return;
+                }
+            }
+        }
+    }
+    // Closed by us: the `Disconnected` the async connection now returns
+    let _ = events.send(connection.next_event().await);
+}
+
+async fn close(connection: &mut AsyncConnection) -> Result<(), ClientError> {
+    tokio::time::timeout(CLOSE_TIMEOUT, connection.close()).await
+        .unwrap_or_else(|_| Err(ClientError::WebSocket(tungstenite::Error::Io(std::io::ErrorKind::TimedOut.into()))))
+}
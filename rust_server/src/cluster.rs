@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use mini_redis::client;
+use serde::{Serialize, Deserialize};
+use tokio::sync::watch;
+use crate::protocol::{Planet, Player};
+use crate::server::GameServer;
+
+/*
+Several server instances sharing one world through Redis (`--cluster
+redis://host:port`, with the `cluster` feature).
+
+Each instance publishes its own active players on `PLAYERS_CHANNEL` at
+every broadcast, and merges what the other instances publish into its
+States, with `remote` set. Remote players are not in the local player
+table, so nothing on this instance can move them; an instance that has not
+been heard from for `REMOTE_TIMEOUT` is dropped along with its players.
+Every instance gives out player ids from its own range, starting at
+`first_player_id`, so ids never collide across the cluster.
+
+Instance 0 is the primary and owns the planets: whenever they change, and
+when it starts, it writes them to `PLANETS_KEY` and says so on
+`PLANETS_CHANNEL`. The other instances load the planets from the key when
+they start and on every such notice, refuse admin edits to the world and
+leave resource regrowth to the primary.
+
+Publishing goes through a task of its own, latest value wins, so a slow
+Redis never holds up a broadcast. A lost connection is retried every
+`RETRY_INTERVAL`. Without `--cluster` none of this runs.
+*/
+
+pub const PLAYERS_CHANNEL: &str = "galavox:players";
+pub const PLANETS_CHANNEL: &str = "galavox:planets";
+pub const PLANETS_KEY: &str = "galavox:planets";
+
+/// How long an instance's players stay after its last update.
+pub const REMOTE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long `ClusterConfig::check_reachable` waits for Redis.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Player ids per instance; instance `n` gives out `n << PLAYER_ID_BITS` on.
+const PLAYER_ID_BITS: u32 = 24;
+
+/// The first player id instance `instance` gives out.
+pub fn first_player_id(instance: u8) -> u32 {
+    (instance as u32) << PLAYER_ID_BITS
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterConfig {
+    pub addr: String,  // host:port of the Redis server
+    pub instance: u8,  // 0 is the primary
+}
+
+impl ClusterConfig {
+    /// From a `redis://host[:port]` URL; the port defaults to 6379.
+    pub fn from_url(url: &str, instance: u8) -> Result<Self, String> {
+        let rest = url.strip_prefix("redis://").ok_or_else(|| format!("cluster URL must start with redis://: {}", url))?;
+        let host = rest.trim_end_matches('/');
+        if host.is_empty() || host.contains('/') || host.contains('@') {
+            return Err(format!("cluster URL must be redis://host[:port]: {}", url));
+        }
+        let addr = if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+            host.to_string()
+        } else {
+            format!("{}:6379", host)
+        };
+        Ok(ClusterConfig { addr, instance })
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.instance == 0
+    }
+
+    /// Connects to Redis once and reads `PLANETS_KEY`, to tell it is there
+    /// and answering. Instances do not need this to start, since they keep
+    /// retrying; `server --check` does (see `startup`).
+    pub async fn check_reachable(&self) -> Result<(), String> {
+        let attempt = async {
+            let mut redis = client::connect(&self.addr).await?;
+            redis.get(PLANETS_KEY).await?;
+            mini_redis::Result::Ok(())
+        };
+        match tokio::time::timeout(CHECK_TIMEOUT, attempt).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(format!("cannot reach Redis at {}: {}", self.addr, e)),
+            Err(_) => Err(format!("Redis at {} did not answer within {}s", self.addr, CHECK_TIMEOUT.as_secs())),
+        }
+    }
+}
+
+/// What each instance publishes on `PLAYERS_CHANNEL`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlayerUpdate {
+    instance: u8,
+    players: Vec<Player>,
+}
+
+/// The players of the other instances, as last heard.
+#[derive(Debug, Clone, Default)]
+pub struct RemotePlayers {
+    instances: HashMap<u8, (Vec<Player>, Instant)>,
+}
+
+impl RemotePlayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces what `instance` has.
+    pub fn update(&mut self, instance: u8, players: Vec<Player>, now: Instant) {
+        self.instances.insert(instance, (players, now));
+    }
+
+    /// Every player of the instances heard from within `REMOTE_TIMEOUT`,
+    /// flagged remote, in id order; the others are forgotten.
+    pub fn players(&mut self, now: Instant) -> Vec<Player> {
+        self.instances.retain(|_, (_, heard)| now.saturating_duration_since(*heard) <= REMOTE_TIMEOUT);
+        let mut players: Vec<Player> = self.instances.values()
+            .flat_map(|(players, _)| players.iter().cloned())
+            .map(|player| Player { remote: true, ..player })
+            .collect();
+        players.sort_by_key(|p| p.id);
+        players
+    }
+
+    /// The instance the player with this id is connected to, if it is a
+    /// remote player.
+    pub fn instance_of(&self, player_id: u32) -> Option<u8> {
+        self.instances.iter().find(|(_, (players, _))| players.iter().any(|p| p.id == player_id)).map(|(instance, _)| *instance)
+    }
+}
+
+/// One instance's side of the cluster.
+pub struct Cluster {
+    config: ClusterConfig,
+    remote: Mutex<RemotePlayers>,
+    players_tx: watch::Sender<Option<Bytes>>,
+    planets_tx: watch::Sender<Option<Bytes>>,
+}
+
+impl Cluster {
+    pub fn new(config: ClusterConfig) -> Self {
+        Cluster {
+            config,
+            remote: Mutex::new(RemotePlayers::new()),
+            players_tx: watch::Sender::new(None),
+            planets_tx: watch::Sender::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &ClusterConfig {
+        &self.config
+    }
+
+    /// Queues this instance's players for the other instances.
+    pub fn publish_players(&self, players: Vec<Player>) {
+        let update = PlayerUpdate { instance: self.config.instance, players };
+        if let Ok(json) = serde_json::to_vec(&update) {
+            self.players_tx.send_replace(Some(json.into()));
+        }
+    }
+
+    /// Queues the planets for the other instances; only the primary's count.
+    pub fn publish_planets(&self, planets: &[Planet]) {
+        if !self.config.is_primary() {
+            return;
+        }
+        if let Ok(json) = serde_json::to_vec(planets) {
+            self.planets_tx.send_replace(Some(json.into()));
+        }
+    }
+
+    pub fn remote_players(&self, now: Instant) -> Vec<Player> {
+        self.remote.lock().unwrap().players(now)
+    }
+
+    pub fn instance_of(&self, player_id: u32) -> Option<u8> {
+        self.remote.lock().unwrap().instance_of(player_id)
+    }
+
+    /// Starts publishing and listening for `server`.
+    pub fn spawn(self: &Arc<Self>, server: GameServer) -> Vec<tokio::task::JoinHandle<()>> {
+        let publisher = self.clone();
+        let subscriber = self.clone();
+        vec![
+            tokio::spawn(async move {
+                let (mut players, mut planets) = (publisher.players_tx.subscribe(), publisher.planets_tx.subscribe());
+                loop {
+                    if let Err(e) = publisher.publish(&mut players, &mut planets).await {
+                        println!("🔌 Cluster: lost Redis at {} while publishing: {}", publisher.config.addr, e);
+                    }
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                }
+            }),
+            tokio::spawn(async move {
+                loop {
+                    if let Err(e) = subscriber.listen(&server).await {
+                        println!("🔌 Cluster: lost Redis at {} while listening: {}", subscriber.config.addr, e);
+                    }
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                }
+            }),
+        ]
+    }
+
+    async fn publish(
+        &self,
+        players: &mut watch::Receiver<Option<Bytes>>,
+        planets: &mut watch::Receiver<Option<Bytes>>,
+    ) -> mini_redis::Result<()> {
+        let mut redis = client::connect(&self.config.addr).await?;
+        // Whatever was last queued goes out again on a new connection
+        planets.mark_changed();
+        loop {
+            tokio::select! {
+                changed = players.changed() => {
+                    changed?;
+                    let update = players.borrow_and_update().clone();
+                    if let Some(update) = update {
+                        redis.publish(PLAYERS_CHANNEL, update).await?;
+                    }
+                }
+                changed = planets.changed() => {
+                    changed?;
+                    let update = planets.borrow_and_update().clone();
+                    if let Some(update) = update {
+                        redis.set(PLANETS_KEY, update).await?;
+                        redis.publish(PLANETS_CHANNEL, Bytes::from(self.config.instance.to_string())).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn listen(&self, server: &GameServer) -> mini_redis::Result<()> {
+        // A subscribed connection cannot GET, so the planets come over another
+        let mut reader = client::connect(&self.config.addr).await?;
+        let channels = vec![PLAYERS_CHANNEL.to_string(), PLANETS_CHANNEL.to_string()];
+        let mut subscriber = client::connect(&self.config.addr).await?.subscribe(channels).await?;
+        println!("🔗 Cluster: instance {} connected to Redis at {}", self.config.instance, self.config.addr);
+        if !self.config.is_primary() {
+            self.load_planets(&mut reader, server).await?;
+        }
+        while let Some(message) = subscriber.next_message().await? {
+            match message.channel.as_str() {
+                PLAYERS_CHANNEL => match serde_json::from_slice::<PlayerUpdate>(&message.content) {
+                    Ok(update) if update.instance != self.config.instance => {
+                        self.remote.lock().unwrap().update(update.instance, update.players, Instant::now());
+                    }
+                    Ok(_) => {}
+                    Err(e) => println!("🔌 Cluster: ignoring a malformed player update: {}", e),
+                },
+                PLANETS_CHANNEL if !self.config.is_primary() => self.load_planets(&mut reader, server).await?,
+                _ => {}
+            }
+        }
+        Err("subscription closed".into())
+    }
+
+    async fn load_planets(&self, reader: &mut client::Client, server: &GameServer) -> mini_redis::Result<()> {
+        let Some(json) = reader.get(PLANETS_KEY).await? else {
+            println!("🔌 Cluster: no planets from the primary yet");
+            return Ok(());
+        };
+        match serde_json::from_slice::<Vec<Planet>>(&json) {
+            Ok(planets) => server.replace_planets(planets),
+            Err(e) => println!("🔌 Cluster: ignoring malformed planets: {}", e),
+        }
+        Ok(())
+    }
+}
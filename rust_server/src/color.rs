@@ -0,0 +1,85 @@
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::protocol::{Color, Planet};
+
+/*
+Color math for labelling planets.
+
+A planet's three colors are random or drawn from a palette (see `palette`),
+so a client labelling one has neither a single color to draw it as from
+afar nor a way to know whether white or black text reads on it. The server
+works both out once, as `Planet::primary_display_color` and
+`Planet::label_contrast`, so no client has to repeat the math:
+
+- The primary display color is the average of the three colors in linear
+  light, converted back to sRGB. Averaging the sRGB bytes directly comes
+  out too dark: red, black and black average to `#550000` that way, but
+  look like `#9c0000`.
+- The label contrast is the text color with the higher WCAG contrast ratio
+  against the primary display color: `Light` (white) text on colors with a
+  relative luminance below about 0.18, `Dark` (black) text above.
+
+Both follow the colors: planets are worked out when a world is generated,
+loaded or imported, when one is added, and when an admin edits a color
+(see `Planet::refresh_display`). A diff carries only the colors, and
+whoever applies it works the rest out again. Binary frames carry them for
+clients with the LABELS capability (see `encoding_for`).
+*/
+
+/// Which text reads on a color: light (white) or dark (black).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum LabelContrast {
+    #[default]
+    Light,
+    Dark,
+}
+
+/// An sRGB channel as linear light, 0 to 1.
+pub fn to_linear(channel: u8) -> f64 {
+    let c = channel as f64 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Linear light back to an sRGB channel, rounded.
+pub fn from_linear(light: f64) -> u8 {
+    let light = light.clamp(0.0, 1.0);
+    let c = if light <= 0.0031308 { light * 12.92 } else { 1.055 * light.powf(1.0 / 2.4) - 0.055 };
+    (c * 255.0).round() as u8
+}
+
+/// WCAG relative luminance: 0 for black, 1 for white.
+pub fn relative_luminance(color: &Color) -> f64 {
+    0.2126 * to_linear(color.r) + 0.7152 * to_linear(color.g) + 0.0722 * to_linear(color.b)
+}
+
+/// The WCAG contrast ratio between two colors, 1 to 21.
+pub fn contrast_ratio(a: &Color, b: &Color) -> f64 {
+    let (a, b) = (relative_luminance(a), relative_luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// The colors averaged in linear light.
+pub fn primary_display_color(colors: &[Color; 3]) -> Color {
+    let average = |channel: fn(&Color) -> u8| from_linear(colors.iter().map(|c| to_linear(channel(c))).sum::<f64>() / 3.0);
+    Color { r: average(|c| c.r), g: average(|c| c.g), b: average(|c| c.b) }
+}
+
+/// The text that reads better on `background`.
+pub fn label_contrast(background: &Color) -> LabelContrast {
+    let white = Color { r: 255, g: 255, b: 255 };
+    let black = Color { r: 0, g: 0, b: 0 };
+    if contrast_ratio(background, &white) >= contrast_ratio(background, &black) {
+        LabelContrast::Light
+    } else {
+        LabelContrast::Dark
+    }
+}
+
+impl Planet {
+    /// Works out `primary_display_color` and `label_contrast` from the
+    /// colors again.
+    pub fn refresh_display(&mut self) {
+        self.primary_display_color = primary_display_color(&self.colors);
+        self.label_contrast = label_contrast(&self.primary_display_color);
+    }
+}
@@ -0,0 +1,691 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::accept_guard::AcceptLimits;
+use crate::admin::PlanetLimits;
+use crate::ambient::{AmbientConfig, AmbientWeights, DEFAULT_AMBIENT_DURATION_TICKS};
+use crate::announce::{render, Placeholders, ScheduledAnnouncement};
+use crate::economy::RegenerationRates;
+use crate::hibernate::HibernationConfig;
+use crate::idle::IdleConfig;
+use crate::module_effects::ModuleEffects;
+use crate::palette::PlanetPalettes;
+use crate::pause::PausedUpdates;
+use crate::quantize::Quantization;
+use crate::prune::PrunePolicy;
+use crate::rate_limit::RateLimitConfig;
+use crate::readiness::Readiness;
+use crate::server::{DEFAULT_BROADCAST_INTERVAL, DEFAULT_MAX_SPEED};
+use crate::spawn::DEFAULT_SPAWN_DISPERSION;
+use crate::trade::DEFAULT_OFFER_TIMEOUT;
+use crate::trail::DEFAULT_TRAIL_LENGTH;
+use crate::webhooks::{WebhookConfig, WebhookSettings, DEFAULT_BACKOFF, DEFAULT_QUEUE_LIMIT, DEFAULT_RETRIES};
+use crate::world::WorldConfig;
+use crate::worldgen::WorldLayout;
+
+/*
+Server configuration: built-in defaults, then `galavox.toml` (or the file
+given with `--config`), then command-line flags, each overriding the last.
+Every key is optional:
+
+    bind = "127.0.0.1:8080"
+    world_seed = 42                  # random when omitted
+    world_layout = "ring"            # or "spiral" or "sphere" (see `worldgen`)
+    world_radius = 10000.0
+    moon_chance = 0.5                # of each planet's first moon, then each next
+    belts = 2                        # asteroid belts
+    min_planet_gap = 20.0            # between planets, surface to surface, generated or added (see `worldgen`)
+    day_length_secs = 1200           # one day and night of `world_time` (see `world_clock`)
+    palette = "pastel"               # planet colors, random when omitted (see `palette`)
+    world = "saved-world.json"       # start from a saved world instead
+    import_world = "designed.json"   # or a hand-edited one (see `world_file`)
+    journal = "events.journal"
+    ban_file = "bans.txt"            # banned player names (see `bans`)
+    mute_file = "mutes.json"         # muted and shadow-muted player names (see `mutes`)
+    stats_file = "stats.json"        # lifetime stats kept across restarts (see `lifetime`)
+    chat_file = "chat.json"          # global chat history kept across restarts (see `chat_history`)
+    admin_token = "secret"           # shared, with an owner's rights
+    roles_file = "roles.json"        # moderators and owners, and their admin tokens (see `roles`)
+    owners = ["Ada"]                 # made owners at every start
+    friends_file = "friends.json"    # friend lists kept across restarts (see `friends`)
+    wallet_file = "wallets.json"     # cargo and credits kept across restarts (see `wallets`)
+    trusted_proxies = ["10.0.0.0/8"]
+    max_open_connections = 4096      # sockets at once, before any handshake; the open-file limit less 64 when omitted (see `accept_guard`)
+    max_open_per_ip = 16             # sockets at once from one address, unlimited when omitted
+    max_accepts_per_ip_per_min = 60  # connections opened a minute from one address, unlimited when omitted
+    tls_cert = "cert.pem"            # needs tls_key, and the `tls` feature
+    tls_key = "key.pem"
+    cluster = "redis://127.0.0.1:6379"  # share the world with other instances; needs the `cluster` feature (see `cluster`)
+    cluster_instance = 0             # 0 to 255, unique in the cluster; 0 is the primary
+    follow = "ws://10.0.0.1:8080"    # be a warm standby for this server; needs its admin_token (see `replication`)
+    advertise = "ws://10.0.0.2:8080" # where clients are sent once promoted; ws://<bind> when omitted
+    casual = false                   # teleport to any planet
+    max_speed = 500.0
+    spawn_dispersion = 10.0          # how close two players may spawn; 0 spawns them on fixed slots (see `spawn`)
+    motd = "Welcome, {name}!"        # see `announce` for placeholders
+    hibernate_after_secs = 60        # with nobody connected; 0 disables
+    catch_up_ticks = 0               # ticks skipped ahead on waking (see `hibernate`)
+    paused_updates = "queue"         # or "reject": position updates while an admin has paused the simulation (see `pause`)
+    fake_players = 0                 # server-owned players for development (see `fakes`)
+    trails = false                   # keep recent positions for `QueryTrail` and spectators (see `trail`)
+    trail_length = 32                # positions kept per player
+    max_planets = 200                # refuses `planet add` beyond it, unlimited when omitted
+    max_planets_per_owner = 5        # unlimited when omitted
+    prune_idle_planets_after_secs = 0  # admin-added planets unvisited and unowned; 0 disables (see `prune`)
+    webhook_queue = 256              # events waiting per webhook before more are dropped (see `webhooks`)
+    webhook_retries = 3              # of a POST that failed
+    webhook_backoff_ms = 500         # before the first retry, doubling after
+    ambient_every_ticks = 0          # between ambient events around planets; 0 disables (see `ambient`)
+    ambient_duration_ticks = 300     # each one lasts
+    ambient_seed = 7                 # the same schedule every run; random when omitted
+    trade_offer_timeout_secs = 60    # before an unanswered trade offer is cancelled (see `trade`)
+    ready_file = "ready.json"        # a JSON line written here once accepting (see `readiness`)
+    ready_fd = 3                     # or to this open file descriptor
+    quantize_resolution = 0.01       # units per step of a moved player's offset in diffs (see `quantize`)
+    quantize_refresh = 32            # offsets in a row before a position is sent whole again
+
+    # Safe to change while running
+    broadcast_interval_ms = 100
+    max_players = 64                 # unlimited when omitted
+    max_spectators = 8               # unlimited when omitted
+    max_connections_per_ip = 4       # unlimited when omitted
+    bandwidth_budget = 65536         # bytes/s per connection, unlimited when omitted (see `bandwidth`)
+    position_updates_per_sec = 60.0
+    chat_messages_per_sec = 5.0
+    abuse_window_secs = 10.0
+    idle_after_secs = 300            # 0 disables
+    disconnect_after_secs = 1800     # 0 disables
+
+    # Tables come after all other keys
+    [module_palettes]                # per module type, over `palette`
+    1 = "rock"
+
+    [palettes]                       # define or replace palettes
+    sunset = ["#ff7e5f", "#feb47b", "#c0392b"]
+
+    [regeneration]                   # resource regrowth multipliers per module type (see `economy`)
+    1 = 2.0
+
+    [modules]                        # what module types do (see `module_effects`)
+    defense_radius = 300.0
+    sell_rate = 1.0                  # credits a unit
+    trade_range = 200.0
+    trade_bonus = 1.5
+    research_xp = 2.0
+
+    [ambient_weights]                # how often each kind of ambient event comes, relative to the others
+    solar_flare = 1
+    meteor_shower = 1
+    aurora = 2
+
+    [webhook_templates]              # message text per event (see `webhooks`)
+    level_up = "{name} is now level {level}"
+
+    [[announcements]]                # repeated to everyone
+    schedule = "every 30m"
+    text = "{player_count} pilots online"
+
+    [[webhooks]]                     # events POSTed as JSON
+    url = "https://hooks.example.com/galavox"
+    events = ["player_joined", "ban_issued"]  # every kind when omitted
+
+Each key has a flag of the same name with dashes, e.g. `--max-players 8`;
+`--trusted-proxy` and `--owner` may be repeated, `--casual` and `--trails`
+take no value and the tables can only be set in the file. `server --check`
+validates the whole configuration, and the files it names, without
+starting (see `startup`).
+
+On SIGHUP or the admin `reload` command the file is read again and the
+flags re-applied. Only the settings in `LIVE_KEYS` take effect; changes to
+any other key are logged and ignored until the next restart.
+*/
+
+pub const DEFAULT_CONFIG_PATH: &str = "galavox.toml";
+
+/// Keys that a reload applies to the running server.
+pub const LIVE_KEYS: &[&str] = &[
+    "broadcast_interval_ms",
+    "max_players",
+    "max_spectators",
+    "max_connections_per_ip",
+    "bandwidth_budget",
+    "position_updates_per_sec",
+    "chat_messages_per_sec",
+    "abuse_window_secs",
+    "idle_after_secs",
+    "disconnect_after_secs",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub world_seed: Option<u64>,
+    pub world_layout: WorldLayout,
+    pub world_radius: f32,
+    pub moon_chance: f64,
+    pub belts: u32,
+    pub min_planet_gap: f32,
+    pub day_length_secs: u64,
+    pub palette: Option<String>,
+    pub module_palettes: HashMap<String, String>,
+    pub palettes: HashMap<String, Vec<String>>,
+    pub regeneration: HashMap<String, f64>,
+    pub modules: ModuleEffects,
+    pub ambient_weights: AmbientWeights,
+    pub world: Option<PathBuf>,
+    pub import_world: Option<PathBuf>,
+    pub journal: Option<PathBuf>,
+    pub ban_file: Option<PathBuf>,
+    pub mute_file: Option<PathBuf>,
+    pub stats_file: Option<PathBuf>,
+    pub chat_file: Option<PathBuf>,
+    pub admin_token: Option<String>,
+    pub roles_file: Option<PathBuf>,
+    pub owners: Vec<String>,
+    pub friends_file: Option<PathBuf>,
+    pub wallet_file: Option<PathBuf>,
+    pub trusted_proxies: Vec<String>,
+    pub max_open_connections: Option<usize>,
+    pub max_open_per_ip: Option<usize>,
+    pub max_accepts_per_ip_per_min: Option<u32>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub cluster: Option<String>,
+    pub cluster_instance: u8,
+    pub follow: Option<String>,
+    pub advertise: Option<String>,
+    pub casual: bool,
+    pub max_speed: f32,
+    pub spawn_dispersion: f32,
+    pub motd: Option<String>,
+    pub hibernate_after_secs: u64,
+    pub catch_up_ticks: u32,
+    pub paused_updates: PausedUpdates,
+    pub fake_players: usize,
+    pub trails: bool,
+    pub trail_length: usize,
+    pub max_planets: Option<usize>,
+    pub max_planets_per_owner: Option<usize>,
+    pub prune_idle_planets_after_secs: u64,
+    pub webhook_queue: usize,
+    pub webhook_retries: u32,
+    pub webhook_backoff_ms: u64,
+    pub ambient_every_ticks: u64,
+    pub ambient_duration_ticks: u64,
+    pub ambient_seed: Option<u64>,
+    pub trade_offer_timeout_secs: u64,
+    pub ready_file: Option<PathBuf>,
+    pub ready_fd: Option<u32>,
+    pub quantize_resolution: f32,
+    pub quantize_refresh: u32,
+    pub webhook_templates: HashMap<String, String>,
+    pub announcements: Vec<ScheduledAnnouncement>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub broadcast_interval_ms: u64,
+    pub max_players: Option<usize>,
+    pub max_spectators: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    pub bandwidth_budget: Option<u64>,
+    pub position_updates_per_sec: f64,
+    pub chat_messages_per_sec: f64,
+    pub abuse_window_secs: f64,
+    pub idle_after_secs: u64,
+    pub disconnect_after_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let rate_limits = RateLimitConfig::default();
+        let idle = IdleConfig::default();
+        let hibernation = HibernationConfig::default();
+        ServerConfig {
+            bind: "127.0.0.1:8080".to_string(),
+            world_seed: None,
+            world_layout: WorldLayout::default(),
+            world_radius: WorldConfig::default().radius,
+            moon_chance: WorldConfig::default().moon_chance,
+            belts: WorldConfig::default().belts,
+            min_planet_gap: WorldConfig::default().min_planet_gap,
+            day_length_secs: WorldConfig::default().day_length.as_secs(),
+            palette: None,
+            module_palettes: HashMap::new(),
+            palettes: HashMap::new(),
+            regeneration: HashMap::new(),
+            modules: ModuleEffects::default(),
+            ambient_weights: AmbientWeights::default(),
+            world: None,
+            import_world: None,
+            journal: None,
+            ban_file: None,
+            mute_file: None,
+            stats_file: None,
+            chat_file: None,
+            admin_token: None,
+            roles_file: None,
+            owners: Vec::new(),
+            friends_file: None,
+            wallet_file: None,
+            trusted_proxies: Vec::new(),
+            max_open_connections: None,
+            max_open_per_ip: None,
+            max_accepts_per_ip_per_min: None,
+            tls_cert: None,
+            tls_key: None,
+            cluster: None,
+            cluster_instance: 0,
+            follow: None,
+            advertise: None,
+            casual: false,
+            max_speed: DEFAULT_MAX_SPEED,
+            spawn_dispersion: DEFAULT_SPAWN_DISPERSION,
+            motd: None,
+            hibernate_after_secs: hibernation.after.as_secs(),
+            catch_up_ticks: hibernation.catch_up_ticks,
+            paused_updates: PausedUpdates::default(),
+            fake_players: 0,
+            trails: false,
+            trail_length: DEFAULT_TRAIL_LENGTH,
+            max_planets: None,
+            max_planets_per_owner: None,
+            prune_idle_planets_after_secs: 0,
+            webhook_queue: DEFAULT_QUEUE_LIMIT,
+            webhook_retries: DEFAULT_RETRIES,
+            webhook_backoff_ms: DEFAULT_BACKOFF.as_millis() as u64,
+            ambient_every_ticks: 0,
+            ambient_duration_ticks: DEFAULT_AMBIENT_DURATION_TICKS,
+            ambient_seed: None,
+            trade_offer_timeout_secs: DEFAULT_OFFER_TIMEOUT.as_secs(),
+            ready_file: None,
+            ready_fd: None,
+            quantize_resolution: Quantization::default().resolution,
+            quantize_refresh: Quantization::default().refresh,
+            webhook_templates: HashMap::new(),
+            announcements: Vec::new(),
+            webhooks: Vec::new(),
+            broadcast_interval_ms: DEFAULT_BROADCAST_INTERVAL.as_millis() as u64,
+            max_players: None,
+            max_spectators: None,
+            max_connections_per_ip: None,
+            bandwidth_budget: None,
+            position_updates_per_sec: rate_limits.position_updates_per_sec,
+            chat_messages_per_sec: rate_limits.chat_messages_per_sec,
+            abuse_window_secs: rate_limits.abuse_window.as_secs_f64(),
+            idle_after_secs: idle.idle_after.as_secs(),
+            disconnect_after_secs: idle.disconnect_after.as_secs(),
+        }
+    }
+}
+
+/// The settings a running server can pick up without a restart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LiveSettings {
+    pub broadcast_interval: Duration,
+    pub max_players: Option<usize>,
+    pub max_spectators: Option<usize>,  // counted separately from players
+    pub max_connections_per_ip: Option<usize>,
+    pub bandwidth_budget: Option<u64>,  // bytes per second per connection
+    pub rate_limits: RateLimitConfig,
+    pub idle: IdleConfig,
+}
+
+impl Default for LiveSettings {
+    fn default() -> Self {
+        ServerConfig::default().live()
+    }
+}
+
+fn parse_flag<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+impl ServerConfig {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Self::from_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The config file named by `--config`, else `galavox.toml` if it exists.
+    pub fn config_path(args: &[String]) -> Result<Option<PathBuf>, String> {
+        match args.iter().position(|a| a == "--config") {
+            Some(i) => Ok(Some(args.get(i + 1).ok_or("--config needs a path")?.into())),
+            None => Ok(Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|p| p.exists())),
+        }
+    }
+
+    /// Defaults, then the config file, then the flags in `args`.
+    pub fn resolve(args: &[String]) -> Result<Self, String> {
+        let mut config = match Self::config_path(args)? {
+            Some(path) => Self::load(&path)?,
+            None => ServerConfig::default(),
+        };
+        config.apply_args(args)?;
+        Ok(config)
+    }
+
+    /// Overrides settings with command-line flags (`--config` is skipped),
+    /// refusing the result if `setting_problems` finds any.
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        self.apply_flags(args)?;
+        match self.setting_problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(()),
+        }
+    }
+
+    /// `apply_args` without the checks.
+    pub(crate) fn apply_flags(&mut self, args: &[String]) -> Result<(), String> {
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let flag = arg.as_str();
+            match flag {
+                "--config" => { iter.next(); }
+                "--casual" => self.casual = true,
+                "--trails" => self.trails = true,
+                "--trusted-proxy" => self.trusted_proxies.push(parse_flag(flag, iter.next())?),
+                "--owner" => self.owners.push(parse_flag(flag, iter.next())?),
+                "--max-open-connections" => self.max_open_connections = Some(parse_flag(flag, iter.next())?),
+                "--max-open-per-ip" => self.max_open_per_ip = Some(parse_flag(flag, iter.next())?),
+                "--max-accepts-per-ip-per-min" => self.max_accepts_per_ip_per_min = Some(parse_flag(flag, iter.next())?),
+                "--bind" => self.bind = parse_flag(flag, iter.next())?,
+                "--world-seed" => self.world_seed = Some(parse_flag(flag, iter.next())?),
+                "--world-layout" => self.world_layout = parse_flag(flag, iter.next())?,
+                "--world-radius" => self.world_radius = parse_flag(flag, iter.next())?,
+                "--moon-chance" => self.moon_chance = parse_flag(flag, iter.next())?,
+                "--belts" => self.belts = parse_flag(flag, iter.next())?,
+                "--min-planet-gap" => self.min_planet_gap = parse_flag(flag, iter.next())?,
+                "--day-length-secs" => self.day_length_secs = parse_flag(flag, iter.next())?,
+                "--palette" => self.palette = Some(parse_flag(flag, iter.next())?),
+                "--world" => self.world = Some(parse_flag(flag, iter.next())?),
+                "--import-world" => self.import_world = Some(parse_flag(flag, iter.next())?),
+                "--journal" => self.journal = Some(parse_flag(flag, iter.next())?),
+                "--ban-file" => self.ban_file = Some(parse_flag(flag, iter.next())?),
+                "--mute-file" => self.mute_file = Some(parse_flag(flag, iter.next())?),
+                "--stats-file" => self.stats_file = Some(parse_flag(flag, iter.next())?),
+                "--chat-file" => self.chat_file = Some(parse_flag(flag, iter.next())?),
+                "--admin-token" => self.admin_token = Some(parse_flag(flag, iter.next())?),
+                "--roles-file" => self.roles_file = Some(parse_flag(flag, iter.next())?),
+                "--friends-file" => self.friends_file = Some(parse_flag(flag, iter.next())?),
+                "--wallet-file" => self.wallet_file = Some(parse_flag(flag, iter.next())?),
+                "--tls-cert" => self.tls_cert = Some(parse_flag(flag, iter.next())?),
+                "--tls-key" => self.tls_key = Some(parse_flag(flag, iter.next())?),
+                "--cluster" => self.cluster = Some(parse_flag(flag, iter.next())?),
+                "--cluster-instance" => self.cluster_instance = parse_flag(flag, iter.next())?,
+                "--follow" => self.follow = Some(parse_flag(flag, iter.next())?),
+                "--advertise" => self.advertise = Some(parse_flag(flag, iter.next())?),
+                "--max-speed" => self.max_speed = parse_flag(flag, iter.next())?,
+                "--spawn-dispersion" => self.spawn_dispersion = parse_flag(flag, iter.next())?,
+                "--motd" => self.motd = Some(parse_flag(flag, iter.next())?),
+                "--hibernate-after-secs" => self.hibernate_after_secs = parse_flag(flag, iter.next())?,
+                "--catch-up-ticks" => self.catch_up_ticks = parse_flag(flag, iter.next())?,
+                "--paused-updates" => self.paused_updates = parse_flag(flag, iter.next())?,
+                "--fake-players" => self.fake_players = parse_flag(flag, iter.next())?,
+                "--trail-length" => self.trail_length = parse_flag(flag, iter.next())?,
+                "--max-planets" => self.max_planets = Some(parse_flag(flag, iter.next())?),
+                "--max-planets-per-owner" => self.max_planets_per_owner = Some(parse_flag(flag, iter.next())?),
+                "--prune-idle-planets-after-secs" => self.prune_idle_planets_after_secs = parse_flag(flag, iter.next())?,
+                "--webhook-queue" => self.webhook_queue = parse_flag(flag, iter.next())?,
+                "--webhook-retries" => self.webhook_retries = parse_flag(flag, iter.next())?,
+                "--webhook-backoff-ms" => self.webhook_backoff_ms = parse_flag(flag, iter.next())?,
+                "--ambient-every-ticks" => self.ambient_every_ticks = parse_flag(flag, iter.next())?,
+                "--ambient-duration-ticks" => self.ambient_duration_ticks = parse_flag(flag, iter.next())?,
+                "--ambient-seed" => self.ambient_seed = Some(parse_flag(flag, iter.next())?),
+                "--trade-offer-timeout-secs" => self.trade_offer_timeout_secs = parse_flag(flag, iter.next())?,
+                "--ready-file" => self.ready_file = Some(parse_flag(flag, iter.next())?),
+                "--ready-fd" => self.ready_fd = Some(parse_flag(flag, iter.next())?),
+                "--quantize-resolution" => self.quantize_resolution = parse_flag(flag, iter.next())?,
+                "--quantize-refresh" => self.quantize_refresh = parse_flag(flag, iter.next())?,
+                "--broadcast-interval-ms" => self.broadcast_interval_ms = parse_flag(flag, iter.next())?,
+                "--max-players" => self.max_players = Some(parse_flag(flag, iter.next())?),
+                "--max-spectators" => self.max_spectators = Some(parse_flag(flag, iter.next())?),
+                "--max-connections-per-ip" => self.max_connections_per_ip = Some(parse_flag(flag, iter.next())?),
+                "--bandwidth-budget" => self.bandwidth_budget = Some(parse_flag(flag, iter.next())?),
+                "--position-updates-per-sec" => self.position_updates_per_sec = parse_flag(flag, iter.next())?,
+                "--chat-messages-per-sec" => self.chat_messages_per_sec = parse_flag(flag, iter.next())?,
+                "--abuse-window-secs" => self.abuse_window_secs = parse_flag(flag, iter.next())?,
+                "--idle-after-secs" => self.idle_after_secs = parse_flag(flag, iter.next())?,
+                "--disconnect-after-secs" => self.disconnect_after_secs = parse_flag(flag, iter.next())?,
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything wrong with the settings themselves, palettes included,
+    /// without reading any of the files they name (see `startup`).
+    pub fn setting_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            problems.push("tls_cert and tls_key must be given together".to_string());
+        }
+        if self.world.is_some() && self.import_world.is_some() {
+            problems.push("world and import_world do not mix".to_string());
+        }
+        if let Some(primary) = &self.follow {
+            if self.admin_token.is_none() {
+                problems.push("follow needs the primary's admin_token".to_string());
+            }
+            if self.cluster.is_some() {
+                problems.push("follow and cluster do not mix".to_string());
+            }
+            if !primary.starts_with("ws://") && !primary.starts_with("wss://") {
+                problems.push("follow must be a ws:// or wss:// URL".to_string());
+            }
+        }
+        if let Some(advertise) = &self.advertise
+            && !advertise.starts_with("ws://") && !advertise.starts_with("wss://")
+        {
+            problems.push("advertise must be a ws:// or wss:// URL".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.moon_chance) {
+            problems.push("moon_chance must be between 0 and 1".to_string());
+        }
+        if !(self.min_planet_gap.is_finite() && self.min_planet_gap >= 0.0) {
+            problems.push("min_planet_gap must not be negative".to_string());
+        }
+        if !(self.spawn_dispersion.is_finite() && self.spawn_dispersion >= 0.0) {
+            problems.push("spawn_dispersion must not be negative".to_string());
+        }
+        if self.day_length_secs == 0 {
+            problems.push("day_length_secs must be positive".to_string());
+        }
+        if self.trade_offer_timeout_secs == 0 {
+            problems.push("trade_offer_timeout_secs must be positive".to_string());
+        }
+        if !(self.quantize_resolution.is_finite() && self.quantize_resolution > 0.0) {
+            problems.push("quantize_resolution must be positive".to_string());
+        }
+        if self.trails && self.trail_length == 0 {
+            problems.push("trail_length must be positive".to_string());
+        }
+        if self.max_open_connections == Some(0) || self.max_open_per_ip == Some(0) || self.max_accepts_per_ip_per_min == Some(0) {
+            problems.push("max_open_connections, max_open_per_ip and max_accepts_per_ip_per_min must be positive".to_string());
+        }
+        if self.broadcast_interval_ms == 0 {
+            problems.push("broadcast_interval_ms must be positive".to_string());
+        }
+        if let Some(motd) = &self.motd
+            && let Err(e) = render(motd, &Placeholders { player_count: 0, name: Some("") })
+        {
+            problems.push(format!("motd: {}", e));
+        }
+        problems.extend(self.announcements.iter().filter_map(|announcement| announcement.parse().err()));
+        problems.extend(self.webhook_settings().problems());
+        problems.extend(self.planet_palettes().err());
+        problems.extend(RegenerationRates::resolve(&self.regeneration).err());
+        problems.extend(self.modules.validate().err());
+        problems.extend(self.ambient().and_then(|ambient| ambient.validate().err()));
+        problems
+    }
+
+    pub fn live(&self) -> LiveSettings {
+        LiveSettings {
+            broadcast_interval: Duration::from_millis(self.broadcast_interval_ms),
+            max_players: self.max_players,
+            max_spectators: self.max_spectators,
+            max_connections_per_ip: self.max_connections_per_ip,
+            bandwidth_budget: self.bandwidth_budget,
+            rate_limits: RateLimitConfig {
+                position_updates_per_sec: self.position_updates_per_sec,
+                chat_messages_per_sec: self.chat_messages_per_sec,
+                abuse_window: Duration::from_secs_f64(self.abuse_window_secs),
+            },
+            idle: IdleConfig {
+                idle_after: Duration::from_secs(self.idle_after_secs),
+                disconnect_after: Duration::from_secs(self.disconnect_after_secs),
+            },
+        }
+    }
+
+    pub fn planet_limits(&self) -> PlanetLimits {
+        PlanetLimits {
+            min_gap: self.min_planet_gap,
+            max_planets: self.max_planets,
+            max_planets_per_owner: self.max_planets_per_owner,
+            ..PlanetLimits::default()
+        }
+    }
+
+    /// How long a trade offer waits for an answer (see `trade`).
+    pub fn trade_offer_timeout(&self) -> Duration {
+        Duration::from_secs(self.trade_offer_timeout_secs)
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        Quantization { resolution: self.quantize_resolution, refresh: self.quantize_refresh }
+    }
+
+    /// Where to say the server is ready (see `readiness`).
+    pub fn readiness(&self) -> Readiness {
+        Readiness { fd: self.ready_fd, file: self.ready_file.clone() }
+    }
+
+    /// The pruning policy, if pruning is on.
+    pub fn pruning(&self) -> Option<PrunePolicy> {
+        Some(PrunePolicy { after: Duration::from_secs(self.prune_idle_planets_after_secs) })
+            .filter(|_| self.prune_idle_planets_after_secs > 0)
+    }
+
+    /// The ambient event schedule, if ambient events are on.
+    pub fn ambient(&self) -> Option<AmbientConfig> {
+        Some(AmbientConfig {
+            every_ticks: self.ambient_every_ticks,
+            duration_ticks: self.ambient_duration_ticks,
+            weights: self.ambient_weights,
+            seed: self.ambient_seed,
+        }).filter(|_| self.ambient_every_ticks > 0)
+    }
+
+    /// What the accept path turns away; see `accept_guard`.
+    pub fn accept_limits(&self) -> AcceptLimits {
+        AcceptLimits {
+            max_open: self.max_open_connections,
+            open_per_ip: self.max_open_per_ip,
+            attempts_per_minute: self.max_accepts_per_ip_per_min,
+        }
+    }
+
+    pub fn hibernation(&self) -> HibernationConfig {
+        HibernationConfig { after: Duration::from_secs(self.hibernate_after_secs), catch_up_ticks: self.catch_up_ticks }
+    }
+
+    /// The `announcements` entries as intervals and templates; they were
+    /// checked when the config was loaded.
+    pub fn scheduled_announcements(&self) -> Vec<(Duration, String)> {
+        self.announcements.iter().filter_map(|a| a.parse().ok()).collect()
+    }
+
+    /// What the `webhooks` and the `webhook_*` keys describe.
+    pub fn webhook_settings(&self) -> WebhookSettings {
+        WebhookSettings {
+            hooks: self.webhooks.clone(),
+            templates: self.webhook_templates.clone(),
+            queue_limit: self.webhook_queue,
+            retries: self.webhook_retries,
+            backoff: Duration::from_millis(self.webhook_backoff_ms),
+        }
+    }
+
+    fn planet_palettes(&self) -> Result<PlanetPalettes, String> {
+        PlanetPalettes::resolve(self.palette.as_deref(), &self.module_palettes, &self.palettes)
+    }
+
+    pub fn world_config(&self) -> WorldConfig {
+        WorldConfig {
+            radius: self.world_radius,
+            seed: self.world_seed,
+            moon_chance: self.moon_chance,
+            belts: self.belts,
+            min_planet_gap: self.min_planet_gap,
+            day_length: Duration::from_secs(self.day_length_secs),
+            // These two were checked when the config was loaded
+            palettes: self.planet_palettes().unwrap_or_default(),
+            regeneration: RegenerationRates::resolve(&self.regeneration).unwrap_or_default(),
+            modules: self.modules,
+            ..WorldConfig::default()
+        }
+    }
+
+    /// Keys outside `LIVE_KEYS` whose values differ in `other`.
+    pub fn restart_only_changes(&self, other: &ServerConfig) -> Vec<String> {
+        let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+            (serde_json::to_value(self), serde_json::to_value(other)) else {
+            return Vec::new();
+        };
+        old.iter()
+            .filter(|(key, value)| !LIVE_KEYS.contains(&key.as_str()) && new.get(*key) != Some(*value))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+/// Where the running configuration came from, so it can be read again.
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    pub path: Option<PathBuf>,
+    pub args: Vec<String>,
+    pub current: ServerConfig,
+}
+
+/// What a reload changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reload {
+    pub live: LiveSettings,
+    pub ignored: Vec<String>,  // restart-only keys that changed
+}
+
+impl ConfigSource {
+    pub fn new(path: Option<PathBuf>, args: Vec<String>, current: ServerConfig) -> Self {
+        ConfigSource { path, args, current }
+    }
+
+    /// Re-reads the file and flags, keeping restart-only settings as they were.
+    pub fn reload(&mut self) -> Result<Reload, String> {
+        let mut fresh = match &self.path {
+            Some(path) => ServerConfig::load(path)?,
+            None => ServerConfig::default(),
+        };
+        fresh.apply_args(&self.args)?;
+
+        let ignored = self.current.restart_only_changes(&fresh);
+        let live = fresh.live();
+        let current = &mut self.current;
+        current.broadcast_interval_ms = fresh.broadcast_interval_ms;
+        current.max_players = fresh.max_players;
+        current.max_spectators = fresh.max_spectators;
+        current.max_connections_per_ip = fresh.max_connections_per_ip;
+        current.bandwidth_budget = fresh.bandwidth_budget;
+        current.position_updates_per_sec = fresh.position_updates_per_sec;
+        current.chat_messages_per_sec = fresh.chat_messages_per_sec;
+        current.abuse_window_secs = fresh.abuse_window_secs;
+        current.idle_after_secs = fresh.idle_after_secs;
+        current.disconnect_after_secs = fresh.disconnect_after_secs;
+        Ok(Reload { live, ignored })
+    }
+}
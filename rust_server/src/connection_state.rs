@@ -0,0 +1,169 @@
+use crate::handshake::valid_player_name;
+use crate::locale::localized;
+use crate::protocol::{ClientMessage, ErrorCode, MessageCode, ServerMessage, WireFormat};
+use crate::session::DisconnectReason;
+
+/*
+What a player or spectator connection may do when, as a pure state
+machine: `handle(state, incoming)` returns the next state and what the
+connection should do about it, and the async shell in `server` only reads
+frames, feeds them in and carries out the `Outgoing` actions. Nothing here
+touches the world, a socket or the clock.
+
+    AwaitingJoin --first message--> Active --server closes--> Draining
+         |                            |
+         +------client goes away------+-------------------> Closed
+
+- AwaitingJoin { role }: the handshake is done and the join messages are
+  out, in the format the URL asked for; the first message may still be a
+  `SetFormat`, which switches format and sends them again. Frames that do
+  not decode do not count as the first message.
+- Active { role }: a `SetFormat` now gets a FormatLocked notice. Players
+  may send everything else; a `Join` whose name is malformed is answered
+  with a ProtocolViolation `Error` without closing, since renaming cannot
+  have been meant to end the session. Spectators may only ask questions
+  (`is_spectator_message`); anything else gets one SpectatorReadOnly notice
+  and is ignored from then on. Invalid JSON gets players an InvalidMessage
+  notice and spectators nothing.
+- Draining { reason }: the server queued an `Error` and a close (kicked,
+  rate limited, idle, keepalives unanswered, a frame too large, shutting
+  down); what is queued is still written, nothing more is read.
+- Closed { reason }: the client closed, the connection broke or the writer
+  gave up on a client too slow to read; there is nothing left to send.
+
+Rate limits, pauses and everything else that depends on the world are the
+shell's to apply to the messages dispatched to it.
+*/
+
+/// Who is on the other end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Player,
+    Spectator { warned: bool },  // told once that it cannot play
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    AwaitingJoin { role: Role },
+    Active { role: Role },
+    Draining { reason: DisconnectReason },
+    Closed { reason: DisconnectReason },
+}
+
+/// What happened on the connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incoming {
+    Message(ClientMessage),
+    InvalidJson(String),
+    FrameTooLarge,
+    /// The client sent a close frame
+    ClientClosed,
+    /// The connection broke or ended without a close frame
+    ConnectionLost,
+    /// The writer gave up on a client that stopped reading
+    WriterGone,
+    RateLimited,
+    IdleTimeout,
+    KeepaliveTimeout,
+    Kicked,
+    /// The server is stopping, handing over to `reconnect_to` if a follower
+    /// took over (see `replication`)
+    ShuttingDown { reconnect_to: Option<String> },
+}
+
+/// What the shell should do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outgoing {
+    /// Switch to `format` and send the join messages again in it
+    SwitchFormat(WireFormat),
+    /// A message this connection may send; the shell carries it out
+    Dispatch(ClientMessage),
+    Reply(ServerMessage),
+    /// Send an `Error` with `code` and close
+    Close { code: ErrorCode, reason: &'static str },
+}
+
+/// The messages a spectator may send, besides an opening `SetFormat`.
+pub fn is_spectator_message(message: &ClientMessage) -> bool {
+    matches!(message, ClientMessage::TimeSync { .. } | ClientMessage::ResyncFrom { .. } | ClientMessage::Keepalive { .. }
+        | ClientMessage::KeepaliveAck { .. } | ClientMessage::QueryPlanets { .. } | ClientMessage::QueryPlanetsFiltered { .. }
+        | ClientMessage::QueryTrail { .. } | ClientMessage::SetSubscriptions { .. })
+}
+
+impl ConnectionState {
+    /// A connection whose join messages have just gone out.
+    pub fn new(role: Role) -> Self {
+        ConnectionState::AwaitingJoin { role }
+    }
+
+    /// Whether the connection is over, and the shell should stop reading.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, ConnectionState::Draining { .. } | ConnectionState::Closed { .. })
+    }
+
+    /// Why it ended, once it has.
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        match self {
+            ConnectionState::Draining { reason } | ConnectionState::Closed { reason } => Some(*reason),
+            _ => None,
+        }
+    }
+}
+
+fn close(code: ErrorCode, reason: &'static str, ending: DisconnectReason) -> (ConnectionState, Vec<Outgoing>) {
+    (ConnectionState::Draining { reason: ending }, vec![Outgoing::Close { code, reason }])
+}
+
+/// The next state after `incoming`, and what to do about it.
+pub fn handle(state: ConnectionState, incoming: Incoming) -> (ConnectionState, Vec<Outgoing>) {
+    let (role, opening) = match state {
+        ConnectionState::AwaitingJoin { role } => (role, true),
+        ConnectionState::Active { role } => (role, false),
+        // Over: whatever else arrives is ignored
+        ConnectionState::Draining { .. } | ConnectionState::Closed { .. } => return (state, Vec::new()),
+    };
+    match incoming {
+        Incoming::Message(message) => {
+            let (role, outgoing) = handle_message(role, opening, message);
+            (ConnectionState::Active { role }, outgoing)
+        }
+        Incoming::InvalidJson(reason) => {
+            let outgoing = match role {
+                Role::Player => vec![Outgoing::Reply(localized(MessageCode::InvalidMessage { reason }))],
+                Role::Spectator { .. } => Vec::new(),
+            };
+            (state, outgoing)
+        }
+        Incoming::FrameTooLarge => close(ErrorCode::ProtocolViolation, "frame too large", DisconnectReason::Error),
+        Incoming::ClientClosed => (ConnectionState::Closed { reason: DisconnectReason::Closed }, Vec::new()),
+        Incoming::ConnectionLost => (ConnectionState::Closed { reason: DisconnectReason::Error }, Vec::new()),
+        Incoming::WriterGone => (ConnectionState::Closed { reason: DisconnectReason::Timeout }, Vec::new()),
+        Incoming::RateLimited => close(ErrorCode::RateLimited, "rate limit exceeded", DisconnectReason::RateLimited),
+        Incoming::IdleTimeout => close(ErrorCode::IdleTimeout, "idle timeout", DisconnectReason::Timeout),
+        Incoming::KeepaliveTimeout => close(ErrorCode::KeepaliveTimeout, "keepalive timeout", DisconnectReason::Timeout),
+        Incoming::Kicked => close(ErrorCode::Kicked, "kicked by an admin", DisconnectReason::Kicked),
+        Incoming::ShuttingDown { reconnect_to } => {
+            let (state, closing) = close(ErrorCode::ShuttingDown, "server shutting down", DisconnectReason::ShuttingDown);
+            let reconnect = reconnect_to.map(|to| Outgoing::Reply(ServerMessage::Reconnect { to }));
+            (state, reconnect.into_iter().chain(closing).collect())
+        }
+    }
+}
+
+fn handle_message(role: Role, opening: bool, message: ClientMessage) -> (Role, Vec<Outgoing>) {
+    match (role, message) {
+        (_, ClientMessage::SetFormat { format }) if opening => (role, vec![Outgoing::SwitchFormat(format)]),
+        (Role::Player, ClientMessage::SetFormat { .. }) => (role, vec![Outgoing::Reply(localized(MessageCode::FormatLocked {}))]),
+        (Role::Player, ClientMessage::Join { name }) if !valid_player_name(&name) => {
+            let error = ServerMessage::Error { code: ErrorCode::ProtocolViolation.code(), message: format!("invalid player name {:?}", name) };
+            (role, vec![Outgoing::Reply(error)])
+        }
+        (Role::Player, message) => (role, vec![Outgoing::Dispatch(message)]),
+        (Role::Spectator { .. }, message) if is_spectator_message(&message) => (role, vec![Outgoing::Dispatch(message)]),
+        // Moving, chatting and the rest need a player; say so once
+        (Role::Spectator { warned: true }, _) => (role, Vec::new()),
+        (Role::Spectator { warned: false }, _) => {
+            (Role::Spectator { warned: true }, vec![Outgoing::Reply(localized(MessageCode::SpectatorReadOnly {}))])
+        }
+    }
+}
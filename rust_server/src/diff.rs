@@ -0,0 +1,229 @@
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::protocol::{moves_unless_omitted, names_unless_omitted, omit_moves, omit_names, omit_terrain, terrain_unless_omitted, Belt, Color, GameState, Moon, Planet, Player, Position, TerrainParams};
+use crate::quantize::PlayerMoves;
+
+/*
+Differences between two game states, for delta broadcasts and client
+reconciliation.
+
+Players and planets are both matched by id (ids are assumed unique within a
+state). A player whose fields differ in any way is sent whole; for planets
+only the fields that changed are sent. Belts rarely change, so when any
+does the whole list is sent; the time of day is sent whenever it moved.
+Applying `a.diff(&b)` to `a` yields exactly `b`, including the order of
+both lists. For clients with the QUANTIZED capability, players that only
+moved come as quantized `moves` instead (see `quantize`); `diff` never
+makes any.
+*/
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct StateDiff {
+    pub removed_players: Vec<u32>,
+    pub added_players: Vec<Player>,
+    pub changed_players: Vec<Player>,
+    /// Final player order by id, only when removal/append would not produce it
+    pub player_order: Option<Vec<u32>>,
+    pub removed_planets: Vec<u32>,
+    pub added_planets: Vec<Planet>,
+    pub changed_planets: Vec<PlanetChange>,
+    /// Final planet order by id, only when removal/append would not produce it
+    pub planet_order: Option<Vec<u32>>,
+    pub initial_player_location: Option<Position>,
+    /// Every belt, only when any of them changed
+    pub belts: Option<Vec<Belt>>,
+    #[serde(default)]
+    pub world_time: Option<f32>,
+    /// Players that only moved, for QUANTIZED clients
+    #[serde(default, skip_serializing_if = "omit_moves", deserialize_with = "moves_unless_omitted")]
+    pub moves: Option<PlayerMoves>,
+}
+
+/// Changed fields of the planet with this `id`; `None` means unchanged.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PlanetChange {
+    pub id: u32,
+    pub size: Option<f32>,
+    pub colors: Option<[Color; 3]>,
+    pub module_type: Option<u8>,
+    pub position: Option<Position>,
+    pub owner: Option<OwnerChange>,
+    pub moons: Option<Vec<Moon>>,
+    pub resources: Option<u32>,
+    pub capacity: Option<u32>,
+    #[serde(default, skip_serializing_if = "omit_terrain", deserialize_with = "terrain_unless_omitted")]
+    pub terrain: Option<TerrainParams>,
+    #[serde(default, skip_serializing_if = "omit_names", deserialize_with = "names_unless_omitted")]
+    pub name: Option<String>,
+}
+
+/// A planet's new owner, `None` when it was released. Wrapped so that JSON
+/// can tell "owner unchanged" from "no owner any more".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct OwnerChange {
+    pub player_id: Option<u32>,
+}
+
+impl PlanetChange {
+    /// `None` when the planets are identical.
+    fn between(old: &Planet, new: &Planet) -> Option<Self> {
+        let change = PlanetChange {
+            id: new.id,
+            size: (old.size != new.size).then_some(new.size),
+            colors: (old.colors != new.colors).then(|| new.colors.clone()),
+            module_type: (old.module_type != new.module_type).then_some(new.module_type),
+            position: (old.position != new.position).then(|| new.position.clone()),
+            owner: (old.owner != new.owner).then_some(OwnerChange { player_id: new.owner }),
+            moons: (old.moons != new.moons).then(|| new.moons.clone()),
+            resources: (old.resources != new.resources).then_some(new.resources),
+            capacity: (old.capacity != new.capacity).then_some(new.capacity),
+            terrain: (old.terrain != new.terrain).then_some(new.terrain),
+            name: (old.name != new.name).then(|| new.name.clone()),
+        };
+        (change != PlanetChange { id: new.id, ..Default::default() }).then_some(change)
+    }
+
+    fn apply(&self, planet: &mut Planet) {
+        if let Some(size) = self.size {
+            planet.size = size;
+        }
+        if let Some(colors) = &self.colors {
+            planet.colors = colors.clone();
+            planet.refresh_display();
+        }
+        if let Some(module_type) = self.module_type {
+            planet.module_type = module_type;
+        }
+        if let Some(position) = &self.position {
+            planet.position = position.clone();
+        }
+        if let Some(owner) = &self.owner {
+            planet.owner = owner.player_id;
+        }
+        if let Some(moons) = &self.moons {
+            planet.moons = moons.clone();
+        }
+        if let Some(resources) = self.resources {
+            planet.resources = resources;
+        }
+        if let Some(capacity) = self.capacity {
+            planet.capacity = capacity;
+        }
+        if let Some(terrain) = self.terrain {
+            planet.terrain = terrain;
+        }
+        if let Some(name) = &self.name {
+            planet.name = name.clone();
+        }
+    }
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
+
+/// Removed, added and kept-but-different items between two lists keyed by id,
+/// plus the final order when removing and appending would not produce it.
+struct ListDiff<'a, T> {
+    removed: Vec<u32>,
+    added: Vec<T>,
+    changed: Vec<(&'a T, &'a T)>,
+    order: Option<Vec<u32>>,
+}
+
+fn diff_lists<'a, T: Clone + PartialEq>(old: &'a [T], new: &'a [T], id: impl Fn(&T) -> u32) -> ListDiff<'a, T> {
+    let find = |items: &'a [T], wanted: u32| items.iter().find(|item| id(item) == wanted);
+
+    let removed: Vec<u32> = old.iter().map(&id).filter(|i| find(new, *i).is_none()).collect();
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for item in new {
+        match find(old, id(item)) {
+            None => added.push(item.clone()),
+            Some(previous) if previous != item => changed.push((previous, item)),
+            Some(_) => {}
+        }
+    }
+
+    // Removing and appending keeps the remaining items in their old order
+    let natural_order: Vec<u32> = old.iter()
+        .map(&id)
+        .filter(|i| !removed.contains(i))
+        .chain(added.iter().map(&id))
+        .collect();
+    let order: Vec<u32> = new.iter().map(&id).collect();
+    ListDiff { removed, added, changed, order: (natural_order != order).then_some(order) }
+}
+
+fn apply_list<T: Clone>(
+    items: &mut Vec<T>,
+    removed: &[u32],
+    added: &[T],
+    order: Option<&Vec<u32>>,
+    id: impl Fn(&T) -> u32,
+) {
+    items.retain(|item| !removed.contains(&id(item)));
+    items.extend(added.iter().cloned());
+    if let Some(order) = order {
+        items.sort_by_key(|item| order.iter().position(|i| *i == id(item)).unwrap_or(usize::MAX));
+    }
+}
+
+impl GameState {
+    /// What must change to turn `self` into `other`.
+    pub fn diff(&self, other: &GameState) -> StateDiff {
+        let players = diff_lists(&self.players, &other.players, |p| p.id);
+        let planets = diff_lists(&self.planets, &other.planets, |p| p.id);
+
+        StateDiff {
+            removed_players: players.removed,
+            added_players: players.added,
+            changed_players: players.changed.into_iter().map(|(_, new)| new.clone()).collect(),
+            player_order: players.order,
+            removed_planets: planets.removed,
+            added_planets: planets.added,
+            changed_planets: planets.changed.into_iter().filter_map(|(old, new)| PlanetChange::between(old, new)).collect(),
+            planet_order: planets.order,
+            initial_player_location: (self.initial_player_location != other.initial_player_location)
+                .then(|| other.initial_player_location.clone()),
+            belts: (self.belts != other.belts).then(|| other.belts.clone()),
+            world_time: (self.world_time != other.world_time).then_some(other.world_time),
+            moves: None,
+        }
+    }
+
+    pub fn apply(&mut self, diff: &StateDiff) {
+        for changed in &diff.changed_players {
+            if let Some(player) = self.players.iter_mut().find(|p| p.id == changed.id) {
+                *player = changed.clone();
+            }
+        }
+        if let Some(moves) = &diff.moves {
+            for moved in &moves.players {
+                if let Some(player) = self.players.iter_mut().find(|p| p.id == moved.id) {
+                    moved.apply(player, moves.resolution);
+                }
+            }
+        }
+        apply_list(&mut self.players, &diff.removed_players, &diff.added_players, diff.player_order.as_ref(), |p| p.id);
+
+        for change in &diff.changed_planets {
+            if let Some(planet) = self.planet_by_id_mut(change.id) {
+                change.apply(planet);
+            }
+        }
+        apply_list(&mut self.planets, &diff.removed_planets, &diff.added_planets, diff.planet_order.as_ref(), |p| p.id);
+
+        if let Some(location) = &diff.initial_player_location {
+            self.initial_player_location = location.clone();
+        }
+        if let Some(belts) = &diff.belts {
+            self.belts = belts.clone();
+        }
+        if let Some(world_time) = diff.world_time {
+            self.world_time = world_time;
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeSet;
+use crate::protocol::{Planet, Position};
+
+/*
+Exploration: which planets each player has been near.
+
+A player discovers a planet by coming within `DISCOVERY_RANGE` times its
+size (its diameter) of the planet's centre, boundary included, whether by
+flying or by teleporting. Each first discovery is broadcast as
+`PlanetDiscovered` and earns `DISCOVERY_XP`; every `XP_PER_LEVEL` XP is a
+level. Visiting a planet again does nothing.
+
+Players have no accounts, so discoveries and XP are remembered by player
+name for as long as the server runs: a player who reconnects under the same
+name keeps both. There is no spatial index; every update checks every planet.
+*/
+
+pub const DISCOVERY_RANGE: f32 = 1.5;
+pub const DISCOVERY_XP: u32 = 100;
+pub const XP_PER_LEVEL: u32 = 500;
+
+pub fn in_discovery_range(planet: &Planet, position: &Position) -> bool {
+    let (dx, dy, dz) = (
+        (position.x - planet.position.x) as f64,
+        (position.y - planet.position.y) as f64,
+        (position.z - planet.position.z) as f64,
+    );
+    (dx * dx + dy * dy + dz * dz).sqrt() <= (planet.size * DISCOVERY_RANGE) as f64
+}
+
+pub fn level_for(xp: u32) -> u32 {
+    1 + xp / XP_PER_LEVEL
+}
+
+/// The planets one player has discovered, and the XP they earned doing it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Discoveries {
+    planets: BTreeSet<u32>,
+    pub xp: u32,
+}
+
+impl Discoveries {
+    /// Discovers every planet in range of `position`, returning the ids of
+    /// those not discovered before.
+    pub fn visit(&mut self, position: &Position, planets: &[Planet]) -> Vec<u32> {
+        let found: Vec<u32> = planets.iter()
+            .filter(|planet| !self.planets.contains(&planet.id) && in_discovery_range(planet, position))
+            .map(|planet| planet.id)
+            .collect();
+        self.planets.extend(&found);
+        self.xp += DISCOVERY_XP * found.len() as u32;
+        found
+    }
+
+    /// Forgets the discovered planets, keeping the XP, for a new world.
+    pub fn forget_planets(&mut self) {
+        self.planets.clear();
+    }
+
+    pub fn contains(&self, planet_id: u32) -> bool {
+        self.planets.contains(&planet_id)
+    }
+
+    /// Discovered planet ids, lowest first.
+    pub fn planet_ids(&self) -> Vec<u32> {
+        self.planets.iter().copied().collect()
+    }
+
+    /// Share of `planets` discovered, from 0 to 100; removed planets do not count.
+    pub fn explored_percent(&self, planets: &[Planet]) -> f32 {
+        if planets.is_empty() {
+            return 0.0;
+        }
+        let found = planets.iter().filter(|planet| self.planets.contains(&planet.id)).count();
+        found as f32 * 100.0 / planets.len() as f32
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use crate::protocol::{GameState, Planet, MODULE_TYPES};
+
+/*
+Planet resources, and how they grow back.
+
+Every planet holds `resources` units out of a `capacity` fixed when it was
+made: generated planets and those added with `planet add` start full, with
+`CAPACITY_PER_SIZE` units per unit of size. Planets saved or written before
+resources existed have neither and stay empty. Players empty planets by
+mining them while landed (see `module_effects`), and an admin with
+`planet set <id> resources <n>`.
+
+On the economy tick, every `ECONOMY_INTERVAL`, each planet below its
+capacity grows back by its rate for the time since the last tick, up to the
+capacity: `BASE_RATES` units a minute for its module type, times the
+world's multiplier for that type (the `[regeneration]` table of the config,
+1 when unset). Fractions of a unit are carried to the next tick per planet,
+so slow planets still grow. Planets that did not change are not sent, and
+those that did go out as `PlanetUpdated` events, so a world at rest costs
+nothing. While the server hibernates the tick is skipped and the first tick
+after waking makes up for the whole sleep.
+
+The admin `economy stats` command sums up the resources in the world, those
+players hold as cargo (see `wallets`) and how fast the world's are coming
+back. Like the rate limiter, everything here
+takes an explicit `now`.
+*/
+
+/// How often planets grow back.
+pub const ECONOMY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Units a new planet holds per unit of size.
+pub const CAPACITY_PER_SIZE: f32 = 10.0;
+
+/// Units a minute a planet of each module type grows back, before the
+/// world's multipliers.
+pub const BASE_RATES: [f64; MODULE_TYPES as usize] = [6.0, 12.0, 3.0, 9.0, 4.5];
+
+/// What a new planet of `size` holds.
+pub fn capacity_for(size: f32) -> u32 {
+    (size.max(0.0) * CAPACITY_PER_SIZE) as u32
+}
+
+/// Multipliers of `BASE_RATES`, per module type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegenerationRates([f64; MODULE_TYPES as usize]);
+
+impl Default for RegenerationRates {
+    fn default() -> Self {
+        RegenerationRates([1.0; MODULE_TYPES as usize])
+    }
+}
+
+impl RegenerationRates {
+    /// The config's `[regeneration]` table: module types (as strings, TOML
+    /// keys being strings) to multipliers; types left out keep 1.
+    pub fn resolve(table: &HashMap<String, f64>) -> Result<Self, String> {
+        let mut rates = Self::default();
+        for (key, multiplier) in table {
+            let module_type = key.parse::<u8>().ok().filter(|t| *t < MODULE_TYPES)
+                .ok_or_else(|| format!("regeneration: {} is not a module type (0 to {})", key, MODULE_TYPES - 1))?;
+            if !(multiplier.is_finite() && *multiplier >= 0.0) {
+                return Err(format!("regeneration: the multiplier for {} must be zero or positive", key));
+            }
+            rates.0[module_type as usize] = *multiplier;
+        }
+        Ok(rates)
+    }
+
+    /// Units a minute `planet` grows back while below capacity; none for
+    /// unknown module types.
+    pub fn per_minute(&self, planet: &Planet) -> f64 {
+        let module_type = planet.module_type as usize;
+        BASE_RATES.get(module_type).map_or(0.0, |base| base * self.0[module_type])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Economy {
+    last_tick: Instant,
+    carry: HashMap<u32, f64>,  // planet id -> fraction of a unit grown but not yet added
+}
+
+impl Economy {
+    pub fn new(now: Instant) -> Self {
+        Economy { last_tick: now, carry: HashMap::new() }
+    }
+
+    /// Growth since the last tick: the id and new resources of every planet
+    /// that gained at least a unit.
+    pub fn tick(&mut self, planets: &[Planet], rates: &RegenerationRates, now: Instant) -> Vec<(u32, u32)> {
+        let minutes = now.saturating_duration_since(self.last_tick).as_secs_f64() / 60.0;
+        self.last_tick = now;
+        let growing: HashSet<u32> = planets.iter().filter(|p| p.resources < p.capacity).map(|p| p.id).collect();
+        // Planets that filled up or went away start from nothing next time
+        self.carry.retain(|id, _| growing.contains(id));
+
+        let mut grown = Vec::new();
+        for planet in planets.iter().filter(|p| growing.contains(&p.id)) {
+            let carry = self.carry.entry(planet.id).or_default();
+            *carry += rates.per_minute(planet) * minutes;
+            let units = carry.floor();
+            if units < 1.0 {
+                continue;
+            }
+            *carry -= units;
+            let resources = (planet.resources as f64 + units).min(planet.capacity as f64) as u32;
+            grown.push((planet.id, resources));
+        }
+        grown
+    }
+}
+
+/// What the admin `economy stats` command reports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EconomyStats {
+    pub planet_resources: u64,
+    pub planet_capacity: u64,
+    pub asteroid_resources: u64,
+    pub held_by_players: u64,  // as cargo in their wallets, online or not
+    pub regrowing: usize,  // planets below capacity
+    pub per_minute: f64,   // what those planets grow back in all
+}
+
+impl EconomyStats {
+    /// The stats of `state`, with players holding `held_by_players` units
+    /// between them.
+    pub fn of(state: &GameState, rates: &RegenerationRates, held_by_players: u64) -> Self {
+        let regrowing: Vec<&Planet> = state.planets.iter().filter(|p| p.resources < p.capacity).collect();
+        EconomyStats {
+            planet_resources: state.planets.iter().map(|p| p.resources as u64).sum(),
+            planet_capacity: state.planets.iter().map(|p| p.capacity as u64).sum(),
+            asteroid_resources: state.belts.iter().flat_map(|b| &b.notable).map(|a| a.resources as u64).sum(),
+            held_by_players,
+            regrowing: regrowing.len(),
+            per_minute: regrowing.iter().fold(0.0, |sum, p| sum + rates.per_minute(p)),
+        }
+    }
+}
+
+impl std::fmt::Display for EconomyStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "planets hold {} of {} units, notable asteroids {}, players {}; {} planet(s) growing back {:.1} units/min",
+               self.planet_resources, self.planet_capacity, self.asteroid_resources, self.held_by_players, self.regrowing, self.per_minute)
+    }
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use crate::protocol::{Planet, Player, PlayerAppearance, Position};
+use crate::snapshot::GameStateSnapshot;
+
+/*
+Game events, for code embedding the server.
+
+Everything the server does that others may care about is emitted once, as
+a `GameEvent`, from the code that does it, under the lock guarding what
+changed, so events come out in the order they happened. They go two ways:
+
+- To the sinks attached while the server is built: the journal, the
+  replication feed and the webhooks each turn the events they care about
+  into records, followers' frames or POSTs (see `journal`, `replication`,
+  `webhooks`). A sink is called on the emitting thread and must not block
+  or take the server's locks; it sees every event.
+- To `GameServer::subscribe_events`, a tokio broadcast channel of
+  `EVENT_CAPACITY` events. Subscribing costs the game nothing until the
+  subscriber falls behind, and then it is the subscriber that pays: a
+  receiver more than `EVENT_CAPACITY` events behind gets
+  `RecvError::Lagged` and skips to the oldest event still held. Nothing
+  is sent while nobody subscribes.
+
+`GameEvent` is non-exhaustive, so new kinds of event are not a breaking
+change; match the ones you want and ignore the rest.
+
+The other way round, `GameServer::inject` runs a `ServerCommand` as if an
+admin had: an announcement, XP for a player, or a new planet. Its events
+are emitted like any others.
+*/
+
+/// Events a subscriber may fall behind by before it starts missing some.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Something that happened in the game.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum GameEvent {
+    PlayerJoined { player: Player },
+    PlayerLeft { player_id: u32, name: String },
+    /// A position update was applied; `seq` is the client's, if it sent one
+    PlayerMoved {
+        player_id: u32,
+        seq: Option<u32>,
+        position: Position,
+        velocity: [f32; 3],
+        rotation: [f32; 4],
+    },
+    IdleChanged { player_id: u32, idle: bool },
+    /// A player chatted, in `room` if they connected to one
+    Chatted { player_id: u32, name: String, room: Option<String>, text: String },
+    AppearanceChanged { player_id: u32, appearance: PlayerAppearance },
+    PlayerRenamed { player_id: u32, name: String },
+    /// Landed on a planet, took off, or moved with the planet landed on
+    LandingChanged { player_id: u32, landed_on: Option<u32>, position: Position },
+    PlanetDiscovered { player_id: u32, planet_id: u32 },
+    /// XP earned other than by discovering, e.g. by mining
+    XpEarned { player_id: u32, xp: u32 },
+    LevelUp { player_id: u32, name: String, level: u32 },
+    PlanetAdded { planet: Planet },
+    PlanetRemoved { planet_id: u32 },
+    PlanetUpdated { planet: Planet },
+    /// A planet gained an owner; follows its `PlanetUpdated`
+    PlanetClaimed { planet_id: u32, owner: u32 },
+    /// A notable asteroid was mined down to `resources`
+    AsteroidMined { asteroid_id: u32, resources: u32 },
+    /// `seller` traded `resources` units of cargo to `buyer` for `credits`
+    Traded { seller: u32, buyer: u32, resources: u32, credits: u64 },
+    /// The world was replaced, by regenerating it or on promotion; `world`
+    /// is all of it afterwards, without the server's fake players
+    WorldReset { world: Arc<GameStateSnapshot> },
+    BanIssued { name: String },
+    ServerStarted,
+    ServerStopped,
+}
+
+/// When an event happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventTime {
+    pub tick: u64,
+    pub server_time_ms: u64,
+    pub unix_ms: u64,
+}
+
+/// Something built into the server that every event is handed to.
+pub trait EventSink: Send + Sync {
+    /// Called for every event, in order, while the server holds the lock
+    /// guarding what changed.
+    fn consume(&self, at: EventTime, event: &GameEvent);
+
+    /// Whether the sink would do anything with an event now, so that
+    /// events costly to build, like `WorldReset`, can be skipped.
+    fn is_listening(&self) -> bool {
+        true
+    }
+}
+
+/// Something for `GameServer::inject` to do.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ServerCommand {
+    /// Sends everyone an `Announcement`; `{player_count}` is filled in as in
+    /// admin announcements (see `announce`)
+    Announce { text: String },
+    /// Gives a connected player XP, as mining does
+    GrantXp { player_id: u32, xp: u32 },
+    /// Adds a planet as the admin `add-planet` does; its id is replaced
+    AddPlanet { planet: Planet },
+}
+
+/// Where the server emits its events.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<GameEvent>,
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus { tx: broadcast::channel(EVENT_CAPACITY).0, sinks: Vec::new() }
+    }
+
+    /// Hands every event emitted from now on to `sink`, after those
+    /// attached before it.
+    pub fn attach(&mut self, sink: Arc<dyn EventSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GameEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Whether an event emitted now would reach anything.
+    pub fn is_observed(&self) -> bool {
+        self.tx.receiver_count() > 0 || self.sinks.iter().any(|sink| sink.is_listening())
+    }
+
+    pub fn emit(&self, at: EventTime, event: GameEvent) {
+        for sink in &self.sinks {
+            sink.consume(at, &event);
+        }
+        if self.tx.receiver_count() > 0 {
+            let _ = self.tx.send(event);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+use crate::protocol::Position;
+
+/*
+Fake players, for developing clients against a populated world without
+running bot processes: `--fake-players N`, or the admin `fakes add|remove
+N` commands.
+
+The server owns them. They get player ids and spawns like anyone else,
+are named `NPC_<id>`, and on the wire look exactly like real players. Each
+broadcast tick moves them (see `wander`), so their movement reaches clients
+in the same snapshots as everyone's. Removing one drops it from the next
+snapshot, which clients see as a player leaving.
+
+Internally the server keeps their connection ids apart, and they have no
+connection: they are not counted against `max_players`, do not keep the
+server awake, are left out of the `players` listing, never discover
+planets, and are not journaled.
+*/
+
+pub const NAME_PREFIX: &str = "NPC_";
+
+/// Ticks per loop around the spawn, at the fastest.
+const LAP_TICKS: f32 = 200.0;
+
+/// Where the fake player with id `seed` is at `tick`, and its velocity and
+/// rotation: a loop around `home` of a radius, direction and starting point
+/// that depend on `seed`, bobbing up and down, a step further every tick.
+pub fn wander(home: &Position, seed: u32, tick: u64, tick_length: Duration) -> (Position, [f32; 3], [f32; 4]) {
+    let radius = 100.0 + (seed % 5) as f32 * 25.0;
+    let direction = if seed.is_multiple_of(2) { 1.0 } else { -1.0 };
+    // Radians per tick; some take two or three times longer per lap
+    let step = direction * TAU / (LAP_TICKS * (1.0 + (seed % 3) as f32 * 0.5));
+    // Wrapped so the angle keeps its f32 precision on long-running servers
+    let angle = seed as f32 * 1.3 + step * (tick % 1_000_000) as f32;
+    let bob = 20.0;
+
+    let position = Position {
+        x: home.x + radius * angle.cos(),
+        y: home.y + bob * (2.0 * angle).sin(),
+        z: home.z + radius * angle.sin(),
+    };
+    // The derivative of the above, from per tick to per second
+    let per_second = step / tick_length.as_secs_f32().max(f32::EPSILON);
+    let velocity = [
+        -radius * angle.sin() * per_second,
+        2.0 * bob * (2.0 * angle).cos() * per_second,
+        radius * angle.cos() * per_second,
+    ];
+    let yaw = -velocity[2].atan2(velocity[0]);
+    let rotation = [0.0, (yaw / 2.0).sin(), 0.0, (yaw / 2.0).cos()];
+    (position, velocity, rotation)
+}
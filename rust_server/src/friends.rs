@@ -0,0 +1,193 @@
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::handshake::valid_player_name;
+use crate::lifetime::replace_file;
+use crate::protocol::{normalize_name, Friend};
+use crate::server::ConnectionId;
+
+/*
+Friend lists, kept across restarts in the file given as `friends_file`.
+
+A player adds and removes friends with `AddFriend` and `RemoveFriend`
+(`friend add <name>` and `friend remove <name>` in the client) and gets
+their list back each time, or with `ListFriends`, each friend marked
+online or not. Lists belong to names, compared by `normalize_name` as
+bans and roles are, and are one-way: friending someone does not put you
+on their list. At most `MAX_FRIENDS` each.
+
+Any well-formed name may be friended. One that has never joined is
+`pending` until it first does; the names that have joined are kept in the
+same file so that it stays resolved across restarts.
+
+When a player joins, everyone online who friended their name is sent a
+`FriendOnline`, and a `FriendOffline` when they leave. Rather than look
+through every list on each join, the server keeps a reverse index from
+each name to the connections of the online players who friended it: a
+player's friends are indexed as they join and as they add friends, and
+unindexed as they remove them and leave, so the index only ever holds
+players who are online. Renaming counts as leaving under the old name and
+joining under the new one.
+
+The file is JSON, rewritten on every change. Without a file lists last
+until the server stops.
+*/
+
+/// Most friends one name may have.
+pub const MAX_FRIENDS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Entry {
+    name: String,  // as friended
+    pending: bool,
+}
+
+/// What the file holds.
+#[derive(Debug, Default, Deserialize)]
+struct FriendsFile {
+    lists: BTreeMap<String, Vec<Entry>>,
+    seen: BTreeSet<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Friends {
+    path: Option<PathBuf>,
+    lists: BTreeMap<String, Vec<Entry>>,  // by normalized name, in the order added
+    seen: BTreeSet<String>,  // normalized names that have joined
+    watchers: HashMap<String, BTreeSet<ConnectionId>>,  // normalized name -> online players who friended it
+}
+
+impl Friends {
+    /// No lists, kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lists in `path`, saved back there on every change. A missing file
+    /// is no lists, created on the first change.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file: FriendsFile = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => FriendsFile::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Friends { path: Some(path.to_path_buf()), lists: file.lists, seen: file.seen, watchers: HashMap::new() })
+    }
+
+    /// How many names have lists.
+    pub fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+
+    /// Notes that `name` has joined, resolving its pending entries the
+    /// first time.
+    pub fn seen(&mut self, name: &str) -> io::Result<()> {
+        let key = normalize_name(name);
+        if !self.seen.insert(key.clone()) {
+            return Ok(());
+        }
+        for entry in self.lists.values_mut().flatten().filter(|entry| normalize_name(&entry.name) == key) {
+            entry.pending = false;
+        }
+        self.save()
+    }
+
+    /// Indexes the friends of `name`, who just came online on `connection`,
+    /// returning the connections to tell.
+    pub fn joined(&mut self, connection: ConnectionId, name: &str) -> Vec<ConnectionId> {
+        let key = normalize_name(name);
+        for entry in self.lists.get(&key).into_iter().flatten() {
+            self.watchers.entry(normalize_name(&entry.name)).or_default().insert(connection);
+        }
+        self.watchers_of(&key, connection)
+    }
+
+    /// Unindexes the friends of `name`, who just went offline from
+    /// `connection`, returning the connections to tell.
+    pub fn left(&mut self, connection: ConnectionId, name: &str) -> Vec<ConnectionId> {
+        let key = normalize_name(name);
+        let friends: Vec<String> = self.lists.get(&key).into_iter().flatten().map(|entry| normalize_name(&entry.name)).collect();
+        for friend in friends {
+            self.unwatch(&friend, connection);
+        }
+        self.watchers_of(&key, connection)
+    }
+
+    /// Adds `friend` to the list of `owner`, online on `connection`.
+    pub fn add(&mut self, connection: ConnectionId, owner: &str, friend: &str) -> Result<(), String> {
+        let (owner_key, friend_key) = (normalize_name(owner), normalize_name(friend));
+        if !valid_player_name(friend) {
+            return Err(format!("invalid player name {:?}", friend));
+        }
+        if owner_key == friend_key {
+            return Err("you cannot friend yourself".to_string());
+        }
+        let list = self.lists.entry(owner_key).or_default();
+        if list.iter().any(|entry| normalize_name(&entry.name) == friend_key) {
+            return Err(format!("{} is already a friend", friend));
+        }
+        if list.len() >= MAX_FRIENDS {
+            return Err(format!("at most {} friends", MAX_FRIENDS));
+        }
+        list.push(Entry { name: friend.to_string(), pending: !self.seen.contains(&friend_key) });
+        self.watchers.entry(friend_key).or_default().insert(connection);
+        self.save().map_err(|e| format!("cannot save friends: {}", e))
+    }
+
+    /// Takes `friend` off the list of `owner`, online on `connection`.
+    pub fn remove(&mut self, connection: ConnectionId, owner: &str, friend: &str) -> Result<(), String> {
+        let (owner_key, friend_key) = (normalize_name(owner), normalize_name(friend));
+        let Some(list) = self.lists.get_mut(&owner_key) else {
+            return Err(format!("{} is not a friend", friend));
+        };
+        let before = list.len();
+        list.retain(|entry| normalize_name(&entry.name) != friend_key);
+        if list.len() == before {
+            return Err(format!("{} is not a friend", friend));
+        }
+        if list.is_empty() {
+            self.lists.remove(&owner_key);
+        }
+        self.unwatch(&friend_key, connection);
+        self.save().map_err(|e| format!("cannot save friends: {}", e))
+    }
+
+    /// The friends of `owner` in the order added, `online` saying which
+    /// normalized names are.
+    pub fn list(&self, owner: &str, online: &BTreeSet<String>) -> Vec<Friend> {
+        self.lists.get(&normalize_name(owner)).into_iter().flatten()
+            .map(|entry| Friend { name: entry.name.clone(), online: online.contains(&normalize_name(&entry.name)), pending: entry.pending })
+            .collect()
+    }
+
+    /// How many names online players have friended, for watching the index.
+    pub fn watched(&self) -> usize {
+        self.watchers.len()
+    }
+
+    /// The online players who friended `key`, other than `connection`.
+    fn watchers_of(&self, key: &str, connection: ConnectionId) -> Vec<ConnectionId> {
+        self.watchers.get(key).into_iter().flatten().copied().filter(|c| *c != connection).collect()
+    }
+
+    fn unwatch(&mut self, key: &str, connection: ConnectionId) {
+        if let Some(watchers) = self.watchers.get_mut(key) {
+            watchers.remove(&connection);
+            if watchers.is_empty() {
+                self.watchers.remove(key);
+            }
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let file = serde_json::json!({ "lists": &self.lists, "seen": &self.seen });
+        replace_file(path, &serde_json::to_string_pretty(&file)?)
+    }
+}
@@ -0,0 +1,200 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use tokio_tungstenite::tungstenite::handshake::server::Request;
+use crate::protocol::{Capabilities, WireFormat};
+use crate::subscriptions::Subscriptions;
+
+/*
+Connection metadata captured during the WebSocket handshake.
+
+Behind a reverse proxy every TCP peer is the proxy itself, so the client's
+real address comes from `X-Forwarded-For` / `X-Real-IP` — but only when the
+peer is a trusted proxy, since anyone can send those headers. Forwarded
+headers carry no client port, so the real address keeps the peer's port.
+
+Clients may offer `permessage-deflate` in `Sec-WebSocket-Extensions`. The
+WebSocket implementation we use (tungstenite) has no support for it and
+rejects compressed frames, so the offer is recorded for logging but never
+accepted: the response carries no extension header and the client falls back
+to uncompressed frames as RFC 7692 requires.
+
+A `?format=json` query parameter selects the JSON wire format; anything else
+(or no parameter) keeps the default binary format. A `?name=` parameter asks
+for a player name (see `valid_player_name`); without a valid one, or when
+the name is already in use (compared by `normalize_name`, so `ada` and
+`Ada` clash), the player is named after the client's port.
+`?role=spectator` connects as a spectator, who gets the world but no player
+in it (see `server`), and `?role=follower&token=<admin token>` as a
+standby server replicating this one (see `replication`). `?caps=` declares
+the client's `Capabilities` as a decimal bitfield; the connection keeps
+only the bits this server supports, and a missing or unparsable value
+means none. `?subs=` is the same for the kinds of broadcast it wants (see
+`subscriptions`), except that a missing or unparsable value means all.
+*/
+
+pub const MAX_PLAYER_NAME_LEN: usize = 24;
+
+/// Player names are 1 to `MAX_PLAYER_NAME_LEN` ASCII letters, digits, `_` or
+/// `-`, so that they need no escaping in URLs or logs.
+pub fn valid_player_name(name: &str) -> bool {
+    (1..=MAX_PLAYER_NAME_LEN).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Whether a connection plays or only watches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Role {
+    #[default]
+    Player,
+    Spectator,
+    Follower,  // another server, replicating this one
+}
+
+/// An IPv4 or IPv6 network in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(net) as u128, u32::from(ip) as u128, 32, self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(net), u128::from(ip), 128, self.prefix_len)
+            }
+            (IpAddr::V4(_), IpAddr::V6(ip)) => ip.to_ipv4_mapped().is_some_and(|v4| self.contains(IpAddr::V4(v4))),
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, bits: u8, prefix_len: u8) -> bool {
+    let shift = bits - prefix_len;
+    if shift >= bits {
+        return true;
+    }
+    (net >> shift) == (ip >> shift)
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let network: IpAddr = addr.trim().parse().map_err(|_| format!("invalid address in CIDR: {}", s))?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix {
+            Some(p) => p.trim().parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(|| format!("invalid prefix length in CIDR: {}", s))?,
+            None => max,
+        };
+        Ok(Cidr { network, prefix_len })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub addr: SocketAddr,  // the client's real address
+    pub peer: SocketAddr,  // the TCP peer, possibly a proxy
+    pub user_agent: Option<String>,
+    pub path: String,
+    pub room: Option<String>,  // from a `/room/<name>` path
+    pub offered_deflate: bool,  // client offered permessage-deflate (always declined)
+    pub format: WireFormat,  // from a `?format=` query parameter
+    pub name: Option<String>,  // a valid `?name=` query parameter
+    pub role: Role,  // from a `?role=` query parameter
+    pub token: Option<String>,  // from a `?token=` query parameter, for followers
+    pub capabilities: Capabilities,  // `?caps=` intersected with ours
+    pub subscriptions: Subscriptions,  // `?subs=`, everything by default
+}
+
+impl ClientInfo {
+    /// Info for a connection whose handshake headers were not captured.
+    pub fn direct(peer: SocketAddr) -> Self {
+        ClientInfo { addr: peer, peer, user_agent: None, path: "/".to_string(), room: None, offered_deflate: false, format: WireFormat::default(), name: None, role: Role::Player, token: None, capabilities: Capabilities::NONE, subscriptions: Subscriptions::ALL }
+    }
+
+    pub fn is_proxied(&self) -> bool {
+        self.addr.ip() != self.peer.ip()
+    }
+}
+
+pub fn client_info(peer: SocketAddr, request: &Request, trusted_proxies: &[Cidr]) -> ClientInfo {
+    let header = |name: &str| {
+        request.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string)
+    };
+    let path = request.uri().path().to_string();
+    let room = path
+        .strip_prefix("/room/")
+        .map(|r| r.trim_end_matches('/'))
+        .filter(|r| !r.is_empty() && !r.contains('/'))
+        .map(str::to_string);
+
+    let query = |key: &str| -> Vec<String> {
+        let prefix = format!("{}=", key);
+        request.uri().query().into_iter()
+            .flat_map(|q| q.split('&'))
+            .filter_map(|pair| pair.strip_prefix(prefix.as_str()).map(str::to_string))
+            .collect()
+    };
+    let format = query("format").into_iter().find_map(|value| value.parse().ok()).unwrap_or_default();
+    let name = query("name").into_iter().find(|name| valid_player_name(name));
+    let role = match query("role").first().map(String::as_str) {
+        Some("spectator") => Role::Spectator,
+        Some("follower") => Role::Follower,
+        _ => Role::Player,
+    };
+    let token = query("token").into_iter().next();
+    let capabilities = query("caps").into_iter().find_map(|caps| caps.parse().ok())
+        .map_or(Capabilities::NONE, |caps| Capabilities(caps).intersect(Capabilities::SUPPORTED));
+    let subscriptions = query("subs").into_iter().find_map(|subs| subs.parse().ok())
+        .map_or(Subscriptions::ALL, Subscriptions::from_mask);
+
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|cidr| cidr.contains(ip));
+    let real_ip = if is_trusted(peer.ip()) {
+        forwarded_client_ip(header("x-forwarded-for").as_deref(), &is_trusted)
+            .or_else(|| header("x-real-ip").and_then(|v| v.trim().parse().ok()))
+    } else {
+        None
+    };
+
+    let offered_deflate = request
+        .headers()
+        .get_all("sec-websocket-extensions")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|ext| ext.split(';').next().is_some_and(|name| name.trim().eq_ignore_ascii_case("permessage-deflate")));
+
+    ClientInfo {
+        addr: SocketAddr::new(real_ip.unwrap_or(peer.ip()), peer.port()),
+        peer,
+        user_agent: header("user-agent"),
+        path,
+        room,
+        offered_deflate,
+        format,
+        name,
+        role,
+        token,
+        capabilities,
+        subscriptions,
+    }
+}
+
+/// Walks `X-Forwarded-For` from the right, skipping our own trusted proxies;
+/// the first untrusted hop is the client. Entries further left could be forged.
+fn forwarded_client_ip(header: Option<&str>, is_trusted: &impl Fn(IpAddr) -> bool) -> Option<IpAddr> {
+    let hops: Vec<IpAddr> = header?
+        .split(',')
+        .map(|hop| hop.trim().parse::<IpAddr>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    hops.iter().rev().find(|ip| !is_trusted(**ip)).or(hops.first()).copied()
+}
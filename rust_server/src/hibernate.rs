@@ -0,0 +1,69 @@
+use std::time::{Duration, Instant};
+
+/*
+Hibernation: an empty server stops ticking.
+
+Once nobody (player or spectator) has been connected for `after`, the
+broadcast loop parks until the next connection completes its handshake,
+then broadcasts straight away. Spectators count because they are watching
+the broadcasts that would stop.
+
+Nothing in the world advances by ticks (moons follow the server time), so
+there is nothing to simulate across the gap; only the tick counter notices
+it. By default it carries on from where it stopped. With `catch_up_ticks`
+set it jumps ahead by the ticks that were missed, up to that many, so tick
+numbers keep roughly tracking time. Like the idle tracker, everything takes
+an explicit `now`.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HibernationConfig {
+    pub after: Duration,      // Duration::ZERO never hibernates
+    pub catch_up_ticks: u32,  // most ticks fast-forwarded on waking; 0 skips the gap
+}
+
+impl Default for HibernationConfig {
+    fn default() -> Self {
+        HibernationConfig {
+            after: Duration::from_secs(60),
+            catch_up_ticks: 0,
+        }
+    }
+}
+
+impl HibernationConfig {
+    /// Ticks to add to the counter after sleeping for `slept`.
+    pub fn catch_up(&self, slept: Duration, interval: Duration) -> u64 {
+        let missed = slept.as_nanos() / interval.as_nanos().max(1);
+        missed.min(self.catch_up_ticks as u128) as u64
+    }
+}
+
+/// Decides, tick by tick, when an empty server goes to sleep.
+#[derive(Debug, Clone)]
+pub struct HibernationTracker {
+    config: HibernationConfig,
+    empty_since: Option<Instant>,
+}
+
+impl HibernationTracker {
+    pub fn new(config: HibernationConfig) -> Self {
+        HibernationTracker { config, empty_since: None }
+    }
+
+    /// Records whether anyone is connected at `now`; true once the server
+    /// has been empty for the whole grace period.
+    pub fn should_sleep(&mut self, connected: bool, now: Instant) -> bool {
+        if connected || self.config.after.is_zero() {
+            self.empty_since = None;
+            return false;
+        }
+        let since = *self.empty_since.get_or_insert(now);
+        now.saturating_duration_since(since) >= self.config.after
+    }
+
+    /// Starts the grace period over, as after waking up.
+    pub fn reset(&mut self) {
+        self.empty_since = None;
+    }
+}
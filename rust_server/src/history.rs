@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::diff::StateDiff;
+use crate::protocol::GameState;
+
+/*
+Recent broadcast history, so a client that missed some broadcasts can catch
+up with diffs instead of a full snapshot.
+
+Only the newest and oldest broadcast states are kept whole; the ticks
+between are kept as the diff from one broadcast to the next, and the state
+of any of them is the oldest with the diffs up to it applied. A client that last saw tick T asks with
+`ResyncFrom { tick: T }` and gets every diff recorded since T, applied in
+order. Entries are dropped once there are more than `capacity` of them or
+they are older than `retention`, which bounds memory; a tick that has fallen
+out gets a full snapshot instead.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryConfig {
+    pub capacity: usize,     // diffs kept at most; 0 disables resync from history
+    pub retention: Duration,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            capacity: 64,
+            retention: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HistoryStats {
+    pub entries: usize,
+    pub bytes: u64,  // bincode size of the stored diffs
+}
+
+#[derive(Debug)]
+struct HistoryEntry {
+    from_tick: u64,
+    recorded_at: Instant,
+    diff: StateDiff,
+    bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct SnapshotHistory {
+    config: HistoryConfig,
+    latest: Option<(u64, GameState)>,
+    oldest: Option<GameState>,  // the state the first entry is a diff from
+    entries: VecDeque<HistoryEntry>,
+    bytes: u64,
+}
+
+impl SnapshotHistory {
+    pub fn new(config: HistoryConfig) -> Self {
+        SnapshotHistory { config, latest: None, oldest: None, entries: VecDeque::new(), bytes: 0 }
+    }
+
+    /// Records the state broadcast at `tick`.
+    pub fn record(&mut self, tick: u64, state: &GameState, now: Instant) {
+        if let Some((from_tick, previous)) = self.latest.take()
+            && self.config.capacity > 0
+        {
+            let diff = previous.diff(state);
+            let bytes = bincode::serialized_size(&diff).unwrap_or(0);
+            self.bytes += bytes;
+            if self.entries.is_empty() {
+                self.oldest = Some(previous);
+            }
+            self.entries.push_back(HistoryEntry { from_tick, recorded_at: now, diff, bytes });
+        }
+        self.latest = Some((tick, state.clone()));
+        self.evict(now);
+    }
+
+    fn evict(&mut self, now: Instant) {
+        while let Some(oldest) = self.entries.front() {
+            let expired = now.saturating_duration_since(oldest.recorded_at) > self.config.retention;
+            if self.entries.len() <= self.config.capacity && !expired {
+                break;
+            }
+            self.bytes -= oldest.bytes;
+            let dropped = self.entries.pop_front().expect("checked above");
+            if self.entries.is_empty() {
+                self.oldest = None;
+            } else if let Some(state) = &mut self.oldest {
+                state.apply(&dropped.diff);
+            }
+        }
+    }
+
+    pub fn latest_tick(&self) -> Option<u64> {
+        self.latest.as_ref().map(|(tick, _)| *tick)
+    }
+
+    /// Diffs that bring a client from `tick` to the latest tick, or `None`
+    /// when `tick` is no longer (or never was) in the history.
+    pub fn diffs_since(&self, tick: u64) -> Option<Vec<StateDiff>> {
+        if self.latest_tick() == Some(tick) {
+            return Some(Vec::new());
+        }
+        let start = self.entries.iter().position(|e| e.from_tick == tick)?;
+        Some(self.entries.iter().skip(start).map(|e| e.diff.clone()).collect())
+    }
+
+    /// The state broadcast at `tick` and the diffs that bring it to the
+    /// latest tick, or `None` when `tick` is no longer (or never was) in the
+    /// history.
+    pub fn since(&self, tick: u64) -> Option<(GameState, Vec<StateDiff>)> {
+        if let Some((latest, state)) = &self.latest
+            && *latest == tick
+        {
+            return Some((state.clone(), Vec::new()));
+        }
+        let start = self.entries.iter().position(|e| e.from_tick == tick)?;
+        let mut state = self.oldest.clone()?;
+        for entry in self.entries.iter().take(start) {
+            state.apply(&entry.diff);
+        }
+        Some((state, self.entries.iter().skip(start).map(|e| e.diff.clone()).collect()))
+    }
+
+    pub fn stats(&self) -> HistoryStats {
+        HistoryStats { entries: self.entries.len(), bytes: self.bytes }
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+
+/*
+Hashing for maps keyed by ids.
+
+The maps a position update goes through (the player shards, the planet
+index) and the others keyed by `ConnectionId` or a player or planet id
+are hashed with `IdHasher` rather than the standard library's SipHash.
+The keys are numbers the server hands out itself, so there is no one to
+guard against choosing keys that collide, which is what SipHash's cost
+buys. `IdHasher` multiplies the key by a large odd constant (Fibonacci
+hashing), one instruction where SipHash takes dozens, and the product
+spreads sequential ids over both the low bits the table indexes by and
+the high bits it tags entries with.
+
+Keys that are not plain integers still hash, a byte at a time, but the maps
+for names and other strings a client chooses keep SipHash.
+
+`cargo bench --bench positions` compares the two on position updates.
+*/
+
+/// 2^64 divided by the golden ratio, odd.
+const MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// A hasher for integer ids; see the module docs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(MULTIPLIER);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+}
+
+pub type BuildIdHasher = BuildHasherDefault<IdHasher>;
+
+/// A map keyed by ids, hashed with `IdHasher`.
+pub type IdMap<K, V> = HashMap<K, V, BuildIdHasher>;
+
+/// A set of ids, hashed with `IdHasher`.
+pub type IdSet<K> = HashSet<K, BuildIdHasher>;
@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+/*
+Per-player activity tracking.
+
+Only gameplay messages (position updates and chat) count as activity; time-sync
+requests and pings are sent automatically by clients and do not keep an
+otherwise absent player alive. Like the rate limiter, everything takes an
+explicit `now` so tests can step time deterministically.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleConfig {
+    pub idle_after: Duration,        // Duration::ZERO disables idle marking
+    pub disconnect_after: Duration,  // Duration::ZERO disables idle disconnects
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        IdleConfig {
+            idle_after: Duration::from_secs(5 * 60),
+            disconnect_after: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    BecameIdle,
+    BecameActive,
+    TimedOut,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActivityTracker {
+    config: IdleConfig,
+    last_activity: Instant,
+    idle: bool,
+}
+
+impl ActivityTracker {
+    pub fn new(config: IdleConfig, now: Instant) -> Self {
+        ActivityTracker {
+            config,
+            last_activity: now,
+            idle: false,
+        }
+    }
+
+    /// Applies new thresholds, keeping the time of the last activity.
+    pub fn set_config(&mut self, config: IdleConfig) {
+        self.config = config;
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Records a gameplay message; returns `BecameActive` if the player was idle.
+    pub fn record_activity(&mut self, now: Instant) -> Option<IdleEvent> {
+        self.last_activity = now;
+        if self.idle {
+            self.idle = false;
+            Some(IdleEvent::BecameActive)
+        } else {
+            None
+        }
+    }
+
+    /// Checks the thresholds; call periodically.
+    pub fn poll(&mut self, now: Instant) -> Option<IdleEvent> {
+        let quiet_for = now.saturating_duration_since(self.last_activity);
+
+        if !self.config.disconnect_after.is_zero() && quiet_for >= self.config.disconnect_after {
+            return Some(IdleEvent::TimedOut);
+        }
+        if !self.idle && !self.config.idle_after.is_zero() && quiet_for >= self.config.idle_after {
+            self.idle = true;
+            return Some(IdleEvent::BecameIdle);
+        }
+        None
+    }
+}
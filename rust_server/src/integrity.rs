@@ -0,0 +1,98 @@
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::tungstenite::Bytes;
+
+/*
+Optional CRC32 trailers on binary frames, for telling corruption in transit
+from a sender encoding the wrong thing.
+
+A client that asks for the CRC capability (see `Capabilities`) and is
+granted it gets, and must send, every binary frame with four more bytes at
+the end: the CRC-32 (IEEE, as in zlib and Ethernet) of everything before
+them, little-endian. Text frames are left alone. The receiver checks and
+strips the trailer before decoding; a frame whose trailer does not match is
+dropped and reported instead of being decoded. The server answers such a
+frame with a `CorruptFrame { expected, got }` and counts it in the
+connection's stats (see `stats`); the client library turns one from the
+server into `ClientEvent::CorruptFrame`. `expected` is the CRC the frame
+carried and `got` the one computed over what arrived.
+
+Clients that do not ask, which is every client by default, see no
+difference.
+*/
+
+pub const CRC_LEN: usize = 4;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// A frame whose trailer does not match its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorruptFrame {
+    pub expected: u32,  // carried by the frame
+    pub got: u32,       // computed over what arrived
+}
+
+impl std::fmt::Display for CorruptFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "corrupt frame: CRC {:08x} expected, {:08x} computed", self.expected, self.got)
+    }
+}
+
+impl std::error::Error for CorruptFrame {}
+
+/// `payload` with its CRC appended.
+pub fn seal(payload: &[u8]) -> Bytes {
+    let mut frame = Vec::with_capacity(payload.len() + CRC_LEN);
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc32(payload).to_le_bytes());
+    frame.into()
+}
+
+/// The payload of a sealed frame, if its CRC matches. A frame too short to
+/// hold a CRC is always corrupt.
+pub fn open(frame: &[u8]) -> Result<&[u8], CorruptFrame> {
+    let Some(split) = frame.len().checked_sub(CRC_LEN) else {
+        return Err(CorruptFrame { expected: 0, got: crc32(&[]) });
+    };
+    let (payload, trailer) = frame.split_at(split);
+    let expected = u32::from_le_bytes(trailer.try_into().expect("CRC_LEN bytes"));
+    let got = crc32(payload);
+    if expected == got { Ok(payload) } else { Err(CorruptFrame { expected, got }) }
+}
+
+/// Seals binary frames and passes everything else through.
+pub fn seal_message(message: Message) -> Message {
+    match message {
+        Message::Binary(payload) => Message::Binary(seal(&payload)),
+        other => other,
+    }
+}
+
+/// Opens binary frames and passes everything else through.
+pub fn open_message(message: Message) -> Result<Message, CorruptFrame> {
+    match message {
+        Message::Binary(frame) => {
+            let len = open(&frame)?.len();
+            Ok(Message::Binary(frame.slice(..len)))
+        }
+        other => Ok(other),
+    }
+}
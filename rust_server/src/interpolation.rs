@@ -0,0 +1,99 @@
+use std::collections::{HashMap, VecDeque};
+use crate::protocol::Position;
+
+/*
+Smooths remote player movement between state broadcasts.
+
+Positions are recorded against the broadcast's `server_time_ms`; rendering
+asks for a position at a render time slightly behind the newest snapshot so
+there is usually a snapshot on either side to interpolate between. When data
+is late, motion is extrapolated from the last two snapshots, but never more
+than `max_extrapolation_ms` past the newest one.
+*/
+
+pub const DEFAULT_HISTORY: usize = 8;
+pub const DEFAULT_MAX_EXTRAPOLATION_MS: u64 = 250;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub time_ms: u64,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone)]
+pub struct Interpolator {
+    history: usize,
+    max_extrapolation_ms: u64,
+    players: HashMap<u32, VecDeque<Snapshot>>,
+}
+
+impl Default for Interpolator {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY, DEFAULT_MAX_EXTRAPOLATION_MS)
+    }
+}
+
+impl Interpolator {
+    pub fn new(history: usize, max_extrapolation_ms: u64) -> Self {
+        Interpolator {
+            history: history.max(2),
+            max_extrapolation_ms,
+            players: HashMap::new(),
+        }
+    }
+
+    /// Records a position; snapshots not newer than the latest one are ignored.
+    pub fn push(&mut self, player_id: u32, time_ms: u64, position: Position) {
+        let snapshots = self.players.entry(player_id).or_default();
+        if snapshots.back().is_some_and(|last| time_ms <= last.time_ms) {
+            return;
+        }
+        if snapshots.len() == self.history {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(Snapshot { time_ms, position });
+    }
+
+    /// Forgets players for which `keep` returns false (e.g. after they leave).
+    pub fn retain_players(&mut self, mut keep: impl FnMut(u32) -> bool) {
+        self.players.retain(|id, _| keep(*id));
+    }
+
+    pub fn player_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.players.keys().copied()
+    }
+
+    pub fn position_at(&self, player_id: u32, render_time_ms: u64) -> Option<Position> {
+        let snapshots = self.players.get(&player_id)?;
+        let first = snapshots.front()?;
+        let last = snapshots.back()?;
+
+        // With a single snapshot first == last, so there is nothing to blend
+        if snapshots.len() == 1 || render_time_ms <= first.time_ms {
+            return Some(first.position.clone());
+        }
+
+        if render_time_ms >= last.time_ms {
+            let prev = &snapshots[snapshots.len() - 2];
+            let ahead = (render_time_ms - last.time_ms).min(self.max_extrapolation_ms);
+            let t = 1.0 + ahead as f32 / (last.time_ms - prev.time_ms) as f32;
+            return Some(lerp(&prev.position, &last.position, t));
+        }
+
+        // Find the pair of snapshots surrounding the render time
+        let (a, b) = snapshots
+            .iter()
+            .zip(snapshots.iter().skip(1))
+            .find(|(_, b)| b.time_ms >= render_time_ms)?;
+        let t = (render_time_ms - a.time_ms) as f32 / (b.time_ms - a.time_ms) as f32;
+        Some(lerp(&a.position, &b.position, t))
+    }
+}
+
+fn lerp(a: &Position, b: &Position, t: f32) -> Position {
+    Position {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        z: a.z + (b.z - a.z) * t,
+    }
+}
@@ -0,0 +1,313 @@
+use serde::{Serialize, Deserialize};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use crate::discovery::{level_for, DISCOVERY_XP};
+use crate::events::{EventSink, EventTime, GameEvent};
+use crate::protocol::{GameState, Planet, Player, PlayerAppearance, Position};
+use crate::snapshot::GameStateSnapshot;
+
+/*
+Append-only journal of every state-mutating event, for debugging desyncs.
+
+The file is a sequence of records, each a little-endian u32 length followed by
+that many bytes of bincode `JournalRecord`. The first record is the world as
+it was when journaling started; replaying the rest with `apply_event` in order
+rebuilds the server's `GameState` at any tick (see the `replay` binary).
+The journal is attached to the server's event bus and records the events
+that change the state (see `events`).
+
+Writing happens on a dedicated task behind an unbounded channel, so recording
+an event never waits on disk. Whole worlds (`WorldCreated` and `WorldReset`)
+are sent as a `GameStateSnapshot` and copied into their event on that task,
+so the copying does not hold up the game either (see `snapshot`). `JournalWriter::shutdown` drains the channel and
+flushes the file; call it on graceful shutdown or the tail of the journal may
+be lost.
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum JournalEvent {
+    WorldCreated { state: GameState },
+    PlayerJoined { player: Player },
+    PlayerLeft { player_id: u32 },
+    PositionUpdated {
+        player_id: u32,
+        seq: Option<u32>,
+        position: Position,
+        velocity: [f32; 3],
+        rotation: [f32; 4],
+    },
+    IdleChanged { player_id: u32, idle: bool },
+    Chat { player_id: u32, text: String },
+    PlanetAdded { planet: Planet },
+    PlanetRemoved { planet_id: u32 },
+    PlanetUpdated { planet: Planet },
+    AppearanceChanged { player_id: u32, appearance: PlayerAppearance },
+    PlayerRenamed { player_id: u32, name: String },
+    PlanetDiscovered { player_id: u32, planet_id: u32 },
+    /// Landed on a planet, took off, or moved with the planet landed on
+    LandingChanged { player_id: u32, landed_on: Option<u32>, position: Position },
+    /// The admin regenerated the world; `state` is all of it afterwards
+    WorldReset { state: GameState },
+    /// XP earned other than by discovering, e.g. by mining
+    XpEarned { player_id: u32, xp: u32 },
+    /// `seller` traded `resources` units of cargo to `buyer` for `credits`
+    /// (see `trade`); wallets are not part of the state but kept apart
+    /// (see `wallets`)
+    Traded { seller: u32, buyer: u32, resources: u32, credits: u64 },
+    /// A notable asteroid was mined down to `resources`
+    AsteroidMined { asteroid_id: u32, resources: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalRecord {
+    pub tick: u64,
+    pub server_time_ms: u64,
+    pub unix_ms: u64,
+    pub event: JournalEvent,
+}
+
+/// Applies one event to a state exactly as the server does.
+pub fn apply_event(state: &mut GameState, event: &JournalEvent) {
+    match event {
+        JournalEvent::WorldCreated { state: world } | JournalEvent::WorldReset { state: world } => *state = world.clone(),
+        JournalEvent::PlayerJoined { player } => state.players.push(player.clone()),
+        JournalEvent::PlayerLeft { player_id } => state.players.retain(|p| p.id != *player_id),
+        JournalEvent::PositionUpdated { player_id, seq, position, velocity, rotation } => {
+            if let Some(player) = state.players.iter_mut().find(|p| p.id == *player_id) {
+                player.position = position.clone();
+                player.velocity = *velocity;
+                player.rotation = *rotation;
+                if let Some(seq) = seq {
+                    player.last_processed_seq = *seq;
+                }
+            }
+        }
+        JournalEvent::IdleChanged { player_id, idle } => {
+            if let Some(player) = state.players.iter_mut().find(|p| p.id == *player_id) {
+                player.idle = *idle;
+            }
+        }
+        JournalEvent::Chat { .. } | JournalEvent::Traded { .. } => {}
+        JournalEvent::PlanetAdded { planet } => state.planets.push(planet.clone()),
+        JournalEvent::PlanetRemoved { planet_id } => state.planets.retain(|p| p.id != *planet_id),
+        JournalEvent::PlanetUpdated { planet } => {
+            if let Some(entry) = state.planet_by_id_mut(planet.id) {
+                *entry = planet.clone();
+            }
+        }
+        JournalEvent::AppearanceChanged { player_id, appearance } => {
+            if let Some(player) = state.players.iter_mut().find(|p| p.id == *player_id) {
+                player.appearance = appearance.clone();
+            }
+        }
+        JournalEvent::PlayerRenamed { player_id, name } => {
+            if let Some(player) = state.players.iter_mut().find(|p| p.id == *player_id) {
+                player.name = name.as_str().into();
+            }
+        }
+        JournalEvent::LandingChanged { player_id, landed_on, position } => {
+            if let Some(player) = state.players.iter_mut().find(|p| p.id == *player_id) {
+                player.landed_on = *landed_on;
+                player.position = position.clone();
+                player.velocity = [0.0; 3];
+            }
+        }
+        JournalEvent::PlanetDiscovered { player_id, .. } => {
+            if let Some(player) = state.players.iter_mut().find(|p| p.id == *player_id) {
+                player.xp += DISCOVERY_XP;
+                player.level = level_for(player.xp);
+            }
+        }
+        JournalEvent::XpEarned { player_id, xp } => {
+            if let Some(player) = state.players.iter_mut().find(|p| p.id == *player_id) {
+                player.xp += xp;
+                player.level = level_for(player.xp);
+            }
+        }
+        JournalEvent::AsteroidMined { asteroid_id, resources } => {
+            if let Some(asteroid) = state.belts.iter_mut().flat_map(|b| b.notable.iter_mut()).find(|a| a.id == *asteroid_id) {
+                asteroid.resources = *resources;
+            }
+        }
+    }
+}
+
+impl JournalEvent {
+    /// How `event` is journaled, if it changes the state (chat aside).
+    pub fn from_game(event: &GameEvent) -> Option<JournalEvent> {
+        Some(match event.clone() {
+            GameEvent::PlayerJoined { player } => JournalEvent::PlayerJoined { player },
+            GameEvent::PlayerLeft { player_id, .. } => JournalEvent::PlayerLeft { player_id },
+            GameEvent::PlayerMoved { player_id, seq, position, velocity, rotation } => {
+                JournalEvent::PositionUpdated { player_id, seq, position, velocity, rotation }
+            }
+            GameEvent::IdleChanged { player_id, idle } => JournalEvent::IdleChanged { player_id, idle },
+            GameEvent::Chatted { player_id, text, .. } => JournalEvent::Chat { player_id, text },
+            GameEvent::AppearanceChanged { player_id, appearance } => JournalEvent::AppearanceChanged { player_id, appearance },
+            GameEvent::PlayerRenamed { player_id, name } => JournalEvent::PlayerRenamed { player_id, name },
+            GameEvent::LandingChanged { player_id, landed_on, position } => JournalEvent::LandingChanged { player_id, landed_on, position },
+            GameEvent::PlanetDiscovered { player_id, planet_id } => JournalEvent::PlanetDiscovered { player_id, planet_id },
+            GameEvent::XpEarned { player_id, xp } => JournalEvent::XpEarned { player_id, xp },
+            GameEvent::PlanetAdded { planet } => JournalEvent::PlanetAdded { planet },
+            GameEvent::PlanetRemoved { planet_id } => JournalEvent::PlanetRemoved { planet_id },
+            GameEvent::PlanetUpdated { planet } => JournalEvent::PlanetUpdated { planet },
+            GameEvent::Traded { seller, buyer, resources, credits } => JournalEvent::Traded { seller, buyer, resources, credits },
+            GameEvent::AsteroidMined { asteroid_id, resources } => JournalEvent::AsteroidMined { asteroid_id, resources },
+            GameEvent::WorldReset { world } => JournalEvent::WorldReset { state: world.to_state() },
+            _ => return None,
+        })
+    }
+}
+
+/// A record on its way to the writer task.
+#[derive(Debug)]
+enum Pending {
+    Record(JournalRecord),
+    /// A `WorldCreated`, or with `reset` a `WorldReset`, still to be copied
+    /// out of its snapshot
+    World { tick: u64, server_time_ms: u64, unix_ms: u64, snapshot: Arc<GameStateSnapshot>, reset: bool },
+}
+
+impl Pending {
+    fn into_record(self) -> JournalRecord {
+        match self {
+            Pending::Record(record) => record,
+            Pending::World { tick, server_time_ms, unix_ms, snapshot, reset } => {
+                let state = snapshot.to_state();
+                let event = if reset { JournalEvent::WorldReset { state } } else { JournalEvent::WorldCreated { state } };
+                JournalRecord { tick, server_time_ms, unix_ms, event }
+            }
+        }
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Cheap, cloneable handle for recording events.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    tx: mpsc::UnboundedSender<Pending>,
+}
+
+impl Journal {
+    pub fn record(&self, tick: u64, server_time_ms: u64, event: JournalEvent) {
+        // Fails only after shutdown, when there is nowhere left to write
+        let _ = self.tx.send(Pending::Record(JournalRecord { tick, server_time_ms, unix_ms: unix_ms(), event }));
+    }
+
+    /// Records the whole world as `WorldCreated`, or as `WorldReset` if
+    /// `reset`, copying it from `snapshot` on the writer task.
+    pub fn record_world(&self, tick: u64, server_time_ms: u64, snapshot: Arc<GameStateSnapshot>, reset: bool) {
+        let _ = self.tx.send(Pending::World { tick, server_time_ms, unix_ms: unix_ms(), snapshot, reset });
+    }
+}
+
+impl EventSink for Journal {
+    fn consume(&self, at: EventTime, event: &GameEvent) {
+        let EventTime { tick, server_time_ms, unix_ms } = at;
+        // Worlds are copied on the writer task
+        let pending = match event {
+            GameEvent::WorldReset { world } => Pending::World { tick, server_time_ms, unix_ms, snapshot: world.clone(), reset: true },
+            _ => match JournalEvent::from_game(event) {
+                Some(event) => Pending::Record(JournalRecord { tick, server_time_ms, unix_ms, event }),
+                None => return,
+            },
+        };
+        let _ = self.tx.send(pending);
+    }
+}
+
+/// Owns the writer task; keep it around until shutdown.
+pub struct JournalWriter {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<io::Result<()>>,
+}
+
+impl JournalWriter {
+    /// Writes out everything recorded so far, flushes and closes the file.
+    pub async fn shutdown(self) -> io::Result<()> {
+        let _ = self.shutdown.send(());
+        self.task.await.map_err(io::Error::other)?
+    }
+}
+
+/// Creates (or appends to) the journal at `path` and starts its writer task.
+pub async fn open(path: impl AsRef<Path>) -> io::Result<(Journal, JournalWriter)> {
+    let file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<Pending>();
+    let (shutdown, mut shutdown_rx) = oneshot::channel();
+
+    let task = tokio::spawn(async move {
+        let mut out = BufWriter::new(file);
+        loop {
+            tokio::select! {
+                record = rx.recv() => match record {
+                    Some(pending) => write_record(&mut out, &pending.into_record()).await?,
+                    None => break,
+                },
+                _ = &mut shutdown_rx => {
+                    while let Ok(pending) = rx.try_recv() {
+                        write_record(&mut out, &pending.into_record()).await?;
+                    }
+                    break;
+                }
+            }
+        }
+        out.flush().await
+    });
+
+    Ok((Journal { tx }, JournalWriter { shutdown, task }))
+}
+
+async fn write_record(out: &mut BufWriter<tokio::fs::File>, record: &JournalRecord) -> io::Result<()> {
+    let bytes = bincode::serialize(record).map_err(io::Error::other)?;
+    out.write_all(&(bytes.len() as u32).to_le_bytes()).await?;
+    out.write_all(&bytes).await
+}
+
+/// Reads every record; a truncated final record (e.g. after a crash) ends the journal.
+pub fn read_journal(mut input: impl Read) -> io::Result<Vec<JournalRecord>> {
+    let mut records = Vec::new();
+    loop {
+        let mut len = [0u8; 4];
+        if !read_full(&mut input, &mut len)? {
+            break;
+        }
+        let Some(bytes) = read_exact_len(&mut input, u32::from_le_bytes(len))? else { break };
+        records.push(bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+    }
+    Ok(records)
+}
+
+/// Reads `len` bytes, or `None` if the input ended first. The buffer grows
+/// with what is actually read, so a corrupt length cannot allocate gigabytes.
+fn read_exact_len(input: &mut impl Read, len: u32) -> io::Result<Option<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    Ok((bytes.len() == len as usize).then_some(bytes))
+}
+
+/// Fills `buf`, returning false if the input ended first.
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match input.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// The state after applying every record up to and including `tick`.
+pub fn replay_until(records: &[JournalRecord], tick: u64) -> GameState {
+    let mut state = GameState::new(Vec::new(), Vec::new(), Position { x: 0.0, y: 0.0, z: 0.0 });
+    for record in records.iter().take_while(|r| r.tick <= tick) {
+        apply_event(&mut state, &record.event);
+    }
+    state
+}
@@ -0,0 +1,130 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::lifetime::replace_file;
+use crate::protocol::normalize_name;
+
+/*
+Records by player name, kept in a JSON file: what mutes, roles and wallets
+have in common (see `mutes`, `roles` and `wallets`).
+
+Records belong to names, compared by `normalize_name` as bans are, so
+`Ada` and `ada` share one. The file is a JSON object of every record by
+normalized name, rewritten whole with `replace_file` when its owner saves;
+a missing file is no records, created on the first save, and one that
+cannot be read is an error rather than an empty store. Changes stand in
+memory until saved, so an owner changing several records saves them all
+at once. Without a file the records last until the server stops.
+*/
+
+#[derive(Debug, Clone)]
+pub struct JsonStore<K, V> {
+    path: Option<PathBuf>,
+    records: BTreeMap<K, V>,  // by normalized name
+}
+
+impl<K, V> Default for JsonStore<K, V> {
+    fn default() -> Self {
+        JsonStore { path: None, records: BTreeMap::new() }
+    }
+}
+
+impl<K, V> JsonStore<K, V>
+where
+    K: Ord + Borrow<str> + From<String> + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    /// No records, kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The records in `path`, saved back there from now on.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let records = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(JsonStore { path: Some(path.to_path_buf()), records })
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&V> {
+        self.records.get(normalize_name(name).as_str())
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut V> {
+        self.records.get_mut(normalize_name(name).as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.records.contains_key(normalize_name(name).as_str())
+    }
+
+    /// Sets `name`'s record, returning the one it replaced.
+    pub fn insert(&mut self, name: &str, record: V) -> Option<V> {
+        self.records.insert(normalize_name(name).into(), record)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<V> {
+        self.records.remove(normalize_name(name).as_str())
+    }
+
+    /// Moves `old`'s record, if it has one, to `new`, unless `new` has one of
+    /// its own; whether it moved.
+    pub fn rename(&mut self, old: &str, new: &str) -> bool {
+        let (old, new) = (normalize_name(old), normalize_name(new));
+        if old == new || self.records.contains_key(new.as_str()) {
+            return false;
+        }
+        let Some(record) = self.records.remove(old.as_str()) else { return false };
+        self.records.insert(new.into(), record);
+        true
+    }
+
+    /// Keeps only the records `keep` accepts; whether any were dropped.
+    pub fn retain(&mut self, mut keep: impl FnMut(&V) -> bool) -> bool {
+        let before = self.records.len();
+        self.records.retain(|_, record| keep(record));
+        self.records.len() != before
+    }
+
+    /// Every record, in normalized name order.
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.records.values()
+    }
+
+    /// Where the records are saved, if anywhere.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The file's text: every record by normalized name, as pretty JSON.
+    pub fn to_file_text(&self) -> String {
+        serde_json::to_string_pretty(&self.records).expect("records are plain JSON")
+    }
+
+    /// Writes every record to the file, if there is one.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        replace_file(path, &self.to_file_text())
+    }
+}
+
+impl<K: Clone, V: Clone> JsonStore<K, V> {
+    /// A copy that changes without saving, to see what a change would write.
+    pub fn detached(&self) -> Self {
+        JsonStore { path: None, records: self.records.clone() }
+    }
+}
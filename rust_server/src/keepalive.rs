@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+/*
+Application-level keepalives, for connections that die without closing.
+
+Some proxies and mobile networks drop an idle TCP connection without a RST,
+and WebSocket pings do not always make it through intermediaries, so such a
+connection can look open to the server for a long time. Clients with the
+KEEPALIVE capability are therefore sent a `Keepalive { seq }` every
+`interval` they have been silent, `seq` counting up from 1, and answer each
+with `KeepaliveAck { seq }`. Any frame from the client puts the next
+keepalive off, so a client that keeps sending updates never sees one, but
+only an ack of the latest keepalive answers it; acks of older ones are
+ignored. Once `misses` keepalives in a row go unanswered the connection is
+closed with `KeepaliveTimeout`, through the same cleanup as any other
+disconnect.
+
+The other way around, the server answers a client's own `Keepalive` with a
+`KeepaliveAck` of the same `seq` whatever its capabilities, so clients can
+check the path to the server the same way. Like the rate limiter,
+everything takes an explicit `now`.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    pub interval: Duration,  // of silence before each keepalive
+    pub misses: u32,         // unanswered keepalives in a row before giving up
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        KeepaliveConfig { interval: Duration::from_secs(1), misses: 3 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveAction {
+    Send { seq: u32 },
+    TimedOut,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeepaliveTracker {
+    config: KeepaliveConfig,
+    last_heard: Instant,
+    last_sent: Option<Instant>,
+    seq: u32,          // of the latest keepalive sent; 0 before the first
+    awaiting: bool,    // the latest one is unanswered
+    missed: u32,
+}
+
+impl KeepaliveTracker {
+    pub fn new(config: KeepaliveConfig, now: Instant) -> Self {
+        KeepaliveTracker { config, last_heard: now, last_sent: None, seq: 0, awaiting: false, missed: 0 }
+    }
+
+    /// Records any frame from the client, putting off the next keepalive.
+    pub fn heard(&mut self, now: Instant) {
+        self.last_heard = now;
+    }
+
+    /// Records a `KeepaliveAck`; only one for the latest keepalive counts.
+    pub fn acked(&mut self, seq: u32) {
+        if self.awaiting && seq == self.seq {
+            self.awaiting = false;
+            self.missed = 0;
+        }
+    }
+
+    /// Unanswered keepalives in a row so far.
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+
+    /// Whether a keepalive is due or the client is gone; call periodically.
+    pub fn poll(&mut self, now: Instant) -> Option<KeepaliveAction> {
+        let quiet_since = self.last_sent.map_or(self.last_heard, |sent| sent.max(self.last_heard));
+        if now.saturating_duration_since(quiet_since) < self.config.interval {
+            return None;
+        }
+        if self.awaiting {
+            self.missed += 1;
+            if self.missed >= self.config.misses {
+                return Some(KeepaliveAction::TimedOut);
+            }
+        }
+        self.seq = self.seq.wrapping_add(1);
+        self.awaiting = true;
+        self.last_sent = Some(now);
+        Some(KeepaliveAction::Send { seq: self.seq })
+    }
+}
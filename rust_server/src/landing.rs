@@ -0,0 +1,64 @@
+use crate::protocol::{speed, LandError, Planet, Position};
+
+/*
+Landing on planets, as opposed to flying near them.
+
+A player lands with `Land { planet_id }` while within `LANDING_RANGE` of the
+planet's surface (its size is a diameter) and moving no faster than
+`MAX_LANDING_SPEED`, going by the velocity of their last position update,
+and the planet is not guarded by someone else's Defense planet (see
+`module_effects`).
+They are then pinned to the point of the surface nearest them: their
+`landed_on` is the planet's id, their position that point, at rest, and
+their position updates are ignored until `TakeOff`. Respawning or
+teleporting takes off on the way.
+
+Planets do not orbit or spin, so a landed player stays put unless an admin
+moves or resizes the planet; they then move with it, to the same spot on
+its surface (`follow`). Removing the planet leaves them floating where they
+were, taken off.
+*/
+
+/// Furthest from a planet's surface, inside or out, a player may land.
+pub const LANDING_RANGE: f32 = 25.0;
+
+/// Fastest a player may be moving to land, in units per second.
+pub const MAX_LANDING_SPEED: f32 = 20.0;
+
+/// The point of `planet`'s surface nearest `position`; the top of the
+/// planet for a position at its very centre.
+pub fn surface_point(planet: &Planet, position: &Position) -> Position {
+    let centre = &planet.position;
+    let (dx, dy, dz) = (position.x - centre.x, position.y - centre.y, position.z - centre.z);
+    let length = (dx * dx + dy * dy + dz * dz).sqrt();
+    let (dx, dy, dz) = if length > f32::EPSILON { (dx / length, dy / length, dz / length) } else { (0.0, 1.0, 0.0) };
+    let radius = planet.size / 2.0;
+    Position { x: centre.x + dx * radius, y: centre.y + dy * radius, z: centre.z + dz * radius }
+}
+
+/// Where a player at `position` moving at `velocity` would stand on
+/// `planet`, if they are close and slow enough to land.
+pub fn try_land(planet: &Planet, position: &Position, velocity: &[f32; 3]) -> Result<Position, LandError> {
+    let centre = &planet.position;
+    let (dx, dy, dz) = (position.x - centre.x, position.y - centre.y, position.z - centre.z);
+    let distance = ((dx * dx + dy * dy + dz * dz).sqrt() - planet.size / 2.0).abs();
+    if distance > LANDING_RANGE {
+        return Err(LandError::TooFar { distance });
+    }
+    let speed = speed(velocity);
+    if speed > MAX_LANDING_SPEED {
+        return Err(LandError::TooFast { speed });
+    }
+    Ok(surface_point(planet, position))
+}
+
+/// Where a player standing at `position` on `before` stands once the planet
+/// has become `after`: the same spot, relative to its centre, on its surface.
+pub fn follow(before: &Planet, after: &Planet, position: &Position) -> Position {
+    let moved = Position {
+        x: position.x - before.position.x + after.position.x,
+        y: position.y - before.position.y + after.position.y,
+        z: position.z - before.position.z + after.position.z,
+    };
+    surface_point(after, &moved)
+}
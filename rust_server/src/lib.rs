@@ -0,0 +1,79 @@
+pub mod accept_guard;
+pub mod admin;
+pub mod ambient;
+pub mod announce;
+pub mod appearance;
+pub mod bandwidth;
+pub mod bans;
+pub mod broadcast;
+pub mod capture;
+pub mod chat_history;
+pub mod chat_receipts;
+pub mod client;
+#[cfg(feature = "cluster")]
+pub mod cluster;
+pub mod color;
+pub mod config;
+pub mod connection_state;
+pub mod diff;
+pub mod discovery;
+pub mod economy;
+pub mod events;
+pub mod fakes;
+pub mod friends;
+pub mod handshake;
+pub mod hibernate;
+pub mod history;
+pub mod id_hash;
+pub mod idle;
+pub mod integrity;
+pub mod interpolation;
+pub mod journal;
+pub mod json_store;
+pub mod keepalive;
+pub mod landing;
+pub mod lifetime;
+pub mod locale;
+pub mod map;
+pub mod module_effects;
+pub mod multi;
+pub mod mutes;
+pub mod ordering;
+pub mod output;
+pub mod palette;
+pub mod pause;
+pub mod planet_names;
+pub mod players;
+pub mod population;
+pub mod prediction;
+pub mod prune;
+pub mod protocol;
+pub mod quantize;
+pub mod query;
+pub mod rate_limit;
+pub mod readiness;
+pub mod replication;
+pub mod roles;
+pub mod schema;
+pub mod server;
+pub mod session;
+pub mod snapshot;
+pub mod spawn;
+pub mod startup;
+pub mod stats;
+pub mod subscriptions;
+pub mod teleport;
+pub mod time_sync;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod trade;
+pub mod traffic;
+pub mod trail;
+pub mod validate;
+pub mod wallets;
+pub mod webhooks;
+pub mod world;
+pub mod world_clock;
+pub mod world_file;
+pub mod world_info;
+pub mod worldgen;
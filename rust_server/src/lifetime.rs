@@ -0,0 +1,149 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/*
+Statistics over the server's whole life, kept across restarts in the file
+given as `stats_file`.
+
+`ServerStats` counts every player name ever seen (by `normalize_name`, as
+bans do, so one pilot writing their name two ways is one player), the most
+players online at once and when that was, the chat messages sent, the
+distance flown and how long the server has been up, all its runs together.
+Fake players count for none of it. Distance is added when a session ends
+(see `session`), the rest as it happens.
+
+The file is JSON, saved every `SAVE_INTERVAL` while the server runs and
+once more when it shuts down cleanly, after every session has ended; a
+crash loses at most the last interval. A missing file starts the stats from
+nothing; so does one that cannot be read, with a warning, after setting it
+aside as `<stats_file>.corrupt` so the next save does not destroy it.
+Without a file the stats last until the server stops.
+
+The admin `stats` command shows them. There is one world per server, so
+there are no per-world stats. Like the session tracker, everything takes an
+explicit `now`.
+*/
+
+/// How often the stats are saved while the server runs.
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerStats {
+    pub players_seen: BTreeSet<String>,  // normalized names
+    pub peak_players: usize,
+    pub peak_at_unix_ms: u64,
+    pub chat_messages: u64,
+    pub distance: f64,
+    pub uptime_ms: u64,
+}
+
+impl std::fmt::Display for ServerStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "players_seen={} peak={} peak_at_unix_ms={} chat_messages={} distance={:.1} uptime={}s",
+               self.players_seen.len(), self.peak_players, self.peak_at_unix_ms, self.chat_messages, self.distance, self.uptime_ms / 1000)
+    }
+}
+
+/// The stats, where they are saved, and the uptime not yet added to them.
+#[derive(Debug, Clone)]
+pub struct LifetimeStats {
+    path: Option<PathBuf>,
+    stats: ServerStats,
+    counted_until: Instant,  // uptime is in `stats` up to here
+}
+
+impl LifetimeStats {
+    /// Stats from nothing, kept in memory only.
+    pub fn new(now: Instant) -> Self {
+        LifetimeStats { path: None, stats: ServerStats::default(), counted_until: now }
+    }
+
+    /// The stats saved in `path`, saved back there from now on; see the
+    /// module comment for a missing or unreadable file.
+    pub fn load(path: impl AsRef<Path>, now: Instant) -> Self {
+        let path = path.as_ref();
+        let stats = match std::fs::read_to_string(path) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(stats) => stats,
+                Err(e) => {
+                    set_aside(path, "stats", &e.to_string());
+                    ServerStats::default()
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ServerStats::default(),
+            Err(e) => {
+                set_aside(path, "stats", &e.to_string());
+                ServerStats::default()
+            }
+        };
+        LifetimeStats { path: Some(path.to_path_buf()), stats, counted_until: now }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The stats as of `now`.
+    pub fn snapshot(&mut self, now: Instant) -> ServerStats {
+        self.count_uptime(now);
+        self.stats.clone()
+    }
+
+    /// Counts the player `name` joining, with `online` players now on, at
+    /// `unix_ms`.
+    pub fn joined(&mut self, name: &str, online: usize, unix_ms: u64) {
+        if !self.stats.players_seen.contains(name) {
+            self.stats.players_seen.insert(name.to_string());
+        }
+        if online > self.stats.peak_players {
+            self.stats.peak_players = online;
+            self.stats.peak_at_unix_ms = unix_ms;
+        }
+    }
+
+    pub fn chatted(&mut self) {
+        self.stats.chat_messages += 1;
+    }
+
+    /// Adds what a finished session flew.
+    pub fn traveled(&mut self, distance: f64) {
+        self.stats.distance += distance;
+    }
+
+    /// Writes the stats as of `now` to the file, if there is one. The file
+    /// is replaced whole, so a crash mid-save leaves the last one intact.
+    pub fn save(&mut self, now: Instant) -> io::Result<()> {
+        self.count_uptime(now);
+        let Some(path) = &self.path else { return Ok(()) };
+        replace_file(path, &serde_json::to_string_pretty(&self.stats)?)
+    }
+
+    fn count_uptime(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.counted_until);
+        self.stats.uptime_ms += elapsed.as_millis() as u64;
+        self.counted_until = now;
+    }
+}
+
+/// Writes `contents` to `path` by way of a file beside it, so a crash
+/// mid-write leaves the old one intact.
+pub(crate) fn replace_file(path: &Path, contents: &str) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, path)
+}
+
+/// Moves an unreadable file of `what` out of the way, saying so.
+pub(crate) fn set_aside(path: &Path, what: &str, error: &str) {
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(".corrupt");
+    let moved = match std::fs::rename(path, &aside) {
+        Ok(()) => format!("moved to {}", Path::new(&aside).display()),
+        Err(e) => format!("could not be moved aside: {}", e),
+    };
+    println!("⚠️  Unreadable {} in {} ({}), {}; starting from nothing", what, path.display(), error, moved);
+}
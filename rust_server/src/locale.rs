@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use crate::protocol::{ErrorCode, MessageCode, ServerMessage};
+
+/*
+What players are told, by code rather than in English, so a client can say
+it in its own language.
+
+The server sends its notices to players as `Localized` messages: a
+`MessageCode` with its parameters, plus the English text for clients that
+do not translate. A `MessageTable` maps each code to a template, English
+by default, and `render` fills in the parameters: `{reason}` stands for
+the `reason` parameter, and `{{` and `}}` are literal braces, as in
+announcement templates (see `announce`). An embedder swaps in its own
+translations with `set`, which checks the template against the code's
+parameters, and hands the table to its `Connection` (see
+`Connection::set_message_table`). Codes a table has no translation for
+fall back to English.
+
+The close reasons share the table under their `ErrorCode::name`, so
+`render_error(ErrorCode::Kicked)` is "Kicked by an admin." until it is
+translated. `ENGLISH` is the registry of every code, its parameters and
+its English text; the codes are documented on `MessageCode`.
+*/
+
+/// Every code, the parameters its template may use, and its English text.
+pub const ENGLISH: &[(&str, &[&str], &str)] = &[
+    ("WELCOME", &[], "Welcome to Crux Server!"),
+    ("WELCOME_SPECTATOR", &[], "Welcome to Crux Server! You are spectating."),
+    ("INVALID_MESSAGE", &["reason"], "Invalid message: {reason}"),
+    ("FORMAT_LOCKED", &[], "The format can only be chosen by the first message."),
+    ("RATE_LIMITED", &["retry_after_ms"], "Slow down! You are sending messages too quickly."),
+    ("PLANET_QUERY_RATE_LIMITED", &["retry_after_ms"], "Slow down! You are querying planets too quickly."),
+    ("TRAIL_QUERY_RATE_LIMITED", &["retry_after_ms"], "Slow down! You are querying trails too quickly."),
+    ("HISTORY_RATE_LIMITED", &["retry_after_ms"], "Slow down! You are asking for chat history too quickly."),
+    ("PLANET_QUERY_REJECTED", &["reason"], "Planet query rejected: {reason}"),
+    ("TRAIL_QUERY_REJECTED", &["reason"], "Trail query rejected: {reason}"),
+    ("SPECTATOR_READ_ONLY", &[], "Spectators cannot move, chat or change the world."),
+    ("CHAT_MUTED", &[], "You are muted and cannot chat."),
+    ("SERVER_FULL", &[], "The server is full. Try again in a little while."),
+    ("TOO_MANY_CONNECTIONS", &[], "Too many connections from your address."),
+    ("DISCONNECTED_RATE_LIMITED", &[], "Disconnected for sending messages too quickly."),
+    ("IDLE_TIMEOUT", &[], "Disconnected after being idle for too long."),
+    ("PROTOCOL_VIOLATION", &[], "The client sent something the server could not accept."),
+    ("KICKED", &[], "Kicked by an admin."),
+    ("BANNED", &[], "Banned from this server."),
+    ("SHUTTING_DOWN", &[], "The server is shutting down."),
+    ("KEEPALIVE_TIMEOUT", &[], "The connection stopped answering keepalives."),
+    ("STANDBY", &[], "This server is on standby. Try again in a little while."),
+];
+
+/// A `Localized` notice with its English text.
+pub fn localized(message: MessageCode) -> ServerMessage {
+    let text = fill(english(message.code()), &message.params());
+    ServerMessage::Localized { message, text }
+}
+
+fn english(code: &str) -> &'static str {
+    ENGLISH.iter().find(|(c, _, _)| *c == code).map_or("", |(_, _, text)| text)
+}
+
+/// Templates by code.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MessageTable {
+    templates: HashMap<String, String>,  // translations; the rest are English
+}
+
+impl MessageTable {
+    /// The English table.
+    pub fn english() -> Self {
+        Self::default()
+    }
+
+    /// Says `code` with `template` from now on.
+    pub fn set(&mut self, code: &str, template: &str) -> Result<(), String> {
+        let (_, params, _) = ENGLISH.iter().find(|(c, _, _)| *c == code).ok_or_else(|| format!("unknown message code {}", code))?;
+        check(template, params).map_err(|e| format!("{}: {}", code, e))?;
+        self.templates.insert(code.to_string(), template.to_string());
+        Ok(())
+    }
+
+    /// The template for `code`.
+    pub fn template(&self, code: &str) -> &str {
+        self.templates.get(code).map_or_else(|| english(code), String::as_str)
+    }
+
+    /// `message` in this table's words.
+    pub fn render(&self, message: &MessageCode) -> String {
+        fill(self.template(message.code()), &message.params())
+    }
+
+    /// Why a connection ended, in this table's words.
+    pub fn render_error(&self, code: ErrorCode) -> String {
+        fill(self.template(code.name()), &[])
+    }
+}
+
+/// Whether `template` is well formed and only refers to `params`.
+fn check(template: &str, params: &[&str]) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{").or_else(|| tail.strip_prefix("}}")) {
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix('{') {
+            let end = after.find('}').ok_or("unclosed `{` (write `{{` for a brace)")?;
+            if !params.contains(&&after[..end]) {
+                return Err(format!("unknown placeholder `{{{}}}`", &after[..end]));
+            }
+            rest = &after[end + 1..];
+        } else {
+            return Err("unmatched `}` (write `}}` for a brace)".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// `template` with its placeholders filled in; `set` has checked it, so
+/// anything unexpected is kept as written.
+fn fill(template: &str, params: &[(&'static str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{") {
+            out.push('{');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("}}") {
+            out.push('}');
+            rest = after;
+        } else if let Some(end) = tail.find('}').filter(|_| tail.starts_with('{')) {
+            match params.iter().find(|(name, _)| *name == &tail[1..end]) {
+                Some((_, value)) => out.push_str(value),
+                None => out.push_str(&tail[..=end]),
+            }
+            rest = &tail[end + 1..];
+        } else {
+            out.push_str(&tail[..1]);
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
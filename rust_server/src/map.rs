@@ -0,0 +1,392 @@
+use std::str::FromStr;
+use crate::protocol::Planet;
+use crate::snapshot::WorldView;
+
+/*
+Top-down maps of the world, for debugging world generation and seeing
+where players are.
+
+A map looks down on the world from above: positions are projected onto the
+XZ plane (the height, y, is dropped), with x growing to the right and z
+growing downwards. The view is fitted to whatever is in the state, with a
+little room around it, and keeps to scale, so circles stay round. Planets
+are filled circles of their size (a diameter), the largest drawn first so
+that small ones stay visible, and players are drawn last, one cell each.
+
+`render_ascii` draws characters, `#` for planets and `@` for players, with
+a character taken to be twice as tall as it is wide; it is what the admin
+`map` command prints. `render_png` draws pixels, planets in their first
+color, for `server --render-map <path>`, which writes the text map instead
+when the path ends in `.txt` (`--map-size <width>x<height>` changes the
+size). Both label the axes with world
+coordinates: the left, middle and right of the x axis and the top, middle
+and bottom of the z axis, each for the middle of its cell. Images smaller
+than `LABELLED_SIDE` are left unlabelled. The PNG is written here, without
+compression, so it needs no image library.
+*/
+
+/// How many cells, characters or pixels, a map has across and down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl MapSize {
+    /// What the admin `map` command prints unless told otherwise.
+    pub const ASCII: MapSize = MapSize { width: 72, height: 32 };
+    /// What `--render-map` draws unless told otherwise.
+    pub const PNG: MapSize = MapSize { width: 512, height: 512 };
+}
+
+const MIN_SIDE: usize = 8;
+const MAX_SIDE: usize = 4096;
+
+impl FromStr for MapSize {
+    type Err = String;
+
+    /// Parses `<width>x<height>`, e.g. `120x40`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = s.split_once('x').and_then(|(width, height)| Some(MapSize { width: width.parse().ok()?, height: height.parse().ok()? }));
+        match size {
+            Some(size) if [size.width, size.height].iter().all(|side| (MIN_SIDE..=MAX_SIDE).contains(side)) => Ok(size),
+            _ => Err(format!("invalid map size {:?} (expected <width>x<height>, each {} to {})", s, MIN_SIDE, MAX_SIDE)),
+        }
+    }
+}
+
+/// Characters are about twice as tall as they are wide.
+const ASCII_ASPECT: f32 = 2.0;
+
+/// How far around an empty world the map reaches.
+const EMPTY_EXTENT: f32 = 100.0;
+
+/// Room left around the contents, as a fraction of their extent.
+const MARGIN: f32 = 0.05;
+
+/// Maps world x and z to cells, and back.
+#[derive(Debug, Clone, Copy)]
+struct Projection {
+    left: f32,        // world x of the left edge
+    top: f32,         // world z of the top edge
+    per_column: f32,  // world units across one cell
+    per_row: f32,     // world units down one cell
+}
+
+impl Projection {
+    /// Fits every planet and player into `columns` by `rows` cells, each
+    /// `aspect` times as tall as it is wide.
+    fn fit(state: &impl WorldView, columns: usize, rows: usize, aspect: f32) -> Self {
+        let extents = state.planets().iter()
+            .map(|p| (p.position.x, p.position.z, p.size / 2.0))
+            .chain(state.players().iter().map(|p| (p.position.x, p.position.z, 0.0)));
+        let (mut min_x, mut max_x, mut min_z, mut max_z) = (f32::INFINITY, f32::NEG_INFINITY, f32::INFINITY, f32::NEG_INFINITY);
+        for (x, z, radius) in extents {
+            min_x = min_x.min(x - radius);
+            max_x = max_x.max(x + radius);
+            min_z = min_z.min(z - radius);
+            max_z = max_z.max(z + radius);
+        }
+        if min_x > max_x {
+            let center = state.initial_player_location();
+            (min_x, max_x, min_z, max_z) = (center.x - EMPTY_EXTENT, center.x + EMPTY_EXTENT, center.z - EMPTY_EXTENT, center.z + EMPTY_EXTENT);
+        }
+        let span_x = (max_x - min_x).max(1.0) * (1.0 + 2.0 * MARGIN);
+        let span_z = (max_z - min_z).max(1.0) * (1.0 + 2.0 * MARGIN);
+        let per_column = (span_x / columns as f32).max(span_z / (rows as f32 * aspect));
+        let per_row = per_column * aspect;
+        Projection {
+            left: (min_x + max_x) / 2.0 - per_column * columns as f32 / 2.0,
+            top: (min_z + max_z) / 2.0 - per_row * rows as f32 / 2.0,
+            per_column,
+            per_row,
+        }
+    }
+
+    /// The world x at the middle of `column`.
+    fn x_of(&self, column: usize) -> f32 {
+        self.left + (column as f32 + 0.5) * self.per_column
+    }
+
+    /// The world z at the middle of `row`.
+    fn z_of(&self, row: usize) -> f32 {
+        self.top + (row as f32 + 0.5) * self.per_row
+    }
+
+    /// The cell a world position falls in, if it is on the map.
+    fn cell(&self, x: f32, z: f32, columns: usize, rows: usize) -> Option<(usize, usize)> {
+        let column = ((x - self.left) / self.per_column).floor();
+        let row = ((z - self.top) / self.per_row).floor();
+        (column >= 0.0 && row >= 0.0 && (column as usize) < columns && (row as usize) < rows)
+            .then_some((column as usize, row as usize))
+    }
+}
+
+/// Paints the planets, largest first, then the players onto `cells`, a
+/// `columns` wide grid of rows.
+fn paint<T: Copy>(state: &impl WorldView, projection: &Projection, cells: &mut [T], columns: usize, planet: impl Fn(&Planet) -> T, player: T) {
+    let rows = cells.len() / columns;
+    let mut planets: Vec<&Planet> = state.planets().iter().collect();
+    planets.sort_by(|a, b| b.size.total_cmp(&a.size));
+    for p in planets {
+        let (x, z, radius) = (p.position.x, p.position.z, p.size / 2.0);
+        let fill = planet(p);
+        let corners = (projection.cell(x - radius, z - radius, columns, rows), projection.cell(x + radius, z + radius, columns, rows));
+        if let (Some((left, top)), Some((right, bottom))) = corners {
+            for row in top..=bottom {
+                for column in left..=right {
+                    let (dx, dz) = (projection.x_of(column) - x, projection.z_of(row) - z);
+                    if dx * dx + dz * dz <= radius * radius {
+                        cells[row * columns + column] = fill;
+                    }
+                }
+            }
+        }
+        // However small, a planet shows up
+        if let Some((column, row)) = projection.cell(x, z, columns, rows) {
+            cells[row * columns + column] = fill;
+        }
+    }
+    for p in state.players() {
+        if let Some((column, row)) = projection.cell(p.position.x, p.position.z, columns, rows) {
+            cells[row * columns + column] = player;
+        }
+    }
+}
+
+/// A world coordinate as it appears on the axes.
+fn coordinate(value: f32) -> String {
+    (value.round() as i64).to_string()
+}
+
+/// The x axis labels above a map `width` characters wide, the middle one
+/// left out when there is no room for it.
+fn x_axis(projection: &Projection, width: usize) -> String {
+    let mut line = vec![' '; width];
+    let mut put = |text: &str, start: usize| {
+        for (cell, c) in line.iter_mut().skip(start).zip(text.chars()) {
+            *cell = c;
+        }
+    };
+    let left = coordinate(projection.x_of(0));
+    let middle = coordinate(projection.x_of(width / 2));
+    let right = coordinate(projection.x_of(width - 1));
+    let middle_start = (width / 2).saturating_sub(middle.len() / 2);
+    if middle_start > left.len() && middle_start + middle.len() < width.saturating_sub(right.len()) {
+        put(&middle, middle_start);
+    }
+    put(&left, 0);
+    put(&right, width.saturating_sub(right.len()));
+    line.into_iter().collect::<String>().trim_end().to_string()
+}
+
+/// The map as text, for the console.
+pub fn render_ascii(state: &impl WorldView, size: MapSize) -> String {
+    let MapSize { width, height } = size;
+    let projection = Projection::fit(state, width, height, ASCII_ASPECT);
+    let mut cells = vec![' '; width * height];
+    paint(state, &projection, &mut cells, width, |_| '#', '@');
+
+    let labelled = [0, height / 2, height - 1];
+    let gutter = labelled.iter().map(|row| coordinate(projection.z_of(*row)).len()).max().unwrap_or(0);
+    let border = format!("{:>gutter$}+{}+\n", "", "-".repeat(width));
+    let mut map = format!("{} planet(s), {} player(s); x across, z down; # planet, @ player\n",
+                          state.planets().len(), state.players().len());
+    map += &format!("{:>gutter$} {}\n", "", x_axis(&projection, width));
+    map += &border;
+    for (row, line) in cells.chunks(width).enumerate() {
+        let label = if labelled.contains(&row) { coordinate(projection.z_of(row)) } else { String::new() };
+        map += &format!("{:>gutter$}|{}|\n", label, line.iter().collect::<String>());
+    }
+    map += &border;
+    map
+}
+
+/// Images at least this wide and tall get axis labels.
+pub const LABELLED_SIDE: usize = 64;
+
+/// Labels may be this many characters long before they crowd the map.
+const LABEL_CHARS: usize = 6;
+
+const BACKGROUND: [u8; 3] = [10, 10, 20];
+const FRAME: [u8; 3] = [90, 90, 110];
+const TEXT: [u8; 3] = [170, 170, 190];
+const PLAYER: [u8; 3] = [255, 255, 255];
+
+/// The digits and minus sign, three pixels wide and five tall, one row of
+/// bits per byte with the leftmost pixel highest.
+const GLYPHS: [(char, [u8; 5]); 11] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+];
+
+/// An RGB image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,  // row by row, from the top left
+}
+
+impl Image {
+    fn new(width: usize, height: usize, fill: [u8; 3]) -> Self {
+        Image { width, height, pixels: vec![fill; width * height] }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixels[y * self.width + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    /// Draws `text` with its top left corner at `x`, `y`, each font pixel
+    /// `scale` pixels square.
+    fn text(&mut self, text: &str, x: usize, y: usize, scale: usize) {
+        for (i, c) in text.chars().enumerate() {
+            let Some((_, rows)) = GLYPHS.iter().find(|(glyph, _)| *glyph == c) else { continue };
+            let left = x + i * 4 * scale;
+            for (row, bits) in rows.iter().enumerate() {
+                for column in (0..3).filter(|column| bits & (0b100 >> column) != 0) {
+                    for (dx, dy) in (0..scale).flat_map(|dx| (0..scale).map(move |dy| (dx, dy))) {
+                        self.set(left + column * scale + dx, y + row * scale + dy, TEXT);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The image as an 8-bit RGB PNG.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);  // bit depth, RGB, deflate, no filter method, no interlace
+
+        // Every row starts with its filter type, none
+        let mut raw = Vec::with_capacity(self.height * (1 + self.width * 3));
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// `data` as a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// The map as an image, for `--render-map`.
+pub fn render_png(state: &impl WorldView, size: MapSize) -> Image {
+    let MapSize { width, height } = size;
+    let mut image = Image::new(width, height, BACKGROUND);
+    let labelled = width >= LABELLED_SIDE && height >= LABELLED_SIDE;
+    let scale = (width.min(height) / 256).max(1);
+    // Room for the frame, and for the labels left of and below the map
+    let (left, top) = if labelled { (LABEL_CHARS * 4 * scale + 2 * scale, 4 * scale) } else { (1, 1) };
+    let (right, bottom) = if labelled { (2 * scale, 9 * scale) } else { (1, 1) };
+    let (columns, rows) = (width - left - right, height - top - bottom);
+
+    let projection = Projection::fit(state, columns, rows, 1.0);
+    let mut cells = vec![BACKGROUND; columns * rows];
+    paint(state, &projection, &mut cells, columns, |p| [p.colors[0].r, p.colors[0].g, p.colors[0].b], PLAYER);
+    for (row, line) in cells.chunks(columns).enumerate() {
+        for (column, color) in line.iter().enumerate() {
+            image.set(left + column, top + row, *color);
+        }
+    }
+    for x in left - 1..=left + columns {
+        image.set(x, top - 1, FRAME);
+        image.set(x, top + rows, FRAME);
+    }
+    for y in top - 1..=top + rows {
+        image.set(left - 1, y, FRAME);
+        image.set(left + columns, y, FRAME);
+    }
+
+    if labelled {
+        let advance = 4 * scale;
+        for row in [0, rows / 2, rows - 1] {
+            let label = coordinate(projection.z_of(row));
+            let y = (top + row).saturating_sub(5 * scale / 2);
+            image.text(&label, (left - 2 * scale).saturating_sub(label.len() * advance), y, scale);
+        }
+        let y = top + rows + 3 * scale;
+        let label = coordinate(projection.x_of(0));
+        image.text(&label, left, y, scale);
+        let label = coordinate(projection.x_of(columns / 2));
+        image.text(&label, (left + columns / 2).saturating_sub(label.len() * advance / 2), y, scale);
+        let label = coordinate(projection.x_of(columns - 1));
+        image.text(&label, (left + columns).saturating_sub(label.len() * advance), y, scale);
+    }
+    image
+}
@@ -0,0 +1,146 @@
+use serde::{Serialize, Deserialize};
+use crate::protocol::{Asteroid, Belt, Planet, Position};
+
+/*
+What a planet's module type does, beyond its regrowth rate (see `economy`).
+
+- Defense (type 1): an owned Defense planet guards every planet whose
+  centre lies within `defense_radius` of its own, itself included; only its
+  owner may land on them. Unowned Defense planets guard nothing.
+- Trade (type 2): resources sell for `trade_bonus` times `sell_rate`
+  credits a unit within `trade_range` of a Trade planet's surface.
+- Research (type 3): XP earned while landed on one, which for now means
+  XP for mining it, is multiplied by `research_xp`.
+
+Every other type, 0 and 4 among them, is `Unknown` here and has no effect.
+
+Players mine with `Mine { amount }` while landed, taking units from the
+planet into their cargo and earning `MINING_XP` a unit. Out in space the
+same message mines the nearest notable asteroid within `ASTEROID_REACH` of
+its surface (see `asteroid_in_reach`), which never grows back and has no
+module to multiply the XP; `Sell { amount }`
+turns cargo into credits at the rate where they are. Like XP, cargo and
+credits are kept by player name for as long as the server runs. The numbers
+are `WorldConfig::modules`, set from the config's `[modules]` table.
+These functions only decide; the server applies what they decide.
+*/
+
+/// XP for each unit mined, before the Research multiplier.
+pub const MINING_XP: u32 = 1;
+
+/// How far from a notable asteroid's surface a player may mine it.
+pub const ASTEROID_REACH: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleKind {
+    Defense,
+    Trade,
+    Research,
+    Unknown,
+}
+
+impl ModuleKind {
+    pub fn of(module_type: u8) -> Self {
+        match module_type {
+            1 => ModuleKind::Defense,
+            2 => ModuleKind::Trade,
+            3 => ModuleKind::Research,
+            _ => ModuleKind::Unknown,
+        }
+    }
+}
+
+/// The tunable numbers of the module effects.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModuleEffects {
+    /// How far from an owned Defense planet's centre other planets' centres
+    /// are guarded.
+    pub defense_radius: f32,
+    /// Credits a unit of cargo sells for away from Trade planets.
+    pub sell_rate: f64,
+    /// How far from a Trade planet's surface its bonus applies.
+    pub trade_range: f32,
+    /// Multiplier of `sell_rate` near a Trade planet.
+    pub trade_bonus: f64,
+    /// Multiplier of XP earned while landed on a Research planet.
+    pub research_xp: f64,
+}
+
+impl Default for ModuleEffects {
+    fn default() -> Self {
+        ModuleEffects { defense_radius: 300.0, sell_rate: 1.0, trade_range: 200.0, trade_bonus: 1.5, research_xp: 2.0 }
+    }
+}
+
+fn distance(a: &Position, b: &Position) -> f64 {
+    let (dx, dy, dz) = ((a.x - b.x) as f64, (a.y - b.y) as f64, (a.z - b.z) as f64);
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+impl ModuleEffects {
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [
+            ("defense_radius", self.defense_radius as f64),
+            ("sell_rate", self.sell_rate),
+            ("trade_range", self.trade_range as f64),
+            ("trade_bonus", self.trade_bonus),
+            ("research_xp", self.research_xp),
+        ];
+        match values.iter().find(|(_, value)| !(value.is_finite() && *value >= 0.0)) {
+            Some((key, _)) => Err(format!("modules: {} must be zero or positive", key)),
+            None => Ok(()),
+        }
+    }
+
+    /// The Defense planet that keeps `player_id` from landing on `target`,
+    /// if any: the nearest one guarding it that someone else owns.
+    pub fn defended_by<'a>(&self, target: &Planet, planets: &'a [Planet], player_id: u32) -> Option<&'a Planet> {
+        planets.iter()
+            .filter(|p| ModuleKind::of(p.module_type) == ModuleKind::Defense)
+            .filter(|p| p.owner.is_some_and(|owner| owner != player_id))
+            .map(|p| (p, distance(&p.position, &target.position)))
+            .filter(|(_, d)| *d <= self.defense_radius as f64)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(p, _)| p)
+    }
+
+    /// Credits a unit of cargo sells for at `position`.
+    pub fn sell_rate_at(&self, position: &Position, planets: &[Planet]) -> f64 {
+        let near_trade = planets.iter()
+            .filter(|p| ModuleKind::of(p.module_type) == ModuleKind::Trade)
+            .any(|p| distance(&p.position, position) - (p.size / 2.0) as f64 <= self.trade_range as f64);
+        if near_trade { self.sell_rate * self.trade_bonus } else { self.sell_rate }
+    }
+
+    /// What `xp` earned while landed on `landed_on` is worth.
+    pub fn xp_while_landed(&self, xp: u32, landed_on: &Planet) -> u32 {
+        match ModuleKind::of(landed_on.module_type) {
+            ModuleKind::Research => (xp as f64 * self.research_xp) as u32,
+            _ => xp,
+        }
+    }
+}
+
+/// The notable asteroid nearest `position` within `ASTEROID_REACH` of its
+/// surface, if any.
+pub fn asteroid_in_reach<'a>(belts: &'a mut [Belt], position: &Position) -> Option<&'a mut Asteroid> {
+    belts.iter_mut()
+        .flat_map(|belt| belt.notable.iter_mut())
+        .map(|asteroid| (distance(&asteroid.position, position) - (asteroid.size / 2.0) as f64, asteroid))
+        .filter(|(d, _)| *d <= ASTEROID_REACH as f64)
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, asteroid)| asteroid)
+}
+
+/// Credits for selling `amount` units at `rate`, fractions dropped.
+pub fn credits_for(amount: u32, rate: f64) -> u64 {
+    (amount as f64 * rate).floor() as u64
+}
+
+/// A player's mined resources and the credits they sold them for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wallet {
+    pub cargo: u64,
+    pub credits: u64,
+}
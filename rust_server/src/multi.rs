@@ -0,0 +1,263 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::client::{ClientError, ClientEvent, Connection};
+use crate::protocol::{Capabilities, ClientMessage, ErrorCode};
+
+/*
+Several servers watched at once, e.g. by a monitoring tool.
+
+`MultiConnection::open` starts one task per source URL, each with its own
+`Connection`, and merges what they see into one stream of `MultiEvent`s,
+every `ClientEvent` tagged with the URL it came from. The tasks share
+nothing but the (unbounded) event channel, so a source that is slow, hangs
+or goes away never holds up the others.
+
+A source that cannot be reached, or whose connection ends, reports a
+`Disconnected` event. With `MultiOptions::reconnect` it then tries again
+after the delay, as long as the end was one a client may come back from:
+a dropped connection, a failed attempt, or a close whose `ErrorCode` allows
+it (see `ErrorCode::may_reconnect`). Otherwise, or once the
+`MultiConnection` is closed or dropped, the source stops for good.
+
+The latest snapshot of every source is summed up in its `SourceStatus`, so
+aggregate queries such as `total_players` need no events of their own.
+Commands go to one source with `send` or to all of them with `send_all`;
+while a source is reconnecting its commands are dropped.
+*/
+
+pub const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// A `ClientEvent` and the URL of the server it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiEvent {
+    pub source: Arc<str>,
+    pub event: ClientEvent,
+}
+
+/// How every source is connected to.
+#[derive(Debug, Clone)]
+pub struct MultiOptions {
+    pub name: Option<String>,  // the same on every server; None lets each choose
+    pub spectate: bool,
+    pub insecure: bool,        // see `Connection::connect_with`
+    pub capabilities: Capabilities,
+    pub reconnect: Option<Duration>,  // how long to wait first; None never reconnects
+}
+
+impl Default for MultiOptions {
+    fn default() -> Self {
+        MultiOptions {
+            name: None,
+            spectate: true,
+            insecure: false,
+            capabilities: Capabilities::DEFAULT,
+            reconnect: Some(DEFAULT_RECONNECT_DELAY),
+        }
+    }
+}
+
+/// What one source looks like as of its latest events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceStatus {
+    pub connected: bool,
+    pub players: usize,  // in the latest snapshot; 0 while disconnected
+    pub tick: u64,       // of the latest snapshot
+    pub connects: u32,   // successful connections so far, reconnections included
+    pub stopped: bool,   // gave up, or was told to
+}
+
+struct Source {
+    url: Arc<str>,
+    status: Arc<Mutex<SourceStatus>>,
+    commands: mpsc::UnboundedSender<ClientMessage>,
+    task: JoinHandle<()>,
+}
+
+pub struct MultiConnection {
+    sources: Vec<Source>,
+    events: mpsc::UnboundedReceiver<MultiEvent>,
+}
+
+impl MultiConnection {
+    /// Starts connecting to every URL in `urls`; a URL given twice is
+    /// watched once. Must be called within a Tokio runtime.
+    pub fn open(urls: &[impl AsRef<str>], options: MultiOptions) -> Self {
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let mut sources: Vec<Source> = Vec::new();
+        for url in urls.iter().map(AsRef::as_ref) {
+            if sources.iter().any(|s| &*s.url == url) {
+                continue;
+            }
+            let url: Arc<str> = url.into();
+            let status = Arc::new(Mutex::new(SourceStatus::default()));
+            let (commands, commands_rx) = mpsc::unbounded_channel();
+            let task = tokio::spawn(run_source(url.clone(), options.clone(), status.clone(), commands_rx, events_tx.clone()));
+            sources.push(Source { url, status, commands, task });
+        }
+        MultiConnection { sources, events }
+    }
+
+    /// The next event from any source, in the order they arrived; `None`
+    /// once every source has stopped and its events have been taken.
+    /// Cancel-safe, so it can be used in `tokio::select!`.
+    pub async fn next_event(&mut self) -> Option<MultiEvent> {
+        self.events.recv().await
+    }
+
+    /// The source URLs, in the order given to `open`.
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.sources.iter().map(|s| &*s.url)
+    }
+
+    pub fn status(&self, url: &str) -> Option<SourceStatus> {
+        self.sources.iter().find(|s| &*s.url == url).map(|s| *s.status.lock().unwrap())
+    }
+
+    /// How many sources are connected right now.
+    pub fn connected(&self) -> usize {
+        self.sources.iter().filter(|s| s.status.lock().unwrap().connected).count()
+    }
+
+    /// Players in the latest snapshot of every connected source, together.
+    pub fn total_players(&self) -> usize {
+        self.sources.iter().map(|s| s.status.lock().unwrap().players).sum()
+    }
+
+    /// Queues `message` for the source at `url`; false if there is no such
+    /// source or it has stopped.
+    pub fn send(&self, url: &str, message: ClientMessage) -> bool {
+        self.sources.iter().find(|s| &*s.url == url).is_some_and(|s| s.commands.send(message).is_ok())
+    }
+
+    /// Queues `message` for every source that has not stopped.
+    pub fn send_all(&self, message: ClientMessage) {
+        for source in &self.sources {
+            let _ = source.commands.send(message.clone());
+        }
+    }
+
+    /// Closes every connection politely and waits for the sources to stop.
+    /// Events not taken yet are lost.
+    pub async fn close(mut self) {
+        for source in std::mem::take(&mut self.sources) {
+            drop(source.commands);
+            let _ = source.task.await;
+        }
+    }
+}
+
+impl Drop for MultiConnection {
+    fn drop(&mut self) {
+        for source in &self.sources {
+            source.task.abort();
+        }
+    }
+}
+
+/// Whether a connection that ended with `code` (`None` for one that was
+/// lost or never made) may be tried again.
+fn may_reconnect(code: Option<u16>) -> bool {
+    code.is_none_or(|code| ErrorCode::from_code(code).is_some_and(ErrorCode::may_reconnect))
+}
+
+/// How one connection to a source ended.
+enum End {
+    Closed(Option<u16>),  // by the server or the network, with its close code
+    Stopped,              // by us: the `MultiConnection` is gone or closing
+}
+
+async fn run_source(
+    url: Arc<str>,
+    options: MultiOptions,
+    status: Arc<Mutex<SourceStatus>>,
+    mut commands: mpsc::UnboundedReceiver<ClientMessage>,
+    events: mpsc::UnboundedSender<MultiEvent>,
+) {
+    let emit = |event: ClientEvent| events.send(MultiEvent { source: url.clone(), event }).is_ok();
+    loop {
+        let connecting = if options.spectate {
+            Connection::spectate_with_capabilities(&url, options.insecure, options.capabilities).await
+        } else {
+            Connection::connect_with_capabilities(&url, options.name.as_deref(), options.insecure, options.capabilities).await
+        };
+        let end = match connecting {
+            Ok(mut connection) => {
+                {
+                    let mut status = status.lock().unwrap();
+                    status.connected = true;
+                    status.connects += 1;
+                }
+                let end = pump(&mut connection, &status, &mut commands, &emit).await;
+                let mut status = status.lock().unwrap();
+                status.connected = false;
+                status.players = 0;
+                end
+            }
+            // Trying again would fail the same way
+            Err(e @ (ClientError::InvalidName(_) | ClientError::TlsUnavailable)) => {
+                emit(ClientEvent::Disconnected { code: None, reason: e.to_string() });
+                break;
+            }
+            Err(e) if emit(ClientEvent::Disconnected { code: None, reason: format!("cannot connect: {}", e) }) => End::Closed(None),
+            Err(_) => End::Stopped,
+        };
+        let delay = match (end, options.reconnect) {
+            (End::Closed(code), Some(delay)) if may_reconnect(code) => delay,
+            _ => break,
+        };
+        // Stop waiting as soon as we are told to stop
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = drain_until_closed(&mut commands) => break,
+        }
+    }
+    status.lock().unwrap().stopped = true;
+}
+
+/// Passes events on and commands in until the connection ends.
+async fn pump(
+    connection: &mut Connection,
+    status: &Mutex<SourceStatus>,
+    commands: &mut mpsc::UnboundedReceiver<ClientMessage>,
+    emit: &impl Fn(ClientEvent) -> bool,
+) -> End {
+    loop {
+        tokio::select! {
+            event = connection.next_event() => {
+                let code = match &event {
+                    ClientEvent::StateSnapshot { tick, state, .. } => {
+                        let mut status = status.lock().unwrap();
+                        status.players = state.players.len();
+                        status.tick = *tick;
+                        None
+                    }
+                    ClientEvent::Disconnected { code, .. } => Some(*code),
+                    _ => None,
+                };
+                if !emit(event) {
+                    let _ = connection.close().await;
+                    return End::Stopped;
+                }
+                if let Some(code) = code {
+                    return End::Closed(code);
+                }
+            }
+            command = commands.recv() => match command {
+                // A failed send shows up as a Disconnected event next
+                Some(message) => { let _ = connection.send(&message).await; }
+                None => {
+                    let _ = connection.close().await;
+                    return End::Stopped;
+                }
+            },
+        }
+    }
+}
+
+/// Drops commands, which cannot be sent while disconnected, until the
+/// `MultiConnection` stops the source.
+async fn drain_until_closed(commands: &mut mpsc::UnboundedReceiver<ClientMessage>) {
+    while commands.recv().await.is_some() {}
+}
@@ -0,0 +1,194 @@
+use serde::{Serialize, Deserialize};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use crate::json_store::JsonStore;
+
+/*
+Muted player names, kept across restarts in the file given as `mute_file`.
+
+Lighter than a kick or a ban: a muted player stays in the game, moving and
+trading as before, but cannot be heard.
+
+- `mute <name> [duration]`: the player's chats are refused, with a
+  `ChatRejected` carrying `ChatError::Muted` to CHAT_ACKS clients that
+  asked for receipts and a `CHAT_MUTED` notice to the rest.
+- `shadowmute <name> [duration]`: the player's chats look accepted to them,
+  echoed, acknowledged and sent back as a `Chatted` as usual, but go no
+  further: they are not logged in the chat history (see `chat_history`),
+  broadcast to anyone else or sent out as a `GameEvent::Chatted`, so
+  nobody else ever sees them.
+
+A duration is a number with `s`, `m`, `h` or `d` after it, e.g. `90s` or
+`2h` (see `parse_duration`); without one the mute lasts until `unmute`.
+Muting a name again replaces its mute. `mutes` lists the mutes in force.
+
+Mutes belong to names, compared by `normalize_name` as bans and roles are,
+so they hold across reconnects and restarts, and move with a rename: a
+muted player cannot rename their way out of it. They end at a wall-clock
+time, not after so long connected: a mute that ran out while the server
+was down is over when it comes back. Expired mutes are ignored as soon as
+they run out, and dropped from the file the next time the list is read or
+changed.
+
+Whispers and local chat, when there are any, are chat like any other and
+go through the same check. Nothing else a muted player does is.
+
+The file is JSON, rewritten on every change (see `json_store`). Without a
+file mutes last until the server stops.
+*/
+
+/// How a name is muted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MuteKind {
+    /// Chats are refused, and the sender told so
+    Mute,
+    /// Chats are echoed to the sender and go nowhere else
+    Shadow,
+}
+
+/// One muted name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mute {
+    pub name: String,  // as muted
+    pub kind: MuteKind,
+    pub until_unix_ms: Option<u64>,  // None until unmuted
+}
+
+impl Mute {
+    /// Whether the mute is still in force at `now_unix_ms`.
+    pub fn in_force(&self, now_unix_ms: u64) -> bool {
+        self.until_unix_ms.is_none_or(|until| now_unix_ms < until)
+    }
+
+    /// How long the mute has left at `now_unix_ms`, if it ends.
+    pub fn remaining_ms(&self, now_unix_ms: u64) -> Option<u64> {
+        self.until_unix_ms.map(|until| until.saturating_sub(now_unix_ms))
+    }
+
+    /// `Ada muted for 9m 30s`, or `Ada shadow-muted until unmuted`.
+    pub fn describe(&self, now_unix_ms: u64) -> String {
+        let kind = match self.kind {
+            MuteKind::Mute => "muted",
+            MuteKind::Shadow => "shadow-muted",
+        };
+        match self.remaining_ms(now_unix_ms) {
+            Some(ms) => format!("{} {} for {}", self.name, kind, format_duration(Duration::from_millis(ms))),
+            None => format!("{} {} until unmuted", self.name, kind),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MuteList(JsonStore<String, Mute>);
+
+impl MuteList {
+    /// No mutes, kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The mutes in `path`, saved back there on every change. A missing
+    /// file is no mutes, created on the first mute.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        JsonStore::load(path).map(MuteList)
+    }
+
+    /// How many names have mutes, expired ones included until dropped.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Mutes `name` for `duration` from `now_unix_ms`, or until unmuted,
+    /// replacing any mute it had.
+    pub fn mute(&mut self, name: &str, kind: MuteKind, duration: Option<Duration>, now_unix_ms: u64) -> io::Result<Mute> {
+        let name = name.trim();
+        let until_unix_ms = duration.map(|duration| now_unix_ms.saturating_add(duration.as_millis() as u64));
+        let mute = Mute { name: name.to_string(), kind, until_unix_ms };
+        self.0.insert(name, mute.clone());
+        self.expire(now_unix_ms);
+        self.0.save()?;
+        Ok(mute)
+    }
+
+    /// Lifts the mute on `name`, if it has one in force.
+    pub fn unmute(&mut self, name: &str, now_unix_ms: u64) -> io::Result<Option<Mute>> {
+        let removed = self.0.remove(name);
+        self.expire(now_unix_ms);
+        self.0.save()?;
+        Ok(removed.filter(|mute| mute.in_force(now_unix_ms)))
+    }
+
+    /// The mute on `name` in force at `now_unix_ms`, if any.
+    pub fn muted(&self, name: &str, now_unix_ms: u64) -> Option<&Mute> {
+        self.0.get(name).filter(|mute| mute.in_force(now_unix_ms))
+    }
+
+    /// Moves `old`'s mute, if it has one in force at `now_unix_ms`, to `new`,
+    /// so a rename does not lift it. If `new` has a mute of its own in force
+    /// that one stands, and holds the player all the same.
+    pub fn rename(&mut self, old: &str, new: &str, now_unix_ms: u64) -> io::Result<()> {
+        let expired = self.expire(now_unix_ms);
+        let moved = self.0.rename(old, new);
+        if let Some(mute) = self.0.get_mut(new).filter(|_| moved) {
+            mute.name = new.trim().to_string();
+        }
+        if expired || moved { self.0.save() } else { Ok(()) }
+    }
+
+    /// The mutes in force at `now_unix_ms`, in normalized name order;
+    /// expired ones are dropped and the file rewritten if there were any.
+    pub fn in_force(&mut self, now_unix_ms: u64) -> io::Result<Vec<Mute>> {
+        if self.expire(now_unix_ms) {
+            self.0.save()?;
+        }
+        Ok(self.0.values().cloned().collect())
+    }
+
+    /// Where the list is saved, if anywhere.
+    pub fn path(&self) -> Option<&Path> {
+        self.0.path()
+    }
+
+    /// The file's text: every mute by normalized name, as pretty JSON.
+    pub fn to_file_text(&self) -> String {
+        self.0.to_file_text()
+    }
+
+    /// Drops the mutes over by `now_unix_ms`; whether there were any.
+    fn expire(&mut self, now_unix_ms: u64) -> bool {
+        self.0.retain(|mute| mute.in_force(now_unix_ms))
+    }
+}
+
+/// A duration as `90s`, `10m`, `2h` or `1d`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration: {} (e.g. 90s, 10m, 2h or 1d)", text);
+    let split = text.len().checked_sub(1).filter(|&i| text.is_char_boundary(i)).ok_or_else(invalid)?;
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(60 * 60),
+        "d" => number.checked_mul(24 * 60 * 60),
+        _ => None,
+    };
+    match secs {
+        Some(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+        _ => Err(invalid()),
+    }
+}
+
+/// A duration to the second, as `1d 2h`, `9m 30s` or `45s`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64().ceil() as u64;
+    let parts = [(secs / 86_400, "d"), (secs / 3_600 % 24, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")];
+    let shown: Vec<String> = parts.iter().filter(|(n, _)| *n > 0).map(|(n, unit)| format!("{}{}", n, unit)).collect();
+    if shown.is_empty() { "0s".to_string() } else { shown.join(" ") }
+}
@@ -0,0 +1,60 @@
+/*
+Keeping a client's view of its own position from going backwards.
+
+Broadcast States are built once a tick and reach each connection through
+the broadcast channel, while position corrections are sent straight to the
+one connection whose update they correct. Both end up in the connection's
+one write queue, but in the order they were queued, not the order they
+were built: a State built before a correction can be queued after it, and
+would put the ship back where the correction had just moved it from.
+
+So everything that sets a player's position says which tick it is from:
+States and Resyncs the tick they were built at, corrections the tick the
+server was on when it made them, which a State of that tick may or may
+not have seen. A `Sequencer` keeps the newest tick applied and turns away
+anything older, a State at the tick of a correction included; the next
+tick's State brings whatever it missed. The server puts every frame for a
+connection through one, so stale States are never sent, and `EventDecoder`
+keeps another, so a client drops any that arrive anyway, e.g. from a
+server from before this or a recorded capture.
+*/
+
+/// The newest tick applied to one connection's view of the world.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sequencer {
+    state: Option<u64>,      // the newest State or Resync applied
+    corrected: Option<u64>,  // the newest correction applied
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a State or Resync built at `tick` is still news, noting it if
+    /// so: anything older than the newest one, or no newer than the newest
+    /// correction, is not.
+    pub fn admit_state(&mut self, tick: u64) -> bool {
+        if self.state.is_some_and(|state| tick < state) || self.corrected.is_some_and(|corrected| tick <= corrected) {
+            return false;
+        }
+        self.state = Some(tick);
+        true
+    }
+
+    /// Whether a correction made at `tick` is still news, noting it if so:
+    /// a State built after it already has the corrected position, and a
+    /// later correction replaces it.
+    pub fn admit_correction(&mut self, tick: u64) -> bool {
+        if self.newest().is_some_and(|newest| tick < newest) {
+            return false;
+        }
+        self.corrected = Some(tick);
+        true
+    }
+
+    /// The newest tick applied, if any.
+    pub fn newest(&self) -> Option<u64> {
+        self.state.max(self.corrected)
+    }
+}
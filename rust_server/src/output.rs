@@ -0,0 +1,88 @@
+use serde_json::{json, Value};
+use crate::client::ClientEvent;
+use crate::protocol::ServerMessage;
+use crate::validate::Violation;
+
+/*
+The client's machine-readable output (`client --output json`): one JSON
+object per line, each with a snake_case `type` and `ts_ms`, the client's
+wall clock in milliseconds since the Unix epoch.
+
+    {"planets":10,"players":2,"server_time_ms":1200,"tick":12,"ts_ms":1760000000000,"type":"state"}
+
+Snapshots and deltas are summarized as counts, since a whole world per
+line is more than a script wants. Server messages without a type of their
+own come as `{"type":"message","message":{...}}`, holding the message as
+the server sends it in JSON mode. Notices sent as codes are `notice`
+lines too, with the `code` and the `message` it came with (see `locale`).
+A server handing over to a standby makes a `redirected` line naming where
+the client went (see `replication`). Time-sync replies are left out. The
+connection's own lifecycle adds `connecting`, `connected` and
+`reconnecting` lines, failures on the client's side `client_error`, and
+with `--validate` each broken protocol invariant a `violation` (see
+`validate`).
+*/
+
+fn stamped(mut record: Value, ts_ms: u64) -> Value {
+    record["ts_ms"] = json!(ts_ms);
+    record
+}
+
+/// The line for `event`, if it gets one.
+pub fn event_record(event: &ClientEvent, ts_ms: u64) -> Option<Value> {
+    let record = match event {
+        ClientEvent::StateSnapshot { tick, server_time_ms, state } => json!({
+            "type": "state",
+            "tick": tick,
+            "server_time_ms": server_time_ms,
+            "planets": state.planets.len(),
+            "players": state.players.len(),
+        }),
+        ClientEvent::Delta { from_tick, tick, diffs } => json!({ "type": "delta", "from_tick": from_tick, "tick": tick, "diffs": diffs.len() }),
+        ClientEvent::Joined { player_id, spawn, spawn_planet_id } => json!({
+            "type": "joined",
+            "player_id": player_id,
+            "spawn": spawn,
+            "spawn_planet_id": spawn_planet_id,
+        }),
+        ClientEvent::PlayerJoined { player } => json!({ "type": "player_joined", "player_id": player.id, "name": player.name }),
+        ClientEvent::PlayerLeft { player_id } => json!({ "type": "player_left", "player_id": player_id }),
+        ClientEvent::Chat { text } => json!({ "type": "chat", "text": text }),
+        ClientEvent::Notice { text } => json!({ "type": "notice", "text": text }),
+        ClientEvent::Localized { message, text } => json!({ "type": "notice", "code": message.code(), "message": message, "text": text }),
+        ClientEvent::Announcement { text } => json!({ "type": "announcement", "text": text }),
+        ClientEvent::WorldReset { seed } => json!({ "type": "world_reset", "seed": seed }),
+        ClientEvent::Redirected { to } => json!({ "type": "redirected", "to": to }),
+        ClientEvent::Disconnected { code, reason } => json!({ "type": "disconnected", "code": code, "reason": reason }),
+        ClientEvent::CorruptFrame { expected, got } => json!({ "type": "corrupt_frame", "expected": expected, "got": got }),
+        ClientEvent::Message(ServerMessage::Error { code, message }) => json!({ "type": "error", "code": code, "message": message }),
+        ClientEvent::Message(ServerMessage::TimeSync { .. }) => return None,
+        ClientEvent::Message(message) => json!({ "type": "message", "message": message }),
+    };
+    Some(stamped(record, ts_ms))
+}
+
+/// Whether `--quiet` still prints `event`.
+pub fn is_error(event: &ClientEvent) -> bool {
+    matches!(event, ClientEvent::Message(ServerMessage::Error { .. } | ServerMessage::CorruptFrame { .. }) | ClientEvent::CorruptFrame { .. })
+}
+
+pub fn connecting_record(url: &str, ts_ms: u64) -> Value {
+    stamped(json!({ "type": "connecting", "url": url }), ts_ms)
+}
+
+pub fn connected_record(url: &str, ts_ms: u64) -> Value {
+    stamped(json!({ "type": "connected", "url": url }), ts_ms)
+}
+
+pub fn reconnecting_record(delay_ms: u64, ts_ms: u64) -> Value {
+    stamped(json!({ "type": "reconnecting", "delay_ms": delay_ms }), ts_ms)
+}
+
+pub fn client_error_record(message: &str, ts_ms: u64) -> Value {
+    stamped(json!({ "type": "client_error", "message": message }), ts_ms)
+}
+
+pub fn violation_record(violation: &Violation, ts_ms: u64) -> Value {
+    stamped(json!({ "type": "violation", "rule": violation.rule.to_string(), "detail": violation.detail, "event": violation.event }), ts_ms)
+}
@@ -0,0 +1,127 @@
+use rand::Rng;
+use std::collections::HashMap;
+use crate::protocol::Color;
+
+/*
+Color palettes for generated planets.
+
+Without a palette every planet color is uniformly random. With one, each of
+a planet's three colors is a palette color picked at random and nudged by up
+to `JITTER` per channel, so planets of one palette look related without
+being identical. A palette may also be chosen per module type, e.g. rocky
+colors for mining planets; module types without one use the world palette.
+
+Built-in palettes are listed in `BUILTIN`; the config file may define more
+(or replace a built-in one) as lists of `#rrggbb` colors:
+
+    palette = "pastel"
+    [module_palettes]
+    1 = "rock"
+    [palettes]
+    sunset = ["#ff7e5f", "#feb47b", "#c0392b"]
+*/
+
+/// How far a picked color may stray from the palette, per channel.
+pub const JITTER: u8 = 12;
+
+pub const BUILTIN: &[(&str, &[&str])] = &[
+    ("pastel", &["#ffd1dc", "#c1e1c1", "#aec6cf", "#fdfd96", "#cbaacb", "#ffdab9"]),
+    ("lava", &["#3b0a00", "#8b1a00", "#d63a00", "#ff7b00", "#ffc300", "#2b2b2b"]),
+    ("ice", &["#e8f6ff", "#b3e0ff", "#7cc4f0", "#4a90c2", "#dfe9f0", "#ffffff"]),
+    ("rock", &["#5a4d41", "#7d6b5d", "#a39382", "#3e3a36", "#8c7b6b", "#b5a898"]),
+    ("gas", &["#d9a066", "#c97b4a", "#e8c39e", "#9e6b4a", "#f2dcb3", "#b07d56"]),
+];
+
+impl Color {
+    /// Parses `#rrggbb`; the `#` is optional.
+    pub fn from_hex(text: &str) -> Result<Color, String> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            // `from_str_radix` would also take a sign
+            (6, Some(r), Some(g), Some(b)) if hex.bytes().all(|b| b.is_ascii_hexdigit()) => Ok(Color { r, g, b }),
+            _ => Err(format!("invalid color {:?} (expected #rrggbb)", text)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<Color>,
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: Vec<Color>) -> Result<Palette, String> {
+        if colors.is_empty() {
+            return Err(format!("palette {} has no colors", name));
+        }
+        Ok(Palette { name: name.to_string(), colors })
+    }
+
+    /// A built-in palette by name.
+    pub fn builtin(name: &str) -> Option<Palette> {
+        let (_, colors) = BUILTIN.iter().find(|(builtin, _)| *builtin == name)?;
+        let colors = colors.iter().map(|hex| Color::from_hex(hex).expect("built-in palettes are valid")).collect();
+        Some(Palette { name: name.to_string(), colors })
+    }
+
+    /// Three colors for a planet.
+    pub fn pick(&self, rng: &mut impl Rng) -> [Color; 3] {
+        std::array::from_fn(|_| jitter(&self.colors[rng.gen_range(0..self.colors.len())], rng))
+    }
+}
+
+/// `color` with each channel moved by up to `JITTER`, staying within 0-255.
+pub fn jitter(color: &Color, rng: &mut impl Rng) -> Color {
+    let mut nudge = |channel: u8| (channel as i16 + rng.gen_range(-(JITTER as i16)..=JITTER as i16)).clamp(0, 255) as u8;
+    Color { r: nudge(color.r), g: nudge(color.g), b: nudge(color.b) }
+}
+
+/// Which palette the planets of each module type are colored from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanetPalettes {
+    pub default: Option<Palette>,
+    pub by_module: HashMap<u8, Palette>,
+}
+
+impl PlanetPalettes {
+    /// Looks `default` and the `by_module` names up among the config's own
+    /// palettes (`#rrggbb` lists), then the built-in ones. Every palette in
+    /// `defined` must be valid, used or not.
+    pub fn resolve(
+        default: Option<&str>,
+        by_module: &HashMap<String, String>,
+        defined: &HashMap<String, Vec<String>>,
+    ) -> Result<PlanetPalettes, String> {
+        let lookup = |name: &str| -> Result<Palette, String> {
+            match defined.get(name) {
+                Some(hexes) => {
+                    let colors = hexes.iter().map(|hex| Color::from_hex(hex)).collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| format!("palette {}: {}", name, e))?;
+                    Palette::new(name, colors)
+                }
+                None => Palette::builtin(name).ok_or_else(|| format!("unknown palette: {}", name)),
+            }
+        };
+        for name in defined.keys() {
+            lookup(name)?;
+        }
+        let by_module = by_module.iter()
+            .map(|(module, name)| {
+                let module = module.parse().map_err(|_| format!("invalid module type in module_palettes: {}", module))?;
+                Ok((module, lookup(name)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(PlanetPalettes { default: default.map(lookup).transpose()?, by_module })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.by_module.is_empty()
+    }
+
+    /// The palette for planets of `module_type`, if they are not random.
+    pub fn for_module(&self, module_type: u8) -> Option<&Palette> {
+        self.by_module.get(&module_type).or(self.default.as_ref())
+    }
+}
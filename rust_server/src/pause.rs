@@ -0,0 +1,129 @@
+use serde::{Serialize, Deserialize};
+use std::str::FromStr;
+use std::time::Instant;
+use crate::protocol::PositionUpdate;
+use crate::server::ConnectionId;
+
+/*
+Pausing the simulation, for debugging it.
+
+The admin `pause` command freezes the world: the broadcast loop stops
+ticking, so nothing that advances by ticks (fake players among it) moves,
+the economy stops growing resources back, and players can no longer move,
+respawn, teleport, land, take off, mine or sell. Connections stay up, and
+chat, queries and admin commands keep working. Moons follow the server
+time, which keeps running.
+
+`step [n]` advances exactly n ticks, one by default, broadcasting a State
+for each; `resume` lets the broadcast loop carry on. Everyone is sent a
+`PauseChanged` when the server pauses or resumes, and players who join
+while it is paused get one after their welcome.
+
+Position updates that arrive while paused are handled as the config's
+`paused_updates` says:
+- queue (the default): held, up to `QUEUE_LIMIT` a player, and applied in
+  the order they came when the world next moves, on `step` or `resume`;
+- reject: refused straight away.
+A refused update, like any other refused action, is answered with
+`ServerPaused`. Like the idle tracker, everything takes an explicit `now`.
+*/
+
+/// Most position updates held for one player while paused.
+pub const QUEUE_LIMIT: usize = 32;
+
+/// Most ticks one `step` may advance.
+pub const MAX_STEPS: u64 = 1_000;
+
+/// What happens to position updates while the simulation is paused.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PausedUpdates {
+    #[default]
+    Queue,
+    Reject,
+}
+
+impl FromStr for PausedUpdates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queue" => Ok(PausedUpdates::Queue),
+            "reject" => Ok(PausedUpdates::Reject),
+            other => Err(format!("unknown paused_updates: {} (expected queue or reject)", other)),
+        }
+    }
+}
+
+/// What became of a position update.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Hold {
+    Running(PositionUpdate),  // not paused: apply it now
+    Held,
+    Refused,
+}
+
+/// Whether the simulation is paused, and the updates held meanwhile.
+#[derive(Debug, Clone, Default)]
+pub struct Pause {
+    policy: PausedUpdates,
+    since: Option<Instant>,
+    queued: Vec<(ConnectionId, PositionUpdate)>,  // in the order they came
+}
+
+impl Pause {
+    pub fn new(policy: PausedUpdates) -> Self {
+        Pause { policy, ..Pause::default() }
+    }
+
+    pub fn since(&self) -> Option<Instant> {
+        self.since
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.since.is_some()
+    }
+
+    /// Pauses at `now`; false if already paused.
+    pub fn pause(&mut self, now: Instant) -> bool {
+        if self.is_paused() {
+            return false;
+        }
+        self.since = Some(now);
+        true
+    }
+
+    /// Resumes, returning the held updates to apply; `None` if not paused.
+    pub fn resume(&mut self) -> Option<Vec<(ConnectionId, PositionUpdate)>> {
+        self.since.take()?;
+        Some(self.take_queued())
+    }
+
+    /// Holds `update` from `connection` if paused and the policy and queue
+    /// allow it.
+    pub fn hold(&mut self, connection: ConnectionId, update: PositionUpdate) -> Hold {
+        if !self.is_paused() {
+            return Hold::Running(update);
+        }
+        let held = self.queued.iter().filter(|(c, _)| *c == connection).count();
+        if self.policy == PausedUpdates::Reject || held >= QUEUE_LIMIT {
+            return Hold::Refused;
+        }
+        self.queued.push((connection, update));
+        Hold::Held
+    }
+
+    /// The held updates, in the order they came, leaving none.
+    pub fn take_queued(&mut self) -> Vec<(ConnectionId, PositionUpdate)> {
+        std::mem::take(&mut self.queued)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Drops what `connection` had held, once it is gone.
+    pub fn forget(&mut self, connection: ConnectionId) {
+        self.queued.retain(|(c, _)| *c != connection);
+    }
+}
@@ -0,0 +1,103 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use crate::protocol::{normalize_name, Planet};
+use crate::worldgen::terrain_seed;
+
+/*
+Planet names.
+
+Every planet has a name no other planet in its world has, compared by
+`normalize_name` as player names are, so `Vesh` and `vesh` clash. Names
+are 1 to `MAX_PLANET_NAME_LEN` ASCII letters, digits, `_` or `-`, like
+player names, so they need no quoting in admin commands or logs.
+
+Generated planets are named by `generate_name`, from syllables drawn from
+a generator of their own seeded by the world seed and the planet's id (see
+`worldgen::terrain_seed`), so a seeded world always has the same names and
+naming changes nothing else about it. Two planets given the same name keep
+it apart with a number, `Vesh-2`, the later one in list order taking it
+(see `unique_name`). Planets added by an admin without a name get one the
+same way, and worlds saved or written before planets had names are named
+when they are loaded (see `name_planets`).
+
+The owner of a planet may rename it with `RenamePlanet`; the new name must
+be valid and free, and everyone is told with a `PlanetRenamed`. Admins
+rename any planet with `planet set <id> name <name>`. `TeleportToPlanetNamed`,
+`LandNamed` and the `name` filter of `QueryPlanetsFiltered` find a planet
+by its name rather than its id.
+*/
+
+pub const MAX_PLANET_NAME_LEN: usize = 24;
+
+/// Mixed into the world seed so names are not drawn from the terrain's
+/// generator.
+const NAME_SALT: u64 = 0x6E61_6D65_706C_616E;
+
+const ONSETS: &[&str] = &["b", "br", "c", "d", "dr", "f", "g", "gr", "h", "k", "kr", "l", "m", "n", "p", "r", "s", "sh", "t", "th", "tr", "v", "z"];
+const VOWELS: &[&str] = &["a", "e", "i", "o", "u", "ae", "ia", "io", "ou"];
+const CODAS: &[&str] = &["", "", "", "n", "r", "s", "th", "x", "l"];
+
+pub fn valid_planet_name(name: &str) -> bool {
+    (1..=MAX_PLANET_NAME_LEN).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// The name of planet `id` in a world seeded with `world_seed`: two or
+/// three syllables, capitalized.
+pub fn generate_name(world_seed: u64, id: u32) -> String {
+    let mut rng = StdRng::seed_from_u64(terrain_seed(world_seed, id) ^ NAME_SALT);
+    let syllables = rng.gen_range(2..=3);
+    let mut name = String::new();
+    for i in 0..syllables {
+        name.push_str(ONSETS[rng.gen_range(0..ONSETS.len())]);
+        name.push_str(VOWELS[rng.gen_range(0..VOWELS.len())]);
+        if i == syllables - 1 {
+            name.push_str(CODAS[rng.gen_range(0..CODAS.len())]);
+        }
+    }
+    name[..1].to_ascii_uppercase() + &name[1..]
+}
+
+/// `name`, or the first of `name-2`, `name-3`, ... that is not `taken`,
+/// cut short where it has to be to stay a valid length.
+pub fn unique_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(name) {
+        return name.to_string();
+    }
+    (2u32..)
+        .map(|n| {
+            let suffix = format!("-{}", n);
+            let stem: String = name.chars().take(MAX_PLANET_NAME_LEN - suffix.len()).collect();
+            stem + &suffix
+        })
+        .find(|candidate| !taken(candidate))
+        .expect("some number is free")
+}
+
+/// Gives every planet without a name, or with one an earlier planet has,
+/// a name of its own, generated as for a world seeded with `world_seed`.
+/// Returns how many were named.
+pub fn name_planets(planets: &mut [Planet], world_seed: u64) -> usize {
+    let mut kept = HashSet::new();
+    let keeps: Vec<bool> = planets.iter()
+        .map(|planet| !planet.name.is_empty() && kept.insert(normalize_name(&planet.name)))
+        .collect();
+    let mut named = 0;
+    for (planet, keep) in planets.iter_mut().zip(keeps) {
+        if keep {
+            continue;
+        }
+        let base = if planet.name.is_empty() { generate_name(world_seed, planet.id) } else { planet.name.clone() };
+        planet.name = unique_name(&base, |candidate| kept.contains(&normalize_name(candidate)));
+        kept.insert(normalize_name(&planet.name));
+        named += 1;
+    }
+    named
+}
+
+/// The planet other than `planet_id` already called `name`, if any.
+pub fn name_taken(planets: &[Planet], name: &str, planet_id: u32) -> Option<u32> {
+    let name = normalize_name(name);
+    planets.iter().find(|p| p.id != planet_id && normalize_name(&p.name) == name).map(|p| p.id)
+}
@@ -0,0 +1,111 @@
+use std::sync::{Mutex, MutexGuard};
+use crate::id_hash::IdMap;
+use crate::protocol::Player;
+use crate::server::ConnectionId;
+
+/*
+The connected players, split into shards by connection id so that position
+updates from different players do not wait for one another.
+
+A position update locks only its own player's shard. Anything that needs
+every player at once (joins, which check names and the player limit, and
+world regeneration) takes `lock_all`, which locks the shards in index order;
+since every caller locks them in the same order, two of them cannot
+deadlock. `snapshot` locks one shard at a time, so each player in it is
+consistent but the players may be from slightly different moments, which
+is all a broadcast needs.
+*/
+
+pub const SHARDS: usize = 16;
+
+type Shard = IdMap<ConnectionId, Player>;
+
+pub struct PlayerShards {
+    shards: Vec<Mutex<Shard>>,
+}
+
+impl Default for PlayerShards {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PlayerShards {
+    pub fn new() -> Self {
+        PlayerShards { shards: (0..SHARDS).map(|_| Mutex::new(IdMap::default())).collect() }
+    }
+
+    /// The shard `connection` lives in, locked.
+    pub fn shard(&self, connection: ConnectionId) -> MutexGuard<'_, Shard> {
+        self.shards[connection.0 as usize % SHARDS].lock().unwrap()
+    }
+
+    /// Every shard, locked in index order.
+    pub fn lock_all(&self) -> AllPlayers<'_> {
+        AllPlayers { shards: self.shards.iter().map(|shard| shard.lock().unwrap()).collect() }
+    }
+
+    /// Every player, in player id (so join) order.
+    pub fn snapshot(&self) -> Vec<Player> {
+        let mut players: Vec<Player> = self.shards.iter()
+            .flat_map(|shard| shard.lock().unwrap().values().cloned().collect::<Vec<_>>())
+            .collect();
+        players.sort_by_key(|p| p.id);
+        players
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// All the shards, held at once.
+pub struct AllPlayers<'a> {
+    shards: Vec<MutexGuard<'a, Shard>>,
+}
+
+impl AllPlayers<'_> {
+    fn index(connection: ConnectionId) -> usize {
+        connection.0 as usize % SHARDS
+    }
+
+    pub fn get(&self, connection: &ConnectionId) -> Option<&Player> {
+        self.shards[Self::index(*connection)].get(connection)
+    }
+
+    pub fn get_mut(&mut self, connection: &ConnectionId) -> Option<&mut Player> {
+        self.shards[Self::index(*connection)].get_mut(connection)
+    }
+
+    pub fn insert(&mut self, connection: ConnectionId, player: Player) {
+        self.shards[Self::index(connection)].insert(connection, player);
+    }
+
+    pub fn remove(&mut self, connection: &ConnectionId) -> Option<Player> {
+        self.shards[Self::index(*connection)].remove(connection)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ConnectionId, &Player)> {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Player> {
+        self.iter().map(|(_, player)| player)
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut Player> {
+        self.shards.iter_mut().flat_map(|shard| shard.values_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
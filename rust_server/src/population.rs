@@ -0,0 +1,45 @@
+use crate::protocol::{Planet, PlanetCount, Player, Position};
+
+/*
+How many players are near each planet.
+
+A player is in a planet's vicinity within `VICINITY` of its surface; one in
+the vicinity of several planets counts for the nearest surface only, and one
+between planets counts for none. Idle players still count, since they are
+still there. The server recounts on every broadcast tick and broadcasts a
+`PlanetPopulation` message when the counts change, instead of adding a count
+to every planet in every State. There is no spatial index, so each count
+checks every player against every planet.
+
+New players spawn at the least loaded planet (see `spawn`).
+*/
+
+/// How far from a planet's surface a player still counts as near it.
+pub const VICINITY: f32 = 200.0;
+
+fn surface_distance(planet: &Planet, position: &Position) -> f32 {
+    let (dx, dy, dz) = (planet.position.x - position.x, planet.position.y - position.y, planet.position.z - position.z);
+    (dx * dx + dy * dy + dz * dz).sqrt() - planet.size / 2.0
+}
+
+/// The planet whose vicinity `position` is in, if any.
+pub fn nearest_planet(planets: &[Planet], position: &Position) -> Option<u32> {
+    planets.iter()
+        .map(|planet| (surface_distance(planet, position), planet))
+        .filter(|(distance, _)| *distance <= VICINITY)
+        .min_by(|(a, p), (b, q)| a.total_cmp(b).then(p.id.cmp(&q.id)))
+        .map(|(_, planet)| planet.id)
+}
+
+/// Every planet's population, in planet order.
+pub fn count(planets: &[Planet], players: &[Player]) -> Vec<PlanetCount> {
+    let mut counts: Vec<PlanetCount> = planets.iter().map(|p| PlanetCount { planet_id: p.id, population: 0 }).collect();
+    for player in players {
+        if let Some(id) = nearest_planet(planets, &player.position)
+            && let Some(entry) = counts.iter_mut().find(|c| c.planet_id == id)
+        {
+            entry.population = entry.population.saturating_add(1);
+        }
+    }
+    counts
+}
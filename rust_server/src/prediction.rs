@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use crate::protocol::{seq_newer, Position};
+
+/*
+Client-side prediction for our own ship.
+
+A predicting client moves its ship as soon as the player steers, without
+waiting for the server, and remembers each position update it sends until
+the server has applied it (the player's `last_processed_seq` in a State).
+When the server corrects an update instead (`PositionCorrection`, e.g. for
+leaving the world), the client has usually moved on since, so snapping to
+the corrected position would throw away those moves and pull the ship
+back. Instead `reconcile` drops the inputs up to the corrected one and
+replays the rest on top of the correction: each input is the step it made
+from the one sent before it, so the ship ends up where it would have been
+had the server never needed to correct it.
+*/
+
+/// At most this many inputs are kept; a server that never acknowledges
+/// anything (one from before sequence numbers) would otherwise grow it forever.
+pub const MAX_PENDING_INPUTS: usize = 1024;
+
+/// A position update sent but not yet applied by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingInput {
+    pub seq: u32,
+    /// Where the update put us, moved along with any later corrections
+    pub position: Position,
+    /// How far it moved us from the update before
+    pub step: [f32; 3],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PendingInputs {
+    inputs: VecDeque<PendingInput>,
+    last: Option<Position>,  // where the newest update (or correction) put us
+}
+
+impl PendingInputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an update sent with `seq`. The first one has no step, it is
+    /// where we start.
+    pub fn push(&mut self, seq: u32, position: Position) {
+        let step = self.last.as_ref().map_or([0.0; 3], |last| {
+            [position.x - last.x, position.y - last.y, position.z - last.z]
+        });
+        if self.inputs.len() == MAX_PENDING_INPUTS {
+            self.inputs.pop_front();
+        }
+        self.last = Some(position.clone());
+        self.inputs.push_back(PendingInput { seq, position, step });
+    }
+
+    /// Forgets the inputs up to and including `seq`, which the server has
+    /// applied.
+    pub fn acknowledge(&mut self, seq: u32) {
+        while self.inputs.front().is_some_and(|input| !seq_newer(input.seq, seq)) {
+            self.inputs.pop_front();
+        }
+    }
+
+    /// The server put us at `position` for update `seq`: forgets the inputs up
+    /// to `seq`, replays the rest from `position` and returns where they put
+    /// us now. Later inputs step on from there.
+    pub fn reconcile(&mut self, seq: u32, position: &Position) -> Position {
+        self.acknowledge(seq);
+        let mut predicted = position.clone();
+        for input in &mut self.inputs {
+            predicted.x += input.step[0];
+            predicted.y += input.step[1];
+            predicted.z += input.step[2];
+            input.position = predicted.clone();
+        }
+        self.last = Some(predicted.clone());
+        predicted
+    }
+
+    /// Where the newest input put us, after any corrections.
+    pub fn predicted(&self) -> Option<&Position> {
+        self.last.as_ref()
+    }
+
+    /// The unacknowledged inputs, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &PendingInput> {
+        self.inputs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
@@ -0,0 +1,2002 @@
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::admin::AdminPlan;
+use crate::chat_receipts::ChatError;
+use crate::color::LabelContrast;
+use crate::diff::StateDiff;
+use crate::integrity::{self, CorruptFrame};
+use crate::world::PlanetIndex;
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame, Message, WebSocketConfig},
+    Bytes, Utf8Bytes,
+};
+
+/*
+Game State Protocol:
+
+Server -> client binary messages are a bincode `ServerMessage`:
+- Joined: sent once on join (and again after a format switch) with the
+  player's id and spawn point, next to a planet chosen per player. The
+  state's initial player location is the world origin, kept for older clients.
+- State: tick, server time (ms since start) and the game state
+  - Planet array: each planet has a stable id, size, colors(3), module type,
+    position, owner, up to three moons, and its resources out of their
+    capacity (see `economy`), the terrain parameters clients draw its
+    surface from (see `TerrainParams`), and its name, unique in the world
+    (see `planet_names`). Messages refer to planets by id, never by index,
+    though some take a name instead. A moon has a size, a color and a circular orbit in its planet's
+    horizontal plane; clients place it at the message's server time
+    t (ms) at angle `phase + angular_speed * t / 1000` from the +x axis
+    towards +z, `orbit_radius` from the planet's centre.
+  - Player array: each player has id, name, level, position, velocity,
+    rotation, appearance (two colors and a ship model below SHIP_MODELS),
+    the XP earned from discoveries and the planet they have landed on, if any,
+    and whether they are connected to another instance of a cluster
+  - Initial player location
+  - Belt array: each asteroid belt is a ring around the origin given by its
+    centre radius, width and rock density, plus a seed from which clients
+    place the small rocks themselves, and a few notable asteroids with ids,
+    positions and resources. Asteroid ids come from the planets' id space,
+    so no asteroid shares an id with a planet.
+  - World time: the time of day, from 0 at midnight towards 1, moving on
+    every tick (see `world_clock`)
+- TimeSync: reply to a client time-sync request
+- PlayerIdle / PlayerActive: a player stopped or resumed sending updates.
+  Idle players are left out of periodic State broadcasts (but not the initial
+  state sent on join) until they become active again.
+- PlanetAdded / PlanetRemoved / PlanetUpdated: an admin edited the world;
+  clients patch their copy by planet id instead of waiting for a State.
+- AdminResult: reply to an admin command. AdminPlanned: reply to a dry
+  run of a destructive one, what it would do (see `admin`).
+- PositionCorrection + OutOfBounds: a position update was outside the world
+  radius. The player was clamped onto the boundary, or after repeated
+  attempts (if configured) sent back to their spawn point.
+  The correction carries the sequence number of the update it corrects and
+  the server tick, so a predicting client can replay the updates it has sent
+  since on top of the corrected position instead of snapping back to it
+  (see `prediction`).
+- PlayerTeleported: a player respawned or teleported to a planet; sent to
+  everyone, the mover included, so nobody waits for the next State.
+- TeleportRejected: why a teleport request was refused. A cooldown
+  rejection carries the milliseconds left.
+- PlayerAppearanceChanged: a player changed how they look; sent to everyone.
+  AppearanceRejected tells the sender why a change was refused.
+- Error: why the server is about to close the connection, as an `ErrorCode`
+  in 4000-4999 and a message. The close frame that follows carries the same
+  code, and the message as its reason.
+- PlanetDiscovered: a player came within discovery range of a planet for
+  the first time (see `discovery`), earning XP; sent to everyone.
+  DiscoveryList replies to a `Discoveries` request with the planet ids the
+  player has discovered and the share of the world's planets they make up.
+- PlanetList: reply to a `QueryPlanets` request, the planets within a
+  radius of a point, nearest first (see `query`).
+  PlanetPage: reply to a `QueryPlanetsFiltered` request, one page of the
+  planets matching its filters, and a cursor for the next page.
+- Announcement: the message of the day, sent after Joined, or a server-wide
+  announcement (see `announce`). Unlike Notice it is sent as a structured
+  frame in binary mode too.
+- PlanetPopulation: how many players are near each planet (see
+  `population`), in planet order; sent to everyone when the counts change
+  rather than adding a count to every planet in every State.
+- WorldReset: an admin regenerated the world from `seed`. Clients drop
+  everything they know about the world, planets, players and discoveries
+  alike, and reload it from the full State that follows. Every player has
+  been moved to a new spawn; claims and discoveries are gone, XP is kept.
+- DebugStats: once a second while the client is subscribed, its own
+  position updates received, accepted and rejected over that second (see
+  `stats`), the current tick, its send-queue depth, the round trip of the
+  server's last answered ping and its bandwidth level (see `bandwidth`). Sent to that connection only, never through
+  the broadcast channel.
+- Batch: the events broadcast during one tick (PlayerIdle/PlayerActive,
+  planet edits, PlayerTeleported, PlayerAppearanceChanged, PlanetDiscovered,
+  PlanetPopulation and Chatted) in one frame, in the order they happened, just
+  before the tick's State; see `broadcast`. Clients unwrap it and handle each
+  message as if it had come on its own. A tick with a single event sends
+  it unwrapped. In binary mode each message in the batch is its own bincode
+  encoding, length-prefixed. Only clients with the BATCH capability get
+  batches.
+- Resync: reply to a resync request, the diffs from the requested tick's
+  broadcast to the latest one, to be applied in order. When the tick is no
+  longer in the server's history, or the client lacks the DELTAS
+  capability, a full State is sent instead. With the QUANTIZED capability,
+  players that only moved come as i16 offsets from where the requested
+  tick's broadcast had them (see `quantize`).
+- StateDelta: the periodic snapshot for a client with the QUANTIZED
+  capability, after the first, which is a whole State: the diff from the
+  last snapshot sent to that client, players that only moved as i16
+  offsets (see `quantize`). Applied to the world as the client built it
+  from the State and deltas before, it gives the snapshot of `tick`.
+- LandRejected: why a `Land` or `TakeOff` was refused. A landed player's
+  `landed_on` in State broadcasts is the planet's id, and their position
+  the point on its surface where they stand (see `landing`). Only the
+  owner of a Defense planet may land on planets close to it (see
+  `module_effects`).
+- PlayerRenamed: a player renamed themselves with a `Join` mid-session;
+  sent to everyone. RenameRejected tells the sender why the new name was
+  refused; `NameTaken` also covers a name whose player is away but whose
+  discoveries or wallet the server keeps. A `Join` repeating the player's
+  current name is answered with their `Joined` again, and one whose name
+  is malformed with a ProtocolViolation `Error` that, unlike every other
+  `Error`, is not followed by a close.
+- Keepalive: sent to a KEEPALIVE client that has been silent for a while;
+  it answers with a `KeepaliveAck` of the same seq or is eventually closed
+  with KeepaliveTimeout (see `keepalive`). KeepaliveAck answers a client's
+  own `Keepalive`.
+- Trail: reply to a `QueryTrail` request, the player's last positions with
+  their server times, oldest first, on a server with trails on (see
+  `trail`). Trails: every player's trail, thinned to every 4th point, sent
+  to spectators only, after each State.
+- CorruptFrame: a binary frame from a CRC client failed its check and was
+  dropped; `expected` is the CRC it carried, `got` the one of what arrived
+  (see `integrity`).
+- Mined / MineRejected: reply to `Mine`, the units taken from the planet
+  the player is landed on, more under a meteor shower (see `ambient`), and
+  their cargo since, or why nothing was. The planet's new resources go out
+  as a PlanetUpdated. A player out in space mines the notable asteroid in
+  reach instead (see `module_effects`), and `planet_id` is the asteroid's;
+  its new resources go out in the next State's belts.
+- Sold / SellRejected: reply to `Sell`, the credits earned at the rate
+  where the player is (better near Trade planets, see `module_effects`) and
+  their balance since, or why nothing was sold.
+- PauseChanged: an admin paused or resumed the simulation (see `pause`);
+  sent to everyone, and to players joining while it is paused. While it
+  is, States only come when an admin steps it. ServerPaused answers a
+  position update or other action refused meanwhile.
+- ChatHistory: the last global chat lines, oldest first, sent after
+  Joined, and the reply to a `MoreHistory` request for earlier ones (see
+  `chat_history`). Each line has its sender, its time in ms since the Unix
+  epoch and the room its sender connected to, if any.
+- TimeSet: an admin set the time of day; sent to everyone. Clients jump to
+  the new `world_time` instead of easing towards it.
+- Localized: a notice for one player as a `MessageCode`, a stable code
+  with its parameters, so clients can show it in their own language, and
+  its English `text`. Only clients with the CODES capability get them;
+  the rest get the text as a `Notice`. See `MessageCode` for the codes.
+- Reconnect: this server is handing its players over to the server at
+  `to`, a `ws://` or `wss://` URL, and will close the connection shortly;
+  connect there instead, under the same name (see `replication`). The
+  client library does so by itself.
+- WorldEvent: an ambient event around a planet started (`active`) or
+  ended; sent to everyone, and for each one under way to players and
+  spectators as they join. Solar flares slow players near the planet,
+  meteor showers make mining it yield more, auroras are for show (see
+  `ambient`).
+- FriendList / FriendRejected: reply to `AddFriend`, `RemoveFriend` and
+  `ListFriends`, the player's friends in the order added, each online or
+  not and pending until someone first joins under the name, or why the
+  list did not change. FriendOnline / FriendOffline: a player on this
+  player's list joined or left; sent only to those who friended them (see
+  `friends`).
+- TradeOffered / TradeIncoming: reply to `TradeOffer`, and the offer as
+  the other player gets it, to accept or decline before it expires.
+  TradeCompleted: an accepted offer was settled; both players get one
+  with their own cargo and credits since. TradeEnded: an offer was
+  declined, expired, found one side short when accepted, or lost a
+  player; both get one. TradeRejected: why an offer or an answer to one
+  was refused (see `trade`).
+- PlanetRenamed: a planet's owner, or an admin, renamed it; sent to
+  everyone. PlanetRenameRejected tells the sender of a `RenamePlanet` why
+  the name was refused.
+- ChatAck / ChatRejected: the receipt for a `Chat` with a `client_ref`,
+  to clients with the CHAT_ACKS capability: the chat's number among the
+  connection's accepted chats and the tick of the `Chatted` it went out
+  in, or why it was refused (see `chat_receipts`). Every client's chats are held to the
+  same limits; a refused chat without a receipt is answered with a notice.
+- Chatted: a player's chat, as logged in the chat history, and the tick
+  whose events it went out with; sent to everyone, the sender included,
+  but never for a shadow-muted sender (see `mutes`).
+
+Client -> server:
+- Binary motion update, little-endian = 44 bytes:
+    offset  size  field
+         0     4  seq          u32
+         4     4  position.x   f32
+         8     4  position.y   f32
+        12     4  position.z   f32
+        16     4  velocity[0]  f32  (units/second)
+        20     4  velocity[1]  f32
+        24     4  velocity[2]  f32
+        28     4  rotation[0]  f32  (quaternion x)
+        32     4  rotation[1]  f32  (quaternion y)
+        36     4  rotation[2]  f32  (quaternion z)
+        40     4  rotation[3]  f32  (quaternion w)
+  Older clients may send only the first 16 bytes (seq + position), or the
+  legacy 12-byte frame of just the position; velocity then defaults to zero and
+  rotation to identity.
+- Binary time-sync request: client time in ms as u64, little-endian = 8 bytes
+- Binary resync request: the byte 'R' (0x52) then the last tick the client
+  saw as u64, little-endian = 9 bytes
+- Text: chat, unless it parses as a JSON `ClientMessage` (see JSON mode),
+  which is how binary clients send `SetFormat`, `Admin`, `Respawn`,
+  `TeleportToPlanet`, `SetAppearance`, `Discoveries`, `QueryPlanets`,
+  `DebugStats`, `Land`, `TakeOff`, `Join`, `Keepalive`, `KeepaliveAck`,
+  `QueryTrail`, `Mine`, `Sell`, `MoreHistory`, `AddFriend`,
+  `RemoveFriend`, `ListFriends`, `QueryPlanetsFiltered`, `TradeOffer`,
+  `TradeAccept`, `TradeDecline`, `SetSubscriptions`, `RenamePlanet`,
+  `TeleportToPlanetNamed` and `LandNamed` messages
+
+Each player's `last_processed_seq` in state broadcasts is the newest sequence
+number the server has applied for them, compared with serial-number arithmetic
+so the counter may wrap at u32::MAX.
+
+JSON mode:
+A client may ask for JSON instead, either with `?format=json` on the
+connection URL or by sending `{"type":"SetFormat","format":"json"}` as its very
+first message (the initial state and welcome are then re-sent as JSON). In
+JSON mode every server message is a text frame holding the `ServerMessage`
+as JSON, tagged by a `type` field:
+    {"type":"TimeSync","client_time_ms":12,"server_time_ms":40,"tick":3}
+and the client sends `ClientMessage`s the same way, e.g.
+    {"type":"Chat","text":"hello"}
+    {"type":"Position","seq":7,"position":{"x":1.0,"y":2.0,"z":3.0}}
+(velocity defaults to zero and rotation to identity when omitted). Binary
+frames are still accepted from JSON clients. Both modes share one server;
+in binary mode `Notice` and `Echo` keep going out as plain text frames.
+
+Capabilities:
+Clients declare the optional frames they understand as a `Capabilities`
+bitfield, `?caps=7` on the connection URL (BATCH = 1, DELTAS = 2,
+KEEPALIVE = 4, CRC = 8, TERRAIN = 16, CODES = 32, NAMES = 64,
+QUANTIZED = 128, LABELS = 256). The server keeps the bits it also knows and echoes them in
+the `X-Galavox-Capabilities` response header; unknown bits are ignored.
+Clients that send no `caps` (everything older than the field) get none:
+each event of a batch comes in its own frame, resync requests are answered
+with a full State, no keepalives are sent (see `keepalive`), binary
+frames carry no CRC (see `integrity`), planets in binary frames come
+without their `terrain`, `name` and label colors, laid out as before they
+existed (JSON always has them; TERRAIN, NAMES and LABELS each bring back
+their own fields), and
+notices come as plain `Notice`s rather than `Localized`.
+
+Subscriptions:
+Clients that want only some broadcasts, say chat, declare the kinds they
+want as a `Subscriptions` bitfield, `?subs=2` on the connection URL or a
+`SetSubscriptions` message at any time (STATE = 1, CHAT = 2, WORLD = 4,
+PLAYERS = 8, PLANETS = 16; see `subscriptions`). Clients that say nothing
+get everything.
+*/
+
+/// Largest binary frame a client may send. Position updates and chat are tiny,
+/// so anything bigger is treated as a protocol violation.
+pub const MAX_CLIENT_FRAME_SIZE: usize = 4 * 1024;
+
+/// Hard transport limit enforced by tungstenite before a message is buffered.
+pub const MAX_WS_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Most bytes a single bincode server message may take to decode, so that a
+/// corrupt frame cannot claim more than a real snapshot would need.
+pub const MAX_BINCODE_MESSAGE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The options of `bincode::deserialize`, plus `MAX_BINCODE_MESSAGE_SIZE`.
+fn bincode_decoder() -> impl bincode::Options {
+    use bincode::Options;
+    bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(MAX_BINCODE_MESSAGE_SIZE)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// Most moons a planet can have.
+pub const MAX_MOONS: usize = 3;
+
+/// Planet module types in use, from 0; generated planets get one of these.
+pub const MODULE_TYPES: u8 = 5;
+
+/// A small body circling its planet; see `Moon::position_at`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Moon {
+    pub size: f32,           // diameter
+    pub color: Color,
+    pub orbit_radius: f32,   // from the planet's centre
+    pub angular_speed: f32,  // radians per second; negative orbits the other way
+    pub phase: f32,          // angle in radians at server time 0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Planet {
+    pub id: u32,  // stable across edits, never reused
+    pub size: f32,
+    pub colors: [Color; 3],  // 3 colors as specified
+    pub module_type: u8,     // 0-255 for different module types
+    pub position: Position,
+    #[serde(default)]
+    pub owner: Option<u32>,  // owning player's id
+    #[serde(default)]
+    pub moons: Vec<Moon>,    // at most `MAX_MOONS`
+    #[serde(default)]
+    pub resources: u32,      // units left, regrowing up to `capacity` (see `economy`)
+    #[serde(default)]
+    pub capacity: u32,
+    // Left out of binary frames for clients without TERRAIN
+    #[serde(default, skip_serializing_if = "omit_terrain", deserialize_with = "terrain_unless_omitted")]
+    pub terrain: TerrainParams,
+    // Unique in the world (see `planet_names`); left out of binary frames
+    // for clients without NAMES
+    #[serde(default, skip_serializing_if = "omit_names", deserialize_with = "names_unless_omitted")]
+    pub name: String,
+    // Worked out from `colors` (see `color`); left out of binary frames for
+    // clients without LABELS
+    #[serde(default, skip_serializing_if = "omit_labels", deserialize_with = "labels_unless_omitted")]
+    pub primary_display_color: Color,
+    #[serde(default, skip_serializing_if = "omit_labels", deserialize_with = "labels_unless_omitted")]
+    pub label_contrast: LabelContrast,
+}
+
+/// How a client draws a planet's surface procedurally; the server never
+/// uses it. Generated planets get theirs from the world seed and their
+/// module type (see `worldgen`); planets from before terrain have all zero,
+/// a smooth, dry, airless rock.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TerrainParams {
+    pub seed: u64,         // for the client's noise
+    pub roughness: f32,    // 0 smooth to 1 jagged
+    pub ocean_level: f32,  // 0 dry to 1 all ocean
+    pub atmosphere: u8,    // thickness, 0 for none
+}
+
+impl TerrainParams {
+    /// Whether the values are in range: roughness and ocean level in 0..=1.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.roughness) {
+            return Err("roughness must be between 0 and 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.ocean_level) {
+            return Err("ocean_level must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Players near a planet; see `population`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct PlanetCount {
+    pub planet_id: u32,
+    pub population: u16,
+}
+
+/// Where a player was at a server time (ms since start); see `trail`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TrailPoint {
+    pub time_ms: u64,
+    pub position: Position,
+}
+
+/// A global chat message as logged; see `chat_history`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ChatLine {
+    pub sender: String,
+    pub timestamp_ms: u64,  // since the Unix epoch, strictly increasing
+    pub room: Option<String>,
+    pub text: String,
+}
+
+/// A name on a player's friend list; see `friends`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Friend {
+    pub name: String,  // as friended
+    pub online: bool,
+    pub pending: bool,  // nobody has joined under it yet
+}
+
+/// The order of a `QueryPlanetsFiltered` reply; see `query`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum PlanetSort {
+    Distance,  // nearest to the query's centre first
+    Size,      // largest first
+}
+
+/// Where the next page of a `QueryPlanetsFiltered` reply starts: just after
+/// the planet with `planet_id`, whose sort key was `key`. Opaque to clients,
+/// which send it back unchanged; see `query`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlanetCursor {
+    pub key: f64,
+    pub planet_id: u32,
+}
+
+impl std::fmt::Display for PlanetCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.key, self.planet_id)
+    }
+}
+
+impl FromStr for PlanetCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s.split_once(':').and_then(|(key, id)| Some(PlanetCursor { key: key.parse().ok()?, planet_id: id.parse().ok()? }));
+        parsed.ok_or_else(|| format!("invalid cursor: {}", s))
+    }
+}
+
+/// What kind of ambient event is under way; see `ambient`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AmbientKind {
+    SolarFlare,    // slows players near the planet
+    MeteorShower,  // mining the planet yields more
+    Aurora,        // for show
+}
+
+/// An ambient event around a planet, from `start_tick` for
+/// `duration_ticks`; see `ambient`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AmbientEvent {
+    pub id: u32,
+    pub kind: AmbientKind,
+    pub planet_id: u32,
+    pub start_tick: u64,
+    pub duration_ticks: u64,
+}
+
+impl AmbientEvent {
+    /// The first tick it is no longer under way.
+    pub fn end_tick(&self) -> u64 {
+        self.start_tick + self.duration_ticks
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlayerTrail {
+    pub player_id: u32,
+    pub points: Vec<TrailPoint>,  // oldest first
+}
+
+/// A ring of small rocks around the world origin. The rocks are not tracked:
+/// clients scatter them from `rock_seed`. Only the notable asteroids are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Belt {
+    pub center_radius: f32,  // of the ring's middle, in the y = 0 plane
+    pub width: f32,          // from the inner edge to the outer one
+    pub density: f32,        // small rocks per 10,000 square units of ring
+    pub rock_seed: u64,
+    pub notable: Vec<Asteroid>,
+}
+
+/// A rock in a belt that the server keeps track of.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Asteroid {
+    pub id: u32,  // from the same id space as planets
+    pub size: f32,  // diameter
+    pub position: Position,
+    pub resources: u32,  // units left to mine
+}
+
+/// Ship models clients can draw, numbered from 0.
+pub const SHIP_MODELS: u8 = 4;
+
+/// How a player is drawn; see `PlayerAppearance::for_name`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlayerAppearance {
+    pub primary: Color,
+    pub secondary: Color,
+    pub model: u8,  // below `SHIP_MODELS`
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Player {
+    pub id: u32,
+    pub name: Arc<str>,  // shared, so that cloning the state does not copy names
+    pub level: u32,
+    pub position: Position,
+    pub velocity: [f32; 3],
+    pub rotation: [f32; 4],  // quaternion (x, y, z, w)
+    pub last_processed_seq: u32,
+    pub idle: bool,
+    pub appearance: PlayerAppearance,
+    pub xp: u32,  // from discoveries; see `discovery`
+    pub landed_on: Option<u32>,  // the planet the player is standing on; see `landing`
+    #[serde(default)]
+    pub remote: bool,  // connected to another instance of a cluster; see `cluster`
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct GameState {
+    pub planets: Vec<Planet>,
+    pub players: Vec<Player>,
+    pub initial_player_location: Position,
+    #[serde(default)]
+    pub belts: Vec<Belt>,
+    #[serde(default)]
+    pub world_time: f32,  // the time of day, 0 to 1 from midnight; see `world_clock`
+    #[serde(skip)]
+    pub(crate) planet_index: PlanetIndex,
+}
+
+tokio::task_local! {
+    static TASK_CAPABILITIES: Capabilities;
+}
+
+thread_local! {
+    // Set only while a binary frame is encoded or decoded
+    static OMIT_TERRAIN: Cell<bool> = const { Cell::new(false) };
+    static OMIT_NAMES: Cell<bool> = const { Cell::new(false) };
+    static OMIT_LABELS: Cell<bool> = const { Cell::new(false) };
+    static OMIT_MOVES: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with frames encoded and decoded for a peer with
+/// `capabilities`: without TERRAIN, planets in binary frames go without
+/// their terrain, without NAMES without their names, without LABELS
+/// without their label colors, without QUANTIZED diffs go without their
+/// quantized moves, and without CODES, `Localized` notices go as plain
+/// `Notice`s. Outside it, and `encoding_for_task`, frames carry everything.
+pub fn encoding_for<T>(capabilities: Capabilities, f: impl FnOnce() -> T) -> T {
+    TASK_CAPABILITIES.sync_scope(capabilities, f)
+}
+
+/// Like `encoding_for`, for everything `future` encodes and decodes.
+pub async fn encoding_for_task<F: Future>(capabilities: Capabilities, future: F) -> F::Output {
+    TASK_CAPABILITIES.scope(capabilities, future).await
+}
+
+/// The capabilities frames are being encoded for (see `encoding_for`).
+fn peer_capabilities() -> Capabilities {
+    TASK_CAPABILITIES.try_with(|capabilities| *capabilities).unwrap_or(Capabilities::SUPPORTED)
+}
+
+/// Runs one bincode encode or decode, planets with or without terrain,
+/// names and label colors, and diffs with or without moves, as `encoding_for` says.
+fn with_binary_layout<T>(f: impl FnOnce() -> T) -> T {
+    let capabilities = peer_capabilities();
+    let outer_terrain = OMIT_TERRAIN.replace(!capabilities.contains(Capabilities::TERRAIN));
+    let outer_names = OMIT_NAMES.replace(!capabilities.contains(Capabilities::NAMES));
+    let outer_labels = OMIT_LABELS.replace(!capabilities.contains(Capabilities::LABELS));
+    let outer_moves = OMIT_MOVES.replace(!capabilities.contains(Capabilities::QUANTIZED));
+    let result = f();
+    OMIT_TERRAIN.set(outer_terrain);
+    OMIT_NAMES.set(outer_names);
+    OMIT_LABELS.set(outer_labels);
+    OMIT_MOVES.set(outer_moves);
+    result
+}
+
+/// For `skip_serializing_if` on terrain fields.
+pub(crate) fn omit_terrain<T>(_: &T) -> bool {
+    OMIT_TERRAIN.get()
+}
+
+/// For `deserialize_with` on terrain fields: an omitted terrain takes no
+/// bytes, so nothing is read for it.
+pub(crate) fn terrain_unless_omitted<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    if OMIT_TERRAIN.get() {
+        return Ok(T::default());
+    }
+    T::deserialize(deserializer)
+}
+
+/// For `skip_serializing_if` on planet names.
+pub(crate) fn omit_names<T>(_: &T) -> bool {
+    OMIT_NAMES.get()
+}
+
+/// For `deserialize_with` on planet names, as `terrain_unless_omitted`.
+pub(crate) fn names_unless_omitted<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    if OMIT_NAMES.get() {
+        return Ok(T::default());
+    }
+    T::deserialize(deserializer)
+}
+
+/// For `skip_serializing_if` on planets' label colors.
+pub(crate) fn omit_labels<T>(_: &T) -> bool {
+    OMIT_LABELS.get()
+}
+
+/// For `deserialize_with` on planets' label colors, as
+/// `terrain_unless_omitted`.
+pub(crate) fn labels_unless_omitted<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    if OMIT_LABELS.get() {
+        return Ok(T::default());
+    }
+    T::deserialize(deserializer)
+}
+
+/// For `skip_serializing_if` on a diff's quantized moves.
+pub(crate) fn omit_moves<T>(_: &T) -> bool {
+    OMIT_MOVES.get()
+}
+
+/// For `deserialize_with` on a diff's quantized moves, as `terrain_unless_omitted`.
+pub(crate) fn moves_unless_omitted<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    if OMIT_MOVES.get() {
+        return Ok(T::default());
+    }
+    T::deserialize(deserializer)
+}
+
+/// Declares a wire enum once for both encodings. JSON uses the enum itself,
+/// tagged with `#[serde(tag = "type")]`. Bincode cannot decode internally tagged
+/// enums (it has no `deserialize_any`), so binary frames go through private,
+/// externally tagged mirrors with the same variants in the same order: always
+/// use `to_bincode` / `from_bincode` rather than `bincode::serialize` on the enum.
+macro_rules! wire_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident via $mirror:ident {
+            $(
+                $(#[$vmeta:meta])*
+                $variant:ident { $( $(#[$fmeta:meta])* $field:ident : $ty:ty ),* $(,)? }
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+        #[serde(tag = "type")]
+        pub enum $name {
+            $( $(#[$vmeta])* $variant { $( $(#[$fmeta])* $field: $ty ),* } ),*
+        }
+
+        mod $mirror {
+            use super::*;
+
+            #[derive(Serialize)]
+            pub(super) enum Borrowed<'a> {
+                $( $variant { $( $field: &'a $ty ),* } ),*
+            }
+
+            #[derive(Deserialize)]
+            pub(super) enum Owned {
+                $( $variant { $( $field: $ty ),* } ),*
+            }
+        }
+
+        impl $name {
+            /// Every variant's name, in wire order.
+            pub const VARIANTS: &'static [&'static str] = &[$( stringify!($variant) ),*];
+
+            /// The name of the variant a bincode frame holds, read from its
+            /// tag alone without decoding the rest.
+            pub fn bincode_variant(data: &[u8]) -> Option<&'static str> {
+                let tag = u32::from_le_bytes(data.get(..4)?.try_into().ok()?);
+                Self::VARIANTS.get(tag as usize).copied()
+            }
+
+            pub fn to_bincode(&self) -> bincode::Result<Vec<u8>> {
+                let mirror = match self {
+                    $( $name::$variant { $($field),* } => $mirror::Borrowed::$variant { $($field),* } ),*
+                };
+                with_binary_layout(|| bincode::serialize(&mirror))
+            }
+
+            /// Like `to_bincode`, appending to `buf` instead of allocating.
+            pub fn to_bincode_into(&self, buf: &mut Vec<u8>) -> bincode::Result<()> {
+                let mirror = match self {
+                    $( $name::$variant { $($field),* } => $mirror::Borrowed::$variant { $($field),* } ),*
+                };
+                with_binary_layout(|| bincode::serialize_into(buf, &mirror))
+            }
+
+            pub fn from_bincode(data: &[u8]) -> bincode::Result<Self> {
+                use bincode::Options;
+                Ok(match with_binary_layout(|| bincode_decoder().deserialize::<$mirror::Owned>(data))? {
+                    $( $mirror::Owned::$variant { $($field),* } => $name::$variant { $($field),* } ),*
+                })
+            }
+        }
+    };
+}
+
+wire_enum! {
+    pub enum ServerMessage via server_message_bincode {
+        State {
+            tick: u64,
+            server_time_ms: u64,
+            state: GameState,
+        },
+        TimeSync {
+            client_time_ms: u64,  // echoed from the request
+            server_time_ms: u64,
+            tick: u64,
+        },
+        PlayerIdle {
+            player_id: u32,
+        },
+        PlayerActive {
+            player_id: u32,
+        },
+        /// Server announcements and warnings (a plain text frame in binary mode)
+        Notice {
+            text: String,
+        },
+        /// A chat message echoed back to its sender (`Echo: ...` text in binary mode)
+        Echo {
+            text: String,
+        },
+        Resync {
+            from_tick: u64,
+            tick: u64,
+            diffs: Vec<StateDiff>,
+        },
+        PlanetAdded {
+            planet: Planet,
+        },
+        PlanetRemoved {
+            planet_id: u32,
+        },
+        PlanetUpdated {
+            planet: Planet,
+        },
+        AdminResult {
+            ok: bool,
+            message: String,
+        },
+        /// Where the server put the player instead of where they asked to be
+        PositionCorrection {
+            position: Position,
+            seq: Option<u32>,  // the update corrected; None for legacy frames
+            tick: u64,
+        },
+        OutOfBounds {
+            radius: f32,
+            teleported: bool,  // sent back to the spawn point rather than clamped
+        },
+        /// Join acknowledgement: this connection's player and where it spawned
+        Joined {
+            player_id: u32,
+            spawn: Position,
+            spawn_planet_id: Option<u32>,
+        },
+        PlayerTeleported {
+            player_id: u32,
+            position: Position,
+        },
+        TeleportRejected {
+            error: TeleportError,
+        },
+        PlayerAppearanceChanged {
+            player_id: u32,
+            appearance: PlayerAppearance,
+        },
+        /// Why a `SetAppearance` was refused
+        AppearanceRejected {
+            reason: String,
+        },
+        /// Sent just before the server closes the connection; `code` is an
+        /// `ErrorCode` and matches the close frame's code
+        Error {
+            code: u16,
+            message: String,
+        },
+        /// A player came near a planet for the first time; sent to everyone
+        PlanetDiscovered {
+            player_id: u32,
+            planet_id: u32,
+        },
+        /// Reply to `Discoveries`
+        DiscoveryList {
+            planet_ids: Vec<u32>,
+            explored_percent: f32,
+        },
+        /// Reply to `QueryPlanets`, nearest first
+        PlanetList {
+            planets: Vec<Planet>,
+        },
+        /// The message of the day, or an announcement to everyone
+        Announcement {
+            text: String,
+        },
+        /// How many players are near each planet, sent when the counts change
+        PlanetPopulation {
+            counts: Vec<PlanetCount>,
+        },
+        /// The world was regenerated; a full State follows
+        WorldReset {
+            seed: u64,
+        },
+        /// This connection's own counters over the last second, while it
+        /// is subscribed with `DebugStats`
+        DebugStats {
+            tick: u64,
+            updates_received: u32,
+            updates_accepted: u32,
+            updates_rejected: u32,
+            send_queue_depth: u32,
+            rtt_ms: Option<u32>,  // None until a ping has been answered
+            bandwidth_level: u8,  // States come every 2^level ticks; see `bandwidth`
+        },
+        /// Broadcast events from one tick in a single frame, in the order
+        /// they happened
+        Batch {
+            messages: Batched,
+        },
+        /// Why a `Land` or `TakeOff` was refused
+        LandRejected {
+            error: LandError,
+        },
+        /// A player took a new name; sent to everyone
+        PlayerRenamed {
+            player_id: u32,
+            old: String,
+            new: String,
+        },
+        /// Why a `Join` with a new name was refused
+        RenameRejected {
+            error: RenameError,
+        },
+        /// To be answered with a `KeepaliveAck` of the same `seq`
+        Keepalive {
+            seq: u32,
+        },
+        KeepaliveAck {
+            seq: u32,
+        },
+        /// Reply to `QueryTrail`
+        Trail {
+            trail: PlayerTrail,
+        },
+        /// Every player's recent path, thinned; sent to spectators after
+        /// each State
+        Trails {
+            trails: Vec<PlayerTrail>,
+        },
+        /// A binary frame from a CRC client failed its check and was
+        /// dropped (see `integrity`)
+        CorruptFrame {
+            expected: u32,
+            got: u32,
+        },
+        /// Reply to `Mine`: what was taken and the player's cargo since
+        Mined {
+            planet_id: u32,
+            amount: u32,
+            cargo: u64,
+        },
+        MineRejected {
+            error: MineError,
+        },
+        /// Reply to `Sell`: the credits earned and the player's balance since
+        Sold {
+            amount: u32,
+            earned: u64,
+            credits: u64,
+        },
+        SellRejected {
+            error: SellError,
+        },
+        /// The simulation was paused or resumed by an admin (see `pause`)
+        PauseChanged {
+            paused: bool,
+            tick: u64,
+        },
+        /// Reply to a move or other action refused because the simulation
+        /// is paused
+        ServerPaused {
+            tick: u64,
+        },
+        /// Global chat lines, oldest first (see `chat_history`)
+        ChatHistory {
+            messages: Vec<ChatLine>,
+        },
+        /// An admin set the time of day; snap to it rather than lerp
+        TimeSet {
+            world_time: f32,
+        },
+        /// A notice as a stable code and its parameters, with the English
+        /// rendering for convenience (see `MessageCode`)
+        Localized {
+            message: MessageCode,
+            text: String,
+        },
+        /// Connect to `to` instead; this server is going away
+        Reconnect {
+            to: String,
+        },
+        /// An ambient event started or, when not `active`, ended (see
+        /// `ambient`)
+        WorldEvent {
+            event: AmbientEvent,
+            active: bool,
+        },
+        /// Reply to `AddFriend`, `RemoveFriend` and `ListFriends` (see
+        /// `friends`)
+        FriendList {
+            friends: Vec<Friend>,
+        },
+        FriendRejected {
+            reason: String,
+        },
+        /// A player on this player's friend list joined
+        FriendOnline {
+            name: String,
+        },
+        FriendOffline {
+            name: String,
+        },
+        /// Reply to `QueryPlanetsFiltered`; `next_cursor` asks for the next
+        /// page, if there is one
+        PlanetPage {
+            results: Vec<Planet>,
+            next_cursor: Option<PlanetCursor>,
+        },
+        /// Reply to `TradeOffer`: the offer is on its way (see `trade`)
+        TradeOffered {
+            offer_id: u32,
+            to_player: u32,
+            expires_in_ms: u64,
+        },
+        /// Another player offers this one cargo for credits
+        TradeIncoming {
+            offer_id: u32,
+            from_player: u32,
+            give_resources: u32,
+            want_credits: u64,
+            expires_in_ms: u64,
+        },
+        /// Reply to a `TradeOffer`, `TradeAccept` or `TradeDecline` that was refused
+        TradeRejected {
+            error: TradeError,
+        },
+        /// An offer was accepted and settled; sent to both players, each
+        /// with their own wallet since
+        TradeCompleted {
+            offer_id: u32,
+            cargo: u64,
+            credits: u64,
+        },
+        /// An offer ended with nothing changing hands; sent to both players
+        TradeEnded {
+            offer_id: u32,
+            reason: TradeEnd,
+        },
+        /// A planet took a new name; sent to everyone (see `planet_names`)
+        PlanetRenamed {
+            planet_id: u32,
+            name: String,
+        },
+        /// Why a `RenamePlanet` was refused
+        PlanetRenameRejected {
+            error: PlanetRenameError,
+        },
+        /// Reply to a dry-run admin command: what it would do
+        AdminPlanned {
+            plan: AdminPlan,
+        },
+        /// A chat with a `client_ref` was accepted (see `chat_receipts`)
+        ChatAck {
+            client_ref: u64,
+            server_seq: u32,
+            tick: u64,
+        },
+        /// A chat with a `client_ref` was refused
+        ChatRejected {
+            client_ref: u64,
+            error: ChatError,
+        },
+        /// A player's chat, as logged, among the events of tick `tick`
+        Chatted {
+            tick: u64,
+            line: ChatLine,
+        },
+        /// The periodic snapshot of `tick`, as the diff from that of
+        /// `from_tick`, for QUANTIZED clients
+        StateDelta {
+            from_tick: u64,
+            tick: u64,
+            server_time_ms: u64,
+            diff: StateDiff,
+        },
+    }
+}
+
+/// The messages of a `Batch`. JSON nests them as usual; bincode cannot decode
+/// the tagged form, so there each one is nested as its own binary encoding.
+/// A batch never holds another batch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Batched(pub Vec<ServerMessage>);
+
+impl Serialize for Batched {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return self.0.serialize(serializer);
+        }
+        let encoded = self.0.iter()
+            .map(ServerMessage::to_bincode)
+            .collect::<bincode::Result<Vec<_>>>()
+            .map_err(serde::ser::Error::custom)?;
+        encoded.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Batched {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let messages = if deserializer.is_human_readable() {
+            Vec::<ServerMessage>::deserialize(deserializer)?
+        } else {
+            Vec::<Vec<u8>>::deserialize(deserializer)?.iter()
+                .map(|data| ServerMessage::from_bincode(data))
+                .collect::<bincode::Result<_>>()
+                .map_err(serde::de::Error::custom)?
+        };
+        if messages.iter().any(|m| matches!(m, ServerMessage::Batch { .. })) {
+            return Err(serde::de::Error::custom("a batch inside a batch"));
+        }
+        Ok(Batched(messages))
+    }
+}
+
+impl JsonSchema for Batched {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Batched".into()
+    }
+
+    fn json_schema(generator: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        generator.subschema_for::<Vec<ServerMessage>>()
+    }
+}
+
+wire_enum! {
+    /// What a JSON-mode client sends. Binary-mode clients use the fixed
+    /// layouts above and plain text chat, which decode into the same variants.
+    pub enum ClientMessage via client_message_bincode {
+        Position {
+            seq: Option<u32>,
+            position: Position,
+            #[serde(default)]
+            velocity: [f32; 3],
+            #[serde(default = "identity_rotation")]
+            rotation: [f32; 4],
+        },
+        TimeSync {
+            client_time_ms: u64,
+        },
+        Chat {
+            text: String,
+            /// Echoed in the chat's `ChatAck` or `ChatRejected`, for
+            /// clients with the CHAT_ACKS capability (see `chat_receipts`)
+            #[serde(default)]
+            client_ref: Option<u64>,
+        },
+        /// Only honoured as a connection's first message
+        SetFormat {
+            format: WireFormat,
+        },
+        /// Asks for the diffs since the last broadcast tick the client saw
+        ResyncFrom {
+            tick: u64,
+        },
+        /// A console command, run only if `token` matches the server's admin token
+        Admin {
+            token: String,
+            command: String,
+            /// Only work out what it would do (see `admin`)
+            #[serde(default)]
+            dry_run: bool,
+        },
+        /// Back to this player's spawn point
+        Respawn {},
+        /// To a planet the player owns (any planet on a casual server)
+        TeleportToPlanet {
+            planet_id: u32,
+        },
+        SetAppearance {
+            appearance: PlayerAppearance,
+        },
+        /// Asks which planets this player has discovered
+        Discoveries {},
+        /// Asks for the planets near a point (see `query`)
+        QueryPlanets {
+            center: Position,
+            radius: f32,
+            max_results: u32,
+        },
+        /// Starts or stops this connection's `DebugStats` frames
+        DebugStats {
+            enabled: bool,
+        },
+        /// Onto the surface of a planet close by (see `landing`)
+        Land {
+            planet_id: u32,
+        },
+        TakeOff {},
+        /// On a connection that has already joined: the player's name again,
+        /// which is acknowledged, or a new one to rename them to
+        Join {
+            name: String,
+        },
+        /// Answered with a `KeepaliveAck` of the same `seq`
+        Keepalive {
+            seq: u32,
+        },
+        KeepaliveAck {
+            seq: u32,
+        },
+        /// Asks for a player's recent positions (see `trail`)
+        QueryTrail {
+            player_id: u32,
+        },
+        /// Up to `amount` units of the planet the player is landed on, or
+        /// of the notable asteroid in reach, into their cargo (see
+        /// `module_effects`)
+        Mine {
+            amount: u32,
+        },
+        /// `amount` units of cargo, for credits
+        Sell {
+            amount: u32,
+        },
+        /// Up to `limit` global chat lines from before `before_timestamp`
+        /// (see `chat_history`)
+        MoreHistory {
+            before_timestamp: u64,
+            limit: u32,
+        },
+        /// Onto this player's friend list (see `friends`)
+        AddFriend {
+            name: String,
+        },
+        RemoveFriend {
+            name: String,
+        },
+        ListFriends {},
+        /// Asks for a page of the planets matching every filter given (see
+        /// `query`); `cursor` is the last reply's `next_cursor`, or none for
+        /// the first page
+        QueryPlanetsFiltered {
+            center: Position,
+            module_type: Option<u8>,
+            min_size: Option<f32>,
+            max_size: Option<f32>,
+            owned_by: Option<u32>,
+            #[serde(default)]
+            unclaimed_only: bool,
+            sort: PlanetSort,
+            limit: u32,
+            #[serde(default)]
+            cursor: Option<PlanetCursor>,
+            /// Only the planet of this name (see `planet_names`)
+            #[serde(default)]
+            name: Option<String>,
+        },
+        /// `give_resources` units of this player's cargo to another player
+        /// for `want_credits` of theirs (see `trade`)
+        TradeOffer {
+            to_player: u32,
+            give_resources: u32,
+            want_credits: u64,
+        },
+        TradeAccept {
+            offer_id: u32,
+        },
+        TradeDecline {
+            offer_id: u32,
+        },
+        /// The kinds of broadcast this connection wants from now on, as a
+        /// `Subscriptions` bitfield (see `subscriptions`)
+        SetSubscriptions {
+            mask: u32,
+        },
+        /// A new name for a planet this player owns (see `planet_names`)
+        RenamePlanet {
+            planet_id: u32,
+            name: String,
+        },
+        /// `TeleportToPlanet`, to the planet of this name
+        TeleportToPlanetNamed {
+            name: String,
+        },
+        /// `Land`, on the planet of this name
+        LandNamed {
+            name: String,
+        },
+    }
+}
+
+fn identity_rotation() -> [f32; 4] {
+    IDENTITY_ROTATION
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum LandError {
+    UnknownPlanet { planet_id: u32 },
+    TooFar { distance: f32 },  // from the surface
+    TooFast { speed: f32 },
+    AlreadyLanded { planet_id: u32 },
+    NotLanded,
+    Defended { planet_id: u32, owner: u32 },  // by that owned Defense planet
+    UnknownName { name: String },
+}
+
+impl std::fmt::Display for LandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LandError::UnknownPlanet { planet_id } => write!(f, "there is no planet {}", planet_id),
+            LandError::TooFar { distance } => write!(f, "{:.1} units from the surface, too far to land", distance),
+            LandError::TooFast { speed } => write!(f, "moving at {:.1} units/s, too fast to land", speed),
+            LandError::AlreadyLanded { planet_id } => write!(f, "already landed on planet {}", planet_id),
+            LandError::NotLanded => write!(f, "not landed"),
+            LandError::Defended { planet_id, owner } => write!(f, "defended by planet {}, only player {} may land", planet_id, owner),
+            LandError::UnknownName { name } => write!(f, "there is no planet called {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum MineError {
+    NotLanded,
+    Depleted { planet_id: u32 },
+    ZeroAmount,
+    Elsewhere,  // another instance of the cluster holds the planets
+}
+
+impl std::fmt::Display for MineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MineError::NotLanded => write!(f, "not landed, nor in reach of a notable asteroid"),
+            MineError::Depleted { planet_id } => write!(f, "planet {} has no resources left", planet_id),
+            MineError::ZeroAmount => write!(f, "nothing to mine"),
+            MineError::Elsewhere => write!(f, "the planets are mined on the cluster's primary instance"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum SellError {
+    ZeroAmount,
+    NotEnough { cargo: u64 },
+}
+
+impl std::fmt::Display for SellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SellError::ZeroAmount => write!(f, "nothing to sell"),
+            SellError::NotEnough { cargo } => write!(f, "only {} unit(s) in cargo", cargo),
+        }
+    }
+}
+
+/// Why a trade offer, or an answer to one, was refused; see `trade`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TradeError {
+    UnknownPlayer { player_id: u32 },
+    OwnOffer,
+    EmptyOffer,
+    TooManyOffers { limit: u32 },
+    UnknownOffer { offer_id: u32 },  // never made, already answered, expired or not to this player
+}
+
+impl std::fmt::Display for TradeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TradeError::UnknownPlayer { player_id } => write!(f, "there is no player {}", player_id),
+            TradeError::OwnOffer => write!(f, "you cannot trade with yourself"),
+            TradeError::EmptyOffer => write!(f, "the offer gives and asks nothing"),
+            TradeError::TooManyOffers { limit } => write!(f, "you already have {} offers waiting", limit),
+            TradeError::UnknownOffer { offer_id } => write!(f, "there is no offer {} waiting for you", offer_id),
+        }
+    }
+}
+
+/// How a trade offer ended without anything changing hands; see `trade`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TradeEnd {
+    Declined,
+    Expired,
+    Cancelled,         // one of the players left
+    NotEnoughCargo,    // the offerer's, at accept time
+    NotEnoughCredits,  // the other player's, at accept time
+}
+
+impl std::fmt::Display for TradeEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TradeEnd::Declined => write!(f, "declined"),
+            TradeEnd::Expired => write!(f, "expired"),
+            TradeEnd::Cancelled => write!(f, "cancelled, a player left"),
+            TradeEnd::NotEnoughCargo => write!(f, "the offerer no longer has the cargo"),
+            TradeEnd::NotEnoughCredits => write!(f, "the buyer does not have the credits"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TeleportError {
+    UnknownPlanet { planet_id: u32 },
+    NotOwner { planet_id: u32 },
+    Cooldown { remaining_ms: u64 },
+    UnknownName { name: String },
+}
+
+impl std::fmt::Display for TeleportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TeleportError::UnknownPlanet { planet_id } => write!(f, "there is no planet {}", planet_id),
+            TeleportError::NotOwner { planet_id } => write!(f, "you do not own planet {}", planet_id),
+            TeleportError::Cooldown { remaining_ms } => write!(f, "teleport is cooling down, {:.1}s left", *remaining_ms as f64 / 1000.0),
+            TeleportError::UnknownName { name } => write!(f, "there is no planet called {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum PlanetRenameError {
+    UnknownPlanet { planet_id: u32 },
+    NotOwner { planet_id: u32 },
+    InvalidName { name: String },
+    NameTaken { name: String, planet_id: u32 },  // by that planet
+    Elsewhere,  // on a cluster instance that is not the primary
+}
+
+impl std::fmt::Display for PlanetRenameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlanetRenameError::UnknownPlanet { planet_id } => write!(f, "there is no planet {}", planet_id),
+            PlanetRenameError::NotOwner { planet_id } => write!(f, "you do not own planet {}", planet_id),
+            PlanetRenameError::InvalidName { name } => write!(f, "{:?} is not a valid planet name", name),
+            PlanetRenameError::NameTaken { name, planet_id } => write!(f, "{} is already the name of planet {}", name, planet_id),
+            PlanetRenameError::Elsewhere => write!(f, "the planets are renamed on the cluster's primary instance"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum RenameError {
+    NameTaken { name: String },
+    Banned { name: String },
+}
+
+impl std::fmt::Display for RenameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenameError::NameTaken { name } => write!(f, "{} is already taken", name),
+            RenameError::Banned { name } => write!(f, "{} is banned", name),
+        }
+    }
+}
+
+/// Why the server ended a connection. Each code is sent in an `Error`
+/// message and then as the close frame's code, from the 4000-4999 range that
+/// WebSocket leaves to applications. Codes are never renumbered or reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    ServerFull = 4000,
+    TooManyConnections = 4001,  // from one address
+    RateLimited = 4002,
+    IdleTimeout = 4003,
+    ProtocolViolation = 4004,   // e.g. an oversized frame
+    Kicked = 4005,
+    Banned = 4006,
+    ShuttingDown = 4007,
+    KeepaliveTimeout = 4008,    // keepalives went unanswered
+    Standby = 4009,             // a follower not yet promoted takes no players
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 10] = [
+        ErrorCode::ServerFull,
+        ErrorCode::TooManyConnections,
+        ErrorCode::RateLimited,
+        ErrorCode::IdleTimeout,
+        ErrorCode::ProtocolViolation,
+        ErrorCode::Kicked,
+        ErrorCode::Banned,
+        ErrorCode::ShuttingDown,
+        ErrorCode::KeepaliveTimeout,
+        ErrorCode::Standby,
+    ];
+
+    pub fn code(self) -> u16 {
+        self as u16
+    }
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.code() == code)
+    }
+
+    /// The code's stable name, the key for its text in a translation table
+    /// (see `locale`).
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::ServerFull => "SERVER_FULL",
+            ErrorCode::TooManyConnections => "TOO_MANY_CONNECTIONS",
+            ErrorCode::RateLimited => "DISCONNECTED_RATE_LIMITED",
+            ErrorCode::IdleTimeout => "IDLE_TIMEOUT",
+            ErrorCode::ProtocolViolation => "PROTOCOL_VIOLATION",
+            ErrorCode::Kicked => "KICKED",
+            ErrorCode::Banned => "BANNED",
+            ErrorCode::ShuttingDown => "SHUTTING_DOWN",
+            ErrorCode::KeepaliveTimeout => "KEEPALIVE_TIMEOUT",
+            ErrorCode::Standby => "STANDBY",
+        }
+    }
+
+    /// What a player should be told.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            ErrorCode::ServerFull => "The server is full. Try again in a little while.",
+            ErrorCode::TooManyConnections => "Too many connections from your address.",
+            ErrorCode::RateLimited => "Disconnected for sending messages too quickly.",
+            ErrorCode::IdleTimeout => "Disconnected after being idle for too long.",
+            ErrorCode::ProtocolViolation => "The client sent something the server could not accept.",
+            ErrorCode::Kicked => "Kicked by an admin.",
+            ErrorCode::Banned => "Banned from this server.",
+            ErrorCode::ShuttingDown => "The server is shutting down.",
+            ErrorCode::KeepaliveTimeout => "The connection stopped answering keepalives.",
+            ErrorCode::Standby => "This server is on standby. Try again in a little while.",
+        }
+    }
+
+    /// Whether a client may reconnect on its own. Not after an admin removed
+    /// the player, after going idle, or after a violation it would repeat.
+    pub fn may_reconnect(self) -> bool {
+        matches!(self, ErrorCode::ServerFull | ErrorCode::TooManyConnections | ErrorCode::RateLimited | ErrorCode::ShuttingDown
+            | ErrorCode::KeepaliveTimeout | ErrorCode::Standby)
+    }
+
+    /// The `Error` message and close frame that end a connection, in order.
+    pub fn messages(self, format: WireFormat, message: &str) -> Result<[Message; 2], EncodeError> {
+        let error = ServerMessage::Error { code: self.code(), message: message.to_string() };
+        let close = CloseFrame { code: CloseCode::from(self.code()), reason: message.into() };
+        Ok([encode_server_message(format, &error)?, Message::Close(Some(close))])
+    }
+}
+
+/// What a `Localized` notice says, as a stable code and its parameters.
+///
+/// The registry: codes are the serialized variant names, and like
+/// `ErrorCode`s they are never renamed or reused, and new ones go at the end.
+/// Parameters may be added to a code but never removed or retyped. `{name}`
+/// in a translation stands for the parameter of that name (see `locale`).
+///
+/// | code                        | parameters       | English                                      |
+/// |-----------------------------|------------------|----------------------------------------------|
+/// | `WELCOME`                   |                  | Welcome to Crux Server!                      |
+/// | `WELCOME_SPECTATOR`         |                  | Welcome to Crux Server! You are spectating.  |
+/// | `INVALID_MESSAGE`           | `reason`         | Invalid message: {reason}                    |
+/// | `FORMAT_LOCKED`             |                  | The format can only be chosen by the first message. |
+/// | `RATE_LIMITED`              | `retry_after_ms` | Slow down! You are sending messages too quickly. |
+/// | `PLANET_QUERY_RATE_LIMITED` | `retry_after_ms` | Slow down! You are querying planets too quickly. |
+/// | `TRAIL_QUERY_RATE_LIMITED`  | `retry_after_ms` | Slow down! You are querying trails too quickly. |
+/// | `HISTORY_RATE_LIMITED`      | `retry_after_ms` | Slow down! You are asking for chat history too quickly. |
+/// | `PLANET_QUERY_REJECTED`     | `reason`         | Planet query rejected: {reason}              |
+/// | `TRAIL_QUERY_REJECTED`      | `reason`         | Trail query rejected: {reason}               |
+/// | `SPECTATOR_READ_ONLY`       |                  | Spectators cannot move, chat or change the world. |
+/// | `CHAT_MUTED`                |                  | You are muted and cannot chat.               |
+///
+/// The reasons a connection ends are `ErrorCode`s, whose `name`s share the
+/// table, so `KICKED` or `SERVER_FULL` translate the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MessageCode {
+    Welcome {},
+    WelcomeSpectator {},
+    InvalidMessage { reason: String },
+    FormatLocked {},
+    RateLimited { retry_after_ms: u64 },
+    PlanetQueryRateLimited { retry_after_ms: u64 },
+    TrailQueryRateLimited { retry_after_ms: u64 },
+    HistoryRateLimited { retry_after_ms: u64 },
+    PlanetQueryRejected { reason: String },
+    TrailQueryRejected { reason: String },
+    SpectatorReadOnly {},
+    ChatMuted {},
+}
+
+impl MessageCode {
+    /// The code, as it is serialized.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MessageCode::Welcome {} => "WELCOME",
+            MessageCode::WelcomeSpectator {} => "WELCOME_SPECTATOR",
+            MessageCode::InvalidMessage { .. } => "INVALID_MESSAGE",
+            MessageCode::FormatLocked {} => "FORMAT_LOCKED",
+            MessageCode::RateLimited { .. } => "RATE_LIMITED",
+            MessageCode::PlanetQueryRateLimited { .. } => "PLANET_QUERY_RATE_LIMITED",
+            MessageCode::TrailQueryRateLimited { .. } => "TRAIL_QUERY_RATE_LIMITED",
+            MessageCode::HistoryRateLimited { .. } => "HISTORY_RATE_LIMITED",
+            MessageCode::PlanetQueryRejected { .. } => "PLANET_QUERY_REJECTED",
+            MessageCode::TrailQueryRejected { .. } => "TRAIL_QUERY_REJECTED",
+            MessageCode::SpectatorReadOnly {} => "SPECTATOR_READ_ONLY",
+            MessageCode::ChatMuted {} => "CHAT_MUTED",
+        }
+    }
+
+    /// The parameters by name, formatted for a translation to place.
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        match self {
+            MessageCode::InvalidMessage { reason }
+            | MessageCode::PlanetQueryRejected { reason }
+            | MessageCode::TrailQueryRejected { reason } => vec![("reason", reason.clone())],
+            MessageCode::RateLimited { retry_after_ms }
+            | MessageCode::PlanetQueryRateLimited { retry_after_ms }
+            | MessageCode::TrailQueryRateLimited { retry_after_ms }
+            | MessageCode::HistoryRateLimited { retry_after_ms } => vec![("retry_after_ms", retry_after_ms.to_string())],
+            MessageCode::Welcome {} | MessageCode::WelcomeSpectator {} | MessageCode::FormatLocked {}
+            | MessageCode::SpectatorReadOnly {} | MessageCode::ChatMuted {} => Vec::new(),
+        }
+    }
+}
+
+/// How a connection's messages are serialized, chosen per connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    #[default]
+    Binary,
+    Json,
+}
+
+impl FromStr for WireFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "binary" | "bincode" => Ok(WireFormat::Binary),
+            "json" => Ok(WireFormat::Json),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+/// What a client understands, as a bitfield sent with `?caps=` on the
+/// connection URL. The server uses the bits both sides know, and sends them
+/// back in the `CAPABILITIES_HEADER` of the handshake response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    /// `Batch` frames; without it a batch's messages come one per frame.
+    pub const BATCH: Capabilities = Capabilities(1 << 0);
+    /// `Resync` replies with diffs; without it a resync request gets a full `State`.
+    pub const DELTAS: Capabilities = Capabilities(1 << 1);
+    /// `Keepalive` frames while the client is silent, which it must answer.
+    pub const KEEPALIVE: Capabilities = Capabilities(1 << 2);
+    /// A CRC32 trailer on every binary frame, both ways (see `integrity`).
+    pub const CRC: Capabilities = Capabilities(1 << 3);
+    /// Planets' `terrain` in binary frames (see `encoding_for`).
+    pub const TERRAIN: Capabilities = Capabilities(1 << 4);
+    /// `Localized` notices; without it they come as plain `Notice`s.
+    pub const CODES: Capabilities = Capabilities(1 << 5);
+    /// Planets' `name` in binary frames (see `encoding_for`).
+    pub const NAMES: Capabilities = Capabilities(1 << 6);
+    /// Periodic snapshots come as `StateDelta`s, and players that only
+    /// moved as quantized `moves` in them and in `Resync` diffs (see
+    /// `quantize`).
+    pub const QUANTIZED: Capabilities = Capabilities(1 << 7);
+    /// Planets' `primary_display_color` and `label_contrast` in binary
+    /// frames (see `color`).
+    pub const LABELS: Capabilities = Capabilities(1 << 8);
+    /// A `ChatAck` or `ChatRejected` for each chat sent with a `client_ref`
+    /// (see `chat_receipts`). Without it chats are held to the same limits,
+    /// and a refused one gets a notice.
+    pub const CHAT_ACKS: Capabilities = Capabilities(1 << 9);
+    /// Everything this build of galavox understands, server and client alike.
+    pub const SUPPORTED: Capabilities = Capabilities(
+        Self::BATCH.0 | Self::DELTAS.0 | Self::KEEPALIVE.0 | Self::CRC.0 | Self::TERRAIN.0 | Self::CODES.0 | Self::NAMES.0
+            | Self::QUANTIZED.0 | Self::LABELS.0 | Self::CHAT_ACKS.0,
+    );
+    /// What the client library asks for unless told otherwise: everything
+    /// but CRC, which costs a checksum a frame and is for debugging links.
+    pub const DEFAULT: Capabilities = Capabilities(Self::SUPPORTED.0 & !Self::CRC.0);
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The bits in both; unknown bits from newer peers drop out here.
+    pub fn intersect(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0)
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+/// Response header carrying the negotiated `Capabilities` as a decimal u32.
+pub const CAPABILITIES_HEADER: &str = "x-galavox-capabilities";
+
+/// Response header carrying the world's radius, as a decimal f32; positions
+/// further from the origin are out of bounds (see `world`).
+pub const WORLD_RADIUS_HEADER: &str = "x-galavox-world-radius";
+
+/// Why a server message could not be encoded.
+#[derive(Debug)]
+pub enum EncodeError {
+    Json(serde_json::Error),
+    Bincode(bincode::Error),
+}
+
+impl std::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::Json(e) => write!(f, "JSON encoding failed: {}", e),
+            EncodeError::Bincode(e) => write!(f, "bincode encoding failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Why a client frame could not be decoded.
+#[derive(Debug)]
+pub enum DecodeError {
+    TooLarge { len: usize, limit: usize },
+    /// No binary frame has this length
+    Length { frame: &'static str, len: usize },
+    Malformed(&'static str),
+    Json(serde_json::Error),
+    Corrupt(CorruptFrame),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::TooLarge { len, limit } => write!(f, "frame of {} bytes exceeds limit of {} bytes", len, limit),
+            DecodeError::Length { frame, len } => write!(f, "invalid {} length {}", frame, len),
+            DecodeError::Malformed(reason) => write!(f, "malformed frame: {}", reason),
+            DecodeError::Json(e) => write!(f, "invalid JSON message: {}", e),
+            DecodeError::Corrupt(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Encode buffers that grow beyond this are freed rather than kept for reuse.
+const MAX_RETAINED_BUFFER: usize = 4 << 20;
+
+thread_local! {
+    static ENCODE_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Encodes into this thread's reusable buffer, so that only the finished
+/// frame is allocated, at its final size.
+fn encode_buffered<E>(encode: impl FnOnce(&mut Vec<u8>) -> Result<(), E>) -> Result<Bytes, E> {
+    ENCODE_BUFFER.with_borrow_mut(|buf| {
+        buf.clear();
+        let encoded = encode(buf).map(|()| Bytes::copy_from_slice(buf));
+        if buf.capacity() > MAX_RETAINED_BUFFER {
+            *buf = Vec::new();
+        }
+        encoded
+    })
+}
+
+fn encode_json(message: &impl Serialize) -> Result<Message, EncodeError> {
+    let bytes = encode_buffered(|buf| serde_json::to_writer(buf, message)).map_err(EncodeError::Json)?;
+    Ok(Message::Text(Utf8Bytes::try_from(bytes).expect("serde_json writes UTF-8")))
+}
+
+/// Encodes a server message as the frame a client in `format` expects.
+pub fn encode_server_message(format: WireFormat, message: &ServerMessage) -> Result<Message, EncodeError> {
+    if let ServerMessage::Localized { text, .. } = message
+        && !peer_capabilities().contains(Capabilities::CODES)
+    {
+        return encode_server_message(format, &ServerMessage::Notice { text: text.clone() });
+    }
+    Ok(match (format, message) {
+        (WireFormat::Json, message) => encode_json(message)?,
+        (WireFormat::Binary, ServerMessage::Notice { text }) => Message::Text(text.as_str().into()),
+        (WireFormat::Binary, ServerMessage::Echo { text }) => Message::Text(format!("Echo: {}", text).into()),
+        (WireFormat::Binary, message) => Message::Binary(
+            encode_buffered(|buf| message.to_bincode_into(buf)).map_err(EncodeError::Bincode)?,
+        ),
+    })
+}
+
+/// `ServerMessage::State` borrowing its state, for `encode_state`.
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum BorrowedState<'a> {
+    State { tick: u64, server_time_ms: u64, state: &'a GameState },
+}
+
+/// Encodes a `ServerMessage::State` for `state` without cloning it, so that
+/// it can be encoded while the state is locked.
+pub fn encode_state(format: WireFormat, tick: u64, server_time_ms: u64, state: &GameState) -> Result<Message, EncodeError> {
+    Ok(match format {
+        WireFormat::Json => encode_json(&BorrowedState::State { tick, server_time_ms, state })?,
+        WireFormat::Binary => {
+            let mirror = server_message_bincode::Borrowed::State { tick: &tick, server_time_ms: &server_time_ms, state };
+            let encoded = with_binary_layout(|| encode_buffered(|buf| bincode::serialize_into(buf, &mirror)));
+            Message::Binary(encoded.map_err(EncodeError::Bincode)?)
+        }
+    })
+}
+
+/// Decodes any data frame from a client: JSON text, or one of the fixed
+/// binary layouts. Frames over `MAX_CLIENT_FRAME_SIZE` are refused unread.
+pub fn decode_client_message(message: &Message) -> Result<ClientMessage, DecodeError> {
+    if message.len() > MAX_CLIENT_FRAME_SIZE {
+        return Err(DecodeError::TooLarge { len: message.len(), limit: MAX_CLIENT_FRAME_SIZE });
+    }
+    match message {
+        Message::Text(text) => serde_json::from_str(text).map_err(DecodeError::Json),
+        Message::Binary(data) => decode_binary_client_message(data),
+        _ => Err(DecodeError::Malformed("not a data frame")),
+    }
+}
+
+/// Like `decode_client_message`, for a client with the CRC capability:
+/// binary frames are checked and stripped of their CRC first.
+pub fn decode_sealed_client_message(message: &Message) -> Result<ClientMessage, DecodeError> {
+    if message.len() > MAX_CLIENT_FRAME_SIZE {
+        return Err(DecodeError::TooLarge { len: message.len(), limit: MAX_CLIENT_FRAME_SIZE });
+    }
+    match message {
+        Message::Binary(data) => decode_binary_client_message(integrity::open(data).map_err(DecodeError::Corrupt)?),
+        message => decode_client_message(message),
+    }
+}
+
+/// Decodes a binary client frame (time-sync, resync request or position update).
+pub fn decode_binary_client_message(data: &[u8]) -> Result<ClientMessage, DecodeError> {
+    if data.len() == TIME_SYNC_FRAME_LEN {
+        return Ok(ClientMessage::TimeSync { client_time_ms: decode_time_sync_request(data)? });
+    }
+    if data.len() == RESYNC_FRAME_LEN {
+        return Ok(ClientMessage::ResyncFrom { tick: decode_resync_request(data)? });
+    }
+    let PositionUpdate { seq, position, velocity, rotation } = decode_position_update(data)?;
+    Ok(ClientMessage::Position { seq, position, velocity, rotation })
+}
+
+/// WebSocket limits applied to every accepted connection.
+pub fn server_ws_config() -> WebSocketConfig {
+    WebSocketConfig::default()
+        .max_message_size(Some(MAX_WS_MESSAGE_SIZE))
+        .max_frame_size(Some(MAX_WS_MESSAGE_SIZE))
+}
+
+/// A decoded client position update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionUpdate {
+    pub seq: Option<u32>,  // None for legacy frames
+    pub position: Position,
+    pub velocity: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+pub const TIME_SYNC_FRAME_LEN: usize = 8;
+pub const RESYNC_FRAME_LEN: usize = 9;
+pub const RESYNC_FRAME_MARKER: u8 = b'R';
+pub const LEGACY_POSITION_FRAME_LEN: usize = 12;
+pub const POSITION_FRAME_LEN: usize = 16;
+pub const MOTION_FRAME_LEN: usize = 44;
+
+/// One field of a fixed-layout binary client frame. Fields are packed
+/// little-endian in order, so offsets follow from the sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FrameField {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub size: usize,
+}
+
+const fn field(name: &'static str, ty: &'static str, size: usize) -> FrameField {
+    FrameField { name, ty, size }
+}
+
+pub const TIME_SYNC_FRAME_FIELDS: &[FrameField] = &[field("client_time_ms", "u64", 8)];
+
+pub const RESYNC_FRAME_FIELDS: &[FrameField] = &[field("marker", "u8", 1), field("tick", "u64", 8)];
+
+pub const LEGACY_POSITION_FRAME_FIELDS: &[FrameField] = &[
+    field("position.x", "f32", 4),
+    field("position.y", "f32", 4),
+    field("position.z", "f32", 4),
+];
+
+pub const POSITION_FRAME_FIELDS: &[FrameField] = &[
+    field("seq", "u32", 4),
+    field("position.x", "f32", 4),
+    field("position.y", "f32", 4),
+    field("position.z", "f32", 4),
+];
+
+pub const MOTION_FRAME_FIELDS: &[FrameField] = &[
+    field("seq", "u32", 4),
+    field("position.x", "f32", 4),
+    field("position.y", "f32", 4),
+    field("position.z", "f32", 4),
+    field("velocity[0]", "f32", 4),
+    field("velocity[1]", "f32", 4),
+    field("velocity[2]", "f32", 4),
+    field("rotation[0]", "f32", 4),
+    field("rotation[1]", "f32", 4),
+    field("rotation[2]", "f32", 4),
+    field("rotation[3]", "f32", 4),
+];
+
+pub const IDENTITY_ROTATION: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// How far a rotation quaternion's length may stray from 1.
+pub const ROTATION_LENGTH_TOLERANCE: f32 = 0.01;
+
+impl PositionUpdate {
+    pub fn speed(&self) -> f32 {
+        speed(&self.velocity)
+    }
+
+    /// Rejects non-finite values, non-unit rotations and speeds above `max_speed`.
+    pub fn validate(&self, max_speed: f32) -> Result<(), &'static str> {
+        let p = &self.position;
+        let all_finite = [p.x, p.y, p.z].iter()
+            .chain(&self.velocity)
+            .chain(&self.rotation)
+            .all(|v| v.is_finite());
+        if !all_finite {
+            return Err("non-finite value");
+        }
+        let length = self.rotation.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if (length - 1.0).abs() > ROTATION_LENGTH_TOLERANCE {
+            return Err("rotation is not a unit quaternion");
+        }
+        if self.speed() > max_speed {
+            return Err("speed exceeds limit");
+        }
+        Ok(())
+    }
+}
+
+pub fn speed(velocity: &[f32; 3]) -> f32 {
+    velocity.iter().map(|v| v * v).sum::<f32>().sqrt()
+}
+
+/// Serial-number comparison (RFC 1982): is `a` newer than `b`, allowing wraparound?
+pub fn seq_newer(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+/// The form of a player name used to compare names: whether two players
+/// clash, whether a name is banned, and whose discoveries are whose. Trims,
+/// collapses inner whitespace to one space, maps the Cyrillic and Greek
+/// letters most often passed off as Latin ones (`CONFUSABLES`) to those, and
+/// lowercases the rest, so `Player`, ` player ` and `Рlayer` (Cyrillic Р)
+/// are all `player`. Unicode normalization (NFC) is not applied; names that
+/// can join are ASCII (see `valid_player_name`), so it only matters to
+/// names typed by admins.
+pub fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for word in name.split_whitespace() {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        for c in word.chars() {
+            match CONFUSABLES.iter().find(|(lookalike, _)| *lookalike == c) {
+                Some((_, latin)) => normalized.push(*latin),
+                None => normalized.extend(c.to_lowercase()),
+            }
+        }
+    }
+    normalized
+}
+
+/// Non-Latin letters that look like Latin ones, and the lowercase Latin
+/// letter each stands for.
+pub const CONFUSABLES: &[(char, char)] = &[
+    // Cyrillic
+    ('а', 'a'), ('А', 'a'), ('В', 'b'), ('с', 'c'), ('С', 'c'), ('ԁ', 'd'), ('е', 'e'), ('Е', 'e'),
+    ('һ', 'h'), ('Н', 'h'), ('і', 'i'), ('І', 'i'), ('ј', 'j'), ('Ј', 'j'), ('К', 'k'), ('М', 'm'),
+    ('о', 'o'), ('О', 'o'), ('р', 'p'), ('Р', 'p'), ('ԛ', 'q'), ('ѕ', 's'), ('Ѕ', 's'), ('Т', 't'),
+    ('ԝ', 'w'), ('х', 'x'), ('Х', 'x'), ('у', 'y'), ('У', 'y'),
+    // Greek
+    ('α', 'a'), ('Α', 'a'), ('Β', 'b'), ('Ε', 'e'), ('Η', 'h'), ('ι', 'i'), ('Ι', 'i'), ('κ', 'k'),
+    ('Κ', 'k'), ('Μ', 'm'), ('Ν', 'n'), ('ν', 'v'), ('ο', 'o'), ('Ο', 'o'), ('ρ', 'p'), ('Ρ', 'p'),
+    ('Τ', 't'), ('τ', 't'), ('υ', 'u'), ('Υ', 'y'), ('χ', 'x'), ('Χ', 'x'), ('Ζ', 'z'),
+];
+
+pub fn encode_position_update(seq: u32, position: &Position) -> Vec<u8> {
+    let mut data = Vec::with_capacity(POSITION_FRAME_LEN);
+    data.extend_from_slice(&seq.to_le_bytes());
+    data.extend_from_slice(&position.x.to_le_bytes());
+    data.extend_from_slice(&position.y.to_le_bytes());
+    data.extend_from_slice(&position.z.to_le_bytes());
+    data
+}
+
+pub fn encode_motion_update(seq: u32, position: &Position, velocity: &[f32; 3], rotation: &[f32; 4]) -> Vec<u8> {
+    let mut data = encode_position_update(seq, position);
+    data.reserve(MOTION_FRAME_LEN - POSITION_FRAME_LEN);
+    for v in velocity.iter().chain(rotation) {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data
+}
+
+pub fn decode_position_update(data: &[u8]) -> Result<PositionUpdate, DecodeError> {
+    let seq = || Some(u32::from_le_bytes([data[0], data[1], data[2], data[3]]));
+    match data.len() {
+        LEGACY_POSITION_FRAME_LEN => Ok(PositionUpdate {
+            seq: None,
+            position: decode_position(data)?,
+            velocity: [0.0; 3],
+            rotation: IDENTITY_ROTATION,
+        }),
+        POSITION_FRAME_LEN => Ok(PositionUpdate {
+            seq: seq(),
+            position: decode_position(&data[4..16])?,
+            velocity: [0.0; 3],
+            rotation: IDENTITY_ROTATION,
+        }),
+        MOTION_FRAME_LEN => {
+            let f = |offset: usize| f32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+            Ok(PositionUpdate {
+                seq: seq(),
+                position: decode_position(&data[4..16])?,
+                velocity: [f(16), f(20), f(24)],
+                rotation: [f(28), f(32), f(36), f(40)],
+            })
+        }
+        len => Err(DecodeError::Length { frame: "position update", len }),
+    }
+}
+
+pub fn encode_time_sync_request(client_time_ms: u64) -> Vec<u8> {
+    client_time_ms.to_le_bytes().to_vec()
+}
+
+pub fn decode_time_sync_request(data: &[u8]) -> Result<u64, DecodeError> {
+    let bytes: [u8; TIME_SYNC_FRAME_LEN] = data.try_into().map_err(|_| DecodeError::Length { frame: "time sync", len: data.len() })?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn encode_resync_request(tick: u64) -> Vec<u8> {
+    let mut data = Vec::with_capacity(RESYNC_FRAME_LEN);
+    data.push(RESYNC_FRAME_MARKER);
+    data.extend_from_slice(&tick.to_le_bytes());
+    data
+}
+
+pub fn decode_resync_request(data: &[u8]) -> Result<u64, DecodeError> {
+    match data {
+        [RESYNC_FRAME_MARKER, tick @ ..] => {
+            let tick: [u8; 8] = tick.try_into().map_err(|_| DecodeError::Length { frame: "resync request", len: data.len() })?;
+            Ok(u64::from_le_bytes(tick))
+        }
+        _ => Err(DecodeError::Malformed("resync request without its marker")),
+    }
+}
+
+pub fn decode_position(data: &[u8]) -> Result<Position, DecodeError> {
+    if data.len() != 12 {
+        return Err(DecodeError::Length { frame: "position", len: data.len() });
+    }
+
+    // Read 3 f32 values in little-endian format
+    let x = f32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let y = f32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+    let z = f32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+
+    Ok(Position { x, y, z })
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/*
+Removing admin-added planets nobody uses.
+
+Planets added with `planet add` are tracked from the moment they are
+added; generated, loaded and imported planets never are, so pruning only
+ever takes back what an admin put in. A tracked planet is active while a
+player is in its vicinity at a broadcast (see `population`) or while
+someone owns it. With pruning enabled, the economy tick removes tracked
+planets that have been inactive for longer than `PrunePolicy::after`,
+broadcasting `PlanetRemoved` as for an admin removal.
+
+Regenerating the world forgets every tracked planet along with the old
+planets.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrunePolicy {
+    pub after: Duration,  // with no visits and no owner
+}
+
+/// When each admin-added planet was last visited or claimed.
+#[derive(Debug, Clone, Default)]
+pub struct PlanetActivity {
+    last_active: HashMap<u32, Instant>,
+}
+
+impl PlanetActivity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking a planet an admin just added.
+    pub fn added(&mut self, planet_id: u32, now: Instant) {
+        self.last_active.insert(planet_id, now);
+    }
+
+    /// Notes activity on a planet; untracked planets are ignored.
+    pub fn active(&mut self, planet_id: u32, now: Instant) {
+        if let Some(last) = self.last_active.get_mut(&planet_id) {
+            *last = now;
+        }
+    }
+
+    pub fn forget(&mut self, planet_id: u32) {
+        self.last_active.remove(&planet_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.last_active.clear();
+    }
+
+    pub fn is_tracked(&self, planet_id: u32) -> bool {
+        self.last_active.contains_key(&planet_id)
+    }
+
+    /// Tracked planets inactive for longer than `after`, with how long,
+    /// oldest id first.
+    pub fn idle(&self, after: Duration, now: Instant) -> Vec<(u32, Duration)> {
+        let mut idle: Vec<(u32, Duration)> = self.last_active.iter()
+            .map(|(id, last)| (*id, now.saturating_duration_since(*last)))
+            .filter(|(_, inactive)| *inactive > after)
+            .collect();
+        idle.sort_by_key(|(id, _)| *id);
+        idle
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use crate::diff::StateDiff;
+use crate::protocol::{GameState, Player, Position, ServerMessage};
+
+/*
+Quantized player movement in state diffs.
+
+Most of a snapshot is players moving: a whole `Player` per player per tick,
+its position three f32s. A client with the QUANTIZED capability gets its
+periodic snapshots as a `QuantizedStream`: a whole `State` first, then each
+one as a `StateDelta`, the diff from the last one sent to it, so players
+and planets that did not change are not sent at all. In those diffs, and
+in `Resync` diffs, players whose only changes are movement (position,
+velocity, rotation and `last_processed_seq`) come as `PlayerMoves` instead
+of whole, each position an i16 offset per axis, in steps of `resolution`
+(0.01 units by default), from where the client has the player. When an
+offset would not fit in an i16, and after `refresh` offsets in a row, the
+position is sent absolute, as f32s, so a client never builds on more than
+`refresh` offsets. Any change other than movement still comes whole in
+`changed_players`, as do added players in `added_players`.
+
+The server measures each offset from the position the client will have
+worked out, not the true one, so rounding never piles up (see
+`MoveEncoder`): after applying a diff every axis of a player's position is
+within `resolution / 2` of the true one, plus the rounding of the result to
+f32 (see `MAX_AXIS_ERROR_ULPS` and `max_axis_error`). Both sides do the
+arithmetic in f64 with `offset_position`, so they agree exactly. A stream's
+encoder starts from the first State it sent; a `Resync`'s starts from the
+history's snapshot of the requested tick, which the client has, or has
+within the bound if it came quantized, leaving it within twice the bound.
+
+The client keeps the world its stream built in a `StreamDecoder` and
+applies each `StateDelta` to it (the library's `EventDecoder` turns them
+into `StateSnapshot` events). Any other State the server sends the
+connection, on joining, switching format or in reply to a resync, starts
+both sides over: the client takes it as the world, and the server sends
+the next snapshot whole. Binary frames carry `moves` only for clients with
+the capability (see `encoding_for`), so older clients keep the layout they
+know. Set `quantize_resolution` and `quantize_refresh` in the config.
+*/
+
+/// f32 ulps, of the largest coordinate of a position, a quantized axis may
+/// be off by beyond half the resolution.
+pub const MAX_AXIS_ERROR_ULPS: f32 = 1.0;
+
+/// How positions in diffs are quantized for QUANTIZED clients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantization {
+    pub resolution: f32,  // units per step of an offset
+    pub refresh: u32,     // offsets in a row before a position is sent absolute again
+}
+
+impl Default for Quantization {
+    fn default() -> Self {
+        Quantization { resolution: 0.01, refresh: 32 }
+    }
+}
+
+/// A moved player's position: absolute, or steps from where it was.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum QuantizedPosition {
+    Absolute(Position),
+    Offset([i16; 3]),
+}
+
+/// A player whose only changes in a diff are movement.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlayerMove {
+    pub id: u32,
+    pub position: QuantizedPosition,
+    pub velocity: [f32; 3],
+    pub rotation: [f32; 4],
+    pub last_processed_seq: u32,
+}
+
+/// The moves of one diff, and the resolution their offsets are in.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PlayerMoves {
+    pub resolution: f32,
+    pub players: Vec<PlayerMove>,
+}
+
+impl PlayerMove {
+    /// Moves `player` as this says, `resolution` being the diff's.
+    pub fn apply(&self, player: &mut Player, resolution: f32) {
+        player.position = match &self.position {
+            QuantizedPosition::Absolute(position) => position.clone(),
+            QuantizedPosition::Offset(offset) => offset_position(&player.position, *offset, resolution),
+        };
+        player.velocity = self.velocity;
+        player.rotation = self.rotation;
+        player.last_processed_seq = self.last_processed_seq;
+    }
+}
+
+/// `from` moved by `offset` steps of `resolution`; the same on both sides.
+pub fn offset_position(from: &Position, offset: [i16; 3], resolution: f32) -> Position {
+    let step = |from: f32, offset: i16| (from as f64 + offset as f64 * resolution as f64) as f32;
+    Position { x: step(from.x, offset[0]), y: step(from.y, offset[1]), z: step(from.z, offset[2]) }
+}
+
+/// The steps from `from` to `to`, or None if an axis does not fit an i16.
+pub fn offset_between(from: &Position, to: &Position, resolution: f32) -> Option<[i16; 3]> {
+    let steps = |from: f32, to: f32| {
+        let steps = ((to as f64 - from as f64) / resolution as f64).round();
+        (steps >= i16::MIN as f64 && steps <= i16::MAX as f64).then_some(steps as i16)
+    };
+    Some([steps(from.x, to.x)?, steps(from.y, to.y)?, steps(from.z, to.z)?])
+}
+
+/// The most an axis of a quantized `position` may be off by.
+pub fn max_axis_error(resolution: f32, position: &Position) -> f32 {
+    let largest = position.x.abs().max(position.y.abs()).max(position.z.abs());
+    let ulp = f32::from_bits(largest.to_bits() + 1) - largest;
+    resolution / 2.0 + MAX_AXIS_ERROR_ULPS * ulp
+}
+
+/// Whether `old` and `new` differ only in how the player moves.
+fn only_moved(old: &Player, new: &Player) -> bool {
+    let moved = Player {
+        position: new.position.clone(),
+        velocity: new.velocity,
+        rotation: new.rotation,
+        last_processed_seq: new.last_processed_seq,
+        ..old.clone()
+    };
+    moved == *new
+}
+
+/// Players as a client has them, to measure their moves from.
+#[derive(Debug, Clone, Default)]
+pub struct MoveEncoder {
+    known: HashMap<u32, (Player, u32)>,  // each player as the client has them, and the offsets since an absolute position
+}
+
+impl MoveEncoder {
+    /// An encoder for a client that has `players` as they are.
+    pub fn seeded(players: &[Player]) -> Self {
+        MoveEncoder { known: players.iter().map(|player| (player.id, (player.clone(), 0))).collect() }
+    }
+
+    /// Turns movement-only changes in `diff` into quantized moves, and
+    /// remembers each player as the client will have it once applied.
+    pub fn quantize(&mut self, diff: &mut StateDiff, quantization: Quantization) {
+        for id in &diff.removed_players {
+            self.known.remove(id);
+        }
+        for player in &diff.added_players {
+            self.known.insert(player.id, (player.clone(), 0));
+        }
+        let mut moves = Vec::new();
+        diff.changed_players.retain(|player| {
+            let Some((old, offsets)) = self.known.get_mut(&player.id).filter(|(old, _)| only_moved(old, player)) else {
+                self.known.insert(player.id, (player.clone(), 0));
+                return true;
+            };
+            let offset = (*offsets < quantization.refresh)
+                .then(|| offset_between(&old.position, &player.position, quantization.resolution))
+                .flatten();
+            let position = match offset {
+                Some(offset) => {
+                    *offsets += 1;
+                    QuantizedPosition::Offset(offset)
+                }
+                None => {
+                    *offsets = 0;
+                    QuantizedPosition::Absolute(player.position.clone())
+                }
+            };
+            let moved = PlayerMove {
+                id: player.id,
+                position,
+                velocity: player.velocity,
+                rotation: player.rotation,
+                last_processed_seq: player.last_processed_seq,
+            };
+            moved.apply(old, quantization.resolution);
+            moves.push(moved);
+            false
+        });
+        diff.moves = (!moves.is_empty()).then_some(PlayerMoves { resolution: quantization.resolution, players: moves });
+    }
+}
+
+/// Turns movement-only changes in `diffs`, to be applied in order to
+/// `base`, into quantized moves.
+pub fn quantize_diffs(diffs: &mut [StateDiff], base: &GameState, quantization: Quantization) {
+    let mut encoder = MoveEncoder::seeded(&base.players);
+    for diff in diffs {
+        encoder.quantize(diff, quantization);
+    }
+}
+
+/// One connection's periodic snapshots, each after the first sent as the
+/// diff from the one before with moves quantized.
+#[derive(Debug, Clone)]
+pub struct QuantizedStream {
+    quantization: Quantization,
+    last: Option<(u64, GameState)>,  // the last snapshot sent, as the server had it
+    moves: MoveEncoder,
+}
+
+impl QuantizedStream {
+    pub fn new(quantization: Quantization) -> Self {
+        QuantizedStream { quantization, last: None, moves: MoveEncoder::default() }
+    }
+
+    /// Starts over with a whole `State`, as the client does after any State
+    /// it is sent.
+    pub fn restart(&mut self) {
+        self.last = None;
+    }
+
+    /// What to send for the snapshot of `tick`: a `State` the first time,
+    /// then a `StateDelta` from the last one sent.
+    pub fn next(&mut self, tick: u64, server_time_ms: u64, state: &GameState) -> ServerMessage {
+        let Some((from_tick, last)) = self.last.replace((tick, state.clone())) else {
+            self.moves = MoveEncoder::seeded(&state.players);
+            return ServerMessage::State { tick, server_time_ms, state: state.clone() };
+        };
+        let mut diff = last.diff(state);
+        self.moves.quantize(&mut diff, self.quantization);
+        ServerMessage::StateDelta { from_tick, tick, server_time_ms, diff }
+    }
+}
+
+/// The client's side of a `QuantizedStream`: the world as the snapshots
+/// and deltas sent so far build it.
+#[derive(Debug, Clone, Default)]
+pub struct StreamDecoder {
+    last: Option<(u64, GameState)>,
+}
+
+impl StreamDecoder {
+    /// Starts over from a whole snapshot.
+    pub fn reset(&mut self, tick: u64, state: &GameState) {
+        self.last = Some((tick, state.clone()));
+    }
+
+    /// The world at `tick`, `diff` applied to the snapshot of `from_tick`;
+    /// None if that is not the last one decoded.
+    pub fn apply(&mut self, from_tick: u64, tick: u64, diff: &StateDiff) -> Option<GameState> {
+        let (last_tick, state) = self.last.as_mut().filter(|(last_tick, _)| *last_tick == from_tick)?;
+        state.apply(diff);
+        *last_tick = tick;
+        Some(state.clone())
+    }
+}
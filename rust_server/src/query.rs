@@ -0,0 +1,129 @@
+use crate::protocol::{normalize_name, Planet, PlanetCursor, PlanetSort, Position};
+
+/*
+Planet queries for clients that only want part of the world.
+
+A `QueryPlanets { center, radius, max_results }` request is answered with a
+`PlanetList` of the planets whose centres lie within `radius` of `center`,
+nearest first, to that client only; it never touches the broadcasts. The
+radius is capped at `MAX_QUERY_RADIUS` and the number of results at
+`MAX_QUERY_RESULTS`, whatever the client asks for. Queries share the chat
+rate limit. There is no spatial index, so every query checks every planet.
+
+A `QueryPlanetsFiltered` request is answered with a `PlanetPage` of up to
+`limit` (at most `MAX_QUERY_RESULTS`) of the planets matching all of its
+filters, nearest to `center` or largest first, ties by planet id. A page
+with more matches after it carries a `next_cursor`: the sort key and id of
+its last planet. The next page starts at the first matching planet after
+that key and id, not at an index, so planets added, removed or edited in
+between neither shift the pages nor make the cursor invalid; a planet that
+moved across the cursor is missed or seen twice. The `name` filter finds a
+planet by its name, however it is capitalized, for a page of one or none.
+These queries share the chat rate limit too.
+*/
+
+pub const MAX_QUERY_RADIUS: f32 = 5_000.0;
+pub const MAX_QUERY_RESULTS: u32 = 50;
+
+fn distance(a: &Position, b: &Position) -> f64 {
+    let (dx, dy, dz) = ((a.x - b.x) as f64, (a.y - b.y) as f64, (a.z - b.z) as f64);
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+/// The planets within `radius` of `center`, nearest first, after capping
+/// the radius and the number of results.
+pub fn planets_near(planets: &[Planet], center: &Position, radius: f32, max_results: u32) -> Result<Vec<Planet>, String> {
+    if ![center.x, center.y, center.z, radius].iter().all(|v| v.is_finite()) {
+        return Err("center and radius must be finite".to_string());
+    }
+    if radius < 0.0 {
+        return Err(format!("negative radius {}", radius));
+    }
+    let radius = radius.min(MAX_QUERY_RADIUS) as f64;
+    let mut found: Vec<(f64, &Planet)> = planets.iter()
+        .map(|planet| (distance(&planet.position, center), planet))
+        .filter(|(d, _)| *d <= radius)
+        .collect();
+    found.sort_by(|(a, p), (b, q)| a.total_cmp(b).then(p.id.cmp(&q.id)));
+    found.truncate(max_results.min(MAX_QUERY_RESULTS) as usize);
+    Ok(found.into_iter().map(|(_, planet)| planet.clone()).collect())
+}
+
+/// The filters of a `QueryPlanetsFiltered` request; a planet matches when
+/// it passes every one given.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanetFilter {
+    pub module_type: Option<u8>,
+    pub min_size: Option<f32>,
+    pub max_size: Option<f32>,
+    pub owned_by: Option<u32>,
+    pub unclaimed_only: bool,
+    pub name: Option<String>,  // compared as `planet_names` does
+}
+
+impl PlanetFilter {
+    fn matches(&self, planet: &Planet) -> bool {
+        self.module_type.is_none_or(|module_type| planet.module_type == module_type)
+            && self.min_size.is_none_or(|min| planet.size >= min)
+            && self.max_size.is_none_or(|max| planet.size <= max)
+            && self.owned_by.is_none_or(|owner| planet.owner == Some(owner))
+            && (!self.unclaimed_only || planet.owner.is_none())
+            && self.name.as_deref().is_none_or(|name| normalize_name(&planet.name) == normalize_name(name))
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let sizes = [self.min_size, self.max_size];
+        if !sizes.iter().flatten().all(|size| size.is_finite()) {
+            return Err("sizes must be finite".to_string());
+        }
+        if let [Some(min), Some(max)] = sizes
+            && min > max
+        {
+            return Err(format!("min size {} is above max size {}", min, max));
+        }
+        if self.unclaimed_only && self.owned_by.is_some() {
+            return Err("an unclaimed planet has no owner".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// One page of the planets matching `filter`, in `sort` order, from just
+/// after `cursor`, and the cursor of the next page when there is one.
+pub fn find_planets(
+    planets: &[Planet],
+    center: &Position,
+    filter: &PlanetFilter,
+    sort: PlanetSort,
+    limit: u32,
+    cursor: Option<PlanetCursor>,
+) -> Result<(Vec<Planet>, Option<PlanetCursor>), String> {
+    if ![center.x, center.y, center.z].iter().all(|v| v.is_finite()) {
+        return Err("center must be finite".to_string());
+    }
+    if limit == 0 {
+        return Err("limit must be at least 1".to_string());
+    }
+    if cursor.is_some_and(|cursor| !cursor.key.is_finite()) {
+        return Err("invalid cursor".to_string());
+    }
+    filter.check()?;
+    let key = |planet: &Planet| match sort {
+        PlanetSort::Distance => distance(&planet.position, center),
+        PlanetSort::Size => -(planet.size as f64),
+    };
+    let after = |key: f64, id: u32| cursor.is_none_or(|c| key.total_cmp(&c.key).then(id.cmp(&c.planet_id)).is_gt());
+    let mut found: Vec<(f64, &Planet)> = planets.iter()
+        .filter(|planet| filter.matches(planet))
+        .map(|planet| (key(planet), planet))
+        .filter(|(key, planet)| after(*key, planet.id))
+        .collect();
+    found.sort_by(|(a, p), (b, q)| a.total_cmp(b).then(p.id.cmp(&q.id)));
+    let limit = limit.min(MAX_QUERY_RESULTS) as usize;
+    let next_cursor = (found.len() > limit).then(|| {
+        let (key, planet) = found[limit - 1];
+        PlanetCursor { key, planet_id: planet.id }
+    });
+    found.truncate(limit);
+    Ok((found.into_iter().map(|(_, planet)| planet.clone()).collect(), next_cursor))
+}
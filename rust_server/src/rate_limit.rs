@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+/*
+Per-connection rate limiting.
+
+Every limiter is driven by an explicit `now` so the connection loop can pass
+`Instant::now()` while tests step time forward deterministically.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitConfig {
+    pub position_updates_per_sec: f64,
+    pub chat_messages_per_sec: f64,
+    pub abuse_window: Duration,  // bucket empty this long = disconnect
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            position_updates_per_sec: 60.0,
+            chat_messages_per_sec: 5.0,
+            abuse_window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Classic token bucket: holds up to `capacity` tokens, refilled at `rate` per second.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, capacity: f64, now: Instant) -> Self {
+        TokenBucket {
+            capacity,
+            rate,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn tokens(&self) -> f64 {
+        self.tokens
+    }
+
+    /// How long until a token is free, as of the last refill.
+    pub fn retry_after(&self) -> Duration {
+        if self.tokens >= 1.0 || self.rate <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((1.0 - self.tokens) / self.rate)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    Limited,
+    Abusive,  // bucket has been empty for the whole abuse window
+}
+
+/// A token bucket that also notices when a client keeps it drained.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: TokenBucket,
+    abuse_window: Duration,
+    empty_since: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Allows `per_sec` messages per second with a burst of one second's worth.
+    pub fn new(per_sec: f64, abuse_window: Duration, now: Instant) -> Self {
+        RateLimiter {
+            bucket: TokenBucket::new(per_sec, per_sec.max(1.0), now),
+            abuse_window,
+            empty_since: None,
+        }
+    }
+
+    pub fn check(&mut self, now: Instant) -> Decision {
+        let allowed = self.bucket.try_acquire(now);
+
+        // A client that keeps the bucket drained counts as empty even if the
+        // odd refilled token lets a message through.
+        if self.bucket.tokens() >= 1.0 {
+            self.empty_since = None;
+        } else if self.empty_since.is_none() {
+            self.empty_since = Some(now);
+        }
+
+        if allowed {
+            return Decision::Allowed;
+        }
+
+        let drained_for = self.empty_since.map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+        if drained_for >= self.abuse_window {
+            Decision::Abusive
+        } else {
+            Decision::Limited
+        }
+    }
+
+    /// How long a `Limited` client should wait before trying again.
+    pub fn retry_after(&self) -> Duration {
+        self.bucket.retry_after()
+    }
+}
+
+/// The limiters owned by a single connection.
+#[derive(Debug, Clone)]
+pub struct ConnectionLimiter {
+    pub positions: RateLimiter,
+    pub chat: RateLimiter,
+}
+
+impl ConnectionLimiter {
+    pub fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        ConnectionLimiter {
+            positions: RateLimiter::new(config.position_updates_per_sec, config.abuse_window, now),
+            chat: RateLimiter::new(config.chat_messages_per_sec, config.abuse_window, now),
+        }
+    }
+}
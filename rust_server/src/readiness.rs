@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+/*
+Telling whatever started the server that it is ready.
+
+Scripts, tests and service managers that start a server need to know when
+it can take connections; sleeping a while or waiting for the banner is
+either slow or fragile. With `--ready-fd <n>` or `--ready-file <path>`
+(`ready_fd`/`ready_file` in the config) the server writes one JSON line,
+e.g.
+
+    {"ready":true,"addr":"127.0.0.1:8080","pid":4242}
+
+to that file descriptor, or to that file (replaced, so a reader never sees
+half of it), once the listener is bound and every background task has
+started: a client connecting after the line appears is accepted. `addr`
+is where the listener is actually bound, so `--bind 127.0.0.1:0` lets the
+system pick a port and the line says which. A descriptor is written
+through `/dev/fd`, so it must be open for writing when the server starts,
+typically the write end of a pipe the launcher reads.
+
+Built with the `systemd` feature, the server also sends `READY=1` to
+`$NOTIFY_SOCKET` when there is one, for units of `Type=notify`.
+
+Embedders wait on `ServerHandle::ready` instead, which resolves at the
+same point.
+*/
+
+/// Where to say the server is ready; nowhere by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Readiness {
+    pub fd: Option<u32>,
+    pub file: Option<PathBuf>,
+}
+
+/// The line written once the server is ready.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadyLine {
+    pub ready: bool,
+    pub addr: SocketAddr,
+    pub pid: u32,
+}
+
+impl ReadyLine {
+    pub fn new(addr: SocketAddr) -> Self {
+        ReadyLine { ready: true, addr, pid: std::process::id() }
+    }
+
+    /// The line, newline included.
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).expect("a ready line is plain JSON");
+        line.push('\n');
+        line
+    }
+}
+
+impl Readiness {
+    /// Says the server listening on `addr` is ready everywhere asked,
+    /// returning what went wrong, if anything, to be logged; a launcher that
+    /// went away does not stop the server.
+    pub fn signal(&self, addr: SocketAddr) -> Vec<String> {
+        let line = ReadyLine::new(addr).to_line();
+        let mut problems = Vec::new();
+        if let Some(fd) = self.fd {
+            use std::io::Write;
+            let written = std::fs::OpenOptions::new().write(true).open(format!("/dev/fd/{}", fd))
+                .and_then(|mut out| out.write_all(line.as_bytes()));
+            if let Err(e) = written {
+                problems.push(format!("cannot write the ready line to fd {}: {}", fd, e));
+            }
+        }
+        if let Some(path) = &self.file
+            && let Err(e) = crate::lifetime::replace_file(path, &line)
+        {
+            problems.push(format!("cannot write the ready line to {}: {}", path.display(), e));
+        }
+        #[cfg(feature = "systemd")]
+        if let Err(e) = notify_systemd() {
+            problems.push(format!("cannot notify systemd: {}", e));
+        }
+        problems
+    }
+}
+
+/// Sends `READY=1` to the service manager's `$NOTIFY_SOCKET`, if it set one.
+#[cfg(feature = "systemd")]
+fn notify_systemd() -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else { return Ok(()) };
+    let datagram = UnixDatagram::unbound()?;
+    // `@name` is a socket in Linux's abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = socket.to_str().and_then(|socket| socket.strip_prefix('@')) {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        return datagram.send_to_addr(b"READY=1", &addr).map(|_| ());
+    }
+    datagram.send_to(b"READY=1", socket).map(|_| ())
+}
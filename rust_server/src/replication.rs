@@ -0,0 +1,232 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Serialize, Deserialize};
+use tokio::sync::{broadcast, watch};
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+#[cfg(feature = "tls")]
+use tokio_tungstenite::{connect_async_tls_with_config, Connector};
+use crate::events::{EventSink, EventTime, GameEvent};
+use crate::journal::{apply_event, JournalEvent, JournalRecord};
+use crate::protocol::GameState;
+
+/*
+A warm standby: a second server (`--follow ws://primary:8080`) that keeps
+a live copy of the primary's world, ready to take over.
+
+The follower connects with `?role=follower&token=<admin token>`; the
+primary turns away a missing or wrong token at the handshake with a 401.
+It then sends the follower binary frames of bincode `JournalRecord`s,
+exactly what it journals (see `journal`): first a `WorldCreated` with the
+world and its players as they are, then every event from there on, so
+applying them in order with `apply_event` keeps the copy current. A
+follower that falls more than `FEED_CAPACITY` records behind is
+disconnected and, like one whose primary went away, reconnects every
+`RETRY_INTERVAL` and starts over from a new `WorldCreated`.
+
+Until it is promoted the follower accepts no players or spectators; they
+are closed with `Standby`. The admin `promote` command installs the copy
+as the follower's world, with each player's XP remembered under their
+name, and sends the primary a `HandOver` naming where clients should go
+(`--advertise`, or `ws://<bind>`). The primary tells every client to
+`Reconnect` there, closes them and stops as if shut down. Clients built
+on `client::Connection` follow the redirect themselves.
+
+Only what the journal holds is replicated: wallets, bans, roles and chat
+history stay with each server.
+*/
+
+/// Records a follower may fall behind by before it is disconnected.
+pub const FEED_CAPACITY: usize = 1024;
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Journal records on their way to the followers.
+#[derive(Debug, Clone)]
+pub struct Feed {
+    tx: broadcast::Sender<Arc<JournalRecord>>,
+}
+
+impl Default for Feed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Feed {
+    pub fn new() -> Self {
+        Feed { tx: broadcast::channel(FEED_CAPACITY).0 }
+    }
+
+    /// Whether any follower is connected; nothing is published otherwise.
+    pub fn is_followed(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub fn publish(&self, record: JournalRecord) {
+        if self.is_followed() {
+            let _ = self.tx.send(Arc::new(record));
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<JournalRecord>> {
+        self.tx.subscribe()
+    }
+}
+
+impl EventSink for Feed {
+    fn consume(&self, at: EventTime, event: &GameEvent) {
+        if !self.is_followed() {
+            return;
+        }
+        if let Some(event) = JournalEvent::from_game(event) {
+            self.publish(JournalRecord { tick: at.tick, server_time_ms: at.server_time_ms, unix_ms: at.unix_ms, event });
+        }
+    }
+
+    fn is_listening(&self) -> bool {
+        self.is_followed()
+    }
+}
+
+/// What a follower sends its primary, as a JSON text frame.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FollowerMessage {
+    /// The follower was promoted; clients should reconnect to `to`
+    HandOver { to: String },
+}
+
+/// The follower's copy of the primary's world.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Replica {
+    pub state: Option<GameState>,  // None until the first `WorldCreated`
+    pub tick: u64,
+    pub records: u64,  // applied since the last `WorldCreated`
+}
+
+impl Replica {
+    pub fn apply(&mut self, record: &JournalRecord) {
+        match (&mut self.state, &record.event) {
+            (_, JournalEvent::WorldCreated { state }) => {
+                self.state = Some(state.clone());
+                self.records = 0;
+            }
+            (Some(state), event) => apply_event(state, event),
+            // Nothing to apply to until the primary sends its world
+            (None, _) => return,
+        }
+        self.tick = record.tick;
+        self.records += 1;
+    }
+}
+
+/// The follower's side: where it follows, its copy, and whether it has
+/// been promoted.
+#[derive(Debug)]
+pub struct Standby {
+    primary: String,
+    token: String,
+    advertise: String,
+    replica: Mutex<Replica>,
+    promoted: watch::Sender<bool>,
+}
+
+impl Standby {
+    /// Follows `primary` with the admin `token`, and once promoted sends
+    /// its clients to `advertise`.
+    pub fn new(primary: String, token: String, advertise: String) -> Self {
+        Standby { primary, token, advertise, replica: Mutex::new(Replica::default()), promoted: watch::Sender::new(false) }
+    }
+
+    pub fn primary(&self) -> &str {
+        &self.primary
+    }
+
+    pub fn advertise(&self) -> &str {
+        &self.advertise
+    }
+
+    pub fn is_promoted(&self) -> bool {
+        *self.promoted.borrow()
+    }
+
+    /// The copy as it stands.
+    pub fn replica(&self) -> Replica {
+        self.replica.lock().unwrap().clone()
+    }
+
+    /// Stops following and gives up the copy, with its tick; fails without
+    /// a world from the primary yet, or if already promoted.
+    pub fn promote(&self) -> Result<(GameState, u64), String> {
+        if self.is_promoted() {
+            return Err("already promoted".to_string());
+        }
+        let mut replica = self.replica.lock().unwrap();
+        let state = replica.state.take().ok_or_else(|| format!("no world from {} yet", self.primary))?;
+        self.promoted.send_replace(true);
+        Ok((state, replica.tick))
+    }
+
+    fn follow_url(&self) -> String {
+        // A URL without a path needs one before its query
+        let has_path = self.primary.split_once("://").is_some_and(|(_, rest)| rest.contains('/'));
+        let separator = if self.primary.contains('?') { "&" } else if has_path { "?" } else { "/?" };
+        format!("{}{}role=follower&token={}", self.primary, separator, self.token)
+    }
+
+    /// Keeps the copy current until promoted, then hands over.
+    pub fn spawn(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let standby = self.clone();
+        tokio::spawn(async move {
+            let mut promoted = standby.promoted.subscribe();
+            loop {
+                match standby.follow(&mut promoted).await {
+                    Ok(()) => return,
+                    // Promoted with the primary gone: nobody to hand over from
+                    Err(e) if standby.is_promoted() => return println!("🪞 Standby: {} is gone ({}); nothing to hand over", standby.primary, e),
+                    Err(e) => println!("🪞 Standby: lost {}: {}", standby.primary, e),
+                }
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        })
+    }
+
+    async fn follow(&self, promoted: &mut watch::Receiver<bool>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let url = self.follow_url();
+        let (mut ws, _) = if url.starts_with("wss://") {
+            #[cfg(feature = "tls")]
+            {
+                let connector = Connector::Rustls(crate::tls::client_config(false));
+                connect_async_tls_with_config(&url, None, false, Some(connector)).await?
+            }
+            #[cfg(not(feature = "tls"))]
+            return Err("wss:// needs the `tls` feature".into());
+        } else {
+            connect_async(&url).await?
+        };
+        println!("🪞 Standby: following {}", self.primary);
+        loop {
+            let frame = tokio::select! {
+                frame = ws.next() => frame,
+                _ = promoted.wait_for(|promoted| *promoted) => break,
+            };
+            match frame {
+                Some(Ok(Message::Binary(data))) => {
+                    let record: JournalRecord = bincode::deserialize(&data)?;
+                    self.replica.lock().unwrap().apply(&record);
+                }
+                Some(Ok(Message::Close(frame))) => {
+                    return Err(frame.map_or_else(|| "closed".to_string(), |frame| frame.reason.to_string()).into());
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err("connection lost".into()),
+            }
+        }
+        let hand_over = FollowerMessage::HandOver { to: self.advertise.clone() };
+        ws.send(Message::Text(serde_json::to_string(&hand_over)?.into())).await?;
+        let _ = ws.close(None).await;
+        println!("🪞 Standby: asked {} to send its clients to {}", self.primary, self.advertise);
+        Ok(())
+    }
+}
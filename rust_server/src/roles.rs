@@ -0,0 +1,227 @@
+use serde::{Serialize, Deserialize};
+use std::io;
+use std::path::Path;
+use crate::admin::AdminCommand;
+use crate::json_store::JsonStore;
+
+/*
+Who may run which admin commands, kept across restarts in the file given
+as `roles_file`.
+
+Every player is a `player` unless granted more: a `moderator` may look
+around and keep order, an `owner` may do anything (see `required_role` for
+the table). Roles belong to names, compared by `normalize_name` as bans
+are. Players have no accounts, so granting `moderator` or `owner` issues
+the name a personal admin token, shown once to whoever granted it. A
+remote `Admin { token, command }` runs only if the token is the one issued
+to the name the connection is playing under and that name's role allows
+the command; the shared `admin_token`, if the server has one, still works
+from any connection and counts as an owner.
+
+The console commands `grant <name> <role>` and `revoke <name>` change
+roles; granting `player` is revoking. A name keeps its token while it
+keeps a role above `player`, and loses it when revoked. `roles` lists
+everyone above `player`. The `owners` config list is granted `owner` at
+every start, so the server's owners cannot lock themselves out; their
+tokens are printed on the console when first issued.
+
+The file is JSON, rewritten on every change (see `json_store`), and holds
+the tokens: keep it private. A file that cannot be read stops the server
+from starting (see `startup`) rather than dropping everyone's roles.
+
+Every admin command, from the console or remotely, allowed or not, is
+logged as an audit line naming who ran it, with which role, when, and
+what came of it: its result, the plan of a dry run, or why it was refused
+(see `audit_line`).
+*/
+
+/// What a player may do, least first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Player,
+    Moderator,
+    Owner,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Role::Player => "player",
+            Role::Moderator => "moderator",
+            Role::Owner => "owner",
+        })
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "player" => Ok(Role::Player),
+            "moderator" => Ok(Role::Moderator),
+            "owner" => Ok(Role::Owner),
+            other => Err(format!("unknown role: {} (expected player, moderator or owner)", other)),
+        }
+    }
+}
+
+/// The least role that may run `command`.
+pub fn required_role(command: &AdminCommand) -> Role {
+    match command {
+        AdminCommand::ListPlanets
+        | AdminCommand::ListPlayers { .. }
+        | AdminCommand::Stats { .. }
+        | AdminCommand::LifetimeStats
+        | AdminCommand::Whois { .. }
+        | AdminCommand::Kick { .. }
+        | AdminCommand::ListBans
+        | AdminCommand::Mute { .. }
+        | AdminCommand::Unmute { .. }
+        | AdminCommand::ListMutes
+        | AdminCommand::BroadcastStats
+        | AdminCommand::Announce { .. }
+        | AdminCommand::EconomyStats
+        | AdminCommand::Map { .. }
+        | AdminCommand::ListRoles => Role::Moderator,
+        AdminCommand::AddPlanet(_)
+        | AdminCommand::RemovePlanet { .. }
+        | AdminCommand::SetPlanet { .. }
+        | AdminCommand::Ban { .. }
+        | AdminCommand::Unban { .. }
+        | AdminCommand::Reload
+        | AdminCommand::RegenerateWorld { .. }
+        | AdminCommand::AddFakes { .. }
+        | AdminCommand::RemoveFakes { .. }
+        | AdminCommand::Pause
+        | AdminCommand::Step { .. }
+        | AdminCommand::Resume
+        | AdminCommand::SetTime { .. }
+        | AdminCommand::ValidateWorld { .. }
+        | AdminCommand::Promote
+        | AdminCommand::Grant { .. }
+        | AdminCommand::Revoke { .. } => Role::Owner,
+    }
+}
+
+/// The audit log line for `command`, run by `who` as `role` at `unix_ms`,
+/// and how it went: what it said it did, or the plan for a dry run, on one
+/// line.
+pub fn audit_line(who: &str, role: Role, command: &str, outcome: &Result<String, String>, unix_ms: u64) -> String {
+    let outcome = match outcome {
+        Ok(message) if message.trim().is_empty() => "done".to_string(),
+        Ok(message) => format!("done: {}", message.trim().replace('\n', "; ")),
+        Err(e) => format!("refused: {}", e),
+    };
+    format!("📋 audit at_unix_ms={} who={} role={} command=`{}`: {}", unix_ms, who, role, command.trim(), outcome)
+}
+
+/// A name granted more than `player`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,  // as granted
+    pub role: Role,
+    token: String,
+}
+
+/// What `grant` did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Granted {
+    /// The name's role changed; `token` is its new admin token, if it was
+    /// just issued one.
+    Changed { token: Option<String> },
+    Unchanged,
+    Revoked,
+}
+
+/// Everyone's roles, and where they are saved.
+#[derive(Debug, Clone, Default)]
+pub struct Roles(JsonStore<String, Profile>);
+
+impl Roles {
+    /// Nobody above `player`, kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The roles in `path`, saved back there on every change. A missing file
+    /// is nobody above `player`, created on the first grant.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        JsonStore::load(path).map(Roles)
+    }
+
+    pub fn role(&self, name: &str) -> Role {
+        self.0.get(name).map_or(Role::Player, |profile| profile.role)
+    }
+
+    /// `name`'s role, if `token` is the one issued to it.
+    pub fn authenticate(&self, name: &str, token: &str) -> Option<Role> {
+        let profile = self.0.get(name)?;
+        crate::server::constant_time_eq(profile.token.as_bytes(), token.as_bytes()).then_some(profile.role)
+    }
+
+    /// Whether anyone has a token to use remotely.
+    pub fn has_tokens(&self) -> bool {
+        !self.0.is_empty()
+    }
+
+    /// Gives `name` `role`, issuing it a token made by `new_token` if it had
+    /// none. Granting `player` revokes.
+    pub fn grant(&mut self, name: &str, role: Role, new_token: impl FnOnce() -> String) -> io::Result<Granted> {
+        let name = name.trim();
+        if role == Role::Player {
+            return Ok(match self.revoke(name)? {
+                Some(_) => Granted::Revoked,
+                None => Granted::Unchanged,
+            });
+        }
+        let granted = match self.0.get_mut(name) {
+            Some(profile) if profile.role == role => return Ok(Granted::Unchanged),
+            Some(profile) => {
+                profile.role = role;
+                Granted::Changed { token: None }
+            }
+            None => {
+                let token = new_token();
+                self.0.insert(name, Profile { name: name.to_string(), role, token: token.clone() });
+                Granted::Changed { token: Some(token) }
+            }
+        };
+        self.0.save()?;
+        Ok(granted)
+    }
+
+    /// Takes `name` back to `player`, returning the role it had.
+    pub fn revoke(&mut self, name: &str) -> io::Result<Option<Role>> {
+        let Some(profile) = self.0.remove(name) else { return Ok(None) };
+        self.0.save()?;
+        Ok(Some(profile.role))
+    }
+
+    /// Everyone above `player`, in normalized name order.
+    pub fn profiles(&self) -> Vec<Profile> {
+        self.0.values().cloned().collect()
+    }
+
+    /// Where the roles are saved, if anywhere.
+    pub fn path(&self) -> Option<&Path> {
+        self.0.path()
+    }
+
+    /// A copy that changes without saving, to see what a change would write.
+    pub fn detached(&self) -> Self {
+        Roles(self.0.detached())
+    }
+
+    /// The file's text: every profile by normalized name, as pretty JSON.
+    pub fn to_file_text(&self) -> String {
+        self.0.to_file_text()
+    }
+}
+
+/// A fresh admin token: 32 random hex digits.
+pub fn new_token() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
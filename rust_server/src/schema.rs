@@ -0,0 +1,86 @@
+use schemars::schema_for;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::protocol::{
+    ClientMessage, FrameField, ServerMessage, LEGACY_POSITION_FRAME_FIELDS, MOTION_FRAME_FIELDS,
+    POSITION_FRAME_FIELDS, RESYNC_FRAME_FIELDS, TIME_SYNC_FRAME_FIELDS,
+};
+
+/*
+Machine-readable description of the wire protocol for client authors.
+
+`server --dump-schema` prints it and the output is committed as
+`schema/protocol.json`; a test fails when the Rust types change without the
+file being regenerated:
+
+    cargo run --bin server -- --dump-schema > schema/protocol.json
+
+JSON mode is described by JSON Schemas generated from the message enums.
+Binary client frames are described by a field table (name, type, byte offset,
+size) computed from the same field lists the protocol module documents.
+Output is deterministic: the schemas come from the type definitions and all
+maps are emitted in sorted key order.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldLayout {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FrameLayout {
+    pub name: &'static str,
+    pub length: usize,
+    pub fields: Vec<FieldLayout>,
+}
+
+impl FrameLayout {
+    fn new(name: &'static str, fields: &[FrameField]) -> Self {
+        let mut offset = 0;
+        let fields = fields
+            .iter()
+            .map(|f| {
+                let layout = FieldLayout { name: f.name, ty: f.ty, offset, size: f.size };
+                offset += f.size;
+                layout
+            })
+            .collect();
+        FrameLayout { name, length: offset, fields }
+    }
+}
+
+/// Every fixed-layout binary client frame; the server tells them apart by length.
+pub fn binary_frame_layouts() -> Vec<FrameLayout> {
+    vec![
+        FrameLayout::new("time_sync_request", TIME_SYNC_FRAME_FIELDS),
+        FrameLayout::new("resync_request", RESYNC_FRAME_FIELDS),
+        FrameLayout::new("legacy_position", LEGACY_POSITION_FRAME_FIELDS),
+        FrameLayout::new("position", POSITION_FRAME_FIELDS),
+        FrameLayout::new("motion", MOTION_FRAME_FIELDS),
+    ]
+}
+
+pub fn protocol_schema() -> Value {
+    json!({
+        "json": {
+            "ServerMessage": schema_for!(ServerMessage),
+            "ClientMessage": schema_for!(ClientMessage),
+        },
+        "binary": {
+            "byte_order": "little-endian",
+            "client_frames": binary_frame_layouts(),
+            "server_messages": "bincode 1.x encoding of ServerMessage: u32 variant index in declaration order, then the variant's fields; text frames carry Notice and Echo",
+        },
+    })
+}
+
+/// The schema as committed to `schema/protocol.json`.
+pub fn dump_schema() -> String {
+    let mut out = serde_json::to_string_pretty(&protocol_schema()).expect("schema is valid JSON");
+    out.push('\n');
+    out
+}
@@ -0,0 +1,4273 @@
+use tokio_tungstenite::{
+    accept_hdr_async_with_config, WebSocketStream,
+    tungstenite::handshake::server::{ErrorResponse, Request, Response},
+    tungstenite::http::StatusCode,
+    tungstenite::protocol::Message,
+};
+use futures_util::{Sink, StreamExt, SinkExt};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Notify};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::time::MissedTickBehavior;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::protocol::{
+    decode_client_message, decode_sealed_client_message, encode_server_message, encode_state, seq_newer, server_ws_config, Capabilities, ClientMessage,
+    DecodeError, EncodeError, ErrorCode, GameState, MessageCode, Planet, PlanetCount, Player, PlayerAppearance, Position, PositionUpdate, ServerMessage, TeleportError, TerrainParams, WireFormat,
+    AmbientEvent, ChatLine, LandError, MineError, PlanetCursor, PlanetRenameError, PlanetSort, PlayerTrail, RenameError, SellError, TradeEnd, TradeError,
+    encoding_for_task, normalize_name, CAPABILITIES_HEADER, IDENTITY_ROTATION, WORLD_RADIUS_HEADER,
+};
+use crate::accept_guard::{AcceptGuard, AcceptLimits};
+use crate::ambient::{Ambient, AmbientConfig, SHOWER_YIELD};
+use crate::admin::{self, check_planet_counts, validate_planet, AdminCommand, AdminPlan, AffectedPlayer, FileWrite, PlanetEdit, PlanetLimits};
+use crate::announce::{render, Placeholders};
+use crate::bans::BanList;
+use crate::bandwidth::BandwidthGovernor;
+use crate::broadcast::{batch, BroadcastFrame, BroadcastStats, Urgency, MAX_BATCH_BYTES};
+use crate::config::{ConfigSource, LiveSettings};
+use crate::connection_state::{self, ConnectionState, Incoming, Outgoing};
+use crate::handshake::{client_info, Cidr, ClientInfo, Role};
+use crate::discovery::{in_discovery_range, level_for, Discoveries};
+use crate::economy::{Economy, EconomyStats, ECONOMY_INTERVAL};
+use crate::events::{EventBus, EventSink, EventTime, GameEvent, ServerCommand};
+use crate::fakes::{self, wander};
+use crate::friends::Friends;
+use crate::hibernate::{HibernationConfig, HibernationTracker};
+use crate::history::{HistoryConfig, HistoryStats, SnapshotHistory};
+use crate::quantize::{quantize_diffs, Quantization, QuantizedStream};
+use crate::journal::{Journal, JournalEvent, JournalRecord};
+use crate::keepalive::{KeepaliveAction, KeepaliveConfig, KeepaliveTracker};
+use crate::landing;
+use crate::chat_history::{ChatLog, JOIN_HISTORY};
+use crate::chat_receipts::{self, ChatError, ChatSequence};
+use crate::lifetime::{replace_file, LifetimeStats, ServerStats, SAVE_INTERVAL};
+use crate::locale::localized;
+use crate::module_effects::{asteroid_in_reach, credits_for, Wallet, MINING_XP};
+use crate::mutes::{Mute, MuteKind, MuteList};
+use crate::pause::{Hold, Pause, PausedUpdates, MAX_STEPS};
+use crate::planet_names::{generate_name, name_planets, name_taken, unique_name, valid_planet_name};
+use crate::players::PlayerShards;
+use crate::integrity::{self, CorruptFrame};
+use crate::id_hash::{IdMap, IdSet};
+use crate::idle::{ActivityTracker, IdleConfig, IdleEvent};
+use crate::population;
+use crate::prune::{PlanetActivity, PrunePolicy};
+use crate::ordering::Sequencer;
+use crate::query::{find_planets, planets_near, PlanetFilter};
+use crate::rate_limit::{ConnectionLimiter, Decision, RateLimitConfig};
+use crate::readiness::Readiness;
+use crate::replication::{Feed, FollowerMessage, Replica, Standby};
+use crate::roles::{self, audit_line, new_token, required_role, Granted, Profile, Roles};
+use crate::session::{DisconnectReason, SessionSummary, SessionTracker};
+use crate::snapshot::GameStateSnapshot;
+use crate::spawn::{choose_spawn_balanced, disperse, landing_position, Spawn, DEFAULT_SPAWN_DISPERSION, MAX_SPAWN_ATTEMPTS};
+use crate::stats::{ConnectionStats, CountingSink, DebugWindow, StatsSnapshot};
+use crate::subscriptions::Subscriptions;
+use crate::teleport::{TeleportConfig, TeleportCooldown};
+use crate::trade::{settle, Offer, Trades};
+use crate::trail::{TrailStats, Trails, SPECTATOR_SAMPLE_EVERY};
+use crate::wallets::Wallets;
+use crate::webhooks::{WebhookStats, Webhooks};
+use crate::world::{BoundsCorrection, BoundsTracker, WorldConfig};
+use crate::world_clock::{Phase, WorldClock};
+use crate::worldgen::{generate_terrain, generate_world, terrain_seed, RingGenerator, WorldGenerator};
+
+pub const DEFAULT_BROADCAST_INTERVAL: Duration = Duration::from_millis(100);
+pub const DEFAULT_MAX_SPEED: f32 = 500.0;  // units per second
+/// How long a single write to a client may take before it is disconnected.
+pub const DEFAULT_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Messages queued for a client before it is disconnected as too slow.
+pub const SEND_QUEUE_CAPACITY: usize = 256;
+/// How often a subscribed client gets its `DebugStats`.
+pub const DEBUG_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Identifies one client connection for as long as it lasts. Never reused,
+/// and unrelated to both the player id clients see and the socket address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(pub u64);
+
+impl std::fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Where a connection comes from, its traffic counters, and how to end it.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub addr: SocketAddr,
+    pub stats: Arc<ConnectionStats>,
+    pub kick: Arc<Notify>,
+}
+
+/// Why a connection was turned away instead of joining.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinRejection {
+    ServerFull,
+    SpectatorsFull,
+    TooManyFromAddress { ip: IpAddr, limit: usize },
+    Banned,
+    Standby,  // a follower not yet promoted (see `replication`)
+}
+
+impl JoinRejection {
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            JoinRejection::ServerFull | JoinRejection::SpectatorsFull => ErrorCode::ServerFull,
+            JoinRejection::TooManyFromAddress { .. } => ErrorCode::TooManyConnections,
+            JoinRejection::Banned => ErrorCode::Banned,
+            JoinRejection::Standby => ErrorCode::Standby,
+        }
+    }
+}
+
+impl std::fmt::Display for JoinRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinRejection::ServerFull => write!(f, "server full"),
+            JoinRejection::SpectatorsFull => write!(f, "no spectator slots left"),
+            JoinRejection::TooManyFromAddress { ip, limit } => write!(f, "too many connections from {} (limit {})", ip, limit),
+            JoinRejection::Banned => write!(f, "name banned"),
+            JoinRejection::Standby => write!(f, "on standby"),
+        }
+    }
+}
+
+/// One connected player, as listed by `players`.
+#[derive(Debug, Clone)]
+pub struct PlayerListing {
+    pub connection: ConnectionId,
+    pub addr: SocketAddr,
+    pub player: Player,
+    pub stats: StatsSnapshot,
+}
+
+/// One connected spectator, as listed by `players`.
+#[derive(Debug, Clone)]
+pub struct SpectatorListing {
+    pub connection: ConnectionId,
+    pub addr: SocketAddr,
+    pub stats: StatsSnapshot,
+}
+
+/// What `regenerate_world` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldReset {
+    pub seed: u64,
+    pub planets: usize,
+    pub players: usize,  // connected players moved to a new spawn
+}
+
+#[derive(Clone)]
+pub struct GameServer {
+    state: Arc<RwLock<Arc<GameState>>>,  // planets and belts; the players are in `players`
+    players: Arc<PlayerShards>,  // locked first, shards in index order
+    fakes: Arc<Mutex<IdSet<ConnectionId>>>,  // server-owned players (see `fakes`); locked after the shards
+    broadcast_tx: broadcast::Sender<Arc<BroadcastFrame>>,
+    live: Arc<watch::Sender<LiveSettings>>,  // settings a config reload may change
+    max_speed: f32,
+    write_timeout: Duration,
+    trusted_proxies: Vec<Cidr>,
+    accept_limits: AcceptLimits,  // see `accept_guard`
+    tick: Arc<AtomicU64>,
+    started_at: Instant,
+    history: Arc<Mutex<SnapshotHistory>>,
+    quantization: Quantization,  // of `StateDelta`s and `Resync` diffs for QUANTIZED clients
+    bus: EventBus,  // see `events`; the journal, feed and webhooks are attached to it
+    feed: Feed,  // the journal, for followers (see `replication`)
+    standby: Option<Arc<Standby>>,  // set when following another server
+    handover: Arc<watch::Sender<Option<String>>>,  // where clients go once a follower has taken over
+    webhooks: Option<Webhooks>,
+    next_planet_id: Arc<AtomicU32>,
+    planet_limits: PlanetLimits,
+    pruning: Option<PrunePolicy>,
+    planet_activity: Arc<Mutex<PlanetActivity>>,  // admin-added planets; never held with another lock
+    admin_token: Option<String>,
+    world: WorldConfig,
+    economy: Arc<Mutex<Economy>>,  // held only with `state`, locked before it
+    spawns: Arc<Mutex<IdMap<ConnectionId, Spawn>>>,
+    spawn_dispersion: f32,  // see `spawn`; 0 keeps players on their slots
+    teleport: TeleportConfig,
+    keepalive: KeepaliveConfig,
+    config_source: Option<Arc<Mutex<ConfigSource>>>,
+    connections: Arc<Mutex<IdMap<ConnectionId, Connection>>>,  // players and spectators; never touched by position updates
+    spectators: Arc<Mutex<IdSet<ConnectionId>>>,  // locked after connections
+    next_connection_id: Arc<AtomicU64>,
+    next_player_id: Arc<AtomicU32>,
+    discoveries: Arc<Mutex<HashMap<Arc<str>, Discoveries>>>,  // by normalized player name; locked last
+    bans: Arc<Mutex<BanList>>,  // never held with another lock
+    population: Arc<Mutex<Vec<PlanetCount>>>,  // as of the last broadcast; never held with another lock
+    sessions: Arc<Mutex<HashMap<Arc<str>, SessionSummary>>>,  // each name's last, by normalized name; never held with another lock
+    wallets: Arc<Mutex<Wallets>>,  // see `wallets`; locked last, never with `discoveries`
+    lifetime: Arc<Mutex<LifetimeStats>>,  // see `lifetime`; never held with another lock
+    chat_log: Arc<Mutex<ChatLog>>,  // see `chat_history`; never held with another lock
+    roles: Arc<Mutex<Roles>>,  // see `roles`; never held with another lock
+    friends: Arc<Mutex<Friends>>,  // see `friends`; never held with another lock
+    mutes: Arc<Mutex<MuteList>>,  // see `mutes`; never held with another lock
+    trades: Arc<Mutex<Trades>>,  // see `trade`; never held with another lock
+    clock: Arc<Mutex<WorldClock>>,  // see `world_clock`; never held with another lock
+    trails: Option<Arc<Mutex<Trails>>>,  // None unless trails are on; locked before the player shards
+    events: Arc<Mutex<Vec<ServerMessage>>>,  // batched until the next broadcast; nothing is locked after it
+    broadcast_stats: Arc<Mutex<BroadcastStats>>,
+    motd: Option<String>,
+    scheduled_announcements: Vec<(Duration, String)>,
+    generator: Arc<dyn WorldGenerator>,
+    shutdown: Arc<watch::Sender<bool>>,  // set once `run` stops accepting
+    readiness: Readiness,
+    ready: Arc<watch::Sender<Option<SocketAddr>>>,  // where `run` is accepting, once it is
+    hibernation: HibernationConfig,
+    wake: Arc<Notify>,  // a connection joined; see `hibernate`
+    asleep_since: Arc<Mutex<Option<Instant>>>,
+    pause: Arc<Mutex<Pause>>,  // see `pause`; never held with another lock
+    ambient: Option<Arc<Mutex<Ambient>>>,  // None unless ambient events are on; never held with another lock
+    #[cfg(feature = "tls")]
+    tls: Option<crate::tls::TlsAcceptor>,
+    #[cfg(feature = "cluster")]
+    cluster: Option<Arc<crate::cluster::Cluster>>,
+}
+
+impl Default for GameServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameServer {
+    /// A server whose worlds come from the built-in ring layout.
+    pub fn new() -> Self {
+        Self::with_generator(Box::new(RingGenerator))
+    }
+
+    /// A server whose worlds come from `generator`, here and in
+    /// `with_world_config`.
+    pub fn with_generator(generator: Box<dyn WorldGenerator>) -> Self {
+        let generator: Arc<dyn WorldGenerator> = Arc::from(generator);
+        let world = WorldConfig::default();
+        let initial_state = generate_world(&*generator, &world);
+        let next_planet_id = initial_state.next_body_id();
+        let (broadcast_tx, _) = broadcast::channel(100);
+        let feed = Feed::new();
+        let mut bus = EventBus::new();
+        bus.attach(Arc::new(feed.clone()));
+        GameServer {
+            state: Arc::new(RwLock::new(Arc::new(initial_state))),
+            players: Arc::new(PlayerShards::new()),
+            fakes: Arc::new(Mutex::new(IdSet::default())),
+            broadcast_tx,
+            live: Arc::new(watch::Sender::new(LiveSettings::default())),
+            max_speed: DEFAULT_MAX_SPEED,
+            write_timeout: DEFAULT_WRITE_TIMEOUT,
+            trusted_proxies: Vec::new(),
+            accept_limits: AcceptLimits::default(),
+            tick: Arc::new(AtomicU64::new(0)),
+            started_at: Instant::now(),
+            history: Arc::new(Mutex::new(SnapshotHistory::new(HistoryConfig::default()))),
+            quantization: Quantization::default(),
+            bus,
+            feed,
+            standby: None,
+            handover: Arc::new(watch::Sender::new(None)),
+            webhooks: None,
+            next_planet_id: Arc::new(AtomicU32::new(next_planet_id)),
+            planet_limits: PlanetLimits::default(),
+            pruning: None,
+            planet_activity: Arc::new(Mutex::new(PlanetActivity::new())),
+            admin_token: None,
+            world,
+            economy: Arc::new(Mutex::new(Economy::new(Instant::now()))),
+            spawns: Arc::new(Mutex::new(IdMap::default())),
+            spawn_dispersion: DEFAULT_SPAWN_DISPERSION,
+            teleport: TeleportConfig::default(),
+            keepalive: KeepaliveConfig::default(),
+            config_source: None,
+            connections: Arc::new(Mutex::new(IdMap::default())),
+            spectators: Arc::new(Mutex::new(IdSet::default())),
+            next_connection_id: Arc::new(AtomicU64::new(0)),
+            next_player_id: Arc::new(AtomicU32::new(0)),
+            discoveries: Arc::new(Mutex::new(HashMap::new())),
+            bans: Arc::new(Mutex::new(BanList::new())),
+            population: Arc::new(Mutex::new(Vec::new())),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            wallets: Arc::new(Mutex::new(Wallets::new())),
+            lifetime: Arc::new(Mutex::new(LifetimeStats::new(Instant::now()))),
+            chat_log: Arc::new(Mutex::new(ChatLog::new())),
+            roles: Arc::new(Mutex::new(Roles::new())),
+            friends: Arc::new(Mutex::new(Friends::new())),
+            mutes: Arc::new(Mutex::new(MuteList::new())),
+            trades: Arc::new(Mutex::new(Trades::default())),
+            clock: Arc::new(Mutex::new(WorldClock::default())),
+            trails: None,
+            events: Arc::new(Mutex::new(Vec::new())),
+            broadcast_stats: Arc::new(Mutex::new(BroadcastStats::default())),
+            motd: None,
+            scheduled_announcements: Vec::new(),
+            generator,
+            shutdown: Arc::new(watch::Sender::new(false)),
+            readiness: Readiness::default(),
+            ready: Arc::new(watch::Sender::new(None)),
+            hibernation: HibernationConfig::default(),
+            wake: Arc::new(Notify::new()),
+            asleep_since: Arc::new(Mutex::new(None)),
+            pause: Arc::new(Mutex::new(Pause::default())),
+            ambient: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "cluster")]
+            cluster: None,
+        }
+    }
+
+    pub fn with_rate_limits(self, rate_limits: RateLimitConfig) -> Self {
+        self.live.send_modify(|live| live.rate_limits = rate_limits);
+        self
+    }
+
+    pub fn with_max_speed(mut self, max_speed: f32) -> Self {
+        self.max_speed = max_speed;
+        self
+    }
+
+    pub fn with_write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    pub fn with_idle_config(self, idle: IdleConfig) -> Self {
+        self.live.send_modify(|live| live.idle = idle);
+        self
+    }
+
+    /// How often the full state is broadcast by `spawn_broadcast_loop`.
+    pub fn with_broadcast_interval(self, interval: Duration) -> Self {
+        self.live.send_modify(|live| live.broadcast_interval = interval);
+        self
+    }
+
+    /// Connections beyond this many players are turned away.
+    pub fn with_max_players(self, max_players: Option<usize>) -> Self {
+        self.live.send_modify(|live| live.max_players = max_players);
+        self
+    }
+
+    /// Spectators beyond this many are turned away; they never count as players.
+    pub fn with_max_spectators(self, max_spectators: Option<usize>) -> Self {
+        self.live.send_modify(|live| live.max_spectators = max_spectators);
+        self
+    }
+
+    /// Connections beyond this many from one IP address are turned away.
+    pub fn with_max_connections_per_ip(self, limit: Option<usize>) -> Self {
+        self.live.send_modify(|live| live.max_connections_per_ip = limit);
+        self
+    }
+
+    /// Connections beyond these are closed as soon as they are accepted
+    /// (see `accept_guard`).
+    pub fn with_accept_limits(mut self, limits: AcceptLimits) -> Self {
+        self.accept_limits = limits;
+        self
+    }
+
+    /// Connections sending more than `budget` bytes a second get fewer
+    /// States (see `bandwidth`).
+    pub fn with_bandwidth_budget(self, budget: Option<u64>) -> Self {
+        self.live.send_modify(|live| live.bandwidth_budget = budget);
+        self
+    }
+
+    /// Where `reload_config` re-reads the settings from.
+    pub fn with_config_source(mut self, source: ConfigSource) -> Self {
+        self.config_source = Some(Arc::new(Mutex::new(source)));
+        self
+    }
+
+    /// Proxies whose `X-Forwarded-For` / `X-Real-IP` headers are believed.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<Cidr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// How many recent broadcasts are kept for `ResyncFrom` requests.
+    pub fn with_history_config(mut self, config: HistoryConfig) -> Self {
+        self.history = Arc::new(Mutex::new(SnapshotHistory::new(config)));
+        self
+    }
+
+    /// How players' moves in `StateDelta`s and `Resync` diffs are quantized
+    /// for clients with the QUANTIZED capability (see `quantize`).
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        self
+    }
+
+    /// When the broadcast loop parks for lack of connections, and how far
+    /// the tick counter jumps when it wakes (see `hibernate`).
+    pub fn with_hibernation(mut self, hibernation: HibernationConfig) -> Self {
+        self.hibernation = hibernation;
+        self
+    }
+
+    /// Whether position updates are held or refused while the simulation
+    /// is paused (see `pause`).
+    pub fn with_paused_updates(self, policy: PausedUpdates) -> Self {
+        *self.pause.lock().unwrap() = Pause::new(policy);
+        self
+    }
+
+    /// How close two players may spawn (see `spawn`); 0 puts each on
+    /// their slot.
+    pub fn with_spawn_dispersion(mut self, radius: f32) -> Self {
+        self.spawn_dispersion = radius;
+        self
+    }
+
+    /// Where to say the server is ready once `run` is accepting (see
+    /// `readiness`).
+    pub fn with_readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    pub fn with_teleport_config(mut self, teleport: TeleportConfig) -> Self {
+        self.teleport = teleport;
+        self
+    }
+
+    /// How long a KEEPALIVE client may stay silent (see `keepalive`).
+    pub fn with_keepalive_config(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// Keeps each player's last `length` positions (see `trail`).
+    pub fn with_trails(mut self, length: usize) -> Self {
+        self.trails = Some(Arc::new(Mutex::new(Trails::new(length))));
+        self
+    }
+
+    /// Starts ambient events around the planets on the schedule `config`
+    /// describes (see `ambient`).
+    pub fn with_ambient(mut self, config: AmbientConfig) -> Self {
+        self.ambient = Some(Arc::new(Mutex::new(Ambient::new(config))));
+        self
+    }
+
+    /// Bounds player positions and regenerates the planets to fit inside the
+    /// radius, so call it before `with_world`.
+    pub fn with_world_config(mut self, world: WorldConfig) -> Self {
+        self.clock.lock().unwrap().set_day_length(world.day_length);
+        let state = generate_world(&*self.generator, &world);
+        self.world = world;
+        self.with_world(state)
+    }
+
+    /// Starts from a saved world instead of a generated one. Any players
+    /// saved with it are dropped: players come and go with their connections.
+    /// Planets saved without a name, or with one taken by another, are
+    /// named (see `planet_names`), and label colors are worked out again
+    /// (see `color`).
+    pub fn with_world(self, mut state: GameState) -> Self {
+        name_planets(&mut state.planets, self.world.seed.unwrap_or_default());
+        state.planets.iter_mut().for_each(Planet::refresh_display);
+        let next_planet_id = state.next_body_id();
+        self.next_planet_id.store(next_planet_id, Ordering::SeqCst);
+        state.players.clear();
+        self.clock.lock().unwrap().set(state.world_time);
+        *self.state.write().unwrap() = Arc::new(state);
+        self
+    }
+
+    /// Records every state change from here on, starting with the current world.
+    pub fn with_journal(self, journal: Journal) -> Self {
+        journal.record_world(self.current_tick(), self.server_time_ms(), self.persisted_snapshot(), false);
+        self.with_event_sink(Arc::new(journal))
+    }
+
+    /// Hands every event to `sink` as it happens (see `events`).
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.bus.attach(sink);
+        self
+    }
+
+    /// Callers hold the lock guarding what they changed so events are
+    /// journaled, and subscribers see them, in the same order as they are
+    /// applied (see `events`).
+    fn emit(&self, event: GameEvent) {
+        self.bus.emit(EventTime { tick: self.current_tick(), server_time_ms: self.server_time_ms(), unix_ms: unix_ms() }, event);
+    }
+
+    /// Every event from now on (see `events`). A subscriber that falls more
+    /// than `EVENT_CAPACITY` events behind misses the oldest of them, and is
+    /// told so with `RecvError::Lagged`.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<GameEvent> {
+        self.bus.subscribe()
+    }
+
+    /// Runs `command` as if an admin had (see `events`).
+    pub fn inject(&self, command: ServerCommand) -> Result<String, String> {
+        match command {
+            ServerCommand::Announce { text } => self.announce(&text),
+            ServerCommand::GrantXp { player_id, xp } => {
+                let mut players = self.players.lock_all();
+                let player = players.values_mut().find(|p| p.id == player_id && !p.remote).ok_or_else(|| self.missing_player(player_id))?;
+                self.earn_xp(player, xp);
+                Ok(format!("gave {} {} XP; now level {}", player.name, xp, player.level))
+            }
+            ServerCommand::AddPlanet { planet } => self.add_planet(planet).map(|planet| format!("added planet {}", planet.id)),
+        }
+    }
+
+    /// Follows `primary` as its warm standby, presenting `token`, and takes
+    /// no players until promoted; clients are then sent to `advertise`
+    /// (see `replication`).
+    pub fn with_standby(mut self, primary: String, token: String, advertise: String) -> Self {
+        self.standby = Some(Arc::new(Standby::new(primary, token, advertise)));
+        self
+    }
+
+    /// Whether this server follows another and has not been promoted.
+    pub fn is_standby(&self) -> bool {
+        self.standby.as_ref().is_some_and(|standby| !standby.is_promoted())
+    }
+
+    /// This server's copy of the primary's world, if it is a standby.
+    pub fn replica(&self) -> Option<Replica> {
+        self.standby.as_ref().filter(|standby| !standby.is_promoted()).map(|standby| standby.replica())
+    }
+
+    /// Takes over from the primary this server follows: its copy of the
+    /// world becomes this server's, with each player's XP kept under their
+    /// name, and the primary is asked to send its clients here.
+    pub fn promote(&self) -> Result<String, String> {
+        let standby = self.standby.as_ref().ok_or("this server is not a standby")?;
+        let (mut world, tick) = standby.promote()?;
+        let replicated = std::mem::take(&mut world.players);
+        {
+            let mut discoveries = self.discoveries.lock().unwrap();
+            for player in &replicated {
+                discoveries.entry(normalize_name(&player.name).into()).or_default().xp = player.xp;
+            }
+        }
+        let next_player_id = replicated.iter().map(|p| p.id + 1).max().unwrap_or(0);
+        self.next_player_id.fetch_max(next_player_id, Ordering::SeqCst);
+        self.next_planet_id.store(world.next_body_id(), Ordering::SeqCst);
+        self.tick.store(tick, Ordering::SeqCst);
+        self.clock.lock().unwrap().set(world.world_time);
+        let world_time = world.world_time;
+        let planets = world.planets.len();
+        let shared = Arc::new(world);
+        *self.state.write().unwrap() = shared.clone();
+        if self.bus.is_observed() {
+            let world = GameStateSnapshot::new(tick, shared, Vec::new(), world_time);
+            self.emit(GameEvent::WorldReset { world: Arc::new(world) });
+        }
+        self.population.lock().unwrap().clear();
+        println!("🪞 Promoted: took {} planets and {} player(s) from {}", planets, replicated.len(), standby.primary());
+        Ok(format!("promoted at tick {}; clients of {} are sent to {}", tick, standby.primary(), standby.advertise()))
+    }
+
+    /// The world as it is now, as a `WorldCreated`, and every record
+    /// published after it, for a follower (see `replication`).
+    fn follow(&self) -> (JournalRecord, broadcast::Receiver<Arc<JournalRecord>>) {
+        let world_time = self.world_time();
+        // Events are published under the lock guarding what they changed,
+        // so none falls between the copy and the subscription
+        let players = self.players.lock_all();
+        let fakes = self.fakes.lock().unwrap();
+        let state = self.state.read().unwrap();
+        let records = self.feed.subscribe();
+        let mut world = (**state).clone();
+        world.players = players.iter().filter(|(connection, _)| !fakes.contains(connection)).map(|(_, p)| p.clone()).collect();
+        world.players.sort_by_key(|p| p.id);
+        world.world_time = world_time;
+        let record = JournalRecord { tick: self.current_tick(), server_time_ms: self.server_time_ms(), unix_ms: unix_ms(), event: JournalEvent::WorldCreated { state: world } };
+        drop(state);
+        drop(fakes);
+        drop(players);
+        (record, records)
+    }
+
+    /// Sends every client to `to` and stops `run`, now that a follower has
+    /// taken over.
+    fn hand_over(&self, to: String) {
+        println!("🪞 Handing over to {}", to);
+        self.handover.send_replace(Some(to));
+    }
+
+    /// Tells `webhooks` about the server's events (see `webhooks`); they
+    /// start posting when the server runs.
+    pub fn with_webhooks(mut self, webhooks: Webhooks) -> Self {
+        self.webhooks = Some(webhooks.clone());
+        self.with_event_sink(Arc::new(webhooks))
+    }
+
+    /// How the webhooks have fared, if there are any.
+    pub fn webhook_stats(&self) -> Option<WebhookStats> {
+        self.webhooks.as_ref().map(Webhooks::stats)
+    }
+
+    /// Enables remote admin commands for clients presenting this token,
+    /// with an owner's rights.
+    pub fn with_admin_token(mut self, token: String) -> Self {
+        self.admin_token = Some(token);
+        self
+    }
+
+    /// Starts from these roles (see `roles`).
+    pub fn with_roles(mut self, roles: Roles) -> Self {
+        self.roles = Arc::new(Mutex::new(roles));
+        self
+    }
+
+    pub fn with_friends(mut self, friends: Friends) -> Self {
+        self.friends = Arc::new(Mutex::new(friends));
+        self
+    }
+
+    /// Starts from these wallets (see `wallets`).
+    pub fn with_wallets(mut self, wallets: Wallets) -> Self {
+        self.wallets = Arc::new(Mutex::new(wallets));
+        self
+    }
+
+    /// How long a trade offer waits for an answer (see `trade`).
+    pub fn with_trade_timeout(mut self, timeout: Duration) -> Self {
+        self.trades = Arc::new(Mutex::new(Trades::new(timeout)));
+        self
+    }
+
+    pub fn with_planet_limits(mut self, limits: PlanetLimits) -> Self {
+        self.planet_limits = limits;
+        self
+    }
+
+    pub fn planet_limits(&self) -> PlanetLimits {
+        self.planet_limits
+    }
+
+    /// Removes admin-added planets left unvisited and unowned on the
+    /// economy tick (see `prune`).
+    pub fn with_pruning(mut self, policy: PrunePolicy) -> Self {
+        self.pruning = Some(policy);
+        self
+    }
+
+    /// Sent to each player after their join ack; see `announce` for the
+    /// placeholders. Templates are checked when the config is loaded.
+    pub fn with_motd(mut self, motd: String) -> Self {
+        self.motd = Some(motd);
+        self
+    }
+
+    /// Terminates TLS on every connection `run` accepts.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, acceptor: crate::tls::TlsAcceptor) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    /// Shares the world with the other instances of a cluster (see
+    /// `cluster`). Player ids start from this instance's range, so call it
+    /// before adding fake players.
+    #[cfg(feature = "cluster")]
+    pub fn with_cluster(mut self, config: crate::cluster::ClusterConfig) -> Self {
+        self.next_player_id.store(crate::cluster::first_player_id(config.instance), Ordering::SeqCst);
+        self.cluster = Some(Arc::new(crate::cluster::Cluster::new(config)));
+        self
+    }
+
+    /// Players connected to the other instances of the cluster, if any.
+    fn remote_players(&self) -> Vec<Player> {
+        #[cfg(feature = "cluster")]
+        if let Some(cluster) = &self.cluster {
+            return cluster.remote_players(Instant::now());
+        }
+        Vec::new()
+    }
+
+    /// Whether the planets are this instance's to change; in a cluster only
+    /// the primary's are.
+    pub fn owns_planets(&self) -> bool {
+        #[cfg(feature = "cluster")]
+        if let Some(cluster) = &self.cluster {
+            return cluster.config().is_primary();
+        }
+        true
+    }
+
+    fn check_owns_planets(&self) -> Result<(), String> {
+        if self.owns_planets() { Ok(()) } else { Err("the planets belong to the cluster's primary instance".to_string()) }
+    }
+
+    /// Tells the rest of the cluster, if there is one, about the planets.
+    fn planets_changed(&self) {
+        #[cfg(feature = "cluster")]
+        if let Some(cluster) = &self.cluster {
+            cluster.publish_planets(&self.world_snapshot().planets);
+        }
+    }
+
+    /// Takes the planets the cluster's primary sent; anyone landed on a
+    /// planet that is gone takes off where they stand.
+    #[cfg(feature = "cluster")]
+    pub(crate) fn replace_planets(&self, planets: Vec<Planet>) {
+        let mut players = self.players.lock_all();
+        let mut world = self.state.write().unwrap();
+        let state = Arc::make_mut(&mut world);
+        state.planets = planets;
+        for player in players.values_mut() {
+            if player.landed_on.is_some_and(|id| state.planet_by_id(id).is_none()) {
+                player.landed_on = None;
+            }
+        }
+        let count = state.planets.len();
+        drop(world);
+        drop(players);
+        println!("🔗 Cluster: took {} planets from the primary", count);
+    }
+
+    /// Announcements repeated to everyone by `spawn_scheduled_announcements`.
+    pub fn with_scheduled_announcements(mut self, announcements: Vec<(Duration, String)>) -> Self {
+        self.scheduled_announcements = announcements;
+        self
+    }
+
+    pub fn get_state(&self) -> GameState {
+        let mut state = (*self.world_snapshot()).clone();
+        state.players = self.players.snapshot();
+        state.world_time = self.world_time();
+        state
+    }
+
+    /// The time of day, 0 to 1 from midnight (see `world_clock`).
+    pub fn world_time(&self) -> f32 {
+        self.clock.lock().unwrap().time()
+    }
+
+    /// Jumps the time of day to `world_time` and tells everyone to snap
+    /// to it.
+    pub fn set_world_time(&self, world_time: f32) -> Result<String, String> {
+        if !(0.0..1.0).contains(&world_time) {
+            return Err("the time of day must be at least 0 and below 1".to_string());
+        }
+        self.clock.lock().unwrap().set(world_time);
+        let phase = Phase::of(world_time);
+        println!("🕰️  Time of day set to {:.3} ({})", world_time, phase);
+        self.broadcast_message(ServerMessage::TimeSet { world_time }, Urgency::Immediate);
+        Ok(format!("time of day set to {:.3} ({})", world_time, phase))
+    }
+
+    /// The world as it is now, to read at length without holding up the
+    /// game: the planets are shared rather than copied (see `snapshot`).
+    pub fn snapshot(&self) -> Arc<GameStateSnapshot> {
+        Arc::new(GameStateSnapshot::new(self.current_tick(), self.world_snapshot(), self.players.snapshot(), self.world_time()))
+    }
+
+    /// Saves a snapshot of the world to `path` as `world::save_world`
+    /// would, serializing and writing it on a blocking thread.
+    pub async fn save_to_file(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let snapshot = self.snapshot();
+        let path = path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || replace_file(&path, &snapshot.to_json()?))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    /// The planets and belts as they are now, without the players. Holds
+    /// the state lock only long enough to share the current copy; admin
+    /// edits copy it again if a snapshot is still in use.
+    fn world_snapshot(&self) -> Arc<GameState> {
+        self.state.read().unwrap().clone()
+    }
+
+    /// Validates and adds a planet under a fresh id, named if it has no
+    /// name (see `planet_names`), with its label colors worked out (see
+    /// `color`).
+    pub fn add_planet(&self, mut planet: Planet) -> Result<Planet, String> {
+        self.check_owns_planets()?;
+        let mut world = self.state.write().unwrap();
+        let state = Arc::make_mut(&mut world);
+        // A name another planet has, say on a copy of it, is told apart by a number
+        if !planet.name.is_empty() {
+            planet.name = unique_name(&planet.name, |name| name_taken(&state.planets, name, u32::MAX).is_some());
+        }
+        let candidate = Planet { id: u32::MAX, ..planet.clone() };
+        validate_planet(&candidate, &state.planets, &self.planet_limits)?;
+        check_planet_counts(&candidate, None, &state.planets, &self.planet_limits)?;
+        planet.id = self.next_planet_id.fetch_add(1, Ordering::SeqCst);
+        let seed = self.world.seed.unwrap_or_else(rand::random);
+        if planet.terrain == TerrainParams::default() {
+            planet.terrain = generate_terrain(terrain_seed(seed, planet.id), planet.module_type);
+        }
+        if planet.name.is_empty() {
+            planet.name = unique_name(&generate_name(seed, planet.id), |name| name_taken(&state.planets, name, planet.id).is_some());
+        }
+        planet.refresh_display();
+        state.planets.push(planet.clone());
+        self.emit(GameEvent::PlanetAdded { planet: planet.clone() });
+        drop(world);
+        self.planet_activity.lock().unwrap().added(planet.id, Instant::now());
+        self.planets_changed();
+
+        println!("🪐 Planet {} added", planet.id);
+        self.broadcast_message(ServerMessage::PlanetAdded { planet: planet.clone() }, Urgency::Batched);
+        Ok(planet)
+    }
+
+    /// Removes a planet; anyone landed on it takes off where they stand.
+    pub fn remove_planet(&self, planet_id: u32) -> Result<(), String> {
+        self.plan_remove_planet(planet_id, false).map(|_| ())
+    }
+
+    /// Removes a planet, or for a dry run only says what removing it would
+    /// do (see `admin`).
+    pub fn plan_remove_planet(&self, planet_id: u32, dry_run: bool) -> Result<AdminPlan, String> {
+        self.check_owns_planets()?;
+        let mut players = self.players.lock_all();
+        let mut world = self.state.write().unwrap();
+        let index = world.planet_position(planet_id)
+            .ok_or_else(|| format!("no planet with id {}", planet_id))?;
+        let mut landed: Vec<AffectedPlayer> = players.values()
+            .filter(|p| p.landed_on == Some(planet_id))
+            .map(|p| AffectedPlayer { id: p.id, name: p.name.to_string() })
+            .collect();
+        landed.sort_by_key(|p| p.id);
+        let plan = AdminPlan {
+            command: format!("planet remove {}", planet_id),
+            players_affected: landed,
+            planets_removed: vec![planet_id],
+            ..AdminPlan::default()
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+        let state = Arc::make_mut(&mut world);
+        state.planets.remove(index);
+        self.emit(GameEvent::PlanetRemoved { planet_id });
+        for player in players.values_mut().filter(|p| p.landed_on == Some(planet_id)) {
+            player.landed_on = None;
+            self.emit(GameEvent::LandingChanged { player_id: player.id, landed_on: None, position: player.position.clone() });
+        }
+        drop(world);
+        drop(players);
+        self.planet_activity.lock().unwrap().forget(planet_id);
+        self.planets_changed();
+
+        println!("🪐 Planet {} removed", planet_id);
+        self.broadcast_message(ServerMessage::PlanetRemoved { planet_id }, Urgency::Batched);
+        Ok(plan)
+    }
+
+    /// Edits a planet; anyone landed on it moves with it (see `landing`).
+    pub fn update_planet(&self, planet_id: u32, edit: &PlanetEdit) -> Result<Planet, String> {
+        self.check_owns_planets()?;
+        let mut players = self.players.lock_all();
+        let mut world = self.state.write().unwrap();
+        let state = Arc::make_mut(&mut world);
+        let index = state.planet_position(planet_id)
+            .ok_or_else(|| format!("no planet with id {}", planet_id))?;
+        let mut planet = state.planets[index].clone();
+        edit.apply(&mut planet);
+        validate_planet(&planet, &state.planets, &self.planet_limits)?;
+        check_planet_counts(&planet, Some(&state.planets[index]), &state.planets, &self.planet_limits)?;
+        let before = std::mem::replace(&mut state.planets[index], planet.clone());
+        self.emit(GameEvent::PlanetUpdated { planet: planet.clone() });
+        for player in players.values_mut().filter(|p| p.landed_on == Some(planet_id)) {
+            player.position = landing::follow(&before, &planet, &player.position);
+            self.emit(GameEvent::LandingChanged { player_id: player.id, landed_on: Some(planet_id), position: player.position.clone() });
+        }
+        drop(world);
+        drop(players);
+        self.planets_changed();
+
+        println!("🪐 Planet {} updated", planet_id);
+        if let Some(owner) = planet.owner.filter(|owner| before.owner != Some(*owner)) {
+            self.emit(GameEvent::PlanetClaimed { planet_id, owner });
+        }
+        self.broadcast_message(ServerMessage::PlanetUpdated { planet: planet.clone() }, Urgency::Batched);
+        Ok(planet)
+    }
+
+    /// Renames a planet the player owns (see `planet_names`), and tells
+    /// everyone.
+    pub fn rename_planet(&self, connection: ConnectionId, planet_id: u32, name: &str) -> Result<Planet, PlanetRenameError> {
+        if !self.owns_planets() {
+            return Err(PlanetRenameError::Elsewhere);
+        }
+        let player_id = self.players.shard(connection).get(&connection).map(|p| p.id);
+        if !valid_planet_name(name) {
+            return Err(PlanetRenameError::InvalidName { name: name.to_string() });
+        }
+        let mut world = self.state.write().unwrap();
+        let state = Arc::make_mut(&mut world);
+        let planet = state.planet_by_id(planet_id).ok_or(PlanetRenameError::UnknownPlanet { planet_id })?;
+        if player_id.is_none() || planet.owner != player_id {
+            return Err(PlanetRenameError::NotOwner { planet_id });
+        }
+        if let Some(other) = name_taken(&state.planets, name, planet_id) {
+            return Err(PlanetRenameError::NameTaken { name: name.to_string(), planet_id: other });
+        }
+        let planet = state.planet_by_id_mut(planet_id).expect("found above");
+        planet.name = name.to_string();
+        let planet = planet.clone();
+        self.emit(GameEvent::PlanetUpdated { planet: planet.clone() });
+        drop(world);
+        self.planets_changed();
+
+        println!("🪐 Planet {} renamed {}", planet_id, name);
+        self.broadcast_message(ServerMessage::PlanetRenamed { planet_id, name: name.to_string() }, Urgency::Batched);
+        Ok(planet)
+    }
+
+    /// The id of the planet called `name`, compared as `planet_names` does.
+    pub fn planet_named(&self, name: &str) -> Option<u32> {
+        self.world_snapshot().planet_by_name(name).map(|planet| planet.id)
+    }
+
+    /// Replaces every planet with a world freshly generated from `seed` (a
+    /// random one when `None`), keeping the connected players. Claims and
+    /// discovered planets go with the old world, but XP stays. Every player
+    /// moves to a spawn in the new world, and everyone gets a `WorldReset`
+    /// followed by the full new state.
+    ///
+    /// All the locks are taken up front, in the usual order, so position
+    /// updates wait for the swap instead of landing in the old world.
+    pub fn regenerate_world(&self, seed: Option<u64>) -> WorldReset {
+        let (_, reset) = self.plan_regenerate_world(seed.unwrap_or_else(rand::random), false);
+        reset.expect("not a dry run")
+    }
+
+    /// Regenerates the world from `seed`, or for a dry run only says what
+    /// that would do (see `admin`), returning the reset when it happened.
+    pub fn plan_regenerate_world(&self, seed: u64, dry_run: bool) -> (AdminPlan, Option<WorldReset>) {
+        let world = generate_world(&*self.generator, &WorldConfig { seed: Some(seed), ..self.world.clone() });
+
+        let mut players = self.players.lock_all();
+        let fakes = self.fakes.lock().unwrap();
+        let mut spawns = self.spawns.lock().unwrap();
+        let mut state = self.state.write().unwrap();
+        let mut discoveries = self.discoveries.lock().unwrap();
+
+        let mut moved: Vec<AffectedPlayer> = players.values().map(|p| AffectedPlayer { id: p.id, name: p.name.to_string() }).collect();
+        moved.sort_by_key(|p| p.id);
+        let plan = AdminPlan {
+            command: format!("regenerate-world {}", seed),
+            players_affected: moved,
+            planets_removed: state.planets.iter().map(|p| p.id).collect(),
+            planets_added: world.planets.len(),
+            files_written: Vec::new(),
+        };
+        if dry_run {
+            return (plan, None);
+        }
+
+        spawns.clear();
+        let mut connections: Vec<ConnectionId> = players.iter().map(|(connection, _)| *connection).collect();
+        connections.sort();
+        for connection in connections {
+            let player = players.get_mut(&connection).expect("listed above");
+            let spawn = choose_spawn_balanced(&world.planets, spawns.values(), &[], &world.initial_player_location);
+            let mut spawn = self.disperse(spawn, &world, &player.name, connection.0, spawns.values().map(|s| &s.position));
+            spawn.position = self.world.clamp(&spawn.position);
+            player.position = spawn.position.clone();
+            player.velocity = [0.0; 3];
+            player.landed_on = None;
+            spawns.insert(connection, spawn);
+        }
+        for log in discoveries.values_mut() {
+            log.forget_planets();
+        }
+        self.next_planet_id.store(world.next_body_id(), Ordering::SeqCst);
+        let shared = Arc::new(world);
+        *state = shared.clone();
+        if self.bus.is_observed() {
+            let mut persisted: Vec<Player> = players.iter().filter(|(connection, _)| !fakes.contains(connection)).map(|(_, p)| p.clone()).collect();
+            persisted.sort_by_key(|p| p.id);
+            let world = GameStateSnapshot::new(self.current_tick(), shared.clone(), persisted, shared.world_time);
+            self.emit(GameEvent::WorldReset { world: Arc::new(world) });
+        }
+        let mut world = (*shared).clone();
+        world.players = players.values().cloned().collect();
+        world.players.sort_by_key(|p| p.id);
+        let reset = WorldReset { seed, planets: world.planets.len(), players: players.len() };
+        let snapshot = ServerMessage::State { tick: self.current_tick(), server_time_ms: self.server_time_ms(), state: world };
+        drop(discoveries);
+        drop(state);
+        drop(spawns);
+        drop(fakes);
+        drop(players);
+        self.population.lock().unwrap().clear();
+        self.planet_activity.lock().unwrap().clear();
+        if let Some(trails) = &self.trails {
+            trails.lock().unwrap().clear_all();
+        }
+        self.planets_changed();
+
+        println!("🌌 World regenerated from seed {} with {} planets", seed, reset.planets);
+        self.broadcast_message(ServerMessage::WorldReset { seed }, Urgency::Immediate);
+        self.broadcast_message(snapshot, Urgency::Immediate);
+        (plan, Some(reset))
+    }
+
+    /// Runs a remote admin command if `token` matches the configured one.
+    pub fn run_admin_command(&self, token: &str, command: &str) -> Result<String, String> {
+        self.admin_with_token(token, command, |parsed, dry_run| admin::run(self, parsed, dry_run))
+    }
+
+    /// Runs a remote admin command sent by `connection`: with the shared
+    /// admin token, or with the token issued to the name it plays under if
+    /// that name's role allows the command (see `roles`).
+    pub fn run_player_admin_command(&self, connection: ConnectionId, token: &str, command: &str) -> Result<String, String> {
+        self.player_admin(connection, token, command, |parsed, dry_run| admin::run(self, parsed, dry_run))
+    }
+
+    /// What a remote admin command sent by `connection` would do, allowed
+    /// as `run_player_admin_command` allows it, changing nothing (see
+    /// `admin`).
+    pub fn plan_player_admin_command(&self, connection: ConnectionId, token: &str, command: &str) -> Result<AdminPlan, String> {
+        self.player_admin(connection, token, command, |parsed, _| admin::plan(self, parsed))
+    }
+
+    /// Runs `run` on the command if `token` is the shared admin token, and
+    /// logs it.
+    fn admin_with_token<T: std::fmt::Display>(&self, token: &str, command: &str, run: impl FnOnce(AdminCommand, bool) -> Result<T, String>) -> Result<T, String> {
+        let outcome = match &self.admin_token {
+            None => Err("remote admin is disabled".to_string()),
+            Some(expected) if !constant_time_eq(expected.as_bytes(), token.as_bytes()) => Err("invalid admin token".to_string()),
+            Some(_) => admin::parse_request(command).and_then(|(parsed, dry_run)| run(parsed, dry_run)),
+        };
+        let logged = outcome.as_ref().map(T::to_string).map_err(String::clone);
+        println!("{}", audit_line("admin token", roles::Role::Owner, command, &logged, unix_ms()));
+        outcome
+    }
+
+    /// Runs `run` on the command if `connection` may, and logs it.
+    fn player_admin<T: std::fmt::Display>(&self, connection: ConnectionId, token: &str, command: &str, run: impl FnOnce(AdminCommand, bool) -> Result<T, String>) -> Result<T, String> {
+        if let Some(expected) = &self.admin_token
+            && constant_time_eq(expected.as_bytes(), token.as_bytes())
+        {
+            return self.admin_with_token(token, command, run);
+        }
+        let name = self.players.shard(connection).get(&connection).map(|player| player.name.clone());
+        let roles = self.roles.lock().unwrap();
+        let authenticated = name.as_deref().and_then(|name| roles.authenticate(name, token));
+        let has_tokens = roles.has_tokens();
+        drop(roles);
+        let who = name.as_deref().unwrap_or("a spectator");
+        let (role, outcome) = match authenticated {
+            None if self.admin_token.is_none() && !has_tokens => (roles::Role::Player, Err("remote admin is disabled".to_string())),
+            None => (roles::Role::Player, Err("invalid admin token".to_string())),
+            Some(role) => (role, admin::parse_request(command).and_then(|(parsed, dry_run)| {
+                let needed = required_role(&parsed);
+                if role < needed {
+                    return Err(format!("{} needs the {} role; {} is a {}", command.split_whitespace().next().unwrap_or_default(), needed, who, role));
+                }
+                run(parsed, dry_run)
+            })),
+        };
+        let logged = outcome.as_ref().map(T::to_string).map_err(String::clone);
+        println!("{}", audit_line(who, role, command, &logged, unix_ms()));
+        outcome
+    }
+
+    /// Gives `name` `role` (see `roles`), saying which token it was issued.
+    pub fn grant_role(&self, name: &str, role: roles::Role) -> Result<String, String> {
+        let name = name.trim();
+        match self.roles.lock().unwrap().grant(name, role, new_token) {
+            Ok(Granted::Changed { token: Some(token) }) => Ok(format!("{} is now a {}; their admin token is {}", name, role, token)),
+            Ok(Granted::Changed { token: None }) => Ok(format!("{} is now a {}", name, role)),
+            Ok(Granted::Unchanged) => Err(format!("{} is already a {}", name, role)),
+            Ok(Granted::Revoked) => Ok(format!("{} is now a player", name)),
+            Err(e) => Err(format!("granted {} the {} role until restart, but could not save the roles: {}", name, role, e)),
+        }
+    }
+
+    pub fn revoke_role(&self, name: &str) -> Result<String, String> {
+        let role = self.roles.lock().unwrap().role(name);
+        self.plan_revoke_role(name, false).map(|_| format!("{} is no longer a {}", name.trim(), role))
+    }
+
+    /// Revokes `name`'s role, or for a dry run only says what that would
+    /// write (see `admin`).
+    pub fn plan_revoke_role(&self, name: &str, dry_run: bool) -> Result<AdminPlan, String> {
+        let name = name.trim();
+        let mut roles = self.roles.lock().unwrap();
+        let mut after = roles.detached();
+        if after.revoke(name).is_ok_and(|role| role.is_none()) {
+            return Err(format!("{} has no role to revoke", name));
+        }
+        let plan = AdminPlan {
+            command: format!("revoke {}", name),
+            files_written: roles.path().map(|path| FileWrite::new(path, &after.to_file_text())).into_iter().collect(),
+            ..AdminPlan::default()
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+        match roles.revoke(name) {
+            Ok(_) => Ok(plan),
+            Err(e) => Err(format!("revoked {}'s role until restart, but could not save the roles: {}", name, e)),
+        }
+    }
+
+    /// Everyone above `player`.
+    pub fn role_profiles(&self) -> Vec<Profile> {
+        self.roles.lock().unwrap().profiles()
+    }
+
+    /// Makes every one of `names` an owner, printing the tokens issued to
+    /// those who were not.
+    pub fn bootstrap_owners(&self, names: &[String]) {
+        for name in names {
+            let granted = self.roles.lock().unwrap().grant(name, roles::Role::Owner, new_token);
+            match granted {
+                Ok(Granted::Changed { token: Some(token) }) => println!("🔑 {} is an owner; their admin token is {}", name.trim(), token),
+                Ok(Granted::Changed { token: None }) => println!("🔑 {} is an owner again", name.trim()),
+                Ok(_) => {}
+                Err(e) => println!("⚠️  Could not save {} as an owner: {}", name.trim(), e),
+            }
+        }
+    }
+
+    pub fn live_settings(&self) -> LiveSettings {
+        *self.live.borrow()
+    }
+
+    /// Re-reads the config source and applies the live-safe settings.
+    /// Changed restart-only settings are logged and left as they were.
+    pub fn reload_config(&self) -> Result<String, String> {
+        let source = self.config_source.as_ref().ok_or("no configuration to reload")?;
+        let reload = source.lock().unwrap().reload()?;
+        for key in &reload.ignored {
+            println!("⚠️  Config reload: `{}` changed but only takes effect after a restart", key);
+        }
+        self.live.send_if_modified(|live| std::mem::replace(live, reload.live) != reload.live);
+        let live = reload.live;
+        println!("🔄 Config reloaded: broadcast every {:?}, max players {}, {}/s positions, {}/s chat, idle after {:?}, disconnect after {:?}",
+                 live.broadcast_interval,
+                 live.max_players.map_or("unlimited".to_string(), |n| n.to_string()),
+                 live.rate_limits.position_updates_per_sec,
+                 live.rate_limits.chat_messages_per_sec,
+                 live.idle.idle_after,
+                 live.idle.disconnect_after);
+        Ok(if reload.ignored.is_empty() {
+            "configuration reloaded".to_string()
+        } else {
+            format!("configuration reloaded; restart to apply {}", reload.ignored.join(", "))
+        })
+    }
+
+    /// Connected players in player id order, with their traffic counters.
+    pub fn player_list(&self, now: Instant) -> Vec<PlayerListing> {
+        let players = self.players.lock_all();
+        let connections = self.connections.lock().unwrap();
+        let mut list: Vec<PlayerListing> = players.iter()
+            .filter_map(|(id, player)| {
+                let connection = connections.get(id)?;
+                Some(PlayerListing { connection: *id, addr: connection.addr, player: player.clone(), stats: connection.stats.snapshot(now) })
+            })
+            .collect();
+        list.sort_by_key(|listing| listing.player.id);
+        list
+    }
+
+    /// Connected spectators in connection order, with their traffic counters.
+    pub fn spectator_list(&self, now: Instant) -> Vec<SpectatorListing> {
+        let connections = self.connections.lock().unwrap();
+        let spectators = self.spectators.lock().unwrap();
+        let mut list: Vec<SpectatorListing> = spectators.iter()
+            .filter_map(|id| {
+                let connection = connections.get(id)?;
+                Some(SpectatorListing { connection: *id, addr: connection.addr, stats: connection.stats.snapshot(now) })
+            })
+            .collect();
+        list.sort_by_key(|listing| listing.connection);
+        list
+    }
+
+    /// `spawn` moved off its slot to a point of its own, clear of `others`,
+    /// for a player called `name` joining with `nonce` (see `spawn`).
+    fn disperse<'a>(&self, spawn: Spawn, state: &GameState, name: &str, nonce: u64, others: impl IntoIterator<Item = &'a Position>) -> Spawn {
+        if self.spawn_dispersion <= 0.0 {
+            return spawn;
+        }
+        let (center, size) = match spawn.planet_id.and_then(|id| state.planet_by_id(id)) {
+            Some(planet) => (planet.position.clone(), planet.size),
+            None => (state.initial_player_location.clone(), 0.0),
+        };
+        match disperse(&center, size, name, nonce, self.spawn_dispersion, others) {
+            Some(position) => Spawn { position, ..spawn },
+            None => {
+                println!("🛸 No clear spawn point for {} in {} attempts; using slot {}", name, MAX_SPAWN_ATTEMPTS, spawn.slot);
+                spawn
+            }
+        }
+    }
+
+    /// Traffic counters of the player with public id `id`.
+    /// Disconnects the player with this id, telling their client it was kicked.
+    pub fn kick_player(&self, id: u32) -> Result<String, String> {
+        let plan = self.plan_kick_player(id, false)?;
+        Ok(format!("kicked #{} {}", id, plan.players_affected[0].name))
+    }
+
+    /// Kicks the player with this id, or for a dry run only says who that
+    /// would be (see `admin`).
+    pub fn plan_kick_player(&self, id: u32, dry_run: bool) -> Result<AdminPlan, String> {
+        let players = self.players.lock_all();
+        let (connection, player) = players.iter().find(|(_, p)| p.id == id)
+            .ok_or_else(|| self.missing_player(id))?;
+        let plan = AdminPlan {
+            command: format!("kick {}", id),
+            players_affected: vec![AffectedPlayer { id, name: player.name.to_string() }],
+            ..AdminPlan::default()
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+        if let Some(kicked) = self.connections.lock().unwrap().get(connection) {
+            kicked.kick.notify_one();
+        }
+        Ok(plan)
+    }
+
+    /// Why there is no local player with this id to act on.
+    fn missing_player(&self, id: u32) -> String {
+        #[cfg(feature = "cluster")]
+        if let Some(instance) = self.cluster.as_ref().and_then(|cluster| cluster.instance_of(id)) {
+            return format!("player {} is connected to cluster instance {}", id, instance);
+        }
+        format!("no connected player with id {}", id)
+    }
+
+    /// Bans `name` (see `bans`). Players already connected under it stay
+    /// until kicked.
+    pub fn ban(&self, name: &str) -> Result<String, String> {
+        self.plan_ban(name, false).map(|_| format!("banned {}", name.trim()))
+    }
+
+    /// Bans `name`, or for a dry run only says what that would write (see
+    /// `admin`).
+    pub fn plan_ban(&self, name: &str, dry_run: bool) -> Result<AdminPlan, String> {
+        let mut bans = self.bans.lock().unwrap();
+        let mut after = bans.detached();
+        if after.ban(name).is_ok_and(|banned| !banned) {
+            return Err(format!("{} is already banned", name.trim()));
+        }
+        let plan = AdminPlan {
+            command: format!("ban {}", name.trim()),
+            files_written: bans.path().map(|path| FileWrite::new(path, &after.to_file_text())).into_iter().collect(),
+            ..AdminPlan::default()
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+        match bans.ban(name) {
+            Ok(_) => {
+                drop(bans);
+                self.emit(GameEvent::BanIssued { name: name.trim().to_string() });
+                Ok(plan)
+            }
+            Err(e) => Err(format!("banned {} until restart, but could not save the ban list: {}", name.trim(), e)),
+        }
+    }
+
+    pub fn unban(&self, name: &str) -> Result<String, String> {
+        match self.bans.lock().unwrap().unban(name) {
+            Ok(Some(banned)) => Ok(format!("unbanned {}", banned)),
+            Ok(None) => Err(format!("{} is not banned", name.trim())),
+            Err(e) => Err(format!("unbanned {} until restart, but could not save the ban list: {}", name.trim(), e)),
+        }
+    }
+
+    /// The banned names, as they were banned.
+    pub fn banned_names(&self) -> Vec<String> {
+        self.bans.lock().unwrap().names()
+    }
+
+    /// Mutes `name` for `duration`, or until unmuted (see `mutes`). It
+    /// holds from the player's next chat, whether they are online or not.
+    pub fn mute(&self, name: &str, kind: MuteKind, duration: Option<Duration>) -> Result<String, String> {
+        let now = unix_ms();
+        match self.mutes.lock().unwrap().mute(name, kind, duration, now) {
+            Ok(mute) => Ok(mute.describe(now)),
+            Err(e) => Err(format!("muted {} until restart, but could not save the mute list: {}", name.trim(), e)),
+        }
+    }
+
+    pub fn unmute(&self, name: &str) -> Result<String, String> {
+        match self.mutes.lock().unwrap().unmute(name, unix_ms()) {
+            Ok(Some(mute)) => Ok(format!("unmuted {}", mute.name)),
+            Ok(None) => Err(format!("{} is not muted", name.trim())),
+            Err(e) => Err(format!("unmuted {} until restart, but could not save the mute list: {}", name.trim(), e)),
+        }
+    }
+
+    /// The mutes in force, one line each, in name order.
+    pub fn muted_names(&self) -> Result<Vec<String>, String> {
+        let now = unix_ms();
+        let mutes = self.mutes.lock().unwrap().in_force(now).map_err(|e| format!("could not save the mute list: {}", e))?;
+        Ok(mutes.iter().map(|mute| mute.describe(now)).collect())
+    }
+
+    /// The mute in force on the player on `connection`, if any.
+    pub fn chat_mute(&self, connection: ConnectionId) -> Option<Mute> {
+        let name = self.players.shard(connection).get(&connection)?.name.clone();
+        self.mutes.lock().unwrap().muted(&name, unix_ms()).cloned()
+    }
+
+    pub fn connection_stats(&self, id: u32, now: Instant) -> Option<StatsSnapshot> {
+        let players = self.players.lock_all();
+        let (connection, _) = players.iter().find(|(_, p)| p.id == id)?;
+        self.connections.lock().unwrap().get(connection).map(|c| c.stats.snapshot(now))
+    }
+
+    /// Where the player spawned, and returns to on respawn.
+    pub fn spawn_of(&self, connection: ConnectionId) -> Option<Spawn> {
+        self.spawns.lock().unwrap().get(&connection).cloned()
+    }
+
+    /// Teleports the player (public id `id`) next to `planet_id` if the
+    /// cooldown and the ownership rules allow it, and tells everyone.
+    pub fn teleport_to_planet(
+        &self,
+        connection: ConnectionId,
+        id: u32,
+        planet_id: u32,
+        cooldown: &mut TeleportCooldown,
+        now: Instant,
+    ) -> Result<Position, TeleportError> {
+        let destination = {
+            let world = self.world_snapshot();
+            let planet = cooldown.try_teleport(id, planet_id, world.planet_by_id(planet_id), now)?;
+            let players = self.players.snapshot();
+            let others = players.iter().filter(|p| p.id != id).map(|p| &p.position);
+            landing_position(planet, others)
+        };
+        self.move_player(connection, destination.clone());
+        Ok(destination)
+    }
+
+    /// Puts the player at `position` at rest, taking off if they had landed,
+    /// and broadcasts the move.
+    pub fn move_player(&self, connection: ConnectionId, position: Position) {
+        let mut players = self.players.shard(connection);
+        let Some(player) = players.get_mut(&connection) else { return };
+        if player.landed_on.take().is_some() {
+            self.emit(GameEvent::LandingChanged { player_id: player.id, landed_on: None, position: player.position.clone() });
+        }
+        player.position = position.clone();
+        player.velocity = [0.0; 3];
+        self.emit(GameEvent::PlayerMoved {
+            player_id: player.id,
+            seq: None,
+            position: position.clone(),
+            velocity: player.velocity,
+            rotation: player.rotation,
+        });
+        let found = self.discover(player, &self.world_snapshot().planets);
+        let player_id = player.id;
+        drop(players);
+        if let Some(trails) = &self.trails {
+            trails.lock().unwrap().clear(player_id);
+        }
+
+        self.broadcast_message(ServerMessage::PlayerTeleported { player_id, position }, Urgency::Batched);
+        for planet_id in found {
+            self.broadcast_message(ServerMessage::PlanetDiscovered { player_id, planet_id }, Urgency::Batched);
+        }
+    }
+
+    /// Lands the player on `planet_id` if they are close and slow enough,
+    /// pinning them to its surface (see `landing`); returns where they stand.
+    pub fn land(&self, connection: ConnectionId, planet_id: u32) -> Result<Position, LandError> {
+        let mut players = self.players.shard(connection);
+        let Some(player) = players.get_mut(&connection) else { return Err(LandError::NotLanded) };
+        if let Some(planet_id) = player.landed_on {
+            return Err(LandError::AlreadyLanded { planet_id });
+        }
+        let world = self.world_snapshot();
+        let planet = world.planet_by_id(planet_id).ok_or(LandError::UnknownPlanet { planet_id })?;
+        let position = landing::try_land(planet, &player.position, &player.velocity)?;
+        if let Some(guard) = self.world.modules.defended_by(planet, &world.planets, player.id) {
+            return Err(LandError::Defended { planet_id: guard.id, owner: guard.owner.expect("only owned planets guard") });
+        }
+        player.position = position.clone();
+        player.velocity = [0.0; 3];
+        player.landed_on = Some(planet_id);
+        self.emit(GameEvent::LandingChanged { player_id: player.id, landed_on: Some(planet_id), position: position.clone() });
+        Ok(position)
+    }
+
+    /// Frees a landed player to fly again, from where they stand.
+    pub fn take_off(&self, connection: ConnectionId) -> Result<(), LandError> {
+        let mut players = self.players.shard(connection);
+        let player = players.get_mut(&connection).ok_or(LandError::NotLanded)?;
+        player.landed_on.take().ok_or(LandError::NotLanded)?;
+        self.emit(GameEvent::LandingChanged { player_id: player.id, landed_on: None, position: player.position.clone() });
+        Ok(())
+    }
+
+    /// Whether the player is landed on a planet, and so ignoring position updates.
+    pub fn is_landed(&self, connection: ConnectionId) -> bool {
+        self.players.shard(connection).get(&connection).is_some_and(|p| p.landed_on.is_some())
+    }
+
+    /// Takes up to `amount` units of the planet the player is landed on, or
+    /// out in space of the notable asteroid in reach, into their cargo,
+    /// earning them XP (see `module_effects`), more from a planet under a
+    /// meteor shower (see `ambient`); returns the planet's or asteroid's id,
+    /// the units added to the cargo and the player's cargo since.
+    pub fn mine(&self, connection: ConnectionId, amount: u32) -> Result<(u32, u32, u64), MineError> {
+        if amount == 0 {
+            return Err(MineError::ZeroAmount);
+        }
+        if !self.owns_planets() {
+            return Err(MineError::Elsewhere);
+        }
+        let mut players = self.players.shard(connection);
+        let player = players.get_mut(&connection).ok_or(MineError::NotLanded)?;
+        let mut world = self.state.write().unwrap();
+        let state = Arc::make_mut(&mut world);
+        let Some(planet_id) = player.landed_on else {
+            let asteroid = asteroid_in_reach(&mut state.belts, &player.position).ok_or(MineError::NotLanded)?;
+            if asteroid.resources == 0 {
+                return Err(MineError::Depleted { planet_id: asteroid.id });
+            }
+            let taken = amount.min(asteroid.resources);
+            asteroid.resources -= taken;
+            let (asteroid_id, resources) = (asteroid.id, asteroid.resources);
+            self.emit(GameEvent::AsteroidMined { asteroid_id, resources });
+            drop(world);
+
+            self.earn_xp(player, MINING_XP * taken);
+            let name = player.name.clone();
+            drop(players);
+            return Ok((asteroid_id, taken, self.add_cargo(&name, taken)));
+        };
+        let planet = state.planet_by_id_mut(planet_id).ok_or(MineError::NotLanded)?;
+        if planet.resources == 0 {
+            return Err(MineError::Depleted { planet_id });
+        }
+        let taken = amount.min(planet.resources);
+        planet.resources -= taken;
+        let planet = planet.clone();
+        self.emit(GameEvent::PlanetUpdated { planet: planet.clone() });
+        drop(world);
+
+        self.earn_xp(player, self.world.modules.xp_while_landed(MINING_XP * taken, &planet));
+        let name = player.name.clone();
+        drop(players);
+        self.planets_changed();
+
+        let showering = self.ambient.as_ref().is_some_and(|ambient| ambient.lock().unwrap().is_showering(planet_id));
+        let yielded = if showering { taken * SHOWER_YIELD } else { taken };
+        let cargo = self.add_cargo(&name, yielded);
+        self.broadcast_message(ServerMessage::PlanetUpdated { planet }, Urgency::Batched);
+        Ok((planet_id, yielded, cargo))
+    }
+
+    /// Puts `units` mined into `name`'s cargo, returning their cargo since.
+    fn add_cargo(&self, name: &str, units: u32) -> u64 {
+        let mut wallets = self.wallets.lock().unwrap();
+        let mut wallet = wallets.get(name);
+        wallet.cargo += units as u64;
+        if let Err(e) = wallets.set([(name, wallet)]) {
+            eprintln!("❌ Could not save the wallets: {}", e);
+        }
+        wallet.cargo
+    }
+
+    /// Adds `xp` to what `player` has earned under their name, levelling
+    /// them up if it is enough.
+    fn earn_xp(&self, player: &mut Player, xp: u32) {
+        let mut discoveries = self.discoveries.lock().unwrap();
+        let log = discoveries_of(&mut discoveries, &player.name);
+        log.xp += xp;
+        player.xp = log.xp;
+        let before = std::mem::replace(&mut player.level, level_for(log.xp));
+        drop(discoveries);
+        self.emit(GameEvent::XpEarned { player_id: player.id, xp });
+        if player.level > before {
+            self.emit(GameEvent::LevelUp { player_id: player.id, name: player.name.to_string(), level: player.level });
+        }
+    }
+
+    /// Sells `amount` units of the player's cargo at the rate where they are
+    /// (see `module_effects`); returns the credits earned and their balance
+    /// since.
+    pub fn sell(&self, connection: ConnectionId, amount: u32) -> Result<(u64, u64), SellError> {
+        if amount == 0 {
+            return Err(SellError::ZeroAmount);
+        }
+        let Some((name, position)) = self.players.shard(connection).get(&connection).map(|p| (p.name.clone(), p.position.clone())) else {
+            return Err(SellError::NotEnough { cargo: 0 });
+        };
+        let rate = self.world.modules.sell_rate_at(&position, &self.world_snapshot().planets);
+        let mut wallets = self.wallets.lock().unwrap();
+        let mut wallet = wallets.get(&name);
+        if wallet.cargo < amount as u64 {
+            return Err(SellError::NotEnough { cargo: wallet.cargo });
+        }
+        let earned = credits_for(amount, rate);
+        wallet.cargo -= amount as u64;
+        wallet.credits += earned;
+        if let Err(e) = wallets.set([(&*name, wallet)]) {
+            eprintln!("❌ Could not save the wallets: {}", e);
+        }
+        Ok((earned, wallet.credits))
+    }
+
+    /// Reply to `TradeOffer`: records the offer and sends it to the other
+    /// player (see `trade`).
+    pub fn offer_trade(&self, connection: ConnectionId, to_player: u32, give_resources: u32, want_credits: u64) -> Result<ServerMessage, TradeError> {
+        let players = self.players.lock_all();
+        let unknown = TradeError::UnknownPlayer { player_id: to_player };
+        let from_player = players.get(&connection).ok_or(unknown.clone())?.id;
+        let to = players.iter().find(|(_, p)| p.id == to_player && !p.remote).map(|(to, _)| *to).ok_or(unknown)?;
+        drop(players);
+        let mut trades = self.trades.lock().unwrap();
+        let offer = trades.offer((connection, from_player), (to, to_player), give_resources, want_credits, Instant::now())?;
+        let expires_in_ms = trades.timeout().as_millis() as u64;
+        drop(trades);
+        self.send_to(vec![to], ServerMessage::TradeIncoming { offer_id: offer.id, from_player, give_resources, want_credits, expires_in_ms });
+        Ok(ServerMessage::TradeOffered { offer_id: offer.id, to_player, expires_in_ms })
+    }
+
+    /// Reply to `TradeAccept`: settles the offer if both players can still
+    /// afford it, telling the offerer too, with their own wallet.
+    pub fn accept_trade(&self, connection: ConnectionId, offer_id: u32) -> Result<ServerMessage, TradeError> {
+        let offer = self.trades.lock().unwrap().take(offer_id, connection, Instant::now())?;
+        let players = self.players.lock_all();
+        let names = players.get(&offer.from).zip(players.get(&offer.to)).map(|(from, to)| (from.name.clone(), to.name.clone()));
+        drop(players);
+        let Some((seller, buyer)) = names else {
+            return Ok(self.end_trade(&offer, TradeEnd::Cancelled));
+        };
+        let mut wallets = self.wallets.lock().unwrap();
+        let mut seller_wallet = wallets.get(&seller);
+        let mut buyer_wallet = wallets.get(&buyer);
+        if let Err(reason) = settle(&offer, &mut seller_wallet, &mut buyer_wallet) {
+            drop(wallets);
+            return Ok(self.end_trade(&offer, reason));
+        }
+        if let Err(e) = wallets.set([(&*seller, seller_wallet), (&*buyer, buyer_wallet)]) {
+            eprintln!("❌ Could not save the wallets: {}", e);
+        }
+        drop(wallets);
+        self.emit(GameEvent::Traded { seller: offer.from_player, buyer: offer.to_player, resources: offer.give_resources, credits: offer.want_credits });
+        let Wallet { cargo, credits } = seller_wallet;
+        self.send_to(vec![offer.from], ServerMessage::TradeCompleted { offer_id, cargo, credits });
+        let Wallet { cargo, credits } = buyer_wallet;
+        Ok(ServerMessage::TradeCompleted { offer_id, cargo, credits })
+    }
+
+    /// Reply to `TradeDecline`, which the offerer is told about too.
+    pub fn decline_trade(&self, connection: ConnectionId, offer_id: u32) -> Result<ServerMessage, TradeError> {
+        let offer = self.trades.lock().unwrap().take(offer_id, connection, Instant::now())?;
+        Ok(self.end_trade(&offer, TradeEnd::Declined))
+    }
+
+    /// Ends the offers whose time is up by `now`, telling both players.
+    pub fn expire_trades(&self, now: Instant) {
+        let expired = self.trades.lock().unwrap().expire(now);
+        for offer in expired {
+            self.send_to(vec![offer.from, offer.to], ServerMessage::TradeEnded { offer_id: offer.id, reason: TradeEnd::Expired });
+        }
+    }
+
+    /// Tells the offerer `offer` ended with nothing traded, returning the
+    /// same `TradeEnded` for the other player.
+    fn end_trade(&self, offer: &Offer, reason: TradeEnd) -> ServerMessage {
+        let ended = ServerMessage::TradeEnded { offer_id: offer.id, reason };
+        self.send_to(vec![offer.from], ended.clone());
+        ended
+    }
+
+    /// How many trade offers are waiting for an answer (see `trade`).
+    pub fn open_trades(&self) -> usize {
+        self.trades.lock().unwrap().len()
+    }
+
+    /// What a player of that name holds; empty for names that never mined.
+    pub fn wallet(&self, name: &str) -> Wallet {
+        self.wallets.lock().unwrap().get(name)
+    }
+
+    /// Renames the player to `name`, which must be a valid player name, and
+    /// tells everyone, returning the old name; None if it is their name
+    /// already. Like joining, the name may not be banned or taken by another
+    /// player, however written, nor hold the discoveries or wallet of a
+    /// player who is away. Their discoveries, XP, cargo and credits go with
+    /// them, and so does a mute.
+    pub fn rename_player(&self, connection: ConnectionId, name: &str) -> Result<Option<String>, RenameError> {
+        if self.bans.lock().unwrap().is_banned(name) {
+            return Err(RenameError::Banned { name: name.to_string() });
+        }
+        let mut players = self.players.lock_all();
+        let Some(player) = players.get(&connection) else { return Ok(None) };
+        if *player.name == *name {
+            return Ok(None);
+        }
+        let (old_key, new_key) = (normalize_name(&player.name), normalize_name(name));
+        if players.iter().any(|(id, p)| *id != connection && normalize_name(&p.name) == new_key) {
+            return Err(RenameError::NameTaken { name: name.to_string() });
+        }
+        // A name that is away keeps its wallet and discoveries; no one else
+        // may take them over
+        if old_key != new_key {
+            let has_wallet = self.wallets.lock().unwrap().contains(name);
+            let has_discoveries = self.discoveries.lock().unwrap().contains_key(new_key.as_str());
+            if has_wallet || has_discoveries {
+                return Err(RenameError::NameTaken { name: name.to_string() });
+            }
+        }
+        let player = players.get_mut(&connection).expect("checked above");
+        let old = std::mem::replace(&mut player.name, name.into()).to_string();
+        let player_id = player.id;
+        self.emit(GameEvent::PlayerRenamed { player_id, name: name.to_string() });
+        if old_key != new_key {
+            let mut discoveries = self.discoveries.lock().unwrap();
+            if let Some(log) = discoveries.remove(old_key.as_str()) {
+                discoveries.insert(new_key.clone().into(), log);
+            }
+        }
+        drop(players);
+        if let Err(e) = self.wallets.lock().unwrap().rename(&old, name) {
+            eprintln!("❌ Could not save the wallets: {}", e);
+        }
+        if let Err(e) = self.mutes.lock().unwrap().rename(&old, name, unix_ms()) {
+            eprintln!("❌ Could not save the mutes: {}", e);
+        }
+
+        println!("🏷️  Player {} is now {}", old, name);
+        self.broadcast_message(ServerMessage::PlayerRenamed { player_id, old: old.clone(), new: name.to_string() }, Urgency::Batched);
+        self.friend_offline(connection, &old);
+        self.friend_online(connection, name);
+        Ok(Some(old))
+    }
+
+    /// Validates a new look for the player and tells everyone about it.
+    pub fn set_player_appearance(&self, connection: ConnectionId, appearance: PlayerAppearance) -> Result<(), String> {
+        appearance.validate()?;
+        let mut players = self.players.shard(connection);
+        let Some(player) = players.get_mut(&connection) else { return Ok(()) };
+        player.appearance = appearance.clone();
+        self.emit(GameEvent::AppearanceChanged { player_id: player.id, appearance: appearance.clone() });
+        let player_id = player.id;
+        drop(players);
+
+        self.broadcast_message(ServerMessage::PlayerAppearanceChanged { player_id, appearance }, Urgency::Batched);
+        Ok(())
+    }
+
+    /// Locks only the player's own shard, so updates from players in other
+    /// shards go ahead at the same time. Returns where the player was before,
+    /// or None if the update was not applied.
+    pub fn update_player_position(&self, connection: ConnectionId, update: PositionUpdate) -> Option<Position> {
+        let mut players = self.players.shard(connection);
+        // Landed players stay pinned to the surface until they take off
+        let player = players.get_mut(&connection).filter(|p| p.landed_on.is_none())?;
+        let position = update.position;
+        let from = std::mem::replace(&mut player.position, position.clone());
+        player.velocity = update.velocity;
+        player.rotation = update.rotation;
+        if let Some(seq) = update.seq {
+            player.last_processed_seq = seq;
+        }
+        self.emit(GameEvent::PlayerMoved {
+            player_id: player.id,
+            seq: update.seq,
+            position,
+            velocity: update.velocity,
+            rotation: update.rotation,
+        });
+
+        let found = self.discover(player, &self.world_snapshot().planets);
+        for planet_id in found {
+            self.broadcast_message(ServerMessage::PlanetDiscovered { player_id: player.id, planet_id }, Urgency::Batched);
+        }
+        Some(from)
+    }
+
+    /// Records the planets `player` has discovered at their current position
+    /// and the XP they earned, returning the new ones. Away from every
+    /// planet, which is most of the time, it leaves the discoveries unlocked.
+    fn discover(&self, player: &mut Player, planets: &[Planet]) -> Vec<u32> {
+        if !planets.iter().any(|planet| in_discovery_range(planet, &player.position)) {
+            return Vec::new();
+        }
+        let mut discoveries = self.discoveries.lock().unwrap();
+        let log = discoveries_of(&mut discoveries, &player.name);
+        let found = log.visit(&player.position, planets);
+        for &planet_id in &found {
+            println!("🔭 Player {} discovered planet {}", player.name, planet_id);
+            self.emit(GameEvent::PlanetDiscovered { player_id: player.id, planet_id });
+        }
+        player.xp = log.xp;
+        let before = std::mem::replace(&mut player.level, level_for(log.xp));
+        drop(discoveries);
+        if player.level > before {
+            self.emit(GameEvent::LevelUp { player_id: player.id, name: player.name.to_string(), level: player.level });
+        }
+        found
+    }
+
+    /// The reply to a player's `Discoveries` request.
+    pub fn discovery_list(&self, connection: ConnectionId) -> Option<ServerMessage> {
+        let name = self.players.shard(connection).get(&connection)?.name.clone();
+        let state = self.world_snapshot();
+        let discoveries = self.discoveries.lock().unwrap();
+        let log = discoveries.get(normalize_name(&name).as_str()).cloned().unwrap_or_default();
+        Some(ServerMessage::DiscoveryList {
+            planet_ids: log.planet_ids(),
+            explored_percent: log.explored_percent(&state.planets),
+        })
+    }
+
+    /// The message of the day for a player called `name`, if there is one.
+    fn motd_for(&self, name: &str) -> Option<ServerMessage> {
+        let player_count = self.players.len();
+        let text = render(self.motd.as_deref()?, &Placeholders { player_count, name: Some(name) });
+        match text {
+            Ok(text) => Some(ServerMessage::Announcement { text }),
+            Err(e) => {
+                println!("⚠️  Bad message of the day: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Fills in `template` and sends it to everyone as an `Announcement`.
+    pub fn announce(&self, template: &str) -> Result<String, String> {
+        let player_count = self.players.len();
+        let text = render(template, &Placeholders { player_count, name: None })?;
+        println!("📢 {}", text);
+        self.broadcast_message(ServerMessage::Announcement { text: text.clone() }, Urgency::Immediate);
+        Ok(text)
+    }
+
+    /// Repeats each scheduled announcement at its interval, the first time
+    /// one interval from now.
+    pub fn spawn_scheduled_announcements(&self) -> Vec<tokio::task::JoinHandle<()>> {
+        self.scheduled_announcements.iter()
+            .cloned()
+            .map(|(every, template)| {
+                let server = self.clone();
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+                    loop {
+                        interval.tick().await;
+                        if let Err(e) = server.announce(&template) {
+                            println!("⚠️  Scheduled announcement skipped: {}", e);
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Periodically pushes the full game state to every connected client,
+    /// following the broadcast interval across config reloads. Parks while
+    /// nobody is connected (see `hibernate`).
+    pub fn spawn_broadcast_loop(&self) -> tokio::task::JoinHandle<()> {
+        let server = self.clone();
+        let mut live = self.live.subscribe();
+        tokio::spawn(async move {
+            let mut period = live.borrow_and_update().broadcast_interval;
+            let mut interval = tokio::time::interval(period);
+            let mut hibernation = HibernationTracker::new(server.hibernation);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        // Only `step` ticks a paused server
+                        if server.is_paused() {
+                            continue;
+                        }
+                        let connected = !server.connections.lock().unwrap().is_empty();
+                        if hibernation.should_sleep(connected, tokio::time::Instant::now().into_std()) {
+                            server.hibernate(period).await;
+                            hibernation.reset();
+                            interval.reset();
+                        }
+                        server.broadcast_game_state().await;
+                    }
+                    Ok(()) = live.changed() => {
+                        let new_period = live.borrow_and_update().broadcast_interval;
+                        if new_period != period {
+                            period = new_period;
+                            interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Saves the lifetime stats and the chat history every `SAVE_INTERVAL`.
+    fn spawn_saver(&self) -> tokio::task::JoinHandle<()> {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + SAVE_INTERVAL, SAVE_INTERVAL);
+            loop {
+                interval.tick().await;
+                server.save_lifetime_stats();
+                server.save_chat_history();
+            }
+        })
+    }
+
+    /// Runs the economy tick every `ECONOMY_INTERVAL`, except while the
+    /// server hibernates or is paused, or when the planets belong to a
+    /// cluster's primary.
+    pub fn spawn_economy_loop(&self) -> tokio::task::JoinHandle<()> {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ECONOMY_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                // The first tick after waking makes up for the whole sleep
+                if server.hibernating_since().is_none() && !server.is_paused() && server.owns_planets() {
+                    server.run_economy(Instant::now());
+                    server.prune_planets(Instant::now());
+                }
+            }
+        })
+    }
+
+    /// Grows planets' resources back for the time since the last economy
+    /// tick (see `economy`) and tells everyone about the planets that gained
+    /// any, returning how many did.
+    pub fn run_economy(&self, now: Instant) -> usize {
+        let mut economy = self.economy.lock().unwrap();
+        let mut world = self.state.write().unwrap();
+        let grown = economy.tick(&world.planets, &self.world.regeneration, now);
+        drop(economy);
+        if grown.is_empty() {
+            return 0;
+        }
+        let state = Arc::make_mut(&mut world);
+        let mut updated = Vec::with_capacity(grown.len());
+        for (planet_id, resources) in grown {
+            if let Some(planet) = state.planet_by_id_mut(planet_id) {
+                planet.resources = resources;
+                self.emit(GameEvent::PlanetUpdated { planet: planet.clone() });
+                updated.push(planet.clone());
+            }
+        }
+        drop(world);
+        self.planets_changed();
+
+        let count = updated.len();
+        for planet in updated {
+            self.broadcast_message(ServerMessage::PlanetUpdated { planet }, Urgency::Batched);
+        }
+        count
+    }
+
+    /// Removes the admin-added planets that have gone unvisited and unowned
+    /// for longer than the pruning policy allows (see `prune`), returning
+    /// their ids. Does nothing without a policy.
+    pub fn prune_planets(&self, now: Instant) -> Vec<u32> {
+        let Some(policy) = self.pruning else {
+            return Vec::new();
+        };
+        let owned: Vec<u32> = self.world_snapshot().planets.iter().filter(|p| p.owner.is_some()).map(|p| p.id).collect();
+        let mut activity = self.planet_activity.lock().unwrap();
+        for planet_id in owned {
+            activity.active(planet_id, now);
+        }
+        let idle = activity.idle(policy.after, now);
+        drop(activity);
+
+        let mut pruned = Vec::with_capacity(idle.len());
+        for (planet_id, inactive) in idle {
+            // An admin may have removed it since
+            if self.remove_planet(planet_id).is_ok() {
+                println!("🧹 Pruned planet {}: no visits or owner for {:.0}s", planet_id, inactive.as_secs_f64());
+                pruned.push(planet_id);
+            }
+        }
+        pruned
+    }
+
+    /// Resources in the world and in players' wallets, and how fast the
+    /// planets' grow back.
+    pub fn economy_stats(&self) -> EconomyStats {
+        let held = self.wallets.lock().unwrap().total().cargo;
+        EconomyStats::of(&self.world_snapshot(), &self.world.regeneration, held)
+    }
+
+    /// Sleeps until a connection joins, then fast-forwards the tick counter
+    /// as configured.
+    async fn hibernate(&self, period: Duration) {
+        let started = tokio::time::Instant::now();
+        *self.asleep_since.lock().unwrap() = Some(Instant::now());
+        println!("💤 Nobody connected for {:?}, hibernating", self.hibernation.after);
+        // Nobody is left to tell, and whoever joins next gets a fresh snapshot
+        self.events.lock().unwrap().clear();
+        // A join between the check and the wait leaves a permit behind
+        while self.connections.lock().unwrap().is_empty() {
+            self.wake.notified().await;
+        }
+        let slept = started.elapsed();
+        let skipped = self.hibernation.catch_up(slept, period);
+        self.tick.fetch_add(skipped, Ordering::SeqCst);
+        self.clock.lock().unwrap().advance(period.saturating_mul(skipped as u32));
+        *self.asleep_since.lock().unwrap() = None;
+        println!("☀️  Waking up after {:.1}s, tick counter moved on by {}", slept.as_secs_f64(), skipped);
+    }
+
+    /// When the broadcast loop went to sleep, if it is asleep.
+    pub fn hibernating_since(&self) -> Option<Instant> {
+        *self.asleep_since.lock().unwrap()
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.tick.load(Ordering::SeqCst)
+    }
+
+    /// Milliseconds since the server started.
+    pub fn server_time_ms(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+
+    /// The current state wrapped in a broadcast envelope stamped with `tick`.
+    /// Periodic broadcasts leave idle players out; join snapshots include them
+    /// (see `encode_snapshot`). In a cluster the other instances' players are
+    /// added, and this instance's are passed on to them.
+    fn state_message(&self, tick: u64) -> ServerMessage {
+        let mut state = self.get_state();
+        state.players.retain(|p| !p.idle);
+        #[cfg(feature = "cluster")]
+        if let Some(cluster) = &self.cluster {
+            cluster.publish_players(state.players.clone());
+        }
+        state.players.extend(self.remote_players());
+        ServerMessage::State {
+            tick,
+            server_time_ms: self.server_time_ms(),
+            state,
+        }
+    }
+
+    /// Publishes the next periodic snapshot, encoding it on a blocking
+    /// thread first when it is large (see `broadcast`).
+    pub async fn broadcast_game_state(&self) {
+        let started = Instant::now();
+        let message = self.advance_tick();
+        self.publish_tick(BroadcastFrame::prepare(message).await, started);
+    }
+
+    /// Moves the world on by a tick, returning the snapshot to publish.
+    fn advance_tick(&self) -> ServerMessage {
+        let tick = self.tick.fetch_add(1, Ordering::SeqCst) + 1;
+        let tick_length = self.live.borrow().broadcast_interval;
+        self.clock.lock().unwrap().advance(tick_length);
+        self.move_fake_players(tick);
+        self.run_ambient(tick);
+        self.expire_trades(Instant::now());
+        self.flush_events();
+        let message = self.state_message(tick);
+        if let ServerMessage::State { state, .. } = &message {
+            self.history.lock().unwrap().record(tick, state, Instant::now());
+        }
+        message
+    }
+
+    /// Starts and ends the ambient events due at `tick`.
+    fn run_ambient(&self, tick: u64) {
+        let Some(ambient) = &self.ambient else { return };
+        let changes = ambient.lock().unwrap().advance(tick, &self.world_snapshot().planets);
+        for change in changes {
+            if let ServerMessage::WorldEvent { event, active } = &change {
+                println!("🌠 {:?} around planet {} {}", event.kind, event.planet_id, if *active { "began" } else { "ended" });
+            }
+            self.broadcast_message(change, Urgency::Batched);
+        }
+    }
+
+    /// The ambient events under way, oldest first.
+    pub fn ambient_events(&self) -> Vec<AmbientEvent> {
+        self.ambient.as_ref().map_or_else(Vec::new, |ambient| ambient.lock().unwrap().active().to_vec())
+    }
+
+    /// The top speed a position update to `position` may claim: the
+    /// configured one, less near a solar flare.
+    pub fn max_speed_at(&self, position: &Position) -> f32 {
+        let Some(ambient) = &self.ambient else { return self.max_speed };
+        let planets = self.world_snapshot();
+        ambient.lock().unwrap().max_speed_at(self.max_speed, position, &planets.planets)
+    }
+
+    fn publish_tick(&self, frame: BroadcastFrame, started: Instant) {
+        self.broadcast_stats.lock().unwrap().record(started.elapsed(), frame.is_large());
+        let _ = self.broadcast_tx.send(Arc::new(frame));
+        self.update_population();
+        self.record_trails();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.lock().unwrap().is_paused()
+    }
+
+    /// When the simulation was paused, if it is (see `pause`).
+    pub fn paused_since(&self) -> Option<Instant> {
+        self.pause.lock().unwrap().since()
+    }
+
+    /// Position updates held until the simulation moves again.
+    pub fn held_updates(&self) -> usize {
+        self.pause.lock().unwrap().queued()
+    }
+
+    /// Stops the broadcast loop and the economy, and tells everyone.
+    pub fn pause(&self) -> Result<String, String> {
+        if !self.pause.lock().unwrap().pause(Instant::now()) {
+            return Err("already paused".to_string());
+        }
+        let tick = self.current_tick();
+        println!("⏸️  Simulation paused at tick {}", tick);
+        self.broadcast_message(ServerMessage::PauseChanged { paused: true, tick }, Urgency::Immediate);
+        Ok(format!("paused at tick {}", tick))
+    }
+
+    /// Applies the held position updates and lets the broadcast loop and
+    /// the economy carry on.
+    pub fn resume(&self) -> Result<String, String> {
+        let held = self.pause.lock().unwrap().resume().ok_or("not paused")?;
+        let applied = self.apply_held_updates(held);
+        let tick = self.current_tick();
+        println!("▶️  Simulation resumed at tick {}", tick);
+        self.broadcast_message(ServerMessage::PauseChanged { paused: false, tick }, Urgency::Immediate);
+        Ok(format!("resumed at tick {}, {} held update(s) applied", tick, applied))
+    }
+
+    /// Advances a paused simulation by exactly `ticks` ticks, broadcasting
+    /// each, after applying the held position updates.
+    pub fn step(&self, ticks: u64) -> Result<String, String> {
+        if !(1..=MAX_STEPS).contains(&ticks) {
+            return Err(format!("steps must be between 1 and {}", MAX_STEPS));
+        }
+        let held = {
+            let mut pause = self.pause.lock().unwrap();
+            if !pause.is_paused() {
+                return Err("not paused (try `pause` first)".to_string());
+            }
+            pause.take_queued()
+        };
+        let applied = self.apply_held_updates(held);
+        for _ in 0..ticks {
+            // Encoded by the first connection to send it, not offloaded
+            let started = Instant::now();
+            let message = self.advance_tick();
+            self.publish_tick(BroadcastFrame::new(message), started);
+        }
+        let tick = self.current_tick();
+        println!("⏯️  Stepped {} tick(s) to tick {}", ticks, tick);
+        Ok(format!("stepped {} tick(s) to tick {}, {} held update(s) applied", ticks, tick, applied))
+    }
+
+    /// Holds a player's position update while the simulation is paused
+    /// (see `pause`), or hands it back to be applied.
+    pub fn hold_position(&self, connection: ConnectionId, update: PositionUpdate) -> Hold {
+        self.pause.lock().unwrap().hold(connection, update)
+    }
+
+    /// The reply to an action refused because the simulation is paused.
+    fn paused_reply(&self) -> ServerMessage {
+        ServerMessage::ServerPaused { tick: self.current_tick() }
+    }
+
+    fn apply_held_updates(&self, held: Vec<(ConnectionId, PositionUpdate)>) -> usize {
+        let count = held.len();
+        for (connection, update) in held {
+            self.update_player_position(connection, update);
+        }
+        count
+    }
+
+    /// Notes where everyone is for their trails, and sends spectators the
+    /// thinned trails if there are any spectators.
+    fn record_trails(&self) {
+        let Some(trails) = &self.trails else { return };
+        let watched = !self.spectators.lock().unwrap().is_empty();
+        // Positions read under the trails lock, so a teleport clearing a
+        // trail cannot be followed by where the player was before it
+        let mut trails = trails.lock().unwrap();
+        trails.record(&self.players.snapshot(), self.server_time_ms());
+        if !watched {
+            return;
+        }
+        let message = ServerMessage::Trails { trails: trails.sampled(SPECTATOR_SAMPLE_EVERY) };
+        drop(trails);
+        let _ = self.broadcast_tx.send(Arc::new(BroadcastFrame::for_spectators(message)));
+    }
+
+    /// The reply to a `QueryTrail` request.
+    pub fn query_trail(&self, player_id: u32) -> Result<ServerMessage, String> {
+        let trails = self.trails.as_ref().ok_or("trails are off on this server")?;
+        if !self.players.snapshot().iter().any(|p| p.id == player_id) {
+            return Err(format!("no player with id {}", player_id));
+        }
+        let points = trails.lock().unwrap().trail(player_id);
+        Ok(ServerMessage::Trail { trail: PlayerTrail { player_id, points } })
+    }
+
+    /// What the trails are holding; None unless trails are on.
+    pub fn trail_stats(&self) -> Option<TrailStats> {
+        self.trails.as_ref().map(|trails| trails.lock().unwrap().stats())
+    }
+
+    /// Recounts the players near each planet, telling everyone when the
+    /// counts changed since the last broadcast.
+    fn update_population(&self) {
+        let counts = population::count(&self.world_snapshot().planets, &self.players.snapshot());
+        let mut activity = self.planet_activity.lock().unwrap();
+        let now = Instant::now();
+        for count in counts.iter().filter(|c| c.population > 0) {
+            activity.active(count.planet_id, now);
+        }
+        drop(activity);
+        let mut population = self.population.lock().unwrap();
+        if *population == counts {
+            return;
+        }
+        *population = counts.clone();
+        drop(population);
+        self.broadcast_message(ServerMessage::PlanetPopulation { counts }, Urgency::Batched);
+    }
+
+    /// The players near each planet as of the last broadcast.
+    pub fn planet_population(&self) -> Vec<PlanetCount> {
+        self.population.lock().unwrap().clone()
+    }
+
+    pub fn broadcast_stats(&self) -> BroadcastStats {
+        *self.broadcast_stats.lock().unwrap()
+    }
+
+    /// Catches a client up from the broadcast at `tick`: the diffs since then
+    /// if they are still in the history and the client takes them (see
+    /// `Capabilities::DELTAS`), otherwise a full snapshot. Moves are
+    /// quantized for clients with `Capabilities::QUANTIZED`, from where the
+    /// broadcast at `tick` had each player.
+    pub fn resync_message(&self, tick: u64, capabilities: Capabilities) -> ServerMessage {
+        if !capabilities.contains(Capabilities::DELTAS) {
+            return self.state_message(self.current_tick());
+        }
+        let history = self.history.lock().unwrap();
+        let found = match capabilities.contains(Capabilities::QUANTIZED) {
+            true => history.since(tick).map(|(base, diffs)| (Some(base), diffs)),
+            false => history.diffs_since(tick).map(|diffs| (None, diffs)),
+        };
+        let latest = history.latest_tick();
+        drop(history);
+        match (found, latest) {
+            (Some((base, mut diffs)), Some(latest)) => {
+                if let Some(base) = base {
+                    quantize_diffs(&mut diffs, &base, self.quantization);
+                }
+                ServerMessage::Resync { from_tick: tick, tick: latest, diffs }
+            }
+            _ => self.state_message(self.current_tick()),
+        }
+    }
+
+    /// The reply to a `QueryPlanets` request.
+    pub fn query_planets(&self, center: &Position, radius: f32, max_results: u32) -> Result<ServerMessage, String> {
+        let state = self.world_snapshot();
+        let planets = planets_near(&state.planets, center, radius, max_results)?;
+        Ok(ServerMessage::PlanetList { planets })
+    }
+
+    /// The reply to a `QueryPlanetsFiltered` request.
+    pub fn query_planets_filtered(
+        &self,
+        center: &Position,
+        filter: &PlanetFilter,
+        sort: PlanetSort,
+        limit: u32,
+        cursor: Option<PlanetCursor>,
+    ) -> Result<ServerMessage, String> {
+        let state = self.world_snapshot();
+        let (results, next_cursor) = find_planets(&state.planets, center, filter, sort, limit, cursor)?;
+        Ok(ServerMessage::PlanetPage { results, next_cursor })
+    }
+
+    /// The join snapshot, idle players included, encoded from a borrow
+    /// rather than wrapped in a `ServerMessage` first, and its tick.
+    fn encode_snapshot(&self, format: WireFormat) -> Result<(u64, Message), EncodeError> {
+        let (tick, server_time_ms) = (self.current_tick(), self.server_time_ms());
+        let mut state = self.get_state();
+        state.players.extend(self.remote_players());
+        Ok((tick, encode_state(format, tick, server_time_ms, &state)?))
+    }
+
+    pub fn history_stats(&self) -> HistoryStats {
+        self.history.lock().unwrap().stats()
+    }
+
+    /// Sends `message` to everyone now, or with the other events at the end
+    /// of the tick (see `broadcast`).
+    fn broadcast_message(&self, message: ServerMessage, urgency: Urgency) {
+        match urgency {
+            Urgency::Batched => self.events.lock().unwrap().push(message),
+            Urgency::Immediate => {
+                self.flush_events();
+                // Send to broadcast channel (ignore if no receivers)
+                let _ = self.broadcast_tx.send(Arc::new(BroadcastFrame::new(message)));
+            }
+        }
+    }
+
+    /// Publishes the events queued since the last flush.
+    fn flush_events(&self) {
+        let events = std::mem::take(&mut *self.events.lock().unwrap());
+        for message in batch(events, MAX_BATCH_BYTES) {
+            let _ = self.broadcast_tx.send(Arc::new(BroadcastFrame::new(message)));
+        }
+    }
+
+    /// Marks a player idle or active and tells everyone about the change.
+    pub fn set_player_idle(&self, connection: ConnectionId, idle: bool) {
+        let mut players = self.players.shard(connection);
+        let Some(player) = players.get_mut(&connection) else { return };
+        player.idle = idle;
+        self.emit(GameEvent::IdleChanged { player_id: player.id, idle });
+
+        let id = player.id;
+        println!("{} Player {} is now {}", if idle { "💤" } else { "▶️ " }, player.name, if idle { "idle" } else { "active" });
+        drop(players);
+
+        if idle {
+            self.broadcast_message(ServerMessage::PlayerIdle { player_id: id }, Urgency::Batched);
+        } else {
+            self.broadcast_message(ServerMessage::PlayerActive { player_id: id }, Urgency::Batched);
+        }
+    }
+
+    /// Adds a player for a new connection from `addr`, unless the server or
+    /// the address is already at its limit.
+    pub fn add_player(&self, joining: Connection, name: String) -> Result<(ConnectionId, Player, Spawn), JoinRejection> {
+        let addr = joining.addr;
+        if self.is_standby() {
+            return Err(JoinRejection::Standby);
+        }
+        if self.bans.lock().unwrap().is_banned(&name) {
+            return Err(JoinRejection::Banned);
+        }
+        let mut players = self.players.lock_all();
+        // Two players may not share a name, however written; the second is
+        // named after its port
+        let taken = normalize_name(&name);
+        let name = if players.values().any(|p| normalize_name(&p.name) == taken) { format!("Player_{}", addr.port()) } else { name };
+        let real_players = players.len() - self.fakes.lock().unwrap().len();
+        let mut connections = self.connections.lock().unwrap();
+        let live = *self.live.borrow();
+        if live.max_players.is_some_and(|max| real_players >= max) {
+            return Err(JoinRejection::ServerFull);
+        }
+        if let Some(limit) = live.max_connections_per_ip
+            && connections.values().filter(|c| c.addr.ip() == addr.ip()).count() >= limit
+        {
+            return Err(JoinRejection::TooManyFromAddress { ip: addr.ip(), limit });
+        }
+        let connection = ConnectionId(self.next_connection_id.fetch_add(1, Ordering::SeqCst));
+        connections.insert(connection, joining);
+        drop(connections);
+
+        let population = self.planet_population();
+        let mut spawns = self.spawns.lock().unwrap();
+        let mut spawn = {
+            let state = self.world_snapshot();
+            let spawn = choose_spawn_balanced(&state.planets, spawns.values(), &population, &state.initial_player_location);
+            self.disperse(spawn, &state, &name, connection.0, players.values().map(|p| &p.position))
+        };
+        spawn.position = self.world.clamp(&spawn.position);
+        spawns.insert(connection, spawn.clone());
+        drop(spawns);
+
+        // Players who were here before under this name keep their XP
+        let xp = self.discoveries.lock().unwrap().get(normalize_name(&name).as_str()).map_or(0, |d| d.xp);
+        let player = Player {
+            id: self.next_player_id.fetch_add(1, Ordering::SeqCst),
+            name: name.as_str().into(),
+            level: level_for(xp),
+            position: spawn.position.clone(),
+            velocity: [0.0; 3],
+            rotation: IDENTITY_ROTATION,
+            last_processed_seq: 0,
+            idle: false,
+            appearance: PlayerAppearance::for_name(&name),
+            xp,
+            landed_on: None,
+            remote: false,
+        };
+        players.insert(connection, player.clone());
+        let online = players.len() - self.fakes.lock().unwrap().len();
+        drop(players);
+        self.lifetime.lock().unwrap().joined(&normalize_name(&name), online, unix_ms());
+        self.emit(GameEvent::PlayerJoined { player: player.clone() });
+        self.wake.notify_one();
+        self.friend_online(connection, &player.name);
+
+        Ok((connection, player, spawn))
+    }
+
+    /// Admits a spectator, unless the spectator slots or the address are
+    /// already at their limit. Spectators have no player and no spawn.
+    pub fn add_spectator(&self, joining: Connection) -> Result<ConnectionId, JoinRejection> {
+        let addr = joining.addr;
+        if self.is_standby() {
+            return Err(JoinRejection::Standby);
+        }
+        let mut connections = self.connections.lock().unwrap();
+        let mut spectators = self.spectators.lock().unwrap();
+        let live = *self.live.borrow();
+        if live.max_spectators.is_some_and(|max| spectators.len() >= max) {
+            return Err(JoinRejection::SpectatorsFull);
+        }
+        if let Some(limit) = live.max_connections_per_ip
+            && connections.values().filter(|c| c.addr.ip() == addr.ip()).count() >= limit
+        {
+            return Err(JoinRejection::TooManyFromAddress { ip: addr.ip(), limit });
+        }
+        let connection = ConnectionId(self.next_connection_id.fetch_add(1, Ordering::SeqCst));
+        connections.insert(connection, joining);
+        spectators.insert(connection);
+        self.wake.notify_one();
+        Ok(connection)
+    }
+
+    pub fn remove_spectator(&self, connection: ConnectionId) {
+        let mut connections = self.connections.lock().unwrap();
+        if self.spectators.lock().unwrap().remove(&connection)
+            && let Some(spectator) = connections.remove(&connection)
+        {
+            println!("👁️  Spectator {} disconnected", spectator.addr);
+        }
+    }
+
+    /// Chat does not change the state, but is journaled for context, kept
+    /// in the chat history (see `chat_history`) under the room the sender
+    /// connected to and sent to everyone with the tick's events. Returns
+    /// the tick it went out with.
+    pub fn record_chat(&self, connection: ConnectionId, room: Option<&str>, text: &str) -> u64 {
+        let players = self.players.shard(connection);
+        let sender = players.get(&connection).map(|player| {
+            self.emit(GameEvent::Chatted { player_id: player.id, name: player.name.to_string(), room: room.map(str::to_string), text: text.to_string() });
+            player.name.clone()
+        });
+        drop(players);
+        let tick = self.current_tick();
+        if let Some(sender) = sender {
+            let line = self.chat_log.lock().unwrap().record(&sender, room, text, unix_ms());
+            self.broadcast_message(ServerMessage::Chatted { tick, line }, Urgency::Batched);
+        }
+        self.lifetime.lock().unwrap().chatted();
+        tick
+    }
+
+    /// A shadow-muted player's chat, sent back to them alone as though it
+    /// had gone to everyone, and otherwise forgotten (see `mutes`). Returns
+    /// the tick it went out with.
+    pub fn shadow_chat(&self, connection: ConnectionId, room: Option<&str>, text: &str) -> u64 {
+        let sender = self.players.shard(connection).get(&connection).map(|player| player.name.to_string());
+        let tick = self.current_tick();
+        if let Some(sender) = sender {
+            let line = ChatLine { sender, timestamp_ms: unix_ms(), room: room.map(str::to_string), text: text.to_string() };
+            self.send_to(vec![connection], ServerMessage::Chatted { tick, line });
+        }
+        tick
+    }
+
+    /// The last `JOIN_HISTORY` chat lines for a joining player, if there
+    /// are any.
+    pub fn join_chat_history(&self) -> Option<ServerMessage> {
+        let messages = self.chat_log.lock().unwrap().latest(JOIN_HISTORY);
+        (!messages.is_empty()).then_some(ServerMessage::ChatHistory { messages })
+    }
+
+    /// Reply to `MoreHistory`: up to `limit` chat lines from before
+    /// `before_ms`.
+    pub fn more_chat_history(&self, before_ms: u64, limit: u32) -> ServerMessage {
+        ServerMessage::ChatHistory { messages: self.chat_log.lock().unwrap().before(before_ms, limit as usize) }
+    }
+
+    /// Tells the players who friended `name`, just online on `connection`,
+    /// and indexes its friends (see `friends`).
+    fn friend_online(&self, connection: ConnectionId, name: &str) {
+        let mut friends = self.friends.lock().unwrap();
+        if let Err(e) = friends.seen(name) {
+            eprintln!("❌ Could not save the friend lists: {}", e);
+        }
+        let watchers = friends.joined(connection, name);
+        drop(friends);
+        self.send_to(watchers, ServerMessage::FriendOnline { name: name.to_string() });
+    }
+
+    /// Tells the players who friended `name`, just offline from
+    /// `connection`, and unindexes its friends.
+    fn friend_offline(&self, connection: ConnectionId, name: &str) {
+        let watchers = self.friends.lock().unwrap().left(connection, name);
+        self.send_to(watchers, ServerMessage::FriendOffline { name: name.to_string() });
+    }
+
+    /// Reply to `AddFriend`: the player's friends with `friend` added, or
+    /// why it was not.
+    pub fn add_friend(&self, connection: ConnectionId, friend: &str) -> Option<ServerMessage> {
+        self.change_friends(connection, |friends, name| friends.add(connection, name, friend))
+    }
+
+    /// Reply to `RemoveFriend`.
+    pub fn remove_friend(&self, connection: ConnectionId, friend: &str) -> Option<ServerMessage> {
+        self.change_friends(connection, |friends, name| friends.remove(connection, name, friend))
+    }
+
+    /// Reply to `ListFriends`.
+    pub fn friend_list(&self, connection: ConnectionId) -> Option<ServerMessage> {
+        self.change_friends(connection, |_, _| Ok(()))
+    }
+
+    /// The `FriendList` of `connection`'s player once `change` has made its
+    /// change to it, or a `FriendRejected` if it could not.
+    fn change_friends(&self, connection: ConnectionId, change: impl FnOnce(&mut Friends, &str) -> Result<(), String>) -> Option<ServerMessage> {
+        let name = self.players.shard(connection).get(&connection)?.name.clone();
+        let online: BTreeSet<String> = self.players.snapshot().iter().map(|p| normalize_name(&p.name)).collect();
+        let mut friends = self.friends.lock().unwrap();
+        if let Err(reason) = change(&mut friends, &name) {
+            return Some(ServerMessage::FriendRejected { reason });
+        }
+        Some(ServerMessage::FriendList { friends: friends.list(&name, &online) })
+    }
+
+    /// How many names online players have friended (see `friends`).
+    pub fn friends_watched(&self) -> usize {
+        self.friends.lock().unwrap().watched()
+    }
+
+    /// Sends `message` to `recipients` only, now.
+    fn send_to(&self, recipients: Vec<ConnectionId>, message: ServerMessage) {
+        if recipients.is_empty() {
+            return;
+        }
+        self.flush_events();
+        let _ = self.broadcast_tx.send(Arc::new(BroadcastFrame::to(message, recipients)));
+    }
+
+    /// Saves the chat history, if it has a file.
+    pub fn save_chat_history(&self) {
+        let log = self.chat_log.lock().unwrap();
+        if let Err(e) = log.save() {
+            let path = log.path().map(|p| p.display().to_string()).unwrap_or_default();
+            eprintln!("❌ Could not save the chat history to {}: {}", path, e);
+        }
+    }
+
+    pub fn remove_player(&self, connection: ConnectionId) {
+        let mut players = self.players.shard(connection);
+        if let Some(player) = players.remove(&connection) {
+            self.connections.lock().unwrap().remove(&connection);
+            self.spawns.lock().unwrap().remove(&connection);
+            self.emit(GameEvent::PlayerLeft { player_id: player.id, name: player.name.to_string() });
+            println!("👤 Player {} disconnected", player.name);
+            drop(players);
+            self.friend_offline(connection, &player.name);
+        }
+        self.pause.lock().unwrap().forget(connection);
+        let cancelled = self.trades.lock().unwrap().forget(connection);
+        for offer in cancelled {
+            let other = if offer.from == connection { offer.to } else { offer.from };
+            self.send_to(vec![other], ServerMessage::TradeEnded { offer_id: offer.id, reason: TradeEnd::Cancelled });
+        }
+    }
+
+    /// The server's stats over all its runs (see `lifetime`).
+    pub fn lifetime_stats(&self) -> ServerStats {
+        self.lifetime.lock().unwrap().snapshot(Instant::now())
+    }
+
+    /// Saves the lifetime stats, if they have a file.
+    pub fn save_lifetime_stats(&self) {
+        let mut lifetime = self.lifetime.lock().unwrap();
+        if let Err(e) = lifetime.save(Instant::now()) {
+            let path = lifetime.path().map(|p| p.display().to_string()).unwrap_or_default();
+            eprintln!("❌ Could not save the stats to {}: {}", path, e);
+        }
+    }
+
+    /// Starts tracking the session of the player on `connection` (see
+    /// `session`).
+    pub fn start_session(&self, connection: ConnectionId, now: Instant) -> SessionTracker {
+        let (_, xp, discovered) = self.progress(connection).unwrap_or_default();
+        SessionTracker::new(xp, discovered, now)
+    }
+
+    /// Logs how the session of the player on `connection` went and keeps it
+    /// as their last; call before `remove_player`.
+    pub fn end_session(&self, connection: ConnectionId, session: &SessionTracker, reason: DisconnectReason, now: Instant) -> Option<SessionSummary> {
+        let (name, xp, discovered) = self.progress(connection)?;
+        let summary = session.finish(&name, xp, discovered, reason, now);
+        println!("📋 Session: {}", summary);
+        self.sessions.lock().unwrap().insert(normalize_name(&name).into(), summary.clone());
+        self.lifetime.lock().unwrap().traveled(summary.distance);
+        Some(summary)
+    }
+
+    /// The last finished session of the player called `name`, however
+    /// written.
+    pub fn last_session(&self, name: &str) -> Option<SessionSummary> {
+        self.sessions.lock().unwrap().get(normalize_name(name).as_str()).cloned()
+    }
+
+    /// The player's name, XP and how many planets they have discovered.
+    fn progress(&self, connection: ConnectionId) -> Option<(String, u32, usize)> {
+        let players = self.players.shard(connection);
+        let player = players.get(&connection)?;
+        let discovered = self.discoveries.lock().unwrap().get(normalize_name(&player.name).as_str()).map_or(0, |d| d.planet_ids().len());
+        Some((player.name.to_string(), player.xp, discovered))
+    }
+
+    /// A snapshot without the fake players, for the journal.
+    fn persisted_snapshot(&self) -> Arc<GameStateSnapshot> {
+        let mut players = self.players.snapshot();
+        let fake_ids: HashSet<u32> = {
+            let all = self.players.lock_all();
+            let fakes = self.fakes.lock().unwrap();
+            all.iter().filter(|(c, _)| fakes.contains(c)).map(|(_, p)| p.id).collect()
+        };
+        players.retain(|p| !fake_ids.contains(&p.id));
+        Arc::new(GameStateSnapshot::new(self.current_tick(), self.world_snapshot(), players, self.world_time()))
+    }
+
+    /// Keeps the lifetime stats in `stats` (see `lifetime`).
+    pub fn with_lifetime_stats(self, stats: LifetimeStats) -> Self {
+        *self.lifetime.lock().unwrap() = stats;
+        self
+    }
+
+    /// Keeps the chat history in `log` (see `chat_history`).
+    pub fn with_chat_log(self, log: ChatLog) -> Self {
+        *self.chat_log.lock().unwrap() = log;
+        self
+    }
+
+    /// Starts with these bans (see `bans`).
+    pub fn with_bans(self, bans: BanList) -> Self {
+        *self.bans.lock().unwrap() = bans;
+        self
+    }
+
+    /// Starts with these mutes (see `mutes`).
+    pub fn with_mutes(self, mutes: MuteList) -> Self {
+        *self.mutes.lock().unwrap() = mutes;
+        self
+    }
+
+    /// Starts with `count` fake players (see `fakes`).
+    pub fn with_fake_players(self, count: usize) -> Self {
+        self.add_fake_players(count);
+        self
+    }
+
+    /// Adds `count` fake players at spawns chosen as for real ones,
+    /// returning them.
+    pub fn add_fake_players(&self, count: usize) -> Vec<Player> {
+        let mut players = self.players.lock_all();
+        let mut fakes = self.fakes.lock().unwrap();
+        let population = self.planet_population();
+        let mut spawns = self.spawns.lock().unwrap();
+        let state = self.world_snapshot();
+        let mut added = Vec::with_capacity(count);
+        for _ in 0..count {
+            let connection = ConnectionId(self.next_connection_id.fetch_add(1, Ordering::SeqCst));
+            let mut spawn = choose_spawn_balanced(&state.planets, spawns.values(), &population, &state.initial_player_location);
+            spawn.position = self.world.clamp(&spawn.position);
+            let id = self.next_player_id.fetch_add(1, Ordering::SeqCst);
+            let name = format!("{}{}", fakes::NAME_PREFIX, id);
+            let player = Player {
+                id,
+                name: name.as_str().into(),
+                level: level_for(0),
+                position: spawn.position.clone(),
+                velocity: [0.0; 3],
+                rotation: IDENTITY_ROTATION,
+                last_processed_seq: 0,
+                idle: false,
+                appearance: PlayerAppearance::for_name(&name),
+                xp: 0,
+                landed_on: None,
+                remote: false,
+            };
+            spawns.insert(connection, spawn);
+            players.insert(connection, player.clone());
+            fakes.insert(connection);
+            added.push(player);
+        }
+        if count > 0 {
+            println!("🤖 Added {} fake player(s), {} in all", count, fakes.len());
+        }
+        added
+    }
+
+    /// Removes up to `count` fake players, the newest first, returning them.
+    pub fn remove_fake_players(&self, count: usize) -> Vec<Player> {
+        self.plan_remove_fake_players(count, false).1
+    }
+
+    /// Removes up to `count` fake players, the newest first, or for a dry
+    /// run only says which (see `admin`); returns the players removed.
+    pub fn plan_remove_fake_players(&self, count: usize, dry_run: bool) -> (AdminPlan, Vec<Player>) {
+        let mut players = self.players.lock_all();
+        let mut fakes = self.fakes.lock().unwrap();
+        let mut spawns = self.spawns.lock().unwrap();
+        let mut newest: Vec<ConnectionId> = fakes.iter().copied().collect();
+        newest.sort_by(|a, b| b.cmp(a));
+        newest.truncate(count);
+        let plan = AdminPlan {
+            command: format!("fakes remove {}", count),
+            players_affected: newest.iter()
+                .filter_map(|connection| players.get(connection))
+                .map(|p| AffectedPlayer { id: p.id, name: p.name.to_string() })
+                .collect(),
+            ..AdminPlan::default()
+        };
+        if dry_run {
+            return (plan, Vec::new());
+        }
+        let mut removed = Vec::with_capacity(newest.len());
+        for connection in newest {
+            fakes.remove(&connection);
+            spawns.remove(&connection);
+            removed.extend(players.remove(&connection));
+        }
+        if !removed.is_empty() {
+            println!("🤖 Removed {} fake player(s), {} left", removed.len(), fakes.len());
+        }
+        (plan, removed)
+    }
+
+    pub fn fake_player_count(&self) -> usize {
+        self.fakes.lock().unwrap().len()
+    }
+
+    /// Whether `connection` is a fake player's rather than a client's.
+    pub fn is_fake(&self, connection: ConnectionId) -> bool {
+        self.fakes.lock().unwrap().contains(&connection)
+    }
+
+    /// Moves every fake player to where `wander` puts it at `tick`.
+    fn move_fake_players(&self, tick: u64) {
+        let fakes: Vec<ConnectionId> = self.fakes.lock().unwrap().iter().copied().collect();
+        if fakes.is_empty() {
+            return;
+        }
+        let tick_length = self.live.borrow().broadcast_interval;
+        let homes: Vec<(ConnectionId, Position)> = {
+            let spawns = self.spawns.lock().unwrap();
+            fakes.iter().filter_map(|c| Some((*c, spawns.get(c)?.position.clone()))).collect()
+        };
+        for (connection, home) in homes {
+            let mut players = self.players.shard(connection);
+            if let Some(player) = players.get_mut(&connection) {
+                let (position, velocity, rotation) = wander(&home, player.id, tick, tick_length);
+                player.position = self.world.clamp(&position);
+                player.velocity = velocity;
+                player.rotation = rotation;
+            }
+        }
+    }
+}
+
+/// A server started by `GameServer::spawn`. Dropping the handle leaves the
+/// server running; `shutdown` stops it.
+pub struct ServerHandle {
+    server: GameServer,
+    local_addr: SocketAddr,
+    stop: oneshot::Sender<()>,
+    task: tokio::task::JoinHandle<std::io::Result<()>>,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.server.player_count()
+    }
+
+    pub fn server(&self) -> &GameServer {
+        &self.server
+    }
+
+    /// Waits until the server is accepting connections, with every
+    /// background task started (see `readiness`).
+    pub async fn ready(&self) {
+        let mut ready = self.server.ready.subscribe();
+        let _ = ready.wait_for(Option::is_some).await;
+    }
+
+    /// See `GameServer::subscribe_events`.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<GameEvent> {
+        self.server.subscribe_events()
+    }
+
+    /// Stops accepting, closes every connection with `ShuttingDown` and
+    /// waits for the server to finish.
+    pub async fn shutdown(self) -> std::io::Result<()> {
+        let _ = self.stop.send(());
+        self.task.await.map_err(std::io::Error::other)?
+    }
+}
+
+impl GameServer {
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Accepts connections on `listener` until `shutdown` completes, or a
+    /// follower takes over, along with the periodic broadcasts, the economy
+    /// tick and scheduled announcements. Then every connection is closed
+    /// with `ShuttingDown`, after a `Reconnect` to the follower if there is
+    /// one, and given the write timeout to finish before it is dropped.
+    /// Only a failed `accept` is an error.
+    pub async fn run(self, listener: TcpListener, shutdown: impl Future<Output = ()>) -> std::io::Result<()> {
+        let local_addr = listener.local_addr()?;
+        let mut background = self.spawn_scheduled_announcements();
+        background.push(self.spawn_broadcast_loop());
+        background.push(self.spawn_economy_loop());
+        background.push(self.spawn_saver());
+        if let Some(webhooks) = &self.webhooks {
+            background.extend(webhooks.run());
+        }
+        self.emit(GameEvent::ServerStarted);
+        #[cfg(feature = "cluster")]
+        if let Some(cluster) = &self.cluster {
+            self.planets_changed();
+            background.extend(cluster.spawn(self.clone()));
+        }
+        if let Some(standby) = &self.standby {
+            background.push(standby.spawn());
+        }
+        let mut connections = tokio::task::JoinSet::new();
+        let guard = Arc::new(Mutex::new(AcceptGuard::new(self.accept_limits.or_fd_limit())));
+        let mut handover = self.handover.subscribe();
+        tokio::pin!(shutdown);
+
+        // Everything is running, and the listener queues connections until
+        // the loop below takes them
+        for problem in self.readiness.signal(local_addr) {
+            println!("⚠️  {}", problem);
+        }
+        self.ready.send_replace(Some(local_addr));
+
+        let result = loop {
+            let (stream, addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => break Err(e),
+                },
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                _ = &mut shutdown => break Ok(()),
+                _ = handover.wait_for(Option::is_some) => break Ok(()),
+            };
+            // A trusted proxy's connections come from many hosts
+            let host = Some(addr.ip()).filter(|ip| !self.trusted_proxies.iter().any(|cidr| cidr.contains(*ip)));
+            let admitted = guard.lock().unwrap().admit(host, Instant::now());
+            match admitted {
+                Ok(()) => {
+                    let admission = Admission { guard: guard.clone(), host };
+                    let server = self.clone();
+                    connections.spawn(async move {
+                        let _admission = admission;
+                        server.serve_stream(stream, addr).await
+                    });
+                }
+                Err(refused) => {
+                    if refused.first_in_window {
+                        println!("🚧 Turning away connections from {}: {}", addr.ip(), refused.refusal);
+                    }
+                    drop(stream);
+                }
+            }
+        };
+
+        println!("\n🛑 Shutting down");
+        drop(listener);
+        self.shutdown.send_replace(true);
+        let drained = async { while connections.join_next().await.is_some() {} };
+        if tokio::time::timeout(self.write_timeout, drained).await.is_err() {
+            connections.shutdown().await;
+        }
+        self.emit(GameEvent::ServerStopped);
+        if let Some(webhooks) = &self.webhooks
+            && !webhooks.flush(self.write_timeout).await
+        {
+            println!("📮 Gave up waiting for the webhooks");
+        }
+        for task in background {
+            task.abort();
+        }
+        // Every session has ended and added its distance
+        self.save_lifetime_stats();
+        self.save_chat_history();
+        result
+    }
+
+    /// Runs the server on `listener` in the background.
+    pub fn spawn(self, listener: TcpListener) -> std::io::Result<ServerHandle> {
+        let local_addr = listener.local_addr()?;
+        let (stop, stopped) = oneshot::channel::<()>();
+        let stopped = async {
+            // A dropped handle is not a request to stop
+            if stopped.await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        };
+        let task = tokio::spawn(self.clone().run(listener, stopped));
+        Ok(ServerHandle { server: self, local_addr, stop, task })
+    }
+
+    /// One accepted connection, after the TLS handshake if there is one.
+    async fn serve_stream(self, stream: TcpStream, addr: SocketAddr) {
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = self.tls.clone() {
+            // Handshake failures only affect this connection
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("❌ TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+            println!("🔒 TLS session with {}: {}", addr, crate::tls::describe_session(&stream));
+            if let Err(e) = handle_connection(stream, addr, self).await {
+                eprintln!("❌ Error handling connection from {}: {}", addr, e);
+            }
+            return;
+        }
+
+        if let Err(e) = handle_connection(stream, addr, self).await {
+            eprintln!("❌ Error handling connection from {}: {}", addr, e);
+        }
+    }
+}
+
+/// An accepted connection's place in the `AcceptGuard`, given back when
+/// its task ends.
+struct Admission {
+    guard: Arc<Mutex<AcceptGuard>>,
+    host: Option<IpAddr>,
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        self.guard.lock().unwrap().release(self.host);
+    }
+}
+
+/// Runs one client session over any byte stream (plain TCP or TLS).
+pub async fn handle_connection<S>(
+    stream: S,
+    addr: std::net::SocketAddr,
+    server: GameServer,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let peer = addr;
+    let mut info = ClientInfo::direct(peer);
+    // The callback signature (and its large error type) is fixed by tungstenite
+    #[allow(clippy::result_large_err)]
+    let capture = |request: &Request, mut response: Response| {
+        info = client_info(peer, request, &server.trusted_proxies);
+        let authorized = server.admin_token.as_ref()
+            .zip(info.token.as_ref())
+            .is_some_and(|(expected, token)| constant_time_eq(expected.as_bytes(), token.as_bytes()));
+        if info.role == Role::Follower && !authorized {
+            let mut refusal = ErrorResponse::new(Some("followers need the admin token".to_string()));
+            *refusal.status_mut() = StatusCode::UNAUTHORIZED;
+            return Err(refusal);
+        }
+        response.headers_mut().insert(CAPABILITIES_HEADER, info.capabilities.0.into());
+        if let Ok(radius) = server.world.radius.to_string().parse() {
+            response.headers_mut().insert(WORLD_RADIUS_HEADER, radius);
+        }
+        Ok(response)
+    };
+    let ws_stream = accept_hdr_async_with_config(stream, capture, Some(server_ws_config())).await?;
+
+    // From here on `addr` is the client's real address, even behind a proxy
+    let addr = info.addr;
+    if info.is_proxied() {
+        println!("✅ New WebSocket connection from: {} (via proxy {})", addr, peer);
+    } else {
+        println!("✅ New WebSocket connection from: {}", addr);
+    }
+    println!("   path={} room={} user-agent={} compression={} capabilities={}",
+             info.path,
+             info.room.as_deref().unwrap_or("-"),
+             info.user_agent.as_deref().unwrap_or("-"),
+             if info.offered_deflate { "offered, declined" } else { "none" },
+             info.capabilities.0);
+    if info.subscriptions != Subscriptions::ALL {
+        println!("   subscriptions={}", info.subscriptions.0);
+    }
+
+    // From here on binary frames are encoded for what the client understands
+    let capabilities = info.capabilities;
+    if info.role == Role::Follower {
+        return feed_follower(ws_stream, addr, server).await;
+    }
+    if info.role == Role::Spectator {
+        return encoding_for_task(capabilities, spectate(ws_stream, addr, server, info.format, capabilities, info.subscriptions)).await;
+    }
+    encoding_for_task(capabilities, play(ws_stream, server, info)).await
+}
+
+/// Runs a player session, once the handshake is done: reads frames and
+/// ticks, feeds them to the connection's state machine and carries out
+/// what it says (see `connection_state`).
+async fn play<S>(
+    ws_stream: WebSocketStream<S>,
+    server: GameServer,
+    info: ClientInfo,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let ClientInfo { addr, format, name, room, capabilities, subscriptions, .. } = info;
+    let (mut write, mut read) = ws_stream.split();
+
+    // Subscribe to broadcast channel
+    let mut broadcast_rx = server.broadcast_tx.subscribe();
+
+    let stats = Arc::new(ConnectionStats::new(Instant::now()));
+    let kick = Arc::new(Notify::new());
+    let joining = Connection { addr, stats: stats.clone(), kick: kick.clone() };
+    let name = name.unwrap_or_else(|| format!("Player_{}", addr.port()));
+    let (connection, player, spawn) = match server.add_player(joining, name) {
+        Ok(joined) => joined,
+        Err(rejection) => {
+            println!("🚫 [{}] Turned away: {}", addr, rejection);
+            for frame in rejection.error_code().messages(format, &rejection.to_string())? {
+                write.send(seal_if_asked(frame, capabilities)).await?;
+            }
+            return Ok(());
+        }
+    };
+    let session = server.start_session(connection, Instant::now());
+    let cleanup = PlayerCleanup { server: &server, connection, session, reason: DisconnectReason::Error };
+
+    // Writes happen on their own task so a client that stops reading cannot
+    // stall this loop; it is dropped when a write times out or its queue fills.
+    let (queue, queued) = mpsc::channel(SEND_QUEUE_CAPACITY);
+    let outbox = Outbox::new(queue, stats.clone(), capabilities.contains(Capabilities::CRC));
+    let write = CountingSink::new(write, stats.clone());
+    let mut writer = tokio::spawn(write_queued(write, queued, server.write_timeout, stats.clone()));
+    let mut writer_finished = false;
+
+    send_join_messages(&outbox, &server, addr, &player, &spawn, format, subscriptions)?;
+
+    // Config reloads replace the rate limiter and the idle thresholds below
+    let mut live = server.live.subscribe();
+    let mut shutdown = server.shutdown.subscribe();
+    let settings = *live.borrow_and_update();
+    let mut shell = PlayerShell {
+        server: &server,
+        addr,
+        connection,
+        player,
+        spawn,
+        room,
+        capabilities,
+        subscriptions,
+        format,
+        cleanup,
+        outbox,
+        stats: stats.clone(),
+        limiter: ConnectionLimiter::new(&settings.rate_limits, Instant::now()),
+        pending_position: None,
+        highest_seq: None,
+        activity: ActivityTracker::new(settings.idle, Instant::now()),
+        bandwidth: BandwidthGovernor::new(settings.bandwidth_budget, 0, Instant::now()),
+        bounds: BoundsTracker::new(server.world.clone()),
+        teleport_cooldown: TeleportCooldown::new(server.teleport),
+        debug: None,
+        // Only clients that said they answer keepalives are sent any
+        keepalive: capabilities.contains(Capabilities::KEEPALIVE).then(|| KeepaliveTracker::new(server.keepalive, Instant::now())),
+        chat_seq: ChatSequence::new(),
+        quantized: capabilities.contains(Capabilities::QUANTIZED).then(|| QuantizedStream::new(server.quantization)),
+    };
+    let mut flush_interval = tokio::time::interval(Duration::from_millis(50));
+    let mut idle_check = tokio::time::interval(Duration::from_secs(1));
+    let mut debug_tick = tokio::time::interval(DEBUG_STATS_INTERVAL);
+    debug_tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut keepalive_check = keepalive_interval(server.keepalive);
+    let mut state = ConnectionState::new(connection_state::Role::Player);
+
+    // Handle incoming messages and broadcast updates concurrently, until
+    // one of them ends the session
+    while !state.is_finished() {
+        let incoming = tokio::select! {
+            // Handle incoming messages from client
+            msg = read.next() => {
+                if let Some(Ok(msg @ (Message::Text(_) | Message::Binary(_)))) = &msg {
+                    stats.record_received(msg.len(), Instant::now());
+                }
+                if let (Some(Ok(_)), Some(tracker)) = (&msg, shell.keepalive.as_mut()) {
+                    tracker.heard(Instant::now());
+                }
+                match msg {
+                    Some(Ok(msg @ (Message::Text(_) | Message::Binary(_)))) => {
+                        match decode_incoming(&shell.outbox, addr, &msg, capabilities, shell.format)? {
+                            Some(Incoming::Message(message)) => shell.admit(message),
+                            Some(incoming) => incoming,
+                            None => continue,
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) => {
+                        println!("👋 [{}] Connection closed", addr);
+                        Incoming::ClientClosed
+                    }
+                    Some(Ok(Message::Ping(data))) => {
+                        shell.outbox.send(Message::Pong(data))?;
+                        continue;
+                    }
+                    Some(Ok(Message::Pong(data))) => {
+                        if let Some(window) = shell.debug.as_mut() {
+                            window.pong(&data, server.server_time_ms());
+                        }
+                        continue;
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("❌ [{}] Error: {}", addr, e);
+                        Incoming::ConnectionLost
+                    }
+                    None => Incoming::ConnectionLost,
+                    _ => continue,
+                }
+            }
+
+            // Receive broadcast updates and send to client
+            broadcast = broadcast_rx.recv() => {
+                if let Ok(broadcast) = broadcast {
+                    if let Some(level) = shell.bandwidth.observe(stats.bytes_sent(), Instant::now()) {
+                        println!("🐢 [{}] Bandwidth level {}: a State every {} tick(s)", addr, level, shell.bandwidth.state_every());
+                        stats.set_bandwidth_level(level);
+                    }
+                    if broadcast.is_for_spectators() || !broadcast.is_for(connection)
+                        || broadcast.state_tick().is_some_and(|tick| !shell.subscriptions.contains(Subscriptions::STATE)
+                            || !shell.bandwidth.wants_state(tick) || !shell.outbox.admit_state(tick))
+                    {
+                        continue;
+                    }
+                    for frame in broadcast_frames(&broadcast, shell.quantized.as_mut(), shell.format, capabilities, shell.subscriptions)? {
+                        shell.outbox.send(frame)?;
+                    }
+                }
+                continue;
+            }
+
+            // The writer gives up on clients that stop reading
+            finished = &mut writer => {
+                writer_finished = true;
+                if let Ok(Err(reason)) = finished {
+                    println!("🐢 [{}] Disconnecting: {}", addr, reason);
+                }
+                Incoming::WriterGone
+            }
+
+            _ = kick.notified() => {
+                println!("👢 [{}] Disconnecting: kicked by an admin", addr);
+                Incoming::Kicked
+            }
+
+            _ = shutdown.wait_for(|stopping| *stopping) => Incoming::ShuttingDown { reconnect_to: server.handover.borrow().clone() },
+
+            Ok(()) = live.changed() => {
+                let settings = *live.borrow_and_update();
+                shell.limiter = ConnectionLimiter::new(&settings.rate_limits, Instant::now());
+                shell.activity.set_config(settings.idle);
+                shell.bandwidth.set_budget(settings.bandwidth_budget);
+                stats.set_bandwidth_level(shell.bandwidth.level());
+                continue;
+            }
+
+            _ = idle_check.tick() => {
+                match shell.activity.poll(Instant::now()) {
+                    Some(IdleEvent::BecameIdle) => {
+                        server.set_player_idle(connection, true);
+                        continue;
+                    }
+                    Some(IdleEvent::TimedOut) => {
+                        println!("⏰ [{}] Disconnecting: idle timeout", addr);
+                        Incoming::IdleTimeout
+                    }
+                    _ => continue,
+                }
+            }
+
+            _ = keepalive_check.tick(), if shell.keepalive.is_some() => {
+                let Some(tracker) = shell.keepalive.as_mut() else { continue };
+                match send_keepalive(tracker, &shell.outbox, addr, shell.format)? {
+                    Some(timed_out) => timed_out,
+                    None => continue,
+                }
+            }
+
+            _ = debug_tick.tick() => {
+                if let Some(window) = shell.debug.as_mut() {
+                    let frame = window.frame(&stats.snapshot(Instant::now()), server.current_tick());
+                    shell.outbox.send(encode_server_message(shell.format, &frame)?)?;
+                    shell.outbox.send(DebugWindow::ping(server.server_time_ms()))?;
+                }
+                continue;
+            }
+
+            // Apply the most recent dropped position update once tokens are
+            // available, and the simulation is not paused
+            _ = flush_interval.tick(), if shell.pending_position.is_some() => {
+                if !server.is_paused()
+                    && shell.limiter.positions.check(Instant::now()) == Decision::Allowed
+                    && let Some((update, teleported)) = shell.pending_position.take()
+                {
+                    shell.apply_position(update, teleported);
+                }
+                continue;
+            }
+        };
+        state = shell.drive(state, incoming)?;
+    }
+
+    // Clean up player on disconnect, then give the writer a moment to send
+    // what is still queued, such as a close frame
+    let PlayerShell { mut cleanup, outbox, .. } = shell;
+    cleanup.reason = state.disconnect_reason().unwrap_or(DisconnectReason::Error);
+    drop(cleanup);
+    drop(outbox);
+    if !writer_finished && tokio::time::timeout(server.write_timeout, &mut writer).await.is_err() {
+        writer.abort();
+    }
+
+    Ok(())
+}
+
+/// The async side of a connection: carries out the `Outgoing` actions of
+/// its state machine (see `connection_state`).
+trait Shell {
+    fn outbox(&self) -> &Outbox;
+    fn format(&self) -> WireFormat;
+    /// Switches to `format` and sends the join messages again in it.
+    fn switch_format(&mut self, format: WireFormat) -> Result<(), Box<dyn std::error::Error>>;
+    /// Carries out a message the connection may send, returning what it
+    /// raised in turn, such as a rate limit.
+    fn dispatch(&mut self, message: ClientMessage) -> Result<Option<Incoming>, Box<dyn std::error::Error>>;
+
+    /// Feeds `incoming` to the state machine and carries out what it says,
+    /// returning the next state.
+    fn drive(&mut self, mut state: ConnectionState, incoming: Incoming) -> Result<ConnectionState, Box<dyn std::error::Error>> {
+        let mut events = vec![incoming];
+        while let Some(incoming) = events.pop() {
+            let (next, outgoing) = connection_state::handle(state, incoming);
+            state = next;
+            for action in outgoing {
+                match action {
+                    Outgoing::SwitchFormat(format) => self.switch_format(format)?,
+                    Outgoing::Reply(message) => self.outbox().send_message(self.format(), &message)?,
+                    Outgoing::Close { code, reason } => self.outbox().close(self.format(), code, reason)?,
+                    Outgoing::Dispatch(message) => events.extend(self.dispatch(message)?),
+                }
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// What a player's connection keeps between messages.
+struct PlayerShell<'a> {
+    server: &'a GameServer,
+    addr: SocketAddr,
+    connection: ConnectionId,
+    player: Player,
+    spawn: Spawn,
+    room: Option<String>,
+    capabilities: Capabilities,
+    // The kinds of broadcast sent on; see `subscriptions`
+    subscriptions: Subscriptions,
+    format: WireFormat,
+    cleanup: PlayerCleanup<'a>,
+    outbox: Outbox,
+    stats: Arc<ConnectionStats>,
+    // Per-connection rate limiting; over-limit position updates are parked
+    // and only the latest one is applied once the bucket refills, along
+    // with whether it sends the player back to spawn.
+    limiter: ConnectionLimiter,
+    pending_position: Option<(PositionUpdate, bool)>,
+    // Newest sequence number accepted from this player; older ones are stale
+    highest_seq: Option<u32>,
+    activity: ActivityTracker,
+    // Slows the States sent to this client while it is over its budget
+    bandwidth: BandwidthGovernor,
+    bounds: BoundsTracker,
+    teleport_cooldown: TeleportCooldown,
+    // Set while the client is subscribed to its own `DebugStats`
+    debug: Option<DebugWindow>,
+    keepalive: Option<KeepaliveTracker>,
+    // Numbers the chats accepted from this player, for their `ChatAck`s
+    chat_seq: ChatSequence,
+    // Set for QUANTIZED clients, whose States go as deltas (see `quantize`)
+    quantized: Option<QuantizedStream>,
+}
+
+impl Shell for PlayerShell<'_> {
+    fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    fn switch_format(&mut self, format: WireFormat) -> Result<(), Box<dyn std::error::Error>> {
+        self.format = format;
+        println!("🔀 [{}] Switched to {:?} format", self.addr, format);
+        // The join messages bring a whole State
+        if let Some(stream) = &mut self.quantized {
+            stream.restart();
+        }
+        send_join_messages(&self.outbox, self.server, self.addr, &self.player, &self.spawn, format, self.subscriptions)
+    }
+
+    fn dispatch(&mut self, incoming: ClientMessage) -> Result<Option<Incoming>, Box<dyn std::error::Error>> {
+        let (server, addr, connection, format) = (self.server, self.addr, self.connection, self.format);
+        let outbox = &self.outbox;
+        let limiter = &mut self.limiter;
+        let reply = |message: &ServerMessage| -> Result<Option<Incoming>, Box<dyn std::error::Error>> {
+            outbox.send(encode_server_message(format, message)?)?;
+            Ok(None)
+        };
+
+        // A paused world stays as it is (see `pause`)
+        if server.is_paused() && matches!(incoming, ClientMessage::Respawn {} | ClientMessage::TeleportToPlanet { .. }
+            | ClientMessage::Land { .. } | ClientMessage::TakeOff {} | ClientMessage::Mine { .. } | ClientMessage::Sell { .. }
+            | ClientMessage::TradeAccept { .. } | ClientMessage::TeleportToPlanetNamed { .. } | ClientMessage::LandNamed { .. })
+        {
+            if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                return Ok(Some(rate_limited(addr)));
+            }
+            return reply(&server.paused_reply());
+        }
+
+        // By name, as by the id of the planet of that name (see `planet_names`)
+        let mut unknown_name = |rejection: ServerMessage| {
+            if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                return Ok(Some(rate_limited(addr)));
+            }
+            reply(&rejection)
+        };
+        let incoming = match incoming {
+            ClientMessage::TeleportToPlanetNamed { name } => match server.planet_named(&name) {
+                Some(planet_id) => ClientMessage::TeleportToPlanet { planet_id },
+                None => return unknown_name(ServerMessage::TeleportRejected { error: TeleportError::UnknownName { name } }),
+            },
+            ClientMessage::LandNamed { name } => match server.planet_named(&name) {
+                Some(planet_id) => ClientMessage::Land { planet_id },
+                None => return unknown_name(ServerMessage::LandRejected { error: LandError::UnknownName { name } }),
+            },
+            incoming => incoming,
+        };
+
+        match incoming {
+            // Answered by the state machine
+            ClientMessage::SetFormat { .. } => Ok(None),
+            // Turned into their by-id forms above
+            ClientMessage::TeleportToPlanetNamed { .. } | ClientMessage::LandNamed { .. } => Ok(None),
+            ClientMessage::Chat { text, client_ref } => {
+                if self.activity.record_activity(Instant::now()) == Some(IdleEvent::BecameActive) {
+                    server.set_player_idle(connection, false);
+                }
+                // Only chats from CHAT_ACKS clients that asked get a receipt
+                let client_ref = client_ref.filter(|_| self.capabilities.contains(Capabilities::CHAT_ACKS));
+                let mute = server.chat_mute(connection);
+                let accepted = match limiter.chat.check(Instant::now()) {
+                    Decision::Allowed => match &mute {
+                        Some(mute) if mute.kind == MuteKind::Mute => Err(ChatError::Muted { remaining_ms: mute.remaining_ms(unix_ms()) }),
+                        _ => chat_receipts::check_length(&text),
+                    },
+                    Decision::Limited => Err(ChatError::RateLimited { retry_after_ms: limiter.chat.retry_after().as_millis() as u64 }),
+                    Decision::Abusive => return Ok(Some(rate_limited(addr))),
+                };
+                match (accepted, client_ref) {
+                    (Ok(()), client_ref) => {
+                        // A shadow-muted chat looks to its sender like any other
+                        let tick = if mute.is_some() {
+                            println!("🔇 [{}] {}", addr, text);
+                            server.shadow_chat(connection, self.room.as_deref(), &text)
+                        } else {
+                            println!("💬 [{}] {}", addr, text);
+                            server.record_chat(connection, self.room.as_deref(), &text)
+                        };
+                        let server_seq = self.chat_seq.accept();
+                        reply(&ServerMessage::Echo { text })?;
+                        match client_ref {
+                            Some(client_ref) => reply(&ServerMessage::ChatAck { client_ref, server_seq, tick }),
+                            None => Ok(None),
+                        }
+                    }
+                    (Err(error), Some(client_ref)) => reply(&ServerMessage::ChatRejected { client_ref, error }),
+                    (Err(ChatError::RateLimited { retry_after_ms }), None) => reply(&localized(MessageCode::RateLimited { retry_after_ms })),
+                    (Err(ChatError::Muted { .. }), None) => reply(&localized(MessageCode::ChatMuted {})),
+                    (Err(error), None) => reply(&localized(MessageCode::InvalidMessage { reason: error.to_string() })),
+                }
+            }
+            ClientMessage::Admin { token, command, dry_run } => {
+                // Shares the chat limit so tokens cannot be guessed quickly
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                let suffixed = admin::parse_request(&command).is_ok_and(|(_, dry_run)| dry_run);
+                let outcome = if dry_run || suffixed {
+                    let command = if suffixed { command } else { format!("{} --dry-run", command.trim_end()) };
+                    server.plan_player_admin_command(connection, &token, &command).map(|plan| ServerMessage::AdminPlanned { plan })
+                } else {
+                    server.run_player_admin_command(connection, &token, &command).map(|message| ServerMessage::AdminResult { ok: true, message })
+                };
+                reply(&outcome.unwrap_or_else(|message| ServerMessage::AdminResult { ok: false, message }))
+            }
+            ClientMessage::Respawn {} => {
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                println!("🛬 [{}] Respawning", addr);
+                server.move_player(connection, self.spawn.position.clone());
+                Ok(None)
+            }
+            ClientMessage::TeleportToPlanet { planet_id } => {
+                match server.teleport_to_planet(connection, self.player.id, planet_id, &mut self.teleport_cooldown, Instant::now()) {
+                    Ok(p) => {
+                        println!("🌀 [{}] Teleported to planet {} at ({:.1}, {:.1}, {:.1})", addr, planet_id, p.x, p.y, p.z);
+                        Ok(None)
+                    }
+                    Err(error) => {
+                        println!("🌀 [{}] Teleport to planet {} rejected: {}", addr, planet_id, error);
+                        reply(&ServerMessage::TeleportRejected { error })
+                    }
+                }
+            }
+            ClientMessage::Land { planet_id } => {
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                match server.land(connection, planet_id) {
+                    Ok(p) => {
+                        println!("🛬 [{}] Landed on planet {} at ({:.1}, {:.1}, {:.1})", addr, planet_id, p.x, p.y, p.z);
+                        Ok(None)
+                    }
+                    Err(error) => {
+                        println!("🛬 [{}] Landing on planet {} rejected: {}", addr, planet_id, error);
+                        reply(&ServerMessage::LandRejected { error })
+                    }
+                }
+            }
+            // Charged to the chat limit in `admit`, and its name checked by
+            // the state machine
+            ClientMessage::Join { name } => match server.rename_player(connection, &name) {
+                Ok(Some(_)) => Ok(None),
+                Ok(None) => {
+                    let spawn = server.spawn_of(connection).unwrap_or_else(|| self.spawn.clone());
+                    reply(&ServerMessage::Joined { player_id: self.player.id, spawn: spawn.position, spawn_planet_id: spawn.planet_id })
+                }
+                Err(error) => {
+                    println!("🏷️  [{}] Rename to {} rejected: {}", addr, name, error);
+                    reply(&ServerMessage::RenameRejected { error })
+                }
+            },
+            ClientMessage::RenamePlanet { planet_id, name } => {
+                // Shares the chat limit, as everyone is told
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                match server.rename_planet(connection, planet_id, &name) {
+                    Ok(_) => Ok(None),
+                    Err(error) => {
+                        println!("🪐 [{}] Renaming planet {} to {} rejected: {}", addr, planet_id, name, error);
+                        reply(&ServerMessage::PlanetRenameRejected { error })
+                    }
+                }
+            }
+            ClientMessage::TakeOff {} => match server.take_off(connection) {
+                Ok(()) => {
+                    println!("🛫 [{}] Took off", addr);
+                    Ok(None)
+                }
+                Err(error) => reply(&ServerMessage::LandRejected { error }),
+            },
+            ClientMessage::Mine { amount } => {
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                match server.mine(connection, amount) {
+                    Ok((planet_id, amount, cargo)) => {
+                        println!("⛏️  [{}] Mined {} unit(s) of body {}", addr, amount, planet_id);
+                        self.cleanup.session.mined(amount as u64);
+                        reply(&ServerMessage::Mined { planet_id, amount, cargo })
+                    }
+                    Err(error) => reply(&ServerMessage::MineRejected { error }),
+                }
+            }
+            ClientMessage::Sell { amount } => {
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                match server.sell(connection, amount) {
+                    Ok((earned, credits)) => {
+                        println!("💰 [{}] Sold {} unit(s) for {} credit(s)", addr, amount, earned);
+                        reply(&ServerMessage::Sold { amount, earned, credits })
+                    }
+                    Err(error) => reply(&ServerMessage::SellRejected { error }),
+                }
+            }
+            ClientMessage::TradeOffer { .. } | ClientMessage::TradeAccept { .. } | ClientMessage::TradeDecline { .. } => {
+                // Shares the chat limit, as each offer is a message to another player
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                let result = match incoming {
+                    ClientMessage::TradeOffer { to_player, give_resources, want_credits } => server.offer_trade(connection, to_player, give_resources, want_credits),
+                    ClientMessage::TradeAccept { offer_id } => server.accept_trade(connection, offer_id),
+                    ClientMessage::TradeDecline { offer_id } => server.decline_trade(connection, offer_id),
+                    _ => return Ok(None),
+                };
+                match result {
+                    Ok(message) => {
+                        if let ServerMessage::TradeCompleted { offer_id, .. } = &message {
+                            println!("🤝 [{}] Accepted trade offer {}", addr, offer_id);
+                        }
+                        reply(&message)
+                    }
+                    Err(error) => {
+                        println!("🤝 [{}] Trade refused: {}", addr, error);
+                        reply(&ServerMessage::TradeRejected { error })
+                    }
+                }
+            }
+            ClientMessage::SetAppearance { appearance } => {
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                match server.set_player_appearance(connection, appearance) {
+                    Ok(()) => Ok(None),
+                    Err(reason) => {
+                        println!("🎨 [{}] Appearance rejected: {}", addr, reason);
+                        reply(&ServerMessage::AppearanceRejected { reason })
+                    }
+                }
+            }
+            ClientMessage::QueryPlanets { center, radius, max_results } => match limiter.chat.check(Instant::now()) {
+                Decision::Allowed => reply(&server.query_planets(&center, radius, max_results)
+                    .unwrap_or_else(|reason| localized(MessageCode::PlanetQueryRejected { reason }))),
+                Decision::Limited => reply(&localized(MessageCode::PlanetQueryRateLimited { retry_after_ms: limiter.chat.retry_after().as_millis() as u64 })),
+                Decision::Abusive => Ok(Some(rate_limited(addr))),
+            },
+            ClientMessage::QueryPlanetsFiltered { center, module_type, min_size, max_size, owned_by, unclaimed_only, sort, limit, cursor, name } => {
+                match limiter.chat.check(Instant::now()) {
+                    Decision::Allowed => {
+                        let filter = PlanetFilter { module_type, min_size, max_size, owned_by, unclaimed_only, name };
+                        reply(&server.query_planets_filtered(&center, &filter, sort, limit, cursor)
+                            .unwrap_or_else(|reason| localized(MessageCode::PlanetQueryRejected { reason })))
+                    }
+                    Decision::Limited => reply(&localized(MessageCode::PlanetQueryRateLimited { retry_after_ms: limiter.chat.retry_after().as_millis() as u64 })),
+                    Decision::Abusive => Ok(Some(rate_limited(addr))),
+                }
+            }
+            ClientMessage::QueryTrail { player_id } => match limiter.chat.check(Instant::now()) {
+                Decision::Allowed => reply(&server.query_trail(player_id)
+                    .unwrap_or_else(|reason| localized(MessageCode::TrailQueryRejected { reason }))),
+                Decision::Limited => reply(&localized(MessageCode::TrailQueryRateLimited { retry_after_ms: limiter.chat.retry_after().as_millis() as u64 })),
+                Decision::Abusive => Ok(Some(rate_limited(addr))),
+            },
+            ClientMessage::MoreHistory { before_timestamp, limit } => match limiter.chat.check(Instant::now()) {
+                Decision::Allowed => reply(&server.more_chat_history(before_timestamp, limit)),
+                Decision::Limited => reply(&localized(MessageCode::HistoryRateLimited { retry_after_ms: limiter.chat.retry_after().as_millis() as u64 })),
+                Decision::Abusive => Ok(Some(rate_limited(addr))),
+            },
+            ClientMessage::Discoveries {} => match server.discovery_list(connection) {
+                Some(list) => reply(&list),
+                None => Ok(None),
+            },
+            ClientMessage::AddFriend { .. } | ClientMessage::RemoveFriend { .. } | ClientMessage::ListFriends {} => {
+                // Shares the chat limit, as each change rewrites the file
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                let list = match &incoming {
+                    ClientMessage::AddFriend { name } => server.add_friend(connection, name),
+                    ClientMessage::RemoveFriend { name } => server.remove_friend(connection, name),
+                    _ => server.friend_list(connection),
+                };
+                if let Some(ServerMessage::FriendRejected { reason }) = &list {
+                    println!("🤝 [{}] Friend list unchanged: {}", addr, reason);
+                }
+                match list {
+                    Some(list) => reply(&list),
+                    None => Ok(None),
+                }
+            }
+            ClientMessage::DebugStats { enabled } => {
+                // Shares the chat limit, and switching on again does
+                // not bring the next frame forward
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                if enabled && self.debug.is_none() {
+                    println!("🐞 [{}] Debug stats on", addr);
+                    self.debug = Some(DebugWindow::new(&self.stats.snapshot(Instant::now())));
+                } else if !enabled && self.debug.take().is_some() {
+                    println!("🐞 [{}] Debug stats off", addr);
+                }
+                Ok(None)
+            }
+            ClientMessage::SetSubscriptions { mask } => {
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                self.subscriptions = Subscriptions::from_mask(mask);
+                println!("📻 [{}] Subscribed to {}", addr, self.subscriptions.0);
+                Ok(None)
+            }
+            ClientMessage::ResyncFrom { tick } => {
+                let resync = server.resync_message(tick, self.capabilities);
+                if let ServerMessage::Resync { diffs, .. } = &resync {
+                    println!("🔁 [{}] Resync from tick {} ({} diffs)", addr, tick, diffs.len());
+                } else {
+                    println!("🔁 [{}] Resync from tick {}: out of history, sending full state", addr, tick);
+                    if let Some(stream) = &mut self.quantized {
+                        stream.restart();
+                    }
+                }
+                reply(&resync)
+            }
+            ClientMessage::TimeSync { client_time_ms } => reply(&ServerMessage::TimeSync {
+                client_time_ms,
+                server_time_ms: server.server_time_ms(),
+                tick: server.current_tick(),
+            }),
+            ClientMessage::Keepalive { seq } => {
+                // Shares the chat limit, but is never slowed down:
+                // a late ack would only look like a bad connection
+                if limiter.chat.check(Instant::now()) == Decision::Abusive {
+                    return Ok(Some(rate_limited(addr)));
+                }
+                reply(&ServerMessage::KeepaliveAck { seq })
+            }
+            ClientMessage::KeepaliveAck { seq } => {
+                if let Some(tracker) = self.keepalive.as_mut() {
+                    tracker.acked(seq);
+                }
+                Ok(None)
+            }
+            ClientMessage::Position { seq, position, velocity, rotation } => {
+                let update = PositionUpdate { seq, position, velocity, rotation };
+                self.position(update)
+            }
+        }
+    }
+}
+
+impl PlayerShell<'_> {
+    /// What a decoded message is to the state machine: itself, unless it is
+    /// a `Join` over the chat limit, which is checked before its name is.
+    fn admit(&mut self, message: ClientMessage) -> Incoming {
+        self.cleanup.session.message();
+        if matches!(message, ClientMessage::Join { .. }) && self.limiter.chat.check(Instant::now()) == Decision::Abusive {
+            return rate_limited(self.addr);
+        }
+        Incoming::Message(message)
+    }
+
+    fn position(&mut self, mut update: PositionUpdate) -> Result<Option<Incoming>, Box<dyn std::error::Error>> {
+        let (server, addr, connection, format) = (self.server, self.addr, self.connection, self.format);
+        if let Err(reason) = update.validate(server.max_speed_at(&update.position)) {
+            println!("⚠️  [{}] Rejected position update: {}", addr, reason);
+            self.stats.record_position(false);
+            return Ok(None);
+        }
+        if self.activity.record_activity(Instant::now()) == Some(IdleEvent::BecameActive) {
+            server.set_player_idle(connection, false);
+        }
+        if server.is_landed(connection) {
+            self.stats.record_position(false);
+            return Ok(None);
+        }
+        if let (Some(seq), Some(highest)) = (update.seq, self.highest_seq)
+            && !seq_newer(seq, highest)
+        {
+            // Duplicate or out-of-order update
+            self.stats.record_position(false);
+            return Ok(None);
+        }
+        self.stats.record_position(true);
+        if update.seq.is_some() {
+            self.highest_seq = update.seq;
+        }
+        let mut teleported = false;
+        if let Some(correction) = self.bounds.check(&update.position, &self.spawn.position) {
+            teleported = matches!(correction, BoundsCorrection::Teleported(_));
+            let p = correction.position();
+            println!("🧱 [{}] Out of bounds, {} ({:.1}, {:.1}, {:.1})", addr,
+                     if teleported { "sent back to" } else { "clamped to" }, p.x, p.y, p.z);
+            update.position = p.clone();
+            let reply = ServerMessage::PositionCorrection { position: update.position.clone(), seq: update.seq, tick: server.current_tick() };
+            self.outbox.send_message(format, &reply)?;
+            let warning = ServerMessage::OutOfBounds { radius: server.world.radius, teleported };
+            self.outbox.send(encode_server_message(format, &warning)?)?;
+        }
+        let update = match server.hold_position(connection, update) {
+            Hold::Running(update) => update,
+            Hold::Held => return Ok(None),
+            Hold::Refused => {
+                self.outbox.send(encode_server_message(format, &server.paused_reply())?)?;
+                return Ok(None);
+            }
+        };
+        match self.limiter.positions.check(Instant::now()) {
+            Decision::Allowed => {
+                self.pending_position = None;
+                self.apply_position(update, teleported);
+            }
+            Decision::Limited => self.pending_position = Some((update, teleported)),
+            Decision::Abusive => return Ok(Some(rate_limited(addr))),
+        }
+        Ok(None)
+    }
+
+    /// Applies a position update, counting the distance flown unless it
+    /// sent the player back to spawn.
+    fn apply_position(&mut self, update: PositionUpdate, teleported: bool) {
+        let to = update.position.clone();
+        if let Some(from) = self.server.update_player_position(self.connection, update)
+            && !teleported
+        {
+            self.cleanup.session.moved(&from, &to);
+        }
+    }
+}
+
+/// Sums up the player's session and removes them when the connection ends,
+/// however it ends; `reason` stays `Error` unless the loop says otherwise.
+struct PlayerCleanup<'a> {
+    server: &'a GameServer,
+    connection: ConnectionId,
+    session: SessionTracker,
+    reason: DisconnectReason,
+}
+
+impl Drop for PlayerCleanup<'_> {
+    fn drop(&mut self) {
+        self.server.end_session(self.connection, &self.session, self.reason, Instant::now());
+        self.server.remove_player(self.connection);
+    }
+}
+
+/// Runs a spectator session: the join snapshot and every broadcast, but no
+/// player, so nothing the client sends can change the world.
+async fn spectate<S>(
+    ws_stream: WebSocketStream<S>,
+    addr: SocketAddr,
+    server: GameServer,
+    format: WireFormat,
+    capabilities: Capabilities,
+    subscriptions: Subscriptions,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut write, mut read) = ws_stream.split();
+    let mut broadcast_rx = server.broadcast_tx.subscribe();
+
+    let stats = Arc::new(ConnectionStats::new(Instant::now()));
+    let kick = Arc::new(Notify::new());
+    let joining = Connection { addr, stats: stats.clone(), kick: kick.clone() };
+    let connection = match server.add_spectator(joining) {
+        Ok(connection) => connection,
+        Err(rejection) => {
+            println!("🚫 [{}] Spectator turned away: {}", addr, rejection);
+            for frame in rejection.error_code().messages(format, &rejection.to_string())? {
+                write.send(seal_if_asked(frame, capabilities)).await?;
+            }
+            return Ok(());
+        }
+    };
+    println!("👁️  [{}] Spectating", addr);
+    let cleanup = SpectatorCleanup { server: &server, connection };
+
+    let (queue, queued) = mpsc::channel(SEND_QUEUE_CAPACITY);
+    let outbox = Outbox::new(queue, stats.clone(), capabilities.contains(Capabilities::CRC));
+    let write = CountingSink::new(write, stats.clone());
+    let mut writer = tokio::spawn(write_queued(write, queued, server.write_timeout, stats.clone()));
+    let mut writer_finished = false;
+
+    send_spectator_join_messages(&outbox, &server, addr, format, subscriptions)?;
+
+    // Spectators only ask questions; they share the chat limit for the costly ones
+    let settings = server.live_settings();
+    let mut bandwidth = BandwidthGovernor::new(settings.bandwidth_budget, 0, Instant::now());
+    let mut shell = SpectatorShell {
+        server: &server,
+        addr,
+        capabilities,
+        subscriptions,
+        format,
+        outbox,
+        limiter: ConnectionLimiter::new(&settings.rate_limits, Instant::now()),
+        keepalive: capabilities.contains(Capabilities::KEEPALIVE).then(|| KeepaliveTracker::new(server.keepalive, Instant::now())),
+        quantized: capabilities.contains(Capabilities::QUANTIZED).then(|| QuantizedStream::new(server.quantization)),
+    };
+    let mut shutdown = server.shutdown.subscribe();
+    let mut keepalive_check = keepalive_interval(server.keepalive);
+    let mut state = ConnectionState::new(connection_state::Role::Spectator { warned: false });
+
+    while !state.is_finished() {
+        let incoming = tokio::select! {
+            msg = read.next() => {
+                if let (Some(Ok(_)), Some(tracker)) = (&msg, shell.keepalive.as_mut()) {
+                    tracker.heard(Instant::now());
+                }
+                match msg {
+                    Some(Ok(msg @ (Message::Text(_) | Message::Binary(_)))) => {
+                        stats.record_received(msg.len(), Instant::now());
+                        match decode_incoming(&shell.outbox, addr, &msg, capabilities, shell.format)? {
+                            Some(incoming) => incoming,
+                            None => continue,
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) => Incoming::ClientClosed,
+                    Some(Err(_)) | None => Incoming::ConnectionLost,
+                    Some(Ok(Message::Ping(data))) => {
+                        shell.outbox.send(Message::Pong(data))?;
+                        continue;
+                    }
+                    _ => continue,
+                }
+            }
+
+            broadcast = broadcast_rx.recv() => {
+                if let Ok(broadcast) = broadcast {
+                    if let Some(level) = bandwidth.observe(stats.bytes_sent(), Instant::now()) {
+                        stats.set_bandwidth_level(level);
+                    }
+                    if !broadcast.is_for(connection)
+                        || broadcast.state_tick().is_some_and(|tick| !shell.subscriptions.contains(Subscriptions::STATE)
+                            || !bandwidth.wants_state(tick) || !shell.outbox.admit_state(tick))
+                    {
+                        continue;
+                    }
+                    for frame in broadcast_frames(&broadcast, shell.quantized.as_mut(), shell.format, capabilities, shell.subscriptions)? {
+                        shell.outbox.send(frame)?;
+                    }
+                }
+                continue;
+            }
+
+            finished = &mut writer => {
+                writer_finished = true;
+                if let Ok(Err(reason)) = finished {
+                    println!("🐢 [{}] Disconnecting spectator: {}", addr, reason);
+                }
+                Incoming::WriterGone
+            }
+
+            _ = keepalive_check.tick(), if shell.keepalive.is_some() => {
+                let Some(tracker) = shell.keepalive.as_mut() else { continue };
+                match send_keepalive(tracker, &shell.outbox, addr, shell.format)? {
+                    Some(timed_out) => timed_out,
+                    None => continue,
+                }
+            }
+
+            _ = kick.notified() => Incoming::Kicked,
+
+            _ = shutdown.wait_for(|stopping| *stopping) => Incoming::ShuttingDown { reconnect_to: server.handover.borrow().clone() },
+        };
+        state = shell.drive(state, incoming)?;
+    }
+
+    drop(cleanup);
+    drop(shell);
+    if !writer_finished && tokio::time::timeout(server.write_timeout, &mut writer).await.is_err() {
+        writer.abort();
+    }
+    Ok(())
+}
+
+/// What a spectator's connection keeps between messages.
+struct SpectatorShell<'a> {
+    server: &'a GameServer,
+    addr: SocketAddr,
+    capabilities: Capabilities,
+    subscriptions: Subscriptions,
+    format: WireFormat,
+    outbox: Outbox,
+    limiter: ConnectionLimiter,
+    keepalive: Option<KeepaliveTracker>,
+    quantized: Option<QuantizedStream>,  // as for players
+}
+
+impl Shell for SpectatorShell<'_> {
+    fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    fn format(&self) -> WireFormat {
+        self.format
+    }
+
+    fn switch_format(&mut self, format: WireFormat) -> Result<(), Box<dyn std::error::Error>> {
+        self.format = format;
+        if let Some(stream) = &mut self.quantized {
+            stream.restart();
+        }
+        send_spectator_join_messages(&self.outbox, self.server, self.addr, format, self.subscriptions)
+    }
+
+    fn dispatch(&mut self, incoming: ClientMessage) -> Result<Option<Incoming>, Box<dyn std::error::Error>> {
+        let server = self.server;
+        let limiter = &mut self.limiter;
+        let reply = match incoming {
+            ClientMessage::TimeSync { client_time_ms } => ServerMessage::TimeSync {
+                client_time_ms,
+                server_time_ms: server.server_time_ms(),
+                tick: server.current_tick(),
+            },
+            ClientMessage::ResyncFrom { tick } => {
+                let resync = server.resync_message(tick, self.capabilities);
+                if let (Some(stream), ServerMessage::State { .. }) = (&mut self.quantized, &resync) {
+                    stream.restart();
+                }
+                resync
+            }
+            ClientMessage::Keepalive { seq } => match limiter.chat.check(Instant::now()) {
+                Decision::Abusive => return Ok(Some(rate_limited(self.addr))),
+                _ => ServerMessage::KeepaliveAck { seq },
+            },
+            ClientMessage::KeepaliveAck { seq } => {
+                if let Some(tracker) = self.keepalive.as_mut() {
+                    tracker.acked(seq);
+                }
+                return Ok(None);
+            }
+            ClientMessage::SetSubscriptions { mask } => match limiter.chat.check(Instant::now()) {
+                Decision::Abusive => return Ok(Some(rate_limited(self.addr))),
+                _ => {
+                    self.subscriptions = Subscriptions::from_mask(mask);
+                    return Ok(None);
+                }
+            },
+            ClientMessage::QueryPlanets { center, radius, max_results } => match limiter.chat.check(Instant::now()) {
+                Decision::Allowed => server.query_planets(&center, radius, max_results)
+                    .unwrap_or_else(|reason| localized(MessageCode::PlanetQueryRejected { reason })),
+                Decision::Limited => return Ok(None),
+                Decision::Abusive => return Ok(Some(rate_limited(self.addr))),
+            },
+            ClientMessage::QueryPlanetsFiltered { center, module_type, min_size, max_size, owned_by, unclaimed_only, sort, limit, cursor, name } => {
+                match limiter.chat.check(Instant::now()) {
+                    Decision::Allowed => {
+                        let filter = PlanetFilter { module_type, min_size, max_size, owned_by, unclaimed_only, name };
+                        server.query_planets_filtered(&center, &filter, sort, limit, cursor)
+                            .unwrap_or_else(|reason| localized(MessageCode::PlanetQueryRejected { reason }))
+                    }
+                    Decision::Limited => return Ok(None),
+                    Decision::Abusive => return Ok(Some(rate_limited(self.addr))),
+                }
+            }
+            ClientMessage::QueryTrail { player_id } => match limiter.chat.check(Instant::now()) {
+                Decision::Allowed => server.query_trail(player_id)
+                    .unwrap_or_else(|reason| localized(MessageCode::TrailQueryRejected { reason })),
+                Decision::Limited => return Ok(None),
+                Decision::Abusive => return Ok(Some(rate_limited(self.addr))),
+            },
+            // The state machine only passes on what `is_spectator_message` allows
+            _ => return Ok(None),
+        };
+        self.outbox.send(encode_server_message(self.format, &reply)?)?;
+        Ok(None)
+    }
+}
+
+/// Streams the journal to a follower until it takes over or goes away
+/// (see `replication`).
+async fn feed_follower<S>(
+    ws_stream: WebSocketStream<S>,
+    addr: SocketAddr,
+    server: GameServer,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut write, mut read) = ws_stream.split();
+    if server.is_standby() {
+        println!("🚫 [{}] Follower turned away: on standby", addr);
+        for frame in ErrorCode::Standby.messages(WireFormat::default(), "on standby")? {
+            write.send(frame).await?;
+        }
+        return Ok(());
+    }
+    let (world, mut records) = server.follow();
+    println!("🪞 [{}] Follower connected at tick {}", addr, world.tick);
+    let send = |record: &JournalRecord| bincode::serialize(record).map(|bytes| Message::Binary(bytes.into()));
+    tokio::time::timeout(server.write_timeout, write.send(send(&world)?)).await??;
+    let mut shutdown = server.shutdown.subscribe();
+    let stopping = async move {
+        let _ = shutdown.wait_for(|stopping| *stopping).await;
+    };
+    tokio::pin!(stopping);
+
+    let shutting_down = loop {
+        tokio::select! {
+            record = records.recv() => match record {
+                Ok(record) => tokio::time::timeout(server.write_timeout, write.send(send(&record)?)).await??,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    // It starts over from a new copy when it reconnects
+                    println!("🐢 [{}] Disconnecting follower: {} records behind", addr, missed);
+                    let _ = write.send(Message::Close(None)).await;
+                    break false;
+                }
+                Err(broadcast::error::RecvError::Closed) => break false,
+            },
+
+            msg = read.next() => match msg {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(FollowerMessage::HandOver { to }) => {
+                        server.hand_over(to);
+                        break false;
+                    }
+                    Err(e) => println!("❓ [{}] Ignoring a follower message: {}", addr, e),
+                },
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break false,
+            },
+
+            _ = &mut stopping => break true,
+        }
+    };
+    if shutting_down {
+        for frame in ErrorCode::ShuttingDown.messages(WireFormat::default(), "server shutting down")? {
+            write.send(frame).await?;
+        }
+    }
+    println!("🪞 [{}] Follower disconnected", addr);
+    Ok(())
+}
+
+/// Removes the spectator when the connection ends, however it ends.
+struct SpectatorCleanup<'a> {
+    server: &'a GameServer,
+    connection: ConnectionId,
+}
+
+impl Drop for SpectatorCleanup<'_> {
+    fn drop(&mut self) {
+        self.server.remove_spectator(self.connection);
+    }
+}
+
+#[derive(Debug)]
+struct SendQueueFull;
+
+impl std::fmt::Display for SendQueueFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "send queue full ({} messages)", SEND_QUEUE_CAPACITY)
+    }
+}
+
+impl std::error::Error for SendQueueFull {}
+
+/// The sending end of a connection's write queue.
+struct Outbox {
+    queue: mpsc::Sender<Message>,
+    stats: Arc<ConnectionStats>,
+    crc: bool,  // seal binary frames (see `integrity`)
+    sequencer: Mutex<Sequencer>,  // keeps stale States out (see `ordering`)
+}
+
+impl Outbox {
+    fn new(queue: mpsc::Sender<Message>, stats: Arc<ConnectionStats>, crc: bool) -> Self {
+        Outbox { queue, stats, crc, sequencer: Mutex::new(Sequencer::new()) }
+    }
+
+    /// Whether a State built at `tick` is still news to this connection,
+    /// noting it as sent if so (see `ordering`).
+    fn admit_state(&self, tick: u64) -> bool {
+        self.sequencer.lock().unwrap().admit_state(tick)
+    }
+
+    /// Queues an encoded State built at `tick`, unless it is stale.
+    fn send_state(&self, tick: u64, state: Message) -> Result<(), SendQueueFull> {
+        if !self.admit_state(tick) {
+            return Ok(());
+        }
+        self.send(state)
+    }
+
+    /// Queues `message`, unless it is a State, Resync or correction older
+    /// than what this connection already has.
+    fn send_message(&self, format: WireFormat, message: &ServerMessage) -> Result<(), Box<dyn std::error::Error>> {
+        let news = match message {
+            ServerMessage::State { tick, .. } | ServerMessage::Resync { tick, .. } | ServerMessage::StateDelta { tick, .. } => self.admit_state(*tick),
+            ServerMessage::PositionCorrection { tick, .. } => self.sequencer.lock().unwrap().admit_correction(*tick),
+            _ => true,
+        };
+        if news {
+            self.send(encode_server_message(format, message)?)?;
+        }
+        Ok(())
+    }
+
+    /// Queues a message without waiting for the client. Once the writer has
+    /// stopped, messages are dropped; the connection loop sees it finish.
+    fn send(&self, message: Message) -> Result<(), SendQueueFull> {
+        let message = if self.crc { integrity::seal_message(message) } else { message };
+        match self.queue.try_send(message) {
+            Err(TrySendError::Full(_)) => return Err(SendQueueFull),
+            Err(TrySendError::Closed(_)) => return Ok(()),
+            Ok(()) => {}
+        }
+        self.stats.set_send_queue_depth(self.queue.max_capacity() - self.queue.capacity());
+        Ok(())
+    }
+
+    /// Tells the client why in an `Error` message, then closes the connection.
+    fn close(&self, format: WireFormat, code: ErrorCode, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+        for frame in code.messages(format, message)? {
+            self.send(frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Seals a binary frame for a client with the CRC capability.
+/// `name`'s discoveries, empty ones the first time. Position updates near a
+/// planet come here every time, so the shared key is only made for a name
+/// not seen before.
+fn discoveries_of<'a>(discoveries: &'a mut HashMap<Arc<str>, Discoveries>, name: &str) -> &'a mut Discoveries {
+    let key = normalize_name(name);
+    if !discoveries.contains_key(key.as_str()) {
+        discoveries.insert(key.as_str().into(), Discoveries::default());
+    }
+    discoveries.get_mut(key.as_str()).expect("inserted above")
+}
+
+/// What to send a connection of `broadcast`: for a QUANTIZED client, the
+/// next `StateDelta` of its own `stream` in place of a State (see
+/// `quantize`), otherwise the frames every client like it gets.
+fn broadcast_frames(
+    broadcast: &BroadcastFrame,
+    stream: Option<&mut QuantizedStream>,
+    format: WireFormat,
+    capabilities: Capabilities,
+    subscriptions: Subscriptions,
+) -> Result<Vec<Message>, EncodeError> {
+    match (stream, broadcast.state()) {
+        (Some(stream), Some((tick, server_time_ms, state))) => Ok(vec![encode_server_message(format, &stream.next(tick, server_time_ms, state))?]),
+        _ => Ok(broadcast.frames_for(format, capabilities, subscriptions)),
+    }
+}
+
+fn seal_if_asked(message: Message, capabilities: Capabilities) -> Message {
+    if capabilities.contains(Capabilities::CRC) { integrity::seal_message(message) } else { message }
+}
+
+/// Decodes a client frame, checking its CRC if the client has the CRC
+/// capability.
+fn decode_frame(message: &Message, capabilities: Capabilities) -> Result<ClientMessage, DecodeError> {
+    if capabilities.contains(Capabilities::CRC) { decode_sealed_client_message(message) } else { decode_client_message(message) }
+}
+
+/// Counts a frame that failed its CRC and tells the client, which stays
+/// connected: the damage may be the link's, not the client's.
+fn report_corrupt_frame(outbox: &Outbox, addr: SocketAddr, format: WireFormat, corrupt: CorruptFrame) -> Result<(), Box<dyn std::error::Error>> {
+    println!("🧮 [{}] Dropped a {}", addr, corrupt);
+    outbox.stats.record_corrupt_frame();
+    let reply = ServerMessage::CorruptFrame { expected: corrupt.expected, got: corrupt.got };
+    outbox.send(encode_server_message(format, &reply)?)?;
+    Ok(())
+}
+
+/// Decodes a client frame into what it is to the state machine, dealing
+/// with frames that are dropped without it: `None` for those.
+fn decode_incoming(
+    outbox: &Outbox,
+    addr: SocketAddr,
+    msg: &Message,
+    capabilities: Capabilities,
+    format: WireFormat,
+) -> Result<Option<Incoming>, Box<dyn std::error::Error>> {
+    let incoming = match (decode_frame(msg, capabilities), msg) {
+        (Ok(message), _) => Incoming::Message(message),
+        // Oversized frames are rejected before their contents are touched
+        (Err(DecodeError::TooLarge { len, limit }), _) => {
+            println!("🚫 [{}] Frame of {} bytes exceeds limit of {} bytes", addr, len, limit);
+            Incoming::FrameTooLarge
+        }
+        (Err(DecodeError::Corrupt(corrupt)), _) => {
+            report_corrupt_frame(outbox, addr, format, corrupt)?;
+            return Ok(None);
+        }
+        // Binary-mode text is chat unless it is a JSON client message
+        (Err(DecodeError::Json(_)), Message::Text(text)) if format == WireFormat::Binary => {
+            Incoming::Message(ClientMessage::Chat { text: text.to_string(), client_ref: None })
+        }
+        (Err(DecodeError::Json(e)), _) => {
+            println!("⚠️  [{}] Invalid JSON message: {}", addr, e);
+            Incoming::InvalidJson(e.to_string())
+        }
+        (Err(e), _) => {
+            println!("📦 [{}] Received binary data ({} bytes) - {}", addr, msg.len(), e);
+            return Ok(None);
+        }
+    };
+    Ok(Some(incoming))
+}
+
+/// Writes queued messages until the queue closes, giving up on the first
+/// write that takes longer than `timeout`.
+async fn write_queued<W>(
+    mut write: W,
+    mut queued: mpsc::Receiver<Message>,
+    timeout: Duration,
+    stats: Arc<ConnectionStats>,
+) -> Result<(), String>
+where
+    W: Sink<Message> + Unpin,
+    W::Error: std::fmt::Display,
+{
+    while let Some(message) = queued.recv().await {
+        stats.set_send_queue_depth(queued.len());
+        match tokio::time::timeout(timeout, write.send(message)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(format!("write failed: {}", e)),
+            Err(_) => return Err(format!("write timed out after {:?}", timeout)),
+        }
+    }
+    Ok(())
+}
+
+/// Sends the join snapshot (idle players included), the join ack, the
+/// message of the day, the welcome notice and the ambient events under way,
+/// leaving out what the client is not subscribed to.
+fn send_join_messages(
+    outbox: &Outbox,
+    server: &GameServer,
+    addr: std::net::SocketAddr,
+    player: &Player,
+    spawn: &Spawn,
+    format: WireFormat,
+    subscriptions: Subscriptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if subscriptions.contains(Subscriptions::STATE) {
+        let (tick, state) = server.encode_snapshot(format)?;
+        println!("📦 Sending initial game state to {} ({} bytes, {:?})", addr, state.len(), format);
+        outbox.send_state(tick, state)?;
+    }
+
+    let joined = ServerMessage::Joined {
+        player_id: player.id,
+        spawn: spawn.position.clone(),
+        spawn_planet_id: spawn.planet_id,
+    };
+    outbox.send(encode_server_message(format, &joined)?)?;
+    if let Some(history) = server.join_chat_history().filter(|history| subscriptions.wants(history)) {
+        outbox.send(encode_server_message(format, &history)?)?;
+    }
+    if let Some(motd) = server.motd_for(&player.name).filter(|motd| subscriptions.wants(motd)) {
+        outbox.send(encode_server_message(format, &motd)?)?;
+    }
+
+    let welcome = localized(MessageCode::Welcome {});
+    outbox.send(encode_server_message(format, &welcome)?)?;
+    if server.is_paused() && subscriptions.contains(Subscriptions::WORLD) {
+        let paused = ServerMessage::PauseChanged { paused: true, tick: server.current_tick() };
+        outbox.send(encode_server_message(format, &paused)?)?;
+    }
+    send_ambient_events(outbox, server, format, subscriptions)
+}
+
+/// Sends a spectator the join snapshot, the welcome notice and the ambient
+/// events under way, as far as it is subscribed to them; there is no join
+/// ack, since no player joined.
+fn send_spectator_join_messages(
+    outbox: &Outbox,
+    server: &GameServer,
+    addr: std::net::SocketAddr,
+    format: WireFormat,
+    subscriptions: Subscriptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if subscriptions.contains(Subscriptions::STATE) {
+        let (tick, state) = server.encode_snapshot(format)?;
+        println!("📦 Sending initial game state to spectator {} ({} bytes, {:?})", addr, state.len(), format);
+        outbox.send_state(tick, state)?;
+    }
+
+    let welcome = localized(MessageCode::WelcomeSpectator {});
+    outbox.send(encode_server_message(format, &welcome)?)?;
+    send_ambient_events(outbox, server, format, subscriptions)
+}
+
+/// Sends a `WorldEvent` for each ambient event under way, so late joiners
+/// see them.
+fn send_ambient_events(outbox: &Outbox, server: &GameServer, format: WireFormat, subscriptions: Subscriptions) -> Result<(), Box<dyn std::error::Error>> {
+    if !subscriptions.contains(Subscriptions::WORLD) {
+        return Ok(());
+    }
+    for event in server.ambient_events() {
+        outbox.send(encode_server_message(format, &ServerMessage::WorldEvent { event, active: true })?)?;
+    }
+    Ok(())
+}
+
+/// Milliseconds since the Unix epoch, by the wall clock.
+pub(crate) fn unix_ms() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Compares secrets without an early exit on the first differing byte.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Logs a connection over its rate limit, which the state machine closes.
+fn rate_limited(addr: SocketAddr) -> Incoming {
+    println!("🚫 [{}] Disconnecting: rate limit exceeded", addr);
+    Incoming::RateLimited
+}
+
+/// Checks on the keepalives a few times per interval, so one goes out soon
+/// after the client falls silent.
+fn keepalive_interval(config: KeepaliveConfig) -> tokio::time::Interval {
+    let mut check = tokio::time::interval((config.interval / 4).max(Duration::from_millis(1)));
+    check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    check
+}
+
+/// Sends the keepalive that is due, if any. A `KeepaliveTimeout` once the
+/// client has left too many unanswered.
+fn send_keepalive(tracker: &mut KeepaliveTracker, outbox: &Outbox, addr: SocketAddr, format: WireFormat) -> Result<Option<Incoming>, Box<dyn std::error::Error>> {
+    match tracker.poll(Instant::now()) {
+        Some(KeepaliveAction::Send { seq }) => outbox.send(encode_server_message(format, &ServerMessage::Keepalive { seq })?)?,
+        Some(KeepaliveAction::TimedOut) => {
+            println!("💔 [{}] Disconnecting: {} keepalives unanswered", addr, tracker.missed());
+            return Ok(Some(Incoming::KeepaliveTimeout));
+        }
+        None => {}
+    }
+    Ok(None)
+}
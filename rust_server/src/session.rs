@@ -0,0 +1,118 @@
+use std::time::{Duration, Instant};
+use crate::protocol::Position;
+
+/*
+What a player did between joining and leaving.
+
+Every player connection keeps a `SessionTracker`. It counts the messages
+the client sends and adds up the distance of every position update the
+server applies, from where the player was to where the update puts them.
+Respawns, teleports, landings and world regenerations move a player
+between updates, never within one, so they never count as flown; neither
+does being sent back to spawn for leaving the world. When the connection
+ends, however it ends, the tracker is finished into a `SessionSummary`
+with the XP and discoveries the player gained and why they left. The
+summary is logged and kept by name until their next session ends, for the
+admin `whois` command.
+
+`resources_mined` counts the units taken with `Mine` (see
+`module_effects`). Like the rate limiter, everything takes an explicit
+`now`.
+*/
+
+/// Why a player's connection ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Closed,        // the client sent a close frame
+    Timeout,       // idle, keepalives unanswered, or too slow to read
+    Kicked,
+    RateLimited,
+    ShuttingDown,
+    Error,         // a protocol violation or a broken connection
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DisconnectReason::Closed => "clean close",
+            DisconnectReason::Timeout => "timeout",
+            DisconnectReason::Kicked => "kick",
+            DisconnectReason::RateLimited => "rate limit",
+            DisconnectReason::ShuttingDown => "server shutdown",
+            DisconnectReason::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub name: String,
+    pub duration: Duration,
+    pub distance: f64,
+    pub messages: u64,  // sent by the client
+    pub planets_discovered: usize,
+    pub xp_gained: u32,
+    pub resources_mined: u64,
+    pub reason: DisconnectReason,
+}
+
+impl std::fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} played {:.0}s, flew {:.1}, sent {} message(s), discovered {} planet(s), gained {} XP, mined {}, left by {}",
+               self.name, self.duration.as_secs_f64(), self.distance, self.messages, self.planets_discovered,
+               self.xp_gained, self.resources_mined, self.reason)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionTracker {
+    started: Instant,
+    xp: u32,          // at the start
+    discovered: usize,
+    distance: f64,
+    messages: u64,
+    mined: u64,
+}
+
+impl SessionTracker {
+    /// Starts a session for a player who already has `xp` and has
+    /// discovered `discovered` planets.
+    pub fn new(xp: u32, discovered: usize, now: Instant) -> Self {
+        SessionTracker { started: now, xp, discovered, distance: 0.0, messages: 0, mined: 0 }
+    }
+
+    pub fn message(&mut self) {
+        self.messages += 1;
+    }
+
+    /// Records an applied position update that took the player from `from`
+    /// to `to`.
+    pub fn moved(&mut self, from: &Position, to: &Position) {
+        let d = |a: f32, b: f32| (b as f64 - a as f64).powi(2);
+        self.distance += (d(from.x, to.x) + d(from.y, to.y) + d(from.z, to.z)).sqrt();
+    }
+
+    pub fn mined(&mut self, units: u64) {
+        self.mined += units;
+    }
+
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+
+    /// The summary of a session that ends at `now`, the player by then
+    /// having `xp` and `discovered` planets.
+    pub fn finish(&self, name: &str, xp: u32, discovered: usize, reason: DisconnectReason, now: Instant) -> SessionSummary {
+        SessionSummary {
+            name: name.to_string(),
+            duration: now.saturating_duration_since(self.started),
+            distance: self.distance,
+            messages: self.messages,
+            // A regenerated world forgets discovered planets, but not XP
+            planets_discovered: discovered.saturating_sub(self.discovered),
+            xp_gained: xp.saturating_sub(self.xp),
+            resources_mined: self.mined,
+            reason,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Serialize, Serializer};
+use std::sync::Arc;
+use crate::protocol::{Belt, GameState, Planet, Player, Position};
+
+/*
+A consistent view of the whole world, cheap enough to take while the game
+runs, for everything that reads the world at length: saving, the journal,
+`--export-world` and the map renderers.
+
+The server keeps its planets and belts in an `Arc<GameState>` that edits
+replace copy-on-write (`Arc::make_mut`), so a snapshot shares the current
+one rather than copying it; an edit made while the snapshot is alive
+copies the planets for itself and leaves the snapshot's alone. The players
+are gathered from their shards one shard at a time (see `players`), the
+only copying a snapshot does. No game lock is held once it is taken, so a
+save can serialize it on another thread for as long as it likes.
+
+`WorldView` is what the renderers and `WorldFile` need of a world, so they
+take a snapshot or a plain `GameState` alike. A snapshot serializes exactly
+as the `GameState` it stands for, so `to_json` writes what `save_world`
+would and `load_world` reads it back.
+*/
+
+/// The planets, belts and players of a world, read-only.
+pub trait WorldView {
+    fn planets(&self) -> &[Planet];
+    fn players(&self) -> &[Player];
+    fn belts(&self) -> &[Belt];
+    fn initial_player_location(&self) -> &Position;
+    fn world_time(&self) -> f32;
+}
+
+impl WorldView for GameState {
+    fn planets(&self) -> &[Planet] {
+        &self.planets
+    }
+
+    fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    fn belts(&self) -> &[Belt] {
+        &self.belts
+    }
+
+    fn initial_player_location(&self) -> &Position {
+        &self.initial_player_location
+    }
+
+    fn world_time(&self) -> f32 {
+        self.world_time
+    }
+}
+
+/// The world at one tick: the planets shared with the server, the players
+/// copied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameStateSnapshot {
+    pub tick: u64,
+    world: Arc<GameState>,  // its players are ignored
+    players: Vec<Player>,
+    world_time: f32,
+}
+
+impl GameStateSnapshot {
+    /// `world`'s planets and belts with `players`, in player id order.
+    pub fn new(tick: u64, world: Arc<GameState>, players: Vec<Player>, world_time: f32) -> Self {
+        GameStateSnapshot { tick, world, players, world_time }
+    }
+
+    /// A full copy, for what needs a `GameState` of its own.
+    pub fn to_state(&self) -> GameState {
+        let mut state = (*self.world).clone();
+        state.players = self.players.clone();
+        state.world_time = self.world_time;
+        state
+    }
+
+    /// The snapshot as a saved world (see `world::save_world`).
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl WorldView for GameStateSnapshot {
+    fn planets(&self) -> &[Planet] {
+        &self.world.planets
+    }
+
+    fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    fn belts(&self) -> &[Belt] {
+        &self.world.belts
+    }
+
+    fn initial_player_location(&self) -> &Position {
+        &self.world.initial_player_location
+    }
+
+    fn world_time(&self) -> f32 {
+        self.world_time
+    }
+}
+
+/// `GameState`'s fields, borrowed and in the same order.
+#[derive(Serialize)]
+struct SavedState<'a> {
+    planets: &'a [Planet],
+    players: &'a [Player],
+    initial_player_location: &'a Position,
+    belts: &'a [Belt],
+    world_time: f32,
+}
+
+impl Serialize for GameStateSnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SavedState {
+            planets: self.planets(),
+            players: &self.players,
+            initial_player_location: self.initial_player_location(),
+            belts: self.belts(),
+            world_time: self.world_time,
+        }.serialize(serializer)
+    }
+}
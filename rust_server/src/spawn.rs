@@ -0,0 +1,131 @@
+use crate::appearance::{fnv1a, FNV_OFFSET};
+use crate::protocol::{normalize_name, Planet, PlanetCount, Position};
+
+/*
+Spawn point selection.
+
+Each new player spawns next to the least loaded planet (ties go to the
+earlier planet in the list). A planet's load is the larger of the players
+spawned at it and the players near it at the last count (see `population`),
+so players who flew off to a busy planet count there, and players who just
+spawned count before the next count sees them. Around a planet,
+spawn slots sit on rings in its horizontal plane: eight per ring, starting
+`SPAWN_CLEARANCE` above the surface, each further ring `SPAWN_SPACING`
+further out and rotated half a slot. A player takes the lowest slot not
+held by a connected player, so slots are reused as players leave and two
+connected players never share one. Without planets, players spawn on the
+same rings around the world's initial player location.
+
+Slots keep the count, but a burst of joins would still fill the first ring
+in order, so players are dispersed from their slot (`spawn_dispersion` in
+the config, `DEFAULT_SPAWN_DISPERSION` by default; 0 keeps the slots). The
+angle around the planet comes from a hash of the player's normalized name
+and a join nonce (the connection id), on the innermost ring; if that point
+is within the dispersion radius of a connected player another angle is
+tried, moving a ring out every `ATTEMPTS_PER_RING` attempts. After
+`MAX_SPAWN_ATTEMPTS` the player keeps their slot, and the server logs it.
+There is no spatial index, so each attempt checks every player; the same
+name and nonce always try the same points.
+*/
+
+/// Distance from a planet's surface to its innermost spawn ring.
+pub const SPAWN_CLEARANCE: f32 = 30.0;
+/// Distance between consecutive spawn rings.
+pub const SPAWN_SPACING: f32 = 20.0;
+const SLOTS_PER_RING: u32 = 8;
+/// How close two players may spawn, unless configured.
+pub const DEFAULT_SPAWN_DISPERSION: f32 = 10.0;
+/// Points tried around a planet before a player keeps their slot.
+pub const MAX_SPAWN_ATTEMPTS: u32 = 64;
+const ATTEMPTS_PER_RING: u32 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spawn {
+    pub planet_id: Option<u32>,
+    pub slot: u32,
+    pub position: Position,
+}
+
+/// Where slot `slot` is around a body of diameter `size` centred at `center`.
+pub fn slot_position(center: &Position, size: f32, slot: u32) -> Position {
+    let ring = slot / SLOTS_PER_RING;
+    let step = std::f32::consts::TAU / SLOTS_PER_RING as f32;
+    let angle = (slot % SLOTS_PER_RING) as f32 * step + (ring % 2) as f32 * step / 2.0;
+    let distance = size / 2.0 + SPAWN_CLEARANCE + ring as f32 * SPAWN_SPACING;
+    Position {
+        x: center.x + angle.cos() * distance,
+        y: center.y,
+        z: center.z + angle.sin() * distance,
+    }
+}
+
+/// The angle around their spawn planet, in radians, that attempt `attempt`
+/// puts a player called `name` at when joining with `nonce`.
+pub fn spawn_angle(name: &str, nonce: u64, attempt: u32) -> f32 {
+    let hash = fnv1a(fnv1a(fnv1a(FNV_OFFSET, normalize_name(name).as_bytes()), &nonce.to_le_bytes()), &attempt.to_le_bytes());
+    // FNV mixes upwards, so the high bits are the well-mixed ones
+    (hash >> 40) as f32 / (1u64 << 24) as f32 * std::f32::consts::TAU
+}
+
+/// The first point tried for a player called `name` joining with `nonce`
+/// around a body of diameter `size` at `center` that is at least `radius`
+/// from every position in `others`, if any is within `MAX_SPAWN_ATTEMPTS`.
+pub fn disperse<'a>(
+    center: &Position,
+    size: f32,
+    name: &str,
+    nonce: u64,
+    radius: f32,
+    others: impl IntoIterator<Item = &'a Position>,
+) -> Option<Position> {
+    let others: Vec<&Position> = others.into_iter().collect();
+    (0..MAX_SPAWN_ATTEMPTS)
+        .map(|attempt| {
+            let angle = spawn_angle(name, nonce, attempt);
+            let distance = size / 2.0 + SPAWN_CLEARANCE + (attempt / ATTEMPTS_PER_RING) as f32 * SPAWN_SPACING;
+            Position { x: center.x + angle.cos() * distance, y: center.y, z: center.z + angle.sin() * distance }
+        })
+        .find(|p| others.iter().all(|o| (p.x - o.x).powi(2) + (p.y - o.y).powi(2) + (p.z - o.z).powi(2) >= radius * radius))
+}
+
+/// Picks a spawn for a new player given the spawns of everyone connected.
+pub fn choose_spawn<'a>(planets: &[Planet], taken: impl IntoIterator<Item = &'a Spawn>, fallback: &Position) -> Spawn {
+    choose_spawn_balanced(planets, taken, &[], fallback)
+}
+
+/// Picks a spawn for a new player given the spawns of everyone connected
+/// and how many players are near each planet.
+pub fn choose_spawn_balanced<'a>(
+    planets: &[Planet],
+    taken: impl IntoIterator<Item = &'a Spawn>,
+    population: &[PlanetCount],
+    fallback: &Position,
+) -> Spawn {
+    let taken: Vec<&Spawn> = taken.into_iter().collect();
+    let occupants = |planet_id: Option<u32>| taken.iter().filter(move |s| s.planet_id == planet_id);
+    let near = |planet_id: u32| population.iter().find(|c| c.planet_id == planet_id).map_or(0, |c| c.population as usize);
+
+    let planet = planets.iter().min_by_key(|p| occupants(Some(p.id)).count().max(near(p.id)));
+    let planet_id = planet.map(|p| p.id);
+    let slot = (0..)
+        .find(|slot| !occupants(planet_id).any(|s| s.slot == *slot))
+        .expect("fewer players than slots");
+    let position = match planet {
+        Some(planet) => slot_position(&planet.position, planet.size, slot),
+        None => slot_position(fallback, 0.0, slot),
+    };
+    Spawn { planet_id, slot, position }
+}
+
+/// The first spawn slot around `planet` at least `SPAWN_SPACING` from every
+/// position in `others`, for players arriving by teleport.
+pub fn landing_position<'a>(planet: &Planet, others: impl IntoIterator<Item = &'a Position>) -> Position {
+    let others: Vec<&Position> = others.into_iter().collect();
+    let clear = |p: &Position| others.iter().all(|o| {
+        (p.x - o.x).powi(2) + (p.y - o.y).powi(2) + (p.z - o.z).powi(2) >= SPAWN_SPACING * SPAWN_SPACING
+    });
+    (0..)
+        .map(|slot| slot_position(&planet.position, planet.size, slot))
+        .find(clear)
+        .expect("fewer players than slots")
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+use crate::admin::PlanetLimits;
+use crate::bans::BanList;
+use crate::chat_history::ChatLog;
+#[cfg(feature = "cluster")]
+use crate::cluster::ClusterConfig;
+use crate::config::{ConfigSource, ServerConfig};
+use crate::friends::Friends;
+use crate::handshake::Cidr;
+use crate::lifetime::LifetimeStats;
+use crate::mutes::MuteList;
+use crate::protocol::GameState;
+use crate::roles::Roles;
+use crate::server::GameServer;
+use crate::teleport::TeleportConfig;
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsAcceptor};
+use crate::wallets::Wallets;
+use crate::webhooks::Webhooks;
+use crate::world::{self, WorldConfig};
+use crate::world_file::{WorldFile, WorldFileError};
+
+/*
+Starting the server from its configuration, in steps that `server --check`
+shares with the real thing:
+
+- `ServerConfig::read`: the defaults, the config file, then the flags,
+  failing only if the file cannot be read or parsed or a flag is malformed;
+- `ServerConfig::validate`: every problem with the settings (see
+  `ServerConfig::setting_problems`, palettes included), then everything
+  they name, read and checked: the saved or imported world, the TLS
+  certificate and key, the cluster URL, the trusted proxies, the ban
+  list, the roles and the friend lists. It reports every problem found
+  rather than the first, each with where it came from;
+- `ServerConfig::build`: the `GameServer`, from what `validate` read. It
+  cannot fail.
+
+So a config that passes `--check` starts, as far as those inputs go. The
+journal is opened, and the port bound, only by the real startup.
+
+`check` runs the first two and, with a cluster configured, connects to
+Redis once (see `ClusterConfig::check_reachable`). Startup itself does
+not wait for Redis, which it keeps retrying, so that check is `--check`'s
+alone. The binary prints the problems and exits 1 if there are any, 0
+otherwise, without binding the port or starting a single loop; deploy
+pipelines run it before restarting the service.
+*/
+
+/// Something wrong with the configuration, and where: a file, `config`
+/// for the settings, `command line` for the flags, or `cluster`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub source: String,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(source: impl fmt::Display, message: impl fmt::Display) -> Self {
+        Problem { source: source.to_string(), message: message.to_string() }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.source, self.message)
+    }
+}
+
+/// Everything `validate` read from disk, for `build`.
+pub struct Validated {
+    world_config: WorldConfig,
+    trusted_proxies: Vec<Cidr>,
+    world: Option<GameState>,
+    imported: Option<WorldFile>,
+    bans: Option<BanList>,
+    mutes: Option<MuteList>,
+    roles: Option<Roles>,
+    friends: Option<Friends>,
+    wallets: Option<Wallets>,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsAcceptor>,
+    #[cfg(feature = "cluster")]
+    cluster: Option<ClusterConfig>,
+}
+
+impl Validated {
+    /// The world's bounds, an imported world's radius included.
+    pub fn world_config(&self) -> &WorldConfig {
+        &self.world_config
+    }
+}
+
+/// Reads `path` whole, as a problem with it if it cannot be.
+fn read_file(path: &Path) -> Result<String, Problem> {
+    std::fs::read_to_string(path).map_err(|e| Problem::new(path.display(), format!("cannot read: {}", e)))
+}
+
+impl ServerConfig {
+    /// Defaults, then the config file, then the flags in `args`, unchecked.
+    pub fn read(args: &[String]) -> Result<ServerConfig, Problem> {
+        let mut config = match ServerConfig::config_path(args).map_err(|e| Problem::new("command line", e))? {
+            Some(path) => ServerConfig::from_toml(&read_file(&path)?).map_err(|e| Problem::new(path.display(), e.trim_end()))?,
+            None => ServerConfig::default(),
+        };
+        config.apply_flags(args).map_err(|e| Problem::new("command line", e))?;
+        Ok(config)
+    }
+
+    /// Checks the settings and reads everything they name; see the module
+    /// comment.
+    pub fn validate(&self) -> Result<Validated, Vec<Problem>> {
+        let mut problems: Vec<Problem> = self.setting_problems().into_iter().map(|e| Problem::new("config", e)).collect();
+
+        let mut trusted_proxies = Vec::new();
+        for proxy in &self.trusted_proxies {
+            match proxy.parse::<Cidr>() {
+                Ok(cidr) => trusted_proxies.push(cidr),
+                Err(e) => problems.push(Problem::new("config", format!("trusted_proxies: {}", e))),
+            }
+        }
+
+        let world = self.world.as_deref().and_then(|path| {
+            let loaded = read_file(path).and_then(|text| world::load_world(&text).map_err(|e| Problem::new(path.display(), e)));
+            loaded.map_err(|problem| problems.push(problem)).ok()
+        });
+        let imported = self.import_world.as_deref().and_then(|path| {
+            let text = read_file(path).map_err(|problem| problems.push(problem)).ok()?;
+            match WorldFile::parse(&text, &PlanetLimits { min_gap: self.min_planet_gap, ..PlanetLimits::default() }) {
+                Ok(file) => Some(file),
+                Err(WorldFileError::Json(e)) => {
+                    problems.push(Problem::new(path.display(), e));
+                    None
+                }
+                Err(WorldFileError::Invalid(found)) => {
+                    problems.extend(found.into_iter().map(|e| Problem::new(path.display(), e)));
+                    None
+                }
+            }
+        });
+
+        #[cfg(feature = "tls")]
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => tls::load_acceptor(cert, key).map_err(|e| problems.push(Problem::new("tls", e))).ok(),
+            _ => None,
+        };
+        #[cfg(not(feature = "tls"))]
+        if self.tls_cert.is_some() {
+            problems.push(Problem::new("config", "TLS requested but the server was built without the `tls` feature"));
+        }
+        #[cfg(feature = "cluster")]
+        let cluster = self.cluster.as_deref().and_then(|url| {
+            ClusterConfig::from_url(url, self.cluster_instance).map_err(|e| problems.push(Problem::new("config", e))).ok()
+        });
+        #[cfg(not(feature = "cluster"))]
+        if self.cluster.is_some() {
+            problems.push(Problem::new("config", "clustering requested but the server was built without the `cluster` feature"));
+        }
+
+        let bans = self.ban_file.as_deref().and_then(|path| {
+            BanList::load(path).map_err(|e| problems.push(Problem::new(path.display(), format!("cannot read: {}", e)))).ok()
+        });
+
+        let mutes = self.mute_file.as_deref().and_then(|path| {
+            MuteList::load(path).map_err(|e| problems.push(Problem::new(path.display(), format!("cannot read: {}", e)))).ok()
+        });
+
+        let roles = self.roles_file.as_deref().and_then(|path| {
+            Roles::load(path).map_err(|e| problems.push(Problem::new(path.display(), format!("cannot read: {}", e)))).ok()
+        });
+
+        let friends = self.friends_file.as_deref().and_then(|path| {
+            Friends::load(path).map_err(|e| problems.push(Problem::new(path.display(), format!("cannot read: {}", e)))).ok()
+        });
+
+        let wallets = self.wallet_file.as_deref().and_then(|path| {
+            Wallets::load(path).map_err(|e| problems.push(Problem::new(path.display(), format!("cannot read: {}", e)))).ok()
+        });
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        let mut world_config = self.world_config();
+        if let Some(file) = &imported {
+            world_config.radius = file.radius;
+        }
+        Ok(Validated {
+            world_config,
+            trusted_proxies,
+            world,
+            imported,
+            bans,
+            mutes,
+            roles,
+            friends,
+            wallets,
+            #[cfg(feature = "tls")]
+            tls,
+            #[cfg(feature = "cluster")]
+            cluster,
+        })
+    }
+
+    /// The server this configuration describes, from what `validate`
+    /// read; `source` is where it came from, for reloads.
+    pub fn build(&self, validated: Validated, source: ConfigSource) -> GameServer {
+        let live = self.live();
+        let mut game_server = GameServer::with_generator(self.world_layout.generator())
+            .with_world_config(validated.world_config)
+            .with_trusted_proxies(validated.trusted_proxies)
+            .with_accept_limits(self.accept_limits())
+            .with_max_speed(self.max_speed)
+            .with_spawn_dispersion(self.spawn_dispersion)
+            .with_planet_limits(self.planet_limits())
+            .with_rate_limits(live.rate_limits)
+            .with_idle_config(live.idle)
+            .with_hibernation(self.hibernation())
+            .with_paused_updates(self.paused_updates)
+            .with_broadcast_interval(live.broadcast_interval)
+            .with_max_players(live.max_players)
+            .with_max_spectators(live.max_spectators)
+            .with_max_connections_per_ip(live.max_connections_per_ip)
+            .with_bandwidth_budget(live.bandwidth_budget)
+            .with_trade_timeout(self.trade_offer_timeout())
+            .with_quantization(self.quantization())
+            .with_readiness(self.readiness())
+            .with_scheduled_announcements(self.scheduled_announcements())
+            .with_config_source(source);
+        if let (Some(state), Some(path)) = (validated.world, &self.world) {
+            game_server = game_server.with_world(state);
+            println!("🌍 Loaded world from {}", path.display());
+        } else if let (Some(file), Some(path)) = (validated.imported, &self.import_world) {
+            println!("🌍 Imported {} planets from {}", file.planets.len(), path.display());
+            game_server = game_server.with_world(file.into_state());
+        } else if let Some(seed) = self.world_seed {
+            println!("🌍 Generated {:?} world from seed {}", self.world_layout, seed);
+        }
+        #[cfg(feature = "cluster")]
+        if let Some(cluster) = validated.cluster {
+            let role = if cluster.is_primary() { "the primary" } else { "a secondary" };
+            println!("🔗 Joining the cluster at {} as instance {}, {}", cluster.addr, cluster.instance, role);
+            game_server = game_server.with_cluster(cluster);
+        }
+        if self.fake_players > 0 {
+            game_server = game_server.with_fake_players(self.fake_players);
+        }
+        if let Some(pruning) = self.pruning() {
+            game_server = game_server.with_pruning(pruning);
+            println!("🧹 Pruning added planets left unvisited for {}s", pruning.after.as_secs());
+        }
+        if self.trails {
+            game_server = game_server.with_trails(self.trail_length);
+            println!("🐾 Keeping trails of the last {} positions per player", self.trail_length);
+        }
+        if let Some(ambient) = self.ambient() {
+            game_server = game_server.with_ambient(ambient);
+            println!("🌠 Ambient events every {} tick(s), lasting {}", ambient.every_ticks, ambient.duration_ticks);
+        }
+        if self.casual {
+            game_server = game_server.with_teleport_config(TeleportConfig { casual: true, ..TeleportConfig::default() });
+            println!("🌀 Casual mode: players may teleport to any planet");
+        }
+        if let Some(token) = self.admin_token.clone() {
+            game_server = game_server.with_admin_token(token);
+            println!("🛠️  Remote admin commands enabled");
+        }
+        if let (Some(primary), Some(token)) = (&self.follow, &self.admin_token) {
+            let scheme = if self.tls_cert.is_some() { "wss" } else { "ws" };
+            let advertise = self.advertise.clone().unwrap_or_else(|| format!("{}://{}", scheme, self.bind));
+            println!("🪞 Standing by for {}; `promote` sends its clients to {}", primary, advertise);
+            game_server = game_server.with_standby(primary.clone(), token.clone(), advertise);
+        }
+        if let (Some(bans), Some(path)) = (validated.bans, &self.ban_file) {
+            println!("🚫 {} banned name(s) from {}", bans.names().len(), path.display());
+            game_server = game_server.with_bans(bans);
+        }
+        if let (Some(mutes), Some(path)) = (validated.mutes, &self.mute_file) {
+            println!("🔇 {} muted name(s) from {}", mutes.len(), path.display());
+            game_server = game_server.with_mutes(mutes);
+        }
+        if let (Some(roles), Some(path)) = (validated.roles, &self.roles_file) {
+            println!("👮 {} moderator(s) and owner(s) from {}", roles.profiles().len(), path.display());
+            game_server = game_server.with_roles(roles);
+        }
+        game_server.bootstrap_owners(&self.owners);
+        if let (Some(friends), Some(path)) = (validated.friends, &self.friends_file) {
+            println!("🤝 {} friend list(s) from {}", friends.len(), path.display());
+            game_server = game_server.with_friends(friends);
+        }
+        if let (Some(wallets), Some(path)) = (validated.wallets, &self.wallet_file) {
+            println!("💰 {} wallet(s) from {}", wallets.len(), path.display());
+            game_server = game_server.with_wallets(wallets);
+        }
+        if let Some(path) = &self.stats_file {
+            let stats = LifetimeStats::load(path, Instant::now());
+            println!("📈 Keeping lifetime stats in {}", path.display());
+            game_server = game_server.with_lifetime_stats(stats);
+        }
+        if let Some(path) = &self.chat_file {
+            let log = ChatLog::load(path);
+            println!("💬 Keeping chat history in {} ({} line(s) so far)", path.display(), log.len());
+            game_server = game_server.with_chat_log(log);
+        }
+        // The settings were checked with the rest
+        if let Ok(webhooks) = Webhooks::new(&self.webhook_settings())
+            && !webhooks.is_empty()
+        {
+            println!("📮 Posting events to {} webhook(s)", webhooks.len());
+            game_server = game_server.with_webhooks(webhooks);
+        }
+        if let Some(motd) = self.motd.clone() {
+            game_server = game_server.with_motd(motd);
+        }
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = validated.tls {
+            game_server = game_server.with_tls(acceptor);
+        }
+        game_server
+    }
+
+    /// Whether the services the configuration names answer; only a cluster's
+    /// Redis, for now.
+    pub async fn check_services(&self) -> Vec<Problem> {
+        #[cfg(feature = "cluster")]
+        if let Some(url) = &self.cluster
+            && let Ok(cluster) = ClusterConfig::from_url(url, self.cluster_instance)
+            && let Err(e) = cluster.check_reachable().await
+        {
+            return vec![Problem::new("cluster", e)];
+        }
+        Vec::new()
+    }
+}
+
+/// Every problem `server --check` finds with the configuration in `args`,
+/// none if the server would start.
+pub async fn check(args: &[String]) -> Vec<Problem> {
+    let config = match ServerConfig::read(args) {
+        Ok(config) => config,
+        Err(problem) => return vec![problem],
+    };
+    let mut problems = config.validate().err().unwrap_or_default();
+    problems.extend(config.check_services().await);
+    problems
+}
@@ -0,0 +1,233 @@
+use futures_util::Sink;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::tungstenite::Bytes;
+use crate::protocol::ServerMessage;
+
+/*
+Per-connection traffic counters for diagnosing lagging players.
+
+The connection task updates a `ConnectionStats` with relaxed atomics as it
+goes; the console `players -v` and admin `stats <id>` commands read a
+`StatsSnapshot` of it. Only text and binary frames count as messages, so
+pings, pongs and close frames are left out. A position update is rejected
+when it fails validation or is older than one already accepted; clamped and
+rate-limited updates still count as accepted. Corrupt frames are binary
+frames from a CRC client that failed their check (see `integrity`). The
+send-queue depth is how many messages are waiting for the connection's
+writer, which grows when a client cannot keep up. The bandwidth level is
+how far the connection's State rate has been cut to keep it within its
+budget (see `bandwidth`).
+
+A client may also watch its own counters: while it is subscribed, a
+`DebugWindow` turns a snapshot a second into a `DebugStats` frame covering
+the updates since the previous one. The round trip comes from a WebSocket
+ping sent with each frame, carrying the server time it was sent at, so it
+needs nothing from the client beyond the pong every client already sends.
+*/
+
+const NEVER: u64 = u64::MAX;
+
+#[derive(Debug)]
+pub struct ConnectionStats {
+    connected_at: Instant,
+    messages_received: AtomicU64,
+    messages_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    positions_accepted: AtomicU64,
+    positions_rejected: AtomicU64,
+    corrupt_frames: AtomicU64,
+    last_message_ms: AtomicU64,  // since `connected_at`, NEVER before the first
+    send_queue_depth: AtomicUsize,
+    bandwidth_level: AtomicU8,
+}
+
+/// The counters of one connection at one moment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub connected_for: Duration,
+    pub messages_received: u64,
+    pub messages_sent: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub positions_accepted: u64,
+    pub positions_rejected: u64,
+    pub corrupt_frames: u64,  // failed their CRC (see `integrity`)
+    pub last_message_age: Option<Duration>,
+    pub send_queue_depth: usize,
+    pub bandwidth_level: u8,  // see `bandwidth`
+}
+
+impl ConnectionStats {
+    pub fn new(now: Instant) -> Self {
+        ConnectionStats {
+            connected_at: now,
+            messages_received: AtomicU64::new(0),
+            messages_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            positions_accepted: AtomicU64::new(0),
+            positions_rejected: AtomicU64::new(0),
+            corrupt_frames: AtomicU64::new(0),
+            last_message_ms: AtomicU64::new(NEVER),
+            send_queue_depth: AtomicUsize::new(0),
+            bandwidth_level: AtomicU8::new(0),
+        }
+    }
+
+    pub fn record_received(&self, bytes: usize, now: Instant) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+        let ms = now.saturating_duration_since(self.connected_at).as_millis() as u64;
+        self.last_message_ms.store(ms, Ordering::Relaxed);
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Total bytes written to the connection so far.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn record_position(&self, accepted: bool) {
+        let counter = if accepted { &self.positions_accepted } else { &self.positions_rejected };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_corrupt_frame(&self) {
+        self.corrupt_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_send_queue_depth(&self, depth: usize) {
+        self.send_queue_depth.store(depth, Ordering::Relaxed);
+    }
+
+    pub fn set_bandwidth_level(&self, level: u8) {
+        self.bandwidth_level.store(level, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, now: Instant) -> StatsSnapshot {
+        let since_connect = now.saturating_duration_since(self.connected_at);
+        let last_message_ms = self.last_message_ms.load(Ordering::Relaxed);
+        StatsSnapshot {
+            connected_for: since_connect,
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            positions_accepted: self.positions_accepted.load(Ordering::Relaxed),
+            positions_rejected: self.positions_rejected.load(Ordering::Relaxed),
+            corrupt_frames: self.corrupt_frames.load(Ordering::Relaxed),
+            last_message_age: (last_message_ms != NEVER)
+                .then(|| since_connect.saturating_sub(Duration::from_millis(last_message_ms))),
+            send_queue_depth: self.send_queue_depth.load(Ordering::Relaxed),
+            bandwidth_level: self.bandwidth_level.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl std::fmt::Display for StatsSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rx={} msgs/{} B tx={} msgs/{} B positions={} ok/{} rejected corrupt={} last={} queue={} states=1/{} up={:.0}s",
+               self.messages_received, self.bytes_received,
+               self.messages_sent, self.bytes_sent,
+               self.positions_accepted, self.positions_rejected, self.corrupt_frames,
+               self.last_message_age.map_or("never".to_string(), |age| format!("{:.1}s ago", age.as_secs_f32())),
+               self.send_queue_depth,
+               1u32 << self.bandwidth_level,
+               self.connected_for.as_secs_f32())
+    }
+}
+
+/// The counters as of the last `DebugStats` frame sent to a connection.
+#[derive(Debug, Clone)]
+pub struct DebugWindow {
+    positions_accepted: u64,
+    positions_rejected: u64,
+    rtt_ms: Option<u32>,
+}
+
+impl DebugWindow {
+    /// A window opening at `snapshot`.
+    pub fn new(snapshot: &StatsSnapshot) -> Self {
+        DebugWindow { positions_accepted: snapshot.positions_accepted, positions_rejected: snapshot.positions_rejected, rtt_ms: None }
+    }
+
+    /// The frame for the updates since the window opened or the last frame,
+    /// moving the window on to `snapshot`.
+    pub fn frame(&mut self, snapshot: &StatsSnapshot, tick: u64) -> ServerMessage {
+        let accepted = snapshot.positions_accepted.saturating_sub(self.positions_accepted);
+        let rejected = snapshot.positions_rejected.saturating_sub(self.positions_rejected);
+        self.positions_accepted = snapshot.positions_accepted;
+        self.positions_rejected = snapshot.positions_rejected;
+        let clamp = |n: u64| n.min(u32::MAX as u64) as u32;
+        ServerMessage::DebugStats {
+            tick,
+            updates_received: clamp(accepted + rejected),
+            updates_accepted: clamp(accepted),
+            updates_rejected: clamp(rejected),
+            send_queue_depth: clamp(snapshot.send_queue_depth as u64),
+            rtt_ms: self.rtt_ms,
+            bandwidth_level: snapshot.bandwidth_level,
+        }
+    }
+
+    /// A ping to measure the round trip with, sent at `server_time_ms`.
+    pub fn ping(server_time_ms: u64) -> Message {
+        Message::Ping(Bytes::copy_from_slice(&server_time_ms.to_le_bytes()))
+    }
+
+    /// Takes the round trip from a pong to one of our pings; pongs to
+    /// anything else are ignored.
+    pub fn pong(&mut self, payload: &[u8], server_time_ms: u64) {
+        if let Ok(sent) = <[u8; 8]>::try_from(payload) {
+            let sent = u64::from_le_bytes(sent);
+            if sent <= server_time_ms {
+                self.rtt_ms = Some((server_time_ms - sent).min(u32::MAX as u64) as u32);
+            }
+        }
+    }
+}
+
+/// Counts every message written to the wrapped sink.
+pub struct CountingSink<S> {
+    inner: S,
+    stats: Arc<ConnectionStats>,
+}
+
+impl<S> CountingSink<S> {
+    pub fn new(inner: S, stats: Arc<ConnectionStats>) -> Self {
+        CountingSink { inner, stats }
+    }
+}
+
+impl<S: Sink<Message> + Unpin> Sink<Message> for CountingSink<S> {
+    type Error = S::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, message: Message) -> Result<(), Self::Error> {
+        if message.is_text() || message.is_binary() {
+            self.stats.record_sent(message.len());
+        }
+        Pin::new(&mut self.inner).start_send(message)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
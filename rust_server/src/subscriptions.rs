@@ -0,0 +1,108 @@
+use crate::protocol::ServerMessage;
+
+/*
+What a connection wants broadcast to it.
+
+A thin client, say a chat window or a map showing only who owns which
+planet, has no use for a State twenty times a second. It says which kinds
+of broadcast it wants as a `Subscriptions` bitfield, with `?subs=` on the
+connection URL (a decimal, like `?caps=`) or at any time with
+`SetSubscriptions { mask }`:
+
+- STATE = 1: `State` (or `StateDelta`) and `PlayerTeleported`, and
+  spectators' `Trails`
+- CHAT = 2: `Chatted`, `Announcement` and `ChatHistory`
+- WORLD = 4: `WorldReset`, `WorldEvent`, `TimeSet` and `PauseChanged`
+- PLAYERS = 8: players going idle or active, changing appearance or name,
+  and friends coming and going
+- PLANETS = 16: planets added, removed, edited, renamed, discovered and
+  populated
+
+Without either, a connection gets everything, as before subscriptions
+existed, and unknown bits are ignored. Only broadcasts are filtered, on
+each connection's way from the broadcast channel to its write queue, and
+only those of the kinds above, along with the join messages of those
+kinds (the snapshot, the chat history, a pause or ambient events under
+way). Replies, errors, notices, keepalives and everything else meant for
+this connection always go out, a chat's `Echo` and a resync's answer
+included, so a client never misses the answer to something it asked.
+
+A `Batch` keeps the events wanted; a connection filtering some of a batch
+out encodes what is left itself rather than sharing the frame encoded once
+for everyone (see `broadcast`), so the saving is in bytes on the wire, not
+work.
+
+A connection not subscribed to STATE misses the join snapshot and every
+State after it, so it knows the world only from the events it does get;
+subscribing again later brings the next tick's State, as for a client
+that dropped some.
+*/
+
+/// The kinds of broadcast a connection wants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subscriptions(pub u32);
+
+impl Subscriptions {
+    pub const NONE: Subscriptions = Subscriptions(0);
+    /// Snapshots and teleports.
+    pub const STATE: Subscriptions = Subscriptions(1 << 0);
+    pub const CHAT: Subscriptions = Subscriptions(1 << 1);
+    /// Resets, ambient events, the time of day and pauses.
+    pub const WORLD: Subscriptions = Subscriptions(1 << 2);
+    pub const PLAYERS: Subscriptions = Subscriptions(1 << 3);
+    pub const PLANETS: Subscriptions = Subscriptions(1 << 4);
+    /// Everything, as a connection that never said gets.
+    pub const ALL: Subscriptions =
+        Subscriptions(Self::STATE.0 | Self::CHAT.0 | Self::WORLD.0 | Self::PLAYERS.0 | Self::PLANETS.0);
+
+    /// The kinds in `mask` this server knows; the rest drop out.
+    pub fn from_mask(mask: u32) -> Self {
+        Subscriptions(mask & Self::ALL.0)
+    }
+
+    pub fn contains(self, other: Subscriptions) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether this connection is sent `message` when it is broadcast; a
+    /// batch is wanted if any of its events is.
+    pub fn wants(self, message: &ServerMessage) -> bool {
+        match message {
+            ServerMessage::Batch { messages } => messages.0.iter().any(|message| self.wants(message)),
+            message => kind(message).is_none_or(|kind| self.contains(kind)),
+        }
+    }
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Subscriptions::ALL
+    }
+}
+
+impl std::ops::BitOr for Subscriptions {
+    type Output = Subscriptions;
+
+    fn bitor(self, other: Subscriptions) -> Subscriptions {
+        Subscriptions(self.0 | other.0)
+    }
+}
+
+/// The kind of broadcast `message` is, or None for what always goes out.
+pub fn kind(message: &ServerMessage) -> Option<Subscriptions> {
+    match message {
+        ServerMessage::State { .. } | ServerMessage::StateDelta { .. } | ServerMessage::PlayerTeleported { .. } | ServerMessage::Trails { .. } => {
+            Some(Subscriptions::STATE)
+        }
+        ServerMessage::Chatted { .. } | ServerMessage::ChatHistory { .. } | ServerMessage::Announcement { .. } => Some(Subscriptions::CHAT),
+        ServerMessage::WorldReset { .. } | ServerMessage::WorldEvent { .. } | ServerMessage::TimeSet { .. }
+            | ServerMessage::PauseChanged { .. } => Some(Subscriptions::WORLD),
+        ServerMessage::PlayerIdle { .. } | ServerMessage::PlayerActive { .. } | ServerMessage::PlayerAppearanceChanged { .. }
+            | ServerMessage::PlayerRenamed { .. } | ServerMessage::FriendOnline { .. }
+            | ServerMessage::FriendOffline { .. } => Some(Subscriptions::PLAYERS),
+        ServerMessage::PlanetAdded { .. } | ServerMessage::PlanetRemoved { .. } | ServerMessage::PlanetUpdated { .. }
+            | ServerMessage::PlanetDiscovered { .. } | ServerMessage::PlanetPopulation { .. }
+            | ServerMessage::PlanetRenamed { .. } => Some(Subscriptions::PLANETS),
+        _ => None,
+    }
+}
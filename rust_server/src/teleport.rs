@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+use crate::protocol::{Planet, TeleportError};
+
+/*
+Rules for `TeleportToPlanet` requests.
+
+A player may teleport only to planets they own, unless the server is casual
+(`--casual`), and then to any planet. Each player has a cooldown that starts
+with every successful teleport; requests during it are rejected with the
+time remaining so clients can show a countdown. Rejected requests do not
+restart the cooldown. `Respawn` is not a teleport and is never limited by
+it. Like the rate limiter, everything takes an explicit `now`.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TeleportConfig {
+    pub cooldown: Duration,
+    pub casual: bool,  // any planet, not just owned ones
+}
+
+impl Default for TeleportConfig {
+    fn default() -> Self {
+        TeleportConfig { cooldown: Duration::from_secs(30), casual: false }
+    }
+}
+
+/// Per-player teleport state.
+#[derive(Debug, Clone)]
+pub struct TeleportCooldown {
+    config: TeleportConfig,
+    ready_at: Option<Instant>,
+}
+
+impl TeleportCooldown {
+    pub fn new(config: TeleportConfig) -> Self {
+        TeleportCooldown { config, ready_at: None }
+    }
+
+    /// Time left before the next teleport is allowed.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.ready_at.map_or(Duration::ZERO, |ready_at| ready_at.saturating_duration_since(now))
+    }
+
+    /// Checks a teleport by `player_id` to `planet` (`None` if there is no such
+    /// planet) and starts the cooldown if it is allowed.
+    pub fn try_teleport<'a>(&mut self, player_id: u32, planet_id: u32, planet: Option<&'a Planet>, now: Instant) -> Result<&'a Planet, TeleportError> {
+        let planet = planet.ok_or(TeleportError::UnknownPlanet { planet_id })?;
+        if !self.config.casual && planet.owner != Some(player_id) {
+            return Err(TeleportError::NotOwner { planet_id });
+        }
+        let remaining = self.remaining(now);
+        if !remaining.is_zero() {
+            return Err(TeleportError::Cooldown { remaining_ms: remaining.as_millis().max(1) as u64 });
+        }
+        self.ready_at = Some(now + self.config.cooldown);
+        Ok(planet)
+    }
+}
@@ -0,0 +1,48 @@
+/*
+Clock synchronisation between client and server.
+
+The client stamps a time-sync request with its own clock, the server echoes it
+together with its clock, and on receipt the client knows the round trip. As in
+NTP, the sample with the smallest round trip gives the best offset estimate.
+*/
+
+/// One completed time-sync exchange, all times in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    pub client_send_ms: u64,
+    pub server_time_ms: u64,
+    pub client_recv_ms: u64,
+}
+
+impl ClockSample {
+    pub fn rtt_ms(&self) -> u64 {
+        self.client_recv_ms.saturating_sub(self.client_send_ms)
+    }
+
+    /// Server clock minus client clock, assuming a symmetric path.
+    pub fn offset_ms(&self) -> i64 {
+        let midpoint = self.client_send_ms as i64 + self.rtt_ms() as i64 / 2;
+        self.server_time_ms as i64 - midpoint
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockEstimate {
+    pub offset_ms: i64,
+    pub rtt_ms: u64,
+}
+
+impl ClockEstimate {
+    /// Converts a client timestamp into the server's time base.
+    pub fn to_server_time(&self, client_ms: u64) -> u64 {
+        (client_ms as i64 + self.offset_ms).max(0) as u64
+    }
+}
+
+/// Estimates the clock offset from a handful of exchanges, or `None` with no samples.
+pub fn estimate_clock_offset(samples: &[ClockSample]) -> Option<ClockEstimate> {
+    samples.iter().min_by_key(|s| s.rtt_ms()).map(|best| ClockEstimate {
+        offset_ms: best.offset_ms(),
+        rtt_ms: best.rtt_ms(),
+    })
+}
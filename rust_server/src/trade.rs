@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use crate::module_effects::Wallet;
+use crate::protocol::{TradeEnd, TradeError};
+use crate::server::ConnectionId;
+
+/*
+Trading cargo for credits between players.
+
+A player offers another some of their cargo for credits with `TradeOffer`
+(`offer <player id> <units> <credits>` in the client). The offerer is told
+the offer's id with `TradeOffered`, and the other player gets it as a
+`TradeIncoming`, to `TradeAccept` or `TradeDecline` within the offer
+timeout (`trade_offer_timeout_secs`, a minute by default). Either amount
+may be zero, for a gift or a plain sale, but not both.
+
+Balances are checked when the offer is accepted, not when it is made: the
+server takes the wallets lock once, checks that the offerer still has the
+cargo and the other player the credits, and moves both, so two trades, a
+sale and a trade, or an accept racing a decline can never spend the same
+units twice. The two wallets are saved together (see `wallets`), then both
+players are sent a `TradeCompleted` with their own wallet, and the trade is
+journaled. An offer that ends any other way
+(declined, expired, one side short at accept time, or one player gone)
+ends with a `TradeEnded` to both saying why, and moves nothing.
+
+Offers belong to the two connections, not to names: they are not saved,
+and they end when either player disconnects. A player may have at most
+`MAX_OPEN_OFFERS` offers of their own waiting. Like the idle tracker,
+everything takes an explicit `now`.
+*/
+
+/// How long an offer waits for an answer, unless configured.
+pub const DEFAULT_OFFER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Most offers one player may have waiting at once.
+pub const MAX_OPEN_OFFERS: usize = 4;
+
+/// An offer waiting for an answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Offer {
+    pub id: u32,
+    pub from: ConnectionId,
+    pub from_player: u32,
+    pub to: ConnectionId,
+    pub to_player: u32,
+    pub give_resources: u32,
+    pub want_credits: u64,
+    pub expires_at: Instant,
+}
+
+/// The offers waiting for an answer.
+#[derive(Debug, Clone)]
+pub struct Trades {
+    timeout: Duration,
+    next_id: u32,
+    offers: BTreeMap<u32, Offer>,  // by id, so the oldest first
+}
+
+impl Default for Trades {
+    fn default() -> Self {
+        Self::new(DEFAULT_OFFER_TIMEOUT)
+    }
+}
+
+impl Trades {
+    /// No offers yet; each will wait `timeout` for an answer.
+    pub fn new(timeout: Duration) -> Self {
+        Trades { timeout, next_id: 1, offers: BTreeMap::new() }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Records an offer from `from` to `to`, each a connection and its
+    /// player's id, returning it with its id.
+    pub fn offer(
+        &mut self,
+        (from, from_player): (ConnectionId, u32),
+        (to, to_player): (ConnectionId, u32),
+        give_resources: u32,
+        want_credits: u64,
+        now: Instant,
+    ) -> Result<Offer, TradeError> {
+        if from == to {
+            return Err(TradeError::OwnOffer);
+        }
+        if give_resources == 0 && want_credits == 0 {
+            return Err(TradeError::EmptyOffer);
+        }
+        if self.offers.values().filter(|offer| offer.from == from).count() >= MAX_OPEN_OFFERS {
+            return Err(TradeError::TooManyOffers { limit: MAX_OPEN_OFFERS as u32 });
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let offer = Offer { id, from, from_player, to, to_player, give_resources, want_credits, expires_at: now + self.timeout };
+        self.offers.insert(id, offer.clone());
+        Ok(offer)
+    }
+
+    /// Takes offer `offer_id` out to answer it, if it is waiting for `to`;
+    /// a second answer finds it gone.
+    pub fn take(&mut self, offer_id: u32, to: ConnectionId, now: Instant) -> Result<Offer, TradeError> {
+        match self.offers.get(&offer_id) {
+            Some(offer) if offer.to == to && offer.expires_at > now => Ok(self.offers.remove(&offer_id).expect("checked above")),
+            _ => Err(TradeError::UnknownOffer { offer_id }),
+        }
+    }
+
+    /// Removes and returns the offers whose time is up by `now`.
+    pub fn expire(&mut self, now: Instant) -> Vec<Offer> {
+        self.remove_where(|offer| offer.expires_at <= now)
+    }
+
+    /// Removes and returns the offers to or from `connection`, which is gone.
+    pub fn forget(&mut self, connection: ConnectionId) -> Vec<Offer> {
+        self.remove_where(|offer| offer.from == connection || offer.to == connection)
+    }
+
+    fn remove_where(&mut self, mut ended: impl FnMut(&Offer) -> bool) -> Vec<Offer> {
+        let ids: Vec<u32> = self.offers.values().filter(|offer| ended(offer)).map(|offer| offer.id).collect();
+        ids.into_iter().filter_map(|id| self.offers.remove(&id)).collect()
+    }
+
+    /// How many offers are waiting.
+    pub fn len(&self) -> usize {
+        self.offers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offers.is_empty()
+    }
+}
+
+/// Moves `offer`'s cargo from `seller` to `buyer` and its credits back, or
+/// neither if one of them is short.
+pub fn settle(offer: &Offer, seller: &mut Wallet, buyer: &mut Wallet) -> Result<(), TradeEnd> {
+    if seller.cargo < offer.give_resources as u64 {
+        return Err(TradeEnd::NotEnoughCargo);
+    }
+    if buyer.credits < offer.want_credits {
+        return Err(TradeEnd::NotEnoughCredits);
+    }
+    seller.cargo -= offer.give_resources as u64;
+    buyer.cargo += offer.give_resources as u64;
+    buyer.credits -= offer.want_credits;
+    seller.credits += offer.want_credits;
+    Ok(())
+}
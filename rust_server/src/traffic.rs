@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use crate::protocol::ServerMessage;
+
+/*
+What a client has received, for measuring what the protocol costs: messages
+and bytes per message type, and how regularly the `State` broadcasts
+arrive. The client-side counterpart of the server's per-connection
+counters in `stats`.
+
+`Connection` records every frame it receives (see `Connection::stats`), and
+anything else holding frames, a recorded capture or a load generator, can
+feed them to `ConnectionStats::record` itself. A binary frame counts under
+the variant its bincode tag names, without decoding it, so a `Batch` counts
+as one `Batch` however many messages it carries. Text frames, which the
+server sends notices and chat echoes as in binary mode, count as `text`.
+Pings, pongs and close frames do not count. Bytes are payload bytes, after
+any CRC trailer is stripped (see `integrity`).
+
+Jitter is the standard deviation of the gaps between consecutive `State`
+frames; with a steady broadcast it stays near zero however long the
+interval. Compression would make payload bytes larger than what crossed
+the wire, but the server declines `permessage-deflate` (see `handshake`),
+so there are no savings to report and the summary says so.
+*/
+
+/// How many frames of one type arrived, and their bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeTraffic {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// How regularly the `State` broadcasts arrived.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BroadcastJitter {
+    pub intervals: u64,
+    pub mean_interval: Duration,
+    pub jitter: Duration,  // standard deviation of the intervals
+    pub max_interval: Duration,
+}
+
+/// Everything received on one connection, by message type.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    by_type: BTreeMap<&'static str, TypeTraffic>,
+    first_at: Option<Instant>,
+    last_at: Option<Instant>,
+    last_broadcast_at: Option<Instant>,
+    // Welford's running mean and sum of squared deviations, in seconds
+    intervals: u64,
+    mean_interval: f64,
+    interval_m2: f64,
+    max_interval: Duration,
+}
+
+impl ConnectionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts `frame`, received at `now`.
+    pub fn record(&mut self, frame: &Message, now: Instant) {
+        let kind = match frame {
+            Message::Binary(data) => ServerMessage::bincode_variant(data).unwrap_or("unknown"),
+            Message::Text(_) => "text",
+            _ => return,
+        };
+        let traffic = self.by_type.entry(kind).or_default();
+        traffic.messages += 1;
+        traffic.bytes += frame.len() as u64;
+        self.first_at.get_or_insert(now);
+        self.last_at = Some(now);
+        if kind == "State"
+            && let Some(previous) = self.last_broadcast_at.replace(now)
+        {
+            self.record_interval(now.saturating_duration_since(previous));
+        }
+    }
+
+    fn record_interval(&mut self, interval: Duration) {
+        let secs = interval.as_secs_f64();
+        self.intervals += 1;
+        let delta = secs - self.mean_interval;
+        self.mean_interval += delta / self.intervals as f64;
+        self.interval_m2 += delta * (secs - self.mean_interval);
+        self.max_interval = self.max_interval.max(interval);
+    }
+
+    /// The frames and bytes of each message type, by name.
+    pub fn by_type(&self) -> &BTreeMap<&'static str, TypeTraffic> {
+        &self.by_type
+    }
+
+    /// The frames and bytes of every type together.
+    pub fn total(&self) -> TypeTraffic {
+        self.by_type.values().fold(TypeTraffic::default(), |total, t| TypeTraffic {
+            messages: total.messages + t.messages,
+            bytes: total.bytes + t.bytes,
+        })
+    }
+
+    /// From the first frame counted to the last.
+    pub fn elapsed(&self) -> Duration {
+        match (self.first_at, self.last_at) {
+            (Some(first), Some(last)) => last.saturating_duration_since(first),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// The gaps between `State` frames, once two have arrived.
+    pub fn broadcast_jitter(&self) -> Option<BroadcastJitter> {
+        (self.intervals > 0).then(|| BroadcastJitter {
+            intervals: self.intervals,
+            mean_interval: Duration::from_secs_f64(self.mean_interval),
+            jitter: Duration::from_secs_f64((self.interval_m2 / self.intervals as f64).max(0.0).sqrt()),
+            max_interval: self.max_interval,
+        })
+    }
+}
+
+/// A table of the types, most bytes first, then the totals and the jitter.
+impl fmt::Display for ConnectionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        let mut types: Vec<(&&str, &TypeTraffic)> = self.by_type.iter().collect();
+        types.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        writeln!(f, "{:<18} {:>8} {:>12} {:>8} {:>7}", "message", "count", "bytes", "avg B", "share")?;
+        for (name, traffic) in types {
+            writeln!(f, "{:<18} {:>8} {:>12} {:>8} {:>6.1}%", name, traffic.messages, traffic.bytes,
+                     traffic.bytes / traffic.messages, 100.0 * traffic.bytes as f64 / total.bytes.max(1) as f64)?;
+        }
+        let secs = self.elapsed().as_secs_f64();
+        let rate = if secs > 0.0 { format!("{:.0} B/s", total.bytes as f64 / secs) } else { "-".to_string() };
+        writeln!(f, "{:<18} {:>8} {:>12} {:>8} over {:.1}s", "total", total.messages, total.bytes, rate, secs)?;
+        match self.broadcast_jitter() {
+            Some(j) => writeln!(f, "broadcasts every {:.1}ms, jitter {:.1}ms, longest gap {:.1}ms",
+                                j.mean_interval.as_secs_f64() * 1e3, j.jitter.as_secs_f64() * 1e3, j.max_interval.as_secs_f64() * 1e3)?,
+            None => writeln!(f, "broadcasts: too few to time")?,
+        }
+        write!(f, "compression: none negotiated")
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use crate::id_hash::IdMap;
+use crate::protocol::{Player, PlayerTrail, TrailPoint};
+
+/*
+Short movement trails, for replay tools and map viewers.
+
+When trails are on, the server notes every player's position at each
+periodic broadcast, keeping the last `length` of them per player in a ring
+buffer, so memory stays at `length` points a player. A client asks for one
+player's trail with `QueryTrail { player_id }` and gets a `Trail` back,
+oldest point first. Spectators are sent a `Trails` frame after each State
+with every player's trail thinned to every `SPECTATOR_SAMPLE_EVERY`th
+point, counted back from the newest so the current position is always
+there.
+
+A player who respawns or teleports starts a new trail, so it never draws a
+line across the world; regenerating the world clears every trail, and a
+player's trail goes at the first broadcast after they leave.
+*/
+
+pub const DEFAULT_TRAIL_LENGTH: usize = 32;
+
+/// Spectators get every this many points of each trail.
+pub const SPECTATOR_SAMPLE_EVERY: usize = 4;
+
+/// How much the trails are holding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TrailStats {
+    pub players: usize,
+    pub points: usize,
+    pub bytes: u64,  // of the stored points
+}
+
+impl std::fmt::Display for TrailStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "trails={} player(s)/{} point(s)/{} bytes", self.players, self.points, self.bytes)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Trails {
+    length: usize,
+    trails: IdMap<u32, VecDeque<TrailPoint>>,  // by player id
+}
+
+impl Trails {
+    /// Keeps the last `length` positions of each player.
+    pub fn new(length: usize) -> Self {
+        Trails { length, trails: IdMap::default() }
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Adds where each of `players` is at `time_ms`, forgetting anyone
+    /// who is no longer among them.
+    pub fn record(&mut self, players: &[Player], time_ms: u64) {
+        self.trails.retain(|id, _| players.iter().any(|p| p.id == *id));
+        for player in players {
+            self.push(player.id, TrailPoint { time_ms, position: player.position.clone() });
+        }
+    }
+
+    /// Adds a point to a player's trail, dropping the oldest when it is full.
+    pub fn push(&mut self, player_id: u32, point: TrailPoint) {
+        if self.length == 0 {
+            return;
+        }
+        let trail = self.trails.entry(player_id).or_insert_with(|| VecDeque::with_capacity(self.length));
+        if trail.len() == self.length {
+            trail.pop_front();
+        }
+        trail.push_back(point);
+    }
+
+    /// Starts the player's trail over, as after a teleport.
+    pub fn clear(&mut self, player_id: u32) {
+        self.trails.remove(&player_id);
+    }
+
+    pub fn clear_all(&mut self) {
+        self.trails.clear();
+    }
+
+    /// The player's trail, oldest first; empty if nothing is recorded yet.
+    pub fn trail(&self, player_id: u32) -> Vec<TrailPoint> {
+        self.trails.get(&player_id).map(|t| t.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Every trail with only every `every`th point, newest included, in
+    /// player id order.
+    pub fn sampled(&self, every: usize) -> Vec<PlayerTrail> {
+        let mut trails: Vec<PlayerTrail> = self.trails.iter()
+            .map(|(id, trail)| {
+                let mut points: Vec<TrailPoint> = trail.iter().rev().step_by(every.max(1)).cloned().collect();
+                points.reverse();
+                PlayerTrail { player_id: *id, points }
+            })
+            .collect();
+        trails.sort_by_key(|t| t.player_id);
+        trails
+    }
+
+    pub fn stats(&self) -> TrailStats {
+        let points = self.trails.values().map(VecDeque::len).sum();
+        TrailStats { players: self.trails.len(), points, bytes: (points * std::mem::size_of::<TrailPoint>()) as u64 }
+    }
+}
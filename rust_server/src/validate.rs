@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+use crate::client::ClientEvent;
+use crate::diff::StateDiff;
+use crate::protocol::{Position, ServerMessage};
+use crate::quantize::QuantizedPosition;
+
+/*
+A conformance check on what a server sends, for testing server changes
+(`client --validate`, or `Connection::validate` when embedding).
+
+`Validator` follows the events of one connection and reports where they
+break the protocol's invariants:
+
+- snapshot ticks strictly increase, and a delta never ends before it starts;
+- deltas and events name only players and planets the client knows of,
+  from the last snapshot and the deltas and events since;
+- `PlayerLeft` only comes for a player who was there;
+- every position is finite and, when the server advertised its world
+  radius in the `WORLD_RADIUS_HEADER` of the handshake, inside it.
+
+Idle players are left out of broadcasts, so as in `EventDecoder` a player
+who went idle stays known until a snapshot leaves them out while active.
+Until the first snapshot, and between a `WorldReset` and the next one,
+nothing is known and no id is checked. A resync may be answered with the
+latest snapshot again, so after `expect_resync` the next snapshot may
+repeat the tick. Each `Violation` holds the event that broke the rule,
+shortened to `MAX_EVENT_CHARS`.
+*/
+
+/// How much of the offending event a violation keeps.
+pub const MAX_EVENT_CHARS: usize = 300;
+
+/// Positions this much (as a fraction of the radius) outside the world
+/// are put down to rounding.
+const BOUNDS_TOLERANCE: f64 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    TickOrder,
+    UnknownPlayer,
+    UnknownPlanet,
+    NonFinitePosition,
+    OutOfBounds,
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Rule::TickOrder => "tick order",
+            Rule::UnknownPlayer => "unknown player",
+            Rule::UnknownPlanet => "unknown planet",
+            Rule::NonFinitePosition => "non-finite position",
+            Rule::OutOfBounds => "out of bounds",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub rule: Rule,
+    pub detail: String,
+    pub event: String,  // the offending event, as `Debug` prints it
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} in {}", self.rule, self.detail, self.event)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    world_radius: Option<f32>,
+    last_tick: Option<u64>,
+    resync_expected: bool,
+    players: Option<HashSet<u32>>,  // None until the first snapshot, and after a reset
+    departed: HashSet<u32>,         // missing from the last snapshot; `PlayerLeft` may follow
+    idle: HashSet<u32>,
+    planets: Option<HashSet<u32>>,
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    /// Checks positions against `world_radius`, if the server gave one.
+    pub fn new(world_radius: Option<f32>) -> Self {
+        Validator { world_radius, ..Self::default() }
+    }
+
+    /// A resync was asked for, so the next snapshot may repeat the tick.
+    pub fn expect_resync(&mut self) {
+        self.resync_expected = true;
+    }
+
+    /// Every violation so far, oldest first.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Checks the next event, returning the violations it brought.
+    pub fn check(&mut self, event: &ClientEvent) -> &[Violation] {
+        let mut found = Vec::new();
+        match event {
+            ClientEvent::StateSnapshot { tick, state, .. } => {
+                if let Some(last) = self.last_tick
+                    && (*tick < last || (*tick == last && !self.resync_expected))
+                {
+                    found.push((Rule::TickOrder, format!("tick {} after tick {}", tick, last)));
+                }
+                self.last_tick = Some(self.last_tick.map_or(*tick, |last| last.max(*tick)));
+                self.resync_expected = false;
+                for player in &state.players {
+                    self.position(&mut found, &format!("player {}", player.id), &player.position);
+                }
+                for planet in &state.planets {
+                    self.position(&mut found, &format!("planet {}", planet.id), &planet.position);
+                }
+
+                self.idle.extend(state.players.iter().filter(|p| p.idle).map(|p| p.id));
+                let mut players: HashSet<u32> = state.players.iter().map(|p| p.id).collect();
+                let known = self.players.take().unwrap_or_default();
+                players.extend(known.iter().filter(|id| self.idle.contains(id)));
+                self.departed = known.difference(&players).copied().collect();
+                self.players = Some(players);
+                self.planets = Some(state.planets.iter().map(|p| p.id).collect());
+            }
+            ClientEvent::Delta { from_tick, tick, diffs } => {
+                if tick < from_tick {
+                    found.push((Rule::TickOrder, format!("delta from tick {} to tick {}", from_tick, tick)));
+                }
+                self.resync_expected = false;
+                for diff in diffs {
+                    self.diff(&mut found, diff);
+                }
+            }
+            ClientEvent::Joined { spawn, spawn_planet_id, .. } => {
+                self.position(&mut found, "spawn", spawn);
+                if let Some(planet_id) = spawn_planet_id {
+                    self.planet(&mut found, *planet_id);
+                }
+            }
+            ClientEvent::PlayerJoined { player } => {
+                self.position(&mut found, &format!("player {}", player.id), &player.position);
+                if let Some(players) = self.players.as_mut() {
+                    players.insert(player.id);
+                }
+            }
+            ClientEvent::PlayerLeft { player_id } => {
+                if !self.departed.remove(player_id) {
+                    self.player(&mut found, *player_id);
+                }
+                if let Some(players) = self.players.as_mut() {
+                    players.remove(player_id);
+                }
+            }
+            ClientEvent::WorldReset { .. } => {
+                self.players = None;
+                self.planets = None;
+                self.departed.clear();
+                self.idle.clear();
+            }
+            ClientEvent::Message(message) => self.message(&mut found, message),
+            ClientEvent::Chat { .. } | ClientEvent::Notice { .. } | ClientEvent::Localized { .. } | ClientEvent::Announcement { .. } | ClientEvent::Redirected { .. } | ClientEvent::Disconnected { .. }
+            | ClientEvent::CorruptFrame { .. } => {}
+        }
+
+        let start = self.violations.len();
+        if !found.is_empty() {
+            let mut summary = format!("{:?}", event);
+            if summary.chars().count() > MAX_EVENT_CHARS {
+                summary = summary.chars().take(MAX_EVENT_CHARS).chain("…".chars()).collect();
+            }
+            self.violations.extend(found.into_iter().map(|(rule, detail)| Violation { rule, detail, event: summary.clone() }));
+        }
+        &self.violations[start..]
+    }
+
+    fn message(&mut self, found: &mut Vec<(Rule, String)>, message: &ServerMessage) {
+        match message {
+            ServerMessage::PlanetAdded { planet } => {
+                self.position(found, &format!("planet {}", planet.id), &planet.position);
+                if let Some(planets) = self.planets.as_mut() {
+                    planets.insert(planet.id);
+                }
+            }
+            ServerMessage::PlanetRemoved { planet_id } => {
+                self.planet(found, *planet_id);
+                if let Some(planets) = self.planets.as_mut() {
+                    planets.remove(planet_id);
+                }
+            }
+            ServerMessage::PlanetUpdated { planet } => {
+                self.planet(found, planet.id);
+                self.position(found, &format!("planet {}", planet.id), &planet.position);
+            }
+            ServerMessage::PlanetList { planets } | ServerMessage::PlanetPage { results: planets, .. } => {
+                for planet in planets {
+                    self.planet(found, planet.id);
+                    self.position(found, &format!("planet {}", planet.id), &planet.position);
+                }
+            }
+            ServerMessage::DiscoveryList { planet_ids, .. } => {
+                for planet_id in planet_ids {
+                    self.planet(found, *planet_id);
+                }
+            }
+            ServerMessage::PlanetPopulation { counts } => {
+                for count in counts {
+                    self.planet(found, count.planet_id);
+                }
+            }
+            ServerMessage::PlanetDiscovered { player_id, planet_id } => {
+                self.player(found, *player_id);
+                self.planet(found, *planet_id);
+            }
+            ServerMessage::PlayerTeleported { player_id, position } => {
+                self.player(found, *player_id);
+                self.position(found, &format!("player {}", player_id), position);
+            }
+            ServerMessage::Trail { trail } => {
+                self.player(found, trail.player_id);
+                for point in &trail.points {
+                    self.position(found, &format!("trail of player {}", trail.player_id), &point.position);
+                }
+            }
+            ServerMessage::Trails { trails } => {
+                for trail in trails {
+                    for point in &trail.points {
+                        self.position(found, &format!("trail of player {}", trail.player_id), &point.position);
+                    }
+                }
+            }
+            ServerMessage::PositionCorrection { position, .. } => self.position(found, "correction", position),
+            ServerMessage::PlayerIdle { player_id } => {
+                self.player(found, *player_id);
+                self.idle.insert(*player_id);
+            }
+            ServerMessage::PlayerActive { player_id } => {
+                self.player(found, *player_id);
+                self.idle.remove(player_id);
+            }
+            ServerMessage::PlayerAppearanceChanged { player_id, .. } | ServerMessage::PlayerRenamed { player_id, .. } => {
+                self.player(found, *player_id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks one diff of a delta against what is known, then applies it.
+    fn diff(&mut self, found: &mut Vec<(Rule, String)>, diff: &StateDiff) {
+        let moves = diff.moves.iter().flat_map(|moves| &moves.players);
+        for player_id in diff.removed_players.iter().chain(diff.changed_players.iter().map(|p| &p.id)).chain(moves.clone().map(|m| &m.id)) {
+            self.player(found, *player_id);
+        }
+        for player in diff.changed_players.iter().chain(&diff.added_players) {
+            self.position(found, &format!("player {}", player.id), &player.position);
+        }
+        for moved in moves {
+            if let QuantizedPosition::Absolute(position) = &moved.position {
+                self.position(found, &format!("player {}", moved.id), position);
+            }
+        }
+        for planet_id in diff.removed_planets.iter().chain(diff.changed_planets.iter().map(|c| &c.id)) {
+            self.planet(found, *planet_id);
+        }
+        for planet in &diff.added_planets {
+            self.position(found, &format!("planet {}", planet.id), &planet.position);
+        }
+        for change in &diff.changed_planets {
+            if let Some(position) = &change.position {
+                self.position(found, &format!("planet {}", change.id), position);
+            }
+        }
+
+        if let Some(players) = self.players.as_mut() {
+            players.retain(|id| !diff.removed_players.contains(id));
+            players.extend(diff.added_players.iter().map(|p| p.id));
+        }
+        if let Some(planets) = self.planets.as_mut() {
+            planets.retain(|id| !diff.removed_planets.contains(id));
+            planets.extend(diff.added_planets.iter().map(|p| p.id));
+        }
+    }
+
+    fn player(&self, found: &mut Vec<(Rule, String)>, player_id: u32) {
+        if self.players.as_ref().is_some_and(|players| !players.contains(&player_id)) {
+            found.push((Rule::UnknownPlayer, format!("player {}", player_id)));
+        }
+    }
+
+    fn planet(&self, found: &mut Vec<(Rule, String)>, planet_id: u32) {
+        if self.planets.as_ref().is_some_and(|planets| !planets.contains(&planet_id)) {
+            found.push((Rule::UnknownPlanet, format!("planet {}", planet_id)));
+        }
+    }
+
+    fn position(&self, found: &mut Vec<(Rule, String)>, what: &str, position: &Position) {
+        let Position { x, y, z } = *position;
+        if ![x, y, z].iter().all(|v| v.is_finite()) {
+            found.push((Rule::NonFinitePosition, format!("{} at ({}, {}, {})", what, x, y, z)));
+            return;
+        }
+        let length = ((x as f64).powi(2) + (y as f64).powi(2) + (z as f64).powi(2)).sqrt();
+        if let Some(radius) = self.world_radius
+            && length > radius as f64 * (1.0 + BOUNDS_TOLERANCE)
+        {
+            found.push((Rule::OutOfBounds, format!("{} at ({:.1}, {:.1}, {:.1}), {:.1} from the centre of a world of radius {}",
+                                                   what, x, y, z, length, radius)));
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::io;
+use std::path::Path;
+use crate::json_store::JsonStore;
+use crate::module_effects::Wallet;
+
+/*
+Players' wallets, kept across restarts in the file given as `wallet_file`.
+
+A wallet holds the cargo a player has mined and the credits they have
+earned (see `module_effects`). Mining, selling and trading (see `trade`)
+change wallets, and each change is saved before the player is told of it:
+a `TradeCompleted` or a sale that reached the player outlasts a restart.
+A trade changes both wallets in one save, so a restart never finds the
+cargo gone from one without the credits reaching the other.
+
+Wallets belong to names, compared by `normalize_name` as bans and roles
+are, and move with a rename. A name with a wallet stays taken while its
+player is away: no one renames into it, and so no one takes over or adds
+to another player's wallet. Names that never mined have none.
+
+The file is JSON, rewritten on every change (see `json_store`). Without a
+file wallets last until the server stops.
+*/
+
+#[derive(Debug, Clone, Default)]
+pub struct Wallets(JsonStore<String, Wallet>);
+
+impl Wallets {
+    /// No wallets, kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The wallets in `path`, saved back there on every change. A missing
+    /// file is no wallets, created on the first change.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        JsonStore::load(path).map(Wallets)
+    }
+
+    /// How many names have wallets.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// What `name` holds; empty for names that never mined.
+    pub fn get(&self, name: &str) -> Wallet {
+        self.0.get(name).copied().unwrap_or_default()
+    }
+
+    /// Every wallet added up.
+    pub fn total(&self) -> Wallet {
+        self.0.values().fold(Wallet::default(), |total, wallet| Wallet {
+            cargo: total.cargo + wallet.cargo,
+            credits: total.credits + wallet.credits,
+        })
+    }
+
+    /// Whether `name` has a wallet.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+
+    /// Sets each name's wallet, then saves them all at once. The change
+    /// stands in memory even if the save fails.
+    pub fn set<'a>(&mut self, changes: impl IntoIterator<Item = (&'a str, Wallet)>) -> io::Result<()> {
+        for (name, wallet) in changes {
+            self.0.insert(name, wallet);
+        }
+        self.0.save()
+    }
+
+    /// Moves `old`'s wallet, if it has one, to `new`. Nothing changes if
+    /// `new` has a wallet of its own.
+    pub fn rename(&mut self, old: &str, new: &str) -> io::Result<()> {
+        if self.0.rename(old, new) { self.0.save() } else { Ok(()) }
+    }
+
+    /// Where the wallets are saved, if anywhere.
+    pub fn path(&self) -> Option<&Path> {
+        self.0.path()
+    }
+
+    /// The file's text: every wallet by normalized name, as pretty JSON.
+    pub fn to_file_text(&self) -> String {
+        self.0.to_file_text()
+    }
+}
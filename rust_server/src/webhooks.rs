@@ -0,0 +1,512 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use crate::events::{EventSink, EventTime, GameEvent};
+
+/*
+Outbound webhooks: server events POSTed as JSON to URLs in the config, for
+chat integrations such as Discord or Slack.
+
+    [[webhooks]]
+    url = "https://discord.com/api/webhooks/..."
+    events = ["player_joined", "ban_issued"]   # every kind when omitted
+
+    [webhook_templates]
+    player_joined = "{name} is online"
+
+The kinds are `player_joined`, `player_left`, `level_up`, `planet_claimed`
+(a planet gained an owner), `server_started`, `server_stopped` and
+`ban_issued`, each from the `GameEvent` of the same name on the server's
+event bus (see `events`). Each body is the event's fields, its kind under
+`event`, the rendered text under both `text` (Slack) and `content`
+(Discord), and `at_unix_ms`:
+
+    {"event":"level_up","name":"Ada","level":3,"text":"Ada reached level 3","content":"...","at_unix_ms":...}
+
+Templates may use the fields of their event in braces (`{name}`, `{level}`,
+`{planet}`, `{owner}`) and `{{`/`}}` for literal braces; kinds without one
+use `DEFAULT_TEMPLATES`. Both URLs and templates are checked when the
+config is loaded. `https` URLs need the `tls` feature.
+
+Each webhook has a task of its own, started by `run`, behind a queue of
+`webhook_queue` events. Notifying never waits: when a webhook's queue is
+full the event is dropped for it and counted (see `WebhookStats`). A POST
+answered with anything but 2xx, or not answered within `POST_TIMEOUT`, is
+retried `webhook_retries` times, waiting `webhook_backoff_ms` and twice as
+long each time after; a 4xx other than 408 or 429 is not retried. On
+shutdown the queues are given the write timeout to drain, so
+`server_stopped` usually gets out.
+*/
+
+pub const DEFAULT_QUEUE_LIMIT: usize = 256;
+pub const DEFAULT_RETRIES: u32 = 3;
+pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+pub const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The text of each kind of event unless the config says otherwise.
+pub const DEFAULT_TEMPLATES: &[(EventKind, &str)] = &[
+    (EventKind::PlayerJoined, "{name} joined"),
+    (EventKind::PlayerLeft, "{name} left"),
+    (EventKind::LevelUp, "{name} reached level {level}"),
+    (EventKind::PlanetClaimed, "Planet {planet} was claimed by player {owner}"),
+    (EventKind::ServerStarted, "The server started"),
+    (EventKind::ServerStopped, "The server stopped"),
+    (EventKind::BanIssued, "{name} was banned"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    PlayerJoined,
+    PlayerLeft,
+    LevelUp,
+    PlanetClaimed,
+    ServerStarted,
+    ServerStopped,
+    BanIssued,
+}
+
+impl std::str::FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DEFAULT_TEMPLATES.iter().map(|(kind, _)| *kind).find(|kind| kind.to_string() == s)
+            .ok_or_else(|| format!("unknown event {}", s))
+    }
+}
+
+impl std::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            EventKind::PlayerJoined => "player_joined",
+            EventKind::PlayerLeft => "player_left",
+            EventKind::LevelUp => "level_up",
+            EventKind::PlanetClaimed => "planet_claimed",
+            EventKind::ServerStarted => "server_started",
+            EventKind::ServerStopped => "server_stopped",
+            EventKind::BanIssued => "ban_issued",
+        })
+    }
+}
+
+/// Something a webhook may be told about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    PlayerJoined { name: String },
+    PlayerLeft { name: String },
+    LevelUp { name: String, level: u32 },
+    PlanetClaimed { planet: u32, owner: u32 },
+    ServerStarted,
+    ServerStopped,
+    BanIssued { name: String },
+}
+
+impl WebhookEvent {
+    /// What a webhook is told about `event`, if anything.
+    pub fn from_game(event: &GameEvent) -> Option<WebhookEvent> {
+        Some(match event {
+            GameEvent::PlayerJoined { player } => WebhookEvent::PlayerJoined { name: player.name.to_string() },
+            GameEvent::PlayerLeft { name, .. } => WebhookEvent::PlayerLeft { name: name.clone() },
+            GameEvent::LevelUp { name, level, .. } => WebhookEvent::LevelUp { name: name.clone(), level: *level },
+            GameEvent::PlanetClaimed { planet_id, owner } => WebhookEvent::PlanetClaimed { planet: *planet_id, owner: *owner },
+            GameEvent::ServerStarted => WebhookEvent::ServerStarted,
+            GameEvent::ServerStopped => WebhookEvent::ServerStopped,
+            GameEvent::BanIssued { name } => WebhookEvent::BanIssued { name: name.clone() },
+            _ => return None,
+        })
+    }
+
+    pub fn kind(&self) -> EventKind {
+        match self {
+            WebhookEvent::PlayerJoined { .. } => EventKind::PlayerJoined,
+            WebhookEvent::PlayerLeft { .. } => EventKind::PlayerLeft,
+            WebhookEvent::LevelUp { .. } => EventKind::LevelUp,
+            WebhookEvent::PlanetClaimed { .. } => EventKind::PlanetClaimed,
+            WebhookEvent::ServerStarted => EventKind::ServerStarted,
+            WebhookEvent::ServerStopped => EventKind::ServerStopped,
+            WebhookEvent::BanIssued { .. } => EventKind::BanIssued,
+        }
+    }
+
+    /// The placeholders its template may use, and their values.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        match self {
+            WebhookEvent::PlayerJoined { name } | WebhookEvent::PlayerLeft { name } | WebhookEvent::BanIssued { name } => {
+                vec![("name", name.clone())]
+            }
+            WebhookEvent::LevelUp { name, level } => vec![("name", name.clone()), ("level", level.to_string())],
+            WebhookEvent::PlanetClaimed { planet, owner } => vec![("planet", planet.to_string()), ("owner", owner.to_string())],
+            WebhookEvent::ServerStarted | WebhookEvent::ServerStopped => Vec::new(),
+        }
+    }
+
+    /// An event of `kind`, for checking templates.
+    fn example(kind: EventKind) -> WebhookEvent {
+        let name = String::new();
+        match kind {
+            EventKind::PlayerJoined => WebhookEvent::PlayerJoined { name },
+            EventKind::PlayerLeft => WebhookEvent::PlayerLeft { name },
+            EventKind::LevelUp => WebhookEvent::LevelUp { name, level: 0 },
+            EventKind::PlanetClaimed => WebhookEvent::PlanetClaimed { planet: 0, owner: 0 },
+            EventKind::ServerStarted => WebhookEvent::ServerStarted,
+            EventKind::ServerStopped => WebhookEvent::ServerStopped,
+            EventKind::BanIssued => WebhookEvent::BanIssued { name },
+        }
+    }
+}
+
+/// `template` with `event`'s fields filled in.
+pub fn render(template: &str, event: &WebhookEvent) -> Result<String, String> {
+    let fields = event.fields();
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{") {
+            out.push('{');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("}}") {
+            out.push('}');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix('{') {
+            let end = after.find('}').ok_or("unclosed `{` (write `{{` for a brace)")?;
+            let (_, value) = fields.iter().find(|(field, _)| *field == &after[..end])
+                .ok_or_else(|| format!("`{{{}}}` is not a field of {}", &after[..end], event.kind()))?;
+            out.push_str(value);
+            rest = &after[end + 1..];
+        } else {
+            return Err("unmatched `}` (write `}}` for a brace)".to_string());
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Where a webhook POSTs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookUrl {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub authority: String,  // for the Host header
+    pub path: String,
+}
+
+impl std::str::FromStr for WebhookUrl {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let (tls, rest) = match url.split_once("://") {
+            Some(("http", rest)) => (false, rest),
+            Some(("https", rest)) => (true, rest),
+            _ => return Err(format!("{} is not an http or https URL", url)),
+        };
+        #[cfg(not(feature = "tls"))]
+        if tls {
+            return Err(format!("{} needs the `tls` feature", url));
+        }
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let default_port = if tls { 443 } else { 80 };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().map_err(|_| format!("invalid port in {}", url))?)
+            }
+            _ => (authority, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("no host in {}", url));
+        }
+        Ok(WebhookUrl { tls, host: host.to_string(), port, authority: authority.to_string(), path: path.to_string() })
+    }
+}
+
+impl std::fmt::Display for WebhookUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}://{}{}", if self.tls { "https" } else { "http" }, self.authority, self.path)
+    }
+}
+
+/// One `[[webhooks]]` entry of the config.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<EventKind>,  // every kind when empty
+}
+
+/// Everything the webhooks are configured with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookSettings {
+    pub hooks: Vec<WebhookConfig>,
+    pub templates: HashMap<String, String>,  // by event kind, over `DEFAULT_TEMPLATES`
+    pub queue_limit: usize,  // events waiting per webhook
+    pub retries: u32,
+    pub backoff: Duration,  // before the first retry, doubling after
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        WebhookSettings {
+            hooks: Vec::new(),
+            templates: HashMap::new(),
+            queue_limit: DEFAULT_QUEUE_LIMIT,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+        }
+    }
+}
+
+impl WebhookSettings {
+    /// Everything wrong with the URLs, templates and queue size.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.queue_limit == 0 {
+            problems.push("webhook_queue must be positive".to_string());
+        }
+        for (i, hook) in self.hooks.iter().enumerate() {
+            if let Err(e) = hook.url.parse::<WebhookUrl>() {
+                problems.push(format!("webhooks[{}].url: {}", i, e));
+            }
+        }
+        let mut kinds: Vec<&String> = self.templates.keys().collect();
+        kinds.sort();
+        for kind in kinds {
+            let checked = kind.parse().and_then(|parsed| render(&self.templates[kind], &WebhookEvent::example(parsed)));
+            if let Err(e) = checked {
+                problems.push(format!("webhook_templates.{}: {}", kind, e));
+            }
+        }
+        problems
+    }
+}
+
+/// How the webhooks have fared, over all of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WebhookStats {
+    pub sent: u64,
+    pub failed: u64,  // given up on after the retries
+    pub dropped: u64,  // a full queue had no room for them
+}
+
+impl std::fmt::Display for WebhookStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "webhooks sent={} failed={} dropped={}", self.sent, self.failed, self.dropped)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+enum Job {
+    Post(Arc<str>),
+    Flush(oneshot::Sender<()>),
+}
+
+struct Hook {
+    url: WebhookUrl,
+    events: Vec<EventKind>,
+    tx: mpsc::Sender<Job>,
+    overflowing: AtomicBool,  // dropping since the last event that fit
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    text: &'a str,
+    content: &'a str,
+    at_unix_ms: u64,
+}
+
+/// Cheap, cloneable handle for notifying the webhooks.
+#[derive(Clone)]
+pub struct Webhooks {
+    hooks: Arc<[Hook]>,
+    templates: Arc<HashMap<EventKind, String>>,
+    retries: u32,
+    backoff: Duration,
+    counters: Arc<Counters>,
+    idle: Arc<Mutex<Vec<mpsc::Receiver<Job>>>>,  // each hook's queue, until `run`
+}
+
+impl Webhooks {
+    /// Webhooks as `settings` describe, not yet running; fails on the first
+    /// of `WebhookSettings::problems`.
+    pub fn new(settings: &WebhookSettings) -> Result<Self, String> {
+        if let Some(problem) = settings.problems().into_iter().next() {
+            return Err(problem);
+        }
+        let mut templates: HashMap<EventKind, String> =
+            DEFAULT_TEMPLATES.iter().map(|(kind, text)| (*kind, text.to_string())).collect();
+        for (kind, text) in &settings.templates {
+            templates.insert(kind.parse()?, text.clone());
+        }
+        let mut hooks = Vec::new();
+        let mut idle = Vec::new();
+        for hook in &settings.hooks {
+            let (tx, rx) = mpsc::channel(settings.queue_limit);
+            hooks.push(Hook { url: hook.url.parse()?, events: hook.events.clone(), tx, overflowing: AtomicBool::new(false) });
+            idle.push(rx);
+        }
+        Ok(Webhooks {
+            hooks: hooks.into(),
+            templates: Arc::new(templates),
+            retries: settings.retries,
+            backoff: settings.backoff,
+            counters: Arc::new(Counters::default()),
+            idle: Arc::new(Mutex::new(idle)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Starts each webhook's task; events notified before were queued.
+    pub fn run(&self) -> Vec<JoinHandle<()>> {
+        let queues = std::mem::take(&mut *self.idle.lock().unwrap());
+        queues.into_iter().enumerate().map(|(i, rx)| tokio::spawn(self.clone().deliver_all(i, rx))).collect()
+    }
+
+    /// Queues `event`, which happened at `unix_ms`, for every webhook that
+    /// wants it, dropping it for those whose queues are full.
+    pub fn notify(&self, event: &WebhookEvent, unix_ms: u64) {
+        let kind = event.kind();
+        let wanted = |hook: &&Hook| hook.events.is_empty() || hook.events.contains(&kind);
+        if !self.hooks.iter().any(|hook| wanted(&hook)) {
+            return;
+        }
+        // Checked when the settings were
+        let text = render(&self.templates[&kind], event).unwrap_or_default();
+        let payload = Payload { event, text: &text, content: &text, at_unix_ms: unix_ms };
+        let body: Arc<str> = serde_json::to_string(&payload).expect("webhook payloads serialize").into();
+        for hook in self.hooks.iter().filter(wanted) {
+            match hook.tx.try_send(Job::Post(body.clone())) {
+                Ok(()) => hook.overflowing.store(false, Ordering::Relaxed),
+                Err(_) => {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    if !hook.overflowing.swap(true, Ordering::Relaxed) {
+                        println!("📮 Webhook queue for {} is full; dropping events", hook.url);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits, for at most `within`, until every event queued so far has been
+    /// sent or given up on. Only for after `run`.
+    pub async fn flush(&self, within: Duration) -> bool {
+        let flushed = async {
+            for hook in self.hooks.iter() {
+                let (done, flushed) = oneshot::channel();
+                if hook.tx.send(Job::Flush(done)).await.is_ok() {
+                    let _ = flushed.await;
+                }
+            }
+        };
+        tokio::time::timeout(within, flushed).await.is_ok()
+    }
+
+    pub fn stats(&self) -> WebhookStats {
+        WebhookStats {
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn deliver_all(self, hook: usize, mut rx: mpsc::Receiver<Job>) {
+        let url = &self.hooks[hook].url;
+        while let Some(job) = rx.recv().await {
+            match job {
+                Job::Post(body) => match self.deliver(url, &body).await {
+                    Ok(()) => { self.counters.sent.fetch_add(1, Ordering::Relaxed); }
+                    Err(e) => {
+                        self.counters.failed.fetch_add(1, Ordering::Relaxed);
+                        println!("📮 Gave up on a webhook to {}: {}", url, e);
+                    }
+                },
+                Job::Flush(done) => { let _ = done.send(()); }
+            }
+        }
+    }
+
+    /// POSTs `body` to `url`, retrying with backoff.
+    async fn deliver(&self, url: &WebhookUrl, body: &str) -> Result<(), String> {
+        let mut wait = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let error = match tokio::time::timeout(POST_TIMEOUT, post(url, body)).await {
+                Ok(Ok(status)) if (200..300).contains(&status) => return Ok(()),
+                Ok(Ok(status)) if (400..500).contains(&status) && status != 408 && status != 429 => {
+                    return Err(format!("HTTP {}", status));
+                }
+                Ok(Ok(status)) => format!("HTTP {}", status),
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => format!("no answer within {}s", POST_TIMEOUT.as_secs()),
+            };
+            if attempt == self.retries {
+                return Err(format!("{} after {} attempt(s)", error, attempt + 1));
+            }
+            attempt += 1;
+            tokio::time::sleep(wait).await;
+            wait = wait.saturating_mul(2);
+        }
+    }
+}
+
+impl EventSink for Webhooks {
+    fn consume(&self, at: EventTime, event: &GameEvent) {
+        if let Some(event) = WebhookEvent::from_game(event) {
+            self.notify(&event, at.unix_ms);
+        }
+    }
+
+    fn is_listening(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+/// POSTs `body` as JSON and returns the status code of the answer.
+async fn post(url: &WebhookUrl, body: &str) -> std::io::Result<u16> {
+    let stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: galavox\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        url.path, url.authority, body.len(), body,
+    );
+    #[cfg(feature = "tls")]
+    if url.tls {
+        return exchange(crate::tls::connect(stream, &url.host).await?, &request).await;
+    }
+    exchange(stream, &request).await
+}
+
+async fn exchange(mut stream: impl AsyncRead + AsyncWrite + Unpin, request: &str) -> std::io::Result<u16> {
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+    let mut status_line = String::new();
+    BufReader::new(stream).take(1024).read_line(&mut status_line).await?;
+    status_line.split(' ').nth(1).and_then(|status| status.parse().ok())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("not an HTTP answer: {:?}", status_line.trim_end())))
+}
@@ -0,0 +1,236 @@
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::economy::RegenerationRates;
+use crate::id_hash::IdMap;
+use crate::module_effects::ModuleEffects;
+use crate::palette::PlanetPalettes;
+use crate::protocol::{normalize_name, GameState, Moon, Planet, Player, Position};
+use crate::world_clock::DEFAULT_DAY_LENGTH;
+use crate::worldgen::MIN_PLANET_GAP;
+
+/*
+World bounds, planet lookup by id or name, moon orbits, and saving/loading
+worlds as JSON.
+
+The world is a sphere of `WorldConfig::radius` around the origin; a position
+exactly on the boundary is inside. Out-of-bounds positions are clamped onto
+the boundary along the line to the origin. Non-finite positions are neither
+inside nor clampable; `PositionUpdate::validate` rejects them before any
+bounds check.
+
+`GameState::planets` stays a plain public `Vec`, so the id index is a cache:
+it is rebuilt whenever a lookup finds it stale (a planet was added, removed
+or moved since it was built) and is never serialized or compared.
+
+Moons are not simulated step by step: each one's orbit is fixed, so its
+position is a function of the server time alone, the same on the server and
+every client.
+
+Worlds saved before planets had ids have no `id` fields. `load_world`
+assigns the missing ones in list order, counting up from the highest id
+already present, so loading the same file always gives the same ids.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldConfig {
+    pub radius: f32,
+    /// Consecutive out-of-bounds updates after which the player is sent back
+    /// to their spawn point instead of clamped; `None` never does.
+    pub teleport_after: Option<u32>,
+    /// Seed for generating the planets; `None` picks a random one.
+    pub seed: Option<u64>,
+    /// Chance that a generated planet gets a first moon, and then each
+    /// further one up to `MAX_MOONS`.
+    pub moon_chance: f64,
+    /// Asteroid belts to generate around the planets.
+    pub belts: u32,
+    /// Where generated planets take their colors from; random when empty.
+    pub palettes: PlanetPalettes,
+    /// How fast planets of each module type grow their resources back.
+    pub regeneration: RegenerationRates,
+    /// What Defense, Trade and Research planets do: the defended radius,
+    /// the sale rate and its bonus near Trade planets, and the XP
+    /// multiplier on Research planets (see `module_effects`).
+    pub modules: ModuleEffects,
+    /// How long a whole day of `world_time` lasts (see `world_clock`).
+    pub day_length: Duration,
+    /// The least distance between generated planets, surface to surface.
+    pub min_planet_gap: f32,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig { radius: 10_000.0, teleport_after: None, seed: None, moon_chance: 0.5, belts: 2, palettes: PlanetPalettes::default(), regeneration: RegenerationRates::default(), modules: ModuleEffects::default(), day_length: DEFAULT_DAY_LENGTH, min_planet_gap: MIN_PLANET_GAP }
+    }
+}
+
+fn length(position: &Position) -> f64 {
+    // In f64 so that huge but finite coordinates do not overflow to infinity
+    let (x, y, z) = (position.x as f64, position.y as f64, position.z as f64);
+    (x * x + y * y + z * z).sqrt()
+}
+
+impl WorldConfig {
+    pub fn contains(&self, position: &Position) -> bool {
+        length(position) <= self.radius as f64
+    }
+
+    /// The nearest point no further than `radius` from the origin.
+    pub fn clamp_to(position: &Position, radius: f32) -> Position {
+        let length = length(position);
+        if length <= radius as f64 {
+            return position.clone();
+        }
+        let scale = radius as f64 / length;
+        Position {
+            x: (position.x as f64 * scale) as f32,
+            y: (position.y as f64 * scale) as f32,
+            z: (position.z as f64 * scale) as f32,
+        }
+    }
+
+    pub fn clamp(&self, position: &Position) -> Position {
+        Self::clamp_to(position, self.radius)
+    }
+}
+
+impl Moon {
+    /// Where the moon is at `server_time_ms` around a planet centred at `center`.
+    pub fn position_at(&self, center: &Position, server_time_ms: u64) -> Position {
+        let seconds = server_time_ms as f64 / 1000.0;
+        let angle = self.phase as f64 + self.angular_speed as f64 * seconds;
+        let radius = self.orbit_radius as f64;
+        Position {
+            x: center.x + (angle.cos() * radius) as f32,
+            y: center.y,
+            z: center.z + (angle.sin() * radius) as f32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoundsCorrection {
+    Clamped(Position),
+    Teleported(Position),
+}
+
+impl BoundsCorrection {
+    pub fn position(&self) -> &Position {
+        match self {
+            BoundsCorrection::Clamped(position) | BoundsCorrection::Teleported(position) => position,
+        }
+    }
+}
+
+/// Per-connection count of consecutive out-of-bounds updates.
+#[derive(Debug, Clone)]
+pub struct BoundsTracker {
+    config: WorldConfig,
+    strikes: u32,
+}
+
+impl BoundsTracker {
+    pub fn new(config: WorldConfig) -> Self {
+        BoundsTracker { config, strikes: 0 }
+    }
+
+    /// `None` when `position` is inside the world, otherwise where the player goes instead.
+    pub fn check(&mut self, position: &Position, spawn: &Position) -> Option<BoundsCorrection> {
+        if self.config.contains(position) {
+            self.strikes = 0;
+            return None;
+        }
+        self.strikes += 1;
+        match self.config.teleport_after {
+            Some(limit) if self.strikes >= limit => {
+                self.strikes = 0;
+                Some(BoundsCorrection::Teleported(spawn.clone()))
+            }
+            _ => Some(BoundsCorrection::Clamped(self.config.clamp(position))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PlanetIndex(Mutex<IdMap<u32, usize>>);
+
+impl Clone for PlanetIndex {
+    fn clone(&self) -> Self {
+        PlanetIndex(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+impl PartialEq for PlanetIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true  // a cache, not part of the state
+    }
+}
+
+fn build_index(planets: &[Planet]) -> IdMap<u32, usize> {
+    planets.iter().enumerate().map(|(i, p)| (p.id, i)).collect()
+}
+
+impl GameState {
+    pub fn new(planets: Vec<Planet>, players: Vec<Player>, initial_player_location: Position) -> Self {
+        GameState { planets, players, initial_player_location, belts: Vec::new(), world_time: 0.0, planet_index: PlanetIndex::default() }
+    }
+
+    /// The lowest id above every planet and notable asteroid.
+    pub fn next_body_id(&self) -> u32 {
+        let asteroids = self.belts.iter().flat_map(|belt| &belt.notable).map(|a| a.id);
+        self.planets.iter().map(|p| p.id).chain(asteroids).map(|id| id + 1).max().unwrap_or(0)
+    }
+
+    /// Where the planet with this id is in `planets`.
+    pub fn planet_position(&self, id: u32) -> Option<usize> {
+        let mut index = self.planet_index.0.lock().unwrap();
+        let fresh = |index: &IdMap<u32, usize>| index.get(&id).copied().filter(|&i| self.planets.get(i).is_some_and(|p| p.id == id));
+        if let Some(i) = fresh(&index) {
+            return Some(i);
+        }
+        *index = build_index(&self.planets);
+        fresh(&index)
+    }
+
+    pub fn planet_by_id(&self, id: u32) -> Option<&Planet> {
+        self.planet_position(id).map(|i| &self.planets[i])
+    }
+
+    pub fn planet_by_id_mut(&mut self, id: u32) -> Option<&mut Planet> {
+        self.planet_position(id).map(|i| &mut self.planets[i])
+    }
+
+    /// The planet called `name`, however it is capitalized; not indexed, as
+    /// names are only looked up when a player asks.
+    pub fn planet_by_name(&self, name: &str) -> Option<&Planet> {
+        let name = normalize_name(name);
+        self.planets.iter().find(|p| normalize_name(&p.name) == name)
+    }
+}
+
+pub fn save_world(state: &GameState) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(state)
+}
+
+/// Parses a saved world, assigning ids to planets saved without one.
+pub fn load_world(json: &str) -> serde_json::Result<GameState> {
+    let mut value: Value = serde_json::from_str(json)?;
+    if let Some(planets) = value.get_mut("planets").and_then(Value::as_array_mut) {
+        assign_missing_planet_ids(planets);
+    }
+    serde_json::from_value(value)
+}
+
+fn assign_missing_planet_ids(planets: &mut [Value]) {
+    let mut next_id = planets.iter()
+        .filter_map(|p| p.get("id").and_then(Value::as_u64))
+        .max()
+        .map_or(0, |max| max as u32 + 1);
+    for planet in planets.iter_mut().filter_map(Value::as_object_mut) {
+        if !planet.contains_key("id") {
+            planet.insert("id".to_string(), next_id.into());
+            next_id += 1;
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+/*
+The world's time of day, for clients to draw a shared day and night.
+
+`world_time` runs from 0 to 1 over a day of `day_length` (20 minutes
+unless configured), 0 being midnight, and wraps back to 0 rather than
+reaching 1. The server moves it on by one broadcast interval every tick,
+and by the ticks skipped when it wakes from hibernation (see `hibernate`);
+while the simulation is paused it stands still with everything else (see
+`pause`).
+
+Every State carries it as `state.world_time`, so clients that lerp between
+snapshots see the sky turn smoothly. The admin `settime <fraction>` command
+jumps it, and sends everyone a `TimeSet` so they snap to the new time
+instead of sweeping across the sky to it. It is saved with the world (see
+`world::save_world` and `world_file`), so a restart from a saved world
+carries on at the same time of day. The journal does not follow it (see
+`journal`): a replayed world keeps the time it was created with.
+
+`Phase` names the part of the day a time falls in, for clients that only
+want to know whether it is light out.
+*/
+
+pub const DEFAULT_DAY_LENGTH: Duration = Duration::from_secs(20 * 60);
+
+/// The part of the day a `world_time` falls in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Dawn,   // from 0.2
+    Day,    // from 0.3
+    Dusk,   // from 0.7
+    Night,  // from 0.8, through midnight at 0
+}
+
+impl Phase {
+    pub fn of(world_time: f32) -> Phase {
+        match world_time {
+            t if (0.2..0.3).contains(&t) => Phase::Dawn,
+            t if (0.3..0.7).contains(&t) => Phase::Day,
+            t if (0.7..0.8).contains(&t) => Phase::Dusk,
+            _ => Phase::Night,
+        }
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Phase::Dawn => "dawn",
+            Phase::Day => "day",
+            Phase::Dusk => "dusk",
+            Phase::Night => "night",
+        })
+    }
+}
+
+/// `world_time` moved on by `by` of a day lasting `day_length`, wrapped
+/// into 0..1.
+pub fn advance(world_time: f32, by: Duration, day_length: Duration) -> f32 {
+    let days = by.as_secs_f64() / day_length.as_secs_f64();
+    let time = (world_time as f64 + days).rem_euclid(1.0) as f32;
+    // Just under a whole day can round up to 1 in f32
+    if time >= 1.0 { 0.0 } else { time }
+}
+
+/// Checks a time an admin asked for, taking 1 as the midnight it is.
+pub fn parse_world_time(value: &str) -> Result<f32, String> {
+    let time: f32 = value.parse().map_err(|_| format!("invalid time of day: {}", value))?;
+    if !(0.0..=1.0).contains(&time) {
+        return Err("the time of day must be between 0 and 1".to_string());
+    }
+    Ok(if time == 1.0 { 0.0 } else { time })
+}
+
+/// The time of day and how long a day lasts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldClock {
+    time: f32,
+    day_length: Duration,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        WorldClock { time: 0.0, day_length: DEFAULT_DAY_LENGTH }
+    }
+}
+
+impl WorldClock {
+    pub fn new(time: f32, day_length: Duration) -> Self {
+        WorldClock { time, day_length }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn day_length(&self) -> Duration {
+        self.day_length
+    }
+
+    pub fn set(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    pub fn set_day_length(&mut self, day_length: Duration) {
+        self.day_length = day_length;
+    }
+
+    /// Moves the time on by `by`, returning the new time.
+    pub fn advance(&mut self, by: Duration) -> f32 {
+        self.time = advance(self.time, by, self.day_length);
+        self.time
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use crate::admin::{surface_gap, PlanetLimits};
+use crate::planet_names::{name_planets, valid_planet_name};
+use crate::protocol::{normalize_name, Belt, GameState, Planet, Position, MAX_MOONS, MODULE_TYPES};
+use crate::snapshot::WorldView;
+use crate::world::WorldConfig;
+
+/*
+Worlds as files for people to write: `server --export-world out.json`
+writes the world the server would start with and exits, and
+`--import-world in.json` (or `import_world` in the config) starts from one.
+
+    {
+      "radius": 10000.0,
+      "seed": 42,
+      "initial_player_location": { "x": 0.0, "y": 0.0, "z": 0.0 },
+      "planets": [
+        { "id": 0, "size": 120.0, "colors": [...], "module_type": 2,
+          "position": { ... }, "owner": null, "moons": [...],
+          "resources": 800, "capacity": 1200, "name": "Vesh" }
+      ],
+      "belts": [...],
+      "world_time": 0.25
+    }
+
+`radius` replaces the configured `world_radius`; `seed` is a note of
+what the world was generated from (null when it was random or made by
+hand). `world_time` is the time of day to start at, midnight when omitted
+(see `world_clock`). Unlike `world` saves, there are no players and nothing is filled
+in: every planet needs all of its fields but its `name`, and unknown
+top-level keys are rejected so a typo does not silently lose a setting.
+Planets without a name are named when the world is loaded, as if generated
+from `seed` (see `planet_names`).
+
+A file that is not valid JSON, or has a value of the wrong type, fails
+with serde's message and the line and column. A well-formed file is then
+checked as a whole and every problem reported by field, e.g.
+`planets[3].size`: sizes outside `PlanetLimits`, non-finite numbers,
+planets outside the radius, unknown module types, more resources than
+capacity, more than `MAX_MOONS` moons, ids used twice (planets and notable
+asteroids share one id space), invalid planet names and names used twice,
+and planets closer together than the
+minimum gap, the same limits the admin `planet` commands enforce. The
+`validate-world` command lists the planets too close together in any world
+file, saved or designed, without loading it.
+*/
+
+/// The file `--export-world` writes and `--import-world` reads.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorldFile {
+    pub radius: f32,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    pub initial_player_location: Position,
+    pub planets: Vec<Planet>,
+    #[serde(default)]
+    pub belts: Vec<Belt>,
+    #[serde(default)]
+    pub world_time: f32,
+}
+
+#[derive(Debug)]
+pub enum WorldFileError {
+    Json(serde_json::Error),
+    /// Every problem found, one per entry
+    Invalid(Vec<String>),
+}
+
+impl std::fmt::Display for WorldFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldFileError::Json(e) => write!(f, "invalid world file: {}", e),
+            WorldFileError::Invalid(problems) => {
+                write!(f, "invalid world file:")?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorldFileError {}
+
+impl WorldFile {
+    /// The world in `state` (its players left out) within `config`'s radius.
+    pub fn new(state: &impl WorldView, config: &WorldConfig) -> Self {
+        WorldFile {
+            radius: config.radius,
+            seed: config.seed,
+            initial_player_location: state.initial_player_location().clone(),
+            planets: state.planets().to_vec(),
+            belts: state.belts().to_vec(),
+            world_time: state.world_time(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Parses and validates a world file.
+    pub fn parse(json: &str, limits: &PlanetLimits) -> Result<Self, WorldFileError> {
+        let file: WorldFile = serde_json::from_str(json).map_err(WorldFileError::Json)?;
+        match file.problems(limits) {
+            problems if problems.is_empty() => Ok(file),
+            problems => Err(WorldFileError::Invalid(problems)),
+        }
+    }
+
+    /// Everything wrong with the world, by field.
+    pub fn problems(&self, limits: &PlanetLimits) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !(self.radius.is_finite() && self.radius > 0.0) {
+            problems.push(format!("radius: {} is not a positive number", self.radius));
+        }
+        if !(0.0..1.0).contains(&self.world_time) {
+            problems.push(format!("world_time: {} is not at least 0 and below 1", self.world_time));
+        }
+        let world = WorldConfig { radius: self.radius, ..WorldConfig::default() };
+        let p = &self.initial_player_location;
+        if ![p.x, p.y, p.z].iter().all(|v| v.is_finite()) {
+            problems.push("initial_player_location: coordinates must be finite".to_string());
+        } else if !world.contains(p) {
+            problems.push("initial_player_location: outside the world radius".to_string());
+        }
+
+        let mut ids: HashMap<u32, String> = HashMap::new();
+        let mut claim = |id: u32, field: String, problems: &mut Vec<String>| {
+            match ids.get(&id) {
+                Some(first) => problems.push(format!("{}: id {} is already used by {}", field, id, first)),
+                None => { ids.insert(id, field); }
+            }
+        };
+
+        for (i, planet) in self.planets.iter().enumerate() {
+            let field = format!("planets[{}]", i);
+            claim(planet.id, format!("{}.id", field), &mut problems);
+            let p = &planet.position;
+            if !planet.size.is_finite() || planet.size < limits.min_size || planet.size > limits.max_size {
+                problems.push(format!("{}.size: {} is outside {} to {}", field, planet.size, limits.min_size, limits.max_size));
+            }
+            if ![p.x, p.y, p.z].iter().all(|v| v.is_finite()) {
+                problems.push(format!("{}.position: coordinates must be finite", field));
+            } else if !world.contains(p) {
+                problems.push(format!("{}.position: outside the world radius", field));
+            }
+            if planet.module_type >= MODULE_TYPES {
+                problems.push(format!("{}.module_type: {} is not a known module type (0 to {})", field, planet.module_type, MODULE_TYPES - 1));
+            }
+            if planet.resources > planet.capacity {
+                problems.push(format!("{}.resources: {} is more than the capacity, {}", field, planet.resources, planet.capacity));
+            }
+            if let Err(e) = planet.terrain.validate() {
+                problems.push(format!("{}.terrain: {}", field, e));
+            }
+            if planet.moons.len() > MAX_MOONS {
+                problems.push(format!("{}.moons: {} moons, at most {}", field, planet.moons.len(), MAX_MOONS));
+            }
+            if !planet.name.is_empty() {
+                if !valid_planet_name(&planet.name) {
+                    problems.push(format!("{}.name: {:?} is not a valid planet name", field, planet.name));
+                } else if let Some(j) = self.planets[..i].iter().position(|other| normalize_name(&other.name) == normalize_name(&planet.name)) {
+                    problems.push(format!("{}.name: {} is already used by planets[{}]", field, planet.name, j));
+                }
+            }
+            for (j, other) in self.planets[..i].iter().enumerate() {
+                let gap = surface_gap(planet, other);
+                if gap < 0.0 {
+                    problems.push(format!("{}: overlaps planets[{}] by {:.1}", field, j, -gap));
+                } else if gap < limits.min_gap {
+                    problems.push(format!("{}: only {:.1} from planets[{}], closer than the minimum gap of {}", field, gap, j, limits.min_gap));
+                }
+            }
+        }
+
+        for (i, belt) in self.belts.iter().enumerate() {
+            for (j, asteroid) in belt.notable.iter().enumerate() {
+                claim(asteroid.id, format!("belts[{}].notable[{}].id", i, j), &mut problems);
+            }
+        }
+        problems
+    }
+
+    /// The world to start from.
+    pub fn into_state(mut self) -> GameState {
+        name_planets(&mut self.planets, self.seed.unwrap_or_default());
+        self.planets.iter_mut().for_each(Planet::refresh_display);
+        let mut state = GameState::new(self.planets, Vec::new(), self.initial_player_location);
+        state.belts = self.belts;
+        state.world_time = self.world_time;
+        state
+    }
+}
@@ -0,0 +1,181 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::admin::PlanetLimits;
+use crate::module_effects::ModuleKind;
+use crate::protocol::{Planet, Position};
+use crate::world::{load_world, WorldConfig};
+use crate::world_file::WorldFile;
+
+/*
+Statistics on a world file, for `worldinfo <path> [--json]`, without
+starting a server.
+
+Both kinds of file are read: worlds exported with `--export-world` (or
+written by hand), told apart by their `radius`, and worlds saved for
+`--world`, whose radius is the configured one (`--world-radius`, else the
+default). Either is checked with the same `WorldFile::problems` that
+`--import-world` uses, against `--min-planet-gap` (else the default), and
+everything it finds is listed as a warning rather than refusing the file.
+
+The report has the planets by module type, a histogram of their sizes in
+buckets `SIZE_BUCKET` wide, the distance from each planet's centre to its
+nearest neighbour's, the resources left against capacity, how many
+planets are owned, and the bounding radius: how far from the origin the
+furthest planet's surface reaches. `--json` prints the same as one JSON
+object.
+*/
+
+/// The width of each bucket of the size histogram.
+pub const SIZE_BUCKET: f32 = 50.0;
+
+/// Which kind of file a world came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorldSource {
+    Exported,
+    Saved,
+}
+
+/// Planets with sizes from `from` up to, not including, `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SizeBucket {
+    pub from: f32,
+    pub to: f32,
+    pub count: usize,
+}
+
+/// Centre-to-centre distances from each planet to its nearest neighbour.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DistanceStats {
+    pub min: f32,
+    pub mean: f32,
+    pub median: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorldInfo {
+    pub source: WorldSource,
+    pub radius: f32,
+    pub planets: usize,
+    pub by_module_type: BTreeMap<u8, usize>,
+    pub sizes: Vec<SizeBucket>,  // from the smallest planet's bucket to the largest's
+    pub nearest_neighbour: Option<DistanceStats>,  // None with fewer than two planets
+    pub resources: u64,
+    pub capacity: u64,
+    pub claimed: usize,
+    pub unclaimed: usize,
+    pub moons: usize,
+    pub belts: usize,
+    pub bounding_radius: f32,
+    pub warnings: Vec<String>,
+}
+
+fn distance(a: &Position, b: &Position) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// Reads an exported or saved world; a saved one gets `radius`.
+pub fn read_world(json: &str, radius: f32) -> Result<(WorldFile, WorldSource), String> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if value.get("radius").is_some() {
+        let file = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        return Ok((file, WorldSource::Exported));
+    }
+    let state = load_world(json).map_err(|e| e.to_string())?;
+    let file = WorldFile::new(&state, &WorldConfig { radius, ..WorldConfig::default() });
+    Ok((file, WorldSource::Saved))
+}
+
+fn size_histogram(planets: &[Planet]) -> Vec<SizeBucket> {
+    let bucket = |size: f32| (size.max(0.0) / SIZE_BUCKET).floor() as usize;
+    let Some(last) = planets.iter().filter(|p| p.size.is_finite()).map(|p| bucket(p.size)).max() else {
+        return Vec::new();
+    };
+    let first = planets.iter().filter(|p| p.size.is_finite()).map(|p| bucket(p.size)).min().unwrap_or(last);
+    (first..=last).map(|i| SizeBucket {
+        from: i as f32 * SIZE_BUCKET,
+        to: (i + 1) as f32 * SIZE_BUCKET,
+        count: planets.iter().filter(|p| p.size.is_finite() && bucket(p.size) == i).count(),
+    }).collect()
+}
+
+fn nearest_neighbour(planets: &[Planet]) -> Option<DistanceStats> {
+    let mut nearest: Vec<f32> = planets.iter().enumerate()
+        .filter_map(|(i, planet)| planets.iter().enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, other)| distance(&planet.position, &other.position))
+            .min_by(f32::total_cmp))
+        .collect();
+    if nearest.is_empty() {
+        return None;
+    }
+    nearest.sort_by(f32::total_cmp);
+    let n = nearest.len();
+    let median = if n % 2 == 1 { nearest[n / 2] } else { (nearest[n / 2 - 1] + nearest[n / 2]) / 2.0 };
+    Some(DistanceStats { min: nearest[0], mean: nearest.iter().sum::<f32>() / n as f32, median, max: nearest[n - 1] })
+}
+
+impl WorldInfo {
+    /// The statistics of `file`, with its problems against `limits`.
+    pub fn of(file: &WorldFile, source: WorldSource, limits: &PlanetLimits) -> Self {
+        let planets = &file.planets;
+        let mut by_module_type = BTreeMap::new();
+        for planet in planets {
+            *by_module_type.entry(planet.module_type).or_insert(0) += 1;
+        }
+        let claimed = planets.iter().filter(|p| p.owner.is_some()).count();
+        let origin = Position { x: 0.0, y: 0.0, z: 0.0 };
+        WorldInfo {
+            source,
+            radius: file.radius,
+            planets: planets.len(),
+            by_module_type,
+            sizes: size_histogram(planets),
+            nearest_neighbour: nearest_neighbour(planets),
+            resources: planets.iter().map(|p| p.resources as u64).sum(),
+            capacity: planets.iter().map(|p| p.capacity as u64).sum(),
+            claimed,
+            unclaimed: planets.len() - claimed,
+            moons: planets.iter().map(|p| p.moons.len()).sum(),
+            belts: file.belts.len(),
+            bounding_radius: planets.iter().map(|p| distance(&p.position, &origin) + p.size / 2.0).fold(0.0, f32::max),
+            warnings: file.problems(limits),
+        }
+    }
+
+    /// The report as text, a line per statistic.
+    pub fn render(&self) -> String {
+        let source = match self.source {
+            WorldSource::Exported => "exported",
+            WorldSource::Saved => "saved",
+        };
+        let mut lines = vec![format!("{} planets, {} world of radius {}", self.planets, source, self.radius)];
+        let types: Vec<String> = self.by_module_type.iter()
+            .map(|(module_type, count)| match ModuleKind::of(*module_type) {
+                ModuleKind::Unknown => format!("{} of type {}", count, module_type),
+                kind => format!("{} of type {} ({:?})", count, module_type, kind),
+            })
+            .collect();
+        if !types.is_empty() {
+            lines.push(format!("module types: {}", types.join(", ")));
+        }
+        lines.push("sizes:".to_string());
+        lines.extend(self.sizes.iter().map(|b| format!("  {:>5}-{:<5} {:>4} {}", b.from, b.to, b.count, "#".repeat(b.count)).trim_end().to_string()));
+        match &self.nearest_neighbour {
+            Some(d) => lines.push(format!("nearest neighbour: min {:.1}, mean {:.1}, median {:.1}, max {:.1}", d.min, d.mean, d.median, d.max)),
+            None => lines.push("nearest neighbour: none".to_string()),
+        }
+        lines.push(format!("resources: {} of {} capacity", self.resources, self.capacity));
+        lines.push(format!("claimed: {}, unclaimed: {}", self.claimed, self.unclaimed));
+        lines.push(format!("moons: {}, belts: {}", self.moons, self.belts));
+        lines.push(format!("bounding radius: {:.1}", self.bounding_radius));
+        if self.warnings.is_empty() {
+            lines.push("no warnings".to_string());
+        } else {
+            lines.push(format!("{} warning(s):", self.warnings.len()));
+            lines.extend(self.warnings.iter().map(|w| format!("  {}", w)));
+        }
+        lines.join("\n")
+    }
+}
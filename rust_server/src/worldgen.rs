@@ -0,0 +1,355 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+use std::str::FromStr;
+use crate::protocol::{Asteroid, Belt, Color, GameState, Moon, Planet, Position, TerrainParams, MAX_MOONS, MODULE_TYPES};
+use crate::color::LabelContrast;
+use crate::economy::capacity_for;
+use crate::module_effects::ModuleKind;
+use crate::planet_names::name_planets;
+use crate::world::WorldConfig;
+
+/*
+World generation. A `WorldGenerator` lays out the planets of a new world;
+moons and asteroid belts are added the same way whatever the layout. Three
+layouts are built in and chosen with the `world_layout` config key:
+
+- `ring` (the default): ten planets on a ring 500-700 units out
+- `spiral`: planets strung along the arms of a spiral galaxy
+- `sphere`: planets scattered uniformly through a ball around the origin
+
+Programs embedding the server may pass their own generator to
+`GameServer::with_generator`. Generators draw all their randomness from the
+`rng` they are given, so the same seed always gives the same world, and
+every built-in one keeps planets at least `min_planet_gap` apart (surface to
+surface; `MIN_PLANET_GAP` unless configured). Planet colors come from the
+config's palettes (see `palette`). Terrain comes from `generate_terrain`,
+seeded by the world seed and the planet's id (see `terrain_seed`) rather
+than drawn from `rng`, so worlds seeded before planets had terrain are laid
+out as they were; names are given the same way, once the generator is
+done, whichever generator it was (see `planet_names`). A planet placed too
+close to one already placed is tried elsewhere, up to `PLACEMENT_ATTEMPTS`
+times, and then left out, so crowded layouts may have fewer planets. The ring only moves a planet when it has to,
+so a seeded ring whose planets fit is laid out as it always was. The admin
+`planet` commands and imported world files keep the same gap (see
+`admin::validate_planet`).
+*/
+
+pub const PLANET_COUNT: u32 = 10;
+pub const MIN_PLANET_GAP: f32 = 20.0;
+/// How often a planet is re-placed before it is left out.
+const PLACEMENT_ATTEMPTS: u32 = 100;
+
+pub trait WorldGenerator: Send + Sync {
+    fn generate(&self, config: &WorldConfig, rng: &mut StdRng) -> GameState;
+}
+
+/// A world from `generator`, seeded from `config.seed` when there is one,
+/// with every planet named (see `planet_names`) and its label colors
+/// worked out (see `color`).
+pub fn generate_world(generator: &dyn WorldGenerator, config: &WorldConfig) -> GameState {
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut state = generator.generate(config, &mut rng);
+    // Drawn after the layout, so an unseeded world's names take nothing from it
+    let name_seed = config.seed.unwrap_or_else(|| rng.r#gen());
+    name_planets(&mut state.planets, name_seed);
+    state.planets.iter_mut().for_each(Planet::refresh_display);
+    state
+}
+
+/// The built-in generators, by config name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorldLayout {
+    #[default]
+    Ring,
+    Spiral,
+    Sphere,
+}
+
+impl WorldLayout {
+    pub fn generator(self) -> Box<dyn WorldGenerator> {
+        match self {
+            WorldLayout::Ring => Box::new(RingGenerator),
+            WorldLayout::Spiral => Box::new(SpiralGenerator::default()),
+            WorldLayout::Sphere => Box::new(SphereGenerator::default()),
+        }
+    }
+}
+
+impl FromStr for WorldLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ring" => Ok(WorldLayout::Ring),
+            "spiral" => Ok(WorldLayout::Spiral),
+            "sphere" => Ok(WorldLayout::Sphere),
+            other => Err(format!("unknown world layout: {} (expected ring, spiral or sphere)", other)),
+        }
+    }
+}
+
+/// Evenly spaced around a ring, at slightly different distances and heights.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RingGenerator;
+
+impl WorldGenerator for RingGenerator {
+    fn generate(&self, config: &WorldConfig, rng: &mut StdRng) -> GameState {
+        let mut planets = Vec::new();
+        for i in 0..PLANET_COUNT {
+            let angle = (i as f32) * PI * 2.0 / PLANET_COUNT as f32;
+            let radius = 500.0 + rng.gen_range(0.0..200.0);
+            let size = rng.gen_range(50.0..150.0);
+            let position = Position {
+                x: angle.cos() * radius,
+                y: rng.gen_range(-100.0..100.0),
+                z: angle.sin() * radius,
+            };
+            let placed = Some(inside(&position, size, config))
+                .filter(|position| clear_of(&planets, position, size, config.min_planet_gap))
+                .or_else(|| place(rng, &planets, config, size, config.min_planet_gap, |rng| {
+                    // Nudged along the ring, towards the neighbours' slots at most halfway
+                    let angle = angle + rng.gen_range(-0.5..0.5) * PI * 2.0 / PLANET_COUNT as f32;
+                    let radius = 500.0 + rng.gen_range(0.0..200.0);
+                    Position { x: angle.cos() * radius, y: rng.gen_range(-100.0..100.0), z: angle.sin() * radius }
+                }));
+            if let Some(position) = placed {
+                let id = planets.len() as u32;
+                planets.push(new_planet(rng, id, size, position, config));
+            }
+        }
+        finish_world(planets, config, rng)
+    }
+}
+
+/// Planets along the arms of a flat spiral, nearer together towards the
+/// core.
+#[derive(Debug, Clone, Copy)]
+pub struct SpiralGenerator {
+    pub arms: u32,
+    pub turns: f32,  // how far each arm winds around
+    pub inner_radius: f32,
+    pub outer_radius: f32,
+}
+
+impl Default for SpiralGenerator {
+    fn default() -> Self {
+        SpiralGenerator { arms: 2, turns: 0.75, inner_radius: 400.0, outer_radius: 2500.0 }
+    }
+}
+
+impl WorldGenerator for SpiralGenerator {
+    fn generate(&self, config: &WorldConfig, rng: &mut StdRng) -> GameState {
+        let arms = self.arms.max(1);
+        let per_arm = PLANET_COUNT.div_ceil(arms);
+        let outer = self.outer_radius.min(config.radius);
+        let mut planets = Vec::new();
+        for i in 0..PLANET_COUNT {
+            let (arm, step) = (i % arms, i / arms);
+            let along = (step as f32 + 0.5) / per_arm as f32;
+            let size = rng.gen_range(50.0..150.0);
+            let placed = place(rng, &planets, config, size, config.min_planet_gap, |rng| {
+                let angle = TAU * (arm as f32 / arms as f32 + self.turns * along) + rng.gen_range(-0.1..0.1);
+                let radius = self.inner_radius + (outer - self.inner_radius).max(0.0) * along + rng.gen_range(-50.0..50.0);
+                Position { x: angle.cos() * radius, y: rng.gen_range(-30.0..30.0), z: angle.sin() * radius }
+            });
+            if let Some(position) = placed {
+                let id = planets.len() as u32;
+                planets.push(new_planet(rng, id, size, position, config));
+            }
+        }
+        finish_world(planets, config, rng)
+    }
+}
+
+/// Planets anywhere within `radius` of the origin, none closer than
+/// `min_gap` to another, or the configured gap if that is wider.
+#[derive(Debug, Clone, Copy)]
+pub struct SphereGenerator {
+    pub radius: f32,
+    pub min_gap: f32,
+}
+
+impl Default for SphereGenerator {
+    fn default() -> Self {
+        SphereGenerator { radius: 2000.0, min_gap: 100.0 }
+    }
+}
+
+impl WorldGenerator for SphereGenerator {
+    fn generate(&self, config: &WorldConfig, rng: &mut StdRng) -> GameState {
+        let radius = self.radius.min(config.radius);
+        let mut planets = Vec::new();
+        for _ in 0..PLANET_COUNT {
+            let size = rng.gen_range(50.0..150.0);
+            let placed = place(rng, &planets, config, size, self.min_gap.max(config.min_planet_gap), |rng| {
+                // Uniform in the ball: points of the cube around it, until one falls inside
+                let (x, y, z): (f32, f32, f32) = loop {
+                    let v = (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                    if v.0 * v.0 + v.1 * v.1 + v.2 * v.2 <= 1.0 {
+                        break v;
+                    }
+                };
+                Position { x: x * radius, y: y * radius, z: z * radius }
+            });
+            if let Some(position) = placed {
+                let id = planets.len() as u32;
+                planets.push(new_planet(rng, id, size, position, config));
+            }
+        }
+        finish_world(planets, config, rng)
+    }
+}
+
+/// Tries positions from `candidate` until one keeps a planet of diameter
+/// `size` at least `gap` from each of `planets`, once it is pulled inside
+/// the world.
+fn place(
+    rng: &mut StdRng,
+    planets: &[Planet],
+    config: &WorldConfig,
+    size: f32,
+    gap: f32,
+    mut candidate: impl FnMut(&mut StdRng) -> Position,
+) -> Option<Position> {
+    (0..PLACEMENT_ATTEMPTS)
+        .map(|_| inside(&candidate(rng), size, config))
+        .find(|position| clear_of(planets, position, size, gap))
+}
+
+/// Whether a planet of diameter `size` at `position` is at least `gap`
+/// from each of `planets`.
+fn clear_of(planets: &[Planet], position: &Position, size: f32, gap: f32) -> bool {
+    planets.iter().all(|p| surface_distance(p, position, size) >= gap)
+}
+
+/// How far apart the surfaces of `planet` and a planet of diameter `size`
+/// at `position` are; negative when they overlap.
+fn surface_distance(planet: &Planet, position: &Position, size: f32) -> f32 {
+    let (dx, dy, dz) = (planet.position.x - position.x, planet.position.y - position.y, planet.position.z - position.z);
+    (dx * dx + dy * dy + dz * dz).sqrt() - (planet.size + size) / 2.0
+}
+
+/// Keeps the whole planet inside the world.
+fn inside(position: &Position, size: f32, config: &WorldConfig) -> Position {
+    WorldConfig::clamp_to(position, (config.radius - size / 2.0).max(0.0))
+}
+
+fn random_color(rng: &mut StdRng) -> Color {
+    Color { r: rng.gen_range(0..255), g: rng.gen_range(0..255), b: rng.gen_range(0..255) }
+}
+
+fn new_planet(rng: &mut StdRng, id: u32, size: f32, position: Position, config: &WorldConfig) -> Planet {
+    // Random colors are drawn either way, and palette picks come from a
+    // generator of their own seeded by them, so a palette changes nothing
+    // else about a seeded world
+    let random = [random_color(rng), random_color(rng), random_color(rng)];
+    let module_type = rng.gen_range(0..MODULE_TYPES);
+    let [a, b, c] = &random;
+    let color_seed = u64::from_le_bytes([a.r, a.g, a.b, b.r, b.g, b.b, c.r, c.g]);
+    // An unseeded world's terrain is as random as its colors
+    let terrain = generate_terrain(terrain_seed(config.seed.unwrap_or(color_seed), id), module_type);
+    let colors = match config.palettes.for_module(module_type) {
+        Some(palette) => palette.pick(&mut StdRng::seed_from_u64(color_seed)),
+        None => random,
+    };
+    Planet {
+        id,
+        size,
+        colors,
+        module_type,
+        position: inside(&position, size, config),
+        owner: None,
+        moons: generate_moons(rng, size, config.moon_chance),
+        resources: capacity_for(size),
+        capacity: capacity_for(size),
+        terrain,
+        name: String::new(),  // named by `generate_world`
+        primary_display_color: Color::default(),  // worked out by `generate_world`
+        label_contrast: LabelContrast::default(),
+    }
+}
+
+/// The seed of the terrain of planet `id` in a world seeded with
+/// `world_seed`, mixed (splitmix64) so that neighbouring ids look unrelated.
+pub fn terrain_seed(world_seed: u64, id: u32) -> u64 {
+    let mut z = world_seed ^ (id as u64).wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Terrain from `seed`, in ranges that suit the module type: Defense
+/// planets are rough and dry, Trade planets ocean worlds under thick air,
+/// Research planets thin-aired rocks, and the rest anything at all.
+pub fn generate_terrain(seed: u64, module_type: u8) -> TerrainParams {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (roughness, ocean_level, atmosphere) = match ModuleKind::of(module_type) {
+        ModuleKind::Defense => (0.6..=1.0, 0.0..=0.2, 0..=80),
+        ModuleKind::Trade => (0.1..=0.5, 0.4..=0.8, 120..=255),
+        ModuleKind::Research => (0.3..=0.7, 0.0..=0.3, 0..=40),
+        ModuleKind::Unknown => (0.0..=1.0, 0.0..=1.0, 0..=255),
+    };
+    TerrainParams {
+        seed: rng.r#gen(),
+        roughness: rng.gen_range(roughness),
+        ocean_level: rng.gen_range(ocean_level),
+        atmosphere: rng.gen_range(atmosphere),
+    }
+}
+
+/// The planets plus the asteroid belts, which every layout gets.
+fn finish_world(planets: Vec<Planet>, config: &WorldConfig, rng: &mut StdRng) -> GameState {
+    let mut state = GameState::new(planets, Vec::new(), Position { x: 0.0, y: 0.0, z: 0.0 });
+    let mut next_id = state.next_body_id();
+    state.belts = (0..config.belts).map(|_| generate_belt(rng, config.radius, &mut next_id)).collect();
+    state
+}
+
+/// Up to `MAX_MOONS` moons on separate orbits around a planet of diameter `planet_size`.
+fn generate_moons(rng: &mut impl Rng, planet_size: f32, chance: f64) -> Vec<Moon> {
+    let mut moons = Vec::new();
+    let mut orbit_radius = planet_size / 2.0;
+    while moons.len() < MAX_MOONS && rng.gen_bool(chance) {
+        let size = planet_size * rng.gen_range(0.1..0.25);
+        orbit_radius += size / 2.0 + rng.gen_range(20.0..40.0);
+        let direction = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+        moons.push(Moon {
+            size,
+            color: Color { r: rng.gen_range(0..255), g: rng.gen_range(0..255), b: rng.gen_range(0..255) },
+            orbit_radius,
+            angular_speed: direction * rng.gen_range(0.05..0.3),
+            phase: rng.gen_range(0.0..TAU),
+        });
+        orbit_radius += size / 2.0;
+    }
+    moons
+}
+
+/// A belt somewhere outside the planets, fitted inside a world of `world_radius`,
+/// whose notable asteroids take ids counting up from `next_id`.
+fn generate_belt(rng: &mut impl Rng, world_radius: f32, next_id: &mut u32) -> Belt {
+    let width = rng.gen_range(100.0..300.0);
+    let center_radius = rng.gen_range(900.0..2500.0f32).min(world_radius - width / 2.0).max(0.0);
+    let notable = (0..rng.gen_range(3..=6))
+        .map(|_| {
+            let angle = rng.gen_range(0.0..TAU);
+            let distance = center_radius + rng.gen_range(-width / 2.0..width / 2.0);
+            let position = Position { x: angle.cos() * distance, y: rng.gen_range(-10.0..10.0), z: angle.sin() * distance };
+            let id = *next_id;
+            *next_id += 1;
+            Asteroid {
+                id,
+                size: rng.gen_range(5.0..20.0),
+                position: WorldConfig::clamp_to(&position, world_radius),
+                resources: rng.gen_range(100..1000),
+            }
+        })
+        .collect();
+    Belt { center_radius, width, density: rng.gen_range(0.5..2.0), rock_seed: rng.r#gen(), notable }
+}
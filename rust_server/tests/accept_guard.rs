@@ -0,0 +1,112 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant};
+
+use common::{connect_json, spawn_server};
+use galavox::accept_guard::{AcceptGuard, AcceptLimits, Refusal, Refused, TRACKED_HOSTS, WINDOW};
+use galavox::config::ServerConfig;
+use galavox::server::GameServer;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio_tungstenite::connect_async;
+
+fn host(n: u32) -> Option<IpAddr> {
+    Some(IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + n)))
+}
+
+#[test]
+fn hammering_is_throttled_until_the_window_is_over() {
+    let mut guard = AcceptGuard::new(AcceptLimits { attempts_per_minute: Some(10), ..AcceptLimits::default() });
+    let start = Instant::now();
+    let mut refusals = Vec::new();
+    for i in 0..100 {
+        match guard.admit(host(1), start + Duration::from_millis(i * 100)) {
+            Ok(()) => guard.release(host(1)),
+            Err(refused) => refusals.push(refused),
+        }
+    }
+    assert_eq!(refusals.len(), 90);
+    let throttled = Refusal::TooManyAttempts { limit: 10 };
+    assert_eq!(refusals[0], Refused { refusal: throttled, first_in_window: true });
+    assert!(refusals[1..].iter().all(|r| *r == Refused { refusal: throttled, first_in_window: false }));
+    // Others are not held up meanwhile
+    assert_eq!(guard.admit(host(2), start + Duration::from_secs(10)), Ok(()));
+
+    assert!(guard.admit(host(1), start + WINDOW - Duration::from_millis(1)).is_err());
+    assert_eq!(guard.admit(host(1), start + WINDOW), Ok(()));
+    assert_eq!(guard.open(), 2);
+}
+
+#[test]
+fn open_sockets_are_limited_per_address_and_overall() {
+    let limits = AcceptLimits { max_open: Some(3), open_per_ip: Some(2), ..AcceptLimits::default() };
+    let mut guard = AcceptGuard::new(limits);
+    let now = Instant::now();
+    assert_eq!(guard.admit(host(1), now), Ok(()));
+    assert_eq!(guard.admit(host(1), now), Ok(()));
+    let refused = guard.admit(host(1), now).unwrap_err();
+    assert_eq!(refused.refusal, Refusal::TooManyOpen { limit: 2 });
+    assert_eq!(guard.admit(host(2), now), Ok(()));
+    assert_eq!(guard.admit(host(3), now).unwrap_err().refusal, Refusal::ServerFull { limit: 3 });
+    // A trusted proxy is only held to the overall limit
+    let proxied = guard.admit(None, now).unwrap_err();
+    assert_eq!(proxied, Refused { refusal: Refusal::ServerFull { limit: 3 }, first_in_window: true });
+    assert!(!guard.admit(None, now).unwrap_err().first_in_window);
+
+    guard.release(host(1));
+    assert_eq!(guard.admit(None, now), Ok(()));
+    guard.release(host(2));
+    assert_eq!(guard.admit(host(1), now), Ok(()));
+    assert_eq!(guard.open(), 3);
+}
+
+#[test]
+fn only_so_many_hosts_are_remembered() {
+    let mut guard = AcceptGuard::new(AcceptLimits { attempts_per_minute: Some(1), ..AcceptLimits::default() });
+    let now = Instant::now();
+    guard.admit(host(0), now).unwrap();
+    for n in 1..=TRACKED_HOSTS as u32 {
+        guard.admit(host(n), now + Duration::from_millis(n as u64)).unwrap();
+        guard.release(host(n));
+    }
+    assert_eq!(guard.tracked(), TRACKED_HOSTS);
+    // The host with a socket open was kept, the least recently seen other one forgotten
+    assert!(guard.admit(host(0), now).is_err());
+    assert_eq!(guard.admit(host(1), now), Ok(()));
+}
+
+#[test]
+fn the_limits_come_from_the_config() {
+    let defaults = ServerConfig::default().accept_limits();
+    assert_eq!(defaults, AcceptLimits::default());
+    // The open-file limit is read where there is one
+    assert_eq!(defaults.or_fd_limit().max_open.is_some(), galavox::accept_guard::fd_limit().is_some());
+
+    let config = ServerConfig::from_toml("max_open_per_ip = 4\nmax_accepts_per_ip_per_min = 30\n").unwrap();
+    assert_eq!(config.accept_limits(), AcceptLimits { max_open: None, open_per_ip: Some(4), attempts_per_minute: Some(30) });
+    let mut flagged = ServerConfig::default();
+    flagged.apply_args(&["--max-open-connections".to_string(), "100".to_string()]).unwrap();
+    assert_eq!(flagged.accept_limits().or_fd_limit().max_open, Some(100));
+    assert!(ServerConfig::default().apply_args(&["--max-open-per-ip".to_string(), "0".to_string()]).is_err());
+}
+
+#[tokio::test]
+async fn a_refused_socket_is_closed_before_the_handshake() {
+    let server = GameServer::new().with_accept_limits(AcceptLimits { open_per_ip: Some(1), ..AcceptLimits::default() });
+    let addr = spawn_server(server).await;
+    let first = connect_json(addr).await;
+
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0u8; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), second.read(&mut buf)).await.expect("the socket was left open");
+    assert!(matches!(read, Ok(0) | Err(_)));
+
+    // The place is given back once the first connection is gone
+    drop(first);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while connect_async(format!("ws://{}", addr)).await.is_err() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("the first connection's place was never given back");
+}
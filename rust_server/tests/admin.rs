@@ -0,0 +1,149 @@
+mod common;
+
+use common::{connect, connect_json, next_json, next_server_message, planet, send_text, spawn_server};
+use galavox::admin::{parse_command, run_line, surface_gap, validate_planet, AdminCommand, PlanetEdit, PlanetLimits};
+use galavox::protocol::{ClientMessage, Color, Planet, Position, ServerMessage};
+use galavox::server::GameServer;
+
+/// A server whose only planets are the ones given.
+fn server_with(planets: Vec<Planet>) -> GameServer {
+    let server = GameServer::new();
+    for p in server.get_state().planets {
+        server.remove_planet(p.id).unwrap();
+    }
+    for p in planets {
+        server.add_planet(p).unwrap();
+    }
+    server
+}
+
+#[test]
+fn parses_commands() {
+    assert_eq!(parse_command("planet list"), Ok(AdminCommand::ListPlanets));
+    assert_eq!(parse_command("planet remove 3"), Ok(AdminCommand::RemovePlanet { id: 3 }));
+    assert_eq!(parse_command("planet set 3 size 120"), Ok(AdminCommand::SetPlanet { id: 3, edit: PlanetEdit::Size(120.0) }));
+    assert_eq!(
+        parse_command("planet set 1 color2 ff8000"),
+        Ok(AdminCommand::SetPlanet { id: 1, edit: PlanetEdit::Color(1, Color { r: 255, g: 128, b: 0 }) })
+    );
+
+    let Ok(AdminCommand::AddPlanet(added)) = parse_command("planet add size=80 x=1 y=2 z=3 module=4 colors=ff0000,00ff00,0000ff") else {
+        panic!("planet add did not parse");
+    };
+    assert_eq!((added.size, added.module_type, added.position), (80.0, 4, Position { x: 1.0, y: 2.0, z: 3.0 }));
+    assert_eq!(added.colors[2], Color { r: 0, g: 0, b: 255 });
+
+    assert!(parse_command("planet add size=80 x=1").is_err());
+    assert!(parse_command("planet set 1 mass 3").is_err());
+    assert!(parse_command("planet remove three").is_err());
+    assert!(parse_command("launch").is_err());
+}
+
+#[test]
+fn validation_checks_size_and_overlap() {
+    let limits = PlanetLimits::default();
+    let existing = [planet(0, 0.0)];
+
+    assert!(validate_planet(&planet(1, 500.0), &existing, &limits).is_ok());
+    assert!(validate_planet(&Planet { size: 5.0, ..planet(1, 500.0) }, &existing, &limits).is_err());
+    assert!(validate_planet(&Planet { size: 1000.0, ..planet(1, 5000.0) }, &existing, &limits).is_err());
+    // Planets keep the minimum gap, and the error names the one crowded
+    assert_eq!(surface_gap(&planet(1, 100.0), &existing[0]), 0.0);
+    assert!(validate_planet(&planet(1, 120.0), &existing, &limits).is_ok());
+    assert_eq!(validate_planet(&planet(1, 110.0), &existing, &limits),
+        Err("only 10.0 from planet 0, closer than the minimum gap of 20".to_string()));
+    assert_eq!(validate_planet(&planet(1, 80.0), &existing, &limits), Err("overlaps planet 0 by 20.0".to_string()));
+    let touching = PlanetLimits { min_gap: 0.0, ..limits };
+    assert!(validate_planet(&planet(1, 100.0), &existing, &touching).is_ok());
+    // A planet never overlaps itself
+    assert!(validate_planet(&Planet { size: 120.0, ..planet(0, 0.0) }, &existing, &limits).is_ok());
+}
+
+#[test]
+fn ids_stay_stable_across_removals() {
+    let server = server_with(vec![
+        Planet { size: 50.0, ..planet(0, 0.0) },
+        Planet { size: 50.0, ..planet(0, 1000.0) },
+        Planet { size: 50.0, ..planet(0, 2000.0) },
+    ]);
+    let ids: Vec<u32> = server.get_state().planets.iter().map(|p| p.id).collect();
+
+    run_line(&server, &format!("planet remove {}", ids[0])).unwrap();
+    run_line(&server, &format!("planet set {} size 120", ids[2])).unwrap();
+    let state = server.get_state();
+    assert_eq!(state.planets.iter().map(|p| p.id).collect::<Vec<_>>(), &ids[1..]);
+    assert_eq!(state.planets[1].size, 120.0);
+
+    // Removed ids are never handed out again
+    run_line(&server, "planet add size=50 x=5000 y=0 z=0").unwrap();
+    assert!(server.get_state().planets.iter().all(|p| p.id != ids[0]));
+
+    assert!(run_line(&server, &format!("planet remove {}", ids[0])).is_err());
+    assert!(run_line(&server, &format!("planet set {} x 1000", ids[2])).is_err(), "would overlap");
+}
+
+#[test]
+fn validate_world_lists_the_planets_too_close_together() {
+    let server = server_with(vec![planet(0, 0.0), planet(0, 500.0)]);
+    assert_eq!(run_line(&server, "validate-world"), Ok("2 planets, none closer than 20".to_string()));
+
+    // A file with a known overlap, whatever is running
+    assert_eq!(
+        run_line(&server, "validate-world tests/fixtures/worlds/overlap.json"),
+        Ok("2 planets, 1 pair(s) closer than 20:\nplanets 0 and 1 overlap by 50.0".to_string())
+    );
+    assert!(run_line(&server, "validate-world tests/fixtures/worlds/missing.json").unwrap_err().starts_with("cannot read"));
+}
+
+#[tokio::test]
+async fn remote_admin_needs_the_token_and_broadcasts_edits() {
+    let server = server_with(vec![]).with_admin_token("s3cret".to_string());
+    let addr = spawn_server(server).await;
+    let mut admin = connect_json(addr).await;
+    let mut watcher = connect(addr).await;
+
+    let command = |token: &str| serde_json::to_string(&ClientMessage::Admin {
+        token: token.to_string(),
+        command: "planet add size=60 x=0 y=0 z=900".to_string(),
+        dry_run: false,
+    }).unwrap();
+
+    send_text(&mut admin, &command("guess")).await;
+    loop {
+        if let ServerMessage::AdminResult { ok, message } = next_json(&mut admin).await {
+            assert!(!ok);
+            assert_eq!(message, "invalid admin token");
+            break;
+        }
+    }
+
+    send_text(&mut admin, &command("s3cret")).await;
+    loop {
+        if let ServerMessage::AdminResult { ok, .. } = next_json(&mut admin).await {
+            assert!(ok);
+            break;
+        }
+    }
+    loop {
+        if let ServerMessage::PlanetAdded { planet } = next_server_message(&mut watcher).await {
+            assert_eq!(planet.size, 60.0);
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn remote_admin_is_off_without_a_token() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect(addr).await;
+
+    let command = ClientMessage::Admin { token: String::new(), command: "planet list".to_string(), dry_run: false };
+    send_text(&mut client, &serde_json::to_string(&command).unwrap()).await;
+    loop {
+        if let ServerMessage::AdminResult { ok, message } = next_server_message(&mut client).await {
+            assert!(!ok);
+            assert_eq!(message, "remote admin is disabled");
+            break;
+        }
+    }
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{connect_json, next_json, send_text, spawn_server, Client};
+use galavox::admin::run_line;
+use galavox::ambient::{Ambient, AmbientConfig, AmbientWeights, FLARE_SPEED_FACTOR};
+use galavox::config::ServerConfig;
+use galavox::protocol::{AmbientEvent, AmbientKind, Position, ServerMessage};
+use galavox::server::{GameServer, DEFAULT_MAX_SPEED};
+
+fn flares_only(every_ticks: u64, duration_ticks: u64) -> AmbientConfig {
+    AmbientConfig {
+        every_ticks,
+        duration_ticks,
+        weights: AmbientWeights { solar_flare: 1, meteor_shower: 0, aurora: 0 },
+        seed: Some(7),
+    }
+}
+
+/// The next `WorldEvent`, skipping the other messages.
+async fn next_world_event(ws: &mut Client) -> (AmbientEvent, bool) {
+    loop {
+        if let ServerMessage::WorldEvent { event, active } = next_json(ws).await {
+            return (event, active);
+        }
+    }
+}
+
+/// Steps a paused server until `done` holds of its ambient events.
+fn step_until(server: &GameServer, done: impl Fn(&[AmbientEvent]) -> bool) {
+    for _ in 0..100 {
+        if done(&server.ambient_events()) {
+            return;
+        }
+        run_line(server, "step").unwrap();
+    }
+    panic!("the ambient events never got there");
+}
+
+#[test]
+fn a_seeded_schedule_repeats() {
+    let planets = GameServer::new().get_state().planets;
+    let config = AmbientConfig { every_ticks: 5, duration_ticks: 3, weights: AmbientWeights::default(), seed: Some(42) };
+    let run = || {
+        let mut ambient = Ambient::new(config);
+        (1..=60).flat_map(|tick| ambient.advance(tick, &planets)).collect::<Vec<_>>()
+    };
+    let first = run();
+    assert_eq!(first, run());
+
+    // One starts every 5 ticks and each ends 3 ticks on
+    let starts: Vec<u64> = first.iter().filter_map(|m| match m {
+        ServerMessage::WorldEvent { event, active: true } => Some(event.start_tick),
+        _ => None,
+    }).collect();
+    assert_eq!(starts, (1..=12).map(|i| i * 5).collect::<Vec<_>>());
+    assert!(first.iter().all(|m| match m {
+        ServerMessage::WorldEvent { event, active: false } => event.end_tick() == event.start_tick + 3,
+        _ => true,
+    }));
+}
+
+#[test]
+fn flares_slow_only_players_near_the_planet() {
+    let planets = GameServer::new().get_state().planets;
+    let mut ambient = Ambient::new(flares_only(1, 2));
+    ambient.advance(1, &planets);
+    let flare = ambient.active()[0].clone();
+    assert_eq!(flare.kind, AmbientKind::SolarFlare);
+    let planet = planets.iter().find(|p| p.id == flare.planet_id).unwrap();
+
+    let near = Position { x: planet.position.x + planet.size / 2.0 + 10.0, ..planet.position.clone() };
+    let far = Position { x: 0.0, y: 1.0e6, z: 0.0 };
+    assert_eq!(ambient.max_speed_at(500.0, &near, &planets), 500.0 * FLARE_SPEED_FACTOR);
+    assert_eq!(ambient.max_speed_at(500.0, &far, &planets), 500.0);
+
+    // Over at its end tick
+    ambient.advance(3, &planets);
+    assert!(ambient.active().iter().all(|e| e.id != flare.id));
+}
+
+#[test]
+fn the_schedule_comes_from_the_config() {
+    assert_eq!(ServerConfig::default().ambient(), None);
+    let config = ServerConfig::from_toml(
+        "ambient_every_ticks = 50\nambient_seed = 3\n\n[ambient_weights]\nsolar_flare = 5\naurora = 0\n",
+    ).unwrap();
+    let ambient = config.ambient().unwrap();
+    assert_eq!((ambient.every_ticks, ambient.seed), (50, Some(3)));
+    assert_eq!(ambient.weights, AmbientWeights { solar_flare: 5, meteor_shower: 1, aurora: 0 });
+
+    let mut flagged = ServerConfig::default();
+    flagged.apply_args(&["--ambient-every-ticks".to_string(), "10".to_string(), "--ambient-duration-ticks".to_string(), "4".to_string()]).unwrap();
+    assert_eq!(flagged.ambient().map(|a| (a.every_ticks, a.duration_ticks)), Some((10, 4)));
+
+    let silent = ServerConfig::from_toml(
+        "ambient_every_ticks = 10\n\n[ambient_weights]\nsolar_flare = 0\nmeteor_shower = 0\naurora = 0\n",
+    ).unwrap();
+    assert!(silent.setting_problems().iter().any(|p| p.contains("ambient_weights")));
+    let mut instant = ServerConfig::default();
+    assert!(instant.apply_args(&["--ambient-every-ticks".to_string(), "10".to_string(), "--ambient-duration-ticks".to_string(), "0".to_string()]).is_err());
+}
+
+#[tokio::test]
+async fn a_flare_slows_players_until_it_ends() {
+    let server = GameServer::new().with_ambient(flares_only(10, 3));
+    let addr = spawn_server(server.clone()).await;
+    run_line(&server, "pause").unwrap();
+    let mut ws = connect_json(addr).await;
+
+    step_until(&server, |events| !events.is_empty());
+    let (flare, active) = next_world_event(&mut ws).await;
+    assert!(active);
+    assert_eq!(server.ambient_events(), vec![flare.clone()]);
+    let planet = server.get_state().planets.into_iter().find(|p| p.id == flare.planet_id).unwrap();
+    let near = Position { x: planet.position.x + planet.size / 2.0 + 50.0, ..planet.position.clone() };
+    let fast = DEFAULT_MAX_SPEED * (FLARE_SPEED_FACTOR + 1.0) / 2.0;
+    let update = format!(
+        r#"{{"type":"Position","position":{{"x":{},"y":{},"z":{}}},"velocity":[{},0.0,0.0]}}"#,
+        near.x, near.y, near.z, fast,
+    );
+
+    // Too fast while the flare lasts: dropped, not held for the next tick
+    send_text(&mut ws, &update).await;
+    send_text(&mut ws, r#"{"type":"Chat","text":"barrier"}"#).await;
+    while next_json(&mut ws).await != (ServerMessage::Echo { text: "barrier".to_string() }) {}
+    assert!(run_line(&server, "broadcast").unwrap().contains("held=0"));
+    run_line(&server, "step").unwrap();
+    assert!(server.get_state().players.iter().all(|p| p.position != near));
+
+    // A late joiner hears of it too
+    let mut late = connect_json(addr).await;
+    assert_eq!(next_world_event(&mut late).await, (flare.clone(), true));
+
+    step_until(&server, |events| events.is_empty());
+    assert_eq!(next_world_event(&mut ws).await, (flare, false));
+    send_text(&mut ws, &update).await;
+    send_text(&mut ws, r#"{"type":"Chat","text":"barrier"}"#).await;
+    while next_json(&mut ws).await != (ServerMessage::Echo { text: "barrier".to_string() }) {}
+    run_line(&server, "step").unwrap();
+    assert!(server.get_state().players.iter().any(|p| p.position == near));
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::spawn_server;
+use galavox::admin::run_line;
+use galavox::announce::{parse_schedule, render, Placeholders};
+use galavox::client::{ClientEvent, Connection};
+use galavox::config::ServerConfig;
+use galavox::server::GameServer;
+use std::time::Duration;
+
+const PLAYER: Placeholders = Placeholders { player_count: 3, name: Some("Ada") };
+const EVERYONE: Placeholders = Placeholders { player_count: 3, name: None };
+
+async fn next_announcement(conn: &mut Connection) -> String {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let ClientEvent::Announcement { text } = conn.next_event().await {
+                return text;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an announcement")
+}
+
+#[test]
+fn placeholders_are_filled_in() {
+    assert_eq!(render("Hi {name}, {player_count} online", &PLAYER).unwrap(), "Hi Ada, 3 online");
+    assert_eq!(render("{{name}} is {name}", &PLAYER).unwrap(), "{name} is Ada");
+    assert_eq!(render("no placeholders }}", &EVERYONE).unwrap(), "no placeholders }");
+}
+
+#[test]
+fn bad_templates_are_rejected() {
+    assert!(render("{nmae}", &PLAYER).unwrap_err().contains("unknown placeholder"));
+    assert!(render("{name}", &EVERYONE).unwrap_err().contains("message of the day"));
+    assert!(render("{player_count", &PLAYER).is_err());
+    assert!(render("oops}", &PLAYER).is_err());
+}
+
+#[test]
+fn schedules_parse() {
+    assert_eq!(parse_schedule("every 30m"), Ok(Duration::from_secs(1800)));
+    assert_eq!(parse_schedule(" every 2h "), Ok(Duration::from_secs(7200)));
+    assert_eq!(parse_schedule("every 45s"), Ok(Duration::from_secs(45)));
+    for bad in ["30m", "every", "every 30", "every m", "every 30x", "every -1m", "everyday 1h", "every 99999999999999999h"] {
+        assert!(parse_schedule(bad).is_err(), "{:?} parsed", bad);
+    }
+    // Too frequent to be useful
+    assert!(parse_schedule("every 1s").is_err());
+}
+
+#[test]
+fn bad_entries_fail_config_validation() {
+    let load = |text: &str| ServerConfig::from_toml(text).and_then(|mut config| config.apply_args(&[]).map(|_| config));
+    let good = load("motd = \"Hi {name}\"\n[[announcements]]\nschedule = \"every 30m\"\ntext = \"{player_count} online\"\n").unwrap();
+    assert_eq!(good.scheduled_announcements(), vec![(Duration::from_secs(1800), "{player_count} online".to_string())]);
+
+    assert!(load("[[announcements]]\nschedule = \"every half hour\"\ntext = \"hi\"\n").is_err());
+    assert!(load("[[announcements]]\nschedule = \"every 30m\"\ntext = \"hi {name}\"\n").is_err());
+    assert!(load("motd = \"Hi {nickname}\"\n").is_err());
+}
+
+#[tokio::test]
+async fn motd_follows_the_join_ack() {
+    let addr = spawn_server(GameServer::new().with_motd("Welcome, {name}! {player_count} online.".to_string())).await;
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    let mut joined = false;
+    let text = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match ada.next_event().await {
+                ClientEvent::Joined { .. } => joined = true,
+                ClientEvent::Announcement { text } => return text,
+                _ => {}
+            }
+        }
+    }).await.unwrap();
+    assert!(joined, "the message of the day came before the join ack");
+    assert_eq!(text, "Welcome, Ada! 1 online.");
+}
+
+#[tokio::test]
+async fn announcements_reach_everyone() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let url = format!("ws://{}", addr);
+    let mut ada = Connection::connect(&url, Some("Ada")).await.unwrap();
+    let mut bob = Connection::connect(&url, Some("Bob")).await.unwrap();
+    // Both have joined once the admin sees them
+    while !run_line(&server, "players").unwrap().contains("Bob") {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(run_line(&server, "announce  Restart in {player_count}  minutes").unwrap(), "announced: Restart in 2  minutes");
+    assert_eq!(next_announcement(&mut ada).await, "Restart in 2  minutes");
+    assert_eq!(next_announcement(&mut bob).await, "Restart in 2  minutes");
+    assert!(run_line(&server, "announce").is_err());
+    assert!(run_line(&server, "announce hi {name}").is_err());
+
+
+    // A running server repeats its scheduled announcements
+    let server = GameServer::new().with_scheduled_announcements(vec![(Duration::from_millis(50), "Every so often".to_string())]);
+    let addr = spawn_server(server).await;
+    let mut cy = Connection::connect(&format!("ws://{}", addr), Some("Cy")).await.unwrap();
+    assert_eq!(next_announcement(&mut cy).await, "Every so often");
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::{connect, connect_json, next_json, next_server_message, player_name, spawn_server, wait_for_self};
+use futures_util::SinkExt;
+use galavox::journal::{apply_event, JournalEvent};
+use galavox::protocol::{ClientMessage, Color, GameState, Player, PlayerAppearance, Position, ServerMessage, SHIP_MODELS};
+use galavox::server::GameServer;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn appearance(model: u8) -> PlayerAppearance {
+    PlayerAppearance { primary: Color { r: 255, g: 0, b: 0 }, secondary: Color { r: 0, g: 0, b: 255 }, model }
+}
+
+async fn send(ws: &mut common::Client, message: &ClientMessage) {
+    ws.send(Message::Text(serde_json::to_string(message).unwrap().into())).await.unwrap();
+}
+
+#[test]
+fn the_same_name_always_looks_the_same() {
+    assert_eq!(PlayerAppearance::for_name("Player_4242"), PlayerAppearance::for_name("Player_4242"));
+    assert_ne!(PlayerAppearance::for_name("Player_4242"), PlayerAppearance::for_name("Player_4243"));
+    // Pinned, so that changing the hash is a deliberate decision
+    assert_eq!(PlayerAppearance::for_name("Ada"), PlayerAppearance {
+        primary: Color { r: 0x9b, g: 0xc4, b: 0x06 },
+        secondary: Color { r: 0xa0, g: 0x19, b: 0xe0 },
+        model: 0xae % SHIP_MODELS,
+    });
+    for i in 0..200 {
+        assert!(PlayerAppearance::for_name(&format!("Player_{}", i)).validate().is_ok());
+    }
+}
+
+#[test]
+fn unknown_models_are_rejected() {
+    assert!(appearance(SHIP_MODELS - 1).validate().is_ok());
+    assert!(appearance(SHIP_MODELS).validate().is_err());
+    assert!(appearance(u8::MAX).validate().is_err());
+}
+
+#[tokio::test]
+async fn changes_are_broadcast_and_rejections_explained() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    // Binary clients send commands as JSON text too
+    let mut ws = connect(addr).await;
+    let mut other = connect_json(addr).await;
+    let me = wait_for_self(&mut ws, |_| true).await;
+    assert_eq!(me.appearance, PlayerAppearance::for_name(&player_name(&ws)));
+
+    send(&mut ws, &ClientMessage::SetAppearance { appearance: appearance(SHIP_MODELS) }).await;
+    loop {
+        match next_server_message(&mut ws).await {
+            ServerMessage::AppearanceRejected { reason } => {
+                assert!(reason.contains("ship model"), "{}", reason);
+                break;
+            }
+            ServerMessage::PlayerAppearanceChanged { .. } => panic!("an invalid appearance was applied"),
+            _ => continue,
+        }
+    }
+
+    send(&mut ws, &ClientMessage::SetAppearance { appearance: appearance(1) }).await;
+    loop {
+        match next_json(&mut other).await {
+            ServerMessage::PlayerAppearanceChanged { player_id, appearance: changed } => {
+                assert_eq!((player_id, changed), (me.id, appearance(1)));
+                break;
+            }
+            _ => continue,
+        }
+    }
+    wait_for_self(&mut ws, |p| p.appearance == appearance(1)).await;
+}
+
+#[test]
+fn journal_replays_appearance_changes() {
+    let origin = Position { x: 0.0, y: 0.0, z: 0.0 };
+    let player = Player {
+        id: 3,
+        name: "Player_3".into(),
+        level: 1,
+        position: origin.clone(),
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("Player_3"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    };
+    let mut state = GameState::new(vec![], vec![], origin);
+    apply_event(&mut state, &JournalEvent::PlayerJoined { player: player.clone() });
+    apply_event(&mut state, &JournalEvent::AppearanceChanged { player_id: player.id, appearance: appearance(2) });
+    assert_eq!(state.players[0].appearance, appearance(2));
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::{connect, next_state, planet, spawn_server};
+use galavox::protocol::{GameState, Planet, Position};
+use galavox::server::GameServer;
+use std::time::{Duration, Instant};
+use tokio::net::TcpSocket;
+
+/// A world whose every state broadcast is large enough to fill socket buffers quickly.
+fn heavy_world() -> GameState {
+    let planets = (0..2000)
+        .map(|i| Planet {
+            size: 10.0,
+            position: Position { x: (i % 50) as f32 * 20.0, y: (i / 50) as f32 * 20.0, z: 0.0 },
+            ..planet(i, 0.0)
+        })
+        .collect();
+    GameState::new(planets, vec![], Position { x: 0.0, y: 0.0, z: 0.0 })
+}
+
+#[tokio::test]
+async fn client_that_never_reads_is_dropped_without_delaying_others() {
+    let write_timeout = Duration::from_secs(2);
+    let server = GameServer::new()
+        .with_world(heavy_world())
+        .with_broadcast_interval(Duration::from_millis(50))
+        .with_write_timeout(write_timeout);
+    let addr = spawn_server(server.clone()).await;
+
+    // Completes the handshake, then never reads another byte
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.set_recv_buffer_size(4096).unwrap();
+    let stream = socket.connect(addr).await.unwrap();
+    let stalled_addr = stream.local_addr().unwrap();
+    let (_stalled, _) = tokio_tungstenite::client_async(format!("ws://{}", addr), stream).await.unwrap();
+
+    let mut reader = connect(addr).await;
+    let start = Instant::now();
+    let mut last_state = Instant::now();
+    let mut longest_gap = Duration::ZERO;
+    let connected = |server: &GameServer| server.player_list(Instant::now()).iter().any(|listing| listing.addr == stalled_addr);
+    while connected(&server) {
+        assert!(start.elapsed() < Duration::from_secs(15), "stalled client was never dropped");
+        next_state(&mut reader).await;
+        longest_gap = longest_gap.max(last_state.elapsed());
+        last_state = Instant::now();
+    }
+
+    // Had the stalled client held anyone up, it would have been for a whole write timeout
+    assert!(longest_gap < write_timeout, "broadcasts stalled for {:?}", longest_gap);
+    assert_eq!(server.player_list(Instant::now()).len(), 1);
+}
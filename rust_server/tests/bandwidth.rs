@@ -0,0 +1,89 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{connect, next_server_message, spawn_server, Client};
+use galavox::bandwidth::{BandwidthGovernor, MAX_LEVEL, WINDOW};
+use galavox::protocol::ServerMessage;
+use galavox::server::GameServer;
+
+#[test]
+fn going_over_budget_halves_the_state_rate_up_to_the_limit() {
+    let start = Instant::now();
+    let mut governor = BandwidthGovernor::new(Some(1_000), 500, start);
+    assert_eq!((governor.level(), governor.state_every()), (0, 1));
+    assert!(governor.wants_state(7));
+
+    // Nothing is measured before a whole window has passed
+    assert_eq!(governor.observe(100_000, start + WINDOW / 2), None);
+    assert_eq!(governor.observe(2_000, start + WINDOW), Some(1));
+    assert_eq!(governor.state_every(), 2);
+    assert!(governor.wants_state(8) && !governor.wants_state(7));
+    assert_eq!(governor.observe(4_000, start + WINDOW * 2), Some(MAX_LEVEL));
+    assert_eq!(governor.state_every(), 4);
+    assert_eq!(governor.observe(6_000, start + WINDOW * 3), None);
+    assert_eq!(governor.level(), MAX_LEVEL);
+}
+
+#[test]
+fn the_rate_comes_back_only_with_room_to_spare() {
+    let start = Instant::now();
+    let mut governor = BandwidthGovernor::new(Some(1_000), 0, start);
+    governor.observe(2_000, start + WINDOW);
+    governor.observe(4_000, start + WINDOW * 2);
+    assert_eq!(governor.level(), 2);
+
+    // 450 B/s would be 900 at twice the rate: within budget, but not within 80% of it
+    assert_eq!(governor.observe(4_450, start + WINDOW * 3), None);
+    assert_eq!(governor.observe(4_850, start + WINDOW * 4), Some(1));
+    assert_eq!(governor.observe(5_250, start + WINDOW * 5), Some(0));
+    assert_eq!(governor.observe(5_650, start + WINDOW * 6), None);
+}
+
+#[test]
+fn lifting_the_budget_restores_the_full_rate() {
+    let start = Instant::now();
+    let mut governor = BandwidthGovernor::new(Some(10), 0, start);
+    assert_eq!(governor.observe(1_000, start + WINDOW), Some(1));
+    governor.set_budget(None);
+    assert_eq!(governor.level(), 0);
+    assert_eq!(governor.observe(1_000_000, start + WINDOW * 2), None);
+    assert_eq!(governor.level(), 0);
+}
+
+/// The tick of the next State.
+async fn next_state_tick(ws: &mut Client) -> u64 {
+    loop {
+        if let ServerMessage::State { tick, .. } = next_server_message(ws).await {
+            return tick;
+        }
+    }
+}
+
+#[tokio::test]
+async fn clients_over_budget_get_fewer_states() {
+    let server = GameServer::new()
+        .with_broadcast_interval(Duration::from_millis(20))
+        .with_bandwidth_budget(Some(1));
+    let addr = spawn_server(server).await;
+    let mut ws = connect(addr).await;
+
+    // Two windows over budget take the client down to every fourth tick
+    let slowed = tokio::time::timeout(Duration::from_secs(10), async {
+        let mut last = next_state_tick(&mut ws).await;
+        loop {
+            let tick = next_state_tick(&mut ws).await;
+            if tick - last == 4 {
+                return tick;
+            }
+            last = tick;
+        }
+    })
+    .await
+    .expect("states never slowed to one in four");
+    assert!(slowed.is_multiple_of(4));
+    for _ in 0..3 {
+        let tick = next_state_tick(&mut ws).await;
+        assert!(tick.is_multiple_of(4), "got the state of tick {}", tick);
+    }
+}
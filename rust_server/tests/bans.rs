@@ -0,0 +1,138 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::{connect, next_server_message, spawn_server};
+use futures_util::StreamExt;
+use galavox::admin::run_line;
+use galavox::bans::BanList;
+use galavox::protocol::{normalize_name, ErrorCode, ServerMessage};
+use galavox::server::{Connection, GameServer, JoinRejection};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn connection(port: u16) -> Connection {
+    Connection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("galavox-{}-{}.bans", name, std::process::id()))
+}
+
+#[test]
+fn names_normalize() {
+    assert_eq!(normalize_name("  Dark   Star\t"), "dark star");
+    assert_eq!(normalize_name("ÉLAN"), "élan");
+    assert_eq!(normalize_name("ΟΡΕΑ"), "opea");  // all Greek capitals
+    assert_eq!(normalize_name(""), "");
+    assert_eq!(normalize_name(&normalize_name("Рlayer One")), normalize_name("Рlayer One"));
+}
+
+#[test]
+fn bypass_attempts_match_the_ban() {
+    let mut bans = BanList::new();
+    assert!(bans.ban("Player").unwrap());
+    for attempt in [
+        "Player",
+        "player",
+        "PLAYER",
+        "pLaYeR",
+        "Player ",
+        " Player",
+        "\tPlayer\n",
+        "Рlayer",        // Cyrillic Р
+        "Plаyer",        // Cyrillic а
+        "Playеr",        // Cyrillic е
+        "Ρlayer",        // Greek Ρ
+        "plαyer",        // Greek α
+        "ΡLΑΥΕR",        // Greek capitals
+        "РLАУЕR",        // Cyrillic capitals
+    ] {
+        assert!(bans.is_banned(attempt), "{:?} got past the ban", attempt);
+    }
+    for different in ["Players", "Play er", "Playr", "P1ayer"] {
+        assert!(!bans.is_banned(different), "{:?} should not be banned", different);
+    }
+
+    assert!(!bans.ban(" PLAYER ").unwrap());
+    assert!(bans.ban("Dark  Star").unwrap());
+    assert!(bans.is_banned("dark star"));
+    assert!(!bans.is_banned("darkstar"));
+    assert_eq!(bans.unban("dark star").unwrap(), Some("Dark  Star".to_string()));
+    assert_eq!(bans.unban("dark star").unwrap(), None);
+}
+
+#[test]
+fn ban_files_are_normalized_when_read() {
+    // A file written by hand, with entries that only differ in case and spacing
+    let bans = BanList::parse("# griefers\nAda\n  ada \n\nBob_Smith\nbob_smith\n");
+    assert_eq!(bans.names(), ["Ada", "Bob_Smith"]);
+    assert!(bans.is_banned("ADA") && bans.is_banned("BOB_SMITH"));
+    assert_eq!(bans.to_file_text(), "Ada\nBob_Smith\n");
+}
+
+#[test]
+fn bans_are_saved_and_reloaded() {
+    let path = temp_path("saved");
+    let _ = std::fs::remove_file(&path);
+    let mut bans = BanList::load(&path).unwrap();
+    assert!(bans.names().is_empty());
+    bans.ban("Mallory").unwrap();
+    bans.ban("Eve").unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "Eve\nMallory\n");
+    bans.unban("EVE").unwrap();
+
+    let reloaded = BanList::load(&path).unwrap();
+    assert_eq!(reloaded.names(), ["Mallory"]);
+    assert!(reloaded.is_banned("mallory"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn names_clash_however_written() {
+    let server = GameServer::new();
+    let (_, first, _) = server.add_player(connection(5000), "Ada".to_string()).unwrap();
+    let (_, second, _) = server.add_player(connection(5001), "aDA".to_string()).unwrap();
+    assert_eq!(&*first.name, "Ada");
+    assert_eq!(&*second.name, "Player_5001");
+
+    let server = GameServer::new().with_bans(BanList::parse("Ada\n"));
+    assert_eq!(server.add_player(connection(5002), "ADA".to_string()).unwrap_err(), JoinRejection::Banned);
+    assert!(server.add_player(connection(5003), "Adam".to_string()).is_ok());
+}
+
+#[tokio::test]
+async fn banned_names_are_turned_away() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    assert_eq!(run_line(&server, "ban  Ada ").unwrap(), "banned Ada");
+    assert!(run_line(&server, "ban ada").is_err());
+    assert!(run_line(&server, "ban").is_err());
+    assert_eq!(run_line(&server, "bans").unwrap(), "Ada");
+
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?name=ADA", addr)).await.unwrap();
+    let code = loop {
+        if let ServerMessage::Error { code, .. } = next_server_message(&mut ws).await {
+            break code;
+        }
+    };
+    assert_eq!(code, ErrorCode::Banned.code());
+    let closed = loop {
+        match ws.next().await {
+            Some(Ok(Message::Close(Some(frame)))) => break u16::from(frame.code),
+            Some(Ok(_)) => continue,
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    };
+    assert_eq!(closed, ErrorCode::Banned.code());
+
+    assert_eq!(run_line(&server, "unban ADA").unwrap(), "unbanned Ada");
+    assert_eq!(run_line(&server, "bans").unwrap(), "no bans");
+    let _ws = connect(addr).await;
+}
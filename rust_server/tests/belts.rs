@@ -0,0 +1,99 @@
+use galavox::journal::{apply_event, JournalEvent};
+use galavox::module_effects::{Wallet, ASTEROID_REACH};
+use galavox::protocol::{Asteroid, Belt, GameState, MineError, Planet, Position, ServerMessage};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use galavox::world::{load_world, WorldConfig};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Notify;
+
+fn seeded(seed: u64, belts: u32) -> GameState {
+    GameServer::new().with_world_config(WorldConfig { seed: Some(seed), belts, ..WorldConfig::default() }).get_state()
+}
+
+#[test]
+fn same_seed_gives_the_same_belts() {
+    assert_eq!(seeded(42, 2).belts, seeded(42, 2).belts);
+    assert_ne!(seeded(42, 2).belts, seeded(43, 2).belts);
+    assert_eq!(seeded(42, 3).belts.len(), 3);
+    assert!(seeded(42, 0).belts.is_empty());
+    // Belts come after the planets, so adding them leaves the planets alone
+    assert_eq!(seeded(42, 0).planets, seeded(42, 4).planets);
+}
+
+#[test]
+fn notable_asteroids_lie_in_their_belt() {
+    for belt in seeded(5, 4).belts {
+        assert!((3..=6).contains(&belt.notable.len()));
+        for asteroid in &belt.notable {
+            let distance = asteroid.position.x.hypot(asteroid.position.z);
+            assert!((distance - belt.center_radius).abs() <= belt.width / 2.0, "asteroid {} strays from its belt", asteroid.id);
+        }
+    }
+}
+
+#[test]
+fn asteroids_and_planets_share_one_id_space() {
+    let server = GameServer::new().with_world_config(WorldConfig { seed: Some(9), ..WorldConfig::default() });
+    let state = server.get_state();
+    let asteroid_ids = state.belts.iter().flat_map(|b| &b.notable).map(|a| a.id);
+    let ids: Vec<u32> = state.planets.iter().map(|p| p.id).chain(asteroid_ids).collect();
+    assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
+
+    // A planet added later does not take an asteroid's id
+    let planet = Planet { position: Position { x: 0.0, y: 0.0, z: 0.0 }, ..state.planets[0].clone() };
+    let added = server.add_planet(planet).unwrap();
+    assert!(!ids.contains(&added.id));
+}
+
+#[test]
+fn belts_survive_both_wire_formats_and_old_saves() {
+    let message = ServerMessage::State { tick: 1, server_time_ms: 0, state: seeded(42, 2) };
+    assert_eq!(ServerMessage::from_bincode(&message.to_bincode().unwrap()).unwrap(), message);
+    assert_eq!(serde_json::from_str::<ServerMessage>(&serde_json::to_string(&message).unwrap()).unwrap(), message);
+
+    let saved = r#"{"planets": [], "players": [], "initial_player_location": {"x":0.0,"y":0.0,"z":0.0}}"#;
+    assert!(load_world(saved).unwrap().belts.is_empty());
+}
+
+#[test]
+fn notable_asteroids_are_mined_from_within_reach() {
+    let at = |x: f32| Position { x, y: 0.0, z: 0.0 };
+    let mut state = GameState::new(vec![], vec![], at(0.0));
+    state.belts.push(Belt {
+        center_radius: 1000.0,
+        width: 100.0,
+        density: 1.0,
+        rock_seed: 7,
+        notable: vec![Asteroid { id: 3, size: 10.0, position: at(1000.0), resources: 30 }],
+    });
+    let server = GameServer::new().with_world(state);
+    let mut events = server.subscribe_events();
+    let connection = Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    };
+    let (ada, _, _) = server.add_player(connection, "Ada".to_string()).unwrap();
+
+    server.move_player(ada, at(1000.0 - 5.0 - ASTEROID_REACH - 1.0));
+    assert_eq!(server.mine(ada, 10), Err(MineError::NotLanded));
+    server.move_player(ada, at(1000.0 - 5.0 - ASTEROID_REACH));
+    assert_eq!(server.mine(ada, 10), Ok((3, 10, 10)));
+    assert_eq!(server.mine(ada, 50), Ok((3, 20, 30)));
+    assert_eq!(server.mine(ada, 1), Err(MineError::Depleted { planet_id: 3 }));
+    assert_eq!(server.wallet("Ada"), Wallet { cargo: 30, credits: 0 });
+    assert_eq!(server.get_state().belts[0].notable[0].resources, 0);
+
+    // The journal follows the asteroid down
+    let mut replayed = server.get_state();
+    replayed.belts[0].notable[0].resources = 30;
+    while let Ok(event) = events.try_recv() {
+        if let Some(event @ JournalEvent::AsteroidMined { .. }) = JournalEvent::from_game(&event) {
+            apply_event(&mut replayed, &event);
+        }
+    }
+    assert_eq!(replayed.belts, server.get_state().belts);
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use galavox::client::blocking::Connection;
+use galavox::client::{ClientError, ClientEvent};
+use galavox::protocol::Position;
+use galavox::server::GameServer;
+use galavox::session::DisconnectReason;
+
+/// A server on a runtime of its own, so that the tests have none.
+fn serve(server: GameServer) -> SocketAddr {
+    let (ready, addr) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        tokio::runtime::Runtime::new().unwrap().block_on(async move {
+            ready.send(common::spawn_server(server).await).unwrap();
+            std::future::pending::<()>().await
+        })
+    });
+    addr.recv().unwrap()
+}
+
+/// The next event matching `f`, skipping the rest.
+fn next_matching<T>(connection: &mut Connection, mut f: impl FnMut(ClientEvent) -> Option<T>) -> T {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if let Some(found) = connection.recv_event(Duration::from_millis(100)).and_then(&mut f) {
+            return found;
+        }
+    }
+    panic!("timed out waiting for an event");
+}
+
+fn eventually(what: &str, check: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !check() {
+        assert!(Instant::now() < deadline, "never {}", what);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn a_blocking_connection_joins_moves_and_chats() {
+    let server = GameServer::new();
+    let url = format!("ws://{}", serve(server.clone()));
+    let mut ada = Connection::connect(&url, Some("Ada")).unwrap();
+    let player_id = next_matching(&mut ada, |event| match event {
+        ClientEvent::Joined { player_id, .. } => Some(player_id),
+        _ => None,
+    });
+
+    assert_eq!(ada.send_position(Position { x: 40.0, y: 4000.0, z: 0.0 }).unwrap(), 1);
+    eventually("moved", || server.get_state().players.iter().any(|player| player.id == player_id && player.position.x == 40.0));
+    ada.send_chat("hello").unwrap();
+    assert_eq!(next_matching(&mut ada, |event| match event {
+        ClientEvent::Chat { text } => Some(text),
+        _ => None,
+    }), "hello");
+
+    ada.close().unwrap();
+    assert!(matches!(ada.recv_event(Duration::ZERO), Some(ClientEvent::Disconnected { .. })));
+    assert!(matches!(ada.send_chat("still there?"), Err(ClientError::WebSocket(_))));
+    assert!(ada.close().is_ok(), "closing twice is harmless");
+    eventually("gone", || server.player_count() == 0);
+    assert_eq!(server.last_session("Ada").unwrap().reason, DisconnectReason::Closed);
+}
+
+#[test]
+fn dropping_a_blocking_connection_closes_it() {
+    let server = GameServer::new();
+    let url = format!("ws://{}", serve(server.clone()));
+    let mut bob = Connection::connect(&url, Some("Bob")).unwrap();
+    next_matching(&mut bob, |event| matches!(event, ClientEvent::Joined { .. }).then_some(()));
+    drop(bob);
+    eventually("gone", || server.player_count() == 0);
+    assert_eq!(server.last_session("Bob").unwrap().reason, DisconnectReason::Closed);
+}
+
+#[test]
+fn failing_to_connect_is_an_error() {
+    let url = format!("ws://{}", serve(GameServer::new()));
+    assert!(matches!(Connection::connect(&url, Some("no spaces")), Err(ClientError::InvalidName(_))));
+    assert!(matches!(Connection::connect("ws://127.0.0.1:1", None), Err(ClientError::WebSocket(_))));
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use common::{connect, next_json, next_server_message, spawn_server, wait_for_self};
+use futures_util::SinkExt;
+use galavox::protocol::{encode_motion_update, ClientMessage, Position, ServerMessage, IDENTITY_ROTATION};
+use galavox::server::GameServer;
+use galavox::world::{BoundsCorrection, BoundsTracker, WorldConfig};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const SPAWN: Position = Position { x: 0.0, y: 0.0, z: 0.0 };
+
+fn world(radius: f32, teleport_after: Option<u32>) -> WorldConfig {
+    WorldConfig { radius, teleport_after, ..WorldConfig::default() }
+}
+
+fn length(p: &Position) -> f32 {
+    (p.x * p.x + p.y * p.y + p.z * p.z).sqrt()
+}
+
+#[test]
+fn exactly_on_the_boundary_is_inside() {
+    let config = world(100.0, None);
+    for p in [Position { x: 100.0, y: 0.0, z: 0.0 }, Position { x: 0.0, y: -100.0, z: 0.0 }, Position { x: 60.0, y: 0.0, z: 80.0 }] {
+        assert!(config.contains(&p));
+        assert_eq!(config.clamp(&p), p);
+        assert_eq!(BoundsTracker::new(config.clone()).check(&p, &SPAWN), None);
+    }
+}
+
+#[test]
+fn just_outside_is_clamped_onto_the_boundary() {
+    let config = world(100.0, None);
+    let outside = Position { x: 60.001, y: 0.0, z: 80.001 };
+    assert!(!config.contains(&outside));
+
+    let clamped = config.clamp(&outside);
+    assert!(config.contains(&clamped));
+    assert!((length(&clamped) - 100.0).abs() < 1e-3);
+    assert!((clamped.x / clamped.z - 0.75).abs() < 1e-4, "direction kept: {:?}", clamped);
+}
+
+#[test]
+fn huge_coordinates_clamp_without_overflow() {
+    let config = world(100.0, None);
+    let clamped = config.clamp(&Position { x: 3e38, y: 3e38, z: 0.0 });
+    assert!((length(&clamped) - 100.0).abs() < 1e-3, "{:?}", clamped);
+}
+
+#[test]
+fn nan_is_never_inside_and_stays_nan_when_clamped() {
+    let config = world(100.0, None);
+    let nan = Position { x: f32::NAN, y: 0.0, z: 0.0 };
+    assert!(!config.contains(&nan));
+    assert!(config.clamp(&nan).x.is_nan());
+}
+
+#[test]
+fn repeated_attempts_teleport_when_configured() {
+    let far = Position { x: 500.0, y: 0.0, z: 0.0 };
+    let near = Position { x: 10.0, y: 0.0, z: 0.0 };
+
+    let mut tracker = BoundsTracker::new(world(100.0, Some(3)));
+    assert!(matches!(tracker.check(&far, &SPAWN), Some(BoundsCorrection::Clamped(_))));
+    assert!(matches!(tracker.check(&far, &SPAWN), Some(BoundsCorrection::Clamped(_))));
+    assert_eq!(tracker.check(&far, &SPAWN), Some(BoundsCorrection::Teleported(SPAWN)));
+    // The count starts over after a teleport and after any in-bounds update
+    assert!(matches!(tracker.check(&far, &SPAWN), Some(BoundsCorrection::Clamped(_))));
+    assert_eq!(tracker.check(&near, &SPAWN), None);
+    assert!(matches!(tracker.check(&far, &SPAWN), Some(BoundsCorrection::Clamped(_))));
+
+    let mut never = BoundsTracker::new(world(100.0, None));
+    for _ in 0..10 {
+        assert!(matches!(never.check(&far, &SPAWN), Some(BoundsCorrection::Clamped(_))));
+    }
+}
+
+#[test]
+fn generated_planets_fit_inside_the_world() {
+    let server = GameServer::new().with_world_config(world(300.0, None));
+    for planet in server.get_state().planets {
+        assert!(length(&planet.position) + planet.size / 2.0 <= 300.0 + 1e-3, "{:?}", planet);
+    }
+}
+
+/// Next JSON message that is not a state or population broadcast.
+async fn next_reply(ws: &mut common::Client) -> ServerMessage {
+    loop {
+        match next_json(ws).await {
+            ServerMessage::State { .. } | ServerMessage::PlanetPopulation { .. } => continue,
+            other => return other,
+        }
+    }
+}
+
+#[tokio::test]
+async fn out_of_bounds_updates_are_corrected() {
+    let addr = spawn_server(GameServer::new().with_world_config(world(1000.0, Some(2)))).await;
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?format=json", addr)).await.unwrap();
+    let mut spawn = None;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::Joined { spawn: position, .. } => spawn = Some(position),
+            ServerMessage::Notice { text } if text.starts_with("Welcome") => break,
+            _ => continue,
+        }
+    }
+
+    let far = ClientMessage::Position { seq: Some(1), position: Position { x: 0.0, y: 2000.0, z: 0.0 }, velocity: [0.0; 3], rotation: IDENTITY_ROTATION };
+    ws.send(Message::Text(serde_json::to_string(&far).unwrap().into())).await.unwrap();
+    let boundary = Position { x: 0.0, y: 1000.0, z: 0.0 };
+    let ServerMessage::PositionCorrection { position, seq, tick } = next_reply(&mut ws).await else { panic!("expected a correction") };
+    assert_eq!((position, seq), (boundary, Some(1)));
+    assert_eq!(next_reply(&mut ws).await, ServerMessage::OutOfBounds { radius: 1000.0, teleported: false });
+
+    let far = ClientMessage::Position { seq: Some(2), position: Position { x: 0.0, y: 2000.0, z: 0.0 }, velocity: [0.0; 3], rotation: IDENTITY_ROTATION };
+    ws.send(Message::Text(serde_json::to_string(&far).unwrap().into())).await.unwrap();
+    let ServerMessage::PositionCorrection { position, seq, tick: later } = next_reply(&mut ws).await else { panic!("expected a correction") };
+    assert_eq!((position, seq), (spawn.unwrap(), Some(2)));
+    assert!(later >= tick);
+    assert_eq!(next_reply(&mut ws).await, ServerMessage::OutOfBounds { radius: 1000.0, teleported: true });
+}
+
+#[tokio::test]
+async fn nan_updates_are_rejected_rather_than_clamped() {
+    let addr = spawn_server(GameServer::new().with_world_config(world(1000.0, None))).await;
+    let mut ws = connect(addr).await;
+
+    let nan = Position { x: f32::NAN, y: 5000.0, z: 0.0 };
+    ws.send(Message::Binary(encode_motion_update(1, &nan, &[0.0; 3], &IDENTITY_ROTATION).into())).await.unwrap();
+    let far = Position { x: 2000.0, y: 0.0, z: 0.0 };
+    ws.send(Message::Binary(encode_motion_update(2, &far, &[0.0; 3], &IDENTITY_ROTATION).into())).await.unwrap();
+
+    // The first correction is for the second update; the NaN one got none
+    let correction = loop {
+        match next_server_message(&mut ws).await {
+            ServerMessage::PositionCorrection { position, .. } => break position,
+            _ => continue,
+        }
+    };
+    assert_eq!(correction, Position { x: 1000.0, y: 0.0, z: 0.0 });
+    let me = wait_for_self(&mut ws, |p| p.last_processed_seq == 2).await;
+    assert_eq!(me.position, correction);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect_json, next_message, planet, spawn_server};
+use galavox::broadcast::{batch, BroadcastFrame, BroadcastStats, OFFLOAD_ENTITIES};
+use galavox::client::{ClientEvent, EventDecoder};
+use galavox::protocol::{encode_server_message, Batched, GameState, Planet, Position, ServerMessage, WireFormat};
+use galavox::server::GameServer;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn snapshot(planets: usize) -> ServerMessage {
+    let planets = (0..planets as u32)
+        .map(|id| Planet { size: 10.0, ..planet(id, id as f32 * 100.0) })
+        .collect();
+    ServerMessage::State { tick: 1, server_time_ms: 0, state: GameState::new(planets, vec![], Position { x: 0.0, y: 0.0, z: 0.0 }) }
+}
+
+#[tokio::test]
+async fn only_large_snapshots_are_offloaded() {
+    assert!(!BroadcastFrame::new(snapshot(OFFLOAD_ENTITIES)).is_large());
+    assert!(BroadcastFrame::new(snapshot(OFFLOAD_ENTITIES + 1)).is_large());
+    assert!(!BroadcastFrame::new(ServerMessage::Notice { text: "x".repeat(100_000) }).is_large());
+
+    // Offloading changes when a frame is encoded, not what it encodes to
+    for message in [snapshot(3), snapshot(OFFLOAD_ENTITIES + 1)] {
+        let frame = BroadcastFrame::prepare(message.clone()).await;
+        for format in [WireFormat::Binary, WireFormat::Json] {
+            assert_eq!(frame.encoded(format), encode_server_message(format, &message).ok());
+        }
+    }
+}
+
+#[test]
+fn stats_track_the_slowest_and_mean_build() {
+    let mut stats = BroadcastStats::default();
+    assert_eq!(stats.mean(), Duration::ZERO);
+    stats.record(Duration::from_millis(2), false);
+    stats.record(Duration::from_millis(6), true);
+    stats.record(Duration::from_millis(1), false);
+    assert_eq!((stats.ticks, stats.offloaded), (3, 1));
+    assert_eq!((stats.last, stats.max, stats.mean()), (Duration::from_millis(1), Duration::from_millis(6), Duration::from_millis(3)));
+}
+
+#[tokio::test]
+async fn periodic_broadcasts_are_timed() {
+    let server = GameServer::new().with_admin_token("secret".to_string());
+    server.broadcast_game_state().await;
+    server.broadcast_game_state().await;
+    assert_eq!(server.broadcast_stats().ticks, 2);
+    let report = server.run_admin_command("secret", "broadcast").unwrap();
+    assert!(report.starts_with("broadcasts=2 "), "{}", report);
+}
+
+fn removed(planet_id: u32) -> ServerMessage {
+    ServerMessage::PlanetRemoved { planet_id }
+}
+
+fn unwrapped(frames: Vec<ServerMessage>) -> Vec<ServerMessage> {
+    frames.into_iter()
+        .flat_map(|frame| match frame {
+            ServerMessage::Batch { messages } => messages.0,
+            message => vec![message],
+        })
+        .collect()
+}
+
+#[test]
+fn batches_split_at_the_cap_and_keep_their_order() {
+    let events: Vec<ServerMessage> = (0..30).map(removed).collect();
+    let frames = batch(events.clone(), 200);
+    assert!(frames.len() > 1);
+    for frame in &frames {
+        assert!(serde_json::to_vec(frame).unwrap().len() <= 200);
+    }
+    assert_eq!(unwrapped(frames), events);
+
+    assert!(matches!(batch(events.clone(), usize::MAX).as_slice(), [ServerMessage::Batch { messages }] if messages.0 == events));
+    assert_eq!(batch(vec![removed(1)], usize::MAX), vec![removed(1)]);
+    assert!(batch(vec![], usize::MAX).is_empty());
+
+    // Too big for any batch: it goes alone, between the others
+    let big = ServerMessage::Announcement { text: "x".repeat(500) };
+    let frames = batch(vec![removed(1), big.clone(), removed(2)], 200);
+    assert_eq!(frames, vec![removed(1), big, removed(2)]);
+}
+
+#[test]
+fn batches_round_trip_in_both_formats() {
+    let message = ServerMessage::Batch { messages: Batched(vec![removed(1), ServerMessage::PlayerIdle { player_id: 2 }]) };
+    assert_eq!(ServerMessage::from_bincode(&message.to_bincode().unwrap()).unwrap(), message);
+    assert_eq!(serde_json::from_str::<ServerMessage>(&serde_json::to_string(&message).unwrap()).unwrap(), message);
+
+    let nested = ServerMessage::Batch { messages: Batched(vec![message.clone()]) };
+    assert!(ServerMessage::from_bincode(&nested.to_bincode().unwrap()).is_err());
+    assert!(serde_json::from_str::<ServerMessage>(&serde_json::to_string(&nested).unwrap()).is_err());
+
+    let mut decoder = EventDecoder::new();
+    assert_eq!(decoder.decode(Message::Binary(message.to_bincode().unwrap().into())), vec![
+        ClientEvent::Message(removed(1)),
+        ClientEvent::Message(ServerMessage::PlayerIdle { player_id: 2 }),
+    ]);
+}
+
+#[tokio::test]
+async fn events_in_one_tick_share_a_frame() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ws = connect_json(addr).await;
+    let ids: Vec<u32> = server.get_state().planets.iter().take(3).map(|p| p.id).collect();
+    for &id in &ids {
+        server.remove_planet(id).unwrap();
+    }
+    loop {
+        let Some(Message::Text(text)) = next_message(&mut ws).await else { continue };
+        match serde_json::from_str(&text).unwrap() {
+            ServerMessage::Batch { messages } => {
+                // Possibly after the population counts from the last tick
+                let removals: Vec<ServerMessage> = messages.0.into_iter().filter(|m| matches!(m, ServerMessage::PlanetRemoved { .. })).collect();
+                assert_eq!(removals, ids.iter().copied().map(removed).collect::<Vec<_>>());
+                break;
+            }
+            ServerMessage::PlanetRemoved { .. } => panic!("a removal was sent on its own"),
+            _ => continue,
+        }
+    }
+}
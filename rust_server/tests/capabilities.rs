@@ -0,0 +1,143 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect, next_message, spawn_server, Client, TEST_CAPS};
+use futures_util::SinkExt;
+use galavox::client;
+use galavox::protocol::{encode_resync_request, encoding_for, Capabilities, ServerMessage, CAPABILITIES_HEADER};
+use galavox::server::GameServer;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+/// A server that broadcasts only when the test says so.
+fn quiet_server() -> GameServer {
+    GameServer::new().with_broadcast_interval(Duration::from_secs(3600))
+}
+
+/// Serves `server`, once its broadcast loop's first, immediate tick is out
+/// of the way.
+async fn spawn_quiet(server: &GameServer) -> std::net::SocketAddr {
+    let addr = spawn_server(server.clone()).await;
+    while server.current_tick() == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    addr
+}
+
+/// Connects with no `caps`, like every client from before capabilities.
+async fn connect_legacy(addr: std::net::SocketAddr) -> Client {
+    let (mut ws, response) = connect_async(format!("ws://{}", addr)).await.unwrap();
+    assert_eq!(response.headers()[CAPABILITIES_HEADER], "0");
+    loop {
+        if let Some(Message::Text(text)) = next_message(&mut ws).await
+            && text.starts_with("Welcome")
+        {
+            return ws;
+        }
+    }
+}
+
+/// The next binary frame as sent to a client with `caps`, batches left as
+/// they are.
+async fn next_raw(ws: &mut Client, caps: Capabilities) -> ServerMessage {
+    loop {
+        if let Some(Message::Binary(data)) = next_message(ws).await {
+            return encoding_for(caps, || ServerMessage::from_bincode(&data)).unwrap();
+        }
+    }
+}
+
+/// Frames up to the `State` of `tick`, other states left out.
+async fn frames_until_state(ws: &mut Client, caps: Capabilities, tick: u64) -> Vec<ServerMessage> {
+    let mut frames = Vec::new();
+    loop {
+        match next_raw(ws, caps).await {
+            ServerMessage::State { tick: at, .. } if at == tick => return frames,
+            ServerMessage::State { .. } => continue,
+            frame => frames.push(frame),
+        }
+    }
+}
+
+#[tokio::test]
+async fn batches_only_reach_clients_that_take_them() {
+    let server = quiet_server();
+    let addr = spawn_quiet(&server).await;
+    let mut old = connect_legacy(addr).await;
+    let mut new = connect(addr).await;
+
+    server.remove_planet(0).unwrap();
+    server.remove_planet(1).unwrap();
+    server.broadcast_game_state().await;
+
+    let tick = server.current_tick();
+    let removed = |frames: &[ServerMessage]| -> Vec<u32> {
+        frames.iter().filter_map(|m| match m {
+            ServerMessage::PlanetRemoved { planet_id } => Some(*planet_id),
+            _ => None,
+        }).collect()
+    };
+    let old_frames = frames_until_state(&mut old, Capabilities::NONE, tick).await;
+    assert!(!old_frames.iter().any(|m| matches!(m, ServerMessage::Batch { .. })));
+    assert_eq!(removed(&old_frames), [0, 1]);
+
+    let new_frames = frames_until_state(&mut new, TEST_CAPS, tick).await;
+    assert!(removed(&new_frames).is_empty());
+    let batched: Vec<ServerMessage> = new_frames.into_iter()
+        .flat_map(|m| match m {
+            ServerMessage::Batch { messages } => messages.0,
+            _ => Vec::new(),
+        })
+        .collect();
+    assert_eq!(removed(&batched), [0, 1]);
+}
+
+#[tokio::test]
+async fn deltas_only_reach_clients_that_take_them() {
+    let server = quiet_server();
+    let addr = spawn_quiet(&server).await;
+    let mut old = connect_legacy(addr).await;
+    let mut new = connect(addr).await;
+    server.broadcast_game_state().await;
+    server.broadcast_game_state().await;
+    let latest = server.current_tick();
+    frames_until_state(&mut old, Capabilities::NONE, latest).await;
+    frames_until_state(&mut new, TEST_CAPS, latest).await;
+
+    let seen = latest - 1;
+    for ws in [&mut old, &mut new] {
+        ws.send(Message::Binary(encode_resync_request(seen).into())).await.unwrap();
+    }
+    frames_until_state(&mut old, Capabilities::NONE, latest).await;
+    loop {
+        match next_raw(&mut new, TEST_CAPS).await {
+            ServerMessage::Resync { from_tick, .. } => break assert_eq!(from_tick, seen),
+            ServerMessage::State { .. } => continue,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn unknown_bits_are_ignored() {
+    let addr = spawn_server(quiet_server()).await;
+    let (_ws, response) = connect_async(format!("ws://{}/?caps={}", addr, u32::MAX)).await.unwrap();
+    assert_eq!(response.headers()[CAPABILITIES_HEADER], Capabilities::SUPPORTED.0.to_string().as_str());
+
+    let (_ws, response) = connect_async(format!("ws://{}/?caps=lots", addr)).await.unwrap();
+    assert_eq!(response.headers()[CAPABILITIES_HEADER], "0");
+
+    let conn = client::Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    assert_eq!(conn.capabilities(), Capabilities::DEFAULT);
+}
+
+#[test]
+fn capability_sets() {
+    let both = Capabilities::BATCH | Capabilities::DELTAS;
+    assert_eq!(both | Capabilities::KEEPALIVE | Capabilities::TERRAIN | Capabilities::CODES | Capabilities::NAMES | Capabilities::QUANTIZED | Capabilities::LABELS | Capabilities::CHAT_ACKS,
+        Capabilities::DEFAULT);
+    assert_eq!(Capabilities::DEFAULT | Capabilities::CRC, Capabilities::SUPPORTED);
+    assert!(both.contains(Capabilities::BATCH));
+    assert!(!Capabilities::BATCH.contains(both));
+    assert_eq!(Capabilities(0b110000000001).intersect(Capabilities::SUPPORTED), Capabilities::BATCH);
+}
@@ -0,0 +1,61 @@
+use galavox::capture::{read_capture, CaptureWriter, CapturedFrame, FrameKind, CAPTURE_VERSION};
+use galavox::protocol::{GameState, Position, ServerMessage};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Message};
+
+fn sample_messages() -> Vec<Message> {
+    let state = ServerMessage::State {
+        tick: 7,
+        server_time_ms: 700,
+        state: GameState::new(vec![], vec![], Position { x: 1.0, y: 2.0, z: 3.0 }),
+    };
+    vec![
+        Message::Binary(state.to_bincode().unwrap().into()),
+        Message::Text("Welcome to Crux Server!".into()),
+        Message::Ping(vec![1, 2, 3].into()),
+        Message::Close(Some(CloseFrame { code: CloseCode::Policy, reason: "idle timeout".into() })),
+        Message::Close(None),
+    ]
+}
+
+fn capture(messages: &[Message]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut writer = CaptureWriter::new(&mut bytes).unwrap();
+    for (i, message) in messages.iter().enumerate() {
+        writer.write_frame(&CapturedFrame::from_message(i as u64 * 100, message).unwrap()).unwrap();
+    }
+    bytes
+}
+
+#[test]
+fn capture_round_trips_every_frame_kind() {
+    let messages = sample_messages();
+    let frames = read_capture(capture(&messages).as_slice()).unwrap();
+
+    assert_eq!(frames.len(), messages.len());
+    for (i, (frame, message)) in frames.iter().zip(&messages).enumerate() {
+        assert_eq!(frame.elapsed_ms, i as u64 * 100);
+        assert_eq!(&frame.to_message().unwrap(), message);
+    }
+    assert_eq!(frames[0].kind, FrameKind::Binary);
+    assert_eq!(frames[3].kind, FrameKind::Close);
+}
+
+#[test]
+fn header_is_versioned() {
+    let bytes = capture(&[]);
+    assert_eq!(&bytes[..6], b"GVXCAP");
+    assert_eq!(u16::from_le_bytes([bytes[6], bytes[7]]), CAPTURE_VERSION);
+
+    let mut future = bytes.clone();
+    future[6..8].copy_from_slice(&(CAPTURE_VERSION + 1).to_le_bytes());
+    assert!(read_capture(future.as_slice()).is_err());
+    assert!(read_capture(&b"not a capture"[..]).is_err());
+}
+
+#[test]
+fn truncated_last_frame_is_dropped() {
+    let messages = sample_messages();
+    let bytes = capture(&messages);
+    let frames = read_capture(&bytes[..bytes.len() - 1]).unwrap();
+    assert_eq!(frames.len(), messages.len() - 1);
+}
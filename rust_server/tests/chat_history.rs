@@ -0,0 +1,109 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{next_json, send_text, spawn_server, Client, TEST_CAPS};
+use galavox::chat_history::{ChatLog, HISTORY_LIMIT, JOIN_HISTORY, MORE_HISTORY_LIMIT};
+use galavox::config::ServerConfig;
+use galavox::protocol::{ChatLine, ServerMessage};
+use galavox::server::GameServer;
+use tokio_tungstenite::connect_async;
+
+fn chat_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("galavox-{}-{}.chat.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn texts(lines: &[ChatLine]) -> Vec<&str> {
+    lines.iter().map(|line| line.text.as_str()).collect()
+}
+
+/// Connects in JSON mode to `path` (e.g. `/room/lobby`) as `name`.
+async fn join(addr: std::net::SocketAddr, path: &str, name: &str) -> Client {
+    let url = format!("ws://{}{}?format=json&caps={}&name={}", addr, path, TEST_CAPS.0, name);
+    connect_async(url).await.unwrap().0
+}
+
+/// The next `ChatHistory`, and whether `Joined` came before it.
+async fn next_history(ws: &mut Client) -> (Vec<ChatLine>, bool) {
+    let mut joined = false;
+    loop {
+        match next_json(ws).await {
+            ServerMessage::Joined { .. } => joined = true,
+            ServerMessage::ChatHistory { messages } => return (messages, joined),
+            _ => continue,
+        }
+    }
+}
+
+#[test]
+fn the_oldest_lines_make_way() {
+    let mut log = ChatLog::new();
+    for i in 0..HISTORY_LIMIT + 5 {
+        log.record("ada", None, &i.to_string(), 1_000);
+    }
+    assert_eq!(log.len(), HISTORY_LIMIT);
+    let latest = log.latest(JOIN_HISTORY);
+    assert_eq!(latest.len(), JOIN_HISTORY);
+    assert_eq!(latest.last().unwrap().text, (HISTORY_LIMIT + 4).to_string());
+    assert_eq!(log.before(u64::MAX, HISTORY_LIMIT).len(), MORE_HISTORY_LIMIT);
+    // Lines from the same millisecond still come in order
+    assert!(latest.windows(2).all(|pair| pair[0].timestamp_ms < pair[1].timestamp_ms));
+}
+
+#[test]
+fn paging_back_stops_where_the_log_does() {
+    let mut log = ChatLog::new();
+    let stamps: Vec<u64> = ["a", "b", "c", "d", "e"].iter().enumerate()
+        .map(|(i, text)| log.record("ada", Some("lobby"), text, 1_000 + i as u64 * 10).timestamp_ms)
+        .collect();
+    assert_eq!(texts(&log.before(stamps[4], 2)), ["c", "d"]);
+    assert_eq!(texts(&log.before(stamps[2], 20)), ["a", "b"]);
+    assert!(log.before(stamps[0], 20).is_empty());
+    assert!(log.before(u64::MAX, 0).is_empty());
+    assert_eq!(log.latest(JOIN_HISTORY).len(), 5);
+}
+
+#[test]
+fn the_log_survives_a_restart() {
+    let path = chat_path("restart");
+    let mut log = ChatLog::load(&path);
+    assert!(log.is_empty());
+    log.record("ada", Some("lobby"), "hello", 1_000);
+    log.record("bob", None, "hi", 2_000);
+    log.save().unwrap();
+    assert_eq!(ChatLog::load(&path).latest(JOIN_HISTORY), log.latest(JOIN_HISTORY));
+
+    std::fs::write(&path, "[ not json").unwrap();
+    assert!(ChatLog::load(&path).is_empty());
+    let mut aside = path.clone().into_os_string();
+    aside.push(".corrupt");
+    let _ = std::fs::remove_file(aside);
+
+    let config = ServerConfig::from_toml("chat_file = \"chat.json\"\n").unwrap();
+    assert_eq!(config.chat_file, Some(PathBuf::from("chat.json")));
+}
+
+#[tokio::test]
+async fn joiners_are_sent_the_latest_chat_and_can_page_back() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = join(addr, "/room/lobby", "Ada").await;
+    for text in ["one", "two", "three"] {
+        send_text(&mut ada, &format!(r#"{{"type":"Chat","text":"{}"}}"#, text)).await;
+        while next_json(&mut ada).await != (ServerMessage::Echo { text: text.to_string() }) {}
+    }
+
+    let mut bob = join(addr, "/", "Bob").await;
+    let (history, after_joined) = next_history(&mut bob).await;
+    assert!(after_joined);
+    assert_eq!(texts(&history), ["one", "two", "three"]);
+    assert_eq!((history[0].sender.as_str(), history[0].room.as_deref()), ("Ada", Some("lobby")));
+
+    let before = history[2].timestamp_ms;
+    send_text(&mut bob, &format!(r#"{{"type":"MoreHistory","before_timestamp":{},"limit":5}}"#, before)).await;
+    assert_eq!(texts(&next_history(&mut bob).await.0), ["one", "two"]);
+    send_text(&mut bob, &format!(r#"{{"type":"MoreHistory","before_timestamp":{},"limit":5}}"#, history[0].timestamp_ms)).await;
+    assert!(next_history(&mut bob).await.0.is_empty());
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use common::{connect_json, next_json, send_text, spawn_server};
+use galavox::chat_receipts::{ChatError, MAX_CHAT_LEN};
+use galavox::client::{ChatReceipt, ClientError, ClientEvent, Connection, PendingChat};
+use galavox::protocol::{Capabilities, ClientMessage, MessageCode, ServerMessage};
+use galavox::server::GameServer;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+async fn join(addr: SocketAddr, capabilities: Capabilities) -> Connection {
+    let mut conn = Connection::connect_with_capabilities(&format!("ws://{}", addr), Some("Ada"), false, capabilities).await.unwrap();
+    while !matches!(conn.next_event().await, ClientEvent::Joined { .. }) {}
+    conn
+}
+
+/// Reads events until `pending` resolves.
+async fn settle(conn: &mut Connection, mut pending: PendingChat) -> ChatReceipt {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            tokio::select! {
+                receipt = &mut pending => return receipt,
+                _ = conn.next_event() => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for a chat receipt")
+}
+
+#[tokio::test]
+async fn accepted_chats_are_numbered_in_order() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ada = join(addr, Capabilities::DEFAULT).await;
+
+    let first = ada.send_chat_tracked("one").await.unwrap();
+    // Untracked chats are numbered too
+    ada.send_chat("two").await.unwrap();
+    let third = ada.send_chat_tracked("three").await.unwrap();
+    let ChatReceipt::Accepted { server_seq: 3, tick } = settle(&mut ada, third).await else { panic!("third chat not acked as the third") };
+    let ChatReceipt::Accepted { server_seq: 1, tick: earlier } = settle(&mut ada, first).await else { panic!("first chat not acked as the first") };
+    assert!(earlier <= tick);
+}
+
+#[tokio::test]
+async fn receipts_carry_the_tick_of_the_chats_broadcast() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ada = join(addr, Capabilities::DEFAULT).await;
+
+    let mut pending = ada.send_chat_tracked("hello").await.unwrap();
+    let (mut receipt, mut heard) = (None, None);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while receipt.is_none() || heard.is_none() {
+            tokio::select! {
+                done = &mut pending, if receipt.is_none() => receipt = Some(done),
+                event = ada.next_event() => if let ClientEvent::Message(ServerMessage::Chatted { tick, line }) = event {
+                    heard = Some((tick, line.sender, line.text));
+                },
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the receipt and the broadcast");
+    let Some(ChatReceipt::Accepted { server_seq: 1, tick }) = receipt else { panic!("not acked: {:?}", receipt) };
+    assert_eq!(heard, Some((tick, "Ada".to_string(), "hello".to_string())));
+}
+
+#[tokio::test]
+async fn long_chats_are_refused() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ada = join(addr, Capabilities::DEFAULT).await;
+
+    let longest = ada.send_chat_tracked(&"é".repeat(MAX_CHAT_LEN)).await.unwrap();
+    assert!(matches!(settle(&mut ada, longest).await, ChatReceipt::Accepted { server_seq: 1, .. }));
+    let too_long = ada.send_chat_tracked(&"é".repeat(MAX_CHAT_LEN + 1)).await.unwrap();
+    assert_eq!(settle(&mut ada, too_long).await, ChatReceipt::Rejected(ChatError::TooLong { max_len: MAX_CHAT_LEN as u32 }));
+    // A refused chat takes no number
+    let next = ada.send_chat_tracked("short").await.unwrap();
+    assert!(matches!(settle(&mut ada, next).await, ChatReceipt::Accepted { server_seq: 2, .. }));
+}
+
+#[tokio::test]
+async fn chats_over_the_rate_limit_are_refused() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ada = join(addr, Capabilities::DEFAULT).await;
+
+    let mut pending = Vec::new();
+    for i in 0..6 {
+        pending.push(ada.send_chat_tracked(&format!("spam {}", i)).await.unwrap());
+    }
+    let mut receipts = Vec::new();
+    for receipt in pending {
+        receipts.push(settle(&mut ada, receipt).await);
+    }
+    assert!(receipts[..5].iter().all(|receipt| matches!(receipt, ChatReceipt::Accepted { .. })), "{:?}", receipts);
+    let ChatReceipt::Rejected(ChatError::RateLimited { retry_after_ms }) = receipts[5] else { panic!("sixth chat not refused: {:?}", receipts[5]) };
+    assert!(retry_after_ms > 0);
+}
+
+#[tokio::test]
+async fn client_refs_are_echoed_in_any_order() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ws = connect_json(addr).await;
+
+    let chat = |text: &str, client_ref| serde_json::to_string(&ClientMessage::Chat { text: text.to_string(), client_ref: Some(client_ref) }).unwrap();
+    send_text(&mut ws, &chat("a", 9)).await;
+    send_text(&mut ws, &chat("b", 3)).await;
+    send_text(&mut ws, &chat("c", 3)).await;
+    send_text(&mut ws, &chat(&"x".repeat(MAX_CHAT_LEN + 1), 7)).await;
+    send_text(&mut ws, &chat("d", u64::MAX)).await;
+    let mut receipts = Vec::new();
+    while receipts.len() < 5 {
+        match next_json(&mut ws).await {
+            ServerMessage::ChatAck { client_ref, server_seq, .. } => receipts.push((client_ref, Ok(server_seq))),
+            ServerMessage::ChatRejected { client_ref, error } => receipts.push((client_ref, Err(error))),
+            _ => {}
+        }
+    }
+    assert_eq!(receipts, [
+        (9, Ok(1)),
+        (3, Ok(2)),
+        (3, Ok(3)),
+        (7, Err(ChatError::TooLong { max_len: MAX_CHAT_LEN as u32 })),
+        (u64::MAX, Ok(4)),
+    ]);
+}
+
+#[tokio::test]
+async fn clients_without_the_capability_get_no_receipts() {
+    let addr = spawn_server(GameServer::new()).await;
+    let older = Capabilities(Capabilities::DEFAULT.0 & !Capabilities::CHAT_ACKS.0);
+    let mut ada = join(addr, older).await;
+    assert!(matches!(ada.send_chat_tracked("hi").await, Err(ClientError::Unsupported(Capabilities::CHAT_ACKS))));
+
+    // A ref sent anyway is ignored, and a long chat refused with a notice:
+    // the length limit holds without the capability too
+    let long = "x".repeat(MAX_CHAT_LEN + 1);
+    ada.send(&ClientMessage::Chat { text: long.clone(), client_ref: Some(1) }).await.unwrap();
+    ada.send(&ClientMessage::Chat { text: "hi".to_string(), client_ref: Some(2) }).await.unwrap();
+    let mut refused = false;
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), ada.next_event()).await.expect("timed out") {
+            ClientEvent::Localized { message: MessageCode::InvalidMessage { .. }, .. } => refused = true,
+            ClientEvent::Message(message @ (ServerMessage::ChatAck { .. } | ServerMessage::ChatRejected { .. })) => panic!("got {:?}", message),
+            ClientEvent::Chat { text } if text == "hi" => break,
+            ClientEvent::Chat { text } => panic!("echoed {} characters", text.len()),
+            _ => {}
+        }
+    }
+    assert!(refused);
+}
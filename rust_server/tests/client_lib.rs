@@ -0,0 +1,90 @@
+mod common;
+
+use common::spawn_server;
+use galavox::client::{ClientError, ClientEvent, Connection};
+use galavox::protocol::{GameState, Position};
+use galavox::server::GameServer;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// The next event matching `f`, skipping the rest.
+async fn next_matching<T>(conn: &mut Connection, mut f: impl FnMut(ClientEvent) -> Option<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = f(conn.next_event().await) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an event")
+}
+
+async fn join(addr: SocketAddr, name: &str) -> (Connection, GameState) {
+    let mut conn = Connection::connect(&format!("ws://{}", addr), Some(name)).await.unwrap();
+    let state = next_matching(&mut conn, |e| match e {
+        ClientEvent::StateSnapshot { state, .. } => Some(state),
+        _ => None,
+    }).await;
+    next_matching(&mut conn, |e| matches!(e, ClientEvent::Joined { .. }).then_some(())).await;
+    (conn, state)
+}
+
+#[tokio::test]
+async fn named_connection_joins_and_chats() {
+    let addr = spawn_server(GameServer::new()).await;
+    let (mut ada, state) = join(addr, "Ada").await;
+    let id = ada.player_id().unwrap();
+    assert!(state.players.iter().any(|p| p.id == id && &*p.name == "Ada"));
+
+    ada.send_chat("hello").await.unwrap();
+    let text = next_matching(&mut ada, |e| match e {
+        ClientEvent::Chat { text } => Some(text),
+        _ => None,
+    }).await;
+    assert_eq!(text, "hello");
+
+    let target = Position { x: 12.0, y: 0.0, z: -4.0 };
+    ada.send_position(target.clone()).await.unwrap();
+    next_matching(&mut ada, |e| match e {
+        ClientEvent::StateSnapshot { state, .. } => state.players.iter()
+            .any(|p| p.id == id && p.position == target)
+            .then_some(()),
+        _ => None,
+    }).await;
+}
+
+#[tokio::test]
+async fn joins_and_leaves_are_reported() {
+    let addr = spawn_server(GameServer::new()).await;
+    let (mut ada, _) = join(addr, "Ada").await;
+    let (mut bob, _) = join(addr, "Bob").await;
+    let bob_id = bob.player_id().unwrap();
+
+    let joined = next_matching(&mut ada, |e| match e {
+        ClientEvent::PlayerJoined { player } => Some(player),
+        _ => None,
+    }).await;
+    assert_eq!((joined.id, &*joined.name), (bob_id, "Bob"));
+
+    bob.close().await.unwrap();
+    let left = next_matching(&mut ada, |e| match e {
+        ClientEvent::PlayerLeft { player_id } => Some(player_id),
+        _ => None,
+    }).await;
+    assert_eq!(left, bob_id);
+}
+
+#[tokio::test]
+async fn taken_and_invalid_names() {
+    let addr = spawn_server(GameServer::new()).await;
+    let (ada, _) = join(addr, "Ada").await;
+    let (second, state) = join(addr, "Ada").await;
+    let second = state.players.iter().find(|p| Some(p.id) == second.player_id()).unwrap();
+    assert_ne!(&*second.name, "Ada");
+    assert!(ada.player_id().is_some());
+
+    let url = format!("ws://{}", addr);
+    assert!(matches!(Connection::connect(&url, Some("no spaces")).await, Err(ClientError::InvalidName(_))));
+    assert!(matches!(Connection::connect(&url, Some("")).await, Err(ClientError::InvalidName(_))));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::{connect, next_server_message, spawn_server, Client};
+use futures_util::StreamExt;
+use galavox::admin;
+use galavox::protocol::{ErrorCode, ServerMessage};
+use galavox::server::GameServer;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// The `Error` message's code and the close frame's code, in that order.
+async fn error_then_close(ws: &mut Client) -> (u16, u16) {
+    let error = loop {
+        if let ServerMessage::Error { code, .. } = next_server_message(ws).await {
+            break code;
+        }
+    };
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), ws.next()).await.unwrap() {
+            Some(Ok(Message::Close(Some(frame)))) => return (error, frame.code.into()),
+            Some(Ok(_)) => continue,
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    }
+}
+
+/// Connects without waiting for the welcome, for connections that are turned away.
+async fn connect_raw(addr: SocketAddr) -> Client {
+    tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap().0
+}
+
+#[test]
+fn codes_are_application_codes_and_round_trip() {
+    for code in ErrorCode::ALL {
+        assert!((4000..5000).contains(&code.code()), "{:?}", code);
+        assert_eq!(ErrorCode::from_code(code.code()), Some(code));
+        assert!(!code.explanation().is_empty());
+    }
+    assert_eq!(ErrorCode::from_code(1000), None);
+    assert!(ErrorCode::ServerFull.may_reconnect());
+    assert!(!ErrorCode::Kicked.may_reconnect() && !ErrorCode::Banned.may_reconnect());
+}
+
+#[tokio::test]
+async fn kick_and_server_full_close_with_their_own_codes() {
+    let server = GameServer::new().with_max_players(Some(1));
+    let addr = spawn_server(server.clone()).await;
+    let mut player = connect(addr).await;
+
+    let mut turned_away = connect_raw(addr).await;
+    let full = ErrorCode::ServerFull.code();
+    assert_eq!(error_then_close(&mut turned_away).await, (full, full));
+
+    let id = server.player_list(std::time::Instant::now())[0].player.id;
+    assert!(admin::run_line(&server, &format!("kick {}", id)).unwrap().starts_with(&format!("kicked #{}", id)));
+    let kicked = ErrorCode::Kicked.code();
+    assert_eq!(error_then_close(&mut player).await, (kicked, kicked));
+    assert!(admin::run_line(&server, "kick 999").is_err());
+}
@@ -0,0 +1,125 @@
+#![cfg(feature = "cluster")]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::spawn_server;
+use galavox::admin::run_line;
+use galavox::client::{ClientEvent, Connection};
+use galavox::cluster::{first_player_id, ClusterConfig, RemotePlayers, REMOTE_TIMEOUT};
+use galavox::protocol::{GameState, Player, PlayerAppearance, Position};
+use galavox::server::GameServer;
+use galavox::worldgen::SpiralGenerator;
+
+fn player(id: u32) -> Player {
+    Player {
+        id,
+        name: format!("p{}", id).into(),
+        level: 1,
+        position: Position { x: 0.0, y: 0.0, z: 0.0 },
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("p"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+#[test]
+fn cluster_urls_name_a_redis_server() {
+    let config = ClusterConfig::from_url("redis://10.0.0.5:7000", 2).unwrap();
+    assert_eq!(config, ClusterConfig { addr: "10.0.0.5:7000".to_string(), instance: 2 });
+    assert!(!config.is_primary());
+    assert_eq!(ClusterConfig::from_url("redis://cache/", 0).unwrap().addr, "cache:6379");
+    assert!(ClusterConfig::from_url("http://cache:6379", 0).is_err());
+    assert!(ClusterConfig::from_url("redis://user@cache", 0).is_err());
+    assert!(ClusterConfig::from_url("redis://cache/0", 0).is_err());
+
+    assert_eq!(first_player_id(0), 0);
+    assert_eq!(first_player_id(3), 3 << 24);
+}
+
+#[test]
+fn remote_players_are_flagged_and_expire_with_their_instance() {
+    let start = Instant::now();
+    let mut remote = RemotePlayers::new();
+    remote.update(1, vec![player(first_player_id(1) + 1), player(first_player_id(1))], start);
+    remote.update(2, vec![player(first_player_id(2))], start + REMOTE_TIMEOUT);
+
+    let ids = |players: Vec<Player>| players.iter().map(|p| p.id).collect::<Vec<_>>();
+    let players = remote.players(start + REMOTE_TIMEOUT);
+    assert!(players.iter().all(|p| p.remote));
+    assert_eq!(ids(players), [first_player_id(1), first_player_id(1) + 1, first_player_id(2)]);
+    assert_eq!(remote.instance_of(first_player_id(2)), Some(2));
+
+    // Instance 1 went quiet
+    assert_eq!(ids(remote.players(start + REMOTE_TIMEOUT + Duration::from_millis(1))), [first_player_id(2)]);
+    assert_eq!(remote.instance_of(first_player_id(1)), None);
+}
+
+#[test]
+fn secondaries_leave_the_planets_to_the_primary() {
+    let secondary = GameServer::new().with_cluster(ClusterConfig::from_url("redis://127.0.0.1", 1).unwrap());
+    assert!(!secondary.owns_planets());
+    let refused = "the planets belong to the cluster's primary instance".to_string();
+    assert_eq!(run_line(&secondary, "planet remove 0"), Err(refused.clone()));
+    assert_eq!(run_line(&secondary, "regenerate-world 4"), Err(refused));
+    assert!(GameServer::new().with_cluster(ClusterConfig::from_url("redis://127.0.0.1", 0).unwrap()).owns_planets());
+}
+
+fn layout(state: &GameState) -> Vec<(u32, Position)> {
+    state.planets.iter().map(|p| (p.id, p.position.clone())).collect()
+}
+
+/// Runs only with `GALAVOX_TEST_REDIS=redis://host:port` pointing at a
+/// Redis server nothing else is using.
+#[tokio::test]
+async fn a_player_on_one_instance_shows_up_on_the_other() {
+    let Ok(url) = std::env::var("GALAVOX_TEST_REDIS") else {
+        eprintln!("GALAVOX_TEST_REDIS is not set, skipping");
+        return;
+    };
+    let primary = GameServer::new().with_cluster(ClusterConfig::from_url(&url, 0).unwrap());
+    let a = spawn_server(primary.clone()).await;
+    // A different world, which the primary's replaces
+    let b = spawn_server(GameServer::with_generator(Box::new(SpiralGenerator::default()))
+        .with_cluster(ClusterConfig::from_url(&url, 1).unwrap())).await;
+    let primary_layout = layout(&primary.get_state());
+
+    let mut ada = Connection::connect(&format!("ws://{}", a), Some("Ada")).await.unwrap();
+    let mut bob = Connection::connect(&format!("ws://{}", b), Some("Bob")).await.unwrap();
+    let (mut seen_on_a, mut seen_on_b) = (None, None);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while seen_on_a.is_none() || seen_on_b.is_none() {
+            // Both connections are read, so neither misses its keepalives
+            let (event, on_a) = tokio::select! {
+                event = ada.next_event() => (event, true),
+                event = bob.next_event() => (event, false),
+            };
+            match event {
+                ClientEvent::StateSnapshot { state, .. } if layout(&state) == primary_layout => {
+                    let (seen, name) = if on_a { (&mut seen_on_a, "Bob") } else { (&mut seen_on_b, "Ada") };
+                    if let Some(remote) = state.players.iter().find(|p| &*p.name == name) {
+                        let local = state.players.iter().find(|p| &*p.name != name).unwrap();
+                        *seen = Some((remote.clone(), local.clone()));
+                    }
+                }
+                ClientEvent::Disconnected { reason, .. } => panic!("disconnected: {}", reason),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("each instance never showed the other's player on the primary's planets");
+
+    let (remote_ada, local_bob) = seen_on_b.unwrap();
+    assert!(remote_ada.remote && !local_bob.remote);
+    assert!(remote_ada.id < first_player_id(1) && local_bob.id >= first_player_id(1));
+    let (remote_bob, local_ada) = seen_on_a.unwrap();
+    assert!(remote_bob.remote && !local_ada.remote);
+    assert_eq!((remote_bob.id, local_ada.id), (local_bob.id, remote_ada.id));
+}
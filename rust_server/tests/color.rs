@@ -0,0 +1,82 @@
+use galavox::admin::{run_line, PlanetEdit};
+use galavox::color::{contrast_ratio, from_linear, label_contrast, primary_display_color, relative_luminance, to_linear, LabelContrast};
+use galavox::protocol::{encode_server_message, encoding_for, Capabilities, Color, GameState, Position, ServerMessage, WireFormat};
+use galavox::server::GameServer;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color { r, g, b }
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[test]
+fn luminance_of_known_colors() {
+    assert_eq!(relative_luminance(&rgb(0, 0, 0)), 0.0);
+    assert!(close(relative_luminance(&rgb(255, 255, 255)), 1.0));
+    assert!(close(relative_luminance(&rgb(255, 0, 0)), 0.2126));
+    assert!(close(relative_luminance(&rgb(128, 128, 128)), 0.2159));
+    assert!(close(relative_luminance(&rgb(10, 10, 10)), 0.0030));
+    assert!(close(contrast_ratio(&rgb(0, 0, 0), &rgb(255, 255, 255)), 21.0));
+    // Every channel survives the trip to linear light and back
+    assert!((0..=255).all(|c| from_linear(to_linear(c)) == c));
+}
+
+#[test]
+fn colors_average_in_linear_light() {
+    let (red, black, grey) = (rgb(255, 0, 0), rgb(0, 0, 0), rgb(128, 128, 128));
+    assert_eq!(primary_display_color(&[red.clone(), red.clone(), red.clone()]), red);
+    assert_eq!(primary_display_color(&[grey.clone(), grey.clone(), grey.clone()]), grey);
+    // Not the #550000 that averaging the bytes gives
+    assert_eq!(primary_display_color(&[red, black.clone(), black.clone()]), rgb(156, 0, 0));
+    assert_eq!(primary_display_color(&[rgb(255, 255, 255), black.clone(), black]), rgb(156, 156, 156));
+}
+
+#[test]
+fn labels_take_the_text_that_reads_better() {
+    assert_eq!(label_contrast(&rgb(255, 0, 0)), LabelContrast::Dark);
+    assert_eq!(label_contrast(&rgb(10, 10, 10)), LabelContrast::Light);
+    assert_eq!(label_contrast(&rgb(128, 128, 128)), LabelContrast::Dark);
+    assert_eq!(label_contrast(&rgb(0, 0, 255)), LabelContrast::Light);
+    // Black and white read equally at a luminance of about 0.18
+    assert_eq!(label_contrast(&rgb(117, 117, 117)), LabelContrast::Light);
+    assert_eq!(label_contrast(&rgb(118, 118, 118)), LabelContrast::Dark);
+}
+
+#[test]
+fn planets_keep_their_label_colors_up_to_date() {
+    let origin = Position { x: 0.0, y: 0.0, z: 0.0 };
+    let server = GameServer::new().with_world(GameState::new(vec![], vec![], origin));
+    run_line(&server, "planet add size=50 x=0 y=0 z=0 colors=ff0000,000000,000000").unwrap();
+    let planet = server.get_state().planets[0].clone();
+    assert_eq!((&planet.primary_display_color, planet.label_contrast), (&rgb(156, 0, 0), LabelContrast::Light));
+
+    let planet = server.update_planet(planet.id, &PlanetEdit::Color(1, rgb(255, 0, 0))).unwrap();
+    assert_eq!(planet.primary_display_color, rgb(213, 0, 0));
+    let planet = server.update_planet(planet.id, &PlanetEdit::Color(2, rgb(255, 255, 255))).unwrap();
+    assert_eq!((&planet.primary_display_color, planet.label_contrast), (&rgb(255, 156, 156), LabelContrast::Dark));
+    assert_eq!(server.get_state().planets[0], planet);
+}
+
+#[test]
+fn only_labels_clients_get_them_in_binary_frames() {
+    let server = GameServer::new();
+    let planet = server.get_state().planets[0].clone();
+    assert_eq!(planet.primary_display_color, primary_display_color(&planet.colors));
+    let message = ServerMessage::PlanetUpdated { planet: planet.clone() };
+    let binary = |capabilities| match encoding_for(capabilities, || encode_server_message(WireFormat::Binary, &message)).unwrap() {
+        Message::Binary(data) => data,
+        other => panic!("expected a binary frame, got {:?}", other),
+    };
+
+    let older = Capabilities(Capabilities::DEFAULT.0 & !Capabilities::LABELS.0);
+    let (with, without) = (binary(Capabilities::DEFAULT), binary(older));
+    assert!(with.len() > without.len(), "{} bytes with labels, {} without", with.len(), without.len());
+    assert_eq!(encoding_for(Capabilities::DEFAULT, || ServerMessage::from_bincode(&with)).unwrap(), message);
+    let ServerMessage::PlanetUpdated { planet: bare } = encoding_for(older, || ServerMessage::from_bincode(&without)).unwrap() else {
+        panic!("not a planet update");
+    };
+    assert_eq!((bare.primary_display_color, bare.label_contrast), (Color::default(), LabelContrast::default()));
+}
@@ -0,0 +1,209 @@
+#![allow(dead_code)]
+
+use futures_util::{SinkExt, StreamExt};
+use galavox::color::LabelContrast;
+use galavox::protocol::{encoding_for, Capabilities, Color, GameState, MessageCode, Planet, Player, Position, ServerMessage, TerrainParams};
+use galavox::server::GameServer;
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{
+    connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream,
+};
+
+pub type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Messages unwrapped from a `Batch` and not yet returned, by the client's
+/// local address.
+static UNBATCHED: Mutex<BTreeMap<SocketAddr, VecDeque<ServerMessage>>> = Mutex::new(BTreeMap::new());
+
+fn local_addr(ws: &Client) -> SocketAddr {
+    match ws.get_ref() {
+        MaybeTlsStream::Plain(tcp) => tcp.local_addr().unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+fn take_unbatched(ws: &Client) -> Option<ServerMessage> {
+    UNBATCHED.lock().unwrap().get_mut(&local_addr(ws))?.pop_front()
+}
+
+/// The first message of a batch, keeping the rest for the next calls.
+fn unbatch(ws: &Client, message: ServerMessage) -> ServerMessage {
+    let ServerMessage::Batch { messages } = message else { return message };
+    let mut messages = VecDeque::from(messages.0);
+    let first = messages.pop_front().expect("batches are never empty");
+    UNBATCHED.lock().unwrap().entry(local_addr(ws)).or_default().extend(messages);
+    first
+}
+
+/// A grey planet of size 100 at `x` on the x axis, with no owner, moons,
+/// resources or name. Tests set what they care about on top of it, as in
+/// `Planet { size: 50.0, ..planet(id, x) }`.
+pub fn planet(id: u32, x: f32) -> Planet {
+    let grey = Color { r: 128, g: 128, b: 128 };
+    Planet {
+        id,
+        size: 100.0,
+        colors: [grey.clone(), grey.clone(), grey],
+        module_type: 0,
+        position: Position { x, y: 0.0, z: 0.0 },
+        owner: None,
+        moons: vec![],
+        resources: 0,
+        capacity: 0,
+        terrain: TerrainParams::default(),
+        name: String::new(),
+        primary_display_color: Color::default(),
+        label_contrast: LabelContrast::default(),
+    }
+}
+
+/// Runs the server on an ephemeral port, as the `server` binary does, until
+/// the test ends; returns once it is ready for connections.
+pub async fn spawn_server(game_server: GameServer) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let handle = game_server.spawn(listener).unwrap();
+    handle.ready().await;
+    handle.local_addr()
+}
+
+/// The client library's capabilities but KEEPALIVE, which the raw test
+/// clients do not answer, and QUANTIZED, as they read every State whole.
+pub const TEST_CAPS: Capabilities = Capabilities(Capabilities::DEFAULT.0 & !Capabilities::KEEPALIVE.0 & !Capabilities::QUANTIZED.0);
+
+/// Connects, declaring `TEST_CAPS`, and consumes the initial state and
+/// welcome messages.
+pub async fn connect(addr: SocketAddr) -> Client {
+    let (mut ws, _) = connect_async(format!("ws://{}/?caps={}", addr, TEST_CAPS.0)).await.unwrap();
+    loop {
+        match next_message(&mut ws).await {
+            Some(Message::Text(text)) if text.starts_with("Welcome") => return ws,
+            Some(Message::Binary(data)) if is_welcome(&data) => return ws,
+            Some(_) => continue,
+            None => panic!("connection closed during handshake"),
+        }
+    }
+}
+
+/// Connects in JSON mode (`?format=json`), declaring `TEST_CAPS`, and
+/// consumes the initial state and welcome.
+pub async fn connect_json(addr: SocketAddr) -> Client {
+    let (mut ws, _) = connect_async(format!("ws://{}/?format=json&caps={}", addr, TEST_CAPS.0)).await.unwrap();
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::Notice { text } if text.starts_with("Welcome") => return ws,
+            ServerMessage::Localized { message: MessageCode::Welcome {} | MessageCode::WelcomeSpectator {}, .. } => return ws,
+            _ => continue,
+        }
+    }
+}
+
+/// A binary frame as sent to a `TEST_CAPS` client.
+fn decode(data: &[u8]) -> bincode::Result<ServerMessage> {
+    encoding_for(TEST_CAPS, || ServerMessage::from_bincode(data))
+}
+
+/// Whether a binary frame is the welcome, sent as a code to `TEST_CAPS`.
+fn is_welcome(data: &[u8]) -> bool {
+    matches!(decode(data),
+             Ok(ServerMessage::Localized { message: MessageCode::Welcome {} | MessageCode::WelcomeSpectator {}, .. }))
+}
+
+/// Next server message from a JSON-mode connection, batches unwrapped.
+pub async fn next_json(ws: &mut Client) -> ServerMessage {
+    if let Some(message) = take_unbatched(ws) {
+        return message;
+    }
+    loop {
+        match next_message(ws).await {
+            Some(Message::Text(text)) => return unbatch(ws, serde_json::from_str(&text).unwrap()),
+            Some(_) => continue,
+            None => panic!("connection closed while waiting for a JSON message"),
+        }
+    }
+}
+
+/// Next non-ping message, or `None` once the connection is closed or errored.
+pub async fn next_message(ws: &mut Client) -> Option<Message> {
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), ws.next()).await {
+            Ok(Some(Ok(Message::Ping(_) | Message::Pong(_)))) => continue,
+            Ok(Some(Ok(Message::Close(_)))) | Ok(Some(Err(_))) | Ok(None) => return None,
+            Ok(Some(Ok(msg))) => return Some(msg),
+            Err(_) => panic!("timed out waiting for a message"),
+        }
+    }
+}
+
+/// Next text message, skipping state broadcasts.
+pub async fn next_text(ws: &mut Client) -> Option<String> {
+    loop {
+        match next_message(ws).await? {
+            Message::Text(text) => return Some(text.to_string()),
+            _ => continue,
+        }
+    }
+}
+
+pub async fn send_text(ws: &mut Client, text: &str) {
+    ws.send(Message::Text(text.into())).await.unwrap();
+}
+
+/// Next decoded binary server message, batches unwrapped.
+pub async fn next_server_message(ws: &mut Client) -> ServerMessage {
+    if let Some(message) = take_unbatched(ws) {
+        return message;
+    }
+    loop {
+        match next_message(ws).await {
+            Some(Message::Binary(data)) => return unbatch(ws, decode(&data).unwrap()),
+            Some(_) => continue,
+            None => panic!("connection closed while waiting for a server message"),
+        }
+    }
+}
+
+/// Next decoded state broadcast.
+pub async fn next_state(ws: &mut Client) -> GameState {
+    loop {
+        match next_message(ws).await {
+            Some(Message::Binary(data)) => match decode(&data).unwrap() {
+                ServerMessage::State { state, .. } => return state,
+                _ => continue,
+            },
+            Some(_) => continue,
+            None => panic!("connection closed while waiting for state"),
+        }
+    }
+}
+
+/// The server names players after the client's local port.
+pub fn player_name(ws: &Client) -> String {
+    match ws.get_ref() {
+        MaybeTlsStream::Plain(tcp) => format!("Player_{}", tcp.local_addr().unwrap().port()),
+        _ => unreachable!(),
+    }
+}
+
+/// Waits for a broadcast in which `check` holds for this client's player.
+pub async fn wait_for_self(ws: &mut Client, check: impl Fn(&Player) -> bool) -> Player {
+    let name = player_name(ws);
+    for _ in 0..50 {
+        let state = next_state(ws).await;
+        if let Some(me) = state.players.iter().find(|p| *p.name == *name)
+            && check(me)
+        {
+            return me.clone();
+        }
+    }
+    panic!("condition never held for {}", name);
+}
+
+/// Like `spawn_server` but terminates TLS first, as the binary does with `--tls-cert`.
+#[cfg(feature = "tls")]
+pub async fn spawn_tls_server(game_server: GameServer, acceptor: galavox::tls::TlsAcceptor) -> SocketAddr {
+    spawn_server(game_server.with_tls(acceptor)).await
+}
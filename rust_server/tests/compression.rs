@@ -0,0 +1,27 @@
+mod common;
+
+use common::{connect, next_text, send_text, spawn_server};
+use galavox::server::GameServer;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+#[tokio::test]
+async fn deflate_offer_is_declined_and_clients_coexist() {
+    let addr = spawn_server(GameServer::new()).await;
+
+    let mut request = format!("ws://{}", addr).into_client_request().unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Extensions",
+        "permessage-deflate; client_max_window_bits".parse().unwrap(),
+    );
+    let (mut offering, response) = connect_async(request).await.unwrap();
+    assert!(response.headers().get("Sec-WebSocket-Extensions").is_none());
+
+    let mut plain = connect(addr).await;
+    assert!(next_text(&mut offering).await.unwrap().starts_with("Welcome"));
+
+    send_text(&mut offering, "from offering client").await;
+    send_text(&mut plain, "from plain client").await;
+    assert_eq!(next_text(&mut offering).await.as_deref(), Some("Echo: from offering client"));
+    assert_eq!(next_text(&mut plain).await.as_deref(), Some("Echo: from plain client"));
+}
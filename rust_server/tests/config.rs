@@ -0,0 +1,124 @@
+mod common;
+
+use common::{connect, next_state, spawn_server, Client};
+use futures_util::StreamExt;
+use galavox::admin;
+use galavox::config::{ConfigSource, ServerConfig};
+use galavox::server::GameServer;
+use galavox::world::WorldConfig;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn config_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("galavox-{}-{}.toml", name, std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn flags_override_the_file_which_overrides_defaults() {
+    let path = config_file("precedence", "bind = \"0.0.0.0:9000\"\nmax_players = 8\nbroadcast_interval_ms = 50\n");
+    let path_arg = path.to_str().unwrap();
+
+    let config = ServerConfig::resolve(&args(&["--config", path_arg, "--max-players", "4"])).unwrap();
+    assert_eq!(config.bind, "0.0.0.0:9000");       // file
+    assert_eq!(config.max_players, Some(4));       // flag over file
+    assert_eq!(config.broadcast_interval_ms, 50);  // file
+    assert_eq!(config.chat_messages_per_sec, ServerConfig::default().chat_messages_per_sec);  // default
+    assert_eq!(config.live().broadcast_interval, Duration::from_millis(50));
+
+    assert_eq!(ServerConfig::resolve(&args(&["--bind", "127.0.0.1:1"])).unwrap().bind, "127.0.0.1:1");
+    assert_eq!(ServerConfig::resolve(&args(&["--max-connections-per-ip", "2"])).unwrap().live().max_connections_per_ip, Some(2));
+    assert_eq!(ServerConfig::resolve(&args(&["--bandwidth-budget", "4096"])).unwrap().live().bandwidth_budget, Some(4096));
+    let hibernation = ServerConfig::resolve(&args(&["--hibernate-after-secs", "0", "--catch-up-ticks", "5"])).unwrap().hibernation();
+    assert_eq!((hibernation.after, hibernation.catch_up_ticks), (Duration::ZERO, 5));
+    let planets = ServerConfig::resolve(&args(&["--max-planets", "50", "--prune-idle-planets-after-secs", "600"])).unwrap();
+    assert_eq!((planets.planet_limits().max_planets, planets.planet_limits().max_planets_per_owner), (Some(50), None));
+    assert_eq!(planets.pruning().map(|p| p.after), Some(Duration::from_secs(600)));
+    assert_eq!(ServerConfig::default().pruning(), None);
+    assert!(ServerConfig::from_toml("bogus_key = 1").is_err());
+    assert!(ServerConfig::resolve(&args(&["--max-players", "lots"])).is_err());
+    assert!(ServerConfig::resolve(&args(&["--world", "a.json", "--import-world", "b.json"])).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn reload_applies_only_live_settings() {
+    let path = config_file("reload", "bind = \"127.0.0.1:8080\"\nworld_seed = 1\nbroadcast_interval_ms = 100\n");
+    let flags = args(&["--config", path.to_str().unwrap()]);
+    let mut source = ConfigSource::new(Some(path.clone()), flags.clone(), ServerConfig::resolve(&flags).unwrap());
+
+    std::fs::write(&path, "bind = \"0.0.0.0:9999\"\nworld_seed = 2\nbroadcast_interval_ms = 20\nmax_players = 3\n").unwrap();
+    let reload = source.reload().unwrap();
+    assert_eq!(reload.ignored, vec!["bind".to_string(), "world_seed".to_string()]);
+    assert_eq!(reload.live.broadcast_interval, Duration::from_millis(20));
+    assert_eq!(reload.live.max_players, Some(3));
+    assert_eq!(source.current.bind, "127.0.0.1:8080");
+    assert_eq!(source.current.world_seed, Some(1));
+
+    // A broken file leaves everything as it was
+    std::fs::write(&path, "broadcast_interval_ms = \"fast\"").unwrap();
+    assert!(source.reload().is_err());
+    assert_eq!(source.current.broadcast_interval_ms, 20);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn seeded_worlds_are_reproducible() {
+    let seeded = |seed| GameServer::new().with_world_config(WorldConfig { seed: Some(seed), ..WorldConfig::default() }).get_state();
+    assert_eq!(seeded(7).planets, seeded(7).planets);
+    assert_ne!(seeded(7).planets, seeded(8).planets);
+}
+
+/// State broadcasts received over `window`.
+async fn count_states(ws: &mut Client, window: Duration) -> usize {
+    let deadline = tokio::time::Instant::now() + window;
+    let mut count = 0;
+    while tokio::time::timeout_at(deadline, next_state(ws)).await.is_ok() {
+        count += 1;
+    }
+    count
+}
+
+#[tokio::test]
+async fn reloading_the_broadcast_interval_changes_the_rate() {
+    let path = config_file("live-rate", "broadcast_interval_ms = 200\n");
+    let flags = args(&["--config", path.to_str().unwrap()]);
+    let config = ServerConfig::resolve(&flags).unwrap();
+    let server = GameServer::new()
+        .with_broadcast_interval(config.live().broadcast_interval)
+        .with_config_source(ConfigSource::new(Some(path.clone()), flags, config));
+    let addr = spawn_server(server.clone()).await;
+    let mut ws = connect(addr).await;
+
+    let slow = count_states(&mut ws, Duration::from_secs(1)).await;
+    std::fs::write(&path, "broadcast_interval_ms = 20\nbind = \"0.0.0.0:1\"\n").unwrap();
+    let reply = admin::run_line(&server, "reload").unwrap();
+    assert!(reply.contains("restart to apply bind"), "{}", reply);
+    let fast = count_states(&mut ws, Duration::from_secs(1)).await;
+
+    assert!(slow <= 7, "{} states at 200ms", slow);
+    assert!(fast >= 25, "{} states at 20ms", fast);
+    assert_eq!(server.live_settings().broadcast_interval, Duration::from_millis(20));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn full_server_turns_players_away() {
+    let addr = spawn_server(GameServer::new().with_max_players(Some(1))).await;
+    let _first = connect(addr).await;
+
+    let (mut second, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), second.next()).await.unwrap() {
+            Some(Ok(Message::Close(Some(frame)))) => break assert_eq!(frame.reason, "server full"),
+            Some(Ok(Message::Binary(_))) => continue,  // the Error message
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use galavox::connection_state::{handle, ConnectionState, Incoming, Outgoing, Role};
+use galavox::locale::localized;
+use galavox::protocol::{ClientMessage, ErrorCode, MessageCode, Position, ServerMessage, WireFormat};
+use galavox::session::DisconnectReason;
+
+const PLAYER: ConnectionState = ConnectionState::AwaitingJoin { role: Role::Player };
+const SPECTATOR: ConnectionState = ConnectionState::AwaitingJoin { role: Role::Spectator { warned: false } };
+
+fn chat(text: &str) -> Incoming {
+    Incoming::Message(ClientMessage::Chat { text: text.to_string(), client_ref: None })
+}
+
+fn set_format(format: WireFormat) -> Incoming {
+    Incoming::Message(ClientMessage::SetFormat { format })
+}
+
+/// Feeds `events` in turn, returning the final state and everything done.
+fn run(state: ConnectionState, events: Vec<Incoming>) -> (ConnectionState, Vec<Outgoing>) {
+    events.into_iter().fold((state, Vec::new()), |(state, mut done), incoming| {
+        let (next, outgoing) = handle(state, incoming);
+        done.extend(outgoing);
+        (next, done)
+    })
+}
+
+#[test]
+fn only_the_first_message_may_switch_format() {
+    let (state, outgoing) = handle(PLAYER, set_format(WireFormat::Binary));
+    assert_eq!(state, ConnectionState::Active { role: Role::Player });
+    assert_eq!(outgoing, [Outgoing::SwitchFormat(WireFormat::Binary)]);
+
+    let (state, outgoing) = handle(state, set_format(WireFormat::Json));
+    assert_eq!(state, ConnectionState::Active { role: Role::Player });
+    assert_eq!(outgoing, [Outgoing::Reply(localized(MessageCode::FormatLocked {}))]);
+
+    // Any other message counts as the first one
+    let (_, outgoing) = run(PLAYER, vec![chat("hi"), set_format(WireFormat::Binary)]);
+    assert_eq!(outgoing[1], Outgoing::Reply(localized(MessageCode::FormatLocked {})));
+}
+
+#[test]
+fn invalid_json_does_not_count_as_the_first_message() {
+    let (state, outgoing) = handle(PLAYER, Incoming::InvalidJson("expected value".to_string()));
+    assert_eq!(state, PLAYER);
+    assert_eq!(outgoing, [Outgoing::Reply(localized(MessageCode::InvalidMessage { reason: "expected value".to_string() }))]);
+    assert_eq!(handle(state, set_format(WireFormat::Binary)).1, [Outgoing::SwitchFormat(WireFormat::Binary)]);
+
+    // Spectators are not told
+    assert_eq!(handle(SPECTATOR, Incoming::InvalidJson("expected value".to_string())), (SPECTATOR, Vec::new()));
+}
+
+#[test]
+fn players_may_send_everything_but_a_malformed_name() {
+    let position = ClientMessage::Position { seq: Some(1), position: Position { x: 1.0, y: 2.0, z: 3.0 }, velocity: [0.0; 3], rotation: [0.0, 0.0, 0.0, 1.0] };
+    assert_eq!(handle(PLAYER, Incoming::Message(position.clone())).1, [Outgoing::Dispatch(position)]);
+    let rename = ClientMessage::Join { name: "Grace".to_string() };
+    assert_eq!(handle(PLAYER, Incoming::Message(rename.clone())).1, [Outgoing::Dispatch(rename)]);
+
+    // Refused without closing: renaming cannot have been meant to end the session
+    let (state, outgoing) = handle(PLAYER, Incoming::Message(ClientMessage::Join { name: "no\nnewlines".to_string() }));
+    assert_eq!(state, ConnectionState::Active { role: Role::Player });
+    assert!(matches!(&outgoing[..], [Outgoing::Reply(ServerMessage::Error { code, .. })] if *code == ErrorCode::ProtocolViolation.code()));
+}
+
+#[test]
+fn spectators_may_only_ask_and_are_told_once() {
+    let time_sync = ClientMessage::TimeSync { client_time_ms: 5 };
+    assert_eq!(handle(SPECTATOR, Incoming::Message(time_sync.clone())).1, [Outgoing::Dispatch(time_sync)]);
+    let (state, outgoing) = handle(SPECTATOR, set_format(WireFormat::Binary));
+    assert_eq!(outgoing, [Outgoing::SwitchFormat(WireFormat::Binary)]);
+
+    let (state, outgoing) = run(state, vec![chat("hi"), Incoming::Message(ClientMessage::Respawn {}), set_format(WireFormat::Json)]);
+    assert_eq!(state, ConnectionState::Active { role: Role::Spectator { warned: true } });
+    assert_eq!(outgoing, [Outgoing::Reply(localized(MessageCode::SpectatorReadOnly {}))]);
+}
+
+#[test]
+fn the_server_closes_with_a_reason() {
+    let cases = [
+        (Incoming::FrameTooLarge, ErrorCode::ProtocolViolation, DisconnectReason::Error),
+        (Incoming::RateLimited, ErrorCode::RateLimited, DisconnectReason::RateLimited),
+        (Incoming::IdleTimeout, ErrorCode::IdleTimeout, DisconnectReason::Timeout),
+        (Incoming::KeepaliveTimeout, ErrorCode::KeepaliveTimeout, DisconnectReason::Timeout),
+        (Incoming::Kicked, ErrorCode::Kicked, DisconnectReason::Kicked),
+        (Incoming::ShuttingDown { reconnect_to: None }, ErrorCode::ShuttingDown, DisconnectReason::ShuttingDown),
+    ];
+    for (incoming, code, reason) in cases {
+        for state in [PLAYER, ConnectionState::Active { role: Role::Spectator { warned: true } }] {
+            let (state, outgoing) = handle(state, incoming.clone());
+            assert_eq!(state, ConnectionState::Draining { reason });
+            assert!(state.is_finished());
+            assert_eq!(state.disconnect_reason(), Some(reason));
+            assert!(matches!(&outgoing[..], [Outgoing::Close { code: c, .. }] if *c == code), "{:?}", incoming);
+        }
+    }
+
+    // Told where to go first, if a follower took over
+    let (_, outgoing) = handle(PLAYER, Incoming::ShuttingDown { reconnect_to: Some("ws://standby:8080".to_string()) });
+    assert_eq!(outgoing[0], Outgoing::Reply(ServerMessage::Reconnect { to: "ws://standby:8080".to_string() }));
+    assert!(matches!(outgoing[1], Outgoing::Close { code: ErrorCode::ShuttingDown, .. }));
+}
+
+#[test]
+fn the_client_going_away_closes_without_a_word() {
+    let cases = [
+        (Incoming::ClientClosed, DisconnectReason::Closed),
+        (Incoming::ConnectionLost, DisconnectReason::Error),
+        (Incoming::WriterGone, DisconnectReason::Timeout),
+    ];
+    for (incoming, reason) in cases {
+        assert_eq!(handle(PLAYER, incoming), (ConnectionState::Closed { reason }, Vec::new()));
+    }
+    assert_eq!(PLAYER.disconnect_reason(), None);
+    assert!(!ConnectionState::Active { role: Role::Player }.is_finished());
+}
+
+#[test]
+fn nothing_happens_once_it_is_over() {
+    for state in [ConnectionState::Draining { reason: DisconnectReason::Kicked }, ConnectionState::Closed { reason: DisconnectReason::Closed }] {
+        let events = vec![
+            chat("hi"),
+            set_format(WireFormat::Binary),
+            Incoming::InvalidJson("oops".to_string()),
+            Incoming::RateLimited,
+            Incoming::ShuttingDown { reconnect_to: Some("ws://standby:8080".to_string()) },
+            Incoming::ClientClosed,
+        ];
+        assert_eq!(run(state, events), (state, Vec::new()));
+    }
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{next_json, spawn_server, Client};
+use futures_util::StreamExt;
+use galavox::protocol::{ErrorCode, ServerMessage};
+use galavox::server::GameServer;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// Connects in JSON mode and returns the player id from the join ack.
+async fn join(addr: SocketAddr) -> (Client, u32) {
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?format=json", addr)).await.unwrap();
+    let mut player_id = None;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::Joined { player_id: id, .. } => player_id = Some(id),
+            ServerMessage::Notice { text } if text.starts_with("Welcome") => return (ws, player_id.unwrap()),
+            _ => continue,
+        }
+    }
+}
+
+fn player_ids(server: &GameServer) -> Vec<u32> {
+    server.player_list(Instant::now()).iter().map(|listing| listing.player.id).collect()
+}
+
+async fn wait_until_gone(server: &GameServer, player_id: u32) {
+    for _ in 0..100 {
+        if !player_ids(server).contains(&player_id) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("player {} never left", player_id);
+}
+
+#[tokio::test]
+async fn players_from_one_address_coexist_and_leave_independently() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let (first, first_id) = join(addr).await;
+    let (mut second, second_id) = join(addr).await;
+
+    assert_ne!(first_id, second_id);
+    let listing = server.player_list(Instant::now());
+    assert_eq!(listing.len(), 2);
+    assert_eq!(listing[0].addr.ip(), listing[1].addr.ip());
+    assert_ne!(listing[0].connection, listing[1].connection);
+
+    drop(first);
+    wait_until_gone(&server, first_id).await;
+    assert_eq!(player_ids(&server), vec![second_id]);
+    // The remaining player is unaffected and keeps getting broadcasts
+    loop {
+        if let ServerMessage::State { state, .. } = next_json(&mut second).await {
+            assert_eq!(state.players.iter().map(|p| p.id).collect::<Vec<_>>(), vec![second_id]);
+            break;
+        }
+    }
+
+    // Ids are never handed out twice, even after a player leaves
+    let (_third, third_id) = join(addr).await;
+    assert!(third_id != first_id && third_id != second_id);
+}
+
+#[tokio::test]
+async fn connections_per_address_can_be_limited() {
+    let server = GameServer::new().with_max_connections_per_ip(Some(2));
+    let addr = spawn_server(server.clone()).await;
+    let (first, first_id) = join(addr).await;
+    let (_second, _) = join(addr).await;
+
+    let (mut third, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+    loop {
+        match tokio::time::timeout(Duration::from_secs(5), third.next()).await.unwrap() {
+            Some(Ok(Message::Close(Some(frame)))) => {
+                assert_eq!(u16::from(frame.code), ErrorCode::TooManyConnections.code());
+                break assert_eq!(frame.reason, "too many connections from 127.0.0.1 (limit 2)");
+            }
+            Some(Ok(Message::Binary(_))) => continue,  // the Error message
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    }
+    assert_eq!(player_ids(&server).len(), 2);
+
+    drop(first);
+    wait_until_gone(&server, first_id).await;
+    let (_fourth, _) = join(addr).await;
+    assert_eq!(player_ids(&server).len(), 2);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::planet;
+use galavox::discovery::{in_discovery_range, level_for, Discoveries, DISCOVERY_XP, XP_PER_LEVEL};
+use galavox::protocol::{GameState, Planet, Position, ServerMessage};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+
+fn at(x: f32) -> Position {
+    Position { x, y: 0.0, z: 0.0 }
+}
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+#[test]
+fn discovery_range_includes_its_boundary() {
+    let p = Planet { size: 10.0, ..planet(1, 100.0) };
+    assert!(in_discovery_range(&p, &at(115.0)));
+    assert!(in_discovery_range(&p, &at(85.0)));
+    assert!(!in_discovery_range(&p, &at(115.5)));
+    assert!(!in_discovery_range(&p, &at(0.0)));
+}
+
+#[test]
+fn repeat_visits_earn_nothing() {
+    let planets = vec![
+        Planet { size: 10.0, ..planet(1, 100.0) },
+        Planet { size: 10.0, ..planet(2, 110.0) },
+        Planet { size: 10.0, ..planet(3, 1000.0) },
+    ];
+    let mut discoveries = Discoveries::default();
+    assert_eq!(discoveries.visit(&at(105.0), &planets), vec![1, 2]);
+    assert_eq!(discoveries.xp, 2 * DISCOVERY_XP);
+    assert_eq!(discoveries.visit(&at(100.0), &planets), Vec::<u32>::new());
+    assert_eq!(discoveries.xp, 2 * DISCOVERY_XP);
+    assert!(discoveries.contains(2) && !discoveries.contains(3));
+    assert!((discoveries.explored_percent(&planets) - 200.0 / 3.0).abs() < 1e-4);
+    assert_eq!(level_for(XP_PER_LEVEL - 1), 1);
+    assert_eq!(level_for(XP_PER_LEVEL), 2);
+}
+
+#[tokio::test]
+async fn discoveries_survive_a_reconnect() {
+    let state = GameState::new(vec![Planet { size: 10.0, ..planet(1, 500.0) }, Planet { size: 10.0, ..planet(2, -500.0) }], vec![], at(0.0));
+    let server = GameServer::new().with_world(state);
+
+    let (first, player, _) = server.add_player(connection(), "Explorer".to_string()).unwrap();
+    assert_eq!(player.xp, 0);
+    server.move_player(first, at(510.0));
+    server.move_player(first, at(505.0));
+    let Some(ServerMessage::DiscoveryList { planet_ids, explored_percent }) = server.discovery_list(first) else { panic!() };
+    assert_eq!(planet_ids, vec![1]);
+    assert_eq!(explored_percent, 50.0);
+    server.remove_player(first);
+
+    let (second, player, _) = server.add_player(connection(), "Explorer".to_string()).unwrap();
+    assert_eq!(player.xp, DISCOVERY_XP);
+    let Some(ServerMessage::DiscoveryList { planet_ids, .. }) = server.discovery_list(second) else { panic!() };
+    assert_eq!(planet_ids, vec![1]);
+
+    // A different name starts from scratch
+    let (stranger, player, _) = server.add_player(connection(), "Stranger".to_string()).unwrap();
+    assert_eq!(player.xp, 0);
+    let Some(ServerMessage::DiscoveryList { planet_ids, .. }) = server.discovery_list(stranger) else { panic!() };
+    assert!(planet_ids.is_empty());
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::{connect_json, next_json, planet, send_text, spawn_server};
+use galavox::admin::{parse_request, plan, run_line, AdminCommand, AdminPlan, AffectedPlayer, FileWrite};
+use galavox::bans::BanList;
+use galavox::protocol::{ClientMessage, GameState, Planet, Position, ServerMessage};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn at(x: f32, y: f32) -> Position {
+    Position { x, y, z: 0.0 }
+}
+
+fn server() -> GameServer {
+    GameServer::new().with_world(GameState::new(vec![
+        Planet { resources: 1_000, capacity: 1_000, name: "Rock1".to_string(), ..planet(1, 0.0) },
+        Planet { resources: 1_000, capacity: 1_000, name: "Rock2".to_string(), ..planet(2, 3000.0) },
+    ], vec![], at(0.0, 5000.0)))
+}
+
+fn affected(players: &[(u32, &str)]) -> Vec<AffectedPlayer> {
+    players.iter().map(|(id, name)| AffectedPlayer { id: *id, name: name.to_string() }).collect()
+}
+
+#[test]
+fn the_suffix_marks_a_dry_run() {
+    assert_eq!(parse_request("kick 3 --dry-run"), Ok((AdminCommand::Kick { player_id: 3 }, true)));
+    assert_eq!(parse_request("kick 3"), Ok((AdminCommand::Kick { player_id: 3 }, false)));
+    assert!(parse_request("kick 3--dry-run").is_err());
+    assert_eq!(
+        run_line(&server(), "planet list --dry-run"),
+        Err("only planet remove, kick, ban, revoke, regenerate-world and fakes remove have a dry run".to_string())
+    );
+}
+
+#[test]
+fn removing_a_planet_lifts_off_who_the_plan_named() {
+    let server = server();
+    let (ada, player, _) = server.add_player(connection(), "Ada".to_string()).unwrap();
+    server.move_player(ada, at(0.0, 55.0));
+    server.land(ada, 1).unwrap();
+    let before = server.get_state();
+
+    let planned = plan(&server, AdminCommand::RemovePlanet { id: 1 }).unwrap();
+    assert_eq!(planned, AdminPlan {
+        command: "planet remove 1".to_string(),
+        players_affected: affected(&[(player.id, "Ada")]),
+        planets_removed: vec![1],
+        ..AdminPlan::default()
+    });
+    assert_eq!(server.get_state(), before);
+    assert_eq!(
+        run_line(&server, "planet remove 1 --dry-run"),
+        Ok(format!("dry run of `planet remove 1`: players affected: #{} Ada; planets removed: 1; planets added: 0; files written: none", player.id))
+    );
+
+    assert_eq!(server.plan_remove_planet(1, false), Ok(planned));
+    let after = server.get_state();
+    assert_eq!(after.planets.iter().map(|p| p.id).collect::<Vec<_>>(), [2]);
+    assert_eq!(after.players[0].landed_on, None);
+}
+
+#[test]
+fn a_ban_plans_the_file_it_writes() {
+    let path = std::env::temp_dir().join(format!("galavox-dry-run-{}.bans", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = server().with_bans(BanList::load(&path).unwrap());
+
+    let planned = plan(&server, AdminCommand::Ban { name: "Mallory".to_string() }).unwrap();
+    assert_eq!(planned.files_written, [FileWrite { path: path.display().to_string(), bytes: "Mallory\n".len() as u64 }]);
+    assert!(!path.exists());
+    assert!(server.banned_names().is_empty());
+
+    run_line(&server, "ban Mallory").unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(planned.files_written[0].bytes, written.len() as u64);
+    assert_eq!(server.banned_names(), ["Mallory"]);
+    assert_eq!(run_line(&server, "ban mallory --dry-run"), Err("mallory is already banned".to_string()));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn regenerating_plans_the_world_it_makes() {
+    let server = server().with_fake_players(2);
+    let before = server.get_state();
+
+    let planned = plan(&server, AdminCommand::RegenerateWorld { seed: Some(7) }).unwrap();
+    assert_eq!(planned.command, "regenerate-world 7");
+    assert_eq!(planned.planets_removed, [1, 2]);
+    assert_eq!(planned.players_affected.len(), 2);
+    assert_eq!(server.get_state(), before);
+
+    let reset = server.regenerate_world(Some(7));
+    assert_eq!((reset.planets, reset.players), (planned.planets_added, planned.players_affected.len()));
+    assert_eq!(server.get_state().planets.len(), planned.planets_added);
+
+    // Without a seed the plan picks one, for the real run to use
+    let planned = plan(&server, AdminCommand::RegenerateWorld { seed: None }).unwrap();
+    let (AdminCommand::RegenerateWorld { seed: Some(seed) }, false) = parse_request(&planned.command).unwrap() else {
+        panic!("no seed in {}", planned.command);
+    };
+    assert_eq!(server.regenerate_world(Some(seed)).planets, planned.planets_added);
+}
+
+#[test]
+fn removing_fakes_takes_the_newest_the_plan_named() {
+    let server = server().with_fake_players(3);
+    let before = server.get_state();
+
+    let planned = plan(&server, AdminCommand::RemoveFakes { count: 2 }).unwrap();
+    assert_eq!(server.get_state(), before);
+    let removed = server.remove_fake_players(2);
+    let removed: Vec<AffectedPlayer> = removed.iter().map(|p| AffectedPlayer { id: p.id, name: p.name.to_string() }).collect();
+    assert_eq!(planned.players_affected, removed);
+    assert_eq!(server.get_state().players.len(), 1);
+}
+
+#[test]
+fn kicking_plans_the_player_and_leaves_them_be() {
+    let server = server();
+    let kicked = connection();
+    let notify = kicked.kick.clone();
+    let (_, player, _) = server.add_player(kicked, "Bob".to_string()).unwrap();
+
+    let planned = plan(&server, AdminCommand::Kick { player_id: player.id }).unwrap();
+    assert_eq!(planned.players_affected, affected(&[(player.id, "Bob")]));
+    assert!(!notified_now(notify.notified()));
+    assert!(plan(&server, AdminCommand::Kick { player_id: player.id + 1 }).is_err());
+
+    assert_eq!(server.plan_kick_player(player.id, false), Ok(planned));
+    assert!(notified_now(notify.notified()));
+}
+
+/// Whether `future` is ready the first time it is polled.
+fn notified_now(future: impl std::future::Future<Output = ()>) -> bool {
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    std::pin::pin!(future).poll(&mut context).is_ready()
+}
+
+#[tokio::test]
+async fn remote_dry_runs_answer_with_the_plan() {
+    let server = server().with_admin_token("s3cret".to_string());
+    let addr = spawn_server(server).await;
+    let mut admin = connect_json(addr).await;
+    let request = |command: &str, dry_run: bool| serde_json::to_string(&ClientMessage::Admin {
+        token: "s3cret".to_string(),
+        command: command.to_string(),
+        dry_run,
+    }).unwrap();
+
+    send_text(&mut admin, &request("planet remove 2", true)).await;
+    let plan = loop {
+        if let ServerMessage::AdminPlanned { plan } = next_json(&mut admin).await {
+            break plan;
+        }
+    };
+    assert_eq!(plan.planets_removed, [2]);
+
+    // The suffix alone is a dry run too, and nothing was removed by either
+    send_text(&mut admin, &request("planet remove 2 --dry-run", false)).await;
+    loop {
+        if let ServerMessage::AdminPlanned { plan: again } = next_json(&mut admin).await {
+            assert_eq!(again, plan);
+            break;
+        }
+    }
+    send_text(&mut admin, &request("planet remove 2", false)).await;
+    loop {
+        if let ServerMessage::AdminResult { ok, message } = next_json(&mut admin).await {
+            assert!(ok, "{}", message);
+            assert_eq!(message, "removed planet 2");
+            break;
+        }
+    }
+}
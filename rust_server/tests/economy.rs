@@ -0,0 +1,123 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::planet;
+use galavox::admin::run_line;
+use galavox::config::ServerConfig;
+use galavox::economy::{capacity_for, Economy, RegenerationRates, CAPACITY_PER_SIZE};
+use galavox::module_effects::Wallet;
+use galavox::protocol::{GameState, Planet, Position};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use galavox::wallets::Wallets;
+use tokio::sync::Notify;
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn minutes(m: f64) -> Duration {
+    Duration::from_secs_f64(m * 60.0)
+}
+
+#[test]
+fn planets_grow_back_at_their_module_rate() {
+    let start = Instant::now();
+    let rates = RegenerationRates::default();
+    let mut economy = Economy::new(start);
+    // Module 0 grows 6 units a minute, module 2 three
+    let planets = vec![
+        Planet { resources: 10, capacity: 100, ..planet(1, 1000.0) },
+        Planet { module_type: 2, resources: 10, capacity: 100, ..planet(2, 2000.0) },
+        Planet { resources: 100, capacity: 100, ..planet(3, 3000.0) },
+    ];
+
+    assert_eq!(economy.tick(&planets, &rates, start + minutes(1.0)), vec![(1, 16), (2, 13)]);
+    // Half a minute is one and a half units for planet 2: one now, the half
+    // carried over to the next tick
+    let planets = vec![
+        Planet { resources: 16, capacity: 100, ..planet(1, 1000.0) },
+        Planet { module_type: 2, resources: 13, capacity: 100, ..planet(2, 2000.0) },
+    ];
+    assert_eq!(economy.tick(&planets, &rates, start + minutes(1.5)), vec![(1, 19), (2, 14)]);
+    let planets = vec![
+        Planet { resources: 19, capacity: 100, ..planet(1, 1000.0) },
+        Planet { module_type: 2, resources: 14, capacity: 100, ..planet(2, 2000.0) },
+    ];
+    assert_eq!(economy.tick(&planets, &rates, start + minutes(2.0)), vec![(1, 22), (2, 16)]);
+    // Nothing is due between ticks that close together
+    assert_eq!(economy.tick(&planets, &rates, start + minutes(2.0)), vec![]);
+}
+
+#[test]
+fn growth_stops_at_capacity() {
+    let start = Instant::now();
+    let mut economy = Economy::new(start);
+    let planets = vec![
+        Planet { module_type: 1, resources: 95, capacity: 100, ..planet(1, 1000.0) },
+        Planet { module_type: 1, resources: 100, capacity: 100, ..planet(2, 2000.0) },
+    ];
+    assert_eq!(economy.tick(&planets, &RegenerationRates::default(), start + minutes(10.0)), vec![(1, 100)]);
+    assert_eq!(capacity_for(40.0), (40.0 * CAPACITY_PER_SIZE) as u32);
+}
+
+#[test]
+fn multipliers_scale_each_module_type() {
+    let table = HashMap::from([("0".to_string(), 2.0), ("2".to_string(), 0.0)]);
+    let rates = RegenerationRates::resolve(&table).unwrap();
+    assert_eq!(rates.per_minute(&planet(1, 1000.0)), 12.0);
+    assert_eq!(rates.per_minute(&Planet { module_type: 1, ..planet(1, 1000.0) }), 12.0);
+    assert_eq!(rates.per_minute(&Planet { module_type: 2, ..planet(1, 1000.0) }), 0.0);
+    assert!(RegenerationRates::resolve(&HashMap::from([("9".to_string(), 1.0)])).is_err());
+    assert!(RegenerationRates::resolve(&HashMap::from([("1".to_string(), -1.0)])).is_err());
+
+    let config = ServerConfig::from_toml("[regeneration]\n0 = 2.0\n2 = 0.0\n").unwrap();
+    assert_eq!(config.world_config().regeneration, rates);
+}
+
+#[test]
+fn the_server_updates_and_reports_resources() {
+    let state = GameState::new(vec![
+        Planet { resources: 40, capacity: 100, ..planet(1, 1000.0) },
+        Planet { module_type: 2, resources: 100, capacity: 100, ..planet(2, 2000.0) },
+    ], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+    // Ada is away with cargo; Bob mines some while here
+    let mut wallets = Wallets::new();
+    wallets.set([("Ada", Wallet { cargo: 25, credits: 7 })]).unwrap();
+    let server = GameServer::new().with_wallets(wallets).with_world(state);
+    let (bob, _, _) = server.add_player(connection(), "Bob".to_string()).unwrap();
+    server.move_player(bob, Position { x: 2000.0, y: 60.0, z: 0.0 });
+    server.land(bob, 2).unwrap();
+    server.mine(bob, 10).unwrap();
+
+    assert!(run_line(&server, "planet set 1 resources 101").is_err());
+    run_line(&server, "planet set 2 resources 0").unwrap();
+    assert_eq!(run_line(&server, "economy stats").unwrap(),
+               "planets hold 40 of 200 units, notable asteroids 0, players 35; 2 planet(s) growing back 9.0 units/min");
+    assert_eq!(server.economy_stats().held_by_players, 35);
+
+    // Two hours is plenty to fill both
+    assert_eq!(server.run_economy(Instant::now() + minutes(120.0)), 2);
+    let resources: Vec<u32> = server.get_state().planets.iter().map(|p| p.resources).collect();
+    assert_eq!(resources, [100, 100]);
+    assert_eq!(server.run_economy(Instant::now() + minutes(240.0)), 0);
+    assert!(run_line(&server, "economy stats").unwrap().ends_with("0 planet(s) growing back 0.0 units/min"));
+}
+
+#[test]
+fn new_planets_start_full() {
+    let server = GameServer::new();
+    assert!(server.get_state().planets.iter().all(|p| p.capacity == capacity_for(p.size) && p.resources == p.capacity));
+    let server = server.with_world(GameState::new(vec![], vec![], Position { x: 0.0, y: 0.0, z: 0.0 }));
+    let added = run_line(&server, "planet add size=50 x=0 y=0 z=0").unwrap();
+    let id: u32 = added.trim_start_matches("added planet ").parse().unwrap();
+    let planet = server.get_state().planets.into_iter().find(|p| p.id == id).unwrap();
+    assert_eq!((planet.resources, planet.capacity), (500, 500));
+}
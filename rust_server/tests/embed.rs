@@ -0,0 +1,73 @@
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::ErrorCode;
+use galavox::server::GameServer;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+async fn close_code(conn: &mut Connection) -> Option<u16> {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let ClientEvent::Disconnected { code, .. } = conn.next_event().await {
+                return code;
+            }
+        }
+    })
+    .await
+    .expect("the connection stayed open")
+}
+
+async fn joined(url: &str, name: &str) -> Connection {
+    let mut conn = Connection::connect(url, Some(name)).await.unwrap();
+    while !matches!(conn.next_event().await, ClientEvent::Joined { .. }) {}
+    conn
+}
+
+#[tokio::test]
+async fn a_spawned_server_serves_until_shut_down() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let handle = GameServer::new().spawn(listener).unwrap();
+    let url = format!("ws://{}", handle.local_addr());
+    assert_eq!(handle.player_count(), 0);
+
+    let mut ada = joined(&url, "Ada").await;
+    let mut spectator = Connection::spectate(&url, false).await.unwrap();
+    let mut bob = joined(&url, "Bob").await;
+    assert_eq!(handle.player_count(), 2);
+    assert_eq!(handle.server().player_list(std::time::Instant::now()).len(), 2);
+
+    tokio::time::timeout(Duration::from_secs(5), handle.shutdown()).await.expect("shutdown hung").unwrap();
+    let shutting_down = Some(ErrorCode::ShuttingDown.code());
+    assert_eq!(close_code(&mut ada).await, shutting_down);
+    assert_eq!(close_code(&mut bob).await, shutting_down);
+    assert_eq!(close_code(&mut spectator).await, shutting_down);
+    assert!(ErrorCode::ShuttingDown.may_reconnect());
+    assert!(Connection::connect(&url, None).await.is_err());
+}
+
+#[tokio::test]
+async fn run_stops_when_its_shutdown_future_completes() {
+    let server = GameServer::new();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (stop, stopped) = oneshot::channel::<()>();
+    let running = tokio::spawn(server.clone().run(listener, async {
+        let _ = stopped.await;
+    }));
+
+    let mut ada = joined(&url, "Ada").await;
+    assert_eq!(server.player_count(), 1);
+    stop.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), running).await.expect("run did not return").unwrap().unwrap();
+    assert_eq!(close_code(&mut ada).await, Some(ErrorCode::ShuttingDown.code()));
+    // The connection tasks have finished, so their players are gone
+    assert_eq!(server.player_count(), 0);
+}
+
+#[tokio::test]
+async fn dropping_the_handle_leaves_the_server_running() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = GameServer::new().spawn(listener).unwrap().local_addr();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    joined(&format!("ws://{}", addr), "Ada").await;
+}
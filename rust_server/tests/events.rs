@@ -0,0 +1,105 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use common::planet;
+use galavox::admin::PlanetEdit;
+use galavox::events::{EventSink, EventTime, GameEvent, ServerCommand, EVENT_CAPACITY};
+use galavox::protocol::{GameState, Position};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::sync::Notify;
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn at(x: f32, y: f32) -> Position {
+    Position { x, y, z: 0.0 }
+}
+
+fn server() -> GameServer {
+    GameServer::new().with_world(GameState::new(vec![planet(1, 0.0)], vec![], at(0.0, 5000.0)))
+}
+
+/// Everything received so far, without waiting.
+fn drain(events: &mut tokio::sync::broadcast::Receiver<GameEvent>) -> Vec<GameEvent> {
+    std::iter::from_fn(|| events.try_recv().ok()).collect()
+}
+
+/// Counts what it is handed.
+#[derive(Default)]
+struct Counter {
+    events: Mutex<Vec<(u64, GameEvent)>>,
+}
+
+impl EventSink for Counter {
+    fn consume(&self, at: EventTime, event: &GameEvent) {
+        self.events.lock().unwrap().push((at.tick, event.clone()));
+    }
+}
+
+#[test]
+fn a_scripted_session_comes_out_in_order() {
+    let server = server();
+    let mut events = server.subscribe_events();
+
+    let (ada, player, _) = server.add_player(connection(), "Ada".to_string()).unwrap();
+    let (bob, _, _) = server.add_player(connection(), "Bob".to_string()).unwrap();
+    server.move_player(ada, at(4000.0, 4000.0));
+    server.record_chat(ada, Some("lobby"), "hello");
+    server.update_planet(1, &PlanetEdit::Owner(Some(player.id))).unwrap();
+    server.inject(ServerCommand::GrantXp { player_id: player.id, xp: 600 }).unwrap();
+    server.remove_player(bob);
+
+    let names: Vec<String> = drain(&mut events).iter().map(|event| match event {
+        GameEvent::PlayerJoined { player } => format!("joined {}", player.name),
+        GameEvent::PlayerMoved { position, .. } => format!("moved to {}", position.x),
+        GameEvent::Chatted { name, room, text, .. } => format!("{} in {:?}: {}", name, room, text),
+        GameEvent::PlanetUpdated { planet } => format!("planet {} owned by {:?}", planet.id, planet.owner),
+        GameEvent::PlanetClaimed { planet_id, owner } => format!("planet {} claimed by {}", planet_id, owner),
+        GameEvent::XpEarned { xp, .. } => format!("{} xp", xp),
+        GameEvent::LevelUp { name, level, .. } => format!("{} is level {}", name, level),
+        GameEvent::PlayerLeft { name, .. } => format!("{} left", name),
+        other => format!("{:?}", other),
+    }).collect();
+    assert_eq!(names, [
+        "joined Ada", "joined Bob", "moved to 4000", "Ada in Some(\"lobby\"): hello",
+        "planet 1 owned by Some(0)", "planet 1 claimed by 0", "600 xp", "Ada is level 2", "Bob left",
+    ]);
+}
+
+#[test]
+fn injected_commands_act_like_an_admin() {
+    let server = server();
+    let mut events = server.subscribe_events();
+    assert!(server.inject(ServerCommand::GrantXp { player_id: 7, xp: 10 }).is_err());
+
+    server.inject(ServerCommand::AddPlanet { planet: planet(0, 3000.0) }).unwrap();
+    let added = drain(&mut events);
+    assert!(matches!(added.as_slice(), [GameEvent::PlanetAdded { planet }] if planet.id == 2 && planet.position.x == 3000.0), "{:?}", added);
+    assert!(server.get_state().planets.iter().any(|planet| planet.id == 2));
+    assert_eq!(server.inject(ServerCommand::Announce { text: "{player_count} online".to_string() }), Ok("0 online".to_string()));
+}
+
+#[test]
+fn sinks_see_everything_while_slow_subscribers_skip_ahead() {
+    let counter = Arc::new(Counter::default());
+    let server = server().with_event_sink(counter.clone());
+    let mut idle = server.subscribe_events();
+    let (ada, _, _) = server.add_player(connection(), "Ada".to_string()).unwrap();
+    for step in 0..EVENT_CAPACITY {
+        server.move_player(ada, at(4000.0, step as f32));
+    }
+
+    assert_eq!(counter.events.lock().unwrap().len(), EVENT_CAPACITY + 1);
+    assert_eq!(idle.try_recv(), Err(TryRecvError::Lagged(1)));
+    assert!(matches!(idle.try_recv(), Ok(GameEvent::PlayerMoved { position, .. }) if position.y == 0.0));
+    assert_eq!(drain(&mut idle).len(), EVENT_CAPACITY - 1);
+}
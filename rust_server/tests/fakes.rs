@@ -0,0 +1,97 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{connect, next_state, spawn_server};
+use galavox::admin::run_line;
+use galavox::client::{self, ClientEvent};
+use galavox::fakes::{wander, NAME_PREFIX};
+use galavox::protocol::{Player, Position};
+use galavox::server::{Connection, GameServer, JoinRejection};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+
+fn connection(port: u16) -> Connection {
+    Connection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn fakes_in(players: &[Player]) -> Vec<Player> {
+    players.iter().filter(|p| p.name.starts_with(NAME_PREFIX)).cloned().collect()
+}
+
+#[test]
+fn fakes_do_not_take_player_slots() {
+    let server = GameServer::new().with_max_players(Some(1)).with_fake_players(3);
+    let fakes = fakes_in(&server.get_state().players);
+    assert_eq!(fakes.len(), 3);
+    assert!(fakes.iter().all(|p| *p.name == format!("NPC_{}", p.id)));
+    assert_eq!(server.fake_player_count(), 3);
+
+    let (joined, _, _) = server.add_player(connection(5000), "Ada".to_string()).unwrap();
+    assert!(!server.is_fake(joined));
+    assert_eq!(server.add_player(connection(5001), "Bob".to_string()).unwrap_err(), JoinRejection::ServerFull);
+    // Not connections, so not listed with them
+    assert_eq!(server.player_list(Instant::now()).len(), 1);
+}
+
+#[test]
+fn wandering_is_deterministic_and_stays_near_home() {
+    let home = Position { x: 1000.0, y: 0.0, z: -500.0 };
+    let tick = Duration::from_millis(100);
+    assert_eq!(wander(&home, 3, 42, tick), wander(&home, 3, 42, tick));
+    assert_ne!(wander(&home, 3, 42, tick).0, wander(&home, 3, 43, tick).0);
+    assert_ne!(wander(&home, 3, 42, tick).0, wander(&home, 4, 42, tick).0);
+    for t in 0..500 {
+        let (position, _, _) = wander(&home, 7, t, tick);
+        let (dx, dy, dz) = (position.x - home.x, position.y - home.y, position.z - home.z);
+        assert!((dx * dx + dy * dy + dz * dz).sqrt() <= 250.0);
+    }
+}
+
+#[tokio::test]
+async fn fake_movement_appears_in_broadcasts() {
+    let addr = spawn_server(GameServer::new().with_fake_players(2)).await;
+    let mut ws = connect(addr).await;
+    let first = fakes_in(&next_state(&mut ws).await.players);
+    let second = fakes_in(&next_state(&mut ws).await.players);
+    assert_eq!(first.len(), 2);
+    assert_eq!(first.iter().map(|p| p.id).collect::<Vec<_>>(), second.iter().map(|p| p.id).collect::<Vec<_>>());
+    for (before, after) in first.iter().zip(&second) {
+        assert_ne!(before.position, after.position);
+        assert_ne!(after.velocity, [0.0; 3]);
+    }
+}
+
+#[tokio::test]
+async fn removing_fakes_emits_leave_events() {
+    let server = GameServer::new().with_fake_players(3);
+    let addr = spawn_server(server.clone()).await;
+    let mut conn = client::Connection::connect(&format!("ws://{}", addr), Some("Watcher")).await.unwrap();
+    let fakes = loop {
+        if let ClientEvent::StateSnapshot { state, .. } = conn.next_event().await {
+            break fakes_in(&state.players);
+        }
+    };
+    assert_eq!(fakes.len(), 3);
+
+    assert_eq!(run_line(&server, "fakes remove 2").unwrap(), "removed 2 fake player(s), 1 left");
+    let mut left = Vec::new();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while left.len() < 2 {
+            if let ClientEvent::PlayerLeft { player_id } = conn.next_event().await {
+                left.push(player_id);
+            }
+        }
+    }).await.expect("timed out waiting for the fakes to leave");
+    left.sort();
+    // The newest go first
+    assert_eq!(left, [fakes[1].id, fakes[2].id]);
+    let remaining: Vec<u32> = fakes_in(&server.get_state().players).iter().map(|p| p.id).collect();
+    assert_eq!(remaining, [fakes[0].id]);
+    assert!(run_line(&server, "fakes add").is_err());
+}
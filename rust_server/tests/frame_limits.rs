@@ -0,0 +1,55 @@
+mod common;
+
+use common::{connect, next_message, next_text, send_text, spawn_server};
+use futures_util::{SinkExt, StreamExt};
+use galavox::protocol::{ErrorCode, MAX_CLIENT_FRAME_SIZE};
+use galavox::server::GameServer;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[tokio::test]
+async fn huge_frame_is_refused_and_others_stay_healthy() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut attacker = connect(addr).await;
+    let mut bystander = connect(addr).await;
+
+    // The write itself may fail once the server hangs up mid-frame
+    let _ = attacker.send(Message::Binary(vec![0u8; 10 * 1024 * 1024].into())).await;
+    loop {
+        match next_message(&mut attacker).await {
+            None => break,
+            Some(Message::Binary(_)) => continue,
+            Some(other) => panic!("unexpected reply to oversized frame: {:?}", other),
+        }
+    }
+
+    send_text(&mut bystander, "still here?").await;
+    assert_eq!(next_text(&mut bystander).await.as_deref(), Some("Echo: still here?"));
+
+    // New clients can still join
+    let mut newcomer = connect(addr).await;
+    send_text(&mut newcomer, "hello").await;
+    assert_eq!(next_text(&mut newcomer).await.as_deref(), Some("Echo: hello"));
+}
+
+#[tokio::test]
+async fn frame_over_application_limit_is_protocol_violation() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect(addr).await;
+
+    client.send(Message::Binary(vec![0u8; MAX_CLIENT_FRAME_SIZE + 1].into())).await.unwrap();
+
+    let close = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match client.next().await {
+                Some(Ok(Message::Close(frame))) => return frame,
+                Some(Ok(_)) => continue,
+                other => panic!("expected close frame, got {:?}", other),
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(close.map(|f| u16::from(f.code)), Some(ErrorCode::ProtocolViolation.code()));
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::{next_json, send_text, spawn_server, Client, TEST_CAPS};
+use galavox::friends::Friends;
+use galavox::protocol::{ClientMessage, Friend, ServerMessage};
+use galavox::server::{Connection, ConnectionId, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+use tokio_tungstenite::connect_async;
+
+const ADA: ConnectionId = ConnectionId(1);
+const BOB: ConnectionId = ConnectionId(2);
+
+fn friends_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("galavox-{}-{}.friends.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn friend(name: &str, online: bool, pending: bool) -> Friend {
+    Friend { name: name.to_string(), online, pending }
+}
+
+fn connection(port: u16) -> Connection {
+    Connection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+/// Connects in JSON mode as `name`.
+async fn join(addr: std::net::SocketAddr, name: &str) -> Client {
+    let url = format!("ws://{}/?format=json&caps={}&name={}", addr, TEST_CAPS.0, name);
+    connect_async(url).await.unwrap().0
+}
+
+async fn send(ws: &mut Client, message: &ClientMessage) {
+    send_text(ws, &serde_json::to_string(message).unwrap()).await;
+}
+
+/// The next friend message, skipping the rest.
+async fn next_friend_message(ws: &mut Client) -> ServerMessage {
+    loop {
+        match next_json(ws).await {
+            message @ (ServerMessage::FriendList { .. } | ServerMessage::FriendRejected { .. }
+                | ServerMessage::FriendOnline { .. } | ServerMessage::FriendOffline { .. }) => return message,
+            _ => continue,
+        }
+    }
+}
+
+#[test]
+fn only_those_who_friended_a_name_hear_of_it() {
+    let mut friends = Friends::new();
+    friends.seen("Ada").unwrap();
+    friends.seen("Bob").unwrap();
+    assert_eq!(friends.joined(ADA, "Ada"), []);
+    friends.add(ADA, "Ada", "Bob").unwrap();
+
+    // One way: Ada hears of Bob, Bob not of Ada
+    assert_eq!(friends.joined(BOB, "Bob"), [ADA]);
+    assert_eq!(friends.left(ADA, "Ada"), []);
+    assert_eq!(friends.joined(ADA, "ada"), []);
+
+    // Both ways once Bob friends Ada too
+    friends.add(BOB, "Bob", "ADA").unwrap();
+    assert_eq!(friends.left(ADA, "Ada"), [BOB]);
+    assert_eq!(friends.joined(ADA, "Ada"), [BOB]);
+    assert_eq!(friends.left(BOB, "Bob"), [ADA]);
+
+    assert!(friends.add(ADA, "Ada", "bob").unwrap_err().contains("already"));
+    assert!(friends.add(ADA, "Ada", "Ada").unwrap_err().contains("yourself"));
+    assert!(friends.add(ADA, "Ada", "no spaces").unwrap_err().contains("invalid"));
+    assert!(friends.remove(ADA, "Ada", "Carol").unwrap_err().contains("not a friend"));
+}
+
+#[test]
+fn a_pending_friend_resolves_when_they_first_join_and_stays_so() {
+    let path = friends_path("pending");
+    let mut friends = Friends::load(&path).unwrap();
+    friends.seen("Ada").unwrap();
+    friends.add(ADA, "Ada", "Carol").unwrap();
+    let nobody = BTreeSet::new();
+    assert_eq!(friends.list("Ada", &nobody), [friend("Carol", false, true)]);
+
+    friends.seen("carol").unwrap();
+    assert_eq!(friends.joined(ConnectionId(3), "carol"), [ADA]);
+    let carol = BTreeSet::from(["carol".to_string()]);
+    assert_eq!(friends.list("Ada", &carol), [friend("Carol", true, false)]);
+
+    // Kept in the file, resolved, and so is whoever has joined
+    let mut reloaded = Friends::load(&path).unwrap();
+    assert_eq!(reloaded.list("ADA", &nobody), [friend("Carol", false, false)]);
+    reloaded.add(ADA, "Ada", "Eve").unwrap();
+    reloaded.add(ADA, "Ada", "CAROL").unwrap_err();
+    reloaded.remove(ADA, "Ada", "carol").unwrap();
+    assert_eq!(Friends::load(&path).unwrap().list("Ada", &nobody), [friend("Eve", false, true)]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn the_index_only_holds_players_online() {
+    let mut friends = Friends::new();
+    friends.add(ADA, "Ada", "Bob").unwrap();
+    friends.add(ADA, "Ada", "Carol").unwrap();
+    friends.add(BOB, "Bob", "Carol").unwrap();
+    assert_eq!(friends.watched(), 2);
+    friends.remove(ADA, "Ada", "Bob").unwrap();
+    assert_eq!(friends.watched(), 1);
+    friends.left(ADA, "Ada");
+    assert_eq!(friends.watched(), 1);
+    friends.left(BOB, "Bob");
+    assert_eq!(friends.watched(), 0);
+    assert_eq!(friends.joined(BOB, "Bob"), []);
+    assert_eq!(friends.watched(), 1);
+
+    // The same on a server, as players come and go
+    let server = GameServer::new();
+    let (ada, _, _) = server.add_player(connection(4000), "Ada".to_string()).unwrap();
+    let (bob, _, _) = server.add_player(connection(4001), "Bob".to_string()).unwrap();
+    server.add_friend(ada, "Bob").unwrap();
+    server.add_friend(ada, "Carol").unwrap();
+    server.add_friend(bob, "Ada").unwrap();
+    assert_eq!(server.friends_watched(), 3);
+    server.remove_player(ada);
+    assert_eq!(server.friends_watched(), 1);
+    server.remove_player(bob);
+    assert_eq!(server.friends_watched(), 0);
+}
+
+#[tokio::test]
+async fn friends_hear_when_a_player_comes_and_goes() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ada = join(addr, "Ada").await;
+    send(&mut ada, &ClientMessage::AddFriend { name: "Bob".to_string() }).await;
+    assert_eq!(next_friend_message(&mut ada).await, ServerMessage::FriendList { friends: vec![friend("Bob", false, true)] });
+
+    let mut bob = join(addr, "Bob").await;
+    assert_eq!(next_friend_message(&mut ada).await, ServerMessage::FriendOnline { name: "Bob".to_string() });
+    send(&mut ada, &ClientMessage::ListFriends {}).await;
+    assert_eq!(next_friend_message(&mut ada).await, ServerMessage::FriendList { friends: vec![friend("Bob", true, false)] });
+    send(&mut bob, &ClientMessage::RemoveFriend { name: "Ada".to_string() }).await;
+    assert!(matches!(next_friend_message(&mut bob).await, ServerMessage::FriendRejected { .. }));
+
+    drop(bob);
+    assert_eq!(next_friend_message(&mut ada).await, ServerMessage::FriendOffline { name: "Bob".to_string() });
+}
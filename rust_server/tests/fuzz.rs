@@ -0,0 +1,125 @@
+use galavox::capture::{read_capture, CAPTURE_MAGIC, CAPTURE_VERSION};
+use galavox::journal::read_journal;
+use galavox::protocol::{
+    decode_binary_client_message, decode_client_message, encode_motion_update, encode_resync_request, encode_time_sync_request,
+    ClientMessage, DecodeError, Position, ServerMessage, LEGACY_POSITION_FRAME_LEN, MAX_CLIENT_FRAME_SIZE, MOTION_FRAME_LEN,
+    POSITION_FRAME_LEN, RESYNC_FRAME_LEN, TIME_SYNC_FRAME_LEN,
+};
+use galavox::server::GameServer;
+use proptest::prelude::*;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/*
+Structured fuzzing of every decoder that reads untrusted bytes: client
+frames on the server, server frames on the client, and journal and capture
+files. Each must return an error rather than panic or allocate without bound.
+*/
+
+/// Arbitrary bytes, weighted towards the lengths the binary decoders accept.
+fn client_frame() -> impl Strategy<Value = Vec<u8>> {
+    let exact = prop_oneof![
+        Just(TIME_SYNC_FRAME_LEN),
+        Just(RESYNC_FRAME_LEN),
+        Just(LEGACY_POSITION_FRAME_LEN),
+        Just(POSITION_FRAME_LEN),
+        Just(MOTION_FRAME_LEN),
+    ];
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..64),
+        exact.prop_flat_map(|len| prop::collection::vec(any::<u8>(), len)),
+    ]
+}
+
+fn server_frame() -> Vec<u8> {
+    let state = GameServer::new().get_state();
+    ServerMessage::State { tick: 1, server_time_ms: 2, state }.to_bincode().unwrap()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2_000))]
+
+    #[test]
+    fn binary_client_frames_never_panic(data in client_frame()) {
+        let _ = decode_binary_client_message(&data);
+        let _ = decode_client_message(&Message::Binary(data.into()));
+    }
+
+    #[test]
+    fn text_client_frames_never_panic(text in ".{0,200}", tag in "[A-Za-z]{0,12}") {
+        let _ = decode_client_message(&Message::Text(text.into()));
+        let tagged = format!(r#"{{"type":"{}","text":"x","position":{{"x":1,"y":2,"z":3}}}}"#, tag);
+        let _ = decode_client_message(&Message::Text(tagged.into()));
+    }
+
+    #[test]
+    fn server_frames_never_panic(data in prop::collection::vec(any::<u8>(), 0..256)) {
+        let _ = ServerMessage::from_bincode(&data);
+    }
+
+    #[test]
+    fn corrupted_server_frames_never_panic(edits in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8), cut in any::<prop::sample::Index>()) {
+        let mut data = server_frame();
+        for (at, byte) in edits {
+            let i = at.index(data.len());
+            data[i] = byte;
+        }
+        let _ = ServerMessage::from_bincode(&data);
+        let _ = ServerMessage::from_bincode(&data[..cut.index(data.len())]);
+    }
+
+    #[test]
+    fn journal_and_capture_files_never_panic(data in prop::collection::vec(any::<u8>(), 0..128)) {
+        let _ = read_journal(data.as_slice());
+        let mut capture = CAPTURE_MAGIC.to_vec();
+        capture.extend_from_slice(&CAPTURE_VERSION.to_le_bytes());
+        capture.extend_from_slice(&data);
+        let _ = read_capture(capture.as_slice());
+    }
+}
+
+#[test]
+fn valid_binary_frames_still_decode() {
+    let position = Position { x: 1.0, y: 2.0, z: 3.0 };
+    let motion = encode_motion_update(9, &position, &[1.0, 0.0, 0.0], &[0.0, 0.0, 0.0, 1.0]);
+    assert!(matches!(decode_binary_client_message(&motion), Ok(ClientMessage::Position { seq: Some(9), .. })));
+    assert!(matches!(decode_binary_client_message(&encode_time_sync_request(5)), Ok(ClientMessage::TimeSync { client_time_ms: 5 })));
+    assert!(matches!(decode_binary_client_message(&encode_resync_request(7)), Ok(ClientMessage::ResyncFrom { tick: 7 })));
+}
+
+#[test]
+fn decode_errors_say_what_was_wrong() {
+    assert!(matches!(decode_binary_client_message(&[0; 13]), Err(DecodeError::Length { len: 13, .. })));
+    // Resync length, but not a resync request
+    assert!(matches!(decode_binary_client_message(&[0; RESYNC_FRAME_LEN]), Err(DecodeError::Malformed(_))));
+    assert!(matches!(decode_client_message(&Message::Text("{".into())), Err(DecodeError::Json(_))));
+    let huge = Message::Binary(vec![0; MAX_CLIENT_FRAME_SIZE + 1].into());
+    assert!(matches!(decode_client_message(&huge), Err(DecodeError::TooLarge { .. })));
+}
+
+#[test]
+fn huge_length_prefixes_do_not_allocate_up_front() {
+    // Used to allocate the claimed 4 GiB before finding the input too short
+    let claim = u32::MAX.to_le_bytes();
+    let mut journal = claim.to_vec();
+    journal.extend_from_slice(&[1, 2, 3]);
+    assert!(read_journal(journal.as_slice()).unwrap().is_empty());
+
+    let mut capture = CAPTURE_MAGIC.to_vec();
+    capture.extend_from_slice(&CAPTURE_VERSION.to_le_bytes());
+    capture.extend_from_slice(&0u64.to_le_bytes());
+    capture.push(0);
+    capture.extend_from_slice(&claim);
+    capture.extend_from_slice(&[1, 2, 3]);
+    assert!(read_capture(capture.as_slice()).unwrap().is_empty());
+}
+
+#[test]
+fn server_frames_claiming_huge_collections_are_refused() {
+    // A State whose planet count says u64::MAX
+    let mut data = 0u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&1u64.to_le_bytes());
+    data.extend_from_slice(&2u64.to_le_bytes());
+    data.extend_from_slice(&u64::MAX.to_le_bytes());
+    data.extend_from_slice(&[0; 32]);
+    assert!(ServerMessage::from_bincode(&data).is_err());
+}
@@ -0,0 +1,248 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{Client, TEST_CAPS};
+use futures_util::{SinkExt, StreamExt};
+use galavox::protocol::ClientMessage;
+use galavox::server::{handle_connection, GameServer};
+use galavox::world::WorldConfig;
+use serde_json::{Map, Value};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+/*
+Golden end-to-end runs of the whole connection loop.
+
+A scenario is a script in tests/fixtures/golden/<name>.script, one step a
+line (blank lines and `#` comments aside):
+
+    connect ada                             a player named ada joins
+    ada {"type":"Chat","text":"hello"}      ada sends a JSON ClientMessage
+    tick                                    one broadcast tick
+    disconnect ada                          the connection is closed
+
+It is played against an in-process server on a fixed seed, whose clock
+moves only on `tick`: no broadcast or economy loop runs. After every step
+each client pings the server twice and everything it was sent until the
+second pong is written down, so every frame lands in the step that caused
+it. Frames are JSON; anything that depends on the wall clock (every `*_ms`
+field) is blanked, and the planets in a State are cut down to their id,
+owner and resources, the rest being the seed's business.
+
+The result is compared with tests/fixtures/golden/<name>.transcript. When
+the protocol changes on purpose, regenerate the transcripts and review the
+diff:
+
+    cargo test --test golden -- --bless
+
+This file has its own `main` (`harness = false`) so that it can take the
+flag, and runs on one thread so that the order of a step's frames is the
+order the server sent them in.
+*/
+
+const SCENARIOS: [&str; 1] = ["claims_and_trade"];
+const SEED: u64 = 42;
+const ADMIN_TOKEN: &str = "golden";
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn fixture_path(name: &str) -> String {
+    format!("{}/tests/fixtures/golden/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+enum Step {
+    Connect(String),
+    Send(String, ClientMessage),
+    Tick,
+    Disconnect(String),
+}
+
+fn parse_script(script: &str) -> Vec<(String, Step)> {
+    let lines = script.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+    lines.map(|line| {
+        let step = match line.split_once(' ') {
+            None if line == "tick" => Step::Tick,
+            Some(("connect", name)) => Step::Connect(name.to_string()),
+            Some(("disconnect", name)) => Step::Disconnect(name.to_string()),
+            Some((name, json)) => match serde_json::from_str(json) {
+                Ok(message) => Step::Send(name.to_string(), message),
+                Err(e) => panic!("not a ClientMessage: {}: {}", line, e),
+            },
+            None => panic!("unknown step: {}", line),
+        };
+        (line.to_string(), step)
+    }).collect()
+}
+
+/// `value` without what changes from run to run.
+fn normalize(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if key.ends_with("_ms") {
+                    *field = Value::String("<ms>".to_string());
+                } else {
+                    normalize(field);
+                }
+            }
+            if fields.get("type").and_then(Value::as_str) == Some("State")
+                && let Some(Value::Array(planets)) = fields.get_mut("state").and_then(|state| state.get_mut("planets"))
+            {
+                for planet in planets {
+                    let keep = ["id", "owner", "resources"].map(|key| (key.to_string(), planet[key].clone()));
+                    *planet = Value::Object(Map::from_iter(keep));
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(normalize),
+        _ => {}
+    }
+}
+
+fn render(message: Message) -> Option<String> {
+    match message {
+        Message::Text(text) => {
+            let mut value: Value = serde_json::from_str(&text).expect("JSON-mode frames are JSON");
+            normalize(&mut value);
+            Some(value.to_string())
+        }
+        Message::Binary(data) => Some(format!("<{} binary bytes>", data.len())),
+        Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => None,
+        Message::Close(frame) => Some(format!("<close {}>", frame.map_or("-".to_string(), |f| u16::from(f.code).to_string()))),
+    }
+}
+
+struct Scenario {
+    server: GameServer,
+    url: String,
+    clients: Vec<(String, Client)>,
+    transcript: Vec<String>,
+    pings: u32,
+}
+
+impl Scenario {
+    async fn start() -> Self {
+        let world = WorldConfig { seed: Some(SEED), belts: 0, ..WorldConfig::default() };
+        let server = GameServer::new().with_world_config(world).with_admin_token(ADMIN_TOKEN.to_string());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/?format=json&caps={}", listener.local_addr().unwrap(), TEST_CAPS.0);
+        let accepting = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, addr)) = listener.accept().await {
+                let server = accepting.clone();
+                tokio::spawn(async move { handle_connection(stream, addr, server).await.unwrap() });
+            }
+        });
+        Scenario { server, url, clients: Vec::new(), transcript: Vec::new(), pings: 0 }
+    }
+
+    fn client(&mut self, name: &str) -> &mut Client {
+        match self.clients.iter_mut().find(|(n, _)| n == name) {
+            Some((_, ws)) => ws,
+            None => panic!("{} is not connected", name),
+        }
+    }
+
+    async fn run(&mut self, line: &str, step: Step) {
+        self.transcript.push(format!("> {}", line));
+        match step {
+            Step::Connect(name) => {
+                let (ws, _) = connect_async(format!("{}&name={}", self.url, name)).await.unwrap();
+                self.clients.push((name, ws));
+            }
+            Step::Send(name, message) => {
+                let text = serde_json::to_string(&message).unwrap();
+                self.client(&name).send(Message::Text(text.into())).await.unwrap();
+                // Whatever it sets off happens before anyone else is asked
+                self.settle(&name).await;
+            }
+            Step::Tick => self.server.broadcast_game_state().await,
+            Step::Disconnect(name) => {
+                let at = self.clients.iter().position(|(n, _)| *n == name).expect("not connected");
+                let (_, mut ws) = self.clients.remove(at);
+                let players = self.server.get_state().players.len();
+                ws.close(None).await.unwrap();
+                while let Some(Ok(message)) = tokio::time::timeout(STEP_TIMEOUT, ws.next()).await.expect("no close") {
+                    if let Some(frame) = render(message) {
+                        self.transcript.push(format!("{} < {}", name, frame));
+                    }
+                }
+                // Gone from the server too, not just from the socket
+                tokio::time::timeout(STEP_TIMEOUT, async {
+                    while self.server.get_state().players.len() == players {
+                        tokio::task::yield_now().await;
+                    }
+                }).await.expect("the player never left");
+            }
+        }
+        let names: Vec<String> = self.clients.iter().map(|(name, _)| name.clone()).collect();
+        for name in names {
+            self.settle(&name).await;
+        }
+    }
+
+    /// Writes down everything `name` is sent before two pings come back.
+    /// The second ping leaves after the first pong arrived, by which time
+    /// the server has passed on every broadcast it had for this client.
+    async fn settle(&mut self, name: &str) {
+        for _ in 0..2 {
+            self.pings += 1;
+            let payload = self.pings.to_be_bytes().to_vec();
+            self.client(name).send(Message::Ping(payload.clone().into())).await.unwrap();
+            loop {
+                let next = tokio::time::timeout(STEP_TIMEOUT, self.client(name).next()).await;
+                let message = match next {
+                    Ok(Some(Ok(message))) => message,
+                    Ok(_) => panic!("{}'s connection ended", name),
+                    Err(_) => panic!("{} never heard back", name),
+                };
+                if matches!(&message, Message::Pong(data) if **data == *payload) {
+                    break;
+                }
+                if let Some(frame) = render(message) {
+                    self.transcript.push(format!("{} < {}", name, frame));
+                }
+            }
+        }
+    }
+}
+
+async fn play(script: &str) -> String {
+    let mut scenario = Scenario::start().await;
+    for (line, step) in parse_script(script) {
+        scenario.run(&line, step).await;
+    }
+    scenario.transcript.join("\n") + "\n"
+}
+
+fn main() {
+    let bless = std::env::args().any(|arg| arg == "--bless");
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let mut failed = Vec::new();
+    for name in SCENARIOS {
+        let script = std::fs::read_to_string(fixture_path(&format!("{}.script", name))).unwrap();
+        let transcript = runtime.block_on(play(&script));
+        let golden_path = fixture_path(&format!("{}.transcript", name));
+        if bless {
+            std::fs::write(&golden_path, &transcript).unwrap();
+            println!("golden {} ... blessed", name);
+            continue;
+        }
+        let golden = std::fs::read_to_string(&golden_path).unwrap_or_default();
+        if transcript == golden {
+            println!("golden {} ... ok", name);
+            continue;
+        }
+        println!("golden {} ... FAILED", name);
+        let (got, want): (Vec<_>, Vec<_>) = (transcript.lines().collect(), golden.lines().collect());
+        let at = got.iter().zip(&want).position(|(a, b)| a != b).unwrap_or(got.len().min(want.len()));
+        println!("  first difference at line {}:", at + 1);
+        println!("  expected: {}", want.get(at).unwrap_or(&"<end of transcript>"));
+        println!("  got:      {}", got.get(at).unwrap_or(&"<end of transcript>"));
+        failed.push(name);
+    }
+    if !failed.is_empty() {
+        println!("\nIf the change is intended: cargo test --test golden -- --bless");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,145 @@
+use galavox::handshake::{client_info, valid_player_name, Cidr, Role};
+use galavox::subscriptions::Subscriptions;
+use std::net::{IpAddr, SocketAddr};
+use tokio_tungstenite::tungstenite::handshake::server::Request;
+
+fn request(path: &str, headers: &[(&str, &str)]) -> Request {
+    let mut builder = Request::builder().uri(path);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    builder.body(()).unwrap()
+}
+
+fn trusted() -> Vec<Cidr> {
+    vec!["10.0.0.0/8".parse().unwrap(), "fd00::/8".parse().unwrap()]
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn cidr_parsing_and_matching() {
+    let net: Cidr = "192.168.1.0/24".parse().unwrap();
+    assert!(net.contains(ip("192.168.1.77")));
+    assert!(!net.contains(ip("192.168.2.1")));
+    assert!(net.contains(ip("::ffff:192.168.1.5")));
+
+    let single: Cidr = "203.0.113.9".parse().unwrap();
+    assert!(single.contains(ip("203.0.113.9")));
+    assert!(!single.contains(ip("203.0.113.10")));
+
+    let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+    assert!(everything.contains(ip("8.8.8.8")));
+
+    let v6: Cidr = "2001:db8::/32".parse().unwrap();
+    assert!(v6.contains(ip("2001:db8:1::1")));
+    assert!(!v6.contains(ip("2001:db9::1")));
+
+    assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    assert!("not-an-ip/8".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn trusted_proxy_forwarded_for_is_honored() {
+    let peer: SocketAddr = "10.1.2.3:40000".parse().unwrap();
+    let req = request("/room/alpha", &[
+        ("X-Forwarded-For", "198.51.100.7, 10.9.9.9"),
+        ("User-Agent", "galavox-test/1.0"),
+    ]);
+
+    let info = client_info(peer, &req, &trusted());
+    assert_eq!(info.addr, "198.51.100.7:40000".parse().unwrap());
+    assert_eq!(info.peer, peer);
+    assert!(info.is_proxied());
+    assert_eq!(info.user_agent.as_deref(), Some("galavox-test/1.0"));
+    assert_eq!(info.path, "/room/alpha");
+    assert_eq!(info.room.as_deref(), Some("alpha"));
+}
+
+#[test]
+fn untrusted_peer_headers_are_ignored() {
+    let peer: SocketAddr = "203.0.113.50:5555".parse().unwrap();
+    let req = request("/", &[("X-Forwarded-For", "1.2.3.4"), ("X-Real-IP", "5.6.7.8")]);
+
+    let info = client_info(peer, &req, &trusted());
+    assert_eq!(info.addr, peer);
+    assert!(!info.is_proxied());
+    assert_eq!(info.room, None);
+}
+
+#[test]
+fn spoofed_leftmost_hops_are_skipped() {
+    // The client prepended a fake hop; the proxy appended the address it saw
+    let peer: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+    let req = request("/", &[("X-Forwarded-For", "6.6.6.6, 198.51.100.7")]);
+    assert_eq!(client_info(peer, &req, &trusted()).addr.ip(), ip("198.51.100.7"));
+}
+
+#[test]
+fn real_ip_header_and_malformed_forwarded_for() {
+    let peer: SocketAddr = "[fd00::1]:9000".parse().unwrap();
+    let req = request("/", &[("X-Real-IP", "2001:db8::42")]);
+    assert_eq!(client_info(peer, &req, &trusted()).addr, "[2001:db8::42]:9000".parse().unwrap());
+
+    let req = request("/", &[("X-Forwarded-For", "garbage")]);
+    assert_eq!(client_info(peer, &req, &trusted()).addr, peer);
+}
+
+#[test]
+fn room_path_parsing() {
+    let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let room = |path: &str| client_info(peer, &request(path, &[]), &[]).room;
+    assert_eq!(room("/room/beta/"), Some("beta".to_string()));
+    assert_eq!(room("/room/"), None);
+    assert_eq!(room("/room/a/b"), None);
+    assert_eq!(room("/lobby"), None);
+}
+
+#[test]
+fn deflate_offer_is_detected() {
+    let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let offered = |value: &str| {
+        client_info(peer, &request("/", &[("Sec-WebSocket-Extensions", value)]), &[]).offered_deflate
+    };
+    assert!(offered("permessage-deflate; client_max_window_bits"));
+    assert!(offered("x-webkit-deflate-frame, permessage-deflate"));
+    assert!(!offered("permessage-unknown"));
+    assert!(!client_info(peer, &request("/", &[]), &[]).offered_deflate);
+}
+
+#[test]
+fn player_name_parameter() {
+    let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let name = |path: &str| client_info(peer, &request(path, &[]), &[]).name;
+    assert_eq!(name("/?name=Ada"), Some("Ada".to_string()));
+    assert_eq!(name("/?format=json&name=ada_2-b"), Some("ada_2-b".to_string()));
+    assert_eq!(name("/?name="), None);
+    assert_eq!(name("/?name=Ada%20L"), None);
+    assert_eq!(name("/"), None);
+    assert!(valid_player_name(&"a".repeat(24)));
+    assert!(!valid_player_name(&"a".repeat(25)));
+}
+
+#[test]
+fn spectator_role_parameter() {
+    let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let role = |path: &str| client_info(peer, &request(path, &[]), &[]).role;
+    assert_eq!(role("/?role=spectator"), Role::Spectator);
+    assert_eq!(role("/?format=json&role=spectator"), Role::Spectator);
+    assert_eq!(role("/?role=player"), Role::Player);
+    assert_eq!(role("/?role=admin"), Role::Player);
+    assert_eq!(role("/"), Role::Player);
+}
+
+#[test]
+fn subscriptions_parameter() {
+    let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let subscriptions = |path: &str| client_info(peer, &request(path, &[]), &[]).subscriptions;
+    assert_eq!(subscriptions("/?subs=2"), Subscriptions::CHAT);
+    assert_eq!(subscriptions("/?format=json&subs=0"), Subscriptions::NONE);
+    assert_eq!(subscriptions("/?subs=4294967295"), Subscriptions::ALL);
+    assert_eq!(subscriptions("/?subs=chat"), Subscriptions::ALL);
+    assert_eq!(subscriptions("/"), Subscriptions::ALL);
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use galavox::hibernate::{HibernationConfig, HibernationTracker};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn config(after_secs: u64, catch_up_ticks: u32) -> HibernationConfig {
+    HibernationConfig { after: Duration::from_secs(after_secs), catch_up_ticks }
+}
+
+fn server(hibernation: HibernationConfig) -> GameServer {
+    GameServer::new()
+        .with_broadcast_interval(Duration::from_millis(100))
+        .with_hibernation(hibernation)
+        .with_admin_token("secret".to_string())
+}
+
+#[test]
+fn sleeps_after_a_whole_grace_period_empty() {
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut tracker = HibernationTracker::new(config(60, 0));
+    assert!(!tracker.should_sleep(false, at(0)));
+    assert!(!tracker.should_sleep(false, at(59)));
+    // Someone came and went: the grace period starts over
+    assert!(!tracker.should_sleep(true, at(59)));
+    assert!(!tracker.should_sleep(false, at(60)));
+    assert!(!tracker.should_sleep(false, at(119)));
+    assert!(tracker.should_sleep(false, at(120)));
+
+    let mut never = HibernationTracker::new(config(0, 0));
+    assert!(!never.should_sleep(false, at(0)));
+    assert!(!never.should_sleep(false, at(1_000_000)));
+}
+
+#[test]
+fn catch_up_is_bounded() {
+    let tick = Duration::from_millis(100);
+    assert_eq!(config(60, 0).catch_up(Duration::from_secs(10), tick), 0);
+    assert_eq!(config(60, 20).catch_up(Duration::from_secs(10), tick), 20);
+    assert_eq!(config(60, 200).catch_up(Duration::from_secs(10), tick), 100);
+    assert_eq!(config(60, 200).catch_up(Duration::from_millis(150), tick), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn ticks_stop_while_empty_and_restart_on_join() {
+    let server = server(config(1, 0));
+    server.spawn_broadcast_loop();
+    tokio::time::sleep(Duration::from_millis(550)).await;
+    assert!(server.current_tick() >= 5);
+    assert!(server.hibernating_since().is_none());
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(server.hibernating_since().is_some());
+    let asleep = server.current_tick();
+    assert!(server.run_admin_command("secret", "broadcast").unwrap().contains("hibernating=0s"));
+    tokio::time::sleep(Duration::from_secs(30)).await;
+    assert_eq!(server.current_tick(), asleep);
+
+    // The first connection gets a broadcast straight away
+    server.add_spectator(connection()).unwrap();
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    assert_eq!(server.current_tick(), asleep + 1);
+    assert!(server.hibernating_since().is_none());
+    tokio::time::sleep(Duration::from_millis(1_000)).await;
+    assert!(server.current_tick() >= asleep + 10);
+    assert!(server.run_admin_command("secret", "broadcast").unwrap().ends_with("hibernating=no"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn waking_fast_forwards_the_configured_ticks() {
+    let server = server(config(1, 20));
+    server.spawn_broadcast_loop();
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    let asleep = server.current_tick();
+    server.add_player(connection(), "Ada".to_string()).unwrap();
+    tokio::time::sleep(Duration::from_millis(1)).await;
+    // 20 skipped, then the wake-up broadcast
+    assert_eq!(server.current_tick(), asleep + 21);
+}
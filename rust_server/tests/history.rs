@@ -0,0 +1,134 @@
+mod common;
+
+use common::{connect, next_server_message, spawn_server};
+use futures_util::SinkExt;
+use galavox::history::{HistoryConfig, SnapshotHistory};
+use galavox::protocol::{encode_resync_request, Capabilities, GameState, Player, PlayerAppearance, Position, ServerMessage};
+use galavox::server::GameServer;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// A state whose single player has moved `tick` units along x.
+fn state_at(tick: u64) -> GameState {
+    GameState::new(
+        vec![],
+        vec![Player {
+            id: 1,
+            name: "Player_1".into(),
+            level: 1,
+            position: Position { x: tick as f32, y: 0.0, z: 0.0 },
+            velocity: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            last_processed_seq: tick as u32,
+            idle: false,
+            appearance: PlayerAppearance::for_name("Player_1"),
+            xp: 0,
+            landed_on: None,
+            remote: false,
+        }],
+        Position { x: 0.0, y: 0.0, z: 0.0 },
+    )
+}
+
+fn recorded(config: HistoryConfig, ticks: std::ops::RangeInclusive<u64>, now: Instant) -> SnapshotHistory {
+    let mut history = SnapshotHistory::new(config);
+    for tick in ticks {
+        history.record(tick, &state_at(tick), now);
+    }
+    history
+}
+
+#[test]
+fn diffs_since_bring_an_old_state_up_to_date() {
+    let history = recorded(HistoryConfig::default(), 1..=5, Instant::now());
+
+    let diffs = history.diffs_since(2).unwrap();
+    assert_eq!(diffs.len(), 3);
+    let mut state = state_at(2);
+    for diff in &diffs {
+        state.apply(diff);
+    }
+    assert_eq!(state, state_at(5));
+
+    assert_eq!(history.diffs_since(5), Some(vec![]));
+    assert_eq!(history.diffs_since(9), None);
+}
+
+#[test]
+fn capacity_bounds_the_history() {
+    let config = HistoryConfig { capacity: 3, ..HistoryConfig::default() };
+    let history = recorded(config, 1..=6, Instant::now());
+
+    assert_eq!(history.stats().entries, 3);
+    assert!(history.stats().bytes > 0);
+    assert_eq!(history.diffs_since(3).map(|d| d.len()), Some(3));
+    assert_eq!(history.diffs_since(2), None);
+}
+
+#[test]
+fn since_gives_the_acknowledged_state_and_the_diffs_after_it() {
+    let config = HistoryConfig { capacity: 3, ..HistoryConfig::default() };
+    let history = recorded(config, 1..=6, Instant::now());
+
+    let (base, diffs) = history.since(4).unwrap();
+    assert_eq!(base, state_at(4));
+    assert_eq!(diffs.len(), 2);
+    assert_eq!(history.since(6), Some((state_at(6), vec![])));
+    assert_eq!(history.since(2), None);
+}
+
+#[test]
+fn old_entries_expire() {
+    let config = HistoryConfig { capacity: 100, retention: Duration::from_secs(10) };
+    let start = Instant::now();
+    let mut history = recorded(config, 1..=3, start);
+
+    history.record(4, &state_at(4), start + Duration::from_secs(11));
+    assert_eq!(history.diffs_since(1), None);
+    assert_eq!(history.diffs_since(3).map(|d| d.len()), Some(1));
+    assert_eq!(history.stats().entries, 1);
+}
+
+async fn next_state_tick(ws: &mut common::Client) -> u64 {
+    loop {
+        if let ServerMessage::State { tick, .. } = next_server_message(ws).await {
+            return tick;
+        }
+    }
+}
+
+#[tokio::test]
+async fn resync_within_history_sends_diffs() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect(addr).await;
+
+    let seen = next_state_tick(&mut client).await;
+    while next_state_tick(&mut client).await < seen + 2 {}
+
+    client.send(Message::Binary(encode_resync_request(seen).into())).await.unwrap();
+    loop {
+        match next_server_message(&mut client).await {
+            ServerMessage::Resync { from_tick, tick, diffs } => {
+                assert_eq!(from_tick, seen);
+                assert_eq!(diffs.len() as u64, tick - seen);
+                break;
+            }
+            ServerMessage::State { .. } | ServerMessage::PlanetPopulation { .. } => continue,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn resync_outside_history_falls_back_to_full_state() {
+    let config = HistoryConfig { capacity: 2, ..HistoryConfig::default() };
+    let server = GameServer::new().with_history_config(config);
+    for _ in 0..5 {
+        server.broadcast_game_state().await;
+    }
+
+    assert!(matches!(server.resync_message(3, Capabilities::SUPPORTED), ServerMessage::Resync { from_tick: 3, tick: 5, .. }));
+    assert!(matches!(server.resync_message(1, Capabilities::SUPPORTED), ServerMessage::State { tick: 5, .. }));
+    assert!(matches!(server.resync_message(3, Capabilities::NONE), ServerMessage::State { tick: 5, .. }));
+    assert_eq!(server.history_stats().entries, 2);
+}
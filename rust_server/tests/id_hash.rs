@@ -0,0 +1,31 @@
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+
+use galavox::id_hash::{BuildIdHasher, IdMap};
+use galavox::server::ConnectionId;
+
+#[test]
+fn sequential_ids_spread_over_low_and_high_bits() {
+    let hashes: Vec<u64> = (0..4096).map(|id| BuildIdHasher::default().hash_one(ConnectionId(id))).collect();
+    // No two ids share a bucket in a table of 4096
+    let buckets: HashSet<u64> = hashes.iter().map(|hash| hash & 4095).collect();
+    assert_eq!(buckets.len(), 4096);
+    // And the top seven bits, which tag entries, take every value
+    let tags: HashSet<u64> = hashes.iter().map(|hash| hash >> 57).collect();
+    assert_eq!(tags.len(), 128);
+}
+
+#[test]
+fn maps_keyed_by_ids_behave_like_any_other() {
+    let mut map: IdMap<u32, &str> = IdMap::default();
+    map.insert(7, "seven");
+    map.insert(u32::MAX, "max");
+    assert_eq!(map.insert(7, "again"), Some("seven"));
+    assert_eq!((map.get(&7), map.get(&u32::MAX), map.get(&0)), (Some(&"again"), Some(&"max"), None));
+
+    // Keys that are not plain integers hash too
+    let mut names: IdMap<(u32, String), u32> = IdMap::default();
+    names.insert((1, "Ada".to_string()), 1);
+    assert_eq!(names.get(&(1, "Ada".to_string())), Some(&1));
+    assert_eq!(names.get(&(1, "Bob".to_string())), None);
+}
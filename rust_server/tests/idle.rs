@@ -0,0 +1,113 @@
+mod common;
+
+use common::{connect, next_server_message, player_name, send_text, spawn_server};
+use futures_util::StreamExt;
+use galavox::idle::{ActivityTracker, IdleConfig, IdleEvent};
+use galavox::protocol::ServerMessage;
+use galavox::server::GameServer;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn config(idle: u64, disconnect: u64) -> IdleConfig {
+    IdleConfig {
+        idle_after: Duration::from_secs(idle),
+        disconnect_after: Duration::from_secs(disconnect),
+    }
+}
+
+#[test]
+fn becomes_idle_then_times_out() {
+    let start = Instant::now();
+    let mut tracker = ActivityTracker::new(config(300, 1800), start);
+
+    assert_eq!(tracker.poll(start + Duration::from_secs(299)), None);
+    assert_eq!(tracker.poll(start + Duration::from_secs(300)), Some(IdleEvent::BecameIdle));
+    assert!(tracker.is_idle());
+    // Only reported once
+    assert_eq!(tracker.poll(start + Duration::from_secs(600)), None);
+    assert_eq!(tracker.poll(start + Duration::from_secs(1800)), Some(IdleEvent::TimedOut));
+}
+
+#[test]
+fn activity_resets_idle_state() {
+    let start = Instant::now();
+    let mut tracker = ActivityTracker::new(config(300, 1800), start);
+
+    assert_eq!(tracker.record_activity(start + Duration::from_secs(100)), None);
+    assert_eq!(tracker.poll(start + Duration::from_secs(399)), None);
+    assert_eq!(tracker.poll(start + Duration::from_secs(400)), Some(IdleEvent::BecameIdle));
+
+    let back = start + Duration::from_secs(1700);
+    assert_eq!(tracker.record_activity(back), Some(IdleEvent::BecameActive));
+    assert!(!tracker.is_idle());
+    assert_eq!(tracker.poll(start + Duration::from_secs(1900)), None);
+}
+
+#[test]
+fn zero_disables_thresholds() {
+    let start = Instant::now();
+    let far = start + Duration::from_secs(365 * 24 * 3600);
+
+    let mut never = ActivityTracker::new(config(0, 0), start);
+    assert_eq!(never.poll(far), None);
+
+    let mut idle_only = ActivityTracker::new(config(300, 0), start);
+    assert_eq!(idle_only.poll(far), Some(IdleEvent::BecameIdle));
+    assert_eq!(idle_only.poll(far), None);
+
+    let mut disconnect_only = ActivityTracker::new(config(0, 60), start);
+    assert_eq!(disconnect_only.poll(start + Duration::from_secs(59)), None);
+    assert_eq!(disconnect_only.poll(start + Duration::from_secs(60)), Some(IdleEvent::TimedOut));
+}
+
+#[tokio::test]
+async fn idle_players_leave_broadcasts_and_time_out() {
+    let server = GameServer::new().with_idle_config(config(1, 4));
+    let addr = spawn_server(server).await;
+    let mut sleeper = connect(addr).await;
+    let mut watcher = connect(addr).await;
+    let sleeper_name = player_name(&sleeper);
+
+    // Watcher keeps itself active while the sleeper goes quiet
+    let mut sleeper_id = None;
+    loop {
+        match next_server_message(&mut watcher).await {
+            ServerMessage::State { state, .. } if sleeper_id.is_none() => {
+                sleeper_id = state.players.iter().find(|p| *p.name == *sleeper_name).map(|p| p.id);
+            }
+            ServerMessage::PlayerIdle { player_id } if Some(player_id) == sleeper_id => break,
+            _ => {}
+        }
+        send_text(&mut watcher, "ping").await;
+    }
+
+    // Next broadcast leaves the sleeper out
+    loop {
+        if let ServerMessage::State { state, .. } = next_server_message(&mut watcher).await {
+            assert!(state.players.iter().all(|p| *p.name != *sleeper_name));
+            break;
+        }
+    }
+
+    send_text(&mut sleeper, "back").await;
+    loop {
+        if let ServerMessage::PlayerActive { player_id } = next_server_message(&mut watcher).await {
+            assert_eq!(Some(player_id), sleeper_id);
+            break;
+        }
+    }
+
+    // Going quiet long enough ends in an idle-timeout close
+    let close = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            match sleeper.next().await {
+                Some(Ok(Message::Close(frame))) => return frame,
+                Some(Ok(_)) => continue,
+                other => panic!("expected close frame, got {:?}", other),
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(close.unwrap().reason.as_str(), "idle timeout");
+}
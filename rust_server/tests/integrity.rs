@@ -0,0 +1,145 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect, next_message, next_state, spawn_server, wait_for_self, TEST_CAPS};
+use futures_util::{SinkExt, StreamExt};
+use galavox::admin::run_line;
+use galavox::client::{ClientEvent, Connection};
+use galavox::integrity::{crc32, open, seal, CorruptFrame};
+use galavox::protocol::{encode_position_update, Capabilities, Position, ServerMessage, CAPABILITIES_HEADER};
+use galavox::server::GameServer;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::{accept_hdr_async, connect_async, tungstenite::protocol::Message};
+
+#[test]
+fn crc32_matches_the_ieee_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn a_flipped_bit_is_caught() {
+    let frame = seal(b"galavox");
+    assert_eq!(frame.len(), b"galavox".len() + 4);
+    assert_eq!(open(&frame), Ok(&b"galavox"[..]));
+
+    let mut flipped = frame.to_vec();
+    flipped[2] ^= 0x10;
+    assert_eq!(open(&flipped), Err(CorruptFrame { expected: crc32(b"galavox"), got: crc32(&flipped[..7]) }));
+    assert!(open(&[1, 2, 3]).is_err());
+}
+
+#[tokio::test]
+async fn the_server_reports_corrupt_frames_from_crc_clients() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let caps = TEST_CAPS | Capabilities::CRC;
+    let (mut ws, response) = connect_async(format!("ws://{}/?caps={}", addr, caps.0)).await.unwrap();
+    assert_eq!(response.headers()[CAPABILITIES_HEADER].to_str().unwrap(), caps.0.to_string());
+
+    // Everything binary the server sends is sealed
+    let spawn = loop {
+        let Some(Message::Binary(frame)) = next_message(&mut ws).await else { continue };
+        if let ServerMessage::State { state, .. } = ServerMessage::from_bincode(open(&frame).unwrap()).unwrap() {
+            break state.players[0].position.clone();
+        }
+    };
+    let moved = Position { x: spawn.x + 5.0, ..spawn };
+    ws.send(Message::Binary(seal(&encode_position_update(1, &moved)))).await.unwrap();
+
+    let mut flipped = seal(&encode_position_update(2, &Position { x: spawn.x + 50.0, ..spawn })).to_vec();
+    flipped[6] ^= 0x01;
+    ws.send(Message::Binary(flipped.clone().into())).await.unwrap();
+    let reported = loop {
+        let Some(Message::Binary(frame)) = next_message(&mut ws).await else { continue };
+        if let ServerMessage::CorruptFrame { expected, got } = ServerMessage::from_bincode(open(&frame).unwrap()).unwrap() {
+            break CorruptFrame { expected, got };
+        }
+    };
+    assert_eq!(Err(reported), open(&flipped));
+
+    // The good update was applied and the corrupt one dropped
+    let me = server.get_state().players[0].clone();
+    assert_eq!(me.position, moved);
+    let stats = run_line(&server, &format!("stats {}", me.id)).unwrap();
+    assert!(stats.contains("corrupt=1"), "{}", stats);
+}
+
+#[tokio::test]
+async fn clients_that_do_not_ask_see_no_trailers() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ws = connect(addr).await;
+    // Undecodable if it carried a trailer
+    next_state(&mut ws).await;
+    let spawn = next_state(&mut ws).await.players[0].position.clone();
+    let moved = Position { x: spawn.x + 5.0, ..spawn };
+    ws.send(Message::Binary(encode_position_update(1, &moved).into())).await.unwrap();
+    wait_for_self(&mut ws, |me| me.position == moved).await;
+
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    assert!(!ada.capabilities().contains(Capabilities::CRC));
+    loop {
+        if let ClientEvent::StateSnapshot { .. } = ada.next_event().await {
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn the_client_library_seals_and_checks_frames() {
+    let addr = spawn_server(GameServer::new()).await;
+    let url = format!("ws://{}", addr);
+    let mut ada = Connection::connect_with_capabilities(&url, Some("Ada"), false, Capabilities::DEFAULT | Capabilities::CRC)
+        .await
+        .unwrap();
+    assert!(ada.capabilities().contains(Capabilities::CRC));
+    let spawn = loop {
+        if let ClientEvent::Joined { spawn, .. } = ada.next_event().await {
+            break spawn;
+        }
+    };
+    let moved = Position { x: spawn.x + 5.0, ..spawn };
+    ada.send_position(moved.clone()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let ClientEvent::StateSnapshot { state, .. } = ada.next_event().await
+                && state.players.iter().any(|p| &*p.name == "Ada" && p.position == moved)
+            {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("the sealed position update was never applied");
+    assert_eq!(ada.corrupt_frames(), 0);
+}
+
+#[tokio::test]
+async fn the_client_library_reports_corrupt_frames_from_the_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut flipped = seal(b"not a real message").to_vec();
+    flipped[0] ^= 0x80;
+    let frame = flipped.clone();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        #[allow(clippy::result_large_err)]  // the signature tungstenite asks for
+        let grant = |_: &Request, mut response: Response| {
+            response.headers_mut().insert(CAPABILITIES_HEADER, Capabilities::CRC.0.to_string().parse().unwrap());
+            Ok(response)
+        };
+        let mut ws = accept_hdr_async(stream, grant).await.unwrap();
+        ws.send(Message::Binary(frame.into())).await.unwrap();
+        while ws.next().await.is_some() {}
+    });
+
+    let mut conn = Connection::connect_with_capabilities(&format!("ws://{}", addr), None, false, Capabilities::CRC)
+        .await
+        .unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(5), conn.next_event()).await.unwrap();
+    let CorruptFrame { expected, got } = open(&flipped).unwrap_err();
+    assert!(matches!(event, ClientEvent::CorruptFrame { expected: e, got: g } if (e, g) == (expected, got)), "{:?}", event);
+    assert_eq!(conn.corrupt_frames(), 1);
+}
@@ -0,0 +1,60 @@
+use galavox::interpolation::Interpolator;
+use galavox::protocol::Position;
+
+fn pos(x: f32) -> Position {
+    Position { x, y: 0.0, z: -x }
+}
+
+fn interpolator() -> Interpolator {
+    let mut interp = Interpolator::new(4, 200);
+    interp.push(1, 1_000, pos(0.0));
+    interp.push(1, 1_100, pos(10.0));
+    interp.push(1, 1_200, pos(30.0));
+    interp
+}
+
+#[test]
+fn exact_snapshot_time_returns_snapshot() {
+    let interp = interpolator();
+    assert_eq!(interp.position_at(1, 1_100), Some(pos(10.0)));
+    assert_eq!(interp.position_at(1, 1_200), Some(pos(30.0)));
+}
+
+#[test]
+fn between_snapshots_is_linear() {
+    let interp = interpolator();
+    assert_eq!(interp.position_at(1, 1_050), Some(pos(5.0)));
+    assert_eq!(interp.position_at(1, 1_175), Some(pos(25.0)));
+}
+
+#[test]
+fn extrapolation_is_capped() {
+    let interp = interpolator();
+    // 20 units per 100ms from the last two snapshots
+    assert_eq!(interp.position_at(1, 1_250), Some(pos(40.0)));
+    assert_eq!(interp.position_at(1, 1_400), Some(pos(70.0)));
+    assert_eq!(interp.position_at(1, 5_000), Some(pos(70.0)));
+}
+
+#[test]
+fn single_snapshot_player_stays_put() {
+    let mut interp = Interpolator::default();
+    interp.push(7, 500, pos(3.0));
+    assert_eq!(interp.position_at(7, 0), Some(pos(3.0)));
+    assert_eq!(interp.position_at(7, 10_000), Some(pos(3.0)));
+    assert_eq!(interp.position_at(8, 500), None);
+}
+
+#[test]
+fn old_and_evicted_snapshots() {
+    let mut interp = interpolator();
+    interp.push(1, 1_150, pos(99.0));  // out of order, ignored
+    assert_eq!(interp.position_at(1, 1_150), Some(pos(20.0)));
+
+    interp.push(1, 1_300, pos(40.0));
+    interp.push(1, 1_400, pos(50.0));  // evicts t=1000
+    assert_eq!(interp.position_at(1, 1_000), Some(pos(10.0)));
+
+    interp.retain_players(|id| id != 1);
+    assert_eq!(interp.position_at(1, 1_300), None);
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{connect, send_text, spawn_server, wait_for_self};
+use futures_util::SinkExt;
+use galavox::journal::{self, read_journal, replay_until, JournalEvent, JournalRecord};
+use galavox::protocol::{encode_position_update, GameState, Position};
+use galavox::server::GameServer;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn temp_journal(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("galavox-{}-{}.journal", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn records_round_trip_through_the_file() {
+    let path = temp_journal("round-trip");
+    let (journal, writer) = journal::open(&path).await.unwrap();
+    journal.record(1, 10, JournalEvent::PlayerLeft { player_id: 3 });
+    journal.record(2, 20, JournalEvent::Chat { player_id: 4, text: "hi".to_string() });
+    writer.shutdown().await.unwrap();
+
+    let records = read_journal(std::fs::File::open(&path).unwrap()).unwrap();
+    let events: Vec<_> = records.iter().map(|r| (r.tick, r.event.clone())).collect();
+    assert_eq!(events, [
+        (1, JournalEvent::PlayerLeft { player_id: 3 }),
+        (2, JournalEvent::Chat { player_id: 4, text: "hi".to_string() }),
+    ]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn truncated_tail_is_ignored() {
+    let state = GameState::new(vec![], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+
+    let record = bincode::serialize(&JournalRecord {
+        tick: 1,
+        server_time_ms: 0,
+        unix_ms: 0,
+        event: JournalEvent::WorldCreated { state },
+    }).unwrap();
+    let mut bytes = (record.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(&record);
+    bytes.extend_from_slice(&[9, 0, 0, 0, 1, 2]);  // a record cut short by a crash
+
+    assert_eq!(read_journal(bytes.as_slice()).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn replaying_a_session_rebuilds_the_final_state() {
+    let path = temp_journal("session");
+    let (journal, writer) = journal::open(&path).await.unwrap();
+    let server = GameServer::new().with_journal(journal);
+    let addr = spawn_server(server.clone()).await;
+
+    let mut alice = connect(addr).await;
+    let mut bob = connect(addr).await;
+    for seq in 1..=3 {
+        let position = Position { x: seq as f32, y: 2.0, z: 3.0 };
+        alice.send(Message::Binary(encode_position_update(seq, &position).into())).await.unwrap();
+    }
+    send_text(&mut bob, "hello").await;
+    bob.send(Message::Binary(encode_position_update(1, &Position { x: -5.0, y: 0.0, z: 0.0 }).into())).await.unwrap();
+    wait_for_self(&mut alice, |me| me.last_processed_seq == 3).await;
+    wait_for_self(&mut bob, |me| me.position.x == -5.0).await;
+
+    drop(bob);
+    for _ in 0..50 {
+        if server.get_state().players.len() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(server.get_state().players.len(), 1);
+
+    let mut expected = server.get_state();
+    writer.shutdown().await.unwrap();
+
+    let records = read_journal(std::fs::File::open(&path).unwrap()).unwrap();
+    let JournalEvent::WorldCreated { state: created } = &records[0].event else { panic!("no WorldCreated") };
+    // The journal does not follow the clock (see `world_clock`)
+    expected.world_time = created.world_time;
+    assert!(records.iter().any(|r| matches!(&r.event, JournalEvent::Chat { text, .. } if text == "hello")));
+    assert_eq!(replay_until(&records, u64::MAX), expected);
+    std::fs::remove_file(&path).unwrap();
+}
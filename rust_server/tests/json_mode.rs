@@ -0,0 +1,127 @@
+mod common;
+
+use common::{connect, connect_json, next_json, next_message, next_state, player_name, send_text, spawn_server};
+use futures_util::SinkExt;
+use galavox::protocol::{encode_position_update, encode_server_message, encode_state, MessageCode, Position, ServerMessage, WireFormat};
+use galavox::server::GameServer;
+use serde_json::json;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+#[test]
+fn json_is_tagged_and_bincode_still_round_trips() {
+    let message = ServerMessage::TimeSync { client_time_ms: 12, server_time_ms: 40, tick: 3 };
+
+    let value: serde_json::Value = serde_json::to_value(&message).unwrap();
+    assert_eq!(value, json!({"type": "TimeSync", "client_time_ms": 12, "server_time_ms": 40, "tick": 3}));
+
+    let bytes = message.to_bincode().unwrap();
+    assert_eq!(ServerMessage::from_bincode(&bytes).unwrap(), message);
+}
+
+#[test]
+fn buffered_and_borrowed_encodings_match_the_plain_ones() {
+    let state = GameServer::new().get_state();
+    let message = ServerMessage::State { tick: 7, server_time_ms: 900, state: state.clone() };
+    let mut buf = vec![0xff];
+    message.to_bincode_into(&mut buf).unwrap();
+    assert_eq!(buf[1..], message.to_bincode().unwrap()[..]);
+
+    // Twice each, so that the second encoding reuses the thread's buffer
+    for format in [WireFormat::Binary, WireFormat::Json, WireFormat::Binary, WireFormat::Json] {
+        let encoded = encode_server_message(format, &message).unwrap();
+        assert_eq!(encode_state(format, 7, 900, &state).unwrap(), encoded);
+        let decoded = match encoded {
+            Message::Binary(data) => ServerMessage::from_bincode(&data).unwrap(),
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(decoded, message);
+    }
+}
+
+#[tokio::test]
+async fn json_and_binary_clients_share_a_server() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut binary = connect(addr).await;
+    let mut json_client = connect_json(addr).await;
+
+    // Chat is echoed as a tagged JSON message, not as "Echo: ..." text
+    send_text(&mut json_client, r#"{"type":"Chat","text":"hello"}"#).await;
+    loop {
+        if let ServerMessage::Echo { text } = next_json(&mut json_client).await {
+            assert_eq!(text, "hello");
+            break;
+        }
+    }
+
+    // A JSON position update reaches the binary client's broadcasts
+    let update = json!({"type": "Position", "seq": 1, "position": {"x": 4.0, "y": 5.0, "z": 6.0}});
+    send_text(&mut json_client, &update.to_string()).await;
+    let json_name = player_name(&json_client);
+    'binary: loop {
+        let state = next_state(&mut binary).await;
+        if let Some(p) = state.players.iter().find(|p| *p.name == *json_name)
+            && p.position == (Position { x: 4.0, y: 5.0, z: 6.0 })
+        {
+            break 'binary;
+        }
+    }
+
+    // ...and a binary update reaches the JSON client's broadcasts
+    let position = Position { x: -1.0, y: 0.0, z: 2.0 };
+    binary.send(Message::Binary(encode_position_update(1, &position).into())).await.unwrap();
+    let binary_name = player_name(&binary);
+    loop {
+        if let ServerMessage::State { state, .. } = next_json(&mut json_client).await
+            && state.players.iter().any(|p| *p.name == *binary_name && p.position == position)
+        {
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn invalid_json_gets_a_notice() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect_json(addr).await;
+
+    send_text(&mut client, "not json").await;
+    loop {
+        if let ServerMessage::Localized { message: MessageCode::InvalidMessage { .. }, text } = next_json(&mut client).await {
+            assert!(text.starts_with("Invalid message"), "{}", text);
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn first_message_can_switch_to_json() {
+    let addr = spawn_server(GameServer::new()).await;
+    let (mut client, _) = connect_async(format!("ws://{}", addr)).await.unwrap();
+
+    // Binary join messages arrive first, then the JSON re-send after the switch
+    send_text(&mut client, r#"{"type":"SetFormat","format":"json"}"#).await;
+    let mut saw_json_state = false;
+    loop {
+        match next_message(&mut client).await.expect("connection closed") {
+            Message::Text(text) if text.starts_with('{') => {
+                match serde_json::from_str::<ServerMessage>(&text).unwrap() {
+                    ServerMessage::State { .. } => saw_json_state = true,
+                    ServerMessage::Notice { text } if text.starts_with("Welcome") => break,
+                    _ => {}
+                }
+            }
+            _ => continue,
+        }
+    }
+    assert!(saw_json_state);
+
+    // From now on chat is JSON too
+    send_text(&mut client, r#"{"type":"Chat","text":"hi"}"#).await;
+    loop {
+        if let ServerMessage::Echo { text } = next_json(&mut client).await {
+            assert_eq!(text, "hi");
+            break;
+        }
+    }
+}
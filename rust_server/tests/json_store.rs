@@ -0,0 +1,52 @@
+use galavox::json_store::JsonStore;
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("galavox-{}-{}.store.json", name, std::process::id()))
+}
+
+#[test]
+fn records_belong_to_normalized_names() {
+    let mut store: JsonStore<String, u32> = JsonStore::new();
+    assert_eq!(store.insert("Ada", 1), None);
+    assert_eq!(store.insert("ADA", 2), Some(1));
+    store.insert("Bob", 3);
+    assert_eq!(store.get("ada"), Some(&2));
+    assert!(store.contains("bob"));
+    assert_eq!(store.len(), 2);
+
+    // A rename never takes over another name's record
+    assert!(!store.rename("Ada", "bob"));
+    assert!(!store.rename("Ada", "aDa"));
+    assert!(!store.rename("Carol", "Dora"));
+    assert!(store.rename("Ada", "Carol"));
+    assert_eq!((store.get("Ada"), store.get("carol")), (None, Some(&2)));
+
+    assert!(store.retain(|n| *n > 2));
+    assert!(!store.retain(|n| *n > 2));
+    assert_eq!(store.values().collect::<Vec<_>>(), [&3]);
+    assert_eq!(store.remove("BOB"), Some(3));
+    assert!(store.is_empty());
+}
+
+#[test]
+fn stores_are_saved_and_loaded() {
+    let path = temp_path("saved");
+    let _ = std::fs::remove_file(&path);
+    let mut store: JsonStore<String, u32> = JsonStore::load(&path).unwrap();
+    assert!(store.is_empty());
+    store.insert("Ada", 1);
+    // Nothing is written until saved, and not at all when detached
+    assert!(!path.exists());
+    store.detached().save().unwrap();
+    assert!(!path.exists());
+    store.save().unwrap();
+
+    let loaded: JsonStore<String, u32> = JsonStore::load(&path).unwrap();
+    assert_eq!(loaded.to_file_text(), store.to_file_text());
+    assert_eq!(loaded.get("ada"), Some(&1));
+    assert_eq!(loaded.path(), Some(path.as_path()));
+
+    std::fs::write(&path, "{ not json").unwrap();
+    assert!(JsonStore::<String, u32>::load(&path).is_err());
+    let _ = std::fs::remove_file(&path);
+}
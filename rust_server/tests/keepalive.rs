@@ -0,0 +1,139 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{connect, next_server_message, send_text, spawn_server, Client};
+use futures_util::StreamExt;
+use galavox::client::{ClientEvent, Connection};
+use galavox::keepalive::{KeepaliveAction, KeepaliveConfig, KeepaliveTracker};
+use galavox::protocol::{Capabilities, ClientMessage, ErrorCode, ServerMessage};
+use galavox::server::GameServer;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const SECOND: Duration = Duration::from_secs(1);
+
+fn config() -> KeepaliveConfig {
+    KeepaliveConfig { interval: SECOND, misses: 3 }
+}
+
+#[test]
+fn a_silent_client_times_out_after_its_misses() {
+    let start = Instant::now();
+    let mut tracker = KeepaliveTracker::new(config(), start);
+    assert_eq!(tracker.poll(start + SECOND / 2), None);
+    assert_eq!(tracker.poll(start + SECOND), Some(KeepaliveAction::Send { seq: 1 }));
+    // Nothing more until another interval has passed since the last one
+    assert_eq!(tracker.poll(start + SECOND * 3 / 2), None);
+    assert_eq!(tracker.poll(start + SECOND * 2), Some(KeepaliveAction::Send { seq: 2 }));
+    assert_eq!(tracker.poll(start + SECOND * 3), Some(KeepaliveAction::Send { seq: 3 }));
+    assert_eq!(tracker.missed(), 2);
+    assert_eq!(tracker.poll(start + SECOND * 4), Some(KeepaliveAction::TimedOut));
+}
+
+#[test]
+fn only_an_ack_of_the_latest_keepalive_counts() {
+    let start = Instant::now();
+    let mut tracker = KeepaliveTracker::new(config(), start);
+    tracker.poll(start + SECOND);
+    tracker.poll(start + SECOND * 2);
+    assert_eq!(tracker.missed(), 1);
+
+    // A late ack of the first one proves nothing about the path now
+    tracker.acked(1);
+    assert_eq!(tracker.missed(), 1);
+    tracker.acked(2);
+    assert_eq!(tracker.missed(), 0);
+    // Answered, so the next one does not count as a miss
+    assert_eq!(tracker.poll(start + SECOND * 3), Some(KeepaliveAction::Send { seq: 3 }));
+    assert_eq!(tracker.missed(), 0);
+}
+
+#[test]
+fn any_frame_puts_the_next_keepalive_off() {
+    let start = Instant::now();
+    let mut tracker = KeepaliveTracker::new(config(), start);
+    tracker.heard(start + SECOND * 9 / 10);
+    assert_eq!(tracker.poll(start + SECOND * 3 / 2), None);
+    assert_eq!(tracker.poll(start + SECOND * 19 / 10), Some(KeepaliveAction::Send { seq: 1 }));
+
+    // Hearing from the client does not answer a keepalive, though
+    tracker.heard(start + SECOND * 2);
+    assert_eq!(tracker.poll(start + SECOND * 3), Some(KeepaliveAction::Send { seq: 2 }));
+    assert_eq!(tracker.missed(), 1);
+}
+
+fn quick_server() -> GameServer {
+    GameServer::new().with_keepalive_config(KeepaliveConfig { interval: Duration::from_millis(50), misses: 2 })
+}
+
+#[tokio::test]
+async fn unanswered_keepalives_close_the_connection() {
+    let addr = spawn_server(quick_server()).await;
+    let (mut ws, _) = connect_async(format!("ws://{}/?caps={}", addr, Capabilities::DEFAULT.0)).await.unwrap();
+
+    let mut seqs = Vec::new();
+    let code = loop {
+        match next_server_message(&mut ws).await {
+            ServerMessage::Keepalive { seq } => seqs.push(seq),
+            ServerMessage::Error { code, .. } => break code,
+            _ => continue,
+        }
+    };
+    assert_eq!(seqs, [1, 2]);
+    assert_eq!(code, ErrorCode::KeepaliveTimeout.code());
+    let closed = loop {
+        match tokio::time::timeout(Duration::from_secs(5), ws.next()).await.unwrap() {
+            Some(Ok(Message::Close(Some(frame)))) => break u16::from(frame.code),
+            Some(Ok(_)) => continue,
+            other => panic!("expected a close frame, got {:?}", other),
+        }
+    };
+    assert_eq!(closed, ErrorCode::KeepaliveTimeout.code());
+}
+
+#[tokio::test]
+async fn the_client_library_answers_keepalives() {
+    let addr = spawn_server(quick_server()).await;
+    let mut connection = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    assert!(connection.capabilities().contains(Capabilities::KEEPALIVE));
+
+    // Many times the two misses allowed, and still connected
+    let listening = tokio::time::timeout(Duration::from_millis(500), async {
+        loop {
+            if let event @ ClientEvent::Disconnected { .. } = connection.next_event().await {
+                return event;
+            }
+        }
+    });
+    if let Ok(event) = listening.await {
+        panic!("disconnected: {:?}", event);
+    }
+}
+
+/// Messages until `deadline`, which must not include a keepalive.
+async fn no_keepalives_until(ws: &mut Client, deadline: Duration) {
+    let _ = tokio::time::timeout(deadline, async {
+        loop {
+            let message = next_server_message(ws).await;
+            assert!(!matches!(message, ServerMessage::Keepalive { .. } | ServerMessage::Error { .. }), "{:?}", message);
+        }
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn client_keepalives_are_echoed_to_anyone() {
+    let addr = spawn_server(quick_server()).await;
+    // Without the capability the server never sends its own
+    let mut ws = connect(addr).await;
+    no_keepalives_until(&mut ws, Duration::from_millis(300)).await;
+
+    send_text(&mut ws, &serde_json::to_string(&ClientMessage::Keepalive { seq: 7 }).unwrap()).await;
+    let acked = loop {
+        if let ServerMessage::KeepaliveAck { seq } = next_server_message(&mut ws).await {
+            break seq;
+        }
+    };
+    assert_eq!(acked, 7);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::{connect, next_server_message, planet, send_text, spawn_server, wait_for_self};
+use futures_util::SinkExt;
+use galavox::admin::run_line;
+use galavox::journal::{apply_event, JournalEvent};
+use galavox::landing::{follow, try_land, LANDING_RANGE, MAX_LANDING_SPEED};
+use galavox::protocol::{encode_position_update, ClientMessage, GameState, LandError, Position, PositionUpdate, ServerMessage, IDENTITY_ROTATION};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn at(x: f32, y: f32) -> Position {
+    Position { x, y, z: 0.0 }
+}
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+#[test]
+fn landing_is_accepted_near_the_surface() {
+    let planet = planet(1, 0.0);
+    assert_eq!(try_land(&planet, &at(0.0, 50.0 + LANDING_RANGE), &[0.0; 3]), Ok(at(0.0, 50.0)));
+    assert_eq!(try_land(&planet, &at(-60.0, 0.0), &[0.0; 3]), Ok(at(-50.0, 0.0)));
+    // Just under the surface counts too
+    assert_eq!(try_land(&planet, &at(40.0, 0.0), &[0.0; 3]), Ok(at(50.0, 0.0)));
+    // The centre is a radius from the surface
+    assert_eq!(try_land(&planet, &at(0.0, 0.0), &[0.0; 3]).unwrap_err(), LandError::TooFar { distance: 50.0 });
+    assert_eq!(try_land(&planet, &at(0.0, 80.0), &[0.0; 3]), Err(LandError::TooFar { distance: 30.0 }));
+}
+
+#[test]
+fn landing_is_rejected_while_moving_fast() {
+    let planet = planet(1, 0.0);
+    assert!(try_land(&planet, &at(0.0, 60.0), &[MAX_LANDING_SPEED, 0.0, 0.0]).is_ok());
+    assert_eq!(try_land(&planet, &at(0.0, 60.0), &[0.0, -30.0, 40.0]), Err(LandError::TooFast { speed: 50.0 }));
+}
+
+#[test]
+fn landed_players_are_pinned_and_move_with_their_planet() {
+    let state = GameState::new(vec![planet(1, 0.0), planet(2, 1000.0)], vec![], at(0.0, 500.0));
+    let server = GameServer::new().with_world(state);
+    let (me, player, _) = server.add_player(connection(), "Lander".to_string()).unwrap();
+    assert_eq!(player.landed_on, None);
+    assert_eq!(server.take_off(me), Err(LandError::NotLanded));
+
+    server.move_player(me, at(0.0, 60.0));
+    assert_eq!(server.land(me, 9), Err(LandError::UnknownPlanet { planet_id: 9 }));
+    assert!(matches!(server.land(me, 2), Err(LandError::TooFar { .. })));
+    assert_eq!(server.land(me, 1), Ok(at(0.0, 50.0)));
+    assert_eq!(server.land(me, 1), Err(LandError::AlreadyLanded { planet_id: 1 }));
+    assert!(server.is_landed(me));
+
+    let landed = |server: &GameServer| server.get_state().players.into_iter().find(|p| p.id == player.id).unwrap();
+    // Updates are ignored while landed
+    server.update_player_position(me, PositionUpdate { seq: Some(1), position: at(300.0, 300.0), velocity: [0.0; 3], rotation: IDENTITY_ROTATION });
+    assert_eq!(landed(&server).position, at(0.0, 50.0));
+    assert_eq!(landed(&server).landed_on, Some(1));
+
+    // The planet moves and grows a step; we stay on the same spot of it
+    run_line(&server, "planet set 1 x 200").unwrap();
+    assert_eq!(landed(&server).position, at(200.0, 50.0));
+    run_line(&server, "planet set 1 size 150").unwrap();
+    assert_eq!(landed(&server).position, at(200.0, 75.0));
+    assert_eq!(follow(&planet(1, 0.0), &planet(1, -40.0), &at(-50.0, 0.0)), at(-90.0, 0.0));
+
+    server.take_off(me).unwrap();
+    server.update_player_position(me, PositionUpdate { seq: Some(2), position: at(300.0, 300.0), velocity: [0.0; 3], rotation: IDENTITY_ROTATION });
+    assert_eq!(landed(&server).position, at(300.0, 300.0));
+    assert_eq!(landed(&server).landed_on, None);
+
+    // Respawning takes off, and so does losing the planet
+    server.move_player(me, at(1000.0, 55.0));
+    server.land(me, 2).unwrap();
+    server.move_player(me, at(0.0, 500.0));
+    assert!(!server.is_landed(me));
+    server.move_player(me, at(1000.0, 55.0));
+    server.land(me, 2).unwrap();
+    server.remove_planet(2).unwrap();
+    assert_eq!(landed(&server).landed_on, None);
+    assert_eq!(landed(&server).position, at(1000.0, 50.0));
+}
+
+#[test]
+fn landing_replays_from_the_journal() {
+    let mut state = GameState::new(vec![planet(1, 0.0)], vec![], at(0.0, 0.0));
+    let server = GameServer::new().with_world(state.clone());
+    let (_, player, _) = server.add_player(connection(), "Lander".to_string()).unwrap();
+    state.players.push(player.clone());
+    apply_event(&mut state, &JournalEvent::LandingChanged { player_id: player.id, landed_on: Some(1), position: at(0.0, 50.0) });
+    assert_eq!(state.players[0].landed_on, Some(1));
+    assert_eq!(state.players[0].position, at(0.0, 50.0));
+    apply_event(&mut state, &JournalEvent::LandingChanged { player_id: player.id, landed_on: None, position: at(0.0, 50.0) });
+    assert_eq!(state.players[0].landed_on, None);
+}
+
+#[tokio::test]
+async fn landing_over_the_wire() {
+    let state = GameState::new(vec![planet(1, 0.0)], vec![], at(0.0, 500.0));
+    let addr = spawn_server(GameServer::new().with_world(state)).await;
+    let mut ws = connect(addr).await;
+    ws.send(Message::Binary(encode_position_update(1, &at(0.0, 500.0)).into())).await.unwrap();
+    wait_for_self(&mut ws, |p| p.last_processed_seq == 1).await;
+
+    send_text(&mut ws, &serde_json::to_string(&ClientMessage::Land { planet_id: 1 }).unwrap()).await;
+    let error = loop {
+        if let ServerMessage::LandRejected { error } = next_server_message(&mut ws).await {
+            break error;
+        }
+    };
+    assert_eq!(error, LandError::TooFar { distance: 450.0 });
+
+    ws.send(Message::Binary(encode_position_update(2, &at(0.0, 60.0)).into())).await.unwrap();
+    wait_for_self(&mut ws, |p| p.last_processed_seq == 2).await;
+    send_text(&mut ws, &serde_json::to_string(&ClientMessage::Land { planet_id: 1 }).unwrap()).await;
+    let me = wait_for_self(&mut ws, |p| p.landed_on == Some(1)).await;
+    assert_eq!(me.position, at(0.0, 50.0));
+
+    // Ignored, so never acknowledged
+    ws.send(Message::Binary(encode_position_update(3, &at(0.0, 400.0)).into())).await.unwrap();
+    send_text(&mut ws, &serde_json::to_string(&ClientMessage::TakeOff {}).unwrap()).await;
+    let me = wait_for_self(&mut ws, |p| p.landed_on.is_none()).await;
+    assert_eq!((me.position, me.last_processed_seq), (at(0.0, 50.0), 2));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use common::{connect_json, next_json, player_name, send_text, Client};
+use galavox::admin::run_line;
+use galavox::lifetime::LifetimeStats;
+use galavox::protocol::{Player, ServerMessage};
+use galavox::server::GameServer;
+use tokio::net::TcpListener;
+
+fn stats_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("galavox-{}-{}.stats.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// This client's player in the next State for which `check` holds.
+async fn next_self(ws: &mut Client, check: impl Fn(&Player) -> bool) -> Player {
+    let name = player_name(ws);
+    loop {
+        if let ServerMessage::State { state, .. } = next_json(ws).await
+            && let Some(me) = state.players.iter().find(|p| *p.name == *name && check(p))
+        {
+            return me.clone();
+        }
+    }
+}
+
+#[test]
+fn peaks_and_names_are_counted_once() {
+    let start = Instant::now();
+    let mut stats = LifetimeStats::new(start);
+    stats.joined("ada", 1, 1_000);
+    stats.joined("bob", 2, 2_000);
+    stats.joined("ada", 2, 3_000);
+    stats.joined("cy", 1, 4_000);
+    stats.chatted();
+    stats.traveled(12.5);
+
+    let snapshot = stats.snapshot(start + Duration::from_secs(90));
+    assert_eq!(snapshot.players_seen.len(), 3);
+    assert_eq!((snapshot.peak_players, snapshot.peak_at_unix_ms), (2, 2_000));
+    assert_eq!((snapshot.chat_messages, snapshot.distance, snapshot.uptime_ms), (1, 12.5, 90_000));
+    assert_eq!(snapshot.to_string(), "players_seen=3 peak=2 peak_at_unix_ms=2000 chat_messages=1 distance=12.5 uptime=90s");
+}
+
+#[test]
+fn uptime_adds_up_across_runs_and_a_bad_file_starts_afresh() {
+    let path = stats_path("uptime");
+    let start = Instant::now();
+    let mut first = LifetimeStats::load(&path, start);
+    first.joined("ada", 1, 1_000);
+    first.save(start + Duration::from_secs(30)).unwrap();
+
+    let later = start + Duration::from_secs(100);
+    let mut second = LifetimeStats::load(&path, later);
+    second.save(later + Duration::from_secs(15)).unwrap();
+    let snapshot = LifetimeStats::load(&path, later).snapshot(later);
+    assert_eq!((snapshot.players_seen.len(), snapshot.uptime_ms), (1, 45_000));
+
+    std::fs::write(&path, "{ not json").unwrap();
+    let mut fresh = LifetimeStats::load(&path, later);
+    assert_eq!(fresh.snapshot(later).players_seen.len(), 0);
+    let mut aside = path.clone().into_os_string();
+    aside.push(".corrupt");
+    assert_eq!(std::fs::read_to_string(&aside).unwrap(), "{ not json");
+    assert!(!path.exists());
+    let _ = std::fs::remove_file(aside);
+}
+
+#[tokio::test]
+async fn the_counters_survive_a_restart() {
+    let path = stats_path("restart");
+    let server = GameServer::new().with_lifetime_stats(LifetimeStats::load(&path, Instant::now()));
+    let handle = server.clone().spawn(TcpListener::bind("127.0.0.1:0").await.unwrap()).unwrap();
+    let mut ada = connect_json(handle.local_addr()).await;
+    let _bob = connect_json(handle.local_addr()).await;
+    send_text(&mut ada, r#"{"type":"Chat","text":"hello"}"#).await;
+    while next_json(&mut ada).await != (ServerMessage::Echo { text: "hello".to_string() }) {}
+    let me = next_self(&mut ada, |_| true).await;
+    let to = me.position.x + 5.0;
+    send_text(&mut ada, &format!(r#"{{"type":"Position","position":{{"x":{},"y":{},"z":{}}}}}"#, to, me.position.y, me.position.z)).await;
+    next_self(&mut ada, |p| p.position.x == to).await;
+    handle.shutdown().await.unwrap();
+
+    let restarted = GameServer::new().with_lifetime_stats(LifetimeStats::load(&path, Instant::now()));
+    let carried = restarted.lifetime_stats();
+    assert_eq!(carried.players_seen.len(), 2);
+    assert_eq!(carried.peak_players, 2);
+    assert_eq!(carried.chat_messages, 1);
+    assert!((carried.distance - 5.0).abs() < 0.01, "{}", carried.distance);
+    assert!(carried.uptime_ms > 0);
+
+    // A bigger crowd sets a new peak
+    let handle = restarted.clone().spawn(TcpListener::bind("127.0.0.1:0").await.unwrap()).unwrap();
+    let _crowd = [connect_json(handle.local_addr()).await, connect_json(handle.local_addr()).await, connect_json(handle.local_addr()).await];
+    let stats = restarted.lifetime_stats();
+    assert_eq!((stats.players_seen.len(), stats.peak_players), (5, 3));
+    assert!(stats.peak_at_unix_ms > carried.peak_at_unix_ms);
+    assert!(run_line(&restarted, "stats").unwrap().starts_with("players_seen=5 peak=3 "));
+    handle.shutdown().await.unwrap();
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::{next_message, spawn_server};
+use galavox::client::{ClientEvent, Connection};
+use galavox::locale::{localized, MessageTable, ENGLISH};
+use galavox::protocol::{ErrorCode, MessageCode, ServerMessage};
+use galavox::server::GameServer;
+use serde_json::json;
+use std::time::Duration;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+#[test]
+fn codes_never_change() {
+    // Clients translate by these; renaming or reordering one breaks them
+    let codes = [
+        (MessageCode::Welcome {}, "WELCOME"),
+        (MessageCode::WelcomeSpectator {}, "WELCOME_SPECTATOR"),
+        (MessageCode::InvalidMessage { reason: String::new() }, "INVALID_MESSAGE"),
+        (MessageCode::FormatLocked {}, "FORMAT_LOCKED"),
+        (MessageCode::RateLimited { retry_after_ms: 0 }, "RATE_LIMITED"),
+        (MessageCode::PlanetQueryRateLimited { retry_after_ms: 0 }, "PLANET_QUERY_RATE_LIMITED"),
+        (MessageCode::TrailQueryRateLimited { retry_after_ms: 0 }, "TRAIL_QUERY_RATE_LIMITED"),
+        (MessageCode::HistoryRateLimited { retry_after_ms: 0 }, "HISTORY_RATE_LIMITED"),
+        (MessageCode::PlanetQueryRejected { reason: String::new() }, "PLANET_QUERY_REJECTED"),
+        (MessageCode::TrailQueryRejected { reason: String::new() }, "TRAIL_QUERY_REJECTED"),
+        (MessageCode::SpectatorReadOnly {}, "SPECTATOR_READ_ONLY"),
+        (MessageCode::ChatMuted {}, "CHAT_MUTED"),
+    ];
+    for (tag, (message, code)) in codes.iter().enumerate() {
+        assert_eq!(message.code(), *code);
+        let value = serde_json::to_value(message).unwrap();
+        assert_eq!(value.as_object().unwrap().keys().collect::<Vec<_>>(), [code]);
+        assert_eq!(bincode::serialize(message).unwrap()[..4], (tag as u32).to_le_bytes());
+        assert!(ENGLISH.iter().any(|(c, _, _)| c == code), "{} has no English text", code);
+    }
+
+    let names: Vec<&str> = ErrorCode::ALL.iter().map(|c| c.name()).collect();
+    assert_eq!(names, ["SERVER_FULL", "TOO_MANY_CONNECTIONS", "DISCONNECTED_RATE_LIMITED", "IDLE_TIMEOUT", "PROTOCOL_VIOLATION",
+                       "KICKED", "BANNED", "SHUTTING_DOWN", "KEEPALIVE_TIMEOUT", "STANDBY"]);
+    assert_eq!(ServerMessage::VARIANTS.last(), Some(&"StateDelta"));
+}
+
+#[test]
+fn english_matches_what_the_server_always_said() {
+    let table = MessageTable::english();
+    assert_eq!(table.render(&MessageCode::Welcome {}), "Welcome to Crux Server!");
+    assert_eq!(table.render(&MessageCode::TrailQueryRejected { reason: "trails are off".to_string() }),
+               "Trail query rejected: trails are off");
+    for code in ErrorCode::ALL {
+        assert_eq!(table.render_error(code), code.explanation());
+    }
+
+    let message = localized(MessageCode::RateLimited { retry_after_ms: 150 });
+    assert_eq!(serde_json::to_value(&message).unwrap(), json!({
+        "type": "Localized",
+        "message": {"RATE_LIMITED": {"retry_after_ms": 150}},
+        "text": "Slow down! You are sending messages too quickly.",
+    }));
+}
+
+#[test]
+fn a_custom_table_renders_in_its_own_words() {
+    let mut table = MessageTable::english();
+    table.set("WELCOME", "Willkommen auf dem Crux-Server!").unwrap();
+    table.set("RATE_LIMITED", "Langsam! Versuch es in {retry_after_ms} ms wieder {{bitte}}.").unwrap();
+    table.set("KICKED", "Von einem Admin entfernt.").unwrap();
+
+    assert_eq!(table.render(&MessageCode::Welcome {}), "Willkommen auf dem Crux-Server!");
+    assert_eq!(table.render(&MessageCode::RateLimited { retry_after_ms: 200 }), "Langsam! Versuch es in 200 ms wieder {bitte}.");
+    assert_eq!(table.render_error(ErrorCode::Kicked), "Von einem Admin entfernt.");
+    // Whatever is not translated stays English
+    assert_eq!(table.render(&MessageCode::FormatLocked {}), "The format can only be chosen by the first message.");
+    assert_eq!(table.render_error(ErrorCode::Banned), "Banned from this server.");
+
+    assert!(table.set("WELCOME_BACK", "Hallo").unwrap_err().contains("unknown message code"));
+    assert!(table.set("WELCOME", "Hallo {name}").unwrap_err().contains("unknown placeholder"));
+    assert!(table.set("INVALID_MESSAGE", "Fehler: {reason").is_err());
+    assert_eq!(table.template("WELCOME"), "Willkommen auf dem Crux-Server!");
+}
+
+#[tokio::test]
+async fn connections_render_with_their_table_and_old_clients_get_text() {
+    let addr = spawn_server(GameServer::new()).await;
+
+    let mut table = MessageTable::english();
+    table.set("WELCOME", "Bienvenue !").unwrap();
+    let mut conn = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    conn.set_message_table(table);
+    let welcome = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let ClientEvent::Localized { message, text } = conn.next_event().await {
+                return (message, text);
+            }
+        }
+    }).await.expect("timed out waiting for the welcome");
+    assert_eq!(welcome, (MessageCode::Welcome {}, "Bienvenue !".to_string()));
+
+    // Without CODES the welcome is the English text, as before codes
+    let (mut ws, _) = connect_async(format!("ws://{}/?caps=0", addr)).await.unwrap();
+    loop {
+        match next_message(&mut ws).await.expect("connection closed") {
+            Message::Text(text) => {
+                assert_eq!(text.as_str(), "Welcome to Crux Server!");
+                break;
+            }
+            Message::Binary(data) => assert!(!matches!(ServerMessage::from_bincode(&data), Ok(ServerMessage::Localized { .. }))),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::planet;
+use galavox::admin::run_line;
+use galavox::map::{render_ascii, render_png, MapSize};
+use galavox::protocol::{Color, GameState, Planet, Player, PlayerAppearance, Position};
+use galavox::server::GameServer;
+use galavox::world::WorldConfig;
+use galavox::worldgen::{generate_world, RingGenerator};
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("{}/tests/fixtures/maps/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn player(id: u32, x: f32, z: f32) -> Player {
+    Player {
+        id,
+        name: format!("p{}", id).into(),
+        level: 1,
+        position: Position { x, y: 0.0, z },
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("p"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+/// The seed 42 ring world with two players, one between the planets and
+/// one out at the edge.
+fn seeded_world() -> GameState {
+    let mut state = generate_world(&RingGenerator, &WorldConfig { seed: Some(42), ..WorldConfig::default() });
+    state.players = vec![player(1, 0.0, 0.0), player(2, 900.0, -100.0)];
+    state
+}
+
+#[test]
+fn the_ascii_map_matches_the_reference() {
+    // When the rendering changes on purpose, check the new map by eye and
+    // write it over the reference
+    assert_eq!(render_ascii(&seeded_world(), MapSize::ASCII), fixture("ring_seed_42.txt"));
+}
+
+#[test]
+fn maps_fit_their_size() {
+    let map = render_ascii(&seeded_world(), MapSize { width: 20, height: 8 });
+    let lines: Vec<&str> = map.lines().collect();
+    // The legend, the x axis, the frame and 8 rows
+    assert_eq!(lines.len(), 12);
+    assert!(lines[3..11].iter().all(|line| line.ends_with('|') && line.chars().count() == lines[2].chars().count()));
+    assert_eq!(lines[3..].concat().matches('@').count(), 2);
+
+    assert_eq!("120x40".parse::<MapSize>(), Ok(MapSize { width: 120, height: 40 }));
+    assert!("120".parse::<MapSize>().is_err());
+    assert!("2x40".parse::<MapSize>().is_err());
+    assert!("120x99999".parse::<MapSize>().is_err());
+}
+
+#[test]
+fn an_empty_world_still_draws() {
+    let state = GameState::new(vec![], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+    let map = render_ascii(&state, MapSize { width: 10, height: 10 });
+    let (legend, rest) = map.split_once('\n').unwrap();
+    assert!(legend.starts_with("0 planet(s), 0 player(s)"));
+    assert!(!rest.contains('#') && !rest.contains('@'));
+}
+
+#[test]
+fn png_maps_use_planet_colors() {
+    let red = Color { r: 200, g: 10, b: 10 };
+    let planet = Planet {
+        size: 200.0,
+        colors: [red.clone(), Color { r: 0, g: 0, b: 255 }, Color { r: 0, g: 0, b: 255 }],
+        position: Position { x: 0.0, y: 50.0, z: 0.0 },
+        ..planet(1, 0.0)
+    };
+    let state = GameState::new(vec![planet], vec![player(1, 400.0, 400.0)], Position { x: 0.0, y: 0.0, z: 0.0 });
+    let image = render_png(&state, MapSize { width: 128, height: 96 });
+    assert_eq!((image.width(), image.height()), (128, 96));
+
+    let pixels: Vec<[u8; 3]> = (0..image.height()).flat_map(|y| (0..image.width()).map(move |x| (x, y))).map(|(x, y)| image.pixel(x, y)).collect();
+    let count = |color: [u8; 3]| pixels.iter().filter(|p| **p == color).count();
+    assert!(count([red.r, red.g, red.b]) > 100);
+    assert_eq!(count([0, 0, 255]), 0);
+    assert_eq!(count([255, 255, 255]), 1);
+
+    let png = image.to_png();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..24], &[0, 0, 0, 128, 0, 0, 0, 96]);
+    assert_eq!(&png[png.len() - 12..png.len() - 4], b"\0\0\0\0IEND");
+    // Stored blocks: each row, its filter byte and the zlib framing
+    let raw = 96 * (1 + 128 * 3);
+    assert_eq!(png.len(), 8 + (12 + 13) + (12 + 2 + 5 + raw + 4) + 12);
+}
+
+#[test]
+fn the_admin_map_command_prints_the_world() {
+    let server = GameServer::new();
+    let map = run_line(&server, "map").unwrap();
+    assert!(map.starts_with(&format!("{} planet(s), 0 player(s)", server.get_state().planets.len())), "{}", map);
+    assert_eq!(map.lines().count(), 4 + MapSize::ASCII.height);
+    assert_eq!(run_line(&server, "map 30x10").unwrap().lines().count(), 14);
+    assert!(run_line(&server, "map big").is_err());
+}
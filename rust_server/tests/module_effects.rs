@@ -0,0 +1,128 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::planet;
+use galavox::config::ServerConfig;
+use galavox::discovery::DISCOVERY_XP;
+use galavox::module_effects::{credits_for, ModuleEffects, ModuleKind, Wallet, MINING_XP};
+use galavox::protocol::{GameState, LandError, MineError, Planet, Position, SellError};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use galavox::world::WorldConfig;
+use tokio::sync::Notify;
+
+fn at(x: f32, y: f32) -> Position {
+    Position { x, y, z: 0.0 }
+}
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn server_with(planets: Vec<Planet>) -> GameServer {
+    GameServer::new().with_world(GameState::new(planets, vec![], at(0.0, 5000.0)))
+}
+
+#[test]
+fn defense_planets_guard_their_neighbours_for_their_owner() {
+    let effects = ModuleEffects::default();
+    let planets = [Planet { module_type: 1, owner: Some(7), ..planet(1, 0.0) }, planet(2, 250.0), planet(3, 1000.0)];
+    assert_eq!(effects.defended_by(&planets[0], &planets, 8).map(|p| p.id), Some(1));
+    assert_eq!(effects.defended_by(&planets[1], &planets, 8).map(|p| p.id), Some(1));
+    assert_eq!(effects.defended_by(&planets[2], &planets, 8), None);
+    assert_eq!(effects.defended_by(&planets[1], &planets, 7), None);
+    // Unowned, it guards nothing
+    let unowned = [Planet { module_type: 1, ..planet(1, 0.0) }, planet(2, 250.0)];
+    assert_eq!(effects.defended_by(&unowned[1], &unowned, 8), None);
+}
+
+#[test]
+fn trade_planets_raise_the_sale_rate_nearby() {
+    let effects = ModuleEffects::default();
+    let planets = [Planet { module_type: 2, ..planet(1, 0.0) }];
+    assert_eq!(effects.sell_rate_at(&at(0.0, 50.0 + 200.0), &planets), 1.5);
+    assert_eq!(effects.sell_rate_at(&at(0.0, 50.0 + 201.0), &planets), 1.0);
+    assert_eq!(credits_for(7, 1.5), 10);
+    assert_eq!(credits_for(7, 1.0), 7);
+}
+
+#[test]
+fn research_planets_multiply_xp_earned_on_them() {
+    let effects = ModuleEffects { research_xp: 3.0, ..ModuleEffects::default() };
+    assert_eq!(effects.xp_while_landed(10, &Planet { module_type: 3, ..planet(1, 0.0) }), 30);
+    assert_eq!(effects.xp_while_landed(10, &Planet { module_type: 2, ..planet(1, 0.0) }), 10);
+}
+
+#[test]
+fn unknown_module_types_have_no_effect() {
+    let effects = ModuleEffects::default();
+    for module_type in [0, 4, 200] {
+        assert_eq!(ModuleKind::of(module_type), ModuleKind::Unknown);
+        let planets = [Planet { module_type, owner: Some(7), ..planet(1, 0.0) }, planet(2, 100.0)];
+        assert_eq!(effects.defended_by(&planets[1], &planets, 8), None);
+        assert_eq!(effects.sell_rate_at(&at(0.0, 60.0), &planets), effects.sell_rate);
+        assert_eq!(effects.xp_while_landed(10, &planets[0]), 10);
+    }
+}
+
+#[test]
+fn only_the_owner_lands_in_a_defense_zone() {
+    let server = server_with(vec![
+        Planet { module_type: 1, resources: 100, capacity: 100, ..planet(1, 0.0) },
+        Planet { resources: 100, capacity: 100, ..planet(2, 250.0) },
+    ]);
+    let (owner, owner_player, _) = server.add_player(connection(), "Owner".to_string()).unwrap();
+    let (visitor, _, _) = server.add_player(connection(), "Visitor".to_string()).unwrap();
+    galavox::admin::run_line(&server, &format!("planet set 1 owner {}", owner_player.id)).unwrap();
+
+    server.move_player(visitor, at(250.0, 60.0));
+    assert_eq!(server.land(visitor, 2), Err(LandError::Defended { planet_id: 1, owner: owner_player.id }));
+    assert!(!server.is_landed(visitor));
+    server.move_player(owner, at(250.0, 60.0));
+    assert_eq!(server.land(owner, 2), Ok(at(250.0, 50.0)));
+}
+
+#[test]
+fn mined_resources_sell_better_near_trade_planets() {
+    let server = server_with(vec![
+        Planet { module_type: 3, resources: 100, capacity: 100, ..planet(1, 0.0) },
+        Planet { module_type: 2, resources: 100, capacity: 100, ..planet(2, 3000.0) },
+    ]);
+    let (me, _, _) = server.add_player(connection(), "Miner".to_string()).unwrap();
+    assert_eq!(server.mine(me, 5), Err(MineError::NotLanded));
+    server.move_player(me, at(0.0, 60.0));
+    server.land(me, 1).unwrap();
+    assert_eq!(server.mine(me, 0), Err(MineError::ZeroAmount));
+    assert_eq!(server.mine(me, 60), Ok((1, 60, 60)));
+    assert_eq!(server.mine(me, 60), Ok((1, 40, 100)));
+    assert_eq!(server.mine(me, 1), Err(MineError::Depleted { planet_id: 1 }));
+    let miner = server.get_state().players.into_iter().find(|p| &*p.name == "Miner").unwrap();
+    // Planet 1 is a Research planet, and was discovered on the way
+    assert_eq!(miner.xp, DISCOVERY_XP + 100 * MINING_XP * 2);
+    assert_eq!(server.get_state().planets[0].resources, 0);
+
+    assert_eq!(server.sell(me, 101), Err(SellError::NotEnough { cargo: 100 }));
+    assert_eq!(server.sell(me, 10), Ok((10, 10)));
+    server.take_off(me).unwrap();
+    server.move_player(me, at(3000.0, 100.0));
+    assert_eq!(server.sell(me, 10), Ok((15, 25)));
+    assert_eq!(server.wallet("MINER"), Wallet { cargo: 80, credits: 25 });
+}
+
+#[test]
+fn module_numbers_come_from_the_config() {
+    let config = ServerConfig::from_toml("[modules]\ndefense_radius = 50.0\ntrade_bonus = 2.0\n").unwrap();
+    let modules = config.world_config().modules;
+    assert_eq!(modules, ModuleEffects { defense_radius: 50.0, trade_bonus: 2.0, ..ModuleEffects::default() });
+    assert_eq!(WorldConfig::default().modules, ModuleEffects::default());
+    // Checked with the rest, once the flags are applied
+    let mut negative = ServerConfig::from_toml("[modules]\nsell_rate = -1.0\n").unwrap();
+    assert_eq!(negative.apply_args(&[]), Err("modules: sell_rate must be zero or positive".to_string()));
+    assert!(ServerConfig::from_toml("[modules]\nbogus = 1.0\n").is_err());
+}
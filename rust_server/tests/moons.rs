@@ -0,0 +1,79 @@
+use galavox::protocol::{Color, Moon, Position, ServerMessage, MAX_MOONS};
+use galavox::server::GameServer;
+use galavox::world::{load_world, WorldConfig};
+
+fn seeded(seed: u64, moon_chance: f64) -> galavox::protocol::GameState {
+    GameServer::new().with_world_config(WorldConfig { seed: Some(seed), moon_chance, ..WorldConfig::default() }).get_state()
+}
+
+#[test]
+fn a_known_seed_always_gives_the_same_moons() {
+    let state = seeded(42, 0.5);
+    let counts: Vec<usize> = state.planets.iter().map(|p| p.moons.len()).collect();
+    assert_eq!(counts, [3, 0, 2, 0, 0, 0, 1, 0, 0, 0]);
+
+    let moon = &state.planets[0].moons[0];
+    assert_eq!(moon.color, Color { r: 0, g: 209, b: 237 });
+    for (actual, expected) in [
+        (moon.size, 23.342653),
+        (moon.orbit_radius, 95.58601),
+        (moon.angular_speed, 0.19971417),
+        (moon.phase, 3.18023),
+    ] {
+        assert!((actual - expected).abs() < 1e-4, "{} != {}", actual, expected);
+    }
+}
+
+#[test]
+fn moon_chance_bounds_the_count() {
+    assert!(seeded(7, 0.0).planets.iter().all(|p| p.moons.is_empty()));
+    assert!(seeded(7, 1.0).planets.iter().all(|p| p.moons.len() == MAX_MOONS));
+}
+
+#[test]
+fn moons_orbit_clear_of_their_planet_and_each_other() {
+    for planet in seeded(3, 1.0).planets {
+        let mut inner_edge = planet.size / 2.0;
+        for moon in &planet.moons {
+            assert!(moon.orbit_radius - moon.size / 2.0 > inner_edge, "moon of planet {} overlaps", planet.id);
+            inner_edge = moon.orbit_radius + moon.size / 2.0;
+        }
+    }
+}
+
+#[test]
+fn moon_position_follows_the_orbit() {
+    let moon = Moon {
+        size: 5.0,
+        color: Color { r: 0, g: 0, b: 0 },
+        orbit_radius: 100.0,
+        angular_speed: std::f32::consts::FRAC_PI_2,
+        phase: 0.0,
+    };
+    let center = Position { x: 10.0, y: 20.0, z: 30.0 };
+    let at = |ms| moon.position_at(&center, ms);
+    assert!((at(0).x - 110.0).abs() < 1e-3 && (at(0).z - 30.0).abs() < 1e-3);
+    // A quarter turn per second, from +x towards +z
+    assert!((at(1000).x - 10.0).abs() < 1e-3 && (at(1000).z - 130.0).abs() < 1e-3);
+    assert_eq!(at(1000).y, 20.0);
+}
+
+#[test]
+fn moons_survive_both_wire_formats() {
+    let state = seeded(42, 1.0);
+    let message = ServerMessage::State { tick: 1, server_time_ms: 0, state };
+    assert_eq!(ServerMessage::from_bincode(&message.to_bincode().unwrap()).unwrap(), message);
+    assert_eq!(serde_json::from_str::<ServerMessage>(&serde_json::to_string(&message).unwrap()).unwrap(), message);
+}
+
+#[test]
+fn worlds_saved_before_moons_still_load() {
+    let saved = r#"{
+        "planets": [
+            {"id": 0, "size": 50.0, "colors": [{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3}], "module_type": 0, "position": {"x":0.0,"y":0.0,"z":0.0}}
+        ],
+        "players": [],
+        "initial_player_location": {"x":0.0,"y":0.0,"z":0.0}
+    }"#;
+    assert_eq!(load_world(saved).unwrap().planets[0].moons, vec![]);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{connect, spawn_server, wait_for_self};
+use futures_util::SinkExt;
+use galavox::protocol::{
+    decode_position_update, encode_motion_update, Position, PositionUpdate, IDENTITY_ROTATION,
+    MOTION_FRAME_LEN,
+};
+use galavox::server::GameServer;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const POS: Position = Position { x: 1.0, y: -2.0, z: 0.5 };
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn motion_frame_golden_layout() {
+    let frame = encode_motion_update(0x01020304, &POS, &[10.0, 0.0, -4.0], &IDENTITY_ROTATION);
+    assert_eq!(frame.len(), MOTION_FRAME_LEN);
+    assert_eq!(
+        hex(&frame),
+        concat!(
+            "04030201",                          //  0: seq
+            "0000803f000000c00000003f",          //  4: position
+            "0000204100000000000080c0",          // 16: velocity
+            "0000000000000000000000000000803f",  // 28: rotation
+        )
+    );
+
+    let update = decode_position_update(&frame).unwrap();
+    assert_eq!(update.seq, Some(0x01020304));
+    assert_eq!(update.position, POS);
+    assert_eq!(update.velocity, [10.0, 0.0, -4.0]);
+    assert_eq!(update.rotation, IDENTITY_ROTATION);
+}
+
+#[test]
+fn legacy_frames_default_velocity_and_rotation() {
+    let frame = encode_motion_update(9, &POS, &[1.0, 2.0, 3.0], &[0.0, 1.0, 0.0, 0.0]);
+    for short in [&frame[..16], &frame[4..16]] {
+        let update = decode_position_update(short).unwrap();
+        assert_eq!(update.position, POS);
+        assert_eq!(update.velocity, [0.0; 3]);
+        assert_eq!(update.rotation, IDENTITY_ROTATION);
+    }
+}
+
+#[test]
+fn validation_rejects_bad_motion() {
+    let update = |velocity: [f32; 3], rotation: [f32; 4]| PositionUpdate { seq: Some(1), position: POS, velocity, rotation };
+
+    assert!(update([300.0, 0.0, 400.0], IDENTITY_ROTATION).validate(500.0).is_ok());
+    assert!(update([300.0, 0.0, 401.0], IDENTITY_ROTATION).validate(500.0).is_err());
+    assert!(update([0.0; 3], [0.0, 0.0, 0.0, 0.0]).validate(500.0).is_err());
+    assert!(update([0.0; 3], [0.0, 0.0, 0.0, 1.02]).validate(500.0).is_err());
+    assert!(update([0.0; 3], [0.5, 0.5, 0.5, 0.5]).validate(500.0).is_ok());
+    assert!(update([f32::NAN, 0.0, 0.0], IDENTITY_ROTATION).validate(500.0).is_err());
+}
+
+#[tokio::test]
+async fn broadcasts_include_motion_and_skip_invalid_updates() {
+    let addr = spawn_server(GameServer::new().with_max_speed(100.0)).await;
+    let mut client = connect(addr).await;
+
+    let rotation = [0.0, 0.70710677, 0.0, 0.70710677];
+    let frame = encode_motion_update(1, &POS, &[3.0, 0.0, 4.0], &rotation);
+    client.send(Message::Binary(frame.into())).await.unwrap();
+
+    let me = wait_for_self(&mut client, |p| p.last_processed_seq == 1).await;
+    assert_eq!(me.velocity, [3.0, 0.0, 4.0]);
+    assert_eq!(me.rotation, rotation);
+
+    // Too fast: dropped without advancing the acknowledged sequence
+    let frame = encode_motion_update(2, &POS, &[0.0, 0.0, 150.0], &rotation);
+    client.send(Message::Binary(frame.into())).await.unwrap();
+    let frame = encode_motion_update(3, &POS, &[0.0; 3], &IDENTITY_ROTATION);
+    client.send(Message::Binary(frame.into())).await.unwrap();
+
+    let me = wait_for_self(&mut client, |p| p.last_processed_seq != 1).await;
+    assert_eq!(me.last_processed_seq, 3);
+    assert_eq!(me.velocity, [0.0; 3]);
+}
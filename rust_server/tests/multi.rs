@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use galavox::client::{ClientEvent, Connection};
+use galavox::multi::{MultiConnection, MultiEvent, MultiOptions};
+use galavox::server::GameServer;
+use tokio::net::TcpListener;
+
+/// Keeps a player connected, answering keepalives, until the server goes.
+fn keep_playing(mut connection: Connection) {
+    tokio::spawn(async move {
+        while !matches!(connection.next_event().await, ClientEvent::Disconnected { .. }) {}
+    });
+}
+
+/// The next event for which `check` holds, within five seconds.
+async fn next_matching(multi: &mut MultiConnection, check: impl Fn(&MultiEvent) -> bool) -> MultiEvent {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = multi.next_event().await.expect("every source stopped");
+            if check(&event) {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("no matching event")
+}
+
+#[tokio::test]
+async fn one_server_going_away_does_not_stall_the_other() {
+    let a = GameServer::new().spawn(TcpListener::bind("127.0.0.1:0").await.unwrap()).unwrap();
+    let b = GameServer::new().spawn(TcpListener::bind("127.0.0.1:0").await.unwrap()).unwrap();
+    let (url_a, url_b) = (format!("ws://{}", a.local_addr()), format!("ws://{}", b.local_addr()));
+    keep_playing(Connection::connect(&url_a, Some("Ada")).await.unwrap());
+    keep_playing(Connection::connect(&url_b, Some("Bob")).await.unwrap());
+
+    let options = MultiOptions { reconnect: Some(Duration::from_millis(50)), ..MultiOptions::default() };
+    let mut multi = MultiConnection::open(&[&url_a, &url_b, &url_a], options);
+    assert_eq!(multi.sources().collect::<Vec<_>>(), [url_a.as_str(), url_b.as_str()]);
+    for url in [&url_a, &url_b] {
+        next_matching(&mut multi, |e| &*e.source == url.as_str() && matches!(e.event, ClientEvent::StateSnapshot { .. })).await;
+    }
+    assert_eq!(multi.connected(), 2);
+    assert_eq!(multi.total_players(), 2);
+
+    b.shutdown().await.unwrap();
+    let gone = next_matching(&mut multi, |e| matches!(e.event, ClientEvent::Disconnected { .. })).await;
+    assert_eq!(&*gone.source, url_b.as_str());
+    // B keeps failing to come back while A carries on
+    next_matching(&mut multi, |e| &*e.source == url_b.as_str()
+        && matches!(&e.event, ClientEvent::Disconnected { reason, .. } if reason.starts_with("cannot connect"))).await;
+    for _ in 0..3 {
+        next_matching(&mut multi, |e| &*e.source == url_a.as_str() && matches!(e.event, ClientEvent::StateSnapshot { .. })).await;
+    }
+    let status_b = multi.status(&url_b).unwrap();
+    assert!(!status_b.connected && !status_b.stopped);
+    assert_eq!(status_b.connects, 1);
+    assert_eq!((multi.connected(), multi.total_players()), (1, 1));
+
+    multi.close().await;
+    a.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn a_source_that_may_not_reconnect_stops() {
+    // Nothing listens there once the listener is dropped
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let url = format!("ws://{}", addr);
+    let mut multi = MultiConnection::open(&[&url], MultiOptions { reconnect: None, ..MultiOptions::default() });
+    let event = next_matching(&mut multi, |_| true).await;
+    assert!(matches!(&event.event, ClientEvent::Disconnected { code: None, reason } if reason.starts_with("cannot connect")), "{:?}", event);
+    assert_eq!(multi.next_event().await, None);
+    assert!(multi.status(&url).unwrap().stopped);
+    assert!(!multi.send(&url, galavox::protocol::ClientMessage::Respawn {}));
+}
@@ -0,0 +1,251 @@
+mod common;
+
+use common::spawn_server;
+use galavox::admin::{parse_command, run_line, AdminCommand};
+use galavox::chat_receipts::ChatError;
+use galavox::client::{ChatReceipt, ClientEvent, Connection, PendingChat};
+use galavox::mutes::{format_duration, parse_duration, MuteKind, MuteList};
+use galavox::protocol::{Capabilities, ChatLine, ClientMessage, MessageCode, Position, ServerMessage};
+use galavox::server::{Connection as ServerConnection, GameServer};
+use galavox::stats::ConnectionStats;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+const MINUTE_MS: u64 = 60_000;
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("galavox-{}-{}.mutes.json", name, std::process::id()))
+}
+
+fn connection(port: u16) -> ServerConnection {
+    ServerConnection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+async fn join(addr: SocketAddr, name: &str, capabilities: Capabilities) -> Connection {
+    let mut conn = Connection::connect_with_capabilities(&format!("ws://{}", addr), Some(name), false, capabilities).await.unwrap();
+    while !matches!(conn.next_event().await, ClientEvent::Joined { .. }) {}
+    conn
+}
+
+/// The next event matching `f`, skipping the rest.
+async fn next_matching<T>(conn: &mut Connection, mut f: impl FnMut(ClientEvent) -> Option<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = f(conn.next_event().await) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an event")
+}
+
+/// Reads events until `pending` resolves.
+async fn settle(conn: &mut Connection, mut pending: PendingChat) -> ChatReceipt {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            tokio::select! {
+                receipt = &mut pending => return receipt,
+                _ = conn.next_event() => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for a chat receipt")
+}
+
+/// The chat history as `conn` is told it.
+async fn history(conn: &mut Connection) -> Vec<ChatLine> {
+    conn.send(&ClientMessage::MoreHistory { before_timestamp: u64::MAX, limit: 100 }).await.unwrap();
+    next_matching(conn, |e| match e {
+        ClientEvent::Message(ServerMessage::ChatHistory { messages }) => Some(messages),
+        _ => None,
+    }).await
+}
+
+#[test]
+fn commands_and_durations_parse() {
+    assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+    assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7_200)));
+    assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86_400)));
+    for invalid in ["", "m", "10", "0m", "-1m", "1.5h", "10w", "10é"] {
+        assert!(parse_duration(invalid).is_err(), "{:?} parsed", invalid);
+    }
+    assert_eq!(format_duration(Duration::from_millis(570_500)), "9m 31s");
+    assert_eq!(format_duration(Duration::from_secs(93_600)), "1d 2h");
+
+    assert_eq!(parse_command("mute Ada 10m"), Ok(AdminCommand::Mute { name: "Ada".to_string(), kind: MuteKind::Mute, duration: Some(Duration::from_secs(600)) }));
+    assert_eq!(parse_command("shadowmute Ada"), Ok(AdminCommand::Mute { name: "Ada".to_string(), kind: MuteKind::Shadow, duration: None }));
+    assert_eq!(parse_command("unmute Ada"), Ok(AdminCommand::Unmute { name: "Ada".to_string() }));
+    assert_eq!(parse_command("mutes"), Ok(AdminCommand::ListMutes));
+    assert!(parse_command("mute").is_err());
+    assert!(parse_command("mute Ada soon").is_err());
+    assert!(parse_command("shadowmute Ada 1m 2m").is_err());
+}
+
+#[test]
+fn mutes_expire_on_time() {
+    let start = 1_700_000_000_000;
+    let mut mutes = MuteList::new();
+    mutes.mute("Ada", MuteKind::Mute, Some(Duration::from_secs(600)), start).unwrap();
+    mutes.mute("Bob", MuteKind::Shadow, None, start).unwrap();
+
+    let ada = mutes.muted("ADA", start + MINUTE_MS).unwrap();
+    assert_eq!(ada.remaining_ms(start + MINUTE_MS), Some(9 * MINUTE_MS));
+    assert_eq!(ada.describe(start + MINUTE_MS), "Ada muted for 9m");
+    assert!(mutes.muted("Ada", start + 10 * MINUTE_MS - 1).is_some());
+    assert!(mutes.muted("Ada", start + 10 * MINUTE_MS).is_none());
+    assert_eq!(mutes.in_force(start + 10 * MINUTE_MS).unwrap().len(), 1);
+    assert_eq!(mutes.len(), 1);
+
+    // Bob's lasts until lifted; lifting an expired or missing mute says so
+    let later = start + 365 * 24 * 60 * MINUTE_MS;
+    assert_eq!(mutes.muted("bob", later).unwrap().describe(later), "Bob shadow-muted until unmuted");
+    assert_eq!(mutes.unmute("Ada", later).unwrap(), None);
+    assert_eq!(mutes.unmute("Bob", later).unwrap().map(|mute| mute.kind), Some(MuteKind::Shadow));
+    assert!(mutes.is_empty());
+}
+
+#[test]
+fn mutes_are_saved_and_loaded() {
+    let path = temp_path("saved");
+    let _ = std::fs::remove_file(&path);
+    let start = 1_700_000_000_000;
+    let mut mutes = MuteList::load(&path).unwrap();
+    mutes.mute("Ada", MuteKind::Mute, Some(Duration::from_secs(60)), start).unwrap();
+    mutes.mute("Bob", MuteKind::Shadow, None, start).unwrap();
+
+    let mut loaded = MuteList::load(&path).unwrap();
+    assert_eq!(loaded.to_file_text(), mutes.to_file_text());
+    assert!(loaded.muted("ada", start).is_some());
+    // Reading the list after Ada's ran out drops it from the file
+    loaded.in_force(start + MINUTE_MS).unwrap();
+    assert_eq!(MuteList::load(&path).unwrap().len(), 1);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn mutes_move_with_a_rename() {
+    let path = temp_path("rename");
+    let _ = std::fs::remove_file(&path);
+    let server = GameServer::new().with_mutes(MuteList::load(&path).unwrap());
+    let (ada, _, _) = server.add_player(connection(4000), "Ada".to_string()).unwrap();
+    let (bob, _, _) = server.add_player(connection(4001), "Bob".to_string()).unwrap();
+    run_line(&server, "mute Ada 1h").unwrap();
+    run_line(&server, "shadowmute Dora").unwrap();
+
+    assert_eq!(server.rename_player(ada, "Carol"), Ok(Some("Ada".to_string())));
+    assert_eq!(server.chat_mute(ada).map(|mute| mute.name), Some("Carol".to_string()));
+    assert_eq!(run_line(&server, "mutes").unwrap(), "Carol muted for 1h\nDora shadow-muted until unmuted");
+    // Ada's name is free of it, for whoever takes it next
+    assert_eq!(server.rename_player(bob, "ada"), Ok(Some("Bob".to_string())));
+    assert!(server.chat_mute(bob).is_none());
+    // A name muted in its own right keeps its own mute
+    assert_eq!(server.rename_player(ada, "Dora"), Ok(Some("Carol".to_string())));
+    assert_eq!(server.chat_mute(ada).map(|mute| mute.kind), Some(MuteKind::Shadow));
+    // Saved as they moved
+    let names: Vec<String> = MuteList::load(&path).unwrap().in_force(0).unwrap().into_iter().map(|mute| mute.name).collect();
+    assert_eq!(names, ["Carol", "Dora"]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn muted_players_are_refused_across_reconnects() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    assert_eq!(run_line(&server, "mute Ada 1h").unwrap(), "Ada muted for 1h");
+    assert_eq!(run_line(&server, "mutes").unwrap(), "Ada muted for 1h");
+
+    let mut ada = join(addr, "Ada", Capabilities::DEFAULT).await;
+    let receipt = ada.send_chat_tracked("hello?").await.unwrap();
+    let ChatReceipt::Rejected(ChatError::Muted { remaining_ms: Some(ms) }) = settle(&mut ada, receipt).await else { panic!("chat not refused") };
+    assert!(ms > 59 * MINUTE_MS);
+    // Moving is not chatting
+    let target = Position { x: 30.0, y: 0.0, z: 0.0 };
+    ada.send_position(target.clone()).await.unwrap();
+    next_matching(&mut ada, |e| match e {
+        ClientEvent::StateSnapshot { state, .. } => state.players.iter().any(|p| &*p.name == "Ada" && p.position == target).then_some(()),
+        _ => None,
+    }).await;
+    ada.close().await.unwrap();
+
+    // Back under another spelling, and without receipts
+    let older = Capabilities(Capabilities::DEFAULT.0 & !Capabilities::CHAT_ACKS.0);
+    let mut ada = join(addr, "ADA", older).await;
+    ada.send_chat("hello?").await.unwrap();
+    next_matching(&mut ada, |e| matches!(e, ClientEvent::Localized { message: MessageCode::ChatMuted {}, .. }).then_some(())).await;
+    assert!(history(&mut ada).await.is_empty());
+
+    assert_eq!(run_line(&server, "unmute ada").unwrap(), "unmuted Ada");
+    assert_eq!(run_line(&server, "mutes").unwrap(), "no mutes");
+    ada.send_chat("hello!").await.unwrap();
+    next_matching(&mut ada, |e| matches!(e, ClientEvent::Chat { text } if text == "hello!").then_some(())).await;
+}
+
+#[tokio::test]
+async fn mutes_outlast_a_restart() {
+    let path = temp_path("restart");
+    let _ = std::fs::remove_file(&path);
+    let server = GameServer::new().with_mutes(MuteList::load(&path).unwrap());
+    run_line(&server, "mute Ada").unwrap();
+    drop(server);
+
+    let addr = spawn_server(GameServer::new().with_mutes(MuteList::load(&path).unwrap())).await;
+    let mut ada = join(addr, "Ada", Capabilities::DEFAULT).await;
+    let receipt = ada.send_chat_tracked("hello?").await.unwrap();
+    assert_eq!(settle(&mut ada, receipt).await, ChatReceipt::Rejected(ChatError::Muted { remaining_ms: None }));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn shadow_muted_chats_only_reach_their_sender() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = join(addr, "Ada", Capabilities::DEFAULT).await;
+    let mut bob = join(addr, "Bob", Capabilities::DEFAULT).await;
+    let mut events = server.subscribe_events();
+
+    let heard = ada.send_chat_tracked("before").await.unwrap();
+    assert!(matches!(settle(&mut ada, heard).await, ChatReceipt::Accepted { server_seq: 1, .. }));
+    run_line(&server, "shadowmute Ada 10m").unwrap();
+    let unheard = ada.send_chat_tracked("after").await.unwrap();
+    ada.send_chat("again").await.unwrap();
+    // To Ada it went as any chat does
+    assert!(matches!(settle(&mut ada, unheard).await, ChatReceipt::Accepted { server_seq: 2, .. }));
+    next_matching(&mut ada, |e| matches!(e, ClientEvent::Chat { text } if text == "again").then_some(())).await;
+    let sender = next_matching(&mut ada, |e| match e {
+        ClientEvent::Message(ServerMessage::Chatted { line, .. }) if line.text == "again" => Some(line.sender),
+        _ => None,
+    }).await;
+    assert_eq!(sender, "Ada");
+
+    // Bob, unmuted, is heard on the same path meanwhile
+    bob.send_chat("bob too").await.unwrap();
+
+    // Bob, watching, hears his own chat and Ada's first, never her later ones
+    let mut heard = Vec::new();
+    next_matching(&mut bob, |e| match e {
+        ClientEvent::Message(ServerMessage::Chatted { line, .. }) => {
+            heard.push(line.text);
+            heard.ends_with(&["bob too".to_string()]).then_some(())
+        }
+        _ => None,
+    }).await;
+    assert_eq!(heard, ["before", "bob too"]);
+    let texts: Vec<String> = history(&mut bob).await.into_iter().map(|line| line.text).collect();
+    assert_eq!(texts, ["before", "bob too"]);
+    let mut chatted = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let galavox::events::GameEvent::Chatted { text, .. } = event {
+            chatted.push(text);
+        }
+    }
+    assert_eq!(chatted, ["before", "bob too"]);
+}
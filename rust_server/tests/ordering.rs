@@ -0,0 +1,117 @@
+use futures_util::{SinkExt, StreamExt};
+use galavox::client::{ClientEvent, Connection, EventDecoder};
+use galavox::ordering::Sequencer;
+use galavox::protocol::{GameState, Player, PlayerAppearance, Position, ServerMessage};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn at(x: f32) -> Position {
+    Position { x, y: 0.0, z: 0.0 }
+}
+
+fn me(x: f32, last_processed_seq: u32) -> Player {
+    Player {
+        id: 1,
+        name: "Ada".into(),
+        level: 1,
+        position: at(x),
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq,
+        idle: false,
+        appearance: PlayerAppearance::for_name("Ada"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+fn state(tick: u64, x: f32) -> ServerMessage {
+    ServerMessage::State { tick, server_time_ms: tick * 50, state: GameState::new(vec![], vec![me(x, 1)], at(0.0)) }
+}
+
+fn correction(tick: u64, x: f32) -> ServerMessage {
+    ServerMessage::PositionCorrection { position: at(x), seq: Some(1), tick }
+}
+
+fn frame(message: &ServerMessage) -> Message {
+    Message::Binary(message.to_bincode().unwrap().into())
+}
+
+/// Where the decoded events leave our ship, as a client drawing them would.
+fn position_after(events: &[ClientEvent]) -> Option<Position> {
+    events.iter().rev().find_map(|event| match event {
+        ClientEvent::StateSnapshot { state, .. } => Some(state.players[0].position.clone()),
+        ClientEvent::Message(ServerMessage::PositionCorrection { position, .. }) => Some(position.clone()),
+        _ => None,
+    })
+}
+
+#[test]
+fn sequencer_turns_away_anything_older_than_applied() {
+    let mut sequencer = Sequencer::new();
+    assert!(sequencer.admit_state(5));
+    assert!(sequencer.admit_state(5), "the same tick again is a duplicate, not a step back");
+    assert!(!sequencer.admit_state(4));
+    assert!(sequencer.admit_correction(7));
+    // A State of the correction's tick may have been built before it
+    assert!(!sequencer.admit_state(6));
+    assert!(!sequencer.admit_state(7));
+    assert!(sequencer.admit_state(8));
+    assert!(!sequencer.admit_correction(7), "State 8 already has the corrected position");
+    assert_eq!(sequencer.newest(), Some(8));
+}
+
+#[test]
+fn stale_snapshots_after_a_correction_are_dropped() {
+    let mut decoder = EventDecoder::new();
+    let mut events = decoder.decode(frame(&state(5, 0.0)));
+    events.extend(decoder.decode(frame(&correction(7, 100.0))));
+    assert_eq!(position_after(&events), Some(at(100.0)));
+
+    // Built before the correction, delivered after it
+    for stale in [state(6, 5_000.0), state(7, 5_000.0), ServerMessage::Resync { from_tick: 5, tick: 6, diffs: vec![] }] {
+        assert_eq!(decoder.decode(frame(&stale)), vec![], "{:?}", stale);
+    }
+    let batched = ServerMessage::Batch { messages: galavox::protocol::Batched(vec![correction(6, 5_000.0), ServerMessage::PlayerIdle { player_id: 2 }]) };
+    assert_eq!(decoder.decode(frame(&batched)), vec![ClientEvent::Message(ServerMessage::PlayerIdle { player_id: 2 })]);
+
+    let events = decoder.decode(frame(&state(8, 100.0)));
+    assert_eq!(position_after(&events), Some(at(100.0)));
+}
+
+#[tokio::test]
+async fn connections_keep_the_corrected_position() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+        ws.send(frame(&state(5, 0.0))).await.unwrap();
+        ws.send(frame(&ServerMessage::Joined { player_id: 1, spawn: at(0.0), spawn_planet_id: None })).await.unwrap();
+        // Wait for the update to correct, then send a snapshot from before the correction after it
+        while !matches!(ws.next().await, Some(Ok(Message::Binary(_)))) {}
+        ws.send(frame(&correction(7, 100.0))).await.unwrap();
+        ws.send(frame(&state(6, 5_000.0))).await.unwrap();
+        ws.send(frame(&state(8, 100.0))).await.unwrap();
+        ws.close(None).await.unwrap();
+    });
+
+    let mut connection = Connection::connect(&url, Some("Ada")).await.unwrap();
+    connection.send_position(at(5_000.0)).await.unwrap();
+    let mut ticks = Vec::new();
+    loop {
+        match connection.next_event().await {
+            ClientEvent::StateSnapshot { tick, state, .. } => {
+                if tick > 5 {
+                    assert_eq!(state.players[0].position, at(100.0));
+                }
+                ticks.push(tick);
+            }
+            ClientEvent::Disconnected { .. } => break,
+            _ => {}
+        }
+    }
+    assert_eq!(ticks, vec![5, 8]);
+    assert_eq!(connection.pending_inputs().predicted(), Some(&at(100.0)));
+}
@@ -0,0 +1,83 @@
+use galavox::client::ClientEvent;
+use galavox::output::{
+    client_error_record, connected_record, connecting_record, event_record, is_error, reconnecting_record,
+};
+use galavox::protocol::{GameState, Player, PlayerAppearance, Position, ServerMessage};
+
+const TS: u64 = 1_760_000_000_000;
+
+fn line(event: ClientEvent) -> String {
+    event_record(&event, TS).expect("a record").to_string()
+}
+
+fn player(id: u32) -> Player {
+    Player {
+        id,
+        name: "Ada".into(),
+        level: 1,
+        position: Position { x: 0.0, y: 0.0, z: 0.0 },
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("Ada"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+#[test]
+fn snapshots_and_deltas_are_summarized() {
+    let state = GameState::new(vec![], vec![player(1), player(2)], Position { x: 0.0, y: 0.0, z: 0.0 });
+    assert_eq!(line(ClientEvent::StateSnapshot { tick: 12, server_time_ms: 1200, state }),
+               r#"{"planets":0,"players":2,"server_time_ms":1200,"tick":12,"ts_ms":1760000000000,"type":"state"}"#);
+    assert_eq!(line(ClientEvent::Delta { from_tick: 3, tick: 5, diffs: vec![] }),
+               r#"{"diffs":0,"from_tick":3,"tick":5,"ts_ms":1760000000000,"type":"delta"}"#);
+}
+
+#[test]
+fn players_and_chat() {
+    let spawn = Position { x: 1.0, y: 2.0, z: 3.0 };
+    assert_eq!(line(ClientEvent::Joined { player_id: 4, spawn, spawn_planet_id: Some(9) }),
+               r#"{"player_id":4,"spawn":{"x":1.0,"y":2.0,"z":3.0},"spawn_planet_id":9,"ts_ms":1760000000000,"type":"joined"}"#);
+    assert_eq!(line(ClientEvent::PlayerJoined { player: player(4) }),
+               r#"{"name":"Ada","player_id":4,"ts_ms":1760000000000,"type":"player_joined"}"#);
+    assert_eq!(line(ClientEvent::PlayerLeft { player_id: 4 }),
+               r#"{"player_id":4,"ts_ms":1760000000000,"type":"player_left"}"#);
+    assert_eq!(line(ClientEvent::Chat { text: "hi".into() }),
+               r#"{"text":"hi","ts_ms":1760000000000,"type":"chat"}"#);
+    assert_eq!(line(ClientEvent::Notice { text: "slow down".into() }),
+               r#"{"text":"slow down","ts_ms":1760000000000,"type":"notice"}"#);
+    assert_eq!(line(ClientEvent::Announcement { text: "welcome".into() }),
+               r#"{"text":"welcome","ts_ms":1760000000000,"type":"announcement"}"#);
+    assert_eq!(line(ClientEvent::WorldReset { seed: 42 }),
+               r#"{"seed":42,"ts_ms":1760000000000,"type":"world_reset"}"#);
+}
+
+#[test]
+fn errors_and_other_messages() {
+    let error = ClientEvent::Message(ServerMessage::Error { code: 4003, message: "full".into() });
+    assert!(is_error(&error));
+    assert_eq!(line(error), r#"{"code":4003,"message":"full","ts_ms":1760000000000,"type":"error"}"#);
+
+    let idle = ClientEvent::Message(ServerMessage::PlayerIdle { player_id: 2 });
+    assert!(!is_error(&idle));
+    assert_eq!(line(idle), r#"{"message":{"player_id":2,"type":"PlayerIdle"},"ts_ms":1760000000000,"type":"message"}"#);
+
+    assert_eq!(line(ClientEvent::Disconnected { code: None, reason: String::new() }),
+               r#"{"code":null,"reason":"","ts_ms":1760000000000,"type":"disconnected"}"#);
+    assert!(event_record(&ClientEvent::Message(ServerMessage::TimeSync { client_time_ms: 1, server_time_ms: 2, tick: 3 }), TS).is_none());
+}
+
+#[test]
+fn lifecycle() {
+    assert_eq!(connecting_record("ws://localhost:8080", TS).to_string(),
+               r#"{"ts_ms":1760000000000,"type":"connecting","url":"ws://localhost:8080"}"#);
+    assert_eq!(connected_record("ws://localhost:8080", TS).to_string(),
+               r#"{"ts_ms":1760000000000,"type":"connected","url":"ws://localhost:8080"}"#);
+    assert_eq!(reconnecting_record(5000, TS).to_string(),
+               r#"{"delay_ms":5000,"ts_ms":1760000000000,"type":"reconnecting"}"#);
+    assert_eq!(client_error_record("usage: tp <planet id>", TS).to_string(),
+               r#"{"message":"usage: tp <planet id>","ts_ms":1760000000000,"type":"client_error"}"#);
+}
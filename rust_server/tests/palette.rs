@@ -0,0 +1,85 @@
+use galavox::config::ServerConfig;
+use galavox::palette::{jitter, Palette, PlanetPalettes, BUILTIN, JITTER};
+use galavox::protocol::Color;
+use galavox::world::WorldConfig;
+use galavox::worldgen::{generate_world, RingGenerator};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+fn near(color: &Color, palette: &Palette) -> bool {
+    let close = |a: u8, b: u8| a.abs_diff(b) <= JITTER;
+    palette.colors.iter().any(|p| close(color.r, p.r) && close(color.g, p.g) && close(color.b, p.b))
+}
+
+#[test]
+fn hex_colors_parse() {
+    assert_eq!(Color::from_hex("#ff8000"), Ok(Color { r: 255, g: 128, b: 0 }));
+    assert_eq!(Color::from_hex("0A0b0C"), Ok(Color { r: 10, g: 11, b: 12 }));
+    for bad in ["", "#", "#fff", "#ff80000", "#gg0000", "ff 000", "#+f0000", "#ff800é"] {
+        assert!(Color::from_hex(bad).is_err(), "{:?} parsed", bad);
+    }
+}
+
+#[test]
+fn builtin_palettes_load() {
+    for (name, colors) in BUILTIN {
+        assert_eq!(Palette::builtin(name).unwrap().colors.len(), colors.len());
+    }
+    assert!(Palette::builtin("plaid").is_none());
+    assert!(Palette::new("empty", vec![]).is_err());
+}
+
+#[test]
+fn jitter_stays_within_byte_bounds() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for color in [Color { r: 0, g: 0, b: 0 }, Color { r: 255, g: 255, b: 255 }, Color { r: 5, g: 250, b: 128 }] {
+        for _ in 0..1_000 {
+            let nudged = jitter(&color, &mut rng);
+            assert!(nudged.r.abs_diff(color.r) <= JITTER && nudged.g.abs_diff(color.g) <= JITTER && nudged.b.abs_diff(color.b) <= JITTER);
+        }
+    }
+}
+
+#[test]
+fn palettes_are_chosen_by_module_type() {
+    let by_module = HashMap::from([("1".to_string(), "rock".to_string()), ("3".to_string(), "mine".to_string())]);
+    let defined = HashMap::from([("mine".to_string(), vec!["#102030".to_string()])]);
+    let palettes = PlanetPalettes::resolve(Some("ice"), &by_module, &defined).unwrap();
+    assert_eq!(palettes.for_module(1).unwrap().name, "rock");
+    assert_eq!(palettes.for_module(3).unwrap().colors, vec![Color { r: 16, g: 32, b: 48 }]);
+    assert_eq!(palettes.for_module(0).unwrap().name, "ice");
+    assert!(PlanetPalettes::resolve(None, &HashMap::new(), &HashMap::new()).unwrap().for_module(0).is_none());
+
+    let world = WorldConfig { seed: Some(4), palettes: palettes.clone(), ..WorldConfig::default() };
+    for planet in generate_world(&RingGenerator, &world).planets {
+        let palette = palettes.for_module(planet.module_type).unwrap();
+        assert!(planet.colors.iter().all(|c| near(c, palette)), "planet {} strays from {}", planet.id, palette.name);
+    }
+}
+
+#[test]
+fn palettes_leave_the_rest_of_a_seeded_world_alone() {
+    let plain = WorldConfig { seed: Some(8), ..WorldConfig::default() };
+    let colored = WorldConfig { palettes: PlanetPalettes::resolve(Some("lava"), &HashMap::new(), &HashMap::new()).unwrap(), ..plain.clone() };
+    let (plain, colored) = (generate_world(&RingGenerator, &plain), generate_world(&RingGenerator, &colored));
+    for (a, b) in plain.planets.iter().zip(&colored.planets) {
+        assert_eq!((&a.position, a.size, a.module_type), (&b.position, b.size, b.module_type));
+    }
+    assert_ne!(plain.planets, colored.planets);
+}
+
+#[test]
+fn bad_palettes_fail_config_validation() {
+    let load = |text: &str| ServerConfig::from_toml(text).and_then(|mut config| config.apply_args(&[]).map(|_| config));
+    let config = load("palette = \"sunset\"\n[module_palettes]\n2 = \"rock\"\n[palettes]\nsunset = [\"#ff7e5f\", \"feb47b\"]\n").unwrap();
+    let palettes = config.world_config().palettes;
+    assert_eq!(palettes.default.unwrap().colors.len(), 2);
+    assert_eq!(palettes.by_module[&2].name, "rock");
+
+    assert!(load("palette = \"plaid\"\n").is_err());
+    assert!(load("[palettes]\nsunset = [\"#ff7e5\"]\n").is_err());
+    assert!(load("palette = \"empty\"\n[palettes]\nempty = []\n").is_err());
+    assert!(load("[module_palettes]\nmining = \"rock\"\n").is_err());
+    assert!(load("[module_palettes]\n1 = \"plaid\"\n").is_err());
+}
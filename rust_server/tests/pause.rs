@@ -0,0 +1,137 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{connect_json, next_json, send_text, spawn_server, Client};
+use galavox::admin::run_line;
+use galavox::config::ServerConfig;
+use galavox::pause::{Hold, Pause, PausedUpdates, QUEUE_LIMIT};
+use galavox::protocol::{PositionUpdate, Position, ServerMessage, IDENTITY_ROTATION};
+use galavox::server::{ConnectionId, GameServer};
+
+fn update(x: f32) -> PositionUpdate {
+    PositionUpdate { seq: None, position: Position { x, y: 0.0, z: 0.0 }, velocity: [0.0; 3], rotation: IDENTITY_ROTATION }
+}
+
+/// The next message for which `check` holds, skipping the rest.
+async fn next_matching(ws: &mut Client, check: impl Fn(&ServerMessage) -> bool) -> ServerMessage {
+    loop {
+        let message = next_json(ws).await;
+        if check(&message) {
+            return message;
+        }
+    }
+}
+
+#[test]
+fn updates_are_held_only_while_paused() {
+    let (a, b) = (ConnectionId(1), ConnectionId(2));
+    let mut pause = Pause::new(PausedUpdates::Queue);
+    assert_eq!(pause.hold(a, update(1.0)), Hold::Running(update(1.0)));
+    assert_eq!(pause.resume(), None);
+
+    assert!(pause.pause(Instant::now()));
+    assert!(!pause.pause(Instant::now()));
+    for i in 0..QUEUE_LIMIT {
+        assert_eq!(pause.hold(a, update(i as f32)), Hold::Held);
+    }
+    assert_eq!(pause.hold(a, update(99.0)), Hold::Refused);
+    assert_eq!(pause.hold(b, update(5.0)), Hold::Held);
+    pause.forget(a);
+    assert_eq!(pause.resume(), Some(vec![(b, update(5.0))]));
+    assert!(!pause.is_paused());
+
+    let mut rejecting = Pause::new(PausedUpdates::Reject);
+    rejecting.pause(Instant::now());
+    assert_eq!(rejecting.hold(a, update(1.0)), Hold::Refused);
+}
+
+#[test]
+fn the_policy_comes_from_the_config() {
+    assert_eq!(ServerConfig::default().paused_updates, PausedUpdates::Queue);
+    let config = ServerConfig::from_toml("paused_updates = \"reject\"\n").unwrap();
+    assert_eq!(config.paused_updates, PausedUpdates::Reject);
+    let mut flagged = ServerConfig::default();
+    flagged.apply_args(&["--paused-updates".to_string(), "reject".to_string()]).unwrap();
+    assert_eq!(flagged.paused_updates, PausedUpdates::Reject);
+    assert!("drop".parse::<PausedUpdates>().is_err());
+}
+
+#[test]
+fn stepping_advances_exactly_the_ticks_asked_for() {
+    let server = GameServer::new();
+    assert_eq!(run_line(&server, "step"), Err("not paused (try `pause` first)".to_string()));
+    assert_eq!(run_line(&server, "resume"), Err("not paused".to_string()));
+    run_line(&server, "pause").unwrap();
+    assert_eq!(run_line(&server, "pause"), Err("already paused".to_string()));
+
+    let before = server.current_tick();
+    run_line(&server, "step 3").unwrap();
+    assert_eq!(server.current_tick(), before + 3);
+    run_line(&server, "step").unwrap();
+    assert_eq!(server.current_tick(), before + 4);
+    assert!(run_line(&server, "step 0").is_err());
+    assert!(run_line(&server, "broadcast").unwrap().contains("paused=0s held=0"));
+    run_line(&server, "resume").unwrap();
+    assert!(run_line(&server, "broadcast").unwrap().contains("paused=no"));
+}
+
+#[tokio::test]
+async fn nothing_moves_while_paused() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ws = connect_json(addr).await;
+    let spawn = next_matching(&mut ws, |m| matches!(m, ServerMessage::State { .. })).await;
+    let ServerMessage::State { state, .. } = spawn else { unreachable!() };
+    let spawn = state.players[0].position.clone();
+
+    run_line(&server, "pause").unwrap();
+    let tick = server.current_tick();
+    let paused = next_matching(&mut ws, |m| matches!(m, ServerMessage::PauseChanged { .. })).await;
+    assert_eq!(paused, ServerMessage::PauseChanged { paused: true, tick });
+    let before = server.get_state();
+
+    let moved = Position { x: spawn.x + 5.0, ..spawn.clone() };
+    send_text(&mut ws, &format!(r#"{{"type":"Position","seq":1,"position":{{"x":{},"y":{},"z":{}}}}}"#, moved.x, moved.y, moved.z)).await;
+    send_text(&mut ws, r#"{"type":"Respawn"}"#).await;
+    assert_eq!(next_json(&mut ws).await, ServerMessage::ServerPaused { tick });
+    send_text(&mut ws, r#"{"type":"Chat","text":"still here"}"#).await;
+    assert_eq!(next_json(&mut ws).await, ServerMessage::Echo { text: "still here".to_string() });
+    // Several broadcast intervals go by without a tick
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    assert_eq!(server.current_tick(), tick);
+    assert_eq!(server.get_state(), before);
+    assert!(run_line(&server, "broadcast").unwrap().contains("held=1"));
+
+    // The held update goes in before the stepped ticks
+    run_line(&server, "step 3").unwrap();
+    assert_eq!(server.current_tick(), tick + 3);
+    let ServerMessage::State { tick: stepped, state, .. } = next_matching(&mut ws, |m| matches!(m, ServerMessage::State { .. })).await
+    else { unreachable!() };
+    assert_eq!(stepped, tick + 1);
+    assert_eq!(state.players[0].position, moved);
+
+    run_line(&server, "resume").unwrap();
+    let resumed = next_matching(&mut ws, |m| matches!(m, ServerMessage::PauseChanged { .. })).await;
+    assert_eq!(resumed, ServerMessage::PauseChanged { paused: false, tick: tick + 3 });
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while server.current_tick() <= tick + 3 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("the broadcast loop never carried on");
+}
+
+#[tokio::test]
+async fn updates_may_be_refused_instead_and_late_joiners_are_told() {
+    let server = GameServer::new().with_paused_updates(PausedUpdates::Reject);
+    let addr = spawn_server(server.clone()).await;
+    run_line(&server, "pause").unwrap();
+    let tick = server.current_tick();
+
+    let mut ws = connect_json(addr).await;
+    assert_eq!(next_json(&mut ws).await, ServerMessage::PauseChanged { paused: true, tick });
+    send_text(&mut ws, r#"{"type":"Position","position":{"x":1.0,"y":2.0,"z":3.0}}"#).await;
+    assert_eq!(next_json(&mut ws).await, ServerMessage::ServerPaused { tick });
+    assert!(run_line(&server, "broadcast").unwrap().contains("held=0"));
+    assert_ne!(server.get_state().players[0].position, Position { x: 1.0, y: 2.0, z: 3.0 });
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::{next_json, planet, spawn_server, Client};
+use futures_util::SinkExt;
+use galavox::admin::PlanetEdit;
+use galavox::planet_names::{generate_name, name_planets, unique_name, valid_planet_name};
+use galavox::protocol::{
+    ClientMessage, GameState, LandError, Planet, PlanetRenameError, Position, ServerMessage, TeleportError,
+};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use galavox::world::WorldConfig;
+use galavox::worldgen::{generate_world, RingGenerator};
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn names(planets: &[Planet]) -> Vec<&str> {
+    planets.iter().map(|p| p.name.as_str()).collect()
+}
+
+#[test]
+fn seeded_worlds_always_get_the_same_names() {
+    let config = WorldConfig { seed: Some(42), ..WorldConfig::default() };
+    let world = generate_world(&RingGenerator, &config);
+    let first: Vec<&str> = names(&world.planets).into_iter().take(4).collect();
+    assert_eq!(first, ["Maekrox", "Nalaeth", "Tremio", "Nouvivul"]);
+    assert_eq!(names(&generate_world(&RingGenerator, &config).planets), names(&world.planets));
+    assert!(world.planets.iter().all(|p| valid_planet_name(&p.name)), "{:?}", names(&world.planets));
+    assert_eq!(generate_name(42, 3), generate_name(42, 3));
+}
+
+#[test]
+fn clashing_names_are_kept_apart_with_a_number() {
+    let mut planets = vec![
+        Planet { name: "Vesh".to_string(), ..planet(0, 0.0) },
+        Planet { name: "vesh".to_string(), ..planet(1, 0.0) },
+        Planet { name: "Vesh-2".to_string(), ..planet(2, 0.0) },
+        planet(3, 0.0),
+    ];
+    assert_eq!(name_planets(&mut planets, 7), 2);
+    assert_eq!(names(&planets)[..3], ["Vesh", "vesh-3", "Vesh-2"]);
+    assert_eq!(planets[3].name, generate_name(7, 3));
+
+    let long = "a".repeat(24);
+    let unique = unique_name(&long, |name| name == long);
+    assert_eq!(unique, format!("{}-2", "a".repeat(22)));
+    assert!(valid_planet_name(&unique));
+}
+
+#[test]
+fn owners_rename_their_planets_to_free_names() {
+    let world = GameState::new(vec![
+        Planet { name: "Vesh".to_string(), ..planet(1, 0.0) },
+        Planet { name: "Ormu".to_string(), ..planet(2, 500.0) },
+    ], vec![], Position { x: 0.0, y: 5000.0, z: 0.0 });
+    let server = GameServer::new().with_world(world);
+    let (ada, player, _) = server.add_player(connection(), "Ada".to_string()).unwrap();
+    server.update_planet(1, &PlanetEdit::Owner(Some(player.id))).unwrap();
+
+    assert_eq!(server.rename_planet(ada, 2, "Mine"), Err(PlanetRenameError::NotOwner { planet_id: 2 }));
+    assert_eq!(server.rename_planet(ada, 9, "Mine"), Err(PlanetRenameError::UnknownPlanet { planet_id: 9 }));
+    assert_eq!(server.rename_planet(ada, 1, "no spaces"), Err(PlanetRenameError::InvalidName { name: "no spaces".to_string() }));
+    assert_eq!(server.rename_planet(ada, 1, "ORMU"), Err(PlanetRenameError::NameTaken { name: "ORMU".to_string(), planet_id: 2 }));
+
+    assert_eq!(server.rename_planet(ada, 1, "Adaheim").unwrap().name, "Adaheim");
+    // Only the case changes, which is no clash with itself
+    assert!(server.rename_planet(ada, 1, "ADAHEIM").is_ok());
+    assert_eq!(server.planet_named("adaheim"), Some(1));
+    assert_eq!(server.planet_named("Vesh"), None);
+}
+
+/// Connects in JSON mode and returns the player's id.
+async fn join(addr: std::net::SocketAddr) -> (Client, u32) {
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?format=json", addr)).await.unwrap();
+    let mut joined = None;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::Joined { player_id, .. } => joined = Some(player_id),
+            ServerMessage::Notice { text } if text.starts_with("Welcome") => break,
+            _ => continue,
+        }
+    }
+    (ws, joined.unwrap())
+}
+
+async fn send(ws: &mut Client, message: &ClientMessage) {
+    ws.send(Message::Text(serde_json::to_string(message).unwrap().into())).await.unwrap();
+}
+
+#[tokio::test]
+async fn planets_are_found_and_renamed_by_name_over_the_wire() {
+    let world = GameState::new(vec![
+        Planet { name: "Vesh".to_string(), ..planet(0, -500.0) },
+        Planet { name: "Ormu".to_string(), ..planet(1, 500.0) },
+    ], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+    let server = GameServer::new().with_world(world);
+    let addr = spawn_server(server.clone()).await;
+    let (mut ws, player_id) = join(addr).await;
+    server.update_planet(1, &PlanetEdit::Owner(Some(player_id))).unwrap();
+
+    send(&mut ws, &ClientMessage::TeleportToPlanetNamed { name: "Nowhere".to_string() }).await;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::TeleportRejected { error } => {
+                assert_eq!(error, TeleportError::UnknownName { name: "Nowhere".to_string() });
+                break;
+            }
+            _ => continue,
+        }
+    }
+    send(&mut ws, &ClientMessage::TeleportToPlanetNamed { name: "ormu".to_string() }).await;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::PlayerTeleported { position, .. } => {
+                assert!(position.x > 500.0, "landed next to Ormu: {:?}", position);
+                break;
+            }
+            ServerMessage::TeleportRejected { error } => panic!("teleport to Ormu was rejected: {}", error),
+            _ => continue,
+        }
+    }
+    send(&mut ws, &ClientMessage::LandNamed { name: "Nowhere".to_string() }).await;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::LandRejected { error } => {
+                assert_eq!(error, LandError::UnknownName { name: "Nowhere".to_string() });
+                break;
+            }
+            _ => continue,
+        }
+    }
+
+    send(&mut ws, &ClientMessage::RenamePlanet { planet_id: 1, name: "Vesh".to_string() }).await;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::PlanetRenameRejected { error } => {
+                assert_eq!(error, PlanetRenameError::NameTaken { name: "Vesh".to_string(), planet_id: 0 });
+                break;
+            }
+            _ => continue,
+        }
+    }
+    send(&mut ws, &ClientMessage::RenamePlanet { planet_id: 1, name: "Home".to_string() }).await;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::PlanetRenamed { planet_id, name } => {
+                assert_eq!((planet_id, name.as_str()), (1, "Home"));
+                break;
+            }
+            ServerMessage::Batch { messages } if messages.0.iter().any(|m| matches!(m, ServerMessage::PlanetRenamed { .. })) => break,
+            _ => continue,
+        }
+    }
+    assert_eq!(server.planet_named("home"), Some(1));
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use galavox::players::{PlayerShards, SHARDS};
+use galavox::protocol::{GameState, Player, PlayerAppearance, Position, PositionUpdate};
+use galavox::server::{Connection, ConnectionId, GameServer};
+use galavox::stats::ConnectionStats;
+use tokio::sync::Notify;
+
+fn connection(port: u16) -> Connection {
+    Connection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn player(id: u32) -> Player {
+    Player {
+        id,
+        name: format!("P{}", id).into(),
+        level: 1,
+        position: Position { x: 0.0, y: 0.0, z: 0.0 },
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("P"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+#[test]
+fn snapshots_list_players_by_id_across_shards() {
+    let shards = PlayerShards::new();
+    let mut all = shards.lock_all();
+    // Connection ids in reverse so the ids do not follow the shard order
+    for id in 0..(SHARDS as u32 * 2) {
+        all.insert(ConnectionId(1000 - id as u64), player(id));
+    }
+    assert_eq!(all.len(), SHARDS * 2);
+    drop(all);
+
+    let ids: Vec<u32> = shards.snapshot().iter().map(|p| p.id).collect();
+    assert_eq!(ids, (0..SHARDS as u32 * 2).collect::<Vec<_>>());
+    assert!(shards.shard(ConnectionId(1000)).contains_key(&ConnectionId(1000)));
+    assert_eq!(shards.len(), SHARDS * 2);
+}
+
+#[test]
+fn concurrent_updates_all_land() {
+    let server = GameServer::new();
+    let writers: Vec<_> = (0..8u16).map(|i| {
+        let server = server.clone();
+        std::thread::spawn(move || {
+            let (connection, player, _) = server.add_player(connection(5000 + i), format!("W{}", i)).unwrap();
+            for seq in 1..=200 {
+                let position = Position { x: 40_000.0 + seq as f32, y: i as f32, z: 0.0 };
+                server.update_player_position(connection, PositionUpdate { seq: Some(seq), position, velocity: [0.0; 3], rotation: [0.0, 0.0, 0.0, 1.0] });
+            }
+            player.id
+        })
+    }).collect();
+    let mut ids: Vec<u32> = writers.into_iter().map(|w| w.join().unwrap()).collect();
+    ids.sort();
+
+    let state = server.get_state();
+    assert_eq!(state.players.iter().map(|p| p.id).collect::<Vec<_>>(), ids);
+    for p in &state.players {
+        assert_eq!(p.last_processed_seq, 200);
+        assert_eq!(p.position.x, 40_200.0);
+    }
+}
+
+#[test]
+fn saved_players_are_not_loaded() {
+    let saved = GameState::new(vec![], vec![player(7)], Position { x: 0.0, y: 0.0, z: 0.0 });
+    let server = GameServer::new().with_world(saved);
+    assert!(server.get_state().players.is_empty());
+    let (_, joined, _) = server.add_player(connection(5000), "Ada".to_string()).unwrap();
+    assert_eq!(server.get_state().players, vec![joined]);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{planet, spawn_server};
+use galavox::client::{ClientEvent, Connection};
+use galavox::population::{count, nearest_planet, VICINITY};
+use galavox::protocol::{
+    GameState, Planet, PlanetCount, Player, PlayerAppearance, Position, ServerMessage, IDENTITY_ROTATION,
+};
+use galavox::server::GameServer;
+use galavox::spawn::{choose_spawn_balanced, Spawn};
+use galavox::world::WorldConfig;
+use galavox::worldgen::{generate_world, RingGenerator, WorldGenerator};
+use rand::rngs::StdRng;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const ORIGIN: Position = Position { x: 0.0, y: 0.0, z: 0.0 };
+
+fn player(id: u32, x: f32) -> Player {
+    Player {
+        id,
+        name: format!("p{}", id).into(),
+        level: 1,
+        position: Position { x, y: 0.0, z: 0.0 },
+        velocity: [0.0; 3],
+        rotation: IDENTITY_ROTATION,
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("p"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+fn populations(counts: &[PlanetCount]) -> Vec<(u32, u16)> {
+    counts.iter().map(|c| (c.planet_id, c.population)).collect()
+}
+
+#[test]
+fn players_count_for_the_planet_they_are_near() {
+    // Surfaces at x = 50 and x = 950
+    let planets = [planet(3, 0.0), planet(7, 1000.0)];
+    let players = [player(1, 60.0), player(2, 50.0 + VICINITY), player(3, 900.0), player(4, -100.0)];
+    assert_eq!(populations(&count(&planets, &players)), [(3, 3), (7, 1)]);
+}
+
+#[test]
+fn players_between_planets_count_for_none() {
+    let planets = [planet(3, 0.0), planet(7, 1000.0)];
+    let players = [player(1, 500.0), player(2, 51.0 + VICINITY)];
+    assert_eq!(populations(&count(&planets, &players)), [(3, 0), (7, 0)]);
+    assert_eq!(nearest_planet(&planets, &Position { x: 500.0, y: 0.0, z: 0.0 }), None);
+}
+
+#[test]
+fn overlapping_vicinities_go_to_the_nearest_surface() {
+    // Surfaces at x = 100 and x = 250; a big planet's centre is further away
+    let planets = [Planet { size: 200.0, ..planet(3, 0.0) }, planet(7, 300.0)];
+    assert_eq!(nearest_planet(&planets, &Position { x: 160.0, y: 0.0, z: 0.0 }), Some(3));
+    assert_eq!(nearest_planet(&planets, &Position { x: 190.0, y: 0.0, z: 0.0 }), Some(7));
+    let players = [player(1, 160.0), player(2, 190.0), player(3, 300.0)];
+    assert_eq!(populations(&count(&planets, &players)), [(3, 1), (7, 2)]);
+}
+
+#[test]
+fn spawns_prefer_emptier_planets() {
+    let planets = [Planet { size: 50.0, ..planet(4, 0.0) }, Planet { size: 50.0, ..planet(9, 500.0) }, Planet { size: 50.0, ..planet(12, 1000.0) }];
+    let population = [PlanetCount { planet_id: 4, population: 3 }, PlanetCount { planet_id: 9, population: 1 }];
+    let mut taken: Vec<Spawn> = Vec::new();
+    for _ in 0..4 {
+        let spawn = choose_spawn_balanced(&planets, &taken, &population, &ORIGIN);
+        taken.push(spawn);
+    }
+    let planet_ids: Vec<_> = taken.iter().map(|s| s.planet_id).collect();
+    // The fresh spawn at 9 is the player counted there already, so 9 stays
+    // level with 12 until it takes a second spawn
+    assert_eq!(planet_ids, [Some(12), Some(9), Some(9), Some(12)]);
+}
+
+/// Two planets far enough apart that nobody is near both.
+struct Pair;
+
+impl WorldGenerator for Pair {
+    fn generate(&self, config: &WorldConfig, _rng: &mut StdRng) -> GameState {
+        let mut state = generate_world(&RingGenerator, config);
+        state.planets.truncate(2);
+        for (planet, x) in state.planets.iter_mut().zip([-1000.0, 1000.0]) {
+            planet.size = 100.0;
+            planet.position = Position { x, y: 0.0, z: 0.0 };
+            planet.moons.clear();
+        }
+        state
+    }
+}
+
+async fn next_population(conn: &mut Connection, expected: &[u16]) -> Vec<PlanetCount> {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let ClientEvent::Message(ServerMessage::PlanetPopulation { counts }) = conn.next_event().await
+                && counts.iter().map(|c| c.population).eq(expected.iter().copied())
+            {
+                return counts;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the population")
+}
+
+async fn join(addr: SocketAddr, name: &str) -> Connection {
+    Connection::connect(&format!("ws://{}", addr), Some(name)).await.unwrap()
+}
+
+#[tokio::test]
+async fn population_follows_players_around() {
+    let server = GameServer::with_generator(Box::new(Pair));
+    let planet_ids: Vec<u32> = server.get_state().planets.iter().map(|p| p.id).collect();
+    let addr = spawn_server(server).await;
+
+    // Each player spawns next to the emptier planet
+    let mut ada = join(addr, "Ada").await;
+    next_population(&mut ada, &[1, 0]).await;
+    let mut bob = join(addr, "Bob").await;
+    let counts = next_population(&mut ada, &[1, 1]).await;
+    assert_eq!(counts.iter().map(|c| c.planet_id).collect::<Vec<_>>(), planet_ids);
+
+    bob.send_position(Position { x: -1000.0, y: 0.0, z: 100.0 }).await.unwrap();
+    next_population(&mut ada, &[2, 0]).await;
+    bob.send_position(ORIGIN).await.unwrap();
+    next_population(&mut bob, &[1, 0]).await;
+}
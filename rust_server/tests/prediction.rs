@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use galavox::client::{ClientEvent, Connection};
+use galavox::prediction::{PendingInputs, MAX_PENDING_INPUTS};
+use galavox::protocol::{Position, ServerMessage};
+use galavox::server::GameServer;
+use galavox::world::WorldConfig;
+
+mod common;
+
+use common::spawn_server;
+
+fn pos(x: f32, z: f32) -> Position {
+    Position { x, y: 0.0, z }
+}
+
+/// Inputs 10 to 14, each a step of +5 x and +1 z from the one before.
+fn inputs() -> PendingInputs {
+    let mut inputs = PendingInputs::new();
+    for seq in 10..=14 {
+        let n = (seq - 10) as f32;
+        inputs.push(seq, pos(100.0 + 5.0 * n, n));
+    }
+    inputs
+}
+
+#[test]
+fn corrections_replay_the_later_inputs() {
+    let mut inputs = inputs();
+    assert_eq!(inputs.predicted(), Some(&pos(120.0, 4.0)));
+
+    // Input 10 went to x 100; the server put us at x 90 instead
+    let predicted = inputs.reconcile(10, &pos(90.0, 0.0));
+    assert_eq!(predicted, pos(110.0, 4.0));
+    assert_eq!(inputs.predicted(), Some(&predicted));
+    let replayed: Vec<(u32, Position)> = inputs.iter().map(|i| (i.seq, i.position.clone())).collect();
+    assert_eq!(replayed, [(11, pos(95.0, 1.0)), (12, pos(100.0, 2.0)), (13, pos(105.0, 3.0)), (14, pos(110.0, 4.0))]);
+
+    // The next input steps on from the corrected prediction
+    inputs.push(15, pos(115.0, 5.0));
+    assert_eq!(inputs.iter().last().unwrap().step, [5.0, 0.0, 1.0]);
+    assert_eq!(inputs.reconcile(13, &pos(0.0, 0.0)), pos(10.0, 2.0));
+}
+
+#[test]
+fn a_correction_for_the_newest_input_is_the_prediction() {
+    let mut inputs = inputs();
+    assert_eq!(inputs.reconcile(14, &pos(-3.0, 7.0)), pos(-3.0, 7.0));
+    assert!(inputs.is_empty());
+}
+
+#[test]
+fn acknowledged_inputs_are_forgotten() {
+    let mut inputs = inputs();
+    inputs.acknowledge(12);
+    assert_eq!(inputs.iter().map(|i| i.seq).collect::<Vec<_>>(), [13, 14]);
+    inputs.acknowledge(9);
+    assert_eq!(inputs.len(), 2);
+
+    // Sequence numbers wrap
+    let mut inputs = PendingInputs::new();
+    for seq in [u32::MAX - 1, u32::MAX, 0, 1] {
+        inputs.push(seq, pos(0.0, 0.0));
+    }
+    inputs.acknowledge(u32::MAX);
+    assert_eq!(inputs.iter().map(|i| i.seq).collect::<Vec<_>>(), [0, 1]);
+
+    let mut inputs = PendingInputs::new();
+    for seq in 0..MAX_PENDING_INPUTS as u32 + 10 {
+        inputs.push(seq, pos(seq as f32, 0.0));
+    }
+    assert_eq!(inputs.len(), MAX_PENDING_INPUTS);
+    assert_eq!(inputs.iter().next().unwrap().seq, 10);
+}
+
+#[tokio::test]
+async fn connections_reconcile_server_corrections() {
+    let world = WorldConfig { radius: 1000.0, ..WorldConfig::default() };
+    let addr = spawn_server(GameServer::new().with_world_config(world)).await;
+    let mut conn = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    while !matches!(conn.next_event().await, ClientEvent::StateSnapshot { .. }) {}
+
+    // Past the edge, then back in by 200
+    let first = conn.send_position(pos(1100.0, 0.0)).await.unwrap();
+    conn.send_position(pos(900.0, 0.0)).await.unwrap();
+    let correction = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let ClientEvent::Message(ServerMessage::PositionCorrection { position, seq, .. }) = conn.next_event().await {
+                break (position, seq);
+            }
+        }
+    }).await.expect("timed out waiting for the correction");
+    assert_eq!(correction, (pos(1000.0, 0.0), Some(first)));
+    assert_eq!(conn.pending_inputs().predicted(), Some(&pos(800.0, 0.0)));
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{connect, next_server_message, spawn_server};
+use galavox::admin::{run_line, PlanetLimits};
+use galavox::protocol::{GameState, Position, ServerMessage};
+use galavox::prune::{PlanetActivity, PrunePolicy};
+use galavox::server::GameServer;
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// A server whose world has just the two planets `planet add` made here,
+/// standing in for seed-generated ones.
+fn server(limits: PlanetLimits) -> GameServer {
+    let origin = Position { x: 0.0, y: 0.0, z: 0.0 };
+    let seeded = GameServer::new().with_world(GameState::new(vec![], vec![], origin.clone()));
+    run_line(&seeded, "planet add size=50 x=0 y=0 z=0").unwrap();
+    run_line(&seeded, "planet add size=50 x=1000 y=0 z=0").unwrap();
+    GameServer::new().with_world(seeded.get_state()).with_planet_limits(limits)
+}
+
+fn add_at(server: &GameServer, x: i32) -> Result<String, String> {
+    run_line(server, &format!("planet add size=50 x={} y=0 z=3000", x))
+}
+
+#[test]
+fn adding_beyond_the_world_cap_is_refused() {
+    let server = server(PlanetLimits { max_planets: Some(3), ..PlanetLimits::default() });
+    assert!(add_at(&server, 0).is_ok());
+    assert_eq!(add_at(&server, 500), Err("the world is full: it has 3 planets, the most allowed".to_string()));
+    assert_eq!(server.get_state().planets.len(), 3);
+
+    // Room again once one is gone, and edits never count as adding
+    run_line(&server, "planet remove 0").unwrap();
+    assert!(add_at(&server, 500).is_ok());
+    assert!(run_line(&server, "planet set 1 size 60").is_ok());
+}
+
+#[test]
+fn an_owner_may_hold_only_so_many_planets() {
+    let server = server(PlanetLimits { max_planets_per_owner: Some(1), ..PlanetLimits::default() });
+    assert!(run_line(&server, "planet set 0 owner 7").is_ok());
+    assert_eq!(run_line(&server, "planet set 1 owner 7"), Err("player 7 already owns 1 planets, the most allowed".to_string()));
+    assert_eq!(server.get_state().planets[1].owner, None);
+
+    // Re-setting the same owner is no new claim, and others are unaffected
+    assert!(run_line(&server, "planet set 0 owner 7").is_ok());
+    assert!(run_line(&server, "planet set 1 owner 8").is_ok());
+    run_line(&server, "planet set 0 owner none").unwrap();
+    assert!(run_line(&server, "planet set 0 owner 8").is_err());
+}
+
+#[test]
+fn activity_puts_pruning_off() {
+    let start = Instant::now();
+    let mut activity = PlanetActivity::new();
+    activity.added(5, start);
+    activity.added(6, start);
+    // Only tracked planets are noted
+    activity.active(1, start + HOUR);
+    assert!(!activity.is_tracked(1));
+
+    activity.active(6, start + HOUR);
+    assert_eq!(activity.idle(HOUR, start + HOUR), []);
+    assert_eq!(activity.idle(HOUR, start + HOUR * 3 / 2), [(5, HOUR * 3 / 2)]);
+    activity.forget(5);
+    assert_eq!(activity.idle(HOUR, start + HOUR * 3), [(6, HOUR * 2)]);
+}
+
+#[test]
+fn only_unvisited_unowned_added_planets_are_pruned() {
+    let server = server(PlanetLimits::default()).with_pruning(PrunePolicy { after: HOUR });
+    let now = Instant::now();
+    add_at(&server, 0).unwrap();
+    add_at(&server, 500).unwrap();
+    run_line(&server, "planet set 3 owner 7").unwrap();
+
+    assert!(server.prune_planets(now + HOUR / 2).is_empty());
+    assert_eq!(server.prune_planets(now + HOUR * 2), [2]);
+    // The seeded planets stay however long nobody comes, and so does the claimed one
+    assert!(server.prune_planets(now + HOUR * 100).is_empty());
+    let ids: Vec<u32> = server.get_state().planets.iter().map(|p| p.id).collect();
+    assert_eq!(ids, [0, 1, 3]);
+
+    // Released, it is idle from the last economy tick that saw it owned
+    run_line(&server, "planet set 3 owner none").unwrap();
+    assert_eq!(server.prune_planets(now + HOUR * 102), [3]);
+}
+
+#[test]
+fn nothing_is_pruned_without_a_policy() {
+    let server = server(PlanetLimits::default());
+    add_at(&server, 0).unwrap();
+    assert!(server.prune_planets(Instant::now() + HOUR * 100).is_empty());
+    assert_eq!(server.get_state().planets.len(), 3);
+}
+
+#[tokio::test]
+async fn pruned_planets_are_removed_for_everyone() {
+    let server = server(PlanetLimits::default()).with_pruning(PrunePolicy { after: HOUR });
+    add_at(&server, 0).unwrap();
+    let addr = spawn_server(server.clone()).await;
+    let mut ws = connect(addr).await;
+
+    assert_eq!(server.prune_planets(Instant::now() + HOUR * 2), [2]);
+    let removed = loop {
+        if let ServerMessage::PlanetRemoved { planet_id } = next_server_message(&mut ws).await {
+            break planet_id;
+        }
+    };
+    assert_eq!(removed, 2);
+}
@@ -0,0 +1,257 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use common::{spawn_server, TEST_CAPS};
+use futures_util::StreamExt;
+use galavox::client::{ClientEvent, Connection, EventDecoder};
+use galavox::diff::StateDiff;
+use galavox::protocol::{encode_server_message, encoding_for, Capabilities, GameState, Player, PlayerAppearance, Position, ServerMessage, WireFormat};
+use galavox::quantize::{max_axis_error, quantize_diffs, Quantization, QuantizedPosition, QuantizedStream, StreamDecoder};
+use galavox::server::GameServer;
+use proptest::prelude::*;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn player(id: u32, position: Position) -> Player {
+    Player {
+        id,
+        name: format!("Player_{}", id).into(),
+        level: 1,
+        position,
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name(&format!("Player_{}", id)),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+fn at(x: f32, y: f32, z: f32) -> Position {
+    Position { x, y, z }
+}
+
+/// The states of one player walking `steps` from `start`, tick by tick.
+fn walk(start: Position, steps: &[(f32, f32, f32)]) -> Vec<GameState> {
+    let mut position = start;
+    let mut states = vec![GameState::new(vec![], vec![player(1, position.clone())], at(0.0, 0.0, 0.0))];
+    for (seq, (dx, dy, dz)) in steps.iter().enumerate() {
+        position = at(position.x + dx, position.y + dy, position.z + dz);
+        let mut moved = player(1, position.clone());
+        moved.velocity = [*dx, *dy, *dz];
+        moved.last_processed_seq = seq as u32 + 1;
+        states.push(GameState::new(vec![], vec![moved], at(0.0, 0.0, 0.0)));
+    }
+    states
+}
+
+fn diffs(states: &[GameState]) -> Vec<StateDiff> {
+    states.windows(2).map(|pair| pair[0].diff(&pair[1])).collect()
+}
+
+fn moves(diff: &StateDiff) -> Vec<QuantizedPosition> {
+    diff.moves.iter().flat_map(|moves| &moves.players).map(|moved| moved.position.clone()).collect()
+}
+
+fn step() -> impl Strategy<Value = (f32, f32, f32)> {
+    // Mostly small steps, sometimes a jump too far for an offset
+    let small = (-50.0f32..50.0, -50.0f32..50.0, -50.0f32..50.0);
+    let jump = (-2000.0f32..2000.0, -2000.0f32..2000.0, -2000.0f32..2000.0);
+    prop_oneof![9 => small, 1 => jump]
+}
+
+proptest! {
+    #[test]
+    fn random_walks_stay_within_the_bound(
+        start in (-10_000.0f32..10_000.0, -10_000.0f32..10_000.0, -10_000.0f32..10_000.0),
+        steps in prop::collection::vec(step(), 1..200),
+        resolution in prop_oneof![Just(0.01f32), Just(0.05f32), Just(0.001f32)],
+        refresh in 1u32..64,
+    ) {
+        let states = walk(at(start.0, start.1, start.2), &steps);
+        let mut quantized = diffs(&states);
+        quantize_diffs(&mut quantized, &states[0], Quantization { resolution, refresh });
+
+        let mut client = states[0].clone();
+        let mut offsets_in_a_row = 0;
+        for (diff, truth) in quantized.iter().zip(&states[1..]) {
+            client.apply(diff);
+            for moved in moves(diff) {
+                offsets_in_a_row = match moved {
+                    QuantizedPosition::Offset(_) => offsets_in_a_row + 1,
+                    QuantizedPosition::Absolute(_) => 0,
+                };
+                prop_assert!(offsets_in_a_row <= refresh);
+            }
+            let (got, want) = (&client.players[0], &truth.players[0]);
+            let bound = max_axis_error(resolution, &want.position);
+            for (got, want) in [(got.position.x, want.position.x), (got.position.y, want.position.y), (got.position.z, want.position.z)] {
+                prop_assert!((got - want).abs() <= bound, "{} off from {} by more than {}", got, want, bound);
+            }
+            prop_assert_eq!(Player { position: want.position.clone(), ..got.clone() }, want.clone());
+        }
+    }
+}
+
+#[test]
+fn far_moves_and_every_refresh_go_absolute() {
+    let steps = [(1.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, 0.0), (500.0, 0.0, 0.0), (1.0, 0.0, 0.0)];
+    let states = walk(at(0.0, 0.0, 0.0), &steps);
+    let mut quantized = diffs(&states);
+    quantize_diffs(&mut quantized, &states[0], Quantization { resolution: 0.01, refresh: 2 });
+
+    // Offsets start from where the first state had the player
+    assert!(quantized.iter().all(|diff| diff.changed_players.is_empty()));
+    let kinds: Vec<&str> = quantized.iter().flat_map(moves).map(|position| match position {
+        QuantizedPosition::Offset(offset) => if offset == [100, 0, 0] { "offset" } else { "other offset" },
+        QuantizedPosition::Absolute(_) => "absolute",
+    }).collect();
+    // A third offset in a row is one too many; 500 units is 50000 steps, too many for an i16
+    assert_eq!(kinds, ["offset", "offset", "absolute", "offset", "absolute", "offset"]);
+}
+
+#[test]
+fn only_quantized_clients_get_moves_in_binary_frames() {
+    let steps: Vec<(f32, f32, f32)> = (0..20).map(|_| (3.25, -1.5, 0.75)).collect();
+    let states = walk(at(4000.0, 0.0, -4000.0), &steps);
+    let plain = diffs(&states);
+    let mut quantized = plain.clone();
+    quantize_diffs(&mut quantized, &states[0], Quantization::default());
+    let resync = |diffs: Vec<StateDiff>| ServerMessage::Resync { from_tick: 0, tick: 20, diffs };
+    let binary = |capabilities, message: &ServerMessage| match encoding_for(capabilities, || encode_server_message(WireFormat::Binary, message)).unwrap() {
+        Message::Binary(data) => data,
+        other => panic!("expected a binary frame, got {:?}", other),
+    };
+
+    let small = binary(Capabilities::DEFAULT, &resync(quantized.clone()));
+    let large = binary(Capabilities::DEFAULT, &resync(plain.clone()));
+    assert!(small.len() < large.len(), "{} bytes quantized, {} plain", small.len(), large.len());
+    let decoded = encoding_for(Capabilities::DEFAULT, || ServerMessage::from_bincode(&small)).unwrap();
+    assert_eq!(decoded, resync(quantized));
+
+    // Without the capability, diffs keep the layout they had before moves
+    let older = Capabilities(Capabilities::DEFAULT.0 & !Capabilities::QUANTIZED.0);
+    let frame = binary(older, &resync(plain.clone()));
+    assert_eq!(frame.len(), large.len() - plain.len());
+    assert_eq!(encoding_for(older, || ServerMessage::from_bincode(&frame)).unwrap(), resync(plain));
+}
+
+#[test]
+fn streams_rebuild_every_snapshot() {
+    let steps: Vec<(f32, f32, f32)> = (0..40).map(|i| if i == 25 { (900.0, 0.0, 0.0) } else { (0.37, -0.11, 0.05) }).collect();
+    let states = walk(at(120.0, 0.0, -80.0), &steps);
+    let quantization = Quantization::default();
+    let mut stream = QuantizedStream::new(quantization);
+    let mut decoder = StreamDecoder::default();
+
+    for (tick, truth) in states.iter().enumerate() {
+        let tick = tick as u64 + 1;
+        let got = match stream.next(tick, 0, truth) {
+            ServerMessage::State { tick, state, .. } => {
+                decoder.reset(tick, &state);
+                state
+            }
+            ServerMessage::StateDelta { from_tick, tick, diff, .. } => {
+                assert_eq!(from_tick, tick - 1);
+                assert!(diff.changed_players.is_empty(), "tick {}: a move came whole", tick);
+                // Smaller on the wire than the move sent whole
+                let whole = states[from_tick as usize - 1].diff(truth);
+                let size = |diff: StateDiff| encoding_for(Capabilities::DEFAULT, || {
+                    encode_server_message(WireFormat::Binary, &ServerMessage::StateDelta { from_tick, tick, server_time_ms: 0, diff }).unwrap().len()
+                });
+                assert!(size(diff.clone()) < size(whole), "tick {}: quantized no smaller", tick);
+                decoder.apply(from_tick, tick, &diff).unwrap()
+            }
+            other => panic!("expected a State or StateDelta, got {:?}", other),
+        };
+        let (got, want) = (&got.players[0], &truth.players[0]);
+        let bound = max_axis_error(quantization.resolution, &want.position);
+        assert!((got.position.x - want.position.x).abs() <= bound && (got.position.y - want.position.y).abs() <= bound
+            && (got.position.z - want.position.z).abs() <= bound, "tick {}: {:?} is not {:?}", tick, got.position, want.position);
+    }
+    // A delta that does not follow the last snapshot decoded is refused
+    assert_eq!(decoder.apply(7, 8, &StateDiff::default()), None);
+
+    // After a restart the next snapshot comes whole again
+    stream.restart();
+    assert!(matches!(stream.next(99, 0, &states[0]), ServerMessage::State { tick: 99, .. }));
+}
+
+/// What a watching client was sent: each snapshot's tick, frame size,
+/// whether it came as a `StateDelta` with Ada moved by an offset, and
+/// where Ada was in it.
+type Watched = BTreeMap<u64, (usize, bool, Position)>;
+
+async fn watch(addr: SocketAddr, name: &str, capabilities: Capabilities, snapshots: usize) -> Watched {
+    let (mut ws, _) = connect_async(format!("ws://{}/?caps={}&name={}", addr, capabilities.0, name)).await.unwrap();
+    let mut decoder = EventDecoder::for_capabilities(capabilities);
+    let mut watched = Watched::new();
+    while watched.len() < snapshots {
+        let frame = ws.next().await.expect("connection closed").unwrap();
+        let Message::Binary(data) = &frame else { continue };
+        let offset = match encoding_for(capabilities, || ServerMessage::from_bincode(data)) {
+            Ok(ServerMessage::StateDelta { diff, .. }) => moves(&diff).iter().any(|moved| matches!(moved, QuantizedPosition::Offset(_))),
+            _ => false,
+        };
+        for event in decoder.decode(frame.clone()) {
+            match event {
+                ClientEvent::StateSnapshot { tick, state, .. } => {
+                    if let Some(ada) = state.players.iter().find(|p| &*p.name == "Ada") {
+                        watched.insert(tick, (data.len(), offset, ada.position.clone()));
+                    }
+                }
+                ClientEvent::Notice { text } => panic!("{}: {}", name, text),
+                _ => {}
+            }
+        }
+    }
+    watched
+}
+
+#[tokio::test]
+async fn a_moving_player_costs_fewer_bytes_quantized() {
+    let addr = spawn_server(GameServer::new().with_broadcast_interval(Duration::from_millis(20))).await;
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    let spawn = loop {
+        if let ClientEvent::Joined { spawn, .. } = ada.next_event().await {
+            break spawn;
+        }
+    };
+    // Ada walks along as players do, a little every tick
+    let walking = tokio::spawn(async move {
+        for step in 1..=400 {
+            let offset = step as f32 * 0.13;
+            ada.send_position(at(spawn.x + offset, spawn.y + offset / 2.0, spawn.z)).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    });
+
+    let quantized = Capabilities(TEST_CAPS.0 | Capabilities::QUANTIZED.0);
+    let (plain, quantized) = tokio::time::timeout(Duration::from_secs(20), async {
+        tokio::join!(watch(addr, "Plain", TEST_CAPS, 60), watch(addr, "Quantized", quantized, 60))
+    }).await.expect("timed out watching");
+    walking.abort();
+
+    // Every tick Ada moved by an offset costs less quantized, and puts her
+    // where the whole State does to within the bound
+    let mut ticks = 0;
+    let (mut plain_bytes, mut quantized_bytes) = (0, 0);
+    for (tick, (bytes, offset, position)) in &quantized {
+        let Some((whole, _, truth)) = plain.get(tick) else { continue };
+        let bound = max_axis_error(Quantization::default().resolution, truth);
+        assert!((position.x - truth.x).abs() <= bound && (position.y - truth.y).abs() <= bound && (position.z - truth.z).abs() <= bound,
+                "tick {}: Ada at {:?}, not {:?}", tick, position, truth);
+        if *offset {
+            ticks += 1;
+            plain_bytes += whole;
+            quantized_bytes += bytes;
+        }
+    }
+    assert!(ticks >= 10, "Ada moved by an offset in {} ticks both were sent", ticks);
+    assert!(quantized_bytes * 4 < plain_bytes, "{} bytes quantized, {} whole", quantized_bytes, plain_bytes);
+}
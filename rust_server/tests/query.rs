@@ -0,0 +1,185 @@
+mod common;
+
+use common::{connect_json, next_json, planet, spawn_server};
+use futures_util::SinkExt;
+use galavox::protocol::{ClientMessage, GameState, Planet, PlanetCursor, PlanetSort, Position, ServerMessage};
+use galavox::query::{find_planets, planets_near, PlanetFilter, MAX_QUERY_RADIUS, MAX_QUERY_RESULTS};
+use galavox::server::GameServer;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// A planet at `x` of `size`, with `module_type` and `owner`.
+fn styled(id: u32, x: f32, size: f32, module_type: u8, owner: Option<u32>) -> Planet {
+    Planet { size, module_type, owner, ..planet(id, x) }
+}
+
+/// Every page of `find_planets`, followed cursor to cursor.
+fn all_pages(planets: &[Planet], filter: &PlanetFilter, sort: PlanetSort, limit: u32) -> Vec<Vec<u32>> {
+    let mut pages = Vec::new();
+    let mut cursor = None;
+    loop {
+        let (page, next) = find_planets(planets, &at(0.0), filter, sort, limit, cursor).unwrap();
+        pages.push(ids(&page));
+        match next {
+            Some(next) => cursor = Some(next),
+            None => return pages,
+        }
+    }
+}
+
+fn at(x: f32) -> Position {
+    Position { x, y: 0.0, z: 0.0 }
+}
+
+fn ids(planets: &[Planet]) -> Vec<u32> {
+    planets.iter().map(|p| p.id).collect()
+}
+
+#[test]
+fn results_are_sorted_by_distance() {
+    let planets = vec![planet(1, 300.0), planet(2, -100.0), planet(3, 150.0), planet(4, 900.0), planet(5, 100.0)];
+    assert_eq!(ids(&planets_near(&planets, &at(0.0), 500.0, 10).unwrap()), vec![2, 5, 3, 1]);
+    assert_eq!(ids(&planets_near(&planets, &at(0.0), 500.0, 2).unwrap()), vec![2, 5]);
+    // The radius is inclusive
+    assert_eq!(ids(&planets_near(&planets, &at(0.0), 100.0, 10).unwrap()), vec![2, 5]);
+}
+
+#[test]
+fn radius_and_result_count_are_capped() {
+    let planets: Vec<Planet> = (0..2 * MAX_QUERY_RESULTS).map(|id| planet(id, id as f32)).collect();
+    assert_eq!(planets_near(&planets, &at(0.0), 1_000.0, u32::MAX).unwrap().len(), MAX_QUERY_RESULTS as usize);
+
+    let far = vec![planet(1, MAX_QUERY_RADIUS), planet(2, MAX_QUERY_RADIUS + 1.0)];
+    assert_eq!(ids(&planets_near(&far, &at(0.0), f32::MAX, 10).unwrap()), vec![1]);
+
+    assert!(planets_near(&far, &at(0.0), -1.0, 10).is_err());
+    assert!(planets_near(&far, &at(0.0), f32::NAN, 10).is_err());
+    assert!(planets_near(&far, &at(f32::INFINITY), 10.0, 10).is_err());
+}
+
+#[test]
+fn each_filter_narrows_the_planets() {
+    let planets = vec![
+        styled(1, 100.0, 10.0, 0, None),
+        styled(2, 200.0, 40.0, 1, Some(7)),
+        styled(3, 300.0, 25.0, 1, None),
+        styled(4, 400.0, 60.0, 2, Some(8)),
+    ];
+    let find = |filter: PlanetFilter| ids(&find_planets(&planets, &at(0.0), &filter, PlanetSort::Distance, 10, None).unwrap().0);
+
+    assert_eq!(find(PlanetFilter::default()), vec![1, 2, 3, 4]);
+    assert_eq!(find(PlanetFilter { module_type: Some(1), ..Default::default() }), vec![2, 3]);
+    // Size bounds are inclusive
+    assert_eq!(find(PlanetFilter { min_size: Some(25.0), ..Default::default() }), vec![2, 3, 4]);
+    assert_eq!(find(PlanetFilter { max_size: Some(40.0), ..Default::default() }), vec![1, 2, 3]);
+    assert_eq!(find(PlanetFilter { owned_by: Some(8), ..Default::default() }), vec![4]);
+    assert_eq!(find(PlanetFilter { unclaimed_only: true, ..Default::default() }), vec![1, 3]);
+}
+
+#[test]
+fn filters_combine_and_sort_by_size() {
+    let planets = vec![
+        styled(1, 100.0, 10.0, 1, None),
+        styled(2, 200.0, 40.0, 1, Some(7)),
+        styled(3, 300.0, 25.0, 1, None),
+        styled(4, 400.0, 60.0, 1, None),
+        styled(5, 500.0, 50.0, 2, None),
+    ];
+    let filter = PlanetFilter { module_type: Some(1), min_size: Some(20.0), unclaimed_only: true, ..Default::default() };
+    let (found, next) = find_planets(&planets, &at(0.0), &filter, PlanetSort::Distance, 10, None).unwrap();
+    assert_eq!((ids(&found), next), (vec![3, 4], None));
+    let (found, _) = find_planets(&planets, &at(0.0), &filter, PlanetSort::Size, 10, None).unwrap();
+    assert_eq!(ids(&found), vec![4, 3]);
+
+    // Contradictory or malformed filters are rejected rather than matching nothing
+    let rejected = [
+        PlanetFilter { min_size: Some(50.0), max_size: Some(10.0), ..Default::default() },
+        PlanetFilter { owned_by: Some(7), unclaimed_only: true, ..Default::default() },
+        PlanetFilter { min_size: Some(f32::NAN), ..Default::default() },
+    ];
+    for filter in rejected {
+        assert!(find_planets(&planets, &at(0.0), &filter, PlanetSort::Size, 10, None).is_err(), "{:?}", filter);
+    }
+    assert!(find_planets(&planets, &at(0.0), &PlanetFilter::default(), PlanetSort::Size, 0, None).is_err());
+}
+
+#[test]
+fn pages_follow_the_cursor_and_cap_the_limit() {
+    let planets: Vec<Planet> = (1..=7).map(|id| planet(id, id as f32 * 100.0)).collect();
+    assert_eq!(all_pages(&planets, &PlanetFilter::default(), PlanetSort::Distance, 3), vec![vec![1, 2, 3], vec![4, 5, 6], vec![7]]);
+    // An exact fit leaves no cursor to an empty page
+    assert_eq!(all_pages(&planets, &PlanetFilter::default(), PlanetSort::Distance, 7), vec![vec![1, 2, 3, 4, 5, 6, 7]]);
+
+    let many: Vec<Planet> = (0..2 * MAX_QUERY_RESULTS).map(|id| planet(id, id as f32)).collect();
+    let (page, next) = find_planets(&many, &at(0.0), &PlanetFilter::default(), PlanetSort::Distance, u32::MAX, None).unwrap();
+    assert_eq!(page.len(), MAX_QUERY_RESULTS as usize);
+    assert!(next.is_some());
+}
+
+#[test]
+fn cursors_survive_world_edits_between_pages() {
+    let mut planets: Vec<Planet> = (1..=6).map(|id| planet(id, id as f32 * 100.0)).collect();
+    let filter = PlanetFilter::default();
+    let (first, cursor) = find_planets(&planets, &at(0.0), &filter, PlanetSort::Distance, 3, None).unwrap();
+    assert_eq!(ids(&first), vec![1, 2, 3]);
+
+    // Planets before the cursor come and go without shifting the next page
+    planets.retain(|p| p.id != 1 && p.id != 2);
+    planets.push(planet(9, 50.0));
+    planets.push(planet(10, 450.0));
+    let (second, cursor) = find_planets(&planets, &at(0.0), &filter, PlanetSort::Distance, 3, cursor).unwrap();
+    assert_eq!(ids(&second), vec![4, 10, 5]);
+
+    // The cursor's own planet is gone now, and the cursor still works
+    planets.retain(|p| p.id != 5);
+    let (third, cursor) = find_planets(&planets, &at(0.0), &filter, PlanetSort::Distance, 3, cursor).unwrap();
+    assert_eq!((ids(&third), cursor), (vec![6], None));
+
+    let stale = PlanetCursor { key: f64::NAN, planet_id: 1 };
+    assert!(find_planets(&planets, &at(0.0), &filter, PlanetSort::Distance, 3, Some(stale)).is_err());
+}
+
+#[tokio::test]
+async fn empty_queries_get_an_empty_list() {
+    let state = GameState::new(vec![planet(1, 1_000.0)], vec![], at(0.0));
+    let addr = spawn_server(GameServer::new().with_world(state)).await;
+    let mut ws = connect_json(addr).await;
+
+    for (radius, expected) in [(10.0, vec![]), (2_000.0, vec![1])] {
+        let query = ClientMessage::QueryPlanets { center: at(0.0), radius, max_results: 5 };
+        ws.send(Message::Text(serde_json::to_string(&query).unwrap().into())).await.unwrap();
+        loop {
+            match next_json(&mut ws).await {
+                ServerMessage::PlanetList { planets } => {
+                    assert_eq!(ids(&planets), expected);
+                    break;
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn filtered_queries_get_an_empty_page_when_nothing_matches() {
+    let state = GameState::new(vec![planet(1, 1_000.0), planet(2, 2_000.0)], vec![], at(0.0));
+    let addr = spawn_server(GameServer::new().with_world(state)).await;
+    let mut ws = connect_json(addr).await;
+
+    for (module_type, expected) in [(Some(3), vec![]), (None, vec![1])] {
+        let query = ClientMessage::QueryPlanetsFiltered {
+            center: at(0.0), module_type, min_size: None, max_size: None, owned_by: None,
+            unclaimed_only: true, sort: PlanetSort::Distance, limit: 1, cursor: None, name: None,
+        };
+        ws.send(Message::Text(serde_json::to_string(&query).unwrap().into())).await.unwrap();
+        loop {
+            match next_json(&mut ws).await {
+                ServerMessage::PlanetPage { results, next_cursor } => {
+                    assert_eq!(ids(&results), expected);
+                    assert_eq!(next_cursor.is_some(), !expected.is_empty());
+                    break;
+                }
+                _ => continue,
+            }
+        }
+    }
+}
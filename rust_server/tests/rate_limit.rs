@@ -0,0 +1,84 @@
+use galavox::rate_limit::{ConnectionLimiter, Decision, RateLimitConfig, RateLimiter, TokenBucket};
+use std::time::{Duration, Instant};
+
+#[test]
+fn bucket_allows_burst_then_refills() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(5.0, 5.0, start);
+
+    for _ in 0..5 {
+        assert!(bucket.try_acquire(start));
+    }
+    assert!(!bucket.try_acquire(start));
+
+    // 200ms at 5/sec is exactly one token
+    assert!(bucket.try_acquire(start + Duration::from_millis(200)));
+    assert!(!bucket.try_acquire(start + Duration::from_millis(200)));
+}
+
+#[test]
+fn bucket_never_exceeds_capacity() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(5.0, 5.0, start);
+
+    let later = start + Duration::from_secs(60);
+    for _ in 0..5 {
+        assert!(bucket.try_acquire(later));
+    }
+    assert!(!bucket.try_acquire(later));
+}
+
+#[test]
+fn chat_over_five_per_second_is_limited() {
+    let start = Instant::now();
+    let mut limiter = ConnectionLimiter::new(&RateLimitConfig::default(), start);
+
+    let decisions: Vec<Decision> = (0..6).map(|_| limiter.chat.check(start)).collect();
+    assert_eq!(&decisions[..5], &[Decision::Allowed; 5]);
+    assert_eq!(decisions[5], Decision::Limited);
+}
+
+#[test]
+fn sustained_spam_becomes_abusive_after_window() {
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(60.0, Duration::from_secs(10), start);
+
+    // 200 updates/sec for just under ten seconds after draining the burst
+    let mut now = start;
+    let mut last = Decision::Allowed;
+    while now < start + Duration::from_millis(9_900) {
+        last = limiter.check(now);
+        assert_ne!(last, Decision::Abusive, "disconnected too early at {:?}", now - start);
+        now += Duration::from_millis(5);
+    }
+    assert_eq!(last, Decision::Limited);
+
+    let mut abusive = false;
+    for _ in 0..200 {
+        if limiter.check(now) == Decision::Abusive {
+            abusive = true;
+            break;
+        }
+        now += Duration::from_millis(5);
+    }
+    assert!(abusive);
+}
+
+#[test]
+fn recovering_resets_abuse_timer() {
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(5.0, Duration::from_secs(10), start);
+
+    for _ in 0..5 {
+        limiter.check(start);
+    }
+    assert_eq!(limiter.check(start + Duration::from_secs(5)), Decision::Allowed);
+
+    // Client backs off long enough for the bucket to refill
+    let later = start + Duration::from_secs(20);
+    assert_eq!(limiter.check(later), Decision::Allowed);
+    for _ in 0..4 {
+        limiter.check(later);
+    }
+    assert_eq!(limiter.check(later), Decision::Limited);
+}
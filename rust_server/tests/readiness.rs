@@ -0,0 +1,117 @@
+mod common;
+
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use common::connect;
+use galavox::readiness::{ReadyLine, Readiness};
+use galavox::server::GameServer;
+use tokio::net::TcpListener;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("galavox-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The server binary on a port of the system's choosing, in `dir` so that
+/// no `galavox.toml` is picked up; killed when dropped.
+struct Server(Child);
+
+impl Server {
+    fn start(dir: &PathBuf, args: &[&str]) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_server"))
+            .current_dir(dir)
+            .args(["--bind", "127.0.0.1:0"])
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        Server(child)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn the_ready_line_is_one_json_line() {
+    let line = ReadyLine { ready: true, addr: "127.0.0.1:8080".parse().unwrap(), pid: 42 };
+    assert_eq!(line.to_line(), "{\"ready\":true,\"addr\":\"127.0.0.1:8080\",\"pid\":42}\n");
+    assert_eq!(serde_json::from_str::<ReadyLine>(&line.to_line()).unwrap(), line);
+}
+
+#[tokio::test]
+async fn the_ready_file_appears_once_clients_can_connect() {
+    let dir = temp_dir("ready-file");
+    let path = dir.join("ready.json");
+    let _ = std::fs::remove_file(&path);
+    let server = Server::start(&dir, &["--ready-file", path.to_str().unwrap()]);
+
+    let started = Instant::now();
+    let text = loop {
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            break text;
+        }
+        assert!(started.elapsed() < Duration::from_secs(30), "never ready");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    let ready: ReadyLine = serde_json::from_str(&text).unwrap();
+    assert!(ready.ready);
+    assert_eq!(ready.pid, server.0.id());
+    assert_ne!(ready.addr.port(), 0, "the bound port, not the one asked for");
+    // At once, without retrying
+    connect(ready.addr).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn the_ready_fd_gets_the_line_once_clients_can_connect() {
+    let dir = temp_dir("ready-fd");
+    let mut server = Server::start(&dir, &["--ready-fd", "1"]);
+    let stdout = server.0.stdout.take().unwrap();
+    // Keeps reading, as the server would stop at a closed stdout
+    let (lines, mut ready) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let _ = lines.send(line);
+        }
+    });
+    let line = tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            match ready.recv().await {
+                Some(line) if line.starts_with('{') => return line,
+                Some(_) => continue,
+                None => panic!("no ready line"),
+            }
+        }
+    }).await.expect("never ready");
+    let ready: ReadyLine = serde_json::from_str(&line).unwrap();
+    assert_eq!(ready.pid, server.0.id());
+    connect(ready.addr).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn embedded_servers_are_ready_when_their_handle_says() {
+    let dir = temp_dir("ready-embedded");
+    let path = dir.join("ready.json");
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let handle = GameServer::new()
+        .with_readiness(Readiness { fd: None, file: Some(path.clone()) })
+        .spawn(listener)
+        .unwrap();
+    handle.ready().await;
+    let ready: ReadyLine = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(ready, ReadyLine::new(handle.local_addr()));
+    connect(handle.local_addr()).await;
+    std::fs::remove_dir_all(&dir).unwrap();
+}
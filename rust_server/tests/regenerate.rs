@@ -0,0 +1,193 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{planet, spawn_server};
+use galavox::admin::{parse_command, AdminCommand, PlanetEdit};
+use galavox::client::{ClientEvent, Connection as Client};
+use galavox::journal::{self, read_journal, replay_until, JournalEvent};
+use galavox::protocol::{Capabilities, GameState, Planet, Position, PositionUpdate, ServerMessage, IDENTITY_ROTATION};
+use galavox::server::{Connection, GameServer};
+use galavox::spawn::SPAWN_CLEARANCE;
+use galavox::stats::ConnectionStats;
+use galavox::world::WorldConfig;
+use galavox::worldgen::{generate_world, RingGenerator};
+use tokio::sync::Notify;
+
+fn at(x: f32) -> Position {
+    Position { x, y: 0.0, z: 0.0 }
+}
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn seeded(seed: u64) -> WorldConfig {
+    WorldConfig { seed: Some(seed), ..WorldConfig::default() }
+}
+
+fn distance(a: &Position, b: &Position) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+#[test]
+fn parses_regenerate_world() {
+    assert_eq!(parse_command("regenerate-world"), Ok(AdminCommand::RegenerateWorld { seed: None }));
+    assert_eq!(parse_command("regenerate-world 42"), Ok(AdminCommand::RegenerateWorld { seed: Some(42) }));
+    assert!(parse_command("regenerate-world soon").unwrap_err().contains("invalid seed"));
+}
+
+#[test]
+fn regeneration_swaps_the_planets_and_keeps_the_players() {
+    let state = GameState::new(vec![Planet { size: 10.0, ..planet(1, 500.0) }, Planet { size: 10.0, ..planet(2, -500.0) }], vec![], at(0.0));
+    let server = GameServer::new().with_world(state);
+    let (ada, _, _) = server.add_player(connection(), "Ada".to_string()).unwrap();
+    let (bob, _, _) = server.add_player(connection(), "Bob".to_string()).unwrap();
+    server.move_player(ada, at(505.0));
+    server.update_planet(2, &PlanetEdit::Owner(Some(0))).unwrap();
+    let xp = server.get_state().players[0].xp;
+    assert!(xp > 0);
+
+    let reset = server.regenerate_world(Some(7));
+    let expected = generate_world(&RingGenerator, &seeded(7));
+    assert_eq!((reset.seed, reset.planets, reset.players), (7, expected.planets.len(), 2));
+
+    let state = server.get_state();
+    assert_eq!(state.planets, expected.planets);
+    assert!(state.planets.iter().all(|p| p.owner.is_none()));
+    assert_eq!(state.players.len(), 2);
+    // Discoveries go with the old world, XP stays
+    assert_eq!(state.players[0].xp, xp);
+    let Some(ServerMessage::DiscoveryList { planet_ids, .. }) = server.discovery_list(ada) else { panic!() };
+    assert!(planet_ids.is_empty());
+
+    // Everyone is at a spawn next to a new planet, and their spawn moved too
+    for (connection, player) in [ada, bob].into_iter().zip(&state.players) {
+        let spawn = server.spawn_of(connection).unwrap();
+        assert_eq!(spawn.position, player.position);
+        let planet = state.planet_by_id(spawn.planet_id.unwrap()).unwrap();
+        assert!((distance(&planet.position, &player.position) - planet.size / 2.0 - SPAWN_CLEARANCE).abs() < 1e-2);
+    }
+
+    // New planets get ids after the new world's
+    let added = server.add_planet(Planet { size: 10.0, ..planet(0, 9000.0) }).unwrap();
+    assert_eq!(added.id, expected.next_body_id());
+}
+
+#[test]
+fn the_same_seed_regenerates_the_same_world() {
+    let server = GameServer::new();
+    server.regenerate_world(Some(3));
+    let first = server.get_state().planets;
+    server.regenerate_world(None);
+    assert_ne!(server.get_state().planets, first);
+    server.regenerate_world(Some(3));
+    assert_eq!(server.get_state().planets, first);
+}
+
+#[tokio::test]
+async fn replaying_the_journal_follows_a_regeneration() {
+    let path = std::env::temp_dir().join(format!("galavox-regenerate-{}.journal", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (journal, writer) = journal::open(&path).await.unwrap();
+    let server = GameServer::new().with_journal(journal);
+    let (ada, _, _) = server.add_player(connection(), "Ada".to_string()).unwrap();
+    server.regenerate_world(Some(5));
+    server.move_player(ada, at(12.0));
+    let expected = server.get_state();
+    writer.shutdown().await.unwrap();
+
+    let records = read_journal(std::fs::File::open(&path).unwrap()).unwrap();
+    assert!(records.iter().any(|r| matches!(r.event, JournalEvent::WorldReset { .. })));
+    assert_eq!(replay_until(&records, u64::MAX), expected);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn regeneration_waits_for_in_flight_position_updates() {
+    let server = GameServer::new();
+    let connections: Vec<_> = (0..4)
+        .map(|i| server.add_player(connection(), format!("Pilot{}", i)).unwrap().0)
+        .collect();
+    let stop = Arc::new(AtomicBool::new(false));
+    let movers: Vec<_> = connections.into_iter().map(|connection| {
+        let (server, stop) = (server.clone(), stop.clone());
+        tokio::task::spawn_blocking(move || {
+            let mut seq = 0;
+            while !stop.load(Ordering::Relaxed) {
+                seq += 1;
+                let position = at((seq % 1000) as f32);
+                server.update_player_position(connection, PositionUpdate { seq: Some(seq), position, velocity: [0.0; 3], rotation: IDENTITY_ROTATION });
+            }
+        })
+    }).collect();
+
+    let regenerating = {
+        let server = server.clone();
+        tokio::task::spawn_blocking(move || {
+            for seed in 0..20 {
+                server.regenerate_world(Some(seed));
+            }
+        })
+    };
+    tokio::time::timeout(Duration::from_secs(10), regenerating).await.expect("regeneration deadlocked").unwrap();
+    stop.store(true, Ordering::Relaxed);
+    for mover in movers {
+        tokio::time::timeout(Duration::from_secs(10), mover).await.expect("position updates deadlocked").unwrap();
+    }
+
+    let state = server.get_state();
+    assert_eq!(state.planets, generate_world(&RingGenerator, &seeded(19)).planets);
+    assert_eq!(state.players.len(), 4);
+}
+
+/// The next event matching `f`, skipping the rest.
+async fn next_matching<T>(conn: &mut Client, mut f: impl FnMut(ClientEvent) -> Option<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = f(conn.next_event().await) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an event")
+}
+
+#[tokio::test]
+async fn clients_get_a_reset_and_then_the_new_world() {
+    let server = GameServer::new().with_admin_token("secret".to_string());
+    let addr = spawn_server(server.clone()).await;
+    // Sent positions whole, to compare them exactly
+    let whole = Capabilities(Capabilities::DEFAULT.0 & !Capabilities::QUANTIZED.0);
+    let mut ada = Client::connect_with_capabilities(&format!("ws://{}", addr), Some("Ada"), false, whole).await.unwrap();
+    next_matching(&mut ada, |e| matches!(e, ClientEvent::Joined { .. }).then_some(())).await;
+
+    let reply = server.run_admin_command("secret", "regenerate-world 11").unwrap();
+    assert!(reply.starts_with("regenerated the world from seed 11"), "{}", reply);
+    let seed = next_matching(&mut ada, |e| match e {
+        ClientEvent::WorldReset { seed } => Some(seed),
+        _ => None,
+    }).await;
+    assert_eq!(seed, 11);
+    // The next snapshot is the new world; nobody joins or leaves, then or after
+    let mut snapshots = Vec::new();
+    while snapshots.len() < 2 {
+        let state = next_matching(&mut ada, |e| match e {
+            ClientEvent::StateSnapshot { state, .. } => Some(state),
+            ClientEvent::PlayerJoined { .. } | ClientEvent::PlayerLeft { .. } => panic!("{:?} in a reset", e),
+            _ => None,
+        }).await;
+        snapshots.push(state);
+    }
+    let state = &snapshots[0];
+    assert_eq!(state.planets, generate_world(&RingGenerator, &seeded(11)).planets);
+    let me = state.players.iter().find(|p| Some(p.id) == ada.player_id()).unwrap();
+    assert_eq!(me.position, server.get_state().players[0].position);
+}
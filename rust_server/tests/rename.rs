@@ -0,0 +1,140 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use common::{connect, next_server_message, planet, player_name, send_text, spawn_server, Client};
+use galavox::journal::{apply_event, JournalEvent};
+use galavox::module_effects::Wallet;
+use galavox::protocol::{ClientMessage, ErrorCode, GameState, Planet, Position, RenameError, ServerMessage};
+use galavox::server::{Connection, GameServer};
+use galavox::stats::ConnectionStats;
+use galavox::wallets::Wallets;
+use tokio::sync::Notify;
+
+fn connection(port: u16) -> Connection {
+    Connection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+#[test]
+fn renaming_checks_the_name_like_joining() {
+    let server = GameServer::new();
+    let (ada, _, _) = server.add_player(connection(4000), "Ada".to_string()).unwrap();
+    let (bob, bob_player, _) = server.add_player(connection(4001), "Bob".to_string()).unwrap();
+    server.ban("Mallory").unwrap();
+
+    assert_eq!(server.rename_player(ada, "Ada"), Ok(None));
+    assert_eq!(server.rename_player(ada, "bob"), Err(RenameError::NameTaken { name: "bob".to_string() }));
+    assert_eq!(server.rename_player(ada, "MALLORY"), Err(RenameError::Banned { name: "MALLORY".to_string() }));
+    assert_eq!(server.rename_player(bob, "Robert"), Ok(Some("Bob".to_string())));
+    // Bob is free again, and a player may change how their own name is written
+    assert_eq!(server.rename_player(ada, "Bob"), Ok(Some("Ada".to_string())));
+    assert_eq!(server.rename_player(bob, "ROBERT"), Ok(Some("Robert".to_string())));
+
+    let names: Vec<_> = server.get_state().players.iter().map(|p| (p.id, p.name.to_string())).collect();
+    assert!(names.contains(&(bob_player.id, "ROBERT".to_string())), "{:?}", names);
+
+    let mut state = GameState::new(vec![], vec![], bob_player.position.clone());
+    state.players.push(bob_player.clone());
+    apply_event(&mut state, &JournalEvent::PlayerRenamed { player_id: bob_player.id, name: "Robert".to_string() });
+    assert_eq!(&*state.players[0].name, "Robert");
+}
+
+#[test]
+fn names_away_keep_their_wallets_and_discoveries() {
+    let mut wallets = Wallets::new();
+    wallets.set([("Ada", Wallet { cargo: 5, credits: 7 }), ("Bob", Wallet { cargo: 1, credits: 0 })]).unwrap();
+    let here = Position { x: 0.0, y: 0.0, z: 0.0 };
+    let world = GameState::new(vec![Planet { size: 10.0, ..planet(1, 500.0) }], vec![], here.clone());
+    let server = GameServer::new().with_wallets(wallets).with_world(world);
+    let (bob, _, _) = server.add_player(connection(4000), "Bob".to_string()).unwrap();
+    let (carol, _, _) = server.add_player(connection(4001), "Carol".to_string()).unwrap();
+    let (dora, _, _) = server.add_player(connection(4002), "Dora".to_string()).unwrap();
+    server.move_player(dora, Position { x: 505.0, ..here });
+    server.remove_player(dora);
+
+    // Ada is away: Bob may not add his wallet to hers, nor Carol, with
+    // none, take it over
+    let taken = |name: &str| Err(RenameError::NameTaken { name: name.to_string() });
+    assert_eq!(server.rename_player(bob, "ada"), taken("ada"));
+    assert_eq!(server.rename_player(carol, "ADA"), taken("ADA"));
+    assert_eq!(server.wallet("Ada"), Wallet { cargo: 5, credits: 7 });
+    assert_eq!(server.wallet("Bob"), Wallet { cargo: 1, credits: 0 });
+    assert_eq!(server.wallet("Carol"), Wallet::default());
+    // Nor Dora's discoveries
+    assert_eq!(server.rename_player(carol, "dora"), taken("dora"));
+    let (dora, player, _) = server.add_player(connection(4002), "Dora".to_string()).unwrap();
+    assert!(player.xp > 0);
+    server.remove_player(dora);
+
+    // A name no one has used is free, and the wallet goes with it
+    assert_eq!(server.rename_player(bob, "Robert"), Ok(Some("Bob".to_string())));
+    assert_eq!((server.wallet("Robert"), server.wallet("Bob")), (Wallet { cargo: 1, credits: 0 }, Wallet::default()));
+
+    let mut wallets = Wallets::new();
+    wallets.set([("Ada", Wallet { cargo: 5, credits: 7 }), ("Bob", Wallet { cargo: 1, credits: 0 })]).unwrap();
+    wallets.rename("Bob", "Ada").unwrap();
+    wallets.rename("Carol", "Bob").unwrap();
+    assert_eq!((wallets.get("Ada"), wallets.get("Bob")), (Wallet { cargo: 5, credits: 7 }, Wallet { cargo: 1, credits: 0 }));
+}
+
+async fn send_join(ws: &mut Client, name: &str) {
+    send_text(ws, &serde_json::to_string(&ClientMessage::Join { name: name.to_string() }).unwrap()).await;
+}
+
+/// The next message `pick` accepts, skipping states and other broadcasts.
+async fn next_matching<T>(ws: &mut Client, pick: impl Fn(ServerMessage) -> Option<T>) -> T {
+    loop {
+        if let Some(found) = pick(next_server_message(ws).await) {
+            return found;
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_second_join_acknowledges_renames_or_refuses() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut ada = connect(addr).await;
+    let mut bob = connect(addr).await;
+    let name = player_name(&ada);
+
+    // The same name again is just acknowledged
+    send_join(&mut ada, &name).await;
+    let first = next_matching(&mut ada, |m| match m {
+        ServerMessage::Joined { player_id, .. } => Some(player_id),
+        _ => None,
+    }).await;
+
+    // A new one renames, and everyone hears of it
+    send_join(&mut ada, "Ada").await;
+    let renamed = next_matching(&mut bob, |m| match m {
+        ServerMessage::PlayerRenamed { player_id, old, new } => Some((player_id, old, new)),
+        _ => None,
+    }).await;
+    assert_eq!(renamed, (first, name, "Ada".to_string()));
+
+    send_join(&mut bob, "ada").await;
+    let error = next_matching(&mut bob, |m| match m {
+        ServerMessage::RenameRejected { error } => Some(error),
+        _ => None,
+    }).await;
+    assert_eq!(error, RenameError::NameTaken { name: "ada".to_string() });
+
+    // A malformed name is an error, but the session goes on
+    send_join(&mut ada, "no spaces!").await;
+    let code = next_matching(&mut ada, |m| match m {
+        ServerMessage::Error { code, .. } => Some(code),
+        _ => None,
+    }).await;
+    assert_eq!(code, ErrorCode::ProtocolViolation.code());
+    send_join(&mut ada, "Ada").await;
+    let again = next_matching(&mut ada, |m| match m {
+        ServerMessage::Joined { player_id, .. } => Some(player_id),
+        _ => None,
+    }).await;
+    assert_eq!(again, first);
+}
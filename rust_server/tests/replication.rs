@@ -0,0 +1,104 @@
+mod common;
+
+use common::spawn_server;
+use galavox::admin::run_line;
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::{ErrorCode, Position};
+use galavox::server::GameServer;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_tungstenite::{connect_async, tungstenite};
+
+/// The next event `want` picks out, skipping the others.
+async fn wait_for<T>(connection: &mut Connection, mut want: impl FnMut(ClientEvent) -> Option<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = want(connection.next_event().await) {
+                return found;
+            }
+        }
+    }).await.expect("timed out waiting for an event")
+}
+
+#[tokio::test]
+async fn a_promoted_standby_takes_over_a_scripted_session() {
+    let primary = GameServer::new().with_admin_token("secret".to_string());
+    let primary_addr = spawn_server(primary.clone()).await;
+
+    // Only followers with the admin token are let in
+    match connect_async(format!("ws://{}/?role=follower&token=wrong", primary_addr)).await {
+        Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 401),
+        other => panic!("a follower with the wrong token got {:?}", other.map(|_| ())),
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let follower_url = format!("ws://{}", listener.local_addr().unwrap());
+    let follower = GameServer::new()
+        .with_admin_token("secret".to_string())
+        .with_standby(format!("ws://{}", primary_addr), "secret".to_string(), follower_url.clone());
+    follower.clone().spawn(listener).unwrap();
+
+    // The session: Ada joins and flies, and an admin resizes a planet
+    let mut ada = Connection::connect(&format!("ws://{}", primary_addr), Some("Ada")).await.unwrap();
+    wait_for(&mut ada, |event| matches!(event, ClientEvent::Joined { .. }).then_some(())).await;
+    let planet_id = primary.get_state().planets[0].id;
+    run_line(&primary, &format!("planet set {} size 123", planet_id)).unwrap();
+    ada.send_position(Position { x: 40.0, y: 5.0, z: -40.0 }).await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let replica = follower.replica().expect("not promoted yet");
+            if let Some(state) = &replica.state
+                && state.planet_by_id(planet_id).is_some_and(|p| p.size == 123.0)
+                && state.players.iter().any(|p| &*p.name == "Ada" && p.position.x == 40.0)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("the follower never caught up");
+
+    // A standby takes no players until it is promoted
+    let mut early = Connection::connect(&follower_url, Some("Bob")).await.unwrap();
+    let code = wait_for(&mut early, |event| match event {
+        ClientEvent::Disconnected { code, .. } => Some(code),
+        _ => None,
+    }).await;
+    assert_eq!(code, Some(ErrorCode::Standby.code()));
+
+    assert!(run_line(&follower, "promote").unwrap().starts_with("promoted"));
+    assert!(run_line(&follower, "promote").is_err());
+
+    // Ada's client follows the primary's redirect to the new server
+    let to = wait_for(&mut ada, |event| match event {
+        ClientEvent::Redirected { to } => Some(to),
+        ClientEvent::Disconnected { reason, .. } => panic!("disconnected instead of redirected: {}", reason),
+        _ => None,
+    }).await;
+    assert_eq!(to, follower_url);
+    wait_for(&mut ada, |event| matches!(event, ClientEvent::Joined { .. }).then_some(())).await;
+    let state = wait_for(&mut ada, |event| match event {
+        ClientEvent::StateSnapshot { state, .. } => Some(state),
+        _ => None,
+    }).await;
+    assert_eq!(state.planet_by_id(planet_id).map(|p| p.size), Some(123.0));
+    assert!(state.players.iter().any(|p| &*p.name == "Ada"));
+
+    // The old primary has stopped
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while connect_async(format!("ws://{}", primary_addr)).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.expect("the primary is still accepting");
+}
+
+#[tokio::test]
+async fn promoting_needs_a_world_from_the_primary() {
+    assert!(GameServer::new().promote().unwrap_err().contains("not a standby"));
+
+    // Nothing listens on the discard port, so no world ever arrives
+    let standby = GameServer::new().with_standby("ws://127.0.0.1:9".to_string(), "secret".to_string(), "ws://127.0.0.1:8080".to_string());
+    assert!(standby.is_standby());
+    assert!(standby.promote().unwrap_err().contains("no world"));
+    assert!(standby.is_standby());
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{next_json, send_text, spawn_server, Client, TEST_CAPS};
+use galavox::admin::{parse_command, run_line};
+use galavox::config::{ConfigSource, ServerConfig};
+use galavox::protocol::{ClientMessage, ServerMessage};
+use galavox::roles::{audit_line, required_role, Granted, Role, Roles};
+use galavox::server::GameServer;
+use tokio_tungstenite::connect_async;
+
+fn roles_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("galavox-{}-{}.roles.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// The token at the end of a `grant` reply.
+fn issued_token(reply: &str) -> String {
+    reply.rsplit(' ').next().unwrap().to_string()
+}
+
+async fn join(addr: std::net::SocketAddr, name: &str) -> Client {
+    let url = format!("ws://{}/?format=json&caps={}&name={}", addr, TEST_CAPS.0, name);
+    connect_async(url).await.unwrap().0
+}
+
+async fn admin(ws: &mut Client, token: &str, command: &str) -> Result<String, String> {
+    let message = ClientMessage::Admin { token: token.to_string(), command: command.to_string(), dry_run: false };
+    send_text(ws, &serde_json::to_string(&message).unwrap()).await;
+    loop {
+        if let ServerMessage::AdminResult { ok, message } = next_json(ws).await {
+            return if ok { Ok(message) } else { Err(message) };
+        }
+    }
+}
+
+#[test]
+fn commands_need_the_role_in_the_table() {
+    let role = |line: &str| required_role(&parse_command(line).unwrap());
+    assert_eq!(role("kick 3"), Role::Moderator);
+    assert_eq!(role("announce hello"), Role::Moderator);
+    assert_eq!(role("players"), Role::Moderator);
+    assert_eq!(role("ban Mallory"), Role::Owner);
+    assert_eq!(role("regenerate-world"), Role::Owner);
+    assert_eq!(role("reload"), Role::Owner);
+    assert_eq!(role("grant Ada moderator"), Role::Owner);
+
+    assert_eq!(parse_command("grant Ada Lovelace owner").unwrap(), parse_command("grant   Ada Lovelace  owner ").unwrap());
+    assert!(parse_command("grant Ada").is_err());
+    assert!(parse_command("grant Ada admin").unwrap_err().contains("unknown role"));
+
+    let line = audit_line("Ada", Role::Moderator, "ban Bob", &Err("ban needs the owner role".to_string()), 1_000);
+    assert_eq!(line, "📋 audit at_unix_ms=1000 who=Ada role=moderator command=`ban Bob`: refused: ban needs the owner role");
+    let line = audit_line("Ada", Role::Moderator, "kick Bob", &Ok("kicked Bob".to_string()), 1_000);
+    assert_eq!(line, "📋 audit at_unix_ms=1000 who=Ada role=moderator command=`kick Bob`: done: kicked Bob");
+    // A dry run logs its plan; a result of several lines stays on one
+    let plan = run_line(&GameServer::new(), "ban Bob --dry-run");
+    let line = audit_line("Ada", Role::Owner, "ban Bob --dry-run", &plan, 1_000);
+    assert!(line.ends_with(": done: dry run of `ban Bob`: players affected: none; planets removed: none; planets added: 0; files written: none"), "{}", line);
+    let line = audit_line("Ada", Role::Moderator, "mutes", &Ok("Ada muted for 1h\nBob muted for 2h\n".to_string()), 1_000);
+    assert!(line.ends_with("`mutes`: done: Ada muted for 1h; Bob muted for 2h"), "{}", line);
+}
+
+#[test]
+fn grants_and_revokes_survive_a_restart() {
+    let path = roles_path("restart");
+    let mut roles = Roles::load(&path).unwrap();
+    assert_eq!(roles.grant("Ada", Role::Moderator, || "t1".to_string()).unwrap(), Granted::Changed { token: Some("t1".to_string()) });
+    assert_eq!(roles.grant("bob", Role::Owner, || "t2".to_string()).unwrap(), Granted::Changed { token: Some("t2".to_string()) });
+    // A promotion keeps the token; the same role again changes nothing
+    assert_eq!(roles.grant("ada", Role::Owner, || "t3".to_string()).unwrap(), Granted::Changed { token: None });
+    assert_eq!(roles.grant("ADA", Role::Owner, || "t4".to_string()).unwrap(), Granted::Unchanged);
+
+    let reloaded = Roles::load(&path).unwrap();
+    assert_eq!((reloaded.role("Ada"), reloaded.role("Bob"), reloaded.role("Carol")), (Role::Owner, Role::Owner, Role::Player));
+    assert_eq!(reloaded.authenticate("ada", "t1"), Some(Role::Owner));
+    assert_eq!(reloaded.authenticate("Bob", "t1"), None);
+
+    assert_eq!(roles.revoke("Bob").unwrap(), Some(Role::Owner));
+    assert_eq!(roles.grant("Ada", Role::Player, String::new).unwrap(), Granted::Revoked);
+    let reloaded = Roles::load(&path).unwrap();
+    assert!(reloaded.profiles().is_empty());
+    assert_eq!(reloaded.authenticate("Ada", "t1"), None);
+
+    // Roles are not dropped over a file that cannot be read
+    std::fs::write(&path, "{ not json").unwrap();
+    assert!(Roles::load(&path).is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn configured_owners_are_made_owners_at_every_start() {
+    let path = roles_path("owners");
+    let toml = format!("roles_file = {:?}\nowners = [\"Ada\"]\n", path.display().to_string());
+    let start = || {
+        let config = ServerConfig::from_toml(&toml).unwrap();
+        config.build(config.validate().unwrap(), ConfigSource::new(None, Vec::new(), config.clone()))
+    };
+
+    let server = start();
+    run_line(&server, "grant Bob moderator").unwrap();
+    run_line(&server, "revoke Ada").unwrap();
+    let profiles = start().role_profiles();
+    let roles: Vec<(&str, Role)> = profiles.iter().map(|p| (p.name.as_str(), p.role)).collect();
+    assert_eq!(roles, [("Ada", Role::Owner), ("Bob", Role::Moderator)]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn remote_commands_need_the_players_own_token_and_role() {
+    let server = GameServer::new();
+    let token = issued_token(&run_line(&server, "grant Ada moderator").unwrap());
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = join(addr, "Ada").await;
+    let mut mallory = join(addr, "Mallory").await;
+
+    assert_eq!(admin(&mut ada, &token, "announce hello").await, Ok("announced: hello".to_string()));
+    assert_eq!(admin(&mut ada, &token, "ban Mallory").await, Err("ban needs the owner role; Ada is a moderator".to_string()));
+    assert!(server.banned_names().is_empty());
+    // A token only works for the name it was issued to
+    assert_eq!(admin(&mut mallory, &token, "announce hi").await, Err("invalid admin token".to_string()));
+
+    run_line(&server, "grant Ada owner").unwrap();
+    assert_eq!(admin(&mut ada, &token, "ban Bob").await, Ok("banned Bob".to_string()));
+    run_line(&server, "revoke Ada").unwrap();
+    assert!(admin(&mut ada, &token, "unban Bob").await.is_err());
+    assert_eq!(server.banned_names(), ["Bob"]);
+}
@@ -0,0 +1,53 @@
+use galavox::protocol::{
+    encode_motion_update, Position, LEGACY_POSITION_FRAME_LEN, MOTION_FRAME_LEN, POSITION_FRAME_LEN,
+    RESYNC_FRAME_LEN, TIME_SYNC_FRAME_LEN,
+};
+use galavox::schema::{binary_frame_layouts, dump_schema};
+
+#[test]
+fn committed_schema_is_up_to_date() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/schema/protocol.json");
+    let committed = std::fs::read_to_string(path).unwrap_or_default();
+    assert!(
+        committed == dump_schema(),
+        "schema/protocol.json is stale; regenerate it with\n    cargo run --bin server -- --dump-schema > schema/protocol.json"
+    );
+}
+
+#[test]
+fn dump_is_deterministic() {
+    assert_eq!(dump_schema(), dump_schema());
+}
+
+#[test]
+fn frame_lengths_match_protocol_constants() {
+    let lengths: Vec<(&str, usize)> = binary_frame_layouts().iter().map(|f| (f.name, f.length)).collect();
+    assert_eq!(
+        lengths,
+        [
+            ("time_sync_request", TIME_SYNC_FRAME_LEN),
+            ("resync_request", RESYNC_FRAME_LEN),
+            ("legacy_position", LEGACY_POSITION_FRAME_LEN),
+            ("position", POSITION_FRAME_LEN),
+            ("motion", MOTION_FRAME_LEN),
+        ]
+    );
+}
+
+#[test]
+fn motion_table_offsets_match_encoder() {
+    // Every float gets a distinct value so a wrong offset cannot go unnoticed
+    let position = Position { x: 1.0, y: 2.0, z: 3.0 };
+    let frame = encode_motion_update(77, &position, &[4.0, 5.0, 6.0], &[7.0, 8.0, 9.0, 10.0]);
+
+    let layouts = binary_frame_layouts();
+    let motion = layouts.iter().find(|f| f.name == "motion").unwrap();
+    for (i, field) in motion.fields.iter().enumerate() {
+        let bytes: [u8; 4] = frame[field.offset..field.offset + field.size].try_into().unwrap();
+        match field.ty {
+            "u32" => assert_eq!(u32::from_le_bytes(bytes), 77, "{}", field.name),
+            "f32" => assert_eq!(f32::from_le_bytes(bytes), i as f32, "{}", field.name),
+            other => panic!("unexpected type {}", other),
+        }
+    }
+}
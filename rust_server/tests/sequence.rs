@@ -0,0 +1,68 @@
+mod common;
+
+use common::{connect, spawn_server, wait_for_self};
+use futures_util::SinkExt;
+use galavox::protocol::{decode_position_update, encode_position_update, seq_newer, Position};
+use galavox::server::GameServer;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn pos(x: f32) -> Position {
+    Position { x, y: 0.0, z: 0.0 }
+}
+
+#[test]
+fn serial_number_comparison_handles_wraparound() {
+    assert!(seq_newer(2, 1));
+    assert!(!seq_newer(1, 2));
+    assert!(!seq_newer(7, 7));
+    assert!(seq_newer(0, u32::MAX));
+    assert!(seq_newer(5, u32::MAX - 5));
+    assert!(!seq_newer(u32::MAX, 0));
+}
+
+#[test]
+fn position_update_round_trip_and_legacy_frame() {
+    let encoded = encode_position_update(42, &Position { x: 1.0, y: -2.5, z: 3.25 });
+    assert_eq!(encoded.len(), 16);
+    let update = decode_position_update(&encoded).unwrap();
+    assert_eq!(update.seq, Some(42));
+    assert_eq!(update.position, Position { x: 1.0, y: -2.5, z: 3.25 });
+
+    let legacy = decode_position_update(&encoded[4..]).unwrap();
+    assert_eq!(legacy.seq, None);
+    assert_eq!(legacy.position, update.position);
+
+    assert!(decode_position_update(&encoded[..10]).is_err());
+}
+
+#[tokio::test]
+async fn stale_updates_are_discarded() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect(addr).await;
+
+    client.send(Message::Binary(encode_position_update(10, &pos(10.0)).into())).await.unwrap();
+    client.send(Message::Binary(encode_position_update(9, &pos(9.0)).into())).await.unwrap();
+    client.send(Message::Binary(encode_position_update(10, &pos(11.0)).into())).await.unwrap();
+
+    let me = wait_for_self(&mut client, |p| p.last_processed_seq == 10).await;
+    assert_eq!(me.position, pos(10.0));
+
+    // A later marker proves the stale frames were processed (and ignored) by now
+    client.send(Message::Binary(encode_position_update(11, &pos(12.0)).into())).await.unwrap();
+    let me = wait_for_self(&mut client, |p| p.last_processed_seq == 11).await;
+    assert_eq!(me.position, pos(12.0));
+}
+
+#[tokio::test]
+async fn sequence_wraps_at_u32_max() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect(addr).await;
+
+    client.send(Message::Binary(encode_position_update(u32::MAX, &pos(1.0)).into())).await.unwrap();
+    client.send(Message::Binary(encode_position_update(0, &pos(2.0)).into())).await.unwrap();
+    client.send(Message::Binary(encode_position_update(u32::MAX, &pos(3.0)).into())).await.unwrap();
+    client.send(Message::Binary(encode_position_update(1, &pos(4.0)).into())).await.unwrap();
+
+    let me = wait_for_self(&mut client, |p| p.last_processed_seq == 1).await;
+    assert_eq!(me.position, pos(4.0));
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::spawn_server;
+use galavox::admin::run_line;
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::{ClientMessage, Position};
+use galavox::server::GameServer;
+use galavox::session::{DisconnectReason, SessionSummary, SessionTracker};
+
+fn at(x: f32, y: f32, z: f32) -> Position {
+    Position { x, y, z }
+}
+
+#[test]
+fn a_scripted_session_adds_up() {
+    let start = Instant::now();
+    let mut session = SessionTracker::new(10, 2, start);
+    for _ in 0..5 {
+        session.message();
+    }
+    session.moved(&at(0.0, 0.0, 0.0), &at(3.0, 4.0, 0.0));
+    session.moved(&at(3.0, 4.0, 0.0), &at(3.0, 4.0, 12.0));
+    // Teleported far away in between: the next update starts from there
+    session.moved(&at(5000.0, 0.0, 0.0), &at(5000.0, 0.0, 1.0));
+
+    let summary = session.finish("Ada", 40, 4, DisconnectReason::Kicked, start + Duration::from_secs(90));
+    assert_eq!(summary, SessionSummary {
+        name: "Ada".to_string(),
+        duration: Duration::from_secs(90),
+        distance: 18.0,
+        messages: 5,
+        planets_discovered: 2,
+        xp_gained: 30,
+        resources_mined: 0,
+        reason: DisconnectReason::Kicked,
+    });
+    assert_eq!(summary.to_string(),
+               "Ada played 90s, flew 18.0, sent 5 message(s), discovered 2 planet(s), gained 30 XP, mined 0, left by kick");
+
+    // A regenerated world forgets discoveries, which never makes the count negative
+    assert_eq!(session.finish("Ada", 40, 0, DisconnectReason::Closed, start).planets_discovered, 0);
+}
+
+async fn last_session(server: &GameServer, name: &str) -> SessionSummary {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(summary) = server.last_session(name) {
+                return summary;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("no session summary")
+}
+
+#[tokio::test]
+async fn respawns_are_not_counted_as_flown() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    let spawn = loop {
+        if let ClientEvent::Joined { spawn, .. } = ada.next_event().await {
+            break spawn;
+        }
+    };
+    for x in [10.0, 20.0, 30.0] {
+        ada.send_position(at(spawn.x + x, spawn.y, spawn.z)).await.unwrap();
+    }
+    ada.send(&ClientMessage::Respawn {}).await.unwrap();
+    ada.send_position(at(spawn.x, spawn.y + 5.0, spawn.z)).await.unwrap();
+    assert!(run_line(&server, "whois Ada").unwrap().ends_with("no finished session yet"));
+    ada.close().await.unwrap();
+
+    let summary = last_session(&server, "ADA").await;
+    assert!((summary.distance - 35.0).abs() < 0.01, "{}", summary);
+    assert!(summary.messages >= 5, "{}", summary);
+    assert_eq!(summary.reason, DisconnectReason::Closed);
+    let whois = run_line(&server, "whois ada").unwrap();
+    assert_eq!(whois, format!("last session: {}", summary));
+    assert_eq!(run_line(&server, "whois Bob"), Err("nobody called Bob has played since the server started".to_string()));
+}
+
+#[tokio::test]
+async fn a_kick_ends_the_session_as_a_kick() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut bob = Connection::connect(&format!("ws://{}", addr), Some("Bob")).await.unwrap();
+    let player_id = loop {
+        if let ClientEvent::Joined { player_id, .. } = bob.next_event().await {
+            break player_id;
+        }
+    };
+    let whois = run_line(&server, "whois bob").unwrap();
+    assert!(whois.starts_with(&format!("#{} Bob is online from ", player_id)), "{}", whois);
+
+    run_line(&server, &format!("kick {}", player_id)).unwrap();
+    assert_eq!(last_session(&server, "Bob").await.reason, DisconnectReason::Kicked);
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use galavox::admin::run_line;
+use galavox::map::{render_ascii, MapSize};
+use galavox::protocol::{GameState, Position, PositionUpdate};
+use galavox::server::{Connection, GameServer};
+use galavox::snapshot::WorldView;
+use galavox::stats::ConnectionStats;
+use galavox::world::{load_world, save_world, WorldConfig};
+use galavox::world_file::WorldFile;
+use tokio::sync::Notify;
+
+fn connection(port: u16) -> Connection {
+    Connection {
+        addr: format!("127.0.0.1:{}", port).parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+/// A world of `count` planets in a grid far apart.
+fn large_world(count: u32) -> GameState {
+    let template = GameServer::new().get_state().planets[0].clone();
+    let planets = (0..count).map(|id| {
+        let mut planet = template.clone();
+        planet.id = id;
+        planet.position = Position { x: (id % 100) as f32 * 1_000.0, y: 0.0, z: (id / 100) as f32 * 1_000.0 };
+        planet
+    }).collect();
+    GameState::new(planets, Vec::new(), Position { x: 0.0, y: 0.0, z: 0.0 })
+}
+
+#[test]
+fn a_snapshot_keeps_its_world_while_the_server_edits_on() {
+    let server = GameServer::new();
+    server.add_player(connection(4000), "Ada".to_string()).unwrap();
+    let snapshot = server.snapshot();
+    let state = server.get_state();
+    assert_eq!(snapshot.to_state(), state);
+    assert_eq!(snapshot.to_json().unwrap(), save_world(&state).unwrap());
+    assert_eq!(render_ascii(&*snapshot, MapSize::ASCII), render_ascii(&state, MapSize::ASCII));
+    let config = WorldConfig::default();
+    assert_eq!(WorldFile::new(&*snapshot, &config).to_json().unwrap(), WorldFile::new(&state, &config).to_json().unwrap());
+
+    // Copy-on-write: the snapshot still has the planet the edit removed
+    let removed = state.planets[0].id;
+    run_line(&server, &format!("planet remove {}", removed)).unwrap();
+    assert!(snapshot.planets().iter().any(|p| p.id == removed));
+    assert!(server.snapshot().planets().iter().all(|p| p.id != removed));
+    assert_eq!(snapshot.players().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn saving_does_not_hold_up_the_ticks() {
+    // Generous, for unoptimized builds on a busy machine
+    const BUDGET: Duration = Duration::from_millis(500);
+    let server = GameServer::new().with_world(large_world(1_000));
+    let dir = std::env::temp_dir().join(format!("galavox-snapshot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("world.json");
+
+    let writing = Arc::new(AtomicBool::new(true));
+    let writers: Vec<_> = (0..4u16).map(|i| {
+        let server = server.clone();
+        std::thread::spawn(move || {
+            let (connection, _, _) = server.add_player(connection(5000 + i), format!("Writer_{}", i)).unwrap();
+            for seq in 1..=2_000 {
+                let position = Position { x: 200_000.0 + (seq % 100) as f32, y: i as f32, z: 0.0 };
+                server.update_player_position(connection, PositionUpdate { seq: Some(seq), position, velocity: [1.0, 0.0, 0.0], rotation: [0.0, 0.0, 0.0, 1.0] });
+            }
+        })
+    }).collect();
+    let saver = {
+        let (server, path, writing) = (server.clone(), path.clone(), writing.clone());
+        tokio::spawn(async move {
+            let mut saves = 0;
+            while writing.load(Ordering::Relaxed) || saves == 0 {
+                server.save_to_file(&path).await.unwrap();
+                saves += 1;
+            }
+            saves
+        })
+    };
+
+    let mut slowest = Duration::ZERO;
+    while writers.iter().any(|w| !w.is_finished()) {
+        let started = Instant::now();
+        server.broadcast_game_state().await;
+        slowest = slowest.max(started.elapsed());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    writing.store(false, Ordering::Relaxed);
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    assert!(saver.await.unwrap() > 0);
+    assert!(slowest < BUDGET, "a tick took {:?}", slowest);
+
+    let saved = load_world(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved.planets.len(), 1_000);
+    assert_eq!(saved.players.len(), 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::{connect_json, next_json, planet, player_name, spawn_server};
+use futures_util::future::join_all;
+use galavox::protocol::{GameState, Planet, Position, ServerMessage};
+use galavox::server::{Connection, GameServer};
+use galavox::spawn::{choose_spawn, disperse, spawn_angle, Spawn, DEFAULT_SPAWN_DISPERSION, SPAWN_CLEARANCE};
+use galavox::stats::ConnectionStats;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Notify;
+
+const ORIGIN: Position = Position { x: 0.0, y: 0.0, z: 0.0 };
+
+fn distance(a: &Position, b: &Position) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+#[test]
+fn spawns_go_to_the_least_populated_planet() {
+    let planets = [Planet { size: 50.0, ..planet(4, 0.0) }, Planet { size: 50.0, ..planet(9, 500.0) }];
+    let mut taken: Vec<Spawn> = Vec::new();
+    for _ in 0..4 {
+        let spawn = choose_spawn(&planets, &taken, &ORIGIN);
+        taken.push(spawn);
+    }
+    let planet_ids: Vec<_> = taken.iter().map(|s| s.planet_id).collect();
+    assert_eq!(planet_ids, [Some(4), Some(9), Some(4), Some(9)]);
+
+    // A freed slot is reused before a new one is opened
+    taken.remove(0);
+    let spawn = choose_spawn(&planets, &taken, &ORIGIN);
+    assert_eq!((spawn.planet_id, spawn.slot), (Some(4), 0));
+}
+
+#[test]
+fn spawns_clear_the_planet_surface() {
+    let planets = [Planet { size: 120.0, ..planet(1, 300.0) }];
+    let mut taken: Vec<Spawn> = Vec::new();
+    for _ in 0..20 {
+        let spawn = choose_spawn(&planets, &taken, &ORIGIN);
+        assert!(distance(&spawn.position, &planets[0].position) >= 60.0 + SPAWN_CLEARANCE - 1e-3, "{:?}", spawn);
+        taken.push(spawn);
+    }
+}
+
+#[test]
+fn without_planets_players_spawn_around_the_initial_location() {
+    let center = Position { x: 10.0, y: 20.0, z: 30.0 };
+    let first = choose_spawn(&[], [], &center);
+    let second = choose_spawn(&[], [&first], &center);
+    assert_eq!(first.planet_id, None);
+    assert!((distance(&first.position, &center) - SPAWN_CLEARANCE).abs() < 1e-3);
+    assert_ne!(first.position, second.position);
+}
+
+#[tokio::test]
+async fn simultaneous_joins_get_distinct_spawns() {
+    // Two planets for ten players, so several share a planet
+    let world = GameState::new(vec![planet(0, -400.0), planet(1, 400.0)], vec![], ORIGIN);
+    let server = GameServer::new().with_world(world.clone());
+    let addr = spawn_server(server.clone()).await;
+
+    // Every client has joined once its welcome arrives
+    let clients = join_all((0..10).map(|_| connect_json(addr))).await;
+    let state = server.get_state();
+
+    let spawns: Vec<Position> = clients.iter()
+        .map(|ws| state.players.iter().find(|p| *p.name == *player_name(ws)).unwrap().position.clone())
+        .collect();
+    for (i, a) in spawns.iter().enumerate() {
+        for b in &spawns[i + 1..] {
+            assert!(distance(a, b) >= 10.0, "{:?} and {:?} collide", a, b);
+        }
+        for planet in &world.planets {
+            assert!(distance(a, &planet.position) >= planet.size / 2.0, "{:?} is inside planet {}", a, planet.id);
+        }
+    }
+}
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+/// Where fifty players joining one after another on a one-planet world spawn.
+fn burst(server: &GameServer) -> Vec<Position> {
+    (0..50).map(|i| server.add_player(connection(), format!("Pilot{}", i)).unwrap().1.position).collect()
+}
+
+#[test]
+fn a_burst_of_joins_spreads_around_the_planet() {
+    let world = GameState::new(vec![planet(3, 300.0)], vec![], ORIGIN);
+    let spawns = burst(&GameServer::new().with_world(world.clone()));
+    for (i, a) in spawns.iter().enumerate() {
+        for b in &spawns[i + 1..] {
+            assert!(distance(a, b) >= DEFAULT_SPAWN_DISPERSION, "{:?} and {:?} collide", a, b);
+        }
+        assert!(distance(a, &world.planets[0].position) >= 50.0 + SPAWN_CLEARANCE - 1e-3, "{:?}", a);
+    }
+    // The same joins spawn in the same places
+    assert_eq!(burst(&GameServer::new().with_world(world.clone())), spawns);
+
+    // Without dispersion they fill the slots in order
+    let slotted = burst(&GameServer::new().with_world(world).with_spawn_dispersion(0.0));
+    assert_eq!(slotted[0], Position { x: 300.0 + 50.0 + SPAWN_CLEARANCE, y: 0.0, z: 0.0 });
+}
+
+#[test]
+fn dispersion_depends_only_on_name_nonce_and_neighbours() {
+    let center = Position { x: 300.0, y: 0.0, z: 0.0 };
+    let first = disperse(&center, 100.0, "Ada", 7, 10.0, []).unwrap();
+    assert_eq!(disperse(&center, 100.0, " ada ", 7, 10.0, []), Some(first.clone()), "names are normalized");
+    assert_ne!(disperse(&center, 100.0, "Ada", 8, 10.0, []), Some(first.clone()));
+    assert!((distance(&first, &center) - (50.0 + SPAWN_CLEARANCE)).abs() < 1e-3);
+    assert!((0.0..std::f32::consts::TAU).contains(&spawn_angle("Ada", 7, 0)));
+
+    // Someone already there moves Ada to her next attempt
+    let moved = disperse(&center, 100.0, "Ada", 7, 10.0, [&first]).unwrap();
+    assert!(distance(&moved, &first) >= 10.0);
+    // With nowhere clear, she keeps her slot
+    assert_eq!(disperse(&center, 100.0, "Ada", 7, 10_000.0, [&first]), None);
+}
+
+#[tokio::test]
+async fn join_ack_carries_the_spawn() {
+    let world = GameState::new(vec![planet(7, 300.0)], vec![], ORIGIN);
+    let addr = spawn_server(GameServer::new().with_world(world)).await;
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?format=json", addr)).await.unwrap();
+
+    let (spawn, spawn_planet_id) = loop {
+        if let ServerMessage::Joined { spawn, spawn_planet_id, .. } = next_json(&mut ws).await {
+            break (spawn, spawn_planet_id);
+        }
+    };
+    assert_eq!(spawn_planet_id, Some(7));
+    assert!((distance(&spawn, &Position { x: 300.0, y: 0.0, z: 0.0 }) - (50.0 + SPAWN_CLEARANCE)).abs() < 1e-3);
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::spawn_server;
+use galavox::admin::run_line;
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::{ErrorCode, MessageCode, Position};
+use galavox::server::GameServer;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+async fn next_matching<T>(conn: &mut Connection, mut f: impl FnMut(ClientEvent) -> Option<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = f(conn.next_event().await) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an event")
+}
+
+async fn snapshot_player_count(conn: &mut Connection) -> usize {
+    next_matching(conn, |e| match e {
+        ClientEvent::StateSnapshot { state, .. } => Some(state.players.len()),
+        _ => None,
+    }).await
+}
+
+async fn spectate(addr: SocketAddr) -> Connection {
+    let mut spectator = Connection::spectate(&format!("ws://{}", addr), false).await.unwrap();
+    next_matching(&mut spectator, |e| match e {
+        ClientEvent::Localized { message: MessageCode::WelcomeSpectator {}, .. } => Some(()),
+        ClientEvent::Joined { .. } => panic!("a spectator must not join as a player"),
+        _ => None,
+    }).await;
+    spectator
+}
+
+async fn wait_for_spectators(server: &GameServer, count: usize) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while server.spectator_list(Instant::now()).len() != count {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("spectator count did not settle");
+}
+
+#[tokio::test]
+async fn spectators_come_and_go_unseen() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    assert_eq!(snapshot_player_count(&mut ada).await, 1);
+
+    let mut spectator = spectate(addr).await;
+    wait_for_spectators(&server, 1).await;
+    assert_eq!(snapshot_player_count(&mut spectator).await, 1);
+    spectator.close().await.unwrap();
+    wait_for_spectators(&server, 0).await;
+
+    // Several broadcasts later Ada has seen nobody come or go
+    for _ in 0..3 {
+        let event = next_matching(&mut ada, |e| match e {
+            e @ (ClientEvent::PlayerJoined { .. } | ClientEvent::PlayerLeft { .. }) => Some(Some(e)),
+            ClientEvent::StateSnapshot { state, .. } => {
+                assert_eq!(state.players.len(), 1);
+                Some(None)
+            }
+            _ => None,
+        }).await;
+        assert!(event.is_none(), "unexpected {:?}", event);
+    }
+}
+
+#[tokio::test]
+async fn spectators_cannot_move_or_chat() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut spectator = spectate(addr).await;
+
+    spectator.send_chat("hello").await.unwrap();
+    spectator.send_position(Position { x: 1.0, y: 2.0, z: 3.0 }).await.unwrap();
+    let notice = next_matching(&mut spectator, |e| match e {
+        ClientEvent::Chat { .. } => panic!("a spectator's chat was echoed"),
+        ClientEvent::Localized { message, text } => Some((message, text)),
+        _ => None,
+    }).await;
+    assert_eq!(notice.0, MessageCode::SpectatorReadOnly {});
+    assert!(notice.1.contains("Spectators cannot"), "{}", notice.1);
+    assert_eq!(snapshot_player_count(&mut spectator).await, 0);
+}
+
+#[tokio::test]
+async fn spectator_slots_are_separate_from_player_slots() {
+    let server = GameServer::new().with_max_players(Some(1)).with_max_spectators(Some(1));
+    let addr = spawn_server(server.clone()).await;
+    let url = format!("ws://{}", addr);
+    let mut ada = Connection::connect(&url, Some("Ada")).await.unwrap();
+    snapshot_player_count(&mut ada).await;
+
+    // The only player slot is taken, but a spectator still gets in
+    let _spectator = spectate(addr).await;
+    wait_for_spectators(&server, 1).await;
+
+    let mut turned_away = Connection::spectate(&url, false).await.unwrap();
+    let code = next_matching(&mut turned_away, |e| match e {
+        ClientEvent::Disconnected { code, .. } => Some(code),
+        _ => None,
+    }).await;
+    assert_eq!(code, Some(ErrorCode::ServerFull as u16));
+
+    let listing = run_line(&server, "players").unwrap();
+    assert!(listing.contains("Ada"), "{}", listing);
+    assert!(listing.contains("1 spectator(s):"), "{}", listing);
+}
@@ -0,0 +1,72 @@
+use galavox::config::{ConfigSource, ServerConfig};
+use galavox::startup::{check, Problem};
+
+fn fixture(name: &str) -> Vec<String> {
+    vec!["--config".to_string(), format!("tests/fixtures/check/{}.toml", name)]
+}
+
+fn messages(problems: &[Problem]) -> Vec<String> {
+    problems.iter().map(Problem::to_string).collect()
+}
+
+#[tokio::test]
+async fn unparseable_configs_and_flags_are_reported_with_their_source() {
+    let problems = check(&fixture("syntax")).await;
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].source, "tests/fixtures/check/syntax.toml");
+    assert!(problems[0].message.contains("line 2"), "{}", problems[0]);
+
+    let problems = check(&["--max-players".to_string(), "nope".to_string()]).await;
+    assert_eq!(messages(&problems), ["command line: invalid value for --max-players: nope"]);
+    let problems = check(&["--config".to_string(), "tests/fixtures/check/missing.toml".to_string()]).await;
+    assert_eq!(problems[0].source, "tests/fixtures/check/missing.toml");
+}
+
+#[tokio::test]
+async fn every_bad_setting_is_reported_at_once() {
+    assert_eq!(messages(&check(&fixture("settings")).await), [
+        "config: moon_chance must be between 0 and 1",
+        "config: palette sunset: invalid color \"orange\" (expected #rrggbb)",
+        "config: trusted_proxies: invalid address in CIDR: not-a-network",
+    ]);
+}
+
+#[tokio::test]
+async fn the_files_a_config_names_are_read_and_checked() {
+    let problems = check(&fixture("files")).await;
+    assert_eq!(problems[0].to_string(), "tests/fixtures/check/no-such-world.json: cannot read: No such file or directory (os error 2)");
+    #[cfg(feature = "tls")]
+    assert!(problems[1].source == "tls" && problems[1].message.contains("no-such-cert.pem"), "{}", problems[1]);
+    #[cfg(not(feature = "tls"))]
+    assert_eq!(problems[1].to_string(), "config: TLS requested but the server was built without the `tls` feature");
+    assert_eq!(problems.len(), 2);
+
+    // One problem per invalid entry of an imported world
+    assert_eq!(messages(&check(&fixture("import")).await), [
+        "tests/fixtures/worlds/duplicate_ids.json: planets[1].id: id 4 is already used by planets[0].id",
+        "tests/fixtures/worlds/duplicate_ids.json: belts[0].notable[0].id: id 4 is already used by planets[0].id",
+    ]);
+}
+
+#[tokio::test]
+async fn a_config_that_passes_the_check_builds_the_server() {
+    let args = fixture("valid");
+    assert!(check(&args).await.is_empty());
+
+    let config = ServerConfig::read(&args).unwrap();
+    let validated = config.validate().unwrap();
+    let radius = validated.world_config().radius;
+    let server = config.build(validated, ConfigSource::new(None, args, config.clone()));
+    let imported = std::fs::read_to_string("tests/fixtures/worlds/valid.json").unwrap();
+    let imported: serde_json::Value = serde_json::from_str(&imported).unwrap();
+    assert_eq!(radius, imported["radius"].as_f64().unwrap() as f32);
+    assert_eq!(server.get_state().planets.len(), imported["planets"].as_array().unwrap().len());
+}
+
+#[cfg(feature = "cluster")]
+#[tokio::test]
+async fn an_unreachable_cluster_fails_the_check() {
+    let problems = check(&["--cluster".to_string(), "redis://127.0.0.1:1".to_string()]).await;
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].source, "cluster");
+}
@@ -0,0 +1,135 @@
+use galavox::color::LabelContrast;
+use galavox::diff::StateDiff;
+use galavox::protocol::{Asteroid, Belt, Color, GameState, Moon, Planet, Player, PlayerAppearance, Position, TerrainParams};
+use proptest::prelude::*;
+
+fn position() -> impl Strategy<Value = Position> {
+    (-1000.0f32..1000.0, -1000.0f32..1000.0, -1000.0f32..1000.0).prop_map(|(x, y, z)| Position { x, y, z })
+}
+
+fn color() -> impl Strategy<Value = Color> {
+    any::<(u8, u8, u8)>().prop_map(|(r, g, b)| Color { r, g, b })
+}
+
+fn moon() -> impl Strategy<Value = Moon> {
+    (prop_oneof![Just(5.0f32), Just(10.0f32)], color(), 60.0f32..120.0)
+        .prop_map(|(size, color, orbit_radius)| Moon { size, color, orbit_radius, angular_speed: 0.1, phase: 0.0 })
+}
+
+fn planet(id: u32) -> impl Strategy<Value = Planet> {
+    // Few distinct values so that planets often share fields
+    let owner = prop_oneof![Just(None), (0u32..2).prop_map(Some)];
+    let moons = prop::collection::vec(moon(), 0..=1);
+    (prop_oneof![Just(50.0f32), Just(100.0f32)], [color(), color(), color()], 0u8..3, position(), owner, moons, 0u32..3)
+        .prop_map(move |(size, colors, module_type, position, owner, moons, resources)| {
+            let mut planet = Planet {
+                id, size, colors, module_type, position, owner, moons, resources, capacity: 2, terrain: TerrainParams::default(), name: String::new(),
+                primary_display_color: Color::default(), label_contrast: LabelContrast::default(),
+            };
+            // As the server keeps them, label colors following the colors
+            planet.refresh_display();
+            planet
+        })
+}
+
+fn planets() -> impl Strategy<Value = Vec<Planet>> {
+    proptest::sample::subsequence((0u32..8).collect::<Vec<_>>(), 0..=6)
+        .prop_flat_map(|ids| ids.into_iter().map(planet).collect::<Vec<_>>())
+        .prop_shuffle()
+}
+
+fn player(id: u32) -> impl Strategy<Value = Player> {
+    (0u32..3, position(), any::<bool>(), 0u32..4).prop_map(move |(level, position, idle, seq)| Player {
+        id,
+        name: format!("Player_{}", id).into(),
+        level,
+        position,
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: seq,
+        idle,
+        appearance: PlayerAppearance::for_name(&format!("Player_{}", id)),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    })
+}
+
+fn players() -> impl Strategy<Value = Vec<Player>> {
+    // Ids from a small range so two states usually share some players
+    proptest::sample::subsequence((0u32..12).collect::<Vec<_>>(), 0..=12)
+        .prop_flat_map(|ids| ids.into_iter().map(player).collect::<Vec<_>>())
+        .prop_shuffle()
+}
+
+fn belt() -> impl Strategy<Value = Belt> {
+    (0u32..1000, position()).prop_map(|(resources, position)| Belt {
+        center_radius: 1000.0,
+        width: 100.0,
+        density: 1.0,
+        rock_seed: 7,
+        notable: vec![Asteroid { id: 100, size: 10.0, position, resources }],
+    })
+}
+
+fn state() -> impl Strategy<Value = GameState> {
+    let belts = prop::collection::vec(belt(), 0..=1);
+    (planets(), players(), prop_oneof![Just(Position { x: 0.0, y: 0.0, z: 0.0 }), position()], belts)
+        .prop_map(|(planets, players, initial_player_location, belts)| {
+            let mut state = GameState::new(planets, players, initial_player_location);
+            state.belts = belts;
+            state
+        })
+}
+
+proptest! {
+    #[test]
+    fn applying_a_diff_yields_the_target(a in state(), b in state()) {
+        let diff = a.diff(&b);
+        let mut patched = a.clone();
+        patched.apply(&diff);
+        prop_assert_eq!(patched, b);
+    }
+
+    #[test]
+    fn diff_survives_bincode(a in state(), b in state()) {
+        let diff = a.diff(&b);
+        let bytes = bincode::serialize(&diff).unwrap();
+        let decoded: StateDiff = bincode::deserialize(&bytes).unwrap();
+        prop_assert_eq!(decoded, diff);
+    }
+
+    #[test]
+    fn diff_with_self_is_empty(a in state()) {
+        prop_assert!(a.diff(&a).is_empty());
+    }
+}
+
+#[test]
+fn only_changed_planet_fields_are_recorded() {
+    let planet = Planet {
+        id: 0,
+        size: 80.0,
+        colors: [Color { r: 1, g: 2, b: 3 }, Color { r: 4, g: 5, b: 6 }, Color { r: 7, g: 8, b: 9 }],
+        module_type: 2,
+        position: Position { x: 1.0, y: 2.0, z: 3.0 },
+        owner: None,
+        moons: vec![],
+        resources: 0,
+        capacity: 0,
+        terrain: TerrainParams::default(),
+        name: String::new(),
+        primary_display_color: Color::default(),
+        label_contrast: LabelContrast::default(),
+    };
+    let a = GameState::new(vec![planet.clone()], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+    let mut b = a.clone();
+    b.planets[0].module_type = 4;
+
+    let diff = a.diff(&b);
+    assert_eq!(diff.changed_planets.len(), 1);
+    let change = &diff.changed_planets[0];
+    assert_eq!(change.module_type, Some(4));
+    assert_eq!((change.size, &change.colors, &change.position, &change.owner), (None, &None, &None, &None));
+    assert_eq!(change.moons, None);
+}
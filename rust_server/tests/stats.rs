@@ -0,0 +1,177 @@
+mod common;
+
+use common::{next_message, spawn_server, Client};
+use futures_util::SinkExt;
+use galavox::admin;
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::{ClientMessage, Position, ServerMessage, IDENTITY_ROTATION};
+use galavox::server::GameServer;
+use galavox::stats::{ConnectionStats, DebugWindow};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn position(seq: u32, speed: f32) -> String {
+    let message = ClientMessage::Position {
+        seq: Some(seq),
+        position: Position { x: seq as f32, y: 0.0, z: 0.0 },
+        velocity: [speed, 0.0, 0.0],
+        rotation: IDENTITY_ROTATION,
+    };
+    serde_json::to_string(&message).unwrap()
+}
+
+/// Next JSON server message and its size on the wire.
+async fn next_sized(ws: &mut Client) -> (ServerMessage, usize) {
+    match next_message(ws).await {
+        Some(Message::Text(text)) => (serde_json::from_str(&text).unwrap(), text.len()),
+        other => panic!("expected a JSON message, got {:?}", other),
+    }
+}
+
+#[test]
+fn snapshot_reports_last_message_age() {
+    let start = Instant::now();
+    let stats = ConnectionStats::new(start);
+    assert_eq!(stats.snapshot(start).last_message_age, None);
+
+    stats.record_received(10, start + Duration::from_secs(2));
+    stats.record_received(5, start + Duration::from_secs(3));
+    let snapshot = stats.snapshot(start + Duration::from_secs(10));
+    assert_eq!(snapshot.last_message_age, Some(Duration::from_secs(7)));
+    assert_eq!((snapshot.messages_received, snapshot.bytes_received), (2, 15));
+    assert_eq!(snapshot.connected_for, Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn counts_a_scripted_exchange() {
+    // After the first broadcast, which goes out at once, no more reach the
+    // client, so every message it gets is a reply
+    let server = GameServer::new().with_broadcast_interval(Duration::from_secs(3600));
+    let addr = spawn_server(server.clone()).await;
+    while server.current_tick() == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?format=json", addr)).await.unwrap();
+
+    let mut player_id = None;
+    let mut bytes_sent = 0;
+    let mut messages_sent = 0;
+    loop {
+        let (message, size) = next_sized(&mut ws).await;
+        messages_sent += 1;
+        bytes_sent += size;
+        match message {
+            ServerMessage::Joined { player_id: id, .. } => player_id = Some(id),
+            ServerMessage::Notice { text } if text.starts_with("Welcome") => break,
+            _ => continue,
+        }
+    }
+    let player_id = player_id.unwrap();
+
+    let script = [
+        position(1, 0.0),
+        position(2, 0.0),
+        position(3, 0.0),
+        position(2, 0.0),        // stale
+        position(4, 100_000.0),  // too fast
+        r#"{"type":"Chat","text":"ping"}"#.to_string(),
+    ];
+    for text in &script {
+        ws.send(Message::Text(text.as_str().into())).await.unwrap();
+    }
+    let (echo, size) = next_sized(&mut ws).await;
+    assert_eq!(echo, ServerMessage::Echo { text: "ping".to_string() });
+    messages_sent += 1;
+    bytes_sent += size;
+
+    let stats = server.connection_stats(player_id, Instant::now()).unwrap();
+    assert_eq!(stats.messages_received, script.len() as u64);
+    assert_eq!(stats.bytes_received, script.iter().map(|t| t.len() as u64).sum::<u64>());
+    assert_eq!(stats.messages_sent, messages_sent);
+    assert_eq!(stats.bytes_sent, bytes_sent as u64);
+    assert_eq!((stats.positions_accepted, stats.positions_rejected), (3, 2));
+    assert!(stats.last_message_age.unwrap() < Duration::from_secs(5));
+
+    let verbose = admin::run_line(&server, "players -v").unwrap();
+    assert!(verbose.contains(&format!("#{} ", player_id)) && verbose.contains("positions=3 ok/2 rejected"), "{}", verbose);
+    assert!(!admin::run_line(&server, "players").unwrap().contains("rx="));
+    assert!(admin::run_line(&server, &format!("stats {}", player_id)).unwrap().contains("rx=6 msgs"));
+    assert!(admin::run_line(&server, "stats 999").is_err());
+
+    drop(ws);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.connection_stats(player_id, Instant::now()), None);
+}
+
+#[test]
+fn debug_frames_cover_the_updates_since_the_last_one() {
+    let start = Instant::now();
+    let stats = ConnectionStats::new(start);
+    stats.record_position(true);
+    let mut window = DebugWindow::new(&stats.snapshot(start));
+    stats.record_position(true);
+    stats.record_position(true);
+    stats.record_position(false);
+    stats.set_send_queue_depth(4);
+
+    let frame = window.frame(&stats.snapshot(start), 9);
+    assert_eq!(frame, ServerMessage::DebugStats {
+        tick: 9, updates_received: 3, updates_accepted: 2, updates_rejected: 1, send_queue_depth: 4, rtt_ms: None, bandwidth_level: 0,
+    });
+    let Message::Ping(payload) = DebugWindow::ping(1_000) else { panic!() };
+    window.pong(b"not ours", 1_040);
+    window.pong(&payload, 1_040);
+    let frame = window.frame(&stats.snapshot(start), 10);
+    assert!(matches!(frame, ServerMessage::DebugStats { updates_received: 0, rtt_ms: Some(40), .. }), "{:?}", frame);
+}
+
+/// The next `DebugStats` frame, skipping other events.
+async fn next_debug_stats(conn: &mut Connection, within: Duration) -> Option<ServerMessage> {
+    tokio::time::timeout(within, async {
+        loop {
+            if let ClientEvent::Message(message @ ServerMessage::DebugStats { .. }) = conn.next_event().await {
+                return message;
+            }
+        }
+    })
+    .await
+    .ok()
+}
+
+#[tokio::test]
+async fn subscribers_get_their_own_debug_stats() {
+    let addr = spawn_server(GameServer::new()).await;
+    let url = format!("ws://{}", addr);
+    let mut ada = Connection::connect(&url, Some("Ada")).await.unwrap();
+    let mut bob = Connection::connect(&url, Some("Bob")).await.unwrap();
+
+    ada.set_debug_stats(true).await.unwrap();
+    for x in 1..=3 {
+        ada.send_position(Position { x: x as f32, y: 0.0, z: 0.0 }).await.unwrap();
+    }
+    let stale = ClientMessage::Position { seq: Some(1), position: Position { x: 0.0, y: 0.0, z: 0.0 }, velocity: [0.0; 3], rotation: IDENTITY_ROTATION };
+    ada.send(&stale).await.unwrap();
+
+    // The updates may straddle two frames; the round trip is known from the
+    // second frame on
+    let (mut accepted, mut rejected, mut rtt) = (0, 0, None);
+    while (accepted, rejected) != (3, 1) || rtt.is_none() {
+        let Some(ServerMessage::DebugStats { updates_received, updates_accepted, updates_rejected, rtt_ms, .. }) =
+            next_debug_stats(&mut ada, Duration::from_secs(5)).await
+        else {
+            panic!("no debug stats after {} accepted, {} rejected", accepted, rejected);
+        };
+        assert_eq!(updates_received, updates_accepted + updates_rejected);
+        accepted += updates_accepted;
+        rejected += updates_rejected;
+        rtt = rtt.or(rtt_ms);
+    }
+    assert!(rtt.unwrap() < 1_000);
+
+    // Once the echo is back the server has seen the unsubscribe too
+    ada.set_debug_stats(false).await.unwrap();
+    ada.send_chat("done").await.unwrap();
+    while !matches!(ada.next_event().await, ClientEvent::Chat { .. }) {}
+    assert_eq!(next_debug_stats(&mut ada, Duration::from_millis(1_500)).await, None);
+    assert_eq!(next_debug_stats(&mut bob, Duration::from_millis(100)).await, None);
+}
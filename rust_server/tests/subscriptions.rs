@@ -0,0 +1,115 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect_json, next_json, send_text, spawn_server, Client, TEST_CAPS};
+use galavox::broadcast::BroadcastFrame;
+use galavox::protocol::{Batched, Capabilities, ChatLine, ClientMessage, Position, ServerMessage, WireFormat};
+use galavox::server::GameServer;
+use galavox::subscriptions::Subscriptions;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn send(message: &ClientMessage) -> String {
+    serde_json::to_string(message).unwrap()
+}
+
+fn is_state(message: &ServerMessage) -> bool {
+    matches!(message, ServerMessage::State { .. } | ServerMessage::Resync { .. } | ServerMessage::PlayerTeleported { .. })
+}
+
+#[test]
+fn only_the_kinds_subscribed_to_are_wanted() {
+    let announcement = ServerMessage::Announcement { text: "hi".to_string() };
+    let idle = ServerMessage::PlayerIdle { player_id: 1 };
+    let chat = Subscriptions::CHAT;
+    assert!(chat.wants(&announcement));
+    assert!(chat.wants(&ServerMessage::Chatted { tick: 1, line: ChatLine { sender: "Ada".to_string(), timestamp_ms: 0, room: None, text: "hi".to_string() } }));
+    assert!(!chat.wants(&idle));
+    assert!(chat.wants(&ServerMessage::Echo { text: "hi".to_string() }), "replies always go out");
+    assert!(Subscriptions::NONE.wants(&ServerMessage::KeepaliveAck { seq: 1 }));
+    assert!(Subscriptions::default().wants(&idle));
+    assert_eq!(Subscriptions::from_mask(u32::MAX), Subscriptions::ALL);
+
+    // A batch loses the events not wanted, and keeps the rest together
+    let frame = BroadcastFrame::new(ServerMessage::Batch { messages: Batched(vec![idle.clone(), announcement.clone(), announcement.clone()]) });
+    let decode = |frames: Vec<Message>| -> Vec<ServerMessage> {
+        frames.iter().map(|frame| serde_json::from_str(frame.to_text().unwrap()).unwrap()).collect()
+    };
+    assert_eq!(decode(frame.frames_for(WireFormat::Json, Capabilities::BATCH, chat)),
+               vec![ServerMessage::Batch { messages: Batched(vec![announcement.clone(), announcement.clone()]) }]);
+    assert_eq!(decode(frame.frames_for(WireFormat::Json, Capabilities::NONE, chat)), vec![announcement.clone(), announcement]);
+    assert_eq!(decode(frame.frames_for(WireFormat::Json, Capabilities::NONE, Subscriptions::PLAYERS)), vec![idle]);
+    assert!(frame.frames_for(WireFormat::Json, Capabilities::BATCH, Subscriptions::WORLD).is_empty());
+}
+
+/// Connects in JSON mode subscribed to `subscriptions`; the welcome is the
+/// last join message.
+async fn connect_subscribed(addr: std::net::SocketAddr, subscriptions: Subscriptions) -> (Client, Vec<ServerMessage>) {
+    let url = format!("ws://{}/?format=json&caps={}&subs={}", addr, TEST_CAPS.0, subscriptions.0);
+    let mut ws = connect_async(url).await.unwrap().0;
+    let mut joined = Vec::new();
+    loop {
+        let message = next_json(&mut ws).await;
+        let welcome = matches!(message, ServerMessage::Localized { .. });
+        joined.push(message);
+        if welcome {
+            return (ws, joined);
+        }
+    }
+}
+
+#[tokio::test]
+async fn a_chat_only_client_gets_no_state() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let (mut reader, joined) = connect_subscribed(addr, Subscriptions::CHAT).await;
+    assert!(!joined.iter().any(is_state), "{:?}", joined);
+    assert!(joined.iter().any(|message| matches!(message, ServerMessage::Joined { .. })));
+
+    // A busy session: players flying about, tick after tick, one of them
+    // chatting as they go
+    let mut movers = Vec::new();
+    for _ in 0..3 {
+        movers.push(connect_json(addr).await);
+    }
+    for seq in 0..20 {
+        for mover in movers.iter_mut() {
+            let position = Position { x: 100.0 + seq as f32, y: 0.0, z: 0.0 };
+            send_text(mover, &send(&ClientMessage::Position { seq: Some(seq), position, velocity: [1.0, 0.0, 0.0], rotation: [0.0, 0.0, 0.0, 1.0] })).await;
+        }
+        if seq % 10 == 5 {
+            send_text(&mut movers[0], &send(&ClientMessage::Chat { text: format!("at {}", seq), client_ref: None })).await;
+        }
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+    send_text(&mut reader, &send(&ClientMessage::Chat { text: "anyone?".to_string(), client_ref: None })).await;
+
+    // Everything sent to the reader meanwhile, until the chat's echo and an
+    // announcement, which goes out after every chat queued before it
+    let mut seen = Vec::new();
+    loop {
+        let message = next_json(&mut reader).await;
+        match &message {
+            ServerMessage::Echo { .. } => { server.announce("{player_count} online").unwrap(); }
+            ServerMessage::Announcement { text } => {
+                assert_eq!(text, "4 online");
+                break;
+            }
+            _ => {}
+        }
+        seen.push(message);
+    }
+    assert!(!seen.iter().any(is_state), "{:?}", seen);
+    let chats: Vec<&str> = seen.iter().filter_map(|message| match message {
+        ServerMessage::Chatted { line, .. } => Some(line.text.as_str()),
+        _ => None,
+    }).collect();
+    assert_eq!(chats, ["at 5", "at 15", "anyone?"]);
+
+    // Subscribing to State brings the next one
+    send_text(&mut reader, &send(&ClientMessage::SetSubscriptions { mask: (Subscriptions::CHAT | Subscriptions::STATE).0 })).await;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !matches!(next_json(&mut reader).await, ServerMessage::State { .. }) {}
+    }).await.expect("no State after subscribing");
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::{next_json, planet, spawn_server, Client};
+use futures_util::SinkExt;
+use galavox::admin::PlanetEdit;
+use galavox::protocol::{ClientMessage, GameState, Planet, Position, ServerMessage, TeleportError};
+use galavox::server::GameServer;
+use galavox::teleport::{TeleportConfig, TeleportCooldown};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[test]
+fn cooldown_holds_across_rapid_requests() {
+    let start = Instant::now();
+    let owned = Planet { owner: Some(5), ..planet(1, 0.0) };
+    let mut cooldown = TeleportCooldown::new(TeleportConfig::default());
+
+    assert!(cooldown.try_teleport(5, 1, Some(&owned), start).is_ok());
+    for ms in [0, 1, 500, 10_000, 29_999] {
+        let now = start + Duration::from_millis(ms);
+        assert_eq!(
+            cooldown.try_teleport(5, 1, Some(&owned), now),
+            Err(TeleportError::Cooldown { remaining_ms: 30_000 - ms }),
+        );
+    }
+    // Rejections did not restart it; the next teleport does
+    assert!(cooldown.try_teleport(5, 1, Some(&owned), start + Duration::from_secs(30)).is_ok());
+    assert!(cooldown.try_teleport(5, 1, Some(&owned), start + Duration::from_secs(31)).is_err());
+}
+
+#[test]
+fn only_owned_planets_unless_casual() {
+    let now = Instant::now();
+    let theirs = Planet { owner: Some(9), ..planet(2, 0.0) };
+    let unowned = planet(3, 0.0);
+
+    let mut strict = TeleportCooldown::new(TeleportConfig::default());
+    assert_eq!(strict.try_teleport(5, 2, Some(&theirs), now), Err(TeleportError::NotOwner { planet_id: 2 }));
+    assert_eq!(strict.try_teleport(5, 3, Some(&unowned), now), Err(TeleportError::NotOwner { planet_id: 3 }));
+    assert_eq!(strict.try_teleport(5, 4, None, now), Err(TeleportError::UnknownPlanet { planet_id: 4 }));
+    // None of the rejections started the cooldown
+    assert_eq!(strict.remaining(now), Duration::ZERO);
+
+    let mut casual = TeleportCooldown::new(TeleportConfig { casual: true, ..TeleportConfig::default() });
+    assert!(casual.try_teleport(5, 2, Some(&theirs), now).is_ok());
+}
+
+/// Connects in JSON mode and returns the player's id and spawn point.
+async fn join(addr: std::net::SocketAddr) -> (Client, u32, Position) {
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?format=json", addr)).await.unwrap();
+    let mut joined = None;
+    loop {
+        match next_json(&mut ws).await {
+            ServerMessage::Joined { player_id, spawn, .. } => joined = Some((player_id, spawn)),
+            ServerMessage::Notice { text } if text.starts_with("Welcome") => break,
+            _ => continue,
+        }
+    }
+    let (player_id, spawn) = joined.unwrap();
+    (ws, player_id, spawn)
+}
+
+async fn send(ws: &mut Client, message: &ClientMessage) {
+    ws.send(Message::Text(serde_json::to_string(message).unwrap().into())).await.unwrap();
+}
+
+/// Next reply to a teleport or respawn request.
+async fn next_teleport_reply(ws: &mut Client) -> ServerMessage {
+    loop {
+        match next_json(ws).await {
+            reply @ (ServerMessage::PlayerTeleported { .. } | ServerMessage::TeleportRejected { .. }) => return reply,
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn teleport_and_respawn_over_the_wire() {
+    let world = GameState::new(vec![planet(0, -500.0), planet(1, 500.0)], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+    let server = GameServer::new().with_world(world);
+    let addr = spawn_server(server.clone()).await;
+    let (mut ws, player_id, spawn) = join(addr).await;
+    server.update_planet(1, &PlanetEdit::Owner(Some(player_id))).unwrap();
+
+    send(&mut ws, &ClientMessage::TeleportToPlanet { planet_id: 0 }).await;
+    assert_eq!(next_teleport_reply(&mut ws).await, ServerMessage::TeleportRejected { error: TeleportError::NotOwner { planet_id: 0 } });
+    send(&mut ws, &ClientMessage::TeleportToPlanet { planet_id: 42 }).await;
+    assert_eq!(next_teleport_reply(&mut ws).await, ServerMessage::TeleportRejected { error: TeleportError::UnknownPlanet { planet_id: 42 } });
+
+    send(&mut ws, &ClientMessage::TeleportToPlanet { planet_id: 1 }).await;
+    let ServerMessage::PlayerTeleported { player_id: moved, position } = next_teleport_reply(&mut ws).await else {
+        panic!("teleport to an owned planet was rejected");
+    };
+    assert_eq!(moved, player_id);
+    assert!(position.x > 500.0, "landed next to planet 1: {:?}", position);
+
+    for _ in 0..3 {
+        send(&mut ws, &ClientMessage::TeleportToPlanet { planet_id: 1 }).await;
+        match next_teleport_reply(&mut ws).await {
+            ServerMessage::TeleportRejected { error: TeleportError::Cooldown { remaining_ms } } => {
+                assert!(remaining_ms > 25_000 && remaining_ms <= 30_000, "{}", remaining_ms);
+            }
+            other => panic!("expected a cooldown rejection, got {:?}", other),
+        }
+    }
+
+    // Respawn is not a teleport, so the cooldown does not apply
+    send(&mut ws, &ClientMessage::Respawn {}).await;
+    assert_eq!(next_teleport_reply(&mut ws).await, ServerMessage::PlayerTeleported { player_id, position: spawn.clone() });
+    let me = server.get_state().players.into_iter().find(|p| p.id == player_id).unwrap();
+    assert_eq!(me.position, spawn);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::spawn_server;
+use galavox::admin::{run_line, PlanetLimits};
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::{encoding_for, Capabilities, GameState, ServerMessage, TerrainParams};
+use galavox::server::GameServer;
+use galavox::world::WorldConfig;
+use galavox::world_file::WorldFile;
+use galavox::worldgen::{generate_terrain, generate_world, terrain_seed, RingGenerator};
+
+fn seeded(seed: u64) -> GameState {
+    generate_world(&RingGenerator, &WorldConfig { seed: Some(seed), ..WorldConfig::default() })
+}
+
+#[test]
+fn terrain_is_pinned_by_the_seed_and_module_type() {
+    let planets = seeded(42).planets;
+    let terrain: Vec<(u8, TerrainParams)> = planets.iter().take(3).map(|p| (p.module_type, p.terrain)).collect();
+    assert_eq!(terrain, [
+        (1, TerrainParams { seed: 3712212126764145572, roughness: 0.72162503, ocean_level: 0.11470887, atmosphere: 26 }),
+        (0, TerrainParams { seed: 17156893137563229957, roughness: 0.5618139, ocean_level: 0.24430099, atmosphere: 0 }),
+        (2, TerrainParams { seed: 7672805708946663998, roughness: 0.32558617, ocean_level: 0.61200505, atmosphere: 226 }),
+    ]);
+    assert_eq!(seeded(42).planets, planets);
+
+    // The module type shapes the ranges; the seed stays the planet's own
+    let defense = generate_terrain(terrain_seed(42, 0), 1);
+    let trade = generate_terrain(terrain_seed(42, 0), 2);
+    assert_eq!(defense.seed, trade.seed);
+    assert!(defense.roughness >= 0.6 && defense.ocean_level <= 0.2);
+    assert!(trade.roughness <= 0.5 && trade.atmosphere >= 120);
+    assert!(seeded(7).planets.iter().all(|p| p.terrain.validate().is_ok()));
+}
+
+#[test]
+fn binary_frames_carry_terrain_only_for_clients_that_know_it() {
+    let state = seeded(42);
+    let message = ServerMessage::State { tick: 1, server_time_ms: 2, state: state.clone() };
+    let full = encoding_for(Capabilities::DEFAULT, || message.to_bincode()).unwrap();
+    let old = encoding_for(Capabilities::NONE, || message.to_bincode()).unwrap();
+    assert!(old.len() < full.len());
+
+    assert_eq!(encoding_for(Capabilities::DEFAULT, || ServerMessage::from_bincode(&full)).unwrap(), message);
+    let ServerMessage::State { state: decoded, .. } = encoding_for(Capabilities::NONE, || ServerMessage::from_bincode(&old)).unwrap()
+    else { panic!("not a state") };
+    assert!(decoded.planets.iter().all(|p| p.terrain == TerrainParams::default()));
+    assert_eq!(decoded.planets.len(), state.planets.len());
+    // JSON always carries it
+    assert_eq!(encoding_for(Capabilities::NONE, || serde_json::to_string(&message)).unwrap(), serde_json::to_string(&message).unwrap());
+}
+
+#[test]
+fn worlds_saved_before_terrain_still_load() {
+    let state = seeded(3);
+    let mut json: serde_json::Value = serde_json::from_str(&WorldFile::new(&state, &WorldConfig::default()).to_json().unwrap()).unwrap();
+    for planet in json["planets"].as_array_mut().unwrap() {
+        planet.as_object_mut().unwrap().remove("terrain");
+    }
+    let loaded = WorldFile::parse(&json.to_string(), &PlanetLimits::default()).unwrap().into_state();
+    assert_eq!(loaded.planets.len(), state.planets.len());
+    assert!(loaded.planets.iter().all(|p| p.terrain == TerrainParams::default()));
+}
+
+#[test]
+fn admins_can_edit_terrain_within_range() {
+    let server = GameServer::new();
+    let id = server.get_state().planets[0].id;
+    run_line(&server, &format!("planet set {} roughness 0.25", id)).unwrap();
+    run_line(&server, &format!("planet set {} atmosphere 200", id)).unwrap();
+    run_line(&server, &format!("planet set {} terrain_seed 99", id)).unwrap();
+    let edited = server.get_state().planets[0].terrain;
+    assert_eq!((edited.roughness, edited.atmosphere, edited.seed), (0.25, 200, 99));
+
+    assert!(run_line(&server, &format!("planet set {} roughness 1.5", id)).is_err());
+    assert!(run_line(&server, &format!("planet set {} ocean_level -0.1", id)).is_err());
+    assert!(run_line(&server, &format!("planet set {} atmosphere 256", id)).is_err());
+    assert_eq!(server.get_state().planets[0].terrain, edited);
+}
+
+#[tokio::test]
+async fn old_clients_get_planets_without_terrain() {
+    let server = GameServer::new();
+    let expected = server.get_state().planets[0].terrain;
+    let addr = spawn_server(server).await;
+    let url = format!("ws://{}", addr);
+    for (capabilities, terrain) in [(Capabilities::NONE, TerrainParams::default()), (Capabilities::DEFAULT, expected)] {
+        let mut conn = Connection::connect_with_capabilities(&url, None, false, capabilities).await.unwrap();
+        let state = loop {
+            if let ClientEvent::StateSnapshot { state, .. } = conn.next_event().await {
+                break state;
+            }
+        };
+        assert_eq!(state.planets[0].terrain, terrain, "{:?}", capabilities);
+    }
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::{connect, next_server_message, spawn_server};
+use futures_util::SinkExt;
+use galavox::protocol::{encode_time_sync_request, ServerMessage};
+use galavox::server::GameServer;
+use galavox::time_sync::{estimate_clock_offset, ClockSample};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[test]
+fn estimate_uses_lowest_rtt_sample() {
+    let samples = [
+        ClockSample { client_send_ms: 0, server_time_ms: 1_080, client_recv_ms: 100 },
+        ClockSample { client_send_ms: 200, server_time_ms: 1_210, client_recv_ms: 220 },
+        ClockSample { client_send_ms: 400, server_time_ms: 1_500, client_recv_ms: 600 },
+    ];
+
+    let estimate = estimate_clock_offset(&samples).unwrap();
+    assert_eq!(estimate.rtt_ms, 20);
+    assert_eq!(estimate.offset_ms, 1_000);
+    assert_eq!(estimate.to_server_time(500), 1_500);
+}
+
+#[test]
+fn estimate_handles_server_behind_client() {
+    let sample = ClockSample { client_send_ms: 5_000, server_time_ms: 1_010, client_recv_ms: 5_020 };
+    assert_eq!(sample.offset_ms(), -4_000);
+    assert_eq!(estimate_clock_offset(&[]), None);
+}
+
+#[tokio::test]
+async fn broadcast_ticks_strictly_increase() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect(addr).await;
+
+    let mut last: Option<(u64, u64)> = None;
+    let mut seen = 0;
+    while seen < 5 {
+        if let ServerMessage::State { tick, server_time_ms, .. } = next_server_message(&mut client).await {
+            if let Some((last_tick, last_time)) = last {
+                assert!(tick > last_tick, "tick {} after {}", tick, last_tick);
+                assert!(server_time_ms >= last_time);
+            }
+            last = Some((tick, server_time_ms));
+            seen += 1;
+        }
+    }
+}
+
+#[tokio::test]
+async fn time_sync_request_is_echoed() {
+    let addr = spawn_server(GameServer::new()).await;
+    let mut client = connect(addr).await;
+
+    client.send(Message::Binary(encode_time_sync_request(123_456).into())).await.unwrap();
+    loop {
+        if let ServerMessage::TimeSync { client_time_ms, .. } = next_server_message(&mut client).await {
+            assert_eq!(client_time_ms, 123_456);
+            break;
+        }
+    }
+}
@@ -0,0 +1,56 @@
+#![cfg(feature = "tls")]
+
+mod common;
+
+use common::{next_text, send_text, spawn_tls_server};
+use galavox::server::GameServer;
+use galavox::tls;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio_tungstenite::{connect_async_tls_with_config, Connector};
+
+fn write_self_signed_cert(tag: &str) -> (PathBuf, PathBuf) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join(format!("galavox-{}-{}-cert.pem", tag, std::process::id()));
+    let key_path = dir.join(format!("galavox-{}-{}-key.pem", tag, std::process::id()));
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+    (cert_path, key_path)
+}
+
+#[tokio::test]
+async fn wss_connection_with_self_signed_cert() {
+    let (cert, key) = write_self_signed_cert("ok");
+    let acceptor = tls::load_acceptor(&cert, &key).unwrap();
+    let addr = spawn_tls_server(GameServer::new(), acceptor).await;
+
+    // A client speaking plain TCP fails the handshake without taking the server down
+    let mut plain = tokio::net::TcpStream::connect(addr).await.unwrap();
+    plain.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+    drop(plain);
+
+    let url = format!("wss://localhost:{}", addr.port());
+    let connector = Connector::Rustls(tls::client_config(true));
+    let (mut ws, _) = connect_async_tls_with_config(url.as_str(), None, false, Some(connector)).await.unwrap();
+    assert!(next_text(&mut ws).await.unwrap().starts_with("Welcome"));
+    send_text(&mut ws, "over tls").await;
+    assert_eq!(next_text(&mut ws).await.as_deref(), Some("Echo: over tls"));
+}
+
+#[tokio::test]
+async fn verifying_client_rejects_self_signed_cert() {
+    let (cert, key) = write_self_signed_cert("verify");
+    let acceptor = tls::load_acceptor(&cert, &key).unwrap();
+    let addr = spawn_tls_server(GameServer::new(), acceptor).await;
+
+    let url = format!("wss://localhost:{}", addr.port());
+    let connector = Connector::Rustls(tls::client_config(false));
+    assert!(connect_async_tls_with_config(url.as_str(), None, false, Some(connector)).await.is_err());
+}
+
+#[test]
+fn missing_key_file_is_an_error() {
+    let (cert, _) = write_self_signed_cert("missing");
+    assert!(tls::load_acceptor(&cert, &PathBuf::from("/nonexistent/key.pem")).is_err());
+}
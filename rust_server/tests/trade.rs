@@ -0,0 +1,232 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use common::{next_json, planet, send_text, spawn_server, Client, TEST_CAPS};
+use galavox::config::{ConfigSource, ServerConfig};
+use galavox::module_effects::Wallet;
+use galavox::protocol::{ClientMessage, GameState, Planet, Position, ServerMessage, TradeEnd, TradeError};
+use galavox::server::{Connection, ConnectionId, GameServer};
+use galavox::stats::ConnectionStats;
+use galavox::trade::{settle, Trades, MAX_OPEN_OFFERS};
+use galavox::wallets::Wallets;
+use tokio::sync::Notify;
+use tokio_tungstenite::connect_async;
+
+const ADA: ConnectionId = ConnectionId(1);
+const BOB: ConnectionId = ConnectionId(2);
+
+fn connection() -> Connection {
+    Connection {
+        addr: "127.0.0.1:4000".parse().unwrap(),
+        stats: Arc::new(ConnectionStats::new(Instant::now())),
+        kick: Arc::new(Notify::new()),
+    }
+}
+
+fn at(x: f32, y: f32) -> Position {
+    Position { x, y, z: 0.0 }
+}
+
+/// A server with one well-stocked planet, on which a seller with 50 units
+/// of cargo and a buyer with 50 credits are landed; returns their
+/// connections and player ids.
+fn market() -> (GameServer, (ConnectionId, u32), (ConnectionId, u32)) {
+    market_with(Wallets::new())
+}
+
+/// `market`, keeping wallets in `wallets`.
+fn market_with(wallets: Wallets) -> (GameServer, (ConnectionId, u32), (ConnectionId, u32)) {
+    let planet = Planet { resources: 1_000, capacity: 1_000, ..planet(1, 0.0) };
+    let server = GameServer::new().with_wallets(wallets).with_world(GameState::new(vec![planet], vec![], at(0.0, 5000.0)));
+    let mut parties = Vec::new();
+    for name in ["Seller", "Buyer"] {
+        let (me, player, _) = server.add_player(connection(), name.to_string()).unwrap();
+        server.move_player(me, at(0.0, 60.0));
+        server.land(me, 1).unwrap();
+        server.mine(me, 50).unwrap();
+        parties.push((me, player.id));
+    }
+    server.sell(parties[1].0, 50).unwrap();
+    assert_eq!(server.wallet("Seller"), Wallet { cargo: 50, credits: 0 });
+    assert_eq!(server.wallet("Buyer"), Wallet { cargo: 0, credits: 50 });
+    (server, parties[0], parties[1])
+}
+
+fn offer_id(offered: Result<ServerMessage, TradeError>) -> u32 {
+    match offered {
+        Ok(ServerMessage::TradeOffered { offer_id, .. }) => offer_id,
+        other => panic!("not offered: {:?}", other),
+    }
+}
+
+#[test]
+fn offers_are_answered_once_by_their_recipient() {
+    let mut trades = Trades::new(Duration::from_secs(60));
+    let now = Instant::now();
+    assert_eq!(trades.offer((ADA, 1), (ADA, 1), 5, 5, now), Err(TradeError::OwnOffer));
+    assert_eq!(trades.offer((ADA, 1), (BOB, 2), 0, 0, now), Err(TradeError::EmptyOffer));
+    let offer = trades.offer((ADA, 1), (BOB, 2), 5, 0, now).unwrap();
+
+    assert_eq!(trades.take(offer.id, ADA, now), Err(TradeError::UnknownOffer { offer_id: offer.id }));
+    assert_eq!(trades.take(offer.id, BOB, now), Ok(offer.clone()));
+    assert_eq!(trades.take(offer.id, BOB, now), Err(TradeError::UnknownOffer { offer_id: offer.id }));
+
+    for _ in 0..MAX_OPEN_OFFERS {
+        trades.offer((ADA, 1), (BOB, 2), 1, 1, now).unwrap();
+    }
+    assert_eq!(trades.offer((ADA, 1), (BOB, 2), 1, 1, now), Err(TradeError::TooManyOffers { limit: MAX_OPEN_OFFERS as u32 }));
+    // Offers to Ada do not count against her
+    trades.offer((BOB, 2), (ADA, 1), 1, 1, now).unwrap();
+    assert_eq!(trades.forget(BOB).len(), MAX_OPEN_OFFERS + 1);
+    assert!(trades.is_empty());
+}
+
+#[test]
+fn offers_expire_after_the_timeout() {
+    let mut trades = Trades::new(Duration::from_secs(60));
+    let now = Instant::now();
+    let offer = trades.offer((ADA, 1), (BOB, 2), 5, 5, now).unwrap();
+    assert_eq!(trades.expire(now + Duration::from_secs(59)), vec![]);
+    let late = now + Duration::from_secs(60);
+    assert_eq!(trades.take(offer.id, BOB, late), Err(TradeError::UnknownOffer { offer_id: offer.id }));
+    assert_eq!(trades.expire(late), vec![offer]);
+    assert!(trades.is_empty());
+}
+
+#[test]
+fn settling_moves_both_sides_or_neither() {
+    let mut trades = Trades::default();
+    let offer = trades.offer((ADA, 1), (BOB, 2), 10, 30, Instant::now()).unwrap();
+    let (mut seller, mut buyer) = (Wallet { cargo: 10, credits: 5 }, Wallet { cargo: 1, credits: 29 });
+    assert_eq!(settle(&offer, &mut seller, &mut buyer), Err(TradeEnd::NotEnoughCredits));
+    assert_eq!((seller, buyer), (Wallet { cargo: 10, credits: 5 }, Wallet { cargo: 1, credits: 29 }));
+    buyer.credits = 30;
+    assert_eq!(settle(&offer, &mut seller, &mut buyer), Ok(()));
+    assert_eq!((seller, buyer), (Wallet { cargo: 0, credits: 35 }, Wallet { cargo: 11, credits: 0 }));
+    assert_eq!(settle(&offer, &mut seller, &mut buyer), Err(TradeEnd::NotEnoughCargo));
+}
+
+#[test]
+fn accepted_offers_trade_cargo_for_credits() {
+    let (server, (seller, _), (buyer, buyer_id)) = market();
+    assert_eq!(server.offer_trade(seller, 999, 20, 30), Err(TradeError::UnknownPlayer { player_id: 999 }));
+    let offer_id = offer_id(server.offer_trade(seller, buyer_id, 20, 30));
+    assert_eq!(server.open_trades(), 1);
+
+    assert_eq!(server.accept_trade(seller, offer_id), Err(TradeError::UnknownOffer { offer_id }), "only the buyer may accept");
+    assert_eq!(server.accept_trade(buyer, offer_id), Ok(ServerMessage::TradeCompleted { offer_id, cargo: 20, credits: 20 }));
+    assert_eq!(server.wallet("Seller"), Wallet { cargo: 30, credits: 30 });
+    assert_eq!(server.wallet("Buyer"), Wallet { cargo: 20, credits: 20 });
+
+    // Accepting twice trades once
+    assert_eq!(server.accept_trade(buyer, offer_id), Err(TradeError::UnknownOffer { offer_id }));
+    assert_eq!(server.wallet("Buyer"), Wallet { cargo: 20, credits: 20 });
+    assert_eq!(server.open_trades(), 0);
+}
+
+#[test]
+fn completed_trades_outlast_a_restart() {
+    let path = std::env::temp_dir().join(format!("galavox-trade-{}.wallets.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (server, (seller, _), (buyer, buyer_id)) = market_with(Wallets::load(&path).unwrap());
+    let offer_id = offer_id(server.offer_trade(seller, buyer_id, 20, 30));
+    assert_eq!(server.accept_trade(buyer, offer_id), Ok(ServerMessage::TradeCompleted { offer_id, cargo: 20, credits: 20 }));
+    drop(server);
+
+    let toml = format!("wallet_file = {:?}\n", path.display().to_string());
+    let config = ServerConfig::from_toml(&toml).unwrap();
+    let server = config.build(config.validate().unwrap(), ConfigSource::new(None, Vec::new(), config.clone()));
+    assert_eq!(server.wallet("Seller"), Wallet { cargo: 30, credits: 30 });
+    assert_eq!(server.wallet("buyer"), Wallet { cargo: 20, credits: 20 });
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn declined_and_expired_offers_move_nothing() {
+    let (server, (seller, _), (buyer, buyer_id)) = market();
+    let declined = offer_id(server.offer_trade(seller, buyer_id, 20, 30));
+    assert_eq!(server.decline_trade(buyer, declined), Ok(ServerMessage::TradeEnded { offer_id: declined, reason: TradeEnd::Declined }));
+    assert_eq!(server.accept_trade(buyer, declined), Err(TradeError::UnknownOffer { offer_id: declined }));
+
+    let expired = offer_id(server.offer_trade(seller, buyer_id, 20, 30));
+    server.expire_trades(Instant::now() + Duration::from_secs(61));
+    assert_eq!(server.open_trades(), 0);
+    assert_eq!(server.accept_trade(buyer, expired), Err(TradeError::UnknownOffer { offer_id: expired }));
+
+    assert_eq!(server.wallet("Seller"), Wallet { cargo: 50, credits: 0 });
+    assert_eq!(server.wallet("Buyer"), Wallet { cargo: 0, credits: 50 });
+}
+
+#[test]
+fn balances_are_checked_when_the_offer_is_accepted() {
+    let (server, (seller, _), (buyer, buyer_id)) = market();
+    // The seller sells the cargo on offer before the buyer accepts
+    let offer = offer_id(server.offer_trade(seller, buyer_id, 40, 10));
+    server.sell(seller, 20).unwrap();
+    assert_eq!(server.accept_trade(buyer, offer), Ok(ServerMessage::TradeEnded { offer_id: offer, reason: TradeEnd::NotEnoughCargo }));
+
+    let offer = offer_id(server.offer_trade(seller, buyer_id, 10, 51));
+    assert_eq!(server.accept_trade(buyer, offer), Ok(ServerMessage::TradeEnded { offer_id: offer, reason: TradeEnd::NotEnoughCredits }));
+    assert_eq!(server.wallet("Seller"), Wallet { cargo: 30, credits: 20 });
+    assert_eq!(server.wallet("Buyer"), Wallet { cargo: 0, credits: 50 });
+}
+
+#[test]
+fn offers_end_when_a_player_leaves() {
+    let (server, (seller, _), (_, buyer_id)) = market();
+    offer_id(server.offer_trade(seller, buyer_id, 20, 30));
+    server.remove_player(seller);
+    assert_eq!(server.open_trades(), 0);
+}
+
+/// Connects in JSON mode as `name` and returns the player id it joined as.
+async fn join(addr: std::net::SocketAddr, name: &str) -> (Client, u32) {
+    let url = format!("ws://{}/?format=json&caps={}&name={}", addr, TEST_CAPS.0, name);
+    let mut ws = connect_async(url).await.unwrap().0;
+    loop {
+        if let ServerMessage::Joined { player_id, .. } = next_json(&mut ws).await {
+            return (ws, player_id);
+        }
+    }
+}
+
+/// The next trade message, skipping everything else.
+async fn next_trade(ws: &mut Client) -> ServerMessage {
+    loop {
+        let message = next_json(ws).await;
+        if matches!(message, ServerMessage::TradeOffered { .. } | ServerMessage::TradeIncoming { .. } | ServerMessage::TradeEnded { .. }
+            | ServerMessage::TradeCompleted { .. } | ServerMessage::TradeRejected { .. })
+        {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn both_players_hear_how_an_offer_ends() {
+    let addr = spawn_server(GameServer::new().with_trade_timeout(Duration::from_millis(200))).await;
+    let (mut ada, _) = join(addr, "Ada").await;
+    let (mut bob, bob_id) = join(addr, "Bob").await;
+    let send = |message: ClientMessage| serde_json::to_string(&message).unwrap();
+
+    // Ada asks for credits Bob does not have
+    send_text(&mut ada, &send(ClientMessage::TradeOffer { to_player: bob_id, give_resources: 0, want_credits: 5 })).await;
+    let ServerMessage::TradeOffered { offer_id, .. } = next_trade(&mut ada).await else { panic!("no TradeOffered") };
+    assert_eq!(next_trade(&mut bob).await, ServerMessage::TradeIncoming {
+        offer_id, from_player: 0, give_resources: 0, want_credits: 5, expires_in_ms: 200,
+    });
+    send_text(&mut bob, &send(ClientMessage::TradeAccept { offer_id })).await;
+    let ended = ServerMessage::TradeEnded { offer_id, reason: TradeEnd::NotEnoughCredits };
+    assert_eq!(next_trade(&mut bob).await, ended);
+    assert_eq!(next_trade(&mut ada).await, ended);
+
+    // Unanswered, the next one times out for both
+    send_text(&mut ada, &send(ClientMessage::TradeOffer { to_player: bob_id, give_resources: 0, want_credits: 5 })).await;
+    let ServerMessage::TradeOffered { offer_id, .. } = next_trade(&mut ada).await else { panic!("no TradeOffered") };
+    let expired = ServerMessage::TradeEnded { offer_id, reason: TradeEnd::Expired };
+    assert_eq!(next_trade(&mut ada).await, expired);
+    assert!(matches!(next_trade(&mut bob).await, ServerMessage::TradeIncoming { .. }));
+    assert_eq!(next_trade(&mut bob).await, expired);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::spawn_server;
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::{Batched, GameState, Position, ServerMessage};
+use galavox::server::GameServer;
+use galavox::traffic::{ConnectionStats, TypeTraffic};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+fn binary(message: &ServerMessage) -> Message {
+    Message::Binary(message.to_bincode().unwrap().into())
+}
+
+#[test]
+fn frames_are_counted_by_type_and_broadcasts_timed() {
+    let state = binary(&ServerMessage::State { tick: 1, server_time_ms: 0, state: GameState::new(Vec::new(), Vec::new(), Position { x: 0.0, y: 0.0, z: 0.0 }) });
+    let idle = ServerMessage::PlayerIdle { player_id: 3 };
+    let batch = binary(&ServerMessage::Batch { messages: Batched(vec![idle.clone(), idle]) });
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+
+    let mut stats = ConnectionStats::new();
+    assert_eq!(stats.broadcast_jitter(), None);
+    // States 100, 100, 120 and 80ms apart
+    for ms in [0, 100, 200] {
+        stats.record(&state, at(ms));
+    }
+    stats.record(&batch, at(250));
+    stats.record(&Message::Text("Echo: hi".into()), at(260));
+    stats.record(&Message::Ping(vec![1, 2].into()), at(270));
+    stats.record(&Message::Binary(vec![0xff; 8].into()), at(280));
+    for ms in [320, 400] {
+        stats.record(&state, at(ms));
+    }
+
+    let by_type = stats.by_type();
+    assert_eq!(by_type.keys().copied().collect::<Vec<_>>(), ["Batch", "State", "text", "unknown"]);
+    assert_eq!(by_type["State"], TypeTraffic { messages: 5, bytes: 5 * state.len() as u64 });
+    assert_eq!(by_type["Batch"], TypeTraffic { messages: 1, bytes: batch.len() as u64 });
+    assert_eq!(by_type["text"], TypeTraffic { messages: 1, bytes: 8 });
+    assert_eq!(stats.total().messages, 8);
+    assert_eq!(stats.elapsed(), Duration::from_millis(400));
+
+    let jitter = stats.broadcast_jitter().unwrap();
+    assert_eq!((jitter.intervals, jitter.max_interval), (4, Duration::from_millis(120)));
+    assert!((jitter.mean_interval.as_secs_f64() - 0.1).abs() < 1e-9);
+    // The gaps are 0, 0, +20 and -20ms off the mean
+    assert!((jitter.jitter.as_secs_f64() - 200f64.sqrt() / 1e3).abs() < 1e-9);
+
+    let table = stats.to_string();
+    assert!(table.lines().nth(1).unwrap().starts_with("State"), "{}", table);
+    assert!(table.contains("broadcasts every 100.0ms, jitter 14.1ms, longest gap 120.0ms"), "{}", table);
+    assert!(table.ends_with("compression: none negotiated"));
+}
+
+#[tokio::test]
+async fn a_connection_counts_what_it_receives() {
+    let addr = spawn_server(GameServer::new().with_motd("hello".to_string())).await;
+    let mut conn = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    assert_eq!(conn.stats().total(), TypeTraffic::default());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !matches!(conn.next_event().await, ClientEvent::Joined { .. }) {}
+    }).await.unwrap();
+
+    let stats = conn.stats();
+    assert_eq!(stats.by_type()["State"].messages, 1);
+    assert_eq!(stats.by_type()["Joined"].messages, 1);
+    assert!(stats.total().bytes > stats.by_type()["State"].bytes);
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::time::Duration;
+
+use common::spawn_server;
+use galavox::client::{ClientEvent, Connection};
+use galavox::protocol::{ClientMessage, MessageCode, Player, PlayerAppearance, PlayerTrail, Position, ServerMessage, TrailPoint};
+use galavox::server::GameServer;
+use galavox::teleport::TeleportConfig;
+use galavox::trail::{Trails, SPECTATOR_SAMPLE_EVERY};
+
+fn at(x: f32) -> Position {
+    Position { x, y: 0.0, z: 0.0 }
+}
+
+fn point(time_ms: u64) -> TrailPoint {
+    TrailPoint { time_ms, position: at(time_ms as f32) }
+}
+
+fn player(id: u32, x: f32) -> Player {
+    Player {
+        id,
+        name: format!("p{}", id).into(),
+        level: 1,
+        position: at(x),
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("p"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+fn times(points: &[TrailPoint]) -> Vec<u64> {
+    points.iter().map(|p| p.time_ms).collect()
+}
+
+#[test]
+fn the_ring_buffer_keeps_the_newest_points() {
+    let mut trails = Trails::new(4);
+    for time_ms in 1..=3 {
+        trails.push(7, point(time_ms));
+    }
+    assert_eq!(times(&trails.trail(7)), [1, 2, 3]);
+    // Wrapping around drops the oldest, and the order stays oldest first
+    for time_ms in 4..=10 {
+        trails.push(7, point(time_ms));
+    }
+    assert_eq!(times(&trails.trail(7)), [7, 8, 9, 10]);
+    assert!(trails.trail(8).is_empty());
+
+    let stats = trails.stats();
+    assert_eq!((stats.players, stats.points), (1, 4));
+    assert_eq!(stats.bytes, 4 * std::mem::size_of::<TrailPoint>() as u64);
+}
+
+#[test]
+fn spectators_get_every_fourth_point_ending_at_the_newest() {
+    let mut trails = Trails::new(32);
+    for time_ms in 0..10 {
+        trails.push(2, point(time_ms));
+    }
+    trails.push(1, point(100));
+    let sampled = trails.sampled(SPECTATOR_SAMPLE_EVERY);
+    assert_eq!(sampled.iter().map(|t| t.player_id).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(times(&sampled[0].points), [100]);
+    assert_eq!(times(&sampled[1].points), [1, 5, 9]);
+}
+
+#[test]
+fn teleports_and_departures_clear_trails() {
+    let mut trails = Trails::new(8);
+    trails.record(&[player(1, 0.0), player(2, 0.0)], 100);
+    trails.record(&[player(1, 10.0), player(2, 10.0)], 200);
+    trails.clear(1);
+    trails.record(&[player(1, 5000.0), player(2, 20.0)], 300);
+    assert_eq!(trails.trail(1), [TrailPoint { time_ms: 300, position: at(5000.0) }]);
+    assert_eq!(times(&trails.trail(2)), [100, 200, 300]);
+
+    // Player 2 left
+    trails.record(&[player(1, 5010.0)], 400);
+    assert!(trails.trail(2).is_empty());
+    assert_eq!(trails.stats().players, 1);
+}
+
+async fn next_matching<T>(conn: &mut Connection, mut f: impl FnMut(ClientEvent) -> Option<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(found) = f(conn.next_event().await) {
+                return found;
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an event")
+}
+
+async fn query_trail(conn: &mut Connection, player_id: u32) -> PlayerTrail {
+    conn.send(&ClientMessage::QueryTrail { player_id }).await.unwrap();
+    next_matching(conn, |e| match e {
+        ClientEvent::Message(ServerMessage::Trail { trail }) => Some(trail),
+        ClientEvent::Localized { message: MessageCode::TrailQueryRejected { reason }, .. } => panic!("{}", reason),
+        _ => None,
+    }).await
+}
+
+async fn snapshots(conn: &mut Connection, count: usize) {
+    for _ in 0..count {
+        next_matching(conn, |e| matches!(e, ClientEvent::StateSnapshot { .. }).then_some(())).await;
+    }
+}
+
+#[tokio::test]
+async fn a_teleport_starts_a_new_trail() {
+    let server = GameServer::new()
+        .with_trails(32)
+        .with_teleport_config(TeleportConfig { casual: true, ..TeleportConfig::default() });
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    let (player_id, spawn_planet) = next_matching(&mut ada, |e| match e {
+        ClientEvent::Joined { player_id, spawn_planet_id, .. } => Some((player_id, spawn_planet_id)),
+        _ => None,
+    }).await;
+    snapshots(&mut ada, 3).await;
+
+    let before = query_trail(&mut ada, player_id).await;
+    assert_eq!(before.player_id, player_id);
+    assert!(before.points.len() >= 2, "{:?}", before);
+    assert!(before.points.windows(2).all(|w| w[0].time_ms < w[1].time_ms));
+    let spawn = before.points[0].position.clone();
+
+    let planet_id = server.get_state().planets.iter().map(|p| p.id).find(|id| Some(*id) != spawn_planet).unwrap();
+    ada.send(&ClientMessage::TeleportToPlanet { planet_id }).await.unwrap();
+    let destination = next_matching(&mut ada, |e| match e {
+        ClientEvent::Message(ServerMessage::PlayerTeleported { position, .. }) => Some(position),
+        _ => None,
+    }).await;
+    snapshots(&mut ada, 2).await;
+
+    let after = query_trail(&mut ada, player_id).await;
+    assert!(!after.points.is_empty());
+    assert!(after.points.iter().all(|p| p.position == destination && p.position != spawn), "{:?}", after);
+    assert!(server.trail_stats().unwrap().points >= after.points.len());
+}
+
+#[tokio::test]
+async fn only_spectators_get_broadcast_trails() {
+    let server = GameServer::new().with_trails(32);
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    let mut spectator = Connection::spectate(&format!("ws://{}", addr), false).await.unwrap();
+
+    let trails = next_matching(&mut spectator, |e| match e {
+        ClientEvent::Message(ServerMessage::Trails { trails }) if !trails.is_empty() => Some(trails),
+        _ => None,
+    }).await;
+    assert_eq!(trails.len(), 1);
+
+    for _ in 0..5 {
+        let event = next_matching(&mut ada, |e| matches!(e, ClientEvent::StateSnapshot { .. } | ClientEvent::Message(_)).then_some(e)).await;
+        assert!(!matches!(event, ClientEvent::Message(ServerMessage::Trails { .. })), "{:?}", event);
+    }
+}
+
+#[tokio::test]
+async fn trails_are_off_unless_asked_for() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ada = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    ada.send(&ClientMessage::QueryTrail { player_id: 0 }).await.unwrap();
+    let notice = next_matching(&mut ada, |e| match e {
+        ClientEvent::Localized { message: MessageCode::TrailQueryRejected { .. }, text } => Some(text),
+        _ => None,
+    }).await;
+    assert_eq!(notice, "Trail query rejected: trails are off on this server");
+    assert_eq!(server.trail_stats(), None);
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use common::{planet, spawn_server};
+use galavox::client::{ClientEvent, Connection};
+use galavox::diff::StateDiff;
+use galavox::protocol::{GameState, Planet, Player, PlayerAppearance, Position, ServerMessage};
+use galavox::server::GameServer;
+use galavox::validate::{Rule, Validator, MAX_EVENT_CHARS};
+
+fn at(x: f32) -> Position {
+    Position { x, y: 0.0, z: 0.0 }
+}
+
+fn player(id: u32, position: Position) -> Player {
+    Player {
+        id,
+        name: format!("p{}", id).into(),
+        level: 1,
+        position,
+        velocity: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        last_processed_seq: 0,
+        idle: false,
+        appearance: PlayerAppearance::for_name("p"),
+        xp: 0,
+        landed_on: None,
+        remote: false,
+    }
+}
+
+fn snapshot(tick: u64, players: Vec<Player>, planets: Vec<Planet>) -> ClientEvent {
+    ClientEvent::StateSnapshot { tick, server_time_ms: tick * 50, state: GameState::new(planets, players, at(0.0)) }
+}
+
+fn delta(diff: StateDiff) -> ClientEvent {
+    ClientEvent::Delta { from_tick: 1, tick: 2, diffs: vec![diff] }
+}
+
+/// The rules each event breaks, one list per event.
+fn rules(validator: &mut Validator, events: &[ClientEvent]) -> Vec<Vec<Rule>> {
+    events.iter().map(|event| validator.check(event).iter().map(|v| v.rule).collect()).collect()
+}
+
+#[test]
+fn snapshot_ticks_strictly_increase() {
+    let mut validator = Validator::new(None);
+    let found = rules(&mut validator, &[
+        snapshot(5, vec![], vec![]),
+        snapshot(5, vec![], vec![]),
+        snapshot(4, vec![], vec![]),
+        snapshot(6, vec![], vec![]),
+        ClientEvent::Delta { from_tick: 7, tick: 6, diffs: vec![] },
+    ]);
+    assert_eq!(found, [vec![], vec![Rule::TickOrder], vec![Rule::TickOrder], vec![], vec![Rule::TickOrder]]);
+
+    // The reply to a resync may repeat the latest snapshot, but only once
+    validator.expect_resync();
+    let found = rules(&mut validator, &[snapshot(6, vec![], vec![]), snapshot(6, vec![], vec![])]);
+    assert_eq!(found, [vec![], vec![Rule::TickOrder]]);
+    assert_eq!(validator.violations().len(), 4);
+}
+
+#[test]
+fn deltas_name_only_known_players_and_planets() {
+    let mut validator = Validator::new(None);
+    let added = StateDiff { added_players: vec![player(3, at(0.0))], ..StateDiff::default() };
+    let found = rules(&mut validator, &[
+        snapshot(1, vec![player(1, at(0.0))], vec![planet(1, 1.0)]),
+        delta(StateDiff { changed_players: vec![player(2, at(0.0))], removed_planets: vec![9], ..StateDiff::default() }),
+        delta(StateDiff { removed_players: vec![1], ..StateDiff::default() }),
+        delta(StateDiff { changed_players: vec![player(1, at(0.0))], ..StateDiff::default() }),
+        ClientEvent::Delta { from_tick: 1, tick: 2, diffs: vec![added, StateDiff { changed_players: vec![player(3, at(1.0))], ..StateDiff::default() }] },
+    ]);
+    assert_eq!(found, [vec![], vec![Rule::UnknownPlayer, Rule::UnknownPlanet], vec![], vec![Rule::UnknownPlayer], vec![]]);
+}
+
+#[test]
+fn player_left_only_for_players_who_were_there() {
+    let mut validator = Validator::new(None);
+    // The decoder follows a snapshot missing a player with their `PlayerLeft`
+    let found = rules(&mut validator, &[
+        snapshot(1, vec![player(1, at(0.0)), player(2, at(0.0))], vec![]),
+        ClientEvent::PlayerLeft { player_id: 7 },
+        snapshot(2, vec![player(2, at(0.0))], vec![]),
+        ClientEvent::PlayerLeft { player_id: 1 },
+        ClientEvent::PlayerLeft { player_id: 1 },
+        ClientEvent::PlayerLeft { player_id: 2 },
+    ]);
+    assert_eq!(found, [vec![], vec![Rule::UnknownPlayer], vec![], vec![], vec![Rule::UnknownPlayer], vec![]]);
+}
+
+#[test]
+fn positions_are_finite_and_inside_the_world() {
+    let mut validator = Validator::new(Some(100.0));
+    let teleported = |x: f32| ClientEvent::Message(ServerMessage::PlayerTeleported { player_id: 1, position: at(x) });
+    let found = rules(&mut validator, &[
+        snapshot(1, vec![player(1, at(100.0)), player(2, at(f32::NAN))], vec![]),
+        teleported(-200.0),
+        teleported(f32::INFINITY),
+        ClientEvent::Joined { player_id: 1, spawn: at(99.0), spawn_planet_id: None },
+    ]);
+    assert_eq!(found, [vec![Rule::NonFinitePosition], vec![Rule::OutOfBounds], vec![Rule::NonFinitePosition], vec![]]);
+
+    // Without a radius only finiteness is checked
+    let mut validator = Validator::new(None);
+    assert!(validator.check(&snapshot(1, vec![player(1, at(1e30))], vec![])).is_empty());
+}
+
+#[test]
+fn events_name_only_known_planets() {
+    let mut validator = Validator::new(None);
+    let discovered = |planet_id| ClientEvent::Message(ServerMessage::PlanetDiscovered { player_id: 1, planet_id });
+    let found = rules(&mut validator, &[
+        snapshot(1, vec![player(1, at(0.0))], vec![planet(1, 1.0)]),
+        discovered(9),
+        ClientEvent::Message(ServerMessage::PlanetAdded { planet: planet(9, 9.0) }),
+        discovered(9),
+        ClientEvent::Message(ServerMessage::PlanetRemoved { planet_id: 1 }),
+        ClientEvent::Message(ServerMessage::PlanetUpdated { planet: planet(1, 1.0) }),
+    ]);
+    assert_eq!(found, [vec![], vec![Rule::UnknownPlanet], vec![], vec![], vec![], vec![Rule::UnknownPlanet]]);
+}
+
+#[test]
+fn idle_players_stay_known_and_resets_forget_everyone() {
+    let mut validator = Validator::new(None);
+    let mut idle = player(1, at(0.0));
+    idle.idle = true;
+    let found = rules(&mut validator, &[
+        // Nothing is known before the first snapshot
+        ClientEvent::Message(ServerMessage::PlayerActive { player_id: 5 }),
+        snapshot(1, vec![idle], vec![]),
+        snapshot(2, vec![], vec![]),
+        ClientEvent::Message(ServerMessage::PlayerActive { player_id: 1 }),
+        ClientEvent::WorldReset { seed: 3 },
+        ClientEvent::Message(ServerMessage::PlanetRemoved { planet_id: 4 }),
+        snapshot(3, vec![], vec![]),
+        ClientEvent::Message(ServerMessage::PlanetRemoved { planet_id: 4 }),
+    ]);
+    assert_eq!(found, [vec![], vec![], vec![], vec![], vec![], vec![], vec![], vec![Rule::UnknownPlanet]]);
+}
+
+#[test]
+fn violations_carry_the_offending_event() {
+    let mut validator = Validator::new(None);
+    validator.check(&snapshot(1, vec![], vec![]));
+    let found = validator.check(&ClientEvent::PlayerLeft { player_id: 7 });
+    assert_eq!(found[0].to_string(), "unknown player: player 7 in PlayerLeft { player_id: 7 }");
+
+    let crowd = (0..100).map(|id| player(id, at(0.0))).collect();
+    let found = validator.check(&snapshot(1, crowd, vec![]));
+    assert_eq!(found[0].rule, Rule::TickOrder);
+    assert_eq!(found[0].event.chars().count(), MAX_EVENT_CHARS + 1);
+    assert!(found[0].event.ends_with('…'));
+}
+
+#[tokio::test]
+async fn a_live_server_keeps_to_the_protocol() {
+    let addr = spawn_server(GameServer::new().with_fake_players(3)).await;
+    let mut connection = Connection::connect(&format!("ws://{}", addr), Some("Ada")).await.unwrap();
+    assert_eq!(connection.world_radius(), Some(10_000.0));
+    connection.validate();
+
+    let mut snapshots = 0;
+    while snapshots < 5 {
+        match connection.next_event().await {
+            ClientEvent::StateSnapshot { .. } => snapshots += 1,
+            ClientEvent::Disconnected { reason, .. } => panic!("disconnected: {}", reason),
+            _ => {}
+        }
+    }
+    connection.request_resync(0).await.unwrap();
+    for _ in 0..3 {
+        connection.next_event().await;
+    }
+    assert_eq!(connection.violations(), []);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{next_json, TEST_CAPS};
+use galavox::config::ServerConfig;
+use galavox::protocol::ServerMessage;
+use galavox::server::GameServer;
+use galavox::webhooks::{render, EventKind, WebhookConfig, WebhookEvent, WebhookSettings, WebhookStats, Webhooks};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::connect_async;
+
+/// A POST the stub received.
+struct Request {
+    path: String,
+    headers: HashMap<String, String>,
+    body: Value,
+}
+
+/// A local HTTP server answering each request with the next of `statuses`,
+/// then 200.
+async fn stub(statuses: &[u16]) -> (SocketAddr, mpsc::UnboundedReceiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let statuses = Arc::new(Mutex::new(VecDeque::from(statuses.to_vec())));
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let mut stream = BufReader::new(stream);
+            let mut request_line = String::new();
+            stream.read_line(&mut request_line).await.unwrap();
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                match line.trim_end().split_once(": ") {
+                    Some((name, value)) => { headers.insert(name.to_lowercase(), value.to_string()); }
+                    None => break,
+                }
+            }
+            let mut body = vec![0; headers["content-length"].parse().unwrap()];
+            stream.read_exact(&mut body).await.unwrap();
+            let status = statuses.lock().unwrap().pop_front().unwrap_or(200);
+            let path = request_line.split(' ').nth(1).unwrap().to_string();
+            let _ = tx.send(Request { path, headers, body: serde_json::from_slice(&body).unwrap() });
+            let answer = format!("HTTP/1.1 {} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            stream.get_mut().write_all(answer.as_bytes()).await.unwrap();
+        }
+    });
+    (addr, rx)
+}
+
+fn settings(addr: SocketAddr, events: Vec<EventKind>) -> WebhookSettings {
+    WebhookSettings {
+        hooks: vec![WebhookConfig { url: format!("http://{}/hooks/galavox", addr), events }],
+        backoff: Duration::from_millis(10),
+        ..WebhookSettings::default()
+    }
+}
+
+#[test]
+fn urls_and_templates_are_checked_with_the_config() {
+    let config = ServerConfig::from_toml(concat!(
+        "webhook_queue = 0\n",
+        "[webhook_templates]\n",
+        "level_up = \"{name} is level {level}\"\n",
+        "player_left = \"{name} left at level {level}\"\n",
+        "planet_sold = \"gone\"\n",
+        "[[webhooks]]\n",
+        "url = \"ftp://example.com\"\n",
+        "[[webhooks]]\n",
+        "url = \"http://example.com:80/hook\"\n",
+        "events = [\"ban_issued\"]\n",
+    )).unwrap();
+    assert_eq!(config.setting_problems(), [
+        "webhook_queue must be positive",
+        "webhooks[0].url: ftp://example.com is not an http or https URL",
+        "webhook_templates.planet_sold: unknown event planet_sold",
+        "webhook_templates.player_left: `{level}` is not a field of player_left",
+    ]);
+    assert!(ServerConfig::from_toml("[[webhooks]]\nurl = \"http://a\"\nevents = [\"chat\"]\n").is_err());
+
+    let event = WebhookEvent::LevelUp { name: "Ada".to_string(), level: 3 };
+    assert_eq!(render("{{{name}}} reached {level}", &event), Ok("{Ada} reached 3".to_string()));
+    assert_eq!("ban_issued".parse(), Ok(EventKind::BanIssued));
+}
+
+#[tokio::test]
+async fn joins_are_posted_to_the_webhooks_that_want_them() {
+    let (addr, mut requests) = stub(&[]).await;
+    let mut settings = settings(addr, vec![EventKind::PlayerJoined]);
+    settings.templates.insert("player_joined".to_string(), "👋 {name} is online".to_string());
+    let server = GameServer::new().with_webhooks(Webhooks::new(&settings).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let handle = server.clone().spawn(listener).unwrap();
+
+    let url = format!("ws://{}/?format=json&caps={}&name=Ada", handle.local_addr(), TEST_CAPS.0);
+    let mut ws = connect_async(url).await.unwrap().0;
+    while !matches!(next_json(&mut ws).await, ServerMessage::Localized { .. }) {}
+
+    // `server_started` was filtered out, so the join is the first
+    let request = requests.recv().await.unwrap();
+    assert_eq!(request.path, "/hooks/galavox");
+    assert_eq!(request.headers["content-type"], "application/json");
+    assert_eq!(request.headers["host"], addr.to_string());
+    let body = request.body.as_object().unwrap();
+    assert_eq!(body["event"], "player_joined");
+    assert_eq!(body["name"], "Ada");
+    assert_eq!(body["text"], "👋 Ada is online");
+    assert_eq!(body["content"], body["text"]);
+    assert!(body["at_unix_ms"].as_u64().unwrap() > 0);
+    assert_eq!(body.len(), 5);
+
+    server.ban("Mallory").unwrap();
+    handle.shutdown().await.unwrap();
+    assert!(requests.try_recv().is_err());
+    assert_eq!(server.webhook_stats(), Some(WebhookStats { sent: 1, failed: 0, dropped: 0 }));
+}
+
+#[tokio::test]
+async fn failed_posts_are_retried_with_backoff() {
+    let (addr, mut requests) = stub(&[500, 503, 429, 204, 404]).await;
+    let webhooks = Webhooks::new(&settings(addr, Vec::new())).unwrap();
+    webhooks.run();
+
+    webhooks.notify(&WebhookEvent::BanIssued { name: "Mallory".to_string() }, 1_000);
+    assert!(webhooks.flush(Duration::from_secs(5)).await);
+    for _ in 0..4 {
+        assert_eq!(requests.recv().await.unwrap().body["text"], "Mallory was banned");
+    }
+    assert_eq!(webhooks.stats(), WebhookStats { sent: 1, failed: 0, dropped: 0 });
+
+    // A client error is not retried, and the queue moves on
+    webhooks.notify(&WebhookEvent::ServerStopped, 2_000);
+    webhooks.notify(&WebhookEvent::PlanetClaimed { planet: 4, owner: 7 }, 3_000);
+    assert!(webhooks.flush(Duration::from_secs(5)).await);
+    assert_eq!(requests.recv().await.unwrap().body["event"], "server_stopped");
+    let claimed = requests.recv().await.unwrap().body;
+    assert_eq!((claimed["planet"].as_u64(), claimed["owner"].as_u64()), (Some(4), Some(7)));
+    assert_eq!(webhooks.stats(), WebhookStats { sent: 2, failed: 1, dropped: 0 });
+
+    // Nobody listening: given up on after the retries
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let webhooks = Webhooks::new(&WebhookSettings { retries: 2, ..settings(closed, Vec::new()) }).unwrap();
+    webhooks.run();
+    webhooks.notify(&WebhookEvent::ServerStarted, 0);
+    assert!(webhooks.flush(Duration::from_secs(5)).await);
+    assert_eq!(webhooks.stats(), WebhookStats { sent: 0, failed: 1, dropped: 0 });
+}
+
+#[tokio::test]
+async fn a_full_queue_drops_and_counts() {
+    let (addr, mut requests) = stub(&[]).await;
+    let webhooks = Webhooks::new(&WebhookSettings { queue_limit: 2, ..settings(addr, vec![EventKind::PlayerLeft]) }).unwrap();
+
+    // Not running yet, so nothing leaves the queue
+    for name in ["a", "b", "c", "d", "e"] {
+        webhooks.notify(&WebhookEvent::PlayerLeft { name: name.to_string() }, 0);
+        webhooks.notify(&WebhookEvent::PlayerJoined { name: name.to_string() }, 0);
+    }
+    assert_eq!(webhooks.stats(), WebhookStats { sent: 0, failed: 0, dropped: 3 });
+
+    webhooks.run();
+    assert!(webhooks.flush(Duration::from_secs(5)).await);
+    assert_eq!(requests.recv().await.unwrap().body["name"], "a");
+    assert_eq!(requests.recv().await.unwrap().body["name"], "b");
+    assert_eq!(webhooks.stats(), WebhookStats { sent: 2, failed: 0, dropped: 3 });
+    assert!(requests.try_recv().is_err());
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::planet;
+use galavox::protocol::{GameState, Position};
+use galavox::server::GameServer;
+use galavox::world::{load_world, save_world};
+
+#[test]
+fn planets_are_found_by_id_after_the_list_changes() {
+    let mut state = GameState::new(vec![planet(4, 0.0), planet(7, 100.0), planet(9, 200.0)], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+    assert_eq!(state.planet_by_id(7).map(|p| p.position.x), Some(100.0));
+
+    state.planets.remove(0);
+    state.planets.reverse();
+    state.planets.push(planet(12, 300.0));
+    assert_eq!(state.planet_by_id(7).map(|p| p.position.x), Some(100.0));
+    assert_eq!(state.planet_position(9), Some(0));
+    assert_eq!(state.planet_by_id(12).map(|p| p.position.x), Some(300.0));
+    assert_eq!(state.planet_by_id(4), None);
+
+    state.planet_by_id_mut(9).unwrap().size = 80.0;
+    assert_eq!(state.planets[0].size, 80.0);
+}
+
+#[test]
+fn ids_survive_save_and_load() {
+    let state = GameState::new(vec![planet(3, 0.0), planet(1, 100.0)], vec![], Position { x: 1.0, y: 2.0, z: 3.0 });
+    assert_eq!(load_world(&save_world(&state).unwrap()).unwrap(), state);
+}
+
+#[test]
+fn worlds_saved_without_ids_get_them_deterministically() {
+    let legacy = r#"{
+        "planets": [
+            {"size": 50.0, "colors": [{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3}], "module_type": 0, "position": {"x":0.0,"y":0.0,"z":0.0}},
+            {"id": 5, "size": 50.0, "colors": [{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3}], "module_type": 0, "position": {"x":100.0,"y":0.0,"z":0.0}},
+            {"size": 50.0, "colors": [{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3},{"r":1,"g":2,"b":3}], "module_type": 0, "position": {"x":200.0,"y":0.0,"z":0.0}}
+        ],
+        "players": [],
+        "initial_player_location": {"x":0.0,"y":0.0,"z":0.0}
+    }"#;
+
+    let ids = |state: GameState| state.planets.iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(load_world(legacy).unwrap()), [6, 5, 7]);
+    assert_eq!(ids(load_world(legacy).unwrap()), [6, 5, 7]);
+}
+
+#[test]
+fn loaded_worlds_continue_their_ids() {
+    let state = GameState::new(vec![planet(3, 0.0), planet(8, 300.0)], vec![], Position { x: 0.0, y: 0.0, z: 0.0 });
+    let server = GameServer::new().with_world(state);
+    let added = server.add_planet(planet(0, -300.0)).unwrap();
+    assert_eq!(added.id, 9);
+    assert_eq!(server.get_state().planet_by_id(9), Some(&added));
+}
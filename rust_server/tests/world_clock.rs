@@ -0,0 +1,77 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{connect_json, next_json, spawn_server};
+use galavox::admin::{run_line, PlanetLimits};
+use galavox::config::ServerConfig;
+use galavox::protocol::ServerMessage;
+use galavox::server::GameServer;
+use galavox::world::{load_world, save_world, WorldConfig};
+use galavox::world_clock::{advance, parse_world_time, Phase, WorldClock};
+use galavox::world_file::WorldFile;
+
+#[test]
+fn the_time_wraps_at_midnight_and_names_its_phase() {
+    let day = Duration::from_secs(100);
+    assert_eq!(advance(0.25, Duration::from_secs(25), day), 0.5);
+    assert!((advance(0.9, Duration::from_secs(20), day) - 0.1).abs() < 1e-6);
+    assert_eq!(advance(0.5, Duration::from_secs(300), day), 0.5);
+    assert!(advance(0.0, Duration::from_nanos(99_999_999_999), day) < 1.0);
+
+    let mut clock = WorldClock::new(0.75, day);
+    assert_eq!(clock.advance(Duration::from_secs(50)), 0.25);
+    assert_eq!(clock.time(), 0.25);
+
+    let phases: Vec<Phase> = [0.0, 0.2, 0.3, 0.69, 0.7, 0.8, 0.99].into_iter().map(Phase::of).collect();
+    assert_eq!(phases, [Phase::Night, Phase::Dawn, Phase::Day, Phase::Day, Phase::Dusk, Phase::Night, Phase::Night]);
+    assert_eq!(Phase::Dusk.to_string(), "dusk");
+
+    assert_eq!(parse_world_time("1"), Ok(0.0));
+    assert_eq!(parse_world_time("1.5"), Err("the time of day must be between 0 and 1".to_string()));
+    assert_eq!(parse_world_time("noon"), Err("invalid time of day: noon".to_string()));
+}
+
+#[test]
+fn the_clock_ticks_with_the_simulation_and_survives_a_save() {
+    let config = WorldConfig { day_length: Duration::from_secs(10), ..WorldConfig::default() };
+    let server = GameServer::new().with_world_config(config.clone());
+    run_line(&server, "pause").unwrap();
+    run_line(&server, "step 10").unwrap();
+    let ticked = server.world_time();
+    assert!(ticked > 0.0, "{}", ticked);
+    assert_eq!(server.get_state().world_time, ticked);
+    // Standing still while paused
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(server.world_time(), ticked);
+
+    let saved = load_world(&save_world(&server.get_state()).unwrap()).unwrap();
+    assert_eq!(GameServer::new().with_world(saved).world_time(), ticked);
+
+    let exported = WorldFile::new(&server.get_state(), &config).to_json().unwrap();
+    let imported = WorldFile::parse(&exported, &PlanetLimits::default()).unwrap();
+    assert_eq!(imported.into_state().world_time, ticked);
+    let out_of_range = exported.replace(&format!("\"world_time\": {}", ticked), "\"world_time\": 1.5");
+    assert!(WorldFile::parse(&out_of_range, &PlanetLimits::default()).is_err());
+
+    let config = ServerConfig::from_toml("day_length_secs = 0\n").unwrap();
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn settime_snaps_everyone_to_the_new_time() {
+    let server = GameServer::new();
+    let addr = spawn_server(server.clone()).await;
+    let mut ws = connect_json(addr).await;
+
+    assert_eq!(run_line(&server, "settime 1.5"), Err("the time of day must be between 0 and 1".to_string()));
+    run_line(&server, "pause").unwrap();
+    assert_eq!(run_line(&server, "settime 0.5"), Ok("time of day set to 0.500 (day)".to_string()));
+    loop {
+        if let ServerMessage::TimeSet { world_time } = next_json(&mut ws).await {
+            assert_eq!(world_time, 0.5);
+            break;
+        }
+    }
+    assert_eq!(server.get_state().world_time, 0.5);
+}
@@ -0,0 +1,82 @@
+use galavox::admin::PlanetLimits;
+use galavox::server::GameServer;
+use galavox::world::WorldConfig;
+use galavox::world_file::{WorldFile, WorldFileError};
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("{}/tests/fixtures/worlds/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+fn problems(name: &str) -> Vec<String> {
+    match WorldFile::parse(&fixture(name), &PlanetLimits::default()) {
+        Err(WorldFileError::Invalid(problems)) => problems,
+        other => panic!("{} should be invalid, got {:?}", name, other),
+    }
+}
+
+#[test]
+fn exported_worlds_import_unchanged() {
+    let config = WorldConfig { seed: Some(7), ..WorldConfig::default() };
+    let state = GameServer::new().with_world_config(config.clone()).get_state();
+    let exported = WorldFile::new(&state, &config).to_json().unwrap();
+
+    let imported = WorldFile::parse(&exported, &PlanetLimits::default()).unwrap();
+    assert_eq!(imported.radius, config.radius);
+    assert_eq!(imported.seed, Some(7));
+    assert_eq!(imported.to_json().unwrap(), exported);
+    assert_eq!(imported.into_state(), state);
+}
+
+#[test]
+fn imported_worlds_replace_the_generated_one() {
+    let file = WorldFile::parse(&fixture("valid.json"), &PlanetLimits::default()).unwrap();
+    assert_eq!(file.radius, 5000.0);
+    let server = GameServer::new().with_world(file.clone().into_state());
+    let state = server.get_state();
+    assert_eq!(state.planets, file.clone().into_state().planets);
+    assert_eq!(state.planets.iter().map(|p| p.id).collect::<Vec<_>>(), file.planets.iter().map(|p| p.id).collect::<Vec<_>>());
+    assert_eq!(state.belts, file.belts);
+    assert_eq!(state.next_body_id(), 3);
+}
+
+#[test]
+fn out_of_range_sizes() {
+    assert_eq!(problems("bad_size.json"), [
+        "planets[0].size: 500 is outside 10 to 400",
+        "planets[1].size: 2 is outside 10 to 400",
+    ]);
+}
+
+#[test]
+fn overlapping_planets() {
+    assert_eq!(problems("overlap.json"), ["planets[1]: overlaps planets[0] by 50.0"]);
+}
+
+#[test]
+fn unknown_module_types() {
+    assert_eq!(problems("unknown_module.json"), ["planets[0].module_type: 9 is not a known module type (0 to 4)"]);
+}
+
+#[test]
+fn duplicate_ids() {
+    assert_eq!(problems("duplicate_ids.json"), [
+        "planets[1].id: id 4 is already used by planets[0].id",
+        "belts[0].notable[0].id: id 4 is already used by planets[0].id",
+    ]);
+}
+
+#[test]
+fn malformed_files_name_the_line() {
+    let error = WorldFile::parse(&fixture("wrong_type.json"), &PlanetLimits::default()).unwrap_err();
+    assert!(matches!(error, WorldFileError::Json(_)));
+    assert_eq!(error.to_string(), "invalid world file: invalid type: string \"huge\", expected f32 at line 12 column 20");
+
+    let error = WorldFile::parse(r#"{"radius": 100.0, "planetz": []}"#, &PlanetLimits::default()).unwrap_err();
+    assert!(error.to_string().starts_with("invalid world file: unknown field `planetz`"), "{}", error);
+}
+
+#[test]
+fn every_problem_is_listed() {
+    let error = WorldFileError::Invalid(problems("bad_size.json"));
+    assert_eq!(error.to_string(), "invalid world file:\n  planets[0].size: 500 is outside 10 to 400\n  planets[1].size: 2 is outside 10 to 400");
+}
@@ -0,0 +1,72 @@
+use galavox::admin::PlanetLimits;
+use galavox::world_info::{read_world, DistanceStats, SizeBucket, WorldInfo, WorldSource};
+use std::collections::BTreeMap;
+use std::process::Command;
+
+fn fixture_path(name: &str) -> String {
+    format!("{}/tests/fixtures/worlds/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn info(name: &str, radius: f32) -> WorldInfo {
+    let (file, source) = read_world(&std::fs::read_to_string(fixture_path(name)).unwrap(), radius).unwrap();
+    WorldInfo::of(&file, source, &PlanetLimits::default())
+}
+
+#[test]
+fn an_exported_world_has_the_numbers_it_was_made_with() {
+    let info = info("stats.json", 10000.0);
+    assert_eq!((info.source, info.radius, info.planets), (WorldSource::Exported, 5000.0, 4));
+    assert_eq!(info.by_module_type, BTreeMap::from([(0, 1), (1, 1), (2, 2)]));
+    let counts: Vec<(f32, usize)> = info.sizes.iter().map(|b| (b.from, b.count)).collect();
+    assert_eq!(counts, [(0.0, 1), (50.0, 1), (100.0, 1), (150.0, 0), (200.0, 1)]);
+    assert_eq!(info.sizes[4], SizeBucket { from: 200.0, to: 250.0, count: 1 });
+    assert_eq!(info.nearest_neighbour, Some(DistanceStats { min: 600.0, mean: 1500.0, median: 700.0, max: 4000.0 }));
+    assert_eq!((info.resources, info.capacity), (700, 1400));
+    assert_eq!((info.claimed, info.unclaimed), (1, 3));
+    assert_eq!(info.bounding_radius, 3050.0);
+    assert!(info.warnings.is_empty());
+
+    let text = info.render();
+    assert!(text.contains("1 of type 1 (Defense), 2 of type 2 (Trade)"));
+    assert!(text.contains("median 700.0"));
+    assert!(text.ends_with("no warnings"));
+}
+
+#[test]
+fn a_saved_world_is_checked_against_the_radius_given() {
+    // The planet saved without an id gets the next one, as on `--world`
+    let roomy = info("saved.json", 10000.0);
+    assert_eq!(roomy.source, WorldSource::Saved);
+    assert_eq!(roomy.planets, 2);
+    assert_eq!(roomy.bounding_radius, 8040.0);
+    assert!(roomy.warnings.is_empty());
+
+    let cramped = info("saved.json", 5000.0);
+    assert_eq!(cramped.warnings, ["planets[1].position: outside the world radius"]);
+}
+
+#[test]
+fn problems_are_warnings_not_failures() {
+    let overlap = info("overlap.json", 10000.0);
+    assert_eq!(overlap.warnings, ["planets[1]: overlaps planets[0] by 50.0"]);
+    let bad_size = info("bad_size.json", 10000.0);
+    assert_eq!(bad_size.warnings.len(), 2);
+
+    let broken = std::fs::read_to_string(fixture_path("wrong_type.json")).unwrap();
+    assert!(read_world(&broken, 10000.0).is_err());
+}
+
+#[test]
+fn the_tool_prints_json_for_tooling() {
+    let output = Command::new(env!("CARGO_BIN_EXE_worldinfo")).args([&fixture_path("stats.json"), "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["source"], "exported");
+    assert_eq!(json["planets"], 4);
+    assert_eq!(json["by_module_type"]["2"], 2);
+    assert_eq!(json["nearest_neighbour"]["median"], 700.0);
+    assert_eq!(json["warnings"], serde_json::json!([]));
+
+    let missing = Command::new(env!("CARGO_BIN_EXE_worldinfo")).arg(fixture_path("missing.json")).output().unwrap();
+    assert!(!missing.status.success());
+}
@@ -0,0 +1,130 @@
+use galavox::config::ServerConfig;
+use galavox::protocol::{GameState, Planet, Position};
+use galavox::server::GameServer;
+use galavox::world::WorldConfig;
+use galavox::worldgen::{
+    generate_world, RingGenerator, SphereGenerator, SpiralGenerator, WorldGenerator, WorldLayout, MIN_PLANET_GAP, PLANET_COUNT,
+};
+use proptest::prelude::*;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+const LAYOUTS: [WorldLayout; 3] = [WorldLayout::Ring, WorldLayout::Spiral, WorldLayout::Sphere];
+
+fn seeded(seed: u64) -> WorldConfig {
+    WorldConfig { seed: Some(seed), ..WorldConfig::default() }
+}
+
+fn gap(a: &Planet, b: &Planet) -> f32 {
+    let (dx, dy, dz) = (a.position.x - b.position.x, a.position.y - b.position.y, a.position.z - b.position.z);
+    (dx * dx + dy * dy + dz * dz).sqrt() - (a.size + b.size) / 2.0
+}
+
+fn smallest_gap(state: &GameState) -> f32 {
+    let planets = &state.planets;
+    (0..planets.len())
+        .flat_map(|i| (i + 1..planets.len()).map(move |j| (i, j)))
+        .map(|(i, j)| gap(&planets[i], &planets[j]))
+        .fold(f32::INFINITY, f32::min)
+}
+
+#[test]
+fn the_same_seed_gives_the_same_world() {
+    for layout in LAYOUTS {
+        let generator = layout.generator();
+        for seed in 0..5 {
+            let first = generate_world(&*generator, &seeded(seed));
+            assert_eq!(first, generate_world(&*generator, &seeded(seed)), "{:?} seed {}", layout, seed);
+            assert_ne!(first.planets, generate_world(&*generator, &seeded(seed + 100)).planets, "{:?} ignores the seed", layout);
+        }
+    }
+}
+
+#[test]
+fn planets_never_overlap() {
+    for layout in LAYOUTS {
+        let generator = layout.generator();
+        for seed in 0..50 {
+            let state = generate_world(&*generator, &seeded(seed));
+            assert_eq!(state.planets.len(), PLANET_COUNT as usize, "{:?} seed {} left planets out", layout, seed);
+            assert!(smallest_gap(&state) >= MIN_PLANET_GAP - 0.01, "{:?} seed {}: gap {}", layout, seed, smallest_gap(&state));
+            let ids: Vec<u32> = state.planets.iter().map(|p| p.id).collect();
+            assert_eq!(ids, (0..PLANET_COUNT).collect::<Vec<_>>());
+        }
+    }
+}
+
+#[test]
+fn crowded_layouts_leave_planets_out_rather_than_overlap() {
+    let tiny = WorldConfig { radius: 250.0, ..seeded(7) };
+    for generator in [Box::new(SphereGenerator::default()) as Box<dyn WorldGenerator>, Box::new(SpiralGenerator::default())] {
+        let state = generate_world(&*generator, &tiny);
+        assert!(state.planets.len() < PLANET_COUNT as usize);
+        assert!(smallest_gap(&state) >= MIN_PLANET_GAP - 0.01);
+        assert!(state.planets.iter().all(|p| tiny.contains(&p.position)));
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(200))]
+
+    #[test]
+    fn no_seed_or_gap_makes_planets_overlap(seed in any::<u64>(), min_planet_gap in 0.0f32..300.0, layout in 0..LAYOUTS.len()) {
+        let config = WorldConfig { min_planet_gap, ..seeded(seed) };
+        let state = generate_world(&*LAYOUTS[layout].generator(), &config);
+        prop_assert!(!state.planets.is_empty());
+        prop_assert!(smallest_gap(&state) >= min_planet_gap - 0.01, "gap {}", smallest_gap(&state));
+    }
+}
+
+#[test]
+fn a_wider_gap_nudges_the_ring_apart() {
+    let config = WorldConfig { min_planet_gap: 250.0, ..seeded(1) };
+    let state = generate_world(&RingGenerator, &config);
+    assert!(smallest_gap(&state) >= 249.99);
+    assert_ne!(state.planets, generate_world(&RingGenerator, &seeded(1)).planets);
+
+    let config = ServerConfig::from_toml("min_planet_gap = 150.0\n").unwrap();
+    assert_eq!(config.world_config().min_planet_gap, 150.0);
+    assert_eq!(config.planet_limits().min_gap, 150.0);
+    assert!(ServerConfig::from_toml("min_planet_gap = -1.0\n").unwrap().validate().is_err());
+}
+
+#[test]
+fn sphere_keeps_its_minimum_gap() {
+    let generator = SphereGenerator { radius: 3000.0, min_gap: 250.0 };
+    for seed in 0..20 {
+        assert!(smallest_gap(&generate_world(&generator, &seeded(seed))) >= 249.99);
+    }
+}
+
+/// A downstream generator: one planet at a random height.
+struct Lonely;
+
+impl WorldGenerator for Lonely {
+    fn generate(&self, config: &WorldConfig, rng: &mut StdRng) -> GameState {
+        let mut state = generate_world(&RingGenerator, config);
+        state.planets.truncate(1);
+        state.planets[0].position = Position { x: 0.0, y: rng.gen_range(0.0..10.0), z: 0.0 };
+        state
+    }
+}
+
+#[test]
+fn servers_accept_their_own_generators() {
+    let server = GameServer::with_generator(Box::new(Lonely));
+    assert_eq!(server.get_state().planets.len(), 1);
+    let server = server.with_world_config(seeded(3));
+    assert_eq!(server.get_state().planets.len(), 1);
+}
+
+#[test]
+fn layouts_are_chosen_in_the_config() {
+    let mut config = ServerConfig::from_toml("world_layout = \"spiral\"\n").unwrap();
+    assert_eq!(config.world_layout, WorldLayout::Spiral);
+    config.apply_args(&["--world-layout".to_string(), "sphere".to_string()]).unwrap();
+    assert_eq!(config.world_layout, WorldLayout::Sphere);
+    assert!(ServerConfig::from_toml("world_layout = \"cube\"\n").is_err());
+    assert!(config.apply_args(&["--world-layout".to_string(), "cube".to_string()]).is_err());
+    assert_eq!(ServerConfig::default().world_layout, WorldLayout::Ring);
+}